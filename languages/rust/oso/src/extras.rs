@@ -1,9 +1,30 @@
+/// Compare `value` with other instances of its type and with the strings that parse as one.
+#[cfg(any(
+    feature = "uuid-06",
+    feature = "uuid-07",
+    feature = "uuid-10",
+    feature = "ipnet"
+))]
+fn compare_parsed<T>(value: &T, other: &crate::PolarValue) -> Option<std::cmp::Ordering>
+where
+    T: std::str::FromStr + Ord + 'static,
+{
+    match other {
+        crate::PolarValue::String(other) => other.parse().ok().map(|other| value.cmp(&other)),
+        crate::PolarValue::Instance(other) => {
+            other.downcast(None).ok().map(|other| value.cmp(other))
+        }
+        _ => None,
+    }
+}
+
 #[cfg(feature = "uuid-06")]
 impl crate::PolarClass for uuid_06::Uuid {
     fn get_polar_class_builder() -> crate::host::ClassBuilder<uuid_06::Uuid> {
         crate::host::Class::builder()
             .name("Uuid")
             .with_equality_check()
+            .set_value_extension(compare_parsed::<uuid_06::Uuid>)
     }
 }
 
@@ -13,6 +34,7 @@ impl crate::PolarClass for uuid_07::Uuid {
         crate::host::Class::builder()
             .name("Uuid")
             .with_equality_check()
+            .set_value_extension(compare_parsed::<uuid_07::Uuid>)
     }
 }
 
@@ -22,5 +44,61 @@ impl crate::PolarClass for uuid_10::Uuid {
         crate::host::Class::builder()
             .name("Uuid")
             .with_equality_check()
+            .set_value_extension(compare_parsed::<uuid_10::Uuid>)
+    }
+}
+
+/// IP networks, e.g., `10.0.0.0/8`, which are equal to the strings they're written as.
+#[cfg(feature = "ipnet")]
+impl crate::PolarClass for ipnet::IpNet {
+    fn get_polar_class_builder() -> crate::host::ClassBuilder<ipnet::IpNet> {
+        crate::host::Class::builder()
+            .name("IpNet")
+            .with_equality_check()
+            .set_value_extension(compare_parsed::<ipnet::IpNet>)
+            .add_method("contains", |net: &ipnet::IpNet, address: String| {
+                matches!(address.parse::<std::net::IpAddr>(), Ok(address) if net.contains(&address))
+            })
+    }
+}
+
+/// Date-times are Polar date-times, so they compare natively, including in data filters.
+/// Instants outside of the years 0000 to 9999 saturate at the earliest or latest one.
+#[cfg(feature = "chrono")]
+impl<Tz: chrono::TimeZone> crate::ToPolar for chrono::DateTime<Tz> {
+    fn to_polar(self) -> crate::PolarValue {
+        crate::ToPolar::to_polar(std::time::SystemTime::from(self))
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl crate::FromPolar for chrono::DateTime<chrono::Utc> {
+    fn from_polar(val: crate::PolarValue) -> crate::Result<Self> {
+        <std::time::SystemTime as crate::FromPolar>::from_polar(val).map(Self::from)
+    }
+}
+
+/// Decimals are Polar decimals, so they compare natively, including in data filters.
+#[cfg(feature = "rust_decimal")]
+impl crate::ToPolar for rust_decimal::Decimal {
+    fn to_polar(self) -> crate::PolarValue {
+        crate::PolarValue::Decimal(polar_core::terms::Decimal::new(
+            self.mantissa().into(),
+            self.scale(),
+        ))
+    }
+}
+
+/// Polar decimals that don't fit in a `rust_decimal::Decimal` can't be converted.
+#[cfg(feature = "rust_decimal")]
+impl crate::FromPolar for rust_decimal::Decimal {
+    fn from_polar(val: crate::PolarValue) -> crate::Result<Self> {
+        match val {
+            crate::PolarValue::Decimal(d) => {
+                Self::from_str_exact(&d.to_string()).map_err(|_| crate::OsoError::FromPolar)
+            }
+            crate::PolarValue::Integer(i) => Ok(i.into()),
+            _ => Err(crate::errors::TypeError::expected("Decimal").user()),
+        }
     }
 }
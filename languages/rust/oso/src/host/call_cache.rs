@@ -0,0 +1,165 @@
+//! Caching for the results of calls to methods and attributes on host instances.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use super::PolarValue;
+
+/// Number of results kept by the default in-memory cache.
+pub const DEFAULT_CALL_CACHE_CAPACITY: usize = 1024;
+
+/// Identifies the result of calling `method` with `args` on an instance of `class`.
+///
+/// `instance` is the key returned by the function passed to `ClassBuilder::set_cache_key`, and
+/// `args` are the arguments formatted as Polar terms, or `None` for an attribute lookup, so that
+/// `x.foo` and `x.foo()` are cached separately.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct CallKey {
+    pub class: String,
+    pub instance: String,
+    pub method: String,
+    pub args: Option<Vec<String>>,
+}
+
+/// Storage for the results of methods registered with `ClassBuilder::cache_method`.
+///
+/// The cache is shared by every query made through an `Oso` instance, so implementations must be
+/// thread-safe. Set one with `Oso::set_call_cache`; the default is an in-memory [`LruCallCache`].
+pub trait CallCache: Send + Sync {
+    /// Return the cached result for `key`, if there is one that hasn't expired.
+    fn get(&self, key: &CallKey) -> Option<PolarValue>;
+
+    /// Cache `value` as the result for `key` for at most `ttl`.
+    fn insert(&self, key: CallKey, value: PolarValue, ttl: Duration);
+}
+
+struct Entry {
+    value: PolarValue,
+    expires: Instant,
+    last_used: u64,
+}
+
+#[derive(Default)]
+struct Lru {
+    entries: HashMap<CallKey, Entry>,
+    /// Keys by the time they were last used, oldest first.
+    recency: BTreeMap<u64, CallKey>,
+    clock: u64,
+}
+
+impl Lru {
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+
+    fn remove(&mut self, key: &CallKey) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.recency.remove(&entry.last_used);
+        }
+    }
+}
+
+/// An in-memory [`CallCache`] that evicts the least recently used result when full.
+pub struct LruCallCache {
+    capacity: usize,
+    lru: Mutex<Lru>,
+}
+
+impl LruCallCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            lru: Mutex::new(Lru::default()),
+        }
+    }
+}
+
+impl Default for LruCallCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_CALL_CACHE_CAPACITY)
+    }
+}
+
+impl CallCache for LruCallCache {
+    fn get(&self, key: &CallKey) -> Option<PolarValue> {
+        let mut lru = self.lru.lock().unwrap();
+        let expired = lru.entries.get(key)?.expires <= Instant::now();
+        if expired {
+            lru.remove(key);
+            return None;
+        }
+
+        let now = lru.tick();
+        let entry = lru.entries.get_mut(key).unwrap();
+        let last_used = std::mem::replace(&mut entry.last_used, now);
+        let value = entry.value.clone();
+        lru.recency.remove(&last_used);
+        lru.recency.insert(now, key.clone());
+        Some(value)
+    }
+
+    fn insert(&self, key: CallKey, value: PolarValue, ttl: Duration) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let mut lru = self.lru.lock().unwrap();
+        lru.remove(&key);
+        while lru.entries.len() >= self.capacity {
+            let oldest = match lru.recency.keys().next() {
+                Some(&oldest) => oldest,
+                None => break,
+            };
+            let key = lru.recency.remove(&oldest).unwrap();
+            lru.entries.remove(&key);
+        }
+
+        let now = lru.tick();
+        lru.recency.insert(now, key.clone());
+        lru.entries.insert(
+            key,
+            Entry {
+                value,
+                expires: Instant::now() + ttl,
+                last_used: now,
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn key(method: &str) -> CallKey {
+        CallKey {
+            class: "User".to_owned(),
+            instance: "1".to_owned(),
+            method: method.to_owned(),
+            args: Some(vec![]),
+        }
+    }
+
+    #[test]
+    fn test_lru_evicts_least_recently_used() {
+        let cache = LruCallCache::new(2);
+        let ttl = Duration::from_secs(60);
+        cache.insert(key("a"), PolarValue::Integer(1), ttl);
+        cache.insert(key("b"), PolarValue::Integer(2), ttl);
+        assert_eq!(cache.get(&key("a")), Some(PolarValue::Integer(1)));
+
+        cache.insert(key("c"), PolarValue::Integer(3), ttl);
+        assert_eq!(cache.get(&key("a")), Some(PolarValue::Integer(1)));
+        assert_eq!(cache.get(&key("b")), None);
+        assert_eq!(cache.get(&key("c")), Some(PolarValue::Integer(3)));
+    }
+
+    #[test]
+    fn test_lru_expires_entries() {
+        let cache = LruCallCache::default();
+        cache.insert(key("a"), PolarValue::Integer(1), Duration::from_secs(0));
+        assert_eq!(cache.get(&key("a")), None);
+    }
+}
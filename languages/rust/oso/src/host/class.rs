@@ -1,18 +1,22 @@
 //! Support for dynamic class objects in Rust
 
 use std::any::TypeId;
-use std::collections::HashMap;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
 use std::sync::Arc;
+use std::time::Duration;
 
 use crate::errors::{InvalidCallError, OsoError};
 
+use super::call_cache::CallKey;
 use super::class_method::{
     AttributeGetter, ClassMethod, Constructor, InstanceMethod, RegisterHook,
 };
 use super::from_polar::FromPolarList;
 use super::method::{Function, Method};
 use super::to_polar::ToPolarResult;
+use super::value_extension::ValueExtension;
 use super::Host;
 use super::PolarValue;
 
@@ -20,6 +24,9 @@ type Attributes = HashMap<&'static str, AttributeGetter>;
 type RegisterHooks = Vec<RegisterHook>;
 type ClassMethods = HashMap<&'static str, ClassMethod>;
 type InstanceMethods = HashMap<&'static str, InstanceMethod>;
+type CachedMethods = HashMap<&'static str, Duration>;
+type Comparison =
+    Arc<dyn Fn(&Host, &Instance, &PolarValue) -> crate::Result<Option<Ordering>> + Send + Sync>;
 
 fn equality_not_supported(
 ) -> Box<dyn Fn(&Host, &Instance, &Instance) -> crate::Result<bool> + Send + Sync> {
@@ -66,6 +73,18 @@ pub struct Class {
     into_iter:
         Arc<dyn Fn(&Host, &Instance) -> crate::Result<crate::host::PolarIterator> + Send + Sync>,
 
+    /// Compares instances of this class with other values; see `ValueExtension`.
+    comparison: Option<Comparison>,
+
+    /// A function that identifies instances of this class in the call cache.
+    cache_key: Option<Arc<dyn Fn(&Host, &Instance) -> crate::Result<String> + Send + Sync>>,
+    /// Methods and attributes whose results are cached, and for how long.
+    cached_methods: CachedMethods,
+    /// Methods whose calls have side effects.
+    pub(crate) effectful_methods: HashSet<&'static str>,
+    /// Methods that can return different results for the same arguments.
+    pub(crate) nondeterministic_methods: HashSet<&'static str>,
+
     // Hooks to be called on the class once it's been registered with host.
     pub register_hooks: RegisterHooks,
 }
@@ -110,6 +129,42 @@ impl Class {
         }
     }
 
+    /// Return the call cache key and TTL for calling `name` with `args` on `instance`, or looking
+    /// up the attribute `name` if there are no `args`, if the results should be cached.
+    pub(crate) fn call_key(
+        &self,
+        host: &Host,
+        instance: &Instance,
+        name: &str,
+        args: Option<Vec<String>>,
+    ) -> crate::Result<Option<(CallKey, Duration)>> {
+        let (cache_key, ttl) = match (&self.cache_key, self.cached_methods.get(name)) {
+            (Some(cache_key), Some(ttl)) => (cache_key, *ttl),
+            _ => return Ok(None),
+        };
+        let key = CallKey {
+            class: self.name.clone(),
+            instance: cache_key(host, instance)?,
+            method: name.to_owned(),
+            args,
+        };
+        Ok(Some((key, ttl)))
+    }
+
+    /// Compare `lhs`, an instance of this class, with `other` using the class's value
+    /// extension. Returns `None` if the class doesn't have one.
+    pub(crate) fn compare(
+        &self,
+        host: &Host,
+        lhs: &Instance,
+        other: &PolarValue,
+    ) -> crate::Result<Option<Option<Ordering>>> {
+        self.comparison
+            .as_ref()
+            .map(|compare| compare(host, lhs, other))
+            .transpose()
+    }
+
     fn equals(&self, host: &Host, lhs: &Instance, rhs: &Instance) -> crate::Result<bool> {
         // equality checking is currently only supported for exactly matching types
         // TODO: support multiple dispatch for equality
@@ -145,6 +200,11 @@ where
                 class_methods: ClassMethods::new(),
                 equality_check: Arc::from(equality_not_supported()),
                 into_iter: Arc::from(iterator_not_supported()),
+                comparison: None,
+                cache_key: None,
+                cached_methods: CachedMethods::new(),
+                effectful_methods: HashSet::new(),
+                nondeterministic_methods: HashSet::new(),
                 type_id: TypeId::of::<T>(),
                 register_hooks: RegisterHooks::new(),
             },
@@ -229,6 +289,48 @@ where
         self.set_into_iter(|t| t.clone().into_iter())
     }
 
+    /// Set the function used to identify instances in the call cache.
+    ///
+    /// Instances with the same key are assumed to return the same results from cached methods.
+    pub fn set_cache_key<F, K>(mut self, f: F) -> Self
+    where
+        F: Fn(&T) -> K + Send + Sync + 'static,
+        K: ToString,
+    {
+        self.class.cache_key = Some(Arc::new(move |host, instance| {
+            let instance = instance.downcast(Some(host)).map_err(|e| e.user())?;
+            Ok(f(instance).to_string())
+        }));
+
+        self
+    }
+
+    /// Cache the results of the method or attribute `name` for `ttl`.
+    ///
+    /// Results are only cached if the class has a cache key; see `set_cache_key`.
+    pub fn cache_method(mut self, name: &'static str, ttl: Duration) -> Self {
+        self.class.cached_methods.insert(name, ttl);
+        self
+    }
+
+    /// Mark the method `name` as having side effects.
+    ///
+    /// Loading a policy warns about rules whose behavior depends on the order that calls to
+    /// effectful methods are evaluated in.
+    pub fn mark_effectful(mut self, name: &'static str) -> Self {
+        self.class.effectful_methods.insert(name);
+        self
+    }
+
+    /// Mark the method `name` as able to return different results for the same arguments.
+    ///
+    /// Queries remember the results of method calls on an instance, so that repeating a call
+    /// doesn't call the method again; calls to nondeterministic methods are always made.
+    pub fn mark_nondeterministic(mut self, name: &'static str) -> Self {
+        self.class.nondeterministic_methods.insert(name);
+        self
+    }
+
     /// Use PartialEq::eq as the equality check for polar `==` statements.
     pub fn with_equality_check(self) -> Self
     where
@@ -237,6 +339,34 @@ where
         self.set_equality_check(|a, b| PartialEq::eq(a, b))
     }
 
+    /// Define how instances compare with other values in unification and comparisons.
+    /// See [`ValueExtension`](crate::ValueExtension).
+    pub fn set_value_extension<E>(mut self, extension: E) -> Self
+    where
+        E: ValueExtension<T>,
+    {
+        self.class.comparison = Some(Arc::new(move |host, value, other| {
+            let value = value.downcast(Some(host)).map_err(|e| e.user())?;
+            Ok(extension.compare(value, other))
+        }));
+
+        self
+    }
+
+    /// Use PartialOrd::partial_cmp to compare instances with other instances of the class.
+    pub fn with_value_extension(self) -> Self
+    where
+        T: PartialOrd + Send + Sync,
+    {
+        self.set_value_extension(|value: &T, other: &PolarValue| match other {
+            PolarValue::Instance(other) => other
+                .downcast::<T>(None)
+                .ok()
+                .and_then(|other| value.partial_cmp(other)),
+            _ => None,
+        })
+    }
+
     /// Add an attribute getter for statments like `foo.bar`
     /// `class.add_attribute_getter("bar", |instance| instance.bar)
     pub fn add_attribute_getter<F, R>(mut self, name: &'static str, f: F) -> Self
@@ -4,8 +4,11 @@
 
 use std::collections::{BTreeMap, BTreeSet, BinaryHeap, HashMap, HashSet, LinkedList, VecDeque};
 use std::hash::Hash;
+use std::time::SystemTime;
 
 use impl_trait_for_tuples::*;
+use num_bigint::BigInt;
+use polar_core::terms::Decimal;
 
 use super::class::Instance;
 use super::PolarValue;
@@ -86,6 +89,51 @@ impl FromPolar for f64 {
     }
 }
 
+impl FromPolar for BigInt {
+    fn from_polar(val: PolarValue) -> crate::Result<Self> {
+        match val {
+            PolarValue::Integer(i) => Ok(i.into()),
+            PolarValue::BigInteger(i) => Ok(i),
+            _ => Err(TypeError::expected("Integer").user()),
+        }
+    }
+}
+
+impl FromPolar for Decimal {
+    fn from_polar(val: PolarValue) -> crate::Result<Self> {
+        if let PolarValue::Decimal(d) = val {
+            Ok(d)
+        } else {
+            Err(TypeError::expected("Decimal").user())
+        }
+    }
+}
+
+impl FromPolar for SystemTime {
+    fn from_polar(val: PolarValue) -> crate::Result<Self> {
+        if let PolarValue::DateTime(t) = val {
+            Ok(t.into())
+        } else {
+            Err(TypeError::expected("DateTime").user())
+        }
+    }
+}
+
+/// Negative durations can't be converted.
+impl FromPolar for std::time::Duration {
+    fn from_polar(val: PolarValue) -> crate::Result<Self> {
+        if let PolarValue::Duration(d) = val {
+            let nanos = u128::try_from(d.as_nanos()).map_err(|_| crate::OsoError::FromPolar)?;
+            Ok(Self::new(
+                (nanos / 1_000_000_000) as u64,
+                (nanos % 1_000_000_000) as u32,
+            ))
+        } else {
+            Err(TypeError::expected("Duration").user())
+        }
+    }
+}
+
 impl FromPolar for String {
     fn from_polar(val: PolarValue) -> crate::Result<Self> {
         if let PolarValue::String(s) = val {
@@ -180,7 +228,7 @@ impl<T: FromPolar> FromPolar for VecDeque<T> {
 
 impl<T: Eq + Hash + FromPolar> FromPolar for HashSet<T> {
     fn from_polar(val: PolarValue) -> crate::Result<Self> {
-        if let PolarValue::List(l) = val {
+        if let PolarValue::List(l) | PolarValue::Set(l) = val {
             let mut result = HashSet::new();
             for v in l {
                 result.insert(T::from_polar(v)?);
@@ -194,7 +242,7 @@ impl<T: Eq + Hash + FromPolar> FromPolar for HashSet<T> {
 
 impl<T: Eq + Ord + FromPolar> FromPolar for BTreeSet<T> {
     fn from_polar(val: PolarValue) -> crate::Result<Self> {
-        if let PolarValue::List(l) = val {
+        if let PolarValue::List(l) | PolarValue::Set(l) = val {
             let mut result = BTreeSet::new();
             for v in l {
                 result.insert(T::from_polar(v)?);
@@ -1,21 +1,26 @@
+use std::cmp::Ordering;
 use std::collections::HashMap;
 use std::sync::{Arc, RwLock};
 
 use crate::errors::OsoError;
 use crate::Polar;
 
+mod call_cache;
 mod class;
 mod class_method;
 mod from_polar;
 mod method;
 mod to_polar;
 mod value;
+mod value_extension;
 
+pub use call_cache::{CallCache, CallKey, LruCallCache};
 pub use class::{Class, ClassBuilder, Instance};
 pub use from_polar::{FromPolar, FromPolarList};
 use polar_core::terms::{Operator, Symbol};
 pub use to_polar::{PolarIterator, ToPolar, ToPolarList};
 pub use value::PolarValue;
+pub use value_extension::ValueExtension;
 
 lazy_static::lazy_static! {
     /// Map of classes that have been globally registered
@@ -47,6 +52,9 @@ pub struct Host {
     /// class name it is registered as
     class_names: HashMap<std::any::TypeId, String>,
 
+    /// Results of calls to cached methods, shared with every clone of the host
+    call_cache: Arc<dyn CallCache>,
+
     pub accept_expression: bool,
 }
 
@@ -56,6 +64,7 @@ impl Host {
             class_names: HashMap::new(),
             classes: HashMap::new(),
             instances: HashMap::new(),
+            call_cache: Arc::new(LruCallCache::default()),
             accept_expression: false,
             polar,
         };
@@ -112,14 +121,24 @@ impl Host {
         }
     }
 
+    pub fn call_cache(&self) -> &dyn CallCache {
+        self.call_cache.as_ref()
+    }
+
+    pub fn set_call_cache(&mut self, cache: Arc<dyn CallCache>) {
+        self.call_cache = cache;
+    }
+
     /// Register an MRO list for every registered class.
     /// Since inheritance is not supported, all lists are empty.
     pub fn register_mros(&self) -> crate::Result<()> {
-        for name in self.classes.keys() {
-            if name != "oso::host::Class" {
-                self.polar.register_mro(Symbol(name.clone()), vec![])?;
+        self.polar.transaction(|txn| {
+            for name in self.classes.keys() {
+                if name != "oso::host::Class" {
+                    txn.register_mro(Symbol::new(name), vec![]);
+                }
             }
-        }
+        })?;
         Ok(())
     }
 
@@ -177,9 +196,13 @@ impl Host {
             PolarValue::Boolean(_) => class_tag == "Boolean",
             PolarValue::Map(_) => class_tag == "Dictionary",
             PolarValue::List(_) => class_tag == "List",
-            PolarValue::Integer(_) => class_tag == "Integer",
+            PolarValue::Set(_) => class_tag == "Set",
+            PolarValue::Integer(_) | PolarValue::BigInteger(_) => class_tag == "Integer",
             PolarValue::Float(_) => class_tag == "Float",
+            PolarValue::Decimal(_) => class_tag == "Decimal",
             PolarValue::String(_) => class_tag == "String",
+            PolarValue::DateTime(_) => class_tag == "DateTime",
+            PolarValue::Duration(_) => class_tag == "Duration",
             _ => false,
         };
         Ok(res)
@@ -190,7 +213,13 @@ impl Host {
         false
     }
 
-    pub fn operator(&self, op: Operator, args: [class::Instance; 2]) -> crate::Result<bool> {
+    pub fn operator(&self, op: Operator, args: [PolarValue; 2]) -> crate::Result<bool> {
+        let [lhs, rhs] = args;
+        if let Some(ordering) = self.compare_with_extension(&lhs, &rhs)? {
+            return value_extension::operator_holds(op, ordering);
+        }
+
+        let args = [Instance::from_polar(lhs)?, Instance::from_polar(rhs)?];
         match op {
             Operator::Eq => args[0].equals(&args[1], self),
             _ => Err(OsoError::UnimplementedOperation {
@@ -200,4 +229,27 @@ impl Host {
         // Operators are not supported
         // TODO (dhatch): Implement.
     }
+
+    /// Compare two values with the value extension of whichever is an instance of a class that
+    /// has one, trying the left-hand side first.
+    fn compare_with_extension(
+        &self,
+        lhs: &PolarValue,
+        rhs: &PolarValue,
+    ) -> crate::Result<Option<Option<Ordering>>> {
+        if let PolarValue::Instance(instance) = lhs {
+            if let Ok(class) = instance.class(self) {
+                if let Some(ordering) = class.compare(self, instance, rhs)? {
+                    return Ok(Some(ordering));
+                }
+            }
+        }
+        if let PolarValue::Instance(instance) = rhs {
+            if let Ok(class) = instance.class(self) {
+                let ordering = class.compare(self, instance, lhs)?;
+                return Ok(ordering.map(|ordering| ordering.map(Ordering::reverse)));
+            }
+        }
+        Ok(None)
+    }
 }
@@ -6,6 +6,10 @@
 use impl_trait_for_tuples::*;
 
 use std::collections::{BTreeMap, BTreeSet, BinaryHeap, HashMap, HashSet, LinkedList, VecDeque};
+use std::time::SystemTime;
+
+use num_bigint::BigInt;
+use polar_core::terms::Decimal;
 
 use super::DEFAULT_CLASSES;
 use crate::PolarValue;
@@ -147,6 +151,34 @@ macro_rules! float_to_polar {
 float_to_polar!(f32);
 float_to_polar!(f64);
 
+/// Integers that fit in an `i64` are converted to `PolarValue::Integer`.
+impl ToPolar for BigInt {
+    fn to_polar(self) -> PolarValue {
+        match i64::try_from(&self) {
+            Ok(i) => PolarValue::Integer(i),
+            Err(_) => PolarValue::BigInteger(self),
+        }
+    }
+}
+
+impl ToPolar for Decimal {
+    fn to_polar(self) -> PolarValue {
+        PolarValue::Decimal(self)
+    }
+}
+
+impl ToPolar for SystemTime {
+    fn to_polar(self) -> PolarValue {
+        PolarValue::DateTime(self.into())
+    }
+}
+
+impl ToPolar for std::time::Duration {
+    fn to_polar(self) -> PolarValue {
+        PolarValue::Duration(self.into())
+    }
+}
+
 impl ToPolar for String {
     fn to_polar(self) -> PolarValue {
         PolarValue::String(self)
@@ -177,6 +209,8 @@ impl<T: ToPolar> ToPolar for LinkedList<T> {
     }
 }
 
+/// Converted to a list, for compatibility with policies written before Polar had sets. Pass a
+/// `PolarValue::Set` to use one as a set.
 impl<T: ToPolar> ToPolar for HashSet<T> {
     fn to_polar(self) -> PolarValue {
         PolarValue::List(self.into_iter().map(|v| v.to_polar()).collect())
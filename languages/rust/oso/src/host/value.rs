@@ -1,3 +1,4 @@
+use num_bigint::BigInt;
 use polar_core::terms::*;
 use std::collections::hash_map::HashMap;
 
@@ -14,11 +15,21 @@ use crate::host::{Host, Instance};
 #[derive(Clone, Debug)]
 pub enum PolarValue {
     Integer(i64),
+    /// An integer that doesn't fit in an `i64`.
+    BigInteger(BigInt),
     Float(f64),
+    /// An exact decimal, e.g., `1.50d`.
+    Decimal(Decimal),
     String(String),
     Boolean(bool),
+    /// An instant in time, e.g., from a `std::time::SystemTime`.
+    DateTime(DateTime),
+    /// A signed span of time, e.g., from a `std::time::Duration`.
+    Duration(Duration),
     Map(HashMap<String, PolarValue>),
     List(Vec<PolarValue>),
+    /// The elements of a set, in no particular order.
+    Set(Vec<PolarValue>),
     Variable(String),
     Instance(Instance),
 }
@@ -29,9 +40,16 @@ impl PartialEq for PolarValue {
             (PolarValue::Boolean(b1), PolarValue::Boolean(b2)) => b1 == b2,
             (PolarValue::Float(f1), PolarValue::Float(f2)) => f1 == f2,
             (PolarValue::Integer(i1), PolarValue::Integer(i2)) => i1 == i2,
+            (PolarValue::BigInteger(i1), PolarValue::BigInteger(i2)) => i1 == i2,
+            (PolarValue::Decimal(d1), PolarValue::Decimal(d2)) => d1 == d2,
             (PolarValue::List(l1), PolarValue::List(l2)) => l1 == l2,
+            (PolarValue::Set(s1), PolarValue::Set(s2)) => {
+                s1.len() == s2.len() && s1.iter().all(|v| s2.contains(v))
+            }
             (PolarValue::Map(m1), PolarValue::Map(m2)) => m1 == m2,
             (PolarValue::String(s1), PolarValue::String(s2)) => s1 == s2,
+            (PolarValue::DateTime(t1), PolarValue::DateTime(t2)) => t1 == t2,
+            (PolarValue::Duration(d1), PolarValue::Duration(d2)) => d1 == d2,
             _ => false,
         }
     }
@@ -49,13 +67,17 @@ impl PolarValue {
     pub(crate) fn from_term(term: &Term, host: &Host) -> crate::Result<Self> {
         let val = match term.value() {
             Value::Number(Numeric::Integer(i)) => PolarValue::Integer(*i),
+            Value::Number(Numeric::BigInteger(i)) => PolarValue::BigInteger(i.clone()),
             Value::Number(Numeric::Float(f)) => PolarValue::Float(*f),
+            Value::Number(Numeric::Decimal(d)) => PolarValue::Decimal(d.clone()),
             Value::String(s) => PolarValue::String(s.clone()),
             Value::Boolean(b) => PolarValue::Boolean(*b),
+            Value::DateTime(t) => PolarValue::DateTime(*t),
+            Value::Duration(d) => PolarValue::Duration(*d),
             Value::Dictionary(dict) => {
                 let mut map = HashMap::new();
                 for (k, v) in &dict.fields {
-                    let key = k.0.clone();
+                    let key = k.as_str().to_string();
                     let value = PolarValue::from_term(v, host)?;
                     map.insert(key, value);
                 }
@@ -71,7 +93,14 @@ impl PolarValue {
                 }
                 PolarValue::List(list)
             }
-            Value::Variable(Symbol(sym)) => PolarValue::Variable(sym.clone()),
+            Value::Set(set) => {
+                let mut elements = vec![];
+                for t in set.iter() {
+                    elements.push(PolarValue::from_term(t, host)?);
+                }
+                PolarValue::Set(elements)
+            }
+            Value::Variable(sym) => PolarValue::Variable(sym.to_string()),
             Value::Expression(_) => {
                 return Err(crate::OsoError::Custom {
                     message: r#"
@@ -94,13 +123,17 @@ This may mean you performed an operation in your policy over an unbound variable
     pub(crate) fn to_term(&self, host: &mut Host) -> Term {
         let value = match self {
             PolarValue::Integer(i) => Value::Number(Numeric::Integer(*i)),
+            PolarValue::BigInteger(i) => Value::Number(Numeric::BigInteger(i.clone())),
             PolarValue::Float(f) => Value::Number(Numeric::Float(*f)),
+            PolarValue::Decimal(d) => Value::Number(Numeric::Decimal(d.clone())),
             PolarValue::String(s) => Value::String(s.clone()),
             PolarValue::Boolean(b) => Value::Boolean(*b),
+            PolarValue::DateTime(t) => Value::DateTime(*t),
+            PolarValue::Duration(d) => Value::Duration(*d),
             PolarValue::Map(map) => {
                 let mut dict = Dictionary::new();
                 for (k, v) in map {
-                    let key = Symbol(k.clone());
+                    let key = Symbol::new(k);
                     let value = v.to_term(host);
                     dict.fields.insert(key, value);
                 }
@@ -123,7 +156,10 @@ This may mean you performed an operation in your policy over an unbound variable
                 }
                 Value::List(list)
             }
-            PolarValue::Variable(s) => Value::Variable(Symbol(s.clone())),
+            PolarValue::Set(elements) => {
+                Value::Set(elements.iter().map(|v| v.to_term(host)).collect())
+            }
+            PolarValue::Variable(s) => Value::Variable(Symbol::new(s)),
         };
         Term::new_from_ffi(value)
     }
@@ -0,0 +1,53 @@
+//! Custom equality and ordering for host value types.
+
+use std::cmp::Ordering;
+
+use polar_core::terms::Operator;
+
+use super::PolarValue;
+use crate::errors::OsoError;
+
+/// Defines how instances of a value type (a UUID, an IP network...) compare with other Polar
+/// values.
+///
+/// A class's extension is used wherever Polar compares its instances: unification (`=`, `in`,
+/// and fields in specializers like `Resource{id: id}`) and the `==`, `!=`, `<`, `<=`, `>` and
+/// `>=` operators. Attach one with `ClassBuilder::set_value_extension`.
+///
+/// Extensions don't apply to data filters: the filters Polar builds hold instances as they are,
+/// for the host's adapter to compare. Types that convert to Polar primitives instead, like
+/// `chrono` date-times and `rust_decimal` decimals, compare the same way everywhere.
+pub trait ValueExtension<T>: Send + Sync + 'static {
+    /// Compare `value` with `other`, which may be a Polar primitive or an instance of any
+    /// class. Return `None` if the two aren't comparable, in which case only `!=` holds.
+    ///
+    /// Two values are equal for unification exactly when this returns `Some(Ordering::Equal)`.
+    fn compare(&self, value: &T, other: &PolarValue) -> Option<Ordering>;
+}
+
+impl<T, F> ValueExtension<T> for F
+where
+    F: Fn(&T, &PolarValue) -> Option<Ordering> + Send + Sync + 'static,
+{
+    fn compare(&self, value: &T, other: &PolarValue) -> Option<Ordering> {
+        self(value, other)
+    }
+}
+
+/// Whether `op` holds for two values ordered by `ordering`.
+pub(crate) fn operator_holds(op: Operator, ordering: Option<Ordering>) -> crate::Result<bool> {
+    use Ordering::*;
+    Ok(match op {
+        Operator::Eq | Operator::Unify => ordering == Some(Equal),
+        Operator::Neq => ordering != Some(Equal),
+        Operator::Lt => ordering == Some(Less),
+        Operator::Leq => matches!(ordering, Some(Less | Equal)),
+        Operator::Gt => ordering == Some(Greater),
+        Operator::Geq => matches!(ordering, Some(Greater | Equal)),
+        _ => {
+            return Err(OsoError::UnimplementedOperation {
+                operation: format!("`{}` operations on value types", op),
+            })
+        }
+    })
+}
@@ -79,11 +79,21 @@ pub mod errors;
 mod extras;
 mod host;
 mod oso;
+mod pool;
 mod query;
+#[cfg(feature = "stream")]
+pub mod stream;
 
 pub use crate::oso::{Action, Oso};
+pub use crate::pool::{OsoPool, PoolMetrics, PooledOso};
 pub use errors::{OsoError, Result};
-pub use host::{Class, ClassBuilder, FromPolar, FromPolarList, PolarValue, ToPolar, ToPolarList};
+pub use host::{
+    CallCache, CallKey, Class, ClassBuilder, FromPolar, FromPolarList, LruCallCache, PolarValue,
+    ToPolar, ToPolarList, ValueExtension,
+};
+pub use num_bigint::BigInt;
+pub use polar_core::diagnostic::sarif::SarifLog;
+pub use polar_core::terms::{DateTime, Decimal, Duration};
 pub use query::{Query, ResultSet};
 
 use polar_core::polar::Polar;
@@ -1,8 +1,10 @@
 //! Communicate with the Polar virtual machine: load rules, make queries, etc/
+use polar_core::diagnostic::sarif::SarifLog;
+use polar_core::repl::{self, ReplSession};
 use polar_core::sources::Source;
-use polar_core::terms::{Call, Symbol, Term, Value};
+use polar_core::terms::{Call, Operation, Operator, Symbol, Term, Value};
 
-use std::collections::HashSet;
+use std::collections::{BTreeSet, HashSet};
 use std::fs::File;
 use std::hash::Hash;
 use std::io::Read;
@@ -46,6 +48,25 @@ impl<T: FromPolar> FromPolar for Action<T> {
     }
 }
 
+/// Read `.polar` files into sources.
+fn read_sources<P: AsRef<std::path::Path>>(filenames: Vec<P>) -> crate::Result<Vec<Source>> {
+    let mut sources = Vec::with_capacity(filenames.len());
+
+    for file in filenames {
+        let file = file.as_ref();
+        let filename = file.to_string_lossy().into_owned();
+        if !file.extension().map_or(false, |ext| ext == "polar") {
+            return Err(crate::OsoError::IncorrectFileType { filename });
+        }
+        let mut f = File::open(&file)?;
+        let mut src = String::new();
+        f.read_to_string(&mut src)?;
+        sources.push(Source::new_with_name(filename, src));
+    }
+
+    Ok(sources)
+}
+
 impl Oso {
     /// Create a new instance of Oso. Each instance is separate and can have different rules and classes loaded into it.
     pub fn new() -> Self {
@@ -129,6 +150,80 @@ impl Oso {
         Ok(set)
     }
 
+    /// Get the resources in `domain` that actor is allowed to take action on, in domain order.
+    ///
+    /// Meant for small in-memory domains, like a list of feature names. The whole domain is
+    /// checked in a single query, and a resource that is allowed in several ways is only
+    /// returned once.
+    /// # Examples
+    /// ```ignore
+    /// oso.load_str(r#"allow(_actor: User, "use", feature) if feature in ["search", "export"];"#);
+    ///
+    /// let features = oso.enumerate_allowed(actor, "use", vec!["search", "billing", "export"])?;
+    /// assert_eq!(features, vec!["search", "export"]);
+    /// ```
+    pub fn enumerate_allowed<Actor, Action, Resource, I>(
+        &self,
+        actor: Actor,
+        action: Action,
+        domain: I,
+    ) -> crate::Result<Vec<Resource>>
+    where
+        Actor: ToPolar,
+        Action: ToPolar,
+        Resource: ToPolar + Clone,
+        I: IntoIterator<Item = Resource>,
+    {
+        let domain = domain.into_iter().collect::<Vec<_>>();
+        let mut query_host = self.host.clone();
+        let var = |name: &str| Term::new_from_ffi(Value::Variable(Symbol::new(name)));
+
+        // [index, resource] in [[0, domain[0]], [1, domain[1]], ...] and
+        //     allow(actor, action, resource)
+        let pairs = domain
+            .iter()
+            .enumerate()
+            .map(|(index, resource)| {
+                let resource = resource.clone().to_polar().to_term(&mut query_host);
+                Term::new_from_ffi(Value::List(vec![
+                    Term::new_from_ffi(Value::Number((index as i64).into())),
+                    resource,
+                ]))
+            })
+            .collect();
+        let choose = Operation {
+            operator: Operator::In,
+            args: vec![
+                Term::new_from_ffi(Value::List(vec![var("index"), var("resource")])),
+                Term::new_from_ffi(Value::List(pairs)),
+            ],
+        };
+        let allow = Call {
+            name: Symbol::new("allow"),
+            args: vec![
+                actor.to_polar().to_term(&mut query_host),
+                action.to_polar().to_term(&mut query_host),
+                var("resource"),
+            ],
+            kwargs: None,
+        };
+        let query_term = Term::new_from_ffi(Value::Expression(Operation {
+            operator: Operator::And,
+            args: vec![
+                Term::new_from_ffi(Value::Expression(choose)),
+                Term::new_from_ffi(Value::Call(allow)),
+            ],
+        }));
+        let query = self.inner.new_query_from_term(query_term, false);
+        check_messages!(self.inner);
+
+        let mut allowed = BTreeSet::new();
+        for result in Query::new(query, query_host) {
+            allowed.insert(result?.get_typed::<i64>("index")? as usize);
+        }
+        Ok(allowed.into_iter().map(|i| domain[i].clone()).collect())
+    }
+
     /// Clear out all files and rules that have been loaded.
     pub fn clear_rules(&mut self) -> crate::Result<()> {
         self.inner.clear_rules();
@@ -175,23 +270,20 @@ impl Oso {
             return Ok(());
         }
 
-        let mut sources = Vec::with_capacity(filenames.len());
-
-        for file in filenames {
-            let file = file.as_ref();
-            let filename = file.to_string_lossy().into_owned();
-            if !file.extension().map_or(false, |ext| ext == "polar") {
-                return Err(crate::OsoError::IncorrectFileType { filename });
-            }
-            let mut f = File::open(&file)?;
-            let mut src = String::new();
-            f.read_to_string(&mut src)?;
-            sources.push(Source::new_with_name(filename, src));
-        }
-
+        let sources = read_sources(filenames)?;
         self.load_sources(sources)
     }
 
+    /// Check files containing Polar rules without loading them, returning the diagnostics in
+    /// SARIF format for code-scanning tools.
+    pub fn diagnostics_sarif<P: AsRef<std::path::Path>>(
+        &self,
+        filenames: Vec<P>,
+    ) -> crate::Result<SarifLog> {
+        let sources = read_sources(filenames)?;
+        Ok(self.inner.diagnostics_sarif(sources))
+    }
+
     /// Load a string of polar source directly.
     /// # Examples
     /// ```ignore
@@ -202,6 +294,25 @@ impl Oso {
         self.load_sources(vec![Source::new(src)])
     }
 
+    /// Define rules typed interactively, e.g., at the REPL, alongside the loaded policy.
+    /// See [`ReplSession`].
+    pub fn define_rules(&self, session: &mut ReplSession, src: &str) -> crate::Result<()> {
+        session.define(&self.inner, src)?;
+        check_messages!(self.inner);
+        Ok(())
+    }
+
+    /// Remove the rules named `name` that were defined with `session`. Returns `false` if there
+    /// weren't any.
+    pub fn undefine_rule(&self, session: &mut ReplSession, name: &str) -> crate::Result<bool> {
+        Ok(session.undefine(&self.inner, name)?)
+    }
+
+    /// The heads of the loaded rules and where each was defined.
+    pub fn loaded_rules(&self) -> Vec<String> {
+        repl::loaded_rules(&self.inner)
+    }
+
     /// Query the knowledge base. This can be an allow query or any other polar expression.
     /// # Examples
     /// ```ignore
@@ -229,7 +340,7 @@ impl Oso {
             .map(|value| value.to_term(&mut query_host))
             .collect();
         let query_value = Value::Call(Call {
-            name: Symbol(name.to_string()),
+            name: Symbol::new(name),
             args,
             kwargs: None,
         });
@@ -249,9 +360,22 @@ impl Oso {
         for hook in &class.register_hooks {
             hook.call(self)?;
         }
+        for method in &class.effectful_methods {
+            self.inner.register_effectful_method(Symbol::new(method));
+        }
+        for method in &class.nondeterministic_methods {
+            self.inner
+                .register_nondeterministic_method(Symbol::new(method));
+        }
         self.register_constant(class, &class_name)
     }
 
+    /// Replace the cache used for the results of methods registered with
+    /// `ClassBuilder::cache_method`. Defaults to an in-memory `LruCallCache`.
+    pub fn set_call_cache<C: crate::CallCache + 'static>(&mut self, cache: C) {
+        self.host.set_call_cache(Arc::new(cache));
+    }
+
     /// Register a rust type as a Polar constant.
     /// See [`oso::Class`] docs.
     pub fn register_constant<V: crate::host::ToPolar + Send + Sync>(
@@ -259,10 +383,8 @@ impl Oso {
         value: V,
         name: &str,
     ) -> crate::Result<()> {
-        self.inner.register_constant(
-            Symbol(name.to_string()),
-            value.to_polar().to_term(&mut self.host),
-        )?;
+        self.inner
+            .register_constant(Symbol::new(name), value.to_polar().to_term(&mut self.host))?;
         Ok(())
     }
 }
@@ -0,0 +1,216 @@
+//! Sharding queries across several `Oso` instances that share a policy.
+//!
+//! Clones of an `Oso` share one knowledge base, and every query briefly locks it for writing
+//! while it is set up. When many threads query one instance, a burst of slow queries can hold
+//! up cheap `is_allowed` checks behind them. An [`OsoPool`] holds independent instances built
+//! from the same setup code and sends each query to the least busy one.
+
+use std::ops::Deref;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+use crate::{Oso, ToPolar};
+
+struct Shard {
+    oso: Oso,
+    in_flight: AtomicUsize,
+    checkouts: AtomicU64,
+}
+
+/// A fixed set of independent `Oso` instances with the same policy and classes.
+///
+/// # Examples
+/// ```ignore
+/// let pool = OsoPool::new(4, || {
+///     let mut oso = Oso::new();
+///     oso.register_class(User::get_polar_class())?;
+///     oso.load_files(vec!["policy.polar"])?;
+///     Ok(oso)
+/// })?;
+///
+/// // Cheap checks are routed around shards that are busy with slow queries.
+/// assert!(pool.is_allowed(user, "read", document)?);
+/// ```
+pub struct OsoPool {
+    shards: Vec<Shard>,
+    next: AtomicUsize,
+    contended_checkouts: AtomicU64,
+}
+
+impl OsoPool {
+    /// Create a pool of `shards` instances, each set up by `build`.
+    ///
+    /// Every instance must be set up the same way, since any of them may answer a query.
+    pub fn new<F>(shards: usize, mut build: F) -> crate::Result<Self>
+    where
+        F: FnMut() -> crate::Result<Oso>,
+    {
+        let shards = (0..shards.max(1))
+            .map(|_| {
+                build().map(|oso| Shard {
+                    oso,
+                    in_flight: AtomicUsize::new(0),
+                    checkouts: AtomicU64::new(0),
+                })
+            })
+            .collect::<crate::Result<_>>()?;
+        Ok(Self {
+            shards,
+            next: AtomicUsize::new(0),
+            contended_checkouts: AtomicU64::new(0),
+        })
+    }
+
+    /// Check out the instance with the fewest queries in flight.
+    ///
+    /// The instance counts as busy until the returned guard is dropped, so keep the guard alive
+    /// until any query made through it has been consumed.
+    pub fn get(&self) -> PooledOso<'_> {
+        // Start the scan at a rotating offset so that idle shards share the load evenly.
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        let len = self.shards.len();
+        let shard = (0..len)
+            .map(|i| &self.shards[(start + i) % len])
+            .min_by_key(|shard| shard.in_flight.load(Ordering::Relaxed))
+            .expect("pool has at least one shard");
+
+        if shard.in_flight.fetch_add(1, Ordering::AcqRel) > 0 {
+            self.contended_checkouts.fetch_add(1, Ordering::Relaxed);
+        }
+        shard.checkouts.fetch_add(1, Ordering::Relaxed);
+        PooledOso { shard }
+    }
+
+    /// [`Oso::is_allowed`] on the least busy instance.
+    pub fn is_allowed<Actor, Action, Resource>(
+        &self,
+        actor: Actor,
+        action: Action,
+        resource: Resource,
+    ) -> crate::Result<bool>
+    where
+        Actor: ToPolar,
+        Action: ToPolar,
+        Resource: ToPolar,
+    {
+        self.get().is_allowed(actor, action, resource)
+    }
+
+    /// Number of instances in the pool.
+    pub fn len(&self) -> usize {
+        self.shards.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shards.is_empty()
+    }
+
+    /// A snapshot of how busy the pool's instances are.
+    pub fn metrics(&self) -> PoolMetrics {
+        PoolMetrics {
+            checkouts: self
+                .shards
+                .iter()
+                .map(|shard| shard.checkouts.load(Ordering::Relaxed))
+                .collect(),
+            in_flight: self
+                .shards
+                .iter()
+                .map(|shard| shard.in_flight.load(Ordering::Relaxed))
+                .collect(),
+            contended_checkouts: self.contended_checkouts.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Contention metrics for an [`OsoPool`]. The per-instance vectors are indexed by shard.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PoolMetrics {
+    /// Number of times each instance has been checked out.
+    pub checkouts: Vec<u64>,
+    /// Number of guards currently held for each instance.
+    pub in_flight: Vec<usize>,
+    /// Number of checkouts that found every instance busy and had to share one.
+    ///
+    /// If this grows with the total number of checkouts, the pool needs more instances.
+    pub contended_checkouts: u64,
+}
+
+impl PoolMetrics {
+    /// Total number of checkouts across the pool.
+    pub fn total_checkouts(&self) -> u64 {
+        self.checkouts.iter().sum()
+    }
+}
+
+/// An `Oso` instance checked out of an [`OsoPool`]. Returned to the pool when dropped.
+pub struct PooledOso<'a> {
+    shard: &'a Shard,
+}
+
+impl Deref for PooledOso<'_> {
+    type Target = Oso;
+
+    fn deref(&self) -> &Oso {
+        &self.shard.oso
+    }
+}
+
+impl Drop for PooledOso<'_> {
+    fn drop(&mut self) {
+        self.shard.in_flight.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+// Make sure the pool can be shared between threads
+#[cfg(test)]
+static_assertions::assert_impl_all!(OsoPool: Send, Sync);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pool(shards: usize) -> OsoPool {
+        OsoPool::new(shards, || {
+            let mut oso = Oso::new();
+            oso.load_str(r#"allow(_actor, "read", _resource);"#)?;
+            Ok(oso)
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_pool_routes_around_busy_shards() {
+        let pool = pool(2);
+        let slow = pool.get();
+        assert!(slow.is_allowed("alice", "read", "doc").unwrap());
+
+        for _ in 0..3 {
+            assert!(pool.is_allowed("alice", "read", "doc").unwrap());
+            assert!(!pool.is_allowed("alice", "write", "doc").unwrap());
+        }
+
+        let metrics = pool.metrics();
+        assert_eq!(metrics.in_flight, vec![1, 0]);
+        assert_eq!(metrics.checkouts, vec![1, 6]);
+        assert_eq!(metrics.contended_checkouts, 0);
+
+        let _other = pool.get();
+        let _shared = pool.get();
+        let metrics = pool.metrics();
+        assert_eq!(metrics.total_checkouts(), 9);
+        assert_eq!(metrics.contended_checkouts, 1);
+
+        drop(slow);
+        assert_eq!(pool.metrics().in_flight.iter().sum::<usize>(), 2);
+    }
+
+    #[test]
+    fn test_pool_propagates_setup_errors() {
+        let result = OsoPool::new(2, || {
+            let mut oso = Oso::new();
+            oso.load_str("allow(")?;
+            Ok(oso)
+        });
+        assert!(result.is_err());
+    }
+}
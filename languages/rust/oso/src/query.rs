@@ -1,5 +1,6 @@
 use std::collections::BTreeMap;
 use std::collections::HashMap;
+use std::ops::ControlFlow;
 
 use crate::errors::OsoError;
 use crate::host::{Host, Instance, PolarIterator};
@@ -37,77 +38,124 @@ impl Query {
 
     pub fn next_result(&mut self) -> Option<crate::Result<ResultSet>> {
         loop {
-            let event = self.inner.next()?;
-            check_messages!(self.inner);
-            if let Err(e) = event {
-                return Some(Err(e.into()));
-            }
-            let event = event.unwrap();
-            tracing::debug!(event=?event);
-            let result = match event {
-                QueryEvent::None => Ok(()),
-                QueryEvent::Done { .. } => return None,
-                QueryEvent::Result { bindings, .. } => {
-                    return Some(ResultSet::from_bindings(bindings, self.host.clone()));
-                }
-                QueryEvent::MakeExternal {
-                    instance_id,
-                    constructor,
-                } => self.handle_make_external(instance_id, constructor),
-                QueryEvent::NextExternal { call_id, iterable } => {
-                    self.handle_next_external(call_id, iterable)
-                }
-                QueryEvent::ExternalCall {
-                    call_id,
-                    instance,
-                    attribute,
-                    args,
-                    kwargs,
-                } => self.handle_external_call(call_id, instance, attribute, args, kwargs),
-                QueryEvent::ExternalOp {
-                    call_id,
-                    operator,
-                    args,
-                } => self.handle_external_op(call_id, operator, args),
-                QueryEvent::ExternalIsa {
-                    call_id,
-                    instance,
-                    class_tag,
-                } => self.handle_external_isa(call_id, instance, class_tag),
-                QueryEvent::ExternalIsSubSpecializer {
-                    call_id,
-                    instance_id,
-                    left_class_tag,
-                    right_class_tag,
-                } => self.handle_external_is_subspecializer(
-                    call_id,
-                    instance_id,
-                    left_class_tag,
-                    right_class_tag,
-                ),
-                QueryEvent::Debug { message } => self.handle_debug(message),
-                QueryEvent::ExternalIsSubclass {
-                    call_id,
-                    left_class_tag,
-                    right_class_tag,
-                } => self.handle_external_is_subclass(call_id, left_class_tag, right_class_tag),
-                event => unimplemented!("Unhandled event {:?}", event),
+            let event = match self.next_event()? {
+                Ok(event) => event,
+                Err(e) => return Some(Err(e)),
             };
+            if let ControlFlow::Break(result) = self.handle_event(event) {
+                return result;
+            }
+        }
+    }
+
+    /// The next event from the VM, or `None` once the query is done.
+    pub(crate) fn next_event(&mut self) -> Option<crate::Result<QueryEvent>> {
+        let event = self.inner.next()?;
+        check_messages!(self.inner);
+        let event = event.map_err(OsoError::from);
+        tracing::debug!(event=?event);
+        Some(event)
+    }
+
+    /// Answer `event` with the host, breaking with the next result, error or end of the query.
+    pub(crate) fn handle_event(
+        &mut self,
+        event: QueryEvent,
+    ) -> ControlFlow<Option<crate::Result<ResultSet>>> {
+        let result = match event {
+            QueryEvent::None => Ok(()),
+            QueryEvent::Done { .. } => return ControlFlow::Break(None),
+            QueryEvent::Result { bindings, .. } => {
+                return ControlFlow::Break(Some(ResultSet::from_bindings(
+                    bindings,
+                    self.host.clone(),
+                )));
+            }
+            QueryEvent::MakeExternal {
+                instance_id,
+                constructor,
+            } => self.handle_make_external(instance_id, constructor),
+            QueryEvent::NextExternal { call_id, iterable } => {
+                self.handle_next_external(call_id, iterable)
+            }
+            QueryEvent::ExternalCall {
+                call_id,
+                instance,
+                attribute,
+                args,
+                kwargs,
+            } => self.handle_external_call(call_id, instance, attribute, args, kwargs),
+            QueryEvent::ExternalOp {
+                call_id,
+                operator,
+                args,
+            } => self.handle_external_op(call_id, operator, args),
+            QueryEvent::ExternalIsa {
+                call_id,
+                instance,
+                class_tag,
+            } => self.handle_external_isa(call_id, instance, class_tag),
+            QueryEvent::ExternalIsSubSpecializer {
+                call_id,
+                instance_id,
+                left_class_tag,
+                right_class_tag,
+            } => self.handle_external_is_subspecializer(
+                call_id,
+                instance_id,
+                left_class_tag,
+                right_class_tag,
+            ),
+            QueryEvent::Debug { message } => self.handle_debug(message),
+            QueryEvent::ExternalIsSubclass {
+                call_id,
+                left_class_tag,
+                right_class_tag,
+            } => self.handle_external_is_subclass(call_id, left_class_tag, right_class_tag),
+            event => unimplemented!("Unhandled event {:?}", event),
+        };
+        self.handle_result(result)
+    }
 
-            match result {
-                // Only call errors get passed back.
-                Err(call_error @ OsoError::InvalidCallError { .. }) => {
-                    tracing::error!("application invalid call error {}", call_error);
-                    if let Err(e) = self.application_error(call_error) {
-                        return Some(Err(e));
-                    }
+    /// Pass call errors back to the VM and return all others.
+    fn handle_result(
+        &mut self,
+        result: crate::Result<()>,
+    ) -> ControlFlow<Option<crate::Result<ResultSet>>> {
+        match result {
+            // Only call errors get passed back.
+            Err(call_error @ OsoError::InvalidCallError { .. }) => {
+                tracing::error!("application invalid call error {}", call_error);
+                if let Err(e) = self.application_error(call_error) {
+                    return ControlFlow::Break(Some(Err(e)));
                 }
-                // All others get returned.
-                Err(err) => return Some(Err(err)),
-                // Continue on ok
-                Ok(_) => {}
             }
+            // All others get returned.
+            Err(err) => return ControlFlow::Break(Some(Err(err))),
+            // Continue on ok
+            Ok(_) => {}
         }
+        ControlFlow::Continue(())
+    }
+
+    /// Answer the external call `call_id` with `result`, as computed outside of the host.
+    #[cfg(feature = "stream")]
+    pub(crate) fn answer_call(
+        &mut self,
+        call_id: u64,
+        result: crate::Result<Option<PolarValue>>,
+    ) -> ControlFlow<Option<crate::Result<ResultSet>>> {
+        let result = match result {
+            Ok(Some(value)) => self.call_result(call_id, value),
+            Ok(None) => self.call_result_none(call_id),
+            Err(e) => self.call_result_none(call_id).and(Err(e)),
+        };
+        self.handle_result(result)
+    }
+
+    #[cfg(feature = "stream")]
+    pub(crate) fn host(&self) -> &Host {
+        &self.host
     }
 
     fn question_result(&mut self, call_id: u64, result: bool) -> crate::Result<()> {
@@ -144,7 +192,7 @@ impl Query {
                         .iter()
                         .map(|term| PolarValue::from_term(term, &self.host))
                         .collect::<crate::Result<Vec<PolarValue>>>()?;
-                    self.host.make_instance(&name.0, args, instance_id)
+                    self.host.make_instance(name.as_str(), args, instance_id)
                 }
             }
             _ => lazy_error!("invalid type for constructing an instance -- internal error"),
@@ -186,17 +234,29 @@ impl Query {
         }
         tracing::trace!(call_id, name = %name, args = ?args, "call");
         let instance = Instance::from_polar(PolarValue::from_term(&instance, &self.host)?)?;
+        let cache_entry = self.call_key(&instance, name.as_str(), args.as_deref())?;
+        if let Some((key, _)) = &cache_entry {
+            if let Some(cached) = self.host.call_cache().get(key) {
+                tracing::trace!(call_id, name = %name, "cached");
+                return self.call_result(call_id, cached);
+            }
+        }
         let result = if let Some(args) = args {
             let args = args
                 .iter()
                 .map(|v| PolarValue::from_term(v, &self.host))
                 .collect::<crate::Result<Vec<PolarValue>>>()?;
-            instance.call(&name.0, args, &mut self.host)
+            instance.call(name.as_str(), args, &mut self.host)
         } else {
-            instance.get_attr(&name.0, &mut self.host)
+            instance.get_attr(name.as_str(), &mut self.host)
         };
         match result {
-            Ok(t) => self.call_result(call_id, t),
+            Ok(t) => {
+                if let Some((key, ttl)) = cache_entry {
+                    self.host.call_cache().insert(key, t.clone(), ttl);
+                }
+                self.call_result(call_id, t)
+            }
             Err(e) => {
                 self.call_result_none(call_id)?;
                 Err(e)
@@ -204,6 +264,21 @@ impl Query {
         }
     }
 
+    /// Return the call cache key for calling `name` on `instance`, if its result is cached.
+    fn call_key(
+        &self,
+        instance: &Instance,
+        name: &str,
+        args: Option<&[Term]>,
+    ) -> crate::Result<Option<(crate::CallKey, std::time::Duration)>> {
+        let class = match instance.class(&self.host) {
+            Ok(class) => class,
+            Err(_) => return Ok(None),
+        };
+        let args = args.map(|args| args.iter().map(ToString::to_string).collect());
+        class.call_key(&self.host, instance, name, args)
+    }
+
     fn handle_external_op(
         &mut self,
         call_id: u64,
@@ -213,8 +288,8 @@ impl Query {
         assert_eq!(args.len(), 2);
         let res = {
             let args = [
-                Instance::from_polar(PolarValue::from_term(&args[0], &self.host)?)?,
-                Instance::from_polar(PolarValue::from_term(&args[1], &self.host)?)?,
+                PolarValue::from_term(&args[0], &self.host)?,
+                PolarValue::from_term(&args[1], &self.host)?,
             ];
             self.host.operator(operator, args)?
         };
@@ -229,9 +304,10 @@ impl Query {
         class_tag: Symbol,
     ) -> crate::Result<()> {
         tracing::debug!(instance = ?instance, class = %class_tag, "isa");
-        let res = self
-            .host
-            .isa(PolarValue::from_term(&instance, &self.host)?, &class_tag.0)?;
+        let res = self.host.isa(
+            PolarValue::from_term(&instance, &self.host)?,
+            class_tag.as_str(),
+        )?;
         self.question_result(call_id, res)?;
         Ok(())
     }
@@ -243,9 +319,11 @@ impl Query {
         left_class_tag: Symbol,
         right_class_tag: Symbol,
     ) -> crate::Result<()> {
-        let res = self
-            .host
-            .is_subspecializer(instance_id, &left_class_tag.0, &right_class_tag.0);
+        let res = self.host.is_subspecializer(
+            instance_id,
+            left_class_tag.as_str(),
+            right_class_tag.as_str(),
+        );
         self.question_result(call_id, res)?;
         Ok(())
     }
@@ -299,11 +377,11 @@ This may mean you performed an operation in your policy over an unbound variable
 
     /// Return the keys in bindings.
     pub fn keys(&self) -> Box<dyn std::iter::Iterator<Item = &str> + '_> {
-        Box::new(self.bindings.keys().map(|sym| sym.0.as_ref()))
+        Box::new(self.bindings.keys().map(|sym| sym.as_str()))
     }
 
     pub fn iter_bindings(&self) -> Box<dyn std::iter::Iterator<Item = (&str, &Value)> + '_> {
-        Box::new(self.bindings.iter().map(|(k, v)| (k.0.as_ref(), v.value())))
+        Box::new(self.bindings.iter().map(|(k, v)| (k.as_str(), v.value())))
     }
 
     pub fn is_empty(&self) -> bool {
@@ -312,7 +390,7 @@ This may mean you performed an operation in your policy over an unbound variable
 
     pub fn get(&self, name: &str) -> Option<crate::PolarValue> {
         self.bindings
-            .get(&Symbol(name.to_string()))
+            .get(&Symbol::new(name))
             .map(|t| PolarValue::from_term(t, &self.host).unwrap())
     }
 
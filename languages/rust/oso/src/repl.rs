@@ -7,9 +7,10 @@ use rustyline::Editor;
 use rustyline_derive::{Completer, Helper, Highlighter, Hinter};
 
 use oso::Oso;
+use polar_core::repl::{classify_input, ReplInput, ReplSession};
 
 use std::env;
-use std::fs::OpenOptions;
+use std::fs::{self, OpenOptions};
 
 /// Build the App for handling command line parameters
 fn build_app() -> App<'static, 'static> {
@@ -22,6 +23,13 @@ fn build_app() -> App<'static, 'static> {
                 .multiple(true)
                 .help("Specify one or more .polar files to load"),
         )
+        .arg(
+            Arg::with_name("format")
+                .long("format")
+                .takes_value(true)
+                .possible_values(&["sarif"])
+                .help("Print diagnostics for FILES in this format instead of starting the REPL"),
+        )
 }
 
 /// Attempt to create a new temporary directory to store
@@ -42,18 +50,49 @@ pub fn try_create_history_file() -> Option<std::path::PathBuf> {
 /// Provides input validation.
 ///
 /// Currently, is only used to determine whether a line is
-/// incomplete (missing a ';').
+/// incomplete, e.g., a rule definition missing its ';'.
 #[derive(Completer, Helper, Highlighter, Hinter)]
 struct InputValidator {}
 
 impl Validator for InputValidator {
     fn validate(&self, ctx: &mut ValidationContext) -> Result<ValidationResult, ReadlineError> {
-        let _input = ctx.input();
-        // if !input.ends_with(';') {
-        //     return Ok(ValidationResult::Incomplete);
-        // }
-        Ok(ValidationResult::Valid(None))
+        let input = ctx.input();
+        if input.starts_with(':') || classify_input(input) != ReplInput::Incomplete {
+            Ok(ValidationResult::Valid(None))
+        } else {
+            Ok(ValidationResult::Incomplete)
+        }
+    }
+}
+
+const HELP: &str = "\
+Enter a query to run it, or rules ending in ';' to define them.
+
+:rules        list the loaded rules
+:undef NAME   remove the rules named NAME that were defined here
+:dump         print the rules defined here
+:save FILE    write the rules defined here to FILE
+:help         show this message";
+
+/// Run a REPL command, e.g., `:rules`.
+fn run_command(oso: &Oso, session: &mut ReplSession, command: &str) -> anyhow::Result<()> {
+    let mut words = command.split_whitespace();
+    match (words.next(), words.next()) {
+        (Some("rules"), None) => {
+            for rule in oso.loaded_rules() {
+                println!("{}", rule);
+            }
+        }
+        (Some("undef"), Some(name)) => {
+            if !oso.undefine_rule(session, name)? {
+                println!("No rules named {} were defined here.", name);
+            }
+        }
+        (Some("dump"), None) => print!("{}", session.dump()),
+        (Some("save"), Some(filename)) => fs::write(filename, session.dump())?,
+        _ => println!("{}", HELP),
     }
+    Ok(())
 }
 
 pub struct Repl {
@@ -110,10 +149,22 @@ impl Drop for Repl {
 
 pub fn main() -> anyhow::Result<()> {
     tracing_subscriber::fmt::init();
-    let mut repl = Repl::new();
     let mut oso = Oso::new();
 
     let matches = build_app().get_matches();
+    if matches.value_of("format") == Some("sarif") {
+        let files = matches.values_of("FILES").into_iter().flatten().collect();
+        let log = oso.diagnostics_sarif(files)?;
+        println!("{}", serde_json::to_string_pretty(&log)?);
+        let has_errors = log
+            .runs
+            .iter()
+            .any(|run| run.results.iter().any(|result| result.level == "error"));
+        std::process::exit(if has_errors { 1 } else { 0 });
+    }
+
+    let mut repl = Repl::new();
+    let mut session = ReplSession::new();
     if matches.is_present("FILES") {
         oso.load_files(matches.values_of("FILES").unwrap().collect())?;
     }
@@ -128,6 +179,24 @@ pub fn main() -> anyhow::Result<()> {
             }
         };
 
+        if let Some(command) = input.trim().strip_prefix(':') {
+            if let Err(e) = run_command(&oso, &mut session, command) {
+                println!("{}", e);
+            }
+            continue;
+        }
+        let input = match classify_input(&input) {
+            ReplInput::Definitions(src) => {
+                if let Err(e) = oso.define_rules(&mut session, &src) {
+                    println!("{}", e);
+                }
+                continue;
+            }
+            ReplInput::Query(query) if query.is_empty() => continue,
+            ReplInput::Query(query) => query,
+            ReplInput::Incomplete => input,
+        };
+
         let query = match oso.query(&input) {
             Err(e) => {
                 println!("{}", e);
@@ -0,0 +1,163 @@
+//! Driving a query from async code.
+//!
+//! A [`QueryStream`] wraps a [`Query`] and exposes its results through
+//! [`QueryStream::poll_next`], which has the signature of `futures::Stream::poll_next`. Events
+//! are answered by the host as they are for [`Query::next_result`], except that external calls
+//! can be answered by an async handler registered with
+//! [`QueryStream::register_external_call_handler`], e.g., to fetch attributes from a database.
+
+use std::future::Future;
+use std::ops::ControlFlow;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use polar_core::events::QueryEvent;
+
+use crate::query::{Query, ResultSet};
+use crate::PolarValue;
+
+/// An external call the query needs answered: look up `attribute` on `instance`, calling it
+/// with `args` if they are present.
+#[derive(Clone, Debug)]
+pub struct ExternalCall {
+    pub instance: PolarValue,
+    pub attribute: String,
+    pub args: Option<Vec<PolarValue>>,
+}
+
+/// The answer to an [`ExternalCall`]. `Ok(None)` means the call has no result.
+pub type ExternalCallResult = crate::Result<Option<PolarValue>>;
+
+type CallFuture = Pin<Box<dyn Future<Output = ExternalCallResult>>>;
+type CallHandler = Box<dyn FnMut(ExternalCall) -> CallFuture>;
+
+/// A [`Query`] driven as a stream of [`ResultSet`]s.
+///
+/// Without a registered handler, every event is answered synchronously by the host, so polling
+/// is always ready. The stream ends after the last result or an error.
+pub struct QueryStream {
+    query: Query,
+    handler: Option<CallHandler>,
+    pending: Option<(u64, CallFuture)>,
+    done: bool,
+}
+
+impl QueryStream {
+    pub fn new(query: Query) -> Self {
+        Self {
+            query,
+            handler: None,
+            pending: None,
+            done: false,
+        }
+    }
+
+    /// Answer external calls with `handler` instead of the classes registered with the host.
+    /// The query is suspended until the future it returns completes. Errors are reported as
+    /// they are for the host: invalid calls to the query, others to the consumer of the stream.
+    pub fn register_external_call_handler<F, Fut>(&mut self, mut handler: F)
+    where
+        F: FnMut(ExternalCall) -> Fut + 'static,
+        Fut: Future<Output = ExternalCallResult> + 'static,
+    {
+        self.handler = Some(Box::new(move |call| Box::pin(handler(call))));
+    }
+
+    pub fn into_query(self) -> Query {
+        self.query
+    }
+
+    /// Attempt to pull out the next result, registering the current task for wakeup if an
+    /// external call handler is still running.
+    pub fn poll_next(&mut self, cx: &mut Context<'_>) -> Poll<Option<crate::Result<ResultSet>>> {
+        loop {
+            if self.done {
+                return Poll::Ready(None);
+            }
+
+            let flow = if let Some((call_id, future)) = self.pending.as_mut() {
+                let result = match future.as_mut().poll(cx) {
+                    Poll::Pending => return Poll::Pending,
+                    Poll::Ready(result) => result,
+                };
+                let call_id = *call_id;
+                self.pending = None;
+                self.query.answer_call(call_id, result)
+            } else {
+                match self.query.next_event() {
+                    Some(Ok(event)) => self.handle_event(event),
+                    Some(Err(e)) => ControlFlow::Break(Some(Err(e))),
+                    None => ControlFlow::Break(None),
+                }
+            };
+            if let ControlFlow::Break(result) = flow {
+                self.done = !matches!(result, Some(Ok(_)));
+                return Poll::Ready(result);
+            }
+        }
+    }
+
+    /// A future resolving to the next result, or `None` once the stream has ended.
+    pub fn next_result(&mut self) -> NextResult<'_> {
+        NextResult { stream: self }
+    }
+
+    fn handle_event(&mut self, event: QueryEvent) -> ControlFlow<Option<crate::Result<ResultSet>>> {
+        let handler = match self.handler.as_mut() {
+            Some(handler) => handler,
+            None => return self.query.handle_event(event),
+        };
+        match event {
+            QueryEvent::ExternalCall {
+                call_id,
+                instance,
+                attribute,
+                args,
+                kwargs: None,
+            } => {
+                let host = self.query.host();
+                let call = PolarValue::from_term(&instance, host).and_then(|instance| {
+                    let args = args
+                        .map(|args| {
+                            args.iter()
+                                .map(|arg| PolarValue::from_term(arg, host))
+                                .collect()
+                        })
+                        .transpose()?;
+                    Ok(ExternalCall {
+                        instance,
+                        attribute: attribute.as_str().to_owned(),
+                        args,
+                    })
+                });
+                match call {
+                    Ok(call) => {
+                        self.pending = Some((call_id, handler(call)));
+                        ControlFlow::Continue(())
+                    }
+                    Err(e) => self.query.answer_call(call_id, Err(e)),
+                }
+            }
+            event => self.query.handle_event(event),
+        }
+    }
+}
+
+impl From<Query> for QueryStream {
+    fn from(query: Query) -> Self {
+        Self::new(query)
+    }
+}
+
+/// Future returned by [`QueryStream::next_result`].
+pub struct NextResult<'a> {
+    stream: &'a mut QueryStream,
+}
+
+impl Future for NextResult<'_> {
+    type Output = Option<crate::Result<ResultSet>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.stream.poll_next(cx)
+    }
+}
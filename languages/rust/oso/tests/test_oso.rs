@@ -222,3 +222,32 @@ fn test_get_allowed_actions() -> oso::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_enumerate_allowed() -> oso::Result<()> {
+    common::setup();
+    let mut oso = Oso::new();
+
+    oso.register_class(User::get_polar_class()).unwrap();
+    oso.register_class(Widget::get_polar_class()).unwrap();
+
+    oso.load_str(
+        r#"allow(_actor: User{name: "sally"}, "use", feature) if
+           feature in ["search", "export"];
+           allow(_actor: User{name: "sally"}, "use", "search");
+           allow(_actor: User, "view", widget: Widget) if widget.id > 1;"#,
+    )?;
+
+    let sally = User::new(String::from("sally"));
+    let features =
+        oso.enumerate_allowed(sally.clone(), "use", vec!["export", "billing", "search"])?;
+    assert_eq!(features, vec!["export", "search"]);
+
+    let widgets = oso.enumerate_allowed(sally.clone(), "view", (1..=3).map(Widget::new))?;
+    assert_eq!(widgets, vec![Widget::new(2), Widget::new(3)]);
+
+    let none = oso.enumerate_allowed(sally, "use", Vec::<String>::new())?;
+    assert!(none.is_empty());
+
+    Ok(())
+}
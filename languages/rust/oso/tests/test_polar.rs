@@ -1,12 +1,13 @@
 /// Common tests for all integrations.
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::io::Write;
 use std::path::{Path, PathBuf};
+use std::time::{Duration as StdDuration, UNIX_EPOCH};
 
-use oso::{Class, FromPolar, Oso, OsoError, PolarClass, PolarValue};
+use oso::{BigInt, Class, Decimal, FromPolar, Oso, OsoError, PolarClass, PolarValue};
 use polar_core::error as polar_error;
 
-use maplit::hashmap;
+use maplit::{hashmap, hashset};
 
 mod common;
 
@@ -213,6 +214,32 @@ fn test_load_file_error_contains_filename() {
     }
 }
 
+#[test]
+fn test_diagnostics_sarif() {
+    common::setup();
+    let oso = test_oso();
+
+    let mut tempfile = tempfile::Builder::new()
+        .suffix(".polar")
+        .tempfile()
+        .unwrap();
+    let file = tempfile.as_file_mut();
+
+    writeln!(file, "allow(_, _, _) if f(1);").unwrap();
+    file.sync_all().unwrap();
+
+    let log = oso.oso.diagnostics_sarif(vec![tempfile.path()]).unwrap();
+    let results = &log.runs[0].results;
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].rule_id, "ValidationError::UndefinedRuleCall");
+    let location = &results[0].locations[0].physical_location;
+    assert_eq!(
+        location.artifact_location.as_ref().unwrap().uri,
+        tempfile.path().to_string_lossy()
+    );
+    assert_eq!(location.region.start_column, 19);
+}
+
 #[test]
 fn test_load_file_extension_check() {
     common::setup();
@@ -852,6 +879,83 @@ fn test_nan_inf() -> oso::Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_big_integers_and_decimals() -> oso::Result<()> {
+    common::setup();
+
+    let mut oso = test_oso();
+    let big = "18446744073709551616".parse::<BigInt>().unwrap();
+    let price = "1.50".parse::<Decimal>().unwrap();
+    oso.oso.register_constant(big.clone(), "big")?;
+    oso.oso.register_constant(price.clone(), "price")?;
+
+    oso.qvar_one("x = big", "x", big.clone());
+    oso.qvar_one("x = big - 18446744073709551615", "x", BigInt::from(1));
+    oso.qvar_one("x = 9223372036854775807 + 1", "x", BigInt::from(1u64 << 63));
+    oso.qvar_one("x = price", "x", price);
+    oso.qvar_one("x = price * 2", "x", "3.00".parse::<Decimal>().unwrap());
+    oso.qeval("price = 1.5d");
+    oso.qeval("big matches Integer and price matches Decimal");
+    assert!(matches!(
+        oso.qvar::<PolarValue>("x = big", "x").pop(),
+        Some(PolarValue::BigInteger(i)) if i == big
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn test_system_times_and_durations() -> oso::Result<()> {
+    common::setup();
+
+    let mut oso = test_oso();
+    let created = UNIX_EPOCH + StdDuration::from_secs(1_704_067_200);
+    let ttl = StdDuration::from_secs(30 * 24 * 60 * 60);
+    oso.oso.register_constant(created, "created")?;
+    oso.oso.register_constant(ttl, "ttl")?;
+
+    oso.qeval(r#"created = datetime"2024-01-01""#);
+    oso.qeval(r#"ttl = duration"P30D""#);
+    oso.qeval("created matches DateTime and ttl matches Duration");
+    oso.qvar_one("x = created", "x", created);
+    oso.qvar_one("x = created + ttl", "x", created + ttl);
+    oso.qvar_one(
+        r#"x = ttl + duration"PT1.5S""#,
+        "x",
+        ttl + StdDuration::from_millis(1500),
+    );
+
+    // Negative durations have no `std::time::Duration` equivalent.
+    let mut query = oso.oso.query(r#"x = created - (created + ttl)"#)?;
+    let result = query.next().unwrap()?;
+    assert!(result.get_typed::<StdDuration>("x").is_err());
+    assert!(matches!(result.get("x"), Some(PolarValue::Duration(_))));
+
+    Ok(())
+}
+
+#[test]
+fn test_sets() -> oso::Result<()> {
+    common::setup();
+
+    let mut oso = test_oso();
+    let roles = PolarValue::Set(vec![
+        PolarValue::String("admin".to_owned()),
+        PolarValue::String("member".to_owned()),
+    ]);
+    oso.oso.register_constant(roles.clone(), "roles")?;
+
+    oso.qeval(r#"roles = {"member", "admin"} and roles matches Set"#);
+    oso.qvar_one("x = roles", "x", roles);
+    let roles = oso.qvar::<HashSet<String>>(r#"x = roles.union({"owner"})"#, "x");
+    assert_eq!(
+        roles,
+        vec![hashset! {"admin".to_owned(), "member".to_owned(), "owner".to_owned()}]
+    );
+
+    Ok(())
+}
+
 #[test]
 fn test_iterators() -> oso::Result<()> {
     common::setup();
@@ -992,3 +1096,80 @@ fn test_exclusive_isa() {
     // oso.qeval("x matches Integer and y matches String");
     // oso.qeval("x.a matches Integer and x.b matches String");
 }
+
+#[test]
+fn test_cached_methods() -> oso::Result<()> {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    common::setup();
+
+    #[derive(Clone, PolarClass)]
+    struct Repo {
+        id: i64,
+        #[polar(attribute)]
+        owner: i64,
+        calls: Arc<AtomicUsize>,
+    }
+
+    impl Repo {
+        fn size(&self, scale: i64) -> i64 {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            self.id * scale
+        }
+
+        fn owner_id(&self) -> i64 {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            self.owner + 1
+        }
+    }
+
+    let mut oso = OsoTest::new();
+    oso.oso.register_class(
+        Repo::get_polar_class_builder()
+            .add_method("size", Repo::size)
+            .add_method("uncached_size", Repo::size)
+            .add_method("owner", Repo::owner_id)
+            .set_cache_key(|repo: &Repo| repo.id)
+            .cache_method("size", Duration::from_secs(60))
+            .cache_method("owner", Duration::from_secs(60))
+            .build(),
+    )?;
+
+    let calls = Arc::new(AtomicUsize::new(0));
+    let repo = |id| Repo {
+        id,
+        owner: 100,
+        calls: calls.clone(),
+    };
+    oso.oso.register_constant(repo(1), "repo")?;
+    oso.oso.register_constant(repo(2), "other")?;
+
+    oso.qvar_one("x = repo.size(10)", "x", 10);
+    oso.qvar_one("x = repo.size(10)", "x", 10);
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+    // Different arguments and instances are cached separately.
+    oso.qvar_one("x = repo.size(20)", "x", 20);
+    oso.qvar_one("x = other.size(10)", "x", 20);
+    assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+    // Looking up an attribute isn't the same as calling a method of the same name.
+    oso.qvar_one("x = repo.owner()", "x", 101);
+    oso.qvar_one("x = repo.owner", "x", 100);
+    oso.qvar_one("x = repo.owner()", "x", 101);
+    assert_eq!(calls.load(Ordering::SeqCst), 4);
+
+    // Methods without a TTL are never cached.
+    oso.qvar_one("x = repo.uncached_size(10)", "x", 10);
+    oso.qvar_one("x = repo.uncached_size(10)", "x", 10);
+    assert_eq!(calls.load(Ordering::SeqCst), 6);
+
+    // Replacing the cache drops the cached results.
+    oso.oso.set_call_cache(oso::LruCallCache::new(16));
+    oso.qvar_one("x = repo.size(10)", "x", 10);
+    assert_eq!(calls.load(Ordering::SeqCst), 7);
+
+    Ok(())
+}
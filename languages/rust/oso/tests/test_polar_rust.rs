@@ -753,15 +753,84 @@ fn test_is_subclass() {
     test.qnull("x matches Foo and x matches Bar");
 }
 
+#[test]
+fn test_value_extension() {
+    common::setup();
+
+    #[derive(Clone, Debug, PartialEq, PolarClass)]
+    struct Version {
+        major: i64,
+        minor: i64,
+    }
+
+    impl Version {
+        fn new(major: i64, minor: i64) -> Self {
+            Self { major, minor }
+        }
+
+        fn parse(s: &str) -> Option<Self> {
+            let (major, minor) = s.split_once('.')?;
+            Some(Self::new(major.parse().ok()?, minor.parse().ok()?))
+        }
+    }
+
+    #[derive(Clone, PolarClass)]
+    struct Release {
+        #[polar(attribute)]
+        version: Version,
+    }
+
+    let mut test = OsoTest::new();
+    test.oso
+        .register_class(
+            Version::get_polar_class_builder()
+                .set_constructor(Version::new)
+                .set_value_extension(|version: &Version, other: &oso::PolarValue| {
+                    let other = match other {
+                        oso::PolarValue::String(s) => Version::parse(s)?,
+                        oso::PolarValue::Instance(i) => i.downcast::<Version>(None).ok()?.clone(),
+                        _ => return None,
+                    };
+                    Some((version.major, version.minor).cmp(&(other.major, other.minor)))
+                })
+                .build(),
+        )
+        .unwrap();
+    test.oso
+        .register_class(
+            Release::get_polar_class_builder()
+                .set_constructor(|version| Release { version })
+                .build(),
+        )
+        .unwrap();
+    test.load_str(r#"is_stable(_: Release{version: "1.2"});"#);
+
+    test.qeval(r#"new Version(1, 2) = "1.2""#);
+    test.qeval(r#""1.2" == new Version(1, 2)"#);
+    test.qeval("new Version(1, 2) < new Version(1, 10)");
+    test.qeval(r#"new Version(2, 0) >= "1.9""#);
+    test.qeval(r#"new Version(2, 0) != "two""#);
+    test.qeval(r#""1.2" in [new Version(1, 0), new Version(1, 2)]"#);
+    test.qeval("is_stable(new Release(new Version(1, 2)))");
+    test.qnull("is_stable(new Release(new Version(1, 3)))");
+    test.qnull("new Version(1, 2) = 12");
+    test.qnull(r#"new Version(1, 2) > "1.2""#);
+}
+
 #[cfg(feature = "uuid-06")]
 #[test]
 fn test_uuid_06() -> Result<(), Box<dyn std::error::Error>> {
     use uuid_06::Uuid;
     let mut test = OsoTest::new();
     test.oso.register_class(Uuid::get_polar_class())?;
-    test.load_str("f(x: Uuid, y: Uuid) if x = y;");
+    test.load_str("f(x: Uuid, y: Uuid) if x = y; g(x: Uuid, s: String) if x = s;");
     let (x, y) = (Uuid::nil(), Uuid::nil());
     test.oso.query_rule("f", (x, y))?.next().unwrap()?;
+    let nil = "00000000-0000-0000-0000-000000000000";
+    test.oso
+        .query_rule("g", (Uuid::nil(), nil))?
+        .next()
+        .unwrap()?;
     Ok(())
 }
 
@@ -771,9 +840,14 @@ fn test_uuid_07() -> Result<(), Box<dyn std::error::Error>> {
     use uuid_07::Uuid;
     let mut test = OsoTest::new();
     test.oso.register_class(Uuid::get_polar_class())?;
-    test.load_str("f(x: Uuid, y: Uuid) if x = y;");
+    test.load_str("f(x: Uuid, y: Uuid) if x = y; g(x: Uuid, s: String) if x = s;");
     let (x, y) = (Uuid::nil(), Uuid::nil());
     test.oso.query_rule("f", (x, y))?.next().unwrap()?;
+    let nil = "00000000-0000-0000-0000-000000000000";
+    test.oso
+        .query_rule("g", (Uuid::nil(), nil))?
+        .next()
+        .unwrap()?;
     Ok(())
 }
 
@@ -783,8 +857,171 @@ fn test_uuid_10() -> Result<(), Box<dyn std::error::Error>> {
     use uuid_10::Uuid;
     let mut test = OsoTest::new();
     test.oso.register_class(Uuid::get_polar_class())?;
-    test.load_str("f(x: Uuid, y: Uuid) if x = y;");
+    test.load_str("f(x: Uuid, y: Uuid) if x = y; g(x: Uuid, s: String) if x = s;");
     let (x, y) = (Uuid::nil(), Uuid::nil());
     test.oso.query_rule("f", (x, y))?.next().unwrap()?;
+    let nil = "00000000-0000-0000-0000-000000000000";
+    test.oso
+        .query_rule("g", (Uuid::nil(), nil))?
+        .next()
+        .unwrap()?;
+    Ok(())
+}
+
+#[cfg(feature = "ipnet")]
+#[test]
+fn test_ipnet() -> Result<(), Box<dyn std::error::Error>> {
+    use ipnet::IpNet;
+    let mut test = OsoTest::new();
+    test.oso.register_class(IpNet::get_polar_class())?;
+    test.load_str(
+        r#"internal(net: IpNet) if net = "10.0.0.0/8";
+           trusted(net: IpNet, address) if net.contains(address);"#,
+    );
+    let net: IpNet = "10.0.0.0/8".parse()?;
+    test.oso.query_rule("internal", (net,))?.next().unwrap()?;
+    assert!(test
+        .oso
+        .query_rule("internal", ("10.0.0.0/16".parse::<IpNet>()?,))?
+        .next()
+        .is_none());
+    test.oso
+        .query_rule("trusted", (net, "10.1.2.3"))?
+        .next()
+        .unwrap()?;
+    assert!(test
+        .oso
+        .query_rule("trusted", (net, "192.168.0.1"))?
+        .next()
+        .is_none());
+    Ok(())
+}
+
+#[cfg(feature = "chrono")]
+#[test]
+fn test_chrono_datetimes() -> Result<(), Box<dyn std::error::Error>> {
+    use chrono::{DateTime, TimeZone, Utc};
+    let mut test = OsoTest::new();
+    let created = Utc.timestamp_opt(1_704_067_200, 500_000_000).unwrap();
+    test.oso.register_constant(created, "created")?;
+
+    test.qeval(r#"created = datetime"2024-01-01T00:00:00.5Z""#);
+    test.qeval(r#"created + duration"P1D" > datetime"2024-01-01T12:00:00+01:00""#);
+    test.qvar_one("x = created", "x", created);
+    assert_eq!(
+        test.qvar::<DateTime<Utc>>(r#"x = created - duration"PT0.5S""#, "x"),
+        vec![Utc.timestamp_opt(1_704_067_200, 0).unwrap()]
+    );
+    Ok(())
+}
+
+#[cfg(feature = "rust_decimal")]
+#[test]
+fn test_rust_decimals() -> Result<(), Box<dyn std::error::Error>> {
+    use rust_decimal::Decimal;
+    let mut test = OsoTest::new();
+    let price = Decimal::new(150, 2);
+    test.oso.register_constant(price, "price")?;
+
+    test.qeval("price = 1.5d and price < 2");
+    test.qvar_one("x = price * 2", "x", Decimal::new(300, 2));
+    test.qvar_one("x = 3", "x", Decimal::from(3));
+    // Polar decimals that don't fit in a `rust_decimal::Decimal` aren't converted.
+    assert!(test
+        .oso
+        .query("x = 100000000000000000000000000000000d")?
+        .next()
+        .unwrap()?
+        .get_typed::<Decimal>("x")
+        .is_err());
+    Ok(())
+}
+
+#[cfg(feature = "stream")]
+#[test]
+fn test_query_stream() -> Result<(), Box<dyn std::error::Error>> {
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::sync::Arc;
+    use std::task::{Context, Poll, Wake, Waker};
+
+    use oso::stream::{ExternalCall, QueryStream};
+    use oso::{FromPolar, PolarValue};
+
+    struct NoopWaker;
+
+    impl Wake for NoopWaker {
+        fn wake(self: Arc<Self>) {}
+    }
+
+    /// Poll `future` to completion, counting how many times it was pending.
+    fn block_on<F: Future>(future: F) -> (F::Output, usize) {
+        let waker = Waker::from(Arc::new(NoopWaker));
+        let mut cx = Context::from_waker(&waker);
+        let mut future = Box::pin(future);
+        let mut pending = 0;
+        loop {
+            match future.as_mut().poll(&mut cx) {
+                Poll::Ready(output) => return (output, pending),
+                Poll::Pending => pending += 1,
+            }
+        }
+    }
+
+    /// Pending on its first poll.
+    struct YieldNow(bool);
+
+    impl Future for YieldNow {
+        type Output = ();
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+            if std::mem::replace(&mut self.0, true) {
+                Poll::Ready(())
+            } else {
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+        }
+    }
+
+    #[derive(PolarClass, Clone)]
+    struct User {
+        #[polar(attribute)]
+        name: String,
+    }
+
+    let mut test = OsoTest::new();
+    test.oso.register_class(User::get_polar_class())?;
+    let alice = User {
+        name: "alice".to_owned(),
+    };
+    test.oso.register_constant(alice, "alice")?;
+
+    // Without a handler, the host answers external calls and the stream is always ready.
+    let mut stream = QueryStream::from(test.oso.query("x = alice.name")?);
+    let (result, pending) = block_on(stream.next_result());
+    assert_eq!(pending, 0);
+    assert_eq!(result.unwrap()?.get_typed::<String>("x")?, "alice");
+    assert!(block_on(stream.next_result()).0.is_none());
+
+    // A handler answers them instead, and may be async.
+    let handler = |call: ExternalCall| async move {
+        YieldNow(false).await;
+        let user = User::from_polar(call.instance)?;
+        Ok(match call.attribute.as_str() {
+            "name" => Some(PolarValue::String(user.name.to_uppercase())),
+            _ => None,
+        })
+    };
+    let mut stream = QueryStream::from(test.oso.query("x = alice.name")?);
+    stream.register_external_call_handler(handler);
+    let (result, pending) = block_on(stream.next_result());
+    assert_eq!(pending, 1);
+    assert_eq!(result.unwrap()?.get_typed::<String>("x")?, "ALICE");
+    assert!(block_on(stream.next_result()).0.is_none());
+
+    let mut stream = QueryStream::from(test.oso.query("x = alice.email")?);
+    stream.register_external_call_handler(handler);
+    assert!(block_on(stream.next_result()).0.is_none());
     Ok(())
 }
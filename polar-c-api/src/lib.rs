@@ -133,6 +133,18 @@ pub extern "C" fn polar_register_constant(
     })
 }
 
+#[no_mangle]
+pub extern "C" fn polar_register_lazy_constant(
+    polar_ptr: *mut Polar,
+    name: *const c_char,
+) -> *mut CResult<c_void> {
+    ffi_try!({
+        let polar = unsafe { ffi_ref!(polar_ptr) };
+        let name = unsafe { ffi_string!(name) };
+        polar.register_lazy_constant(terms::Symbol::new(name.as_ref()))
+    })
+}
+
 #[no_mangle]
 pub extern "C" fn polar_register_mro(
     polar_ptr: *mut Polar,
@@ -241,6 +253,27 @@ pub extern "C" fn polar_debug_command(
     })
 }
 
+/// Send one structured debugger request for the given query.
+///
+/// ## Returns
+/// - `0` on error.
+/// - `1` on success.
+///
+/// ## Errors
+/// - Provided value is NULL.
+/// - Provided value cannot be parsed to a DebugRequest.
+/// - Query.debug_request returns an error.
+#[no_mangle]
+pub extern "C" fn polar_debug_request(
+    query_ptr: *mut Query,
+    request: *const c_char,
+) -> *mut CResult<c_void> {
+    ffi_try!({
+        let query = unsafe { ffi_ref!(query_ptr) };
+        from_json(request).and_then(|request| query.debug_request(request))
+    })
+}
+
 #[no_mangle]
 pub extern "C" fn polar_call_result(
     query_ptr: *mut Query,
@@ -0,0 +1,47 @@
+//! WebAssembly component exposing the policy engine through the `oso:polar` WIT world.
+//!
+//! Unlike `polar-wasm-api`, this doesn't depend on a JavaScript host, so it can be embedded by any
+//! runtime that supports the component model.
+
+mod polar;
+mod query;
+
+wit_bindgen::generate!({
+    world: "polar",
+    path: "wit",
+});
+
+use exports::oso::polar::engine;
+
+struct Component;
+
+impl engine::Guest for Component {
+    type Polar = polar::Polar;
+    type Query = query::Query;
+}
+
+export!(Component);
+
+type Result<T> = std::result::Result<T, engine::Error>;
+
+fn error(e: polar_core::error::PolarError) -> engine::Error {
+    engine::Error {
+        kind: e.kind(),
+        message: e.to_string(),
+    }
+}
+
+fn serialization_error(e: serde_json::Error) -> engine::Error {
+    error(polar_core::error::OperationalError::Serialization { msg: e.to_string() }.into())
+}
+
+fn message(message: polar_core::messages::Message) -> engine::Message {
+    use polar_core::messages::MessageKind;
+    engine::Message {
+        kind: match message.kind {
+            MessageKind::Print => engine::MessageKind::Print,
+            MessageKind::Warning => engine::MessageKind::Warning,
+        },
+        msg: message.msg,
+    }
+}
@@ -0,0 +1,96 @@
+use std::cell::RefCell;
+
+use polar_core::{polar, sources::Source, terms::Symbol};
+
+use crate::exports::oso::polar::engine;
+use crate::query::Query;
+use crate::{error, message, serialization_error, Result};
+
+pub struct Polar(RefCell<polar::Polar>);
+
+impl engine::GuestPolar for Polar {
+    fn new() -> Self {
+        Self(RefCell::new(polar::Polar::new()))
+    }
+
+    fn load(&self, sources: Vec<engine::Source>) -> Result<()> {
+        let sources = sources
+            .into_iter()
+            .map(|source| Source {
+                filename: source.filename,
+                src: source.src,
+            })
+            .collect();
+        self.0.borrow().load(sources).map_err(error)
+    }
+
+    fn clear_rules(&self) {
+        self.0.borrow().clear_rules()
+    }
+
+    fn register_constant(&self, name: String, value: String) -> Result<()> {
+        let value = serde_json::from_str(&value).map_err(serialization_error)?;
+        self.0
+            .borrow()
+            .register_constant(Symbol::new(&name), value)
+            .map_err(error)
+    }
+
+    fn register_mro(&self, name: String, mro: Vec<u64>) -> Result<()> {
+        self.0
+            .borrow()
+            .register_mro(Symbol::new(&name), mro)
+            .map_err(error)
+    }
+
+    fn next_inline_query(&self) -> Option<engine::Query> {
+        self.0
+            .borrow()
+            .next_inline_query(false)
+            .map(|query| engine::Query::new(Query::from(query)))
+    }
+
+    fn new_query_from_str(&self, src: String) -> Result<engine::Query> {
+        self.0
+            .borrow()
+            .new_query(&src, false)
+            .map(|query| engine::Query::new(Query::from(query)))
+            .map_err(error)
+    }
+
+    fn new_query_from_term(&self, term: String) -> Result<engine::Query> {
+        let term = serde_json::from_str(&term).map_err(serialization_error)?;
+        let query = self.0.borrow().new_query_from_term(term, false);
+        Ok(engine::Query::new(Query::from(query)))
+    }
+
+    fn new_id(&self) -> u64 {
+        self.0.borrow().get_external_id()
+    }
+
+    fn next_message(&self) -> Option<engine::Message> {
+        self.0.borrow().next_message().map(message)
+    }
+
+    fn build_data_filter(
+        &self,
+        types: String,
+        partial_results: String,
+        variable: String,
+        class_tag: String,
+    ) -> Result<String> {
+        let types = serde_json::from_str(&types).map_err(serialization_error)?;
+        let partial_results =
+            serde_json::from_str(&partial_results).map_err(serialization_error)?;
+        let filter = self
+            .0
+            .borrow()
+            .build_data_filter(types, partial_results, &variable, &class_tag)
+            .map_err(error)?;
+        serde_json::to_string(&filter).map_err(serialization_error)
+    }
+
+    fn set_ignore_no_allow_warning(&self, ignore: bool) {
+        self.0.borrow_mut().set_ignore_no_allow_warning(ignore);
+    }
+}
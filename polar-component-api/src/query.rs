@@ -0,0 +1,66 @@
+use std::cell::RefCell;
+
+use polar_core::{query, terms::Symbol};
+
+use crate::exports::oso::polar::engine;
+use crate::{error, message, serialization_error, Result};
+
+pub struct Query(RefCell<query::Query>);
+
+impl From<query::Query> for Query {
+    fn from(q: query::Query) -> Self {
+        Self(RefCell::new(q))
+    }
+}
+
+impl engine::GuestQuery for Query {
+    fn next_event(&self) -> Result<String> {
+        let event = self.0.borrow_mut().next_event().map_err(error)?;
+        serde_json::to_string(&event).map_err(serialization_error)
+    }
+
+    fn call_result(&self, call_id: u64, term: Option<String>) -> Result<()> {
+        let term = term
+            .map(|term| serde_json::from_str(&term))
+            .transpose()
+            .map_err(serialization_error)?;
+        self.0
+            .borrow_mut()
+            .call_result(call_id, term)
+            .map_err(error)
+    }
+
+    fn question_result(&self, call_id: u64, answer: bool) -> Result<()> {
+        self.0
+            .borrow_mut()
+            .question_result(call_id, answer)
+            .map_err(error)
+    }
+
+    fn debug_command(&self, command: String) -> Result<()> {
+        self.0.borrow_mut().debug_command(&command).map_err(error)
+    }
+
+    fn app_error(&self, message: String) -> Result<()> {
+        self.0
+            .borrow_mut()
+            .application_error(message)
+            .map_err(error)
+    }
+
+    fn next_message(&self) -> Option<engine::Message> {
+        self.0.borrow().next_message().map(message)
+    }
+
+    fn source(&self) -> String {
+        self.0.borrow().source_info()
+    }
+
+    fn bind(&self, name: String, term: String) -> Result<()> {
+        let term = serde_json::from_str(&term).map_err(serialization_error)?;
+        self.0
+            .borrow_mut()
+            .bind(Symbol::new(&name), term)
+            .map_err(error)
+    }
+}
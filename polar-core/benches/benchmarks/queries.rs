@@ -184,6 +184,32 @@ pub fn many_rules(c: &mut Criterion) {
     });
 }
 
+/// Bench: create `TARGET` rules of the form `f(i, x) if g(i, y) and x = y` and `g(i, x) if f(i-1, x)`
+/// and measure the time to compute `f(TARGET, x)`
+/// This basically measures the cost of renaming and binding rule variables
+pub fn indirect_rules(c: &mut Criterion) {
+    const TARGET: usize = 10;
+    fn make_runner() -> Runner {
+        let mut runner = runner_from_query(&format!("f({}, x)", TARGET));
+        let mut policy = "f(0, x) if x = 0;".to_owned();
+        for i in 1..=TARGET {
+            policy += &format!("f({}, x) if g({}, y) and x = y;", i, i);
+            policy += &format!("g({}, x) if f({}, x);", i, i - 1);
+        }
+        runner.load_str(&policy).unwrap();
+        runner.expected_result(maplit::hashmap!(sym!("x") => term!(0)));
+        runner
+    }
+
+    c.bench_function("indirect_rules", |b| {
+        b.iter_batched_ref(
+            make_runner,
+            |runner| runner.run(),
+            criterion::BatchSize::SmallInput,
+        )
+    });
+}
+
 fn load_policy(c: &mut Criterion) {
     let policy = include_str!("roles_policy.polar");
     c.bench_function("load_policy", |b| {
@@ -198,6 +224,7 @@ criterion_group!(
     benches,
     simple_queries,
     many_rules,
+    indirect_rules,
     fib,
     prime,
     indexed_rules,
@@ -1,25 +1,27 @@
-use polar_core::{events::*, kb::Bindings, parser, polar::Polar, query::Query};
+use polar_core::{events::*, kb::Bindings, parser, polar::Polar, query::Query, terms::Term};
 
 pub fn runner_from_query(q: &str) -> Runner {
     let polar = Polar::new();
     let query_term = parser::parse_query(q).unwrap();
-    let query = polar.new_query_from_term(query_term, false);
-    Runner::new(polar, query)
+    Runner::new(polar, query_term)
 }
 
 /// Used to run benchmarks by providing helper methods
 pub struct Runner {
     polar: Polar,
     expected_result: Option<Bindings>,
-    query: Query,
+    term: Term,
+    /// Created on the first event, so that it runs against the policy loaded after `new`.
+    query: Option<Query>,
 }
 
 impl Runner {
-    pub fn new(polar: Polar, query: Query) -> Self {
+    pub fn new(polar: Polar, term: Term) -> Self {
         Self {
             polar,
             expected_result: None,
-            query,
+            term,
+            query: None,
         }
     }
 
@@ -28,7 +30,12 @@ impl Runner {
     }
 
     pub fn next(&mut self) -> QueryEvent {
-        self.query.next_event().expect("query errored")
+        let polar = &self.polar;
+        let term = &self.term;
+        self.query
+            .get_or_insert_with(|| polar.new_query_from_term(term.clone(), false))
+            .next_event()
+            .expect("query errored")
     }
 
     pub fn run(&mut self) {
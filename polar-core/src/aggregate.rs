@@ -0,0 +1,101 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::counter::Counter;
+use crate::debug_protocol::DebugRequest;
+use crate::error::{PolarError, PolarResult};
+use crate::events::QueryEvent;
+use crate::runnable::Runnable;
+use crate::terms::{Operation, Operator, Term, TermList, Value};
+use crate::vm::{Goal, PolarVirtualMachine};
+
+/// `Aggregator` collects the values for the aggregates `count`, `sum`, `min` and `max`.
+///
+/// It is a `Runnable` that queries `var in collection and condition` in a child VM and appends
+/// the value of `var` in each result to `values`, for the parent VM to aggregate. Events from
+/// the child VM, such as `NextExternal` when iterating over an external collection, are passed
+/// through to the host, so the collection is streamed rather than materialized by the host.
+/// With `QueryFlags::STREAM_AGGREGATES`, each value is also yielded to the host as an
+/// `AggregateValue` event.
+#[derive(Clone)]
+pub struct Aggregator {
+    vm: PolarVirtualMachine,
+    aggregate: Operator,
+    var: Term,
+    values: Rc<RefCell<TermList>>,
+}
+
+impl Aggregator {
+    pub fn new(
+        vm: &PolarVirtualMachine,
+        aggregate: Operator,
+        var: Term,
+        collection: Term,
+        condition: Term,
+        values: Rc<RefCell<TermList>>,
+    ) -> Self {
+        let member = var.clone_with_value(Value::Expression(Operation {
+            operator: Operator::In,
+            args: vec![var.clone(), collection],
+        }));
+        let term = condition.clone_with_value(Value::Expression(Operation {
+            operator: Operator::And,
+            args: vec![member, condition.clone()],
+        }));
+        Self {
+            vm: vm.clone_with_goals(vec![Goal::Query { term }]),
+            aggregate,
+            var,
+            values,
+        }
+    }
+}
+
+impl Runnable for Aggregator {
+    fn run(&mut self, _: Option<&mut Counter>) -> PolarResult<QueryEvent> {
+        loop {
+            match self.vm.run(None)? {
+                QueryEvent::Result { .. } => {
+                    let value = self
+                        .vm
+                        .relevant_bindings(&[&self.var])
+                        .remove(self.var.as_symbol()?)
+                        .unwrap_or_else(|| self.var.clone());
+                    self.values.borrow_mut().push(value.clone());
+                    if self.vm.stream_aggregates {
+                        return Ok(QueryEvent::AggregateValue {
+                            aggregate: self.aggregate,
+                            value,
+                        });
+                    }
+                }
+                QueryEvent::Done { .. } => return Ok(QueryEvent::Done { result: true }),
+                event => return Ok(event),
+            }
+        }
+    }
+
+    fn external_question_result(&mut self, call_id: u64, answer: bool) -> PolarResult<()> {
+        self.vm.external_question_result(call_id, answer)
+    }
+
+    fn external_call_result(&mut self, call_id: u64, term: Option<Term>) -> PolarResult<()> {
+        self.vm.external_call_result(call_id, term)
+    }
+
+    fn debug_command(&mut self, command: &str) -> PolarResult<()> {
+        self.vm.debug_command(command)
+    }
+
+    fn debug_request(&mut self, request: DebugRequest) -> PolarResult<()> {
+        self.vm.debug_request(request)
+    }
+
+    fn clone_runnable(&self) -> Box<dyn Runnable> {
+        Box::new(self.clone())
+    }
+
+    fn handle_error(&mut self, error: PolarError) -> PolarResult<QueryEvent> {
+        self.vm.handle_error(error)
+    }
+}
@@ -0,0 +1,494 @@
+//! Hooks for editor tooling, such as a language server.
+//!
+//! [`parse_without_loading`] parses policy files into an [`Analysis`] without touching a
+//! knowledge base, so a language server can answer hover, go-to-definition, find-references and
+//! completion requests as the user types. Files are analyzed independently: updating one file
+//! only reparses that file, and a file that stops parsing keeps the symbols from its last
+//! successful parse so that navigation keeps working while an edit is in progress.
+//!
+//! Offsets are byte offsets into the source, as in [`Span::start`].
+
+use std::collections::BTreeSet;
+
+use serde::Serialize;
+
+use crate::diagnostic::structured::StructuredDiagnostic;
+use crate::introspection::Span;
+use crate::parser::{parse_lines, Line};
+use crate::rules::Rule;
+use crate::sources::Source;
+use crate::terms::{Operation, Operator, Pattern, Symbol, Term, Value};
+use crate::visitor::{walk_term, Visitor};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SymbolKind {
+    /// A rule definition, e.g., `f(x) if g(x);`.
+    Rule,
+    /// A rule type declaration, e.g., `type f(x: Integer);`.
+    RuleType,
+    /// A call to a rule from the body of a rule or an inline query, e.g., `g(x)`.
+    RuleCall,
+    /// A class named in a specializer or `matches` pattern, e.g., `User` in `x: User`.
+    ClassReference,
+    /// The resource or actor named by a resource block, e.g., `Org` in `resource Org {}`.
+    ResourceBlock,
+}
+
+/// A named thing in a policy file.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct SymbolInfo {
+    pub name: Symbol,
+    pub kind: SymbolKind,
+    /// The number of arguments, for rules, rule types and rule calls.
+    pub arity: Option<usize>,
+    pub span: Span,
+}
+
+impl SymbolInfo {
+    fn contains(&self, offset: usize) -> bool {
+        self.span.start <= offset && offset <= self.span.end
+    }
+
+    fn is_rule_like(&self) -> bool {
+        matches!(
+            self.kind,
+            SymbolKind::Rule | SymbolKind::RuleType | SymbolKind::RuleCall
+        )
+    }
+
+    fn is_class_like(&self) -> bool {
+        matches!(
+            self.kind,
+            SymbolKind::ClassReference | SymbolKind::ResourceBlock
+        )
+    }
+
+    /// Whether `self` and `other` refer to the same rule or class.
+    fn same_target(&self, other: &SymbolInfo) -> bool {
+        self.name == other.name
+            && ((self.is_rule_like() && other.is_rule_like() && self.arity == other.arity)
+                || (self.is_class_like() && other.is_class_like()))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Hover {
+    /// The symbol being hovered.
+    pub span: Span,
+    /// The signature of every matching rule type and rule, one per line.
+    pub contents: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CompletionKind {
+    Rule,
+    Constant,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct Completion {
+    pub label: String,
+    pub kind: CompletionKind,
+}
+
+#[derive(Clone, Debug)]
+struct FileAnalysis {
+    filename: Option<String>,
+    /// Symbols ordered by the start of their span.
+    symbols: Vec<SymbolInfo>,
+    /// The signatures of the rules and rule types in the file, for hovers.
+    signatures: Vec<(SymbolInfo, String)>,
+    /// Parse errors from the most recent version of the file.
+    diagnostics: Vec<StructuredDiagnostic>,
+}
+
+/// Symbol tables for a set of policy files. See the module documentation.
+#[derive(Clone, Debug, Default)]
+pub struct Analysis {
+    files: Vec<FileAnalysis>,
+    /// Registered constants, offered as completions.
+    constants: Vec<Symbol>,
+}
+
+/// Parse `sources` for tooling, without loading them into a knowledge base.
+pub fn parse_without_loading(sources: Vec<Source>) -> Analysis {
+    let mut analysis = Analysis::default();
+    for source in sources {
+        analysis.update(source);
+    }
+    analysis
+}
+
+impl Analysis {
+    pub(crate) fn set_constants(&mut self, constants: Vec<Symbol>) {
+        self.constants = constants;
+    }
+
+    /// Reparse one file, adding it if it's new. If the file doesn't parse, its parse error is
+    /// recorded and the symbols from its last successful parse are kept.
+    pub fn update(&mut self, source: Source) {
+        let filename = source.filename.clone();
+        let index = match self.files.iter().position(|f| f.filename == filename) {
+            Some(index) => index,
+            None => {
+                self.files.push(FileAnalysis {
+                    filename,
+                    symbols: vec![],
+                    signatures: vec![],
+                    diagnostics: vec![],
+                });
+                self.files.len() - 1
+            }
+        };
+        let file = &mut self.files[index];
+        match parse_lines(source) {
+            Ok(lines) => {
+                let mut collector = Collector::default();
+                for line in &lines {
+                    collector.collect_line(line);
+                }
+                collector
+                    .symbols
+                    .sort_by_key(|s| (s.span.start, s.span.end));
+                file.symbols = collector.symbols;
+                file.signatures = collector.signatures;
+                file.diagnostics = vec![];
+            }
+            Err(e) => file.diagnostics = vec![StructuredDiagnostic::from(&e)],
+        }
+    }
+
+    /// Forget a file, e.g., when it's closed or deleted.
+    pub fn remove(&mut self, filename: &str) {
+        self.files
+            .retain(|f| f.filename.as_deref() != Some(filename));
+    }
+
+    fn file(&self, filename: Option<&str>) -> Option<&FileAnalysis> {
+        self.files
+            .iter()
+            .find(|f| f.filename.as_deref() == filename)
+    }
+
+    /// Parse errors for a file.
+    pub fn diagnostics(&self, filename: Option<&str>) -> &[StructuredDiagnostic] {
+        self.file(filename).map_or(&[], |f| &f.diagnostics)
+    }
+
+    /// The symbol table for a file, ordered by offset.
+    pub fn symbols(&self, filename: Option<&str>) -> &[SymbolInfo] {
+        self.file(filename).map_or(&[], |f| &f.symbols)
+    }
+
+    /// The innermost symbol at `offset` in a file, e.g., the rule call under the cursor rather
+    /// than the rule containing it.
+    pub fn symbol_at(&self, filename: Option<&str>, offset: usize) -> Option<&SymbolInfo> {
+        self.symbols(filename)
+            .iter()
+            .filter(|s| s.contains(offset))
+            .min_by_key(|s| s.span.end - s.span.start)
+    }
+
+    fn all_symbols(&self) -> impl Iterator<Item = &SymbolInfo> {
+        self.files.iter().flat_map(|f| &f.symbols)
+    }
+
+    /// Where the rule or class referred to by `symbol` is defined, across all files: rule types
+    /// and rules for rules, and resource blocks for classes.
+    pub fn definitions(&self, symbol: &SymbolInfo) -> Vec<&SymbolInfo> {
+        self.all_symbols()
+            .filter(|s| {
+                matches!(
+                    s.kind,
+                    SymbolKind::Rule | SymbolKind::RuleType | SymbolKind::ResourceBlock
+                ) && s.same_target(symbol)
+            })
+            .collect()
+    }
+
+    /// Every use of the rule or class referred to by `symbol`, across all files.
+    pub fn references(&self, symbol: &SymbolInfo) -> Vec<&SymbolInfo> {
+        self.all_symbols()
+            .filter(|s| {
+                matches!(s.kind, SymbolKind::RuleCall | SymbolKind::ClassReference)
+                    && s.same_target(symbol)
+            })
+            .collect()
+    }
+
+    /// The signatures of the rule under `offset`, if any.
+    pub fn hover(&self, filename: Option<&str>, offset: usize) -> Option<Hover> {
+        let symbol = self.symbol_at(filename, offset)?;
+        let signatures = self
+            .files
+            .iter()
+            .flat_map(|f| &f.signatures)
+            .filter(|(s, _)| s.same_target(symbol))
+            .map(|(_, signature)| signature.as_str())
+            .collect::<Vec<_>>();
+        if signatures.is_empty() {
+            return None;
+        }
+        Some(Hover {
+            span: symbol.span.clone(),
+            contents: signatures.join("\n"),
+        })
+    }
+
+    /// Rule names and registered constants starting with `prefix`, sorted by label and
+    /// deduplicated.
+    pub fn completions(&self, prefix: &str) -> Vec<Completion> {
+        let rules = self
+            .all_symbols()
+            .filter(|s| matches!(s.kind, SymbolKind::Rule | SymbolKind::RuleType))
+            .map(|s| Completion {
+                label: s.name.as_str().to_string(),
+                kind: CompletionKind::Rule,
+            });
+        let constants = self.constants.iter().map(|c| Completion {
+            label: c.as_str().to_string(),
+            kind: CompletionKind::Constant,
+        });
+        rules
+            .chain(constants)
+            .filter(|c| c.label.starts_with(prefix))
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect()
+    }
+}
+
+#[derive(Default)]
+struct Collector {
+    symbols: Vec<SymbolInfo>,
+    signatures: Vec<(SymbolInfo, String)>,
+}
+
+impl Collector {
+    fn push(&mut self, name: &Symbol, kind: SymbolKind, arity: Option<usize>, span: Span) {
+        self.symbols.push(SymbolInfo {
+            name: name.clone(),
+            kind,
+            arity,
+            span,
+        })
+    }
+
+    fn collect_line(&mut self, line: &Line) {
+        match line {
+            Line::Rule(rule) => self.collect_rule(rule, SymbolKind::Rule),
+            Line::RuleType(rule) => self.collect_rule(rule, SymbolKind::RuleType),
+            Line::DeprecatedRule { rule, .. } | Line::PrivateRule(rule) => {
+                self.collect_rule(rule, SymbolKind::Rule)
+            }
+            Line::Query { term, expected } => {
+                self.visit_term(term);
+                if let Some(expected) = expected {
+                    self.visit_term(expected);
+                }
+            }
+            Line::UnionType { name, members } => {
+                for term in std::iter::once(name).chain(members) {
+                    self.collect_class(term, SymbolKind::ClassReference);
+                }
+            }
+            Line::ResourceBlock { resource, .. } => {
+                self.collect_class(resource, SymbolKind::ResourceBlock)
+            }
+        }
+    }
+
+    fn collect_rule(&mut self, rule: &Rule, kind: SymbolKind) {
+        if let Some(span) = Span::of_rule(rule) {
+            let params = rule
+                .params
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>();
+            let signature = format!(
+                "{}{}({})",
+                if kind == SymbolKind::RuleType {
+                    "type "
+                } else {
+                    ""
+                },
+                rule.name,
+                params.join(", ")
+            );
+            self.push(&rule.name, kind, Some(rule.params.len()), span);
+            let symbol = self.symbols.last().unwrap().clone();
+            self.signatures.push((symbol, signature));
+        }
+        for param in &rule.params {
+            self.visit_param(param);
+        }
+        self.visit_term(&rule.body);
+    }
+
+    fn collect_class(&mut self, term: &Term, kind: SymbolKind) {
+        if let (Value::Variable(name), Some(span)) = (term.value(), Span::of_term(term)) {
+            self.push(name, kind, None, span);
+        }
+    }
+}
+
+impl Visitor for Collector {
+    fn visit_term(&mut self, term: &Term) {
+        match term.value() {
+            Value::Call(call) => {
+                if let Some(span) = Span::of_term(term) {
+                    self.push(
+                        &call.name,
+                        SymbolKind::RuleCall,
+                        Some(call.args.len()),
+                        span,
+                    );
+                }
+            }
+            Value::Pattern(Pattern::Instance(instance)) => {
+                if let Some(span) = Span::of_term(term) {
+                    self.push(&instance.tag, SymbolKind::ClassReference, None, span);
+                }
+            }
+            // Method calls aren't rule calls.
+            Value::Expression(Operation {
+                operator: Operator::Dot,
+                args,
+            }) => {
+                for arg in args {
+                    match arg.value() {
+                        Value::Call(call) => call.args.iter().for_each(|a| self.visit_term(a)),
+                        _ => self.visit_term(arg),
+                    }
+                }
+                return;
+            }
+            _ => (),
+        }
+        walk_term(self, term)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::polar::Polar;
+
+    const POLICY: &str = r#"actor User {}
+type is_admin(user: User);
+is_admin(user: User) if user.role = "admin";
+allow(user, _action, _resource) if is_admin(user);
+allow(user, "read", _resource) if user.can("read") and not is_admin(user);
+"#;
+
+    fn offset_of(src: &str, needle: &str, nth: usize) -> usize {
+        src.match_indices(needle).nth(nth).unwrap().0
+    }
+
+    #[test]
+    fn test_symbols_and_navigation() {
+        let helpers = "helper(x) if is_admin(x);";
+        let analysis = parse_without_loading(vec![
+            Source::new_with_name("policy.polar", POLICY),
+            Source::new_with_name("helpers.polar", helpers),
+        ]);
+        assert!(analysis.diagnostics(Some("policy.polar")).is_empty());
+
+        // The method call isn't a rule call.
+        let calls = analysis
+            .symbols(Some("policy.polar"))
+            .iter()
+            .filter(|s| s.kind == SymbolKind::RuleCall)
+            .map(|s| s.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(calls, vec!["is_admin", "is_admin"]);
+
+        let offset = offset_of(POLICY, "is_admin(user);", 0) + 2;
+        let call = analysis.symbol_at(Some("policy.polar"), offset).unwrap();
+        assert_eq!(call.kind, SymbolKind::RuleCall);
+        assert_eq!(call.arity, Some(1));
+
+        let definitions = analysis.definitions(call);
+        let kinds = definitions.iter().map(|s| s.kind).collect::<Vec<_>>();
+        assert_eq!(kinds, vec![SymbolKind::RuleType, SymbolKind::Rule]);
+        assert_eq!(definitions[1].span.start_line, 3);
+
+        // References are found across files.
+        let references = analysis.references(definitions[0]);
+        let files = references
+            .iter()
+            .map(|s| s.span.filename.as_deref().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(files, vec!["policy.polar", "policy.polar", "helpers.polar"]);
+
+        // Specializers refer to resource blocks.
+        let offset = offset_of(POLICY, "User", 1);
+        let class = analysis.symbol_at(Some("policy.polar"), offset).unwrap();
+        assert_eq!(class.kind, SymbolKind::ClassReference);
+        let definitions = analysis.definitions(class);
+        assert_eq!(definitions.len(), 1);
+        assert_eq!(definitions[0].kind, SymbolKind::ResourceBlock);
+
+        let hover = analysis.hover(Some("helpers.polar"), 15).unwrap();
+        assert_eq!(
+            hover.contents,
+            "type is_admin(user: User{})\nis_admin(user: User{})"
+        );
+        assert_eq!(hover.span.filename.as_deref(), Some("helpers.polar"));
+    }
+
+    #[test]
+    fn test_incremental_updates() {
+        let mut analysis =
+            parse_without_loading(vec![Source::new_with_name("policy.polar", "f(1);")]);
+        assert_eq!(analysis.symbols(Some("policy.polar")).len(), 1);
+
+        // A file that stops parsing keeps its symbols.
+        analysis.update(Source::new_with_name("policy.polar", "f(1); g("));
+        let diagnostics = analysis.diagnostics(Some("policy.polar"));
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].code, "P004");
+        assert_eq!(analysis.symbols(Some("policy.polar")).len(), 1);
+
+        analysis.update(Source::new_with_name("policy.polar", "f(1); g(2);"));
+        assert!(analysis.diagnostics(Some("policy.polar")).is_empty());
+        assert_eq!(analysis.symbols(Some("policy.polar")).len(), 2);
+
+        analysis.remove("policy.polar");
+        assert!(analysis.symbols(Some("policy.polar")).is_empty());
+    }
+
+    #[test]
+    fn test_completions() {
+        let polar = Polar::new();
+        polar
+            .register_constant(sym!("Integer"), term!(true))
+            .unwrap();
+        polar.register_constant(sym!("User"), term!(true)).unwrap();
+        let analysis = polar.analyze(vec![Source::new_with_name("policy.polar", POLICY)]);
+        let labels = analysis
+            .completions("")
+            .into_iter()
+            .map(|c| (c.label, c.kind))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            labels,
+            vec![
+                ("Integer".to_owned(), CompletionKind::Constant),
+                ("User".to_owned(), CompletionKind::Constant),
+                ("allow".to_owned(), CompletionKind::Rule),
+                ("is_admin".to_owned(), CompletionKind::Rule),
+            ]
+        );
+        let labels = analysis
+            .completions("is")
+            .into_iter()
+            .map(|c| c.label)
+            .collect::<Vec<_>>();
+        assert_eq!(labels, vec!["is_admin"]);
+        // Analyzing doesn't load the policy.
+        assert!(!polar.kb.snapshot().has_rules());
+    }
+}
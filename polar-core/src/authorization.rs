@@ -0,0 +1,195 @@
+//! Enforcement queries that list what an actor is authorized to do, rather than checking one
+//! request, e.g., every action an actor can take on a resource.
+//!
+//! An [`Authorization`] runs the query like any other, passing the events the host has to answer
+//! through [`Authorization::next_event`], and collects its results instead of returning them.
+
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::PolarResult;
+use crate::events::QueryEvent;
+use crate::query::Query;
+use crate::terms::{Symbol, Term, Value};
+
+/// What an actor is authorized for.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Authorized {
+    /// Everything, because a result left the action or field unbound, e.g., for a rule like
+    /// `allow(_actor: Admin, _action, _resource);`. Hosts usually show this as `"*"`.
+    Wildcard,
+    /// The ground values the query succeeded with, in the order they were first found.
+    Some(Vec<Term>),
+    /// The ground values the query succeeded with, and the constraints of results that only
+    /// authorize values satisfying them, e.g., `_this != "delete"` for a rule like
+    /// `allow(_actor, action, _resource) if action != "delete";`.
+    Constrained {
+        values: Vec<Term>,
+        constraints: Vec<Term>,
+    },
+}
+
+/// A query for the values of `var` that an actor is authorized for.
+pub struct Authorization {
+    query: Query,
+    var: Symbol,
+    wildcard: bool,
+    authorized: Vec<Term>,
+    constraints: Vec<Term>,
+}
+
+impl Authorization {
+    pub(crate) fn new(query: Query, var: Symbol) -> Self {
+        Self {
+            query,
+            var,
+            wildcard: false,
+            authorized: vec![],
+            constraints: vec![],
+        }
+    }
+
+    /// Run the query until the host has to answer an event, such as `ExternalCall`, or the query
+    /// is done. Results are collected rather than returned.
+    pub fn next_event(&mut self) -> PolarResult<QueryEvent> {
+        loop {
+            match self.query.next_event()? {
+                QueryEvent::Result { bindings, .. } => match bindings.get(&self.var) {
+                    Some(value) if matches!(value.value(), Value::Variable(_)) => {
+                        self.wildcard = true
+                    }
+                    Some(value) => {
+                        let found = if is_constrained(value) {
+                            &mut self.constraints
+                        } else {
+                            &mut self.authorized
+                        };
+                        if !found.contains(value) {
+                            found.push(value.clone());
+                        }
+                    }
+                    None => self.wildcard = true,
+                },
+                event => return Ok(event),
+            }
+        }
+    }
+
+    /// The underlying query, for answering the events returned by `next_event`.
+    pub fn query_mut(&mut self) -> &mut Query {
+        &mut self.query
+    }
+
+    /// What the results so far authorize. Complete once `next_event` returns `Done`.
+    pub fn authorized(&self) -> Authorized {
+        if self.wildcard {
+            Authorized::Wildcard
+        } else if !self.constraints.is_empty() {
+            Authorized::Constrained {
+                values: self.authorized.clone(),
+                constraints: self.constraints.clone(),
+            }
+        } else {
+            Authorized::Some(self.authorized.clone())
+        }
+    }
+}
+
+/// Whether a result's value is a partial, or has unbound variables in it, rather than being one
+/// value the actor is authorized for.
+fn is_constrained(value: &Term) -> bool {
+    let mut vars = HashSet::new();
+    value.variables(&mut vars);
+    !vars.is_empty() || matches!(value.value(), Value::Expression(_))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::polar::Polar;
+
+    fn run(mut authorization: Authorization) -> Authorized {
+        match authorization.next_event().unwrap() {
+            QueryEvent::Done { .. } => authorization.authorized(),
+            event => panic!("unexpected event {:?}", event),
+        }
+    }
+
+    #[test]
+    fn test_authorized_actions() {
+        let polar = Polar::new();
+        polar
+            .load_str(
+                r#"allow(_actor, action, resource) if resource.public and action in ["read", "list"];
+                   allow("alice", action, _resource) if action in ["read", "write"];
+                   allow("admin", _action, _resource);"#,
+            )
+            .unwrap();
+        let public = term!(btreemap! {sym!("public") => term!(true)});
+        let private = term!(btreemap! {sym!("public") => term!(false)});
+
+        let actions = |actor: &str, resource: &Term| {
+            run(polar.authorized_actions(term!(actor), resource.clone(), false))
+        };
+        assert_eq!(
+            actions("alice", &public),
+            Authorized::Some(vec![term!("read"), term!("list"), term!("write")])
+        );
+        assert_eq!(actions("bob", &private), Authorized::Some(vec![]));
+        assert_eq!(actions("admin", &private), Authorized::Wildcard);
+    }
+
+    #[test]
+    fn test_constrained_actions() {
+        let polar = Polar::new();
+        polar
+            .load_str(
+                r#"allow(_actor, action, _resource) if action != "delete";
+                   allow("alice", "delete", _resource);"#,
+            )
+            .unwrap();
+        let actions = |actor: &str| run(polar.authorized_actions(term!(actor), term!(1), false));
+        let constraints = |authorized| match authorized {
+            Authorized::Constrained {
+                values,
+                constraints,
+            } => (
+                values,
+                constraints.iter().map(Term::to_string).collect::<Vec<_>>(),
+            ),
+            authorized => panic!("unexpected {:?}", authorized),
+        };
+        assert_eq!(
+            constraints(actions("bob")),
+            (vec![], vec![r#"_this != "delete""#.to_owned()])
+        );
+        assert_eq!(
+            constraints(actions("alice")),
+            (
+                vec![term!("delete")],
+                vec![r#"_this != "delete""#.to_owned()]
+            )
+        );
+    }
+
+    #[test]
+    fn test_authorized_fields() {
+        let polar = Polar::new();
+        polar
+            .load_str(
+                r#"allow_field(_actor, "read", _resource, field) if field in ["name", "email"];
+                   allow_field("admin", "update", _resource, _field);"#,
+            )
+            .unwrap();
+        let fields = |actor: &str, action: &str| {
+            run(polar.authorized_fields(term!(actor), term!(action), term!(1), false))
+        };
+        assert_eq!(
+            fields("bob", "read"),
+            Authorized::Some(vec![term!("name"), term!("email")])
+        );
+        assert_eq!(fields("bob", "update"), Authorized::Some(vec![]));
+        assert_eq!(fields("admin", "update"), Authorized::Wildcard);
+    }
+}
@@ -0,0 +1,139 @@
+//! Running many queries as one batch, e.g., checking every row of a table against the same policy.
+//!
+//! A [`BatchQuery`] runs its queries one after another against a snapshot of the KB taken when it
+//! was created, so rules loaded while the batch runs don't change its answers. External calls that
+//! one query memoizes are memoized for the rest, so the host answers each call on an instance
+//! once per batch rather than once per query. Each event is tagged with the index of the query it
+//! came from.
+
+use crate::error::PolarResult;
+use crate::events::QueryEvent;
+use crate::query::Query;
+
+/// An event from one query in a batch.
+#[derive(Debug)]
+pub struct BatchEvent {
+    /// The position of the query in the batch.
+    pub index: usize,
+    pub event: QueryEvent,
+}
+
+/// Queries run one after another against the same KB snapshot.
+pub struct BatchQuery {
+    queries: Vec<Query>,
+    current: usize,
+    /// Whether the current query is done, so the next event comes from the one after it.
+    finished: bool,
+}
+
+impl BatchQuery {
+    pub(crate) fn new(mut queries: Vec<Query>) -> Self {
+        if let Some((first, rest)) = queries.split_first_mut() {
+            for query in rest {
+                query.share_external_call_memo(first);
+            }
+        }
+        Self {
+            queries,
+            current: 0,
+            finished: false,
+        }
+    }
+
+    /// Run the current query until the host has to answer an event, it has a result, or it is
+    /// done. A query's `Done` event is returned before the next query starts. Returns `None` once
+    /// every query is done.
+    ///
+    /// An error ends the query that raised it, which is still [`BatchQuery::current`] when the
+    /// error is returned; the next call starts the following query.
+    pub fn next_event(&mut self) -> PolarResult<Option<BatchEvent>> {
+        if self.finished {
+            self.current += 1;
+            self.finished = false;
+        }
+        let query = match self.queries.get_mut(self.current) {
+            Some(query) => query,
+            None => return Ok(None),
+        };
+        let event = query.next_event();
+        self.finished = matches!(event, Err(_) | Ok(QueryEvent::Done { .. }));
+        Ok(Some(BatchEvent {
+            index: self.current,
+            event: event?,
+        }))
+    }
+
+    /// The index of the query that returned the last event.
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    /// The query at [`BatchQuery::current`], for answering the event it returned.
+    pub fn query_mut(&mut self) -> Option<&mut Query> {
+        self.queries.get_mut(self.current)
+    }
+
+    pub fn len(&self) -> usize {
+        self.queries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queries.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::polar::Polar;
+    use crate::sources::Source;
+    use crate::terms::{ExternalInstance, Term, Value};
+
+    fn instance(instance_id: u64) -> Term {
+        Term::from(Value::ExternalInstance(ExternalInstance {
+            instance_id,
+            constructor: None,
+            repr: None,
+            class_repr: None,
+            class_id: None,
+        }))
+    }
+
+    #[test]
+    fn test_batch_shares_memoized_calls() {
+        let p = Polar::new();
+        p.load(vec![Source::new("f(x, y) if x.name = y;")]).unwrap();
+        let queries = vec![
+            term!(call!("f", [sym!("x"), "alice"])),
+            term!(call!("f", [sym!("x"), "bob"])),
+        ];
+        let mut batch = p.new_batch_query(queries);
+        for query in 0..2 {
+            batch.queries[query].bind(sym!("x"), instance(1)).unwrap();
+        }
+
+        // Rules cleared after the batch starts are still seen by it.
+        p.clear_rules();
+
+        let mut calls = 0;
+        let mut results = vec![];
+        while let Some(BatchEvent { index, event }) = batch.next_event().unwrap() {
+            match event {
+                QueryEvent::ExternalCall { call_id, .. } => {
+                    calls += 1;
+                    batch
+                        .query_mut()
+                        .unwrap()
+                        .call_result(call_id, Some(term!("alice")))
+                        .unwrap();
+                }
+                QueryEvent::Result { .. } => results.push(index),
+                QueryEvent::Done { .. } => {}
+                event => panic!("unexpected event {:?}", event),
+            }
+        }
+        assert_eq!(calls, 1);
+        assert_eq!(results, vec![0]);
+        assert!(batch.next_event().unwrap().is_none());
+    }
+}
@@ -3,8 +3,10 @@
 /// Bindings associate variables in the VM with constraints or values.
 use std::collections::{HashMap, HashSet};
 
+use serde::{Deserialize, Serialize};
+
 use crate::{
-    error::{PolarResult, RuntimeError},
+    error::{invalid_state, PolarResult, RuntimeError},
     folder::{fold_list, fold_term, Folder},
     terms::{has_rest_var, Operation, Symbol, Term, Value},
     vm::Goal,
@@ -22,7 +24,7 @@ pub type Bsp = Bsps;
 pub type FollowerId = usize;
 
 /// Bsps represents bsps of a binding manager and its followers as a tree.
-#[derive(Clone, Debug, Default, PartialEq)]
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
 pub struct Bsps {
     /// Index into `bindings` array
     bindings_index: usize,
@@ -61,11 +63,12 @@ impl<'a> Folder for Derefer<'a> {
         let has_rest = has_rest_var(&list);
         let mut list = fold_list(list, self);
         if has_rest {
-            let last = list.pop().unwrap();
-            if let Value::List(rest) = last.value() {
-                list.append(&mut rest.clone());
-            } else {
-                list.push(last);
+            if let Some(last) = list.pop() {
+                if let Value::List(rest) = last.value() {
+                    list.append(&mut rest.clone());
+                } else {
+                    list.push(last);
+                }
             }
         }
         list
@@ -230,8 +233,7 @@ impl BindingManager {
         self.do_followers(|_, follower| {
             follower.bind(var, val.clone())?;
             Ok(())
-        })
-        .unwrap();
+        })?;
 
         Ok(goal)
     }
@@ -249,7 +251,7 @@ impl BindingManager {
     /// (The only current usage is for replacing default values with call ids).
     pub fn unsafe_rebind(&mut self, var: &Symbol, val: Term) {
         use BindingManagerVariableState::*;
-        assert!(matches!(self._variable_state(var), Unbound | Bound(_)));
+        debug_assert!(matches!(self._variable_state(var), Unbound | Bound(_)));
         self.add_binding(var, val);
     }
 
@@ -262,7 +264,7 @@ impl BindingManager {
         use BindingManagerVariableState::*;
         self.do_followers(|_, follower| follower.add_constraint(term))?;
 
-        assert!(term.as_expression().is_ok());
+        term.as_expression()?;
         let mut op = op!(And, term.clone());
 
         // include all constraints applying to any of its variables.
@@ -302,15 +304,13 @@ impl BindingManager {
 
     /// Reset the state of `BindingManager` to what it was at `to`.
     pub fn backtrack(&mut self, to: &Bsp) {
-        self.do_followers(|follower_id, follower| {
-            if let Some(follower_to) = to.followers.get(&follower_id) {
+        for (follower_id, follower) in self.followers.iter_mut() {
+            if let Some(follower_to) = to.followers.get(follower_id) {
                 follower.backtrack(follower_to);
             } else {
                 follower.backtrack(&Bsp::default());
             }
-            Ok(())
-        })
-        .unwrap();
+        }
 
         self.bindings.truncate(to.bindings_index)
     }
@@ -357,6 +357,14 @@ impl BindingManager {
         VariableState::Unbound
     }
 
+    /// Return a non-temporary variable bound together with `variable` in a cycle, if any.
+    pub fn named_alias(&self, variable: &Symbol) -> Option<Symbol> {
+        match self._variable_state(variable) {
+            BindingManagerVariableState::Cycle(c) => c.into_iter().find(|v| !v.is_temporary_var()),
+            _ => None,
+        }
+    }
+
     /// Return all variables used in this binding manager.
     pub fn variables(&self) -> HashSet<Symbol> {
         self.bindings
@@ -452,8 +460,11 @@ impl BindingManager {
 
             // free x cycle, cycle x free -- create a pair of bindings var -> var
             ((var, Unbound), (cvar, Cycle(cycle))) | ((cvar, Cycle(cycle)), (var, Unbound)) => {
-                let last = cycle.last().unwrap();
-                assert_ne!(last, cvar);
+                let last = match cycle.last() {
+                    Some(last) => last,
+                    None => return invalid_state(format!("empty cycle for {}", cvar)),
+                };
+                debug_assert_ne!(last, cvar);
                 self.add_binding(last, term!(var.clone()));
                 self.add_binding(var, term!(cvar.clone()));
                 Ok(None)
@@ -466,13 +477,17 @@ impl BindingManager {
 
                 // already the same cycle? then do nothing
                 if iter_left.intersection(&iter_right).next().is_some() {
-                    assert_eq!(iter_left, iter_right);
+                    debug_assert_eq!(iter_left, iter_right);
                 // else join them with a pair of bindings var -> var
                 } else {
-                    let last_left = left_cycle.last().unwrap();
-                    let last_right = right_cycle.last().unwrap();
-                    assert_ne!(last_left, left);
-                    assert_ne!(last_right, right);
+                    let (last_left, last_right) = match (left_cycle.last(), right_cycle.last()) {
+                        (Some(last_left), Some(last_right)) => (last_left, last_right),
+                        _ => {
+                            return invalid_state(format!("empty cycle for {} or {}", left, right))
+                        }
+                    };
+                    debug_assert_ne!(last_left, left);
+                    debug_assert_ne!(last_right, right);
                     self.add_binding(last_left, term!(right.clone()));
                     self.add_binding(last_right, term!(left.clone()));
                 }
@@ -578,7 +593,8 @@ impl BindingManager {
         use BindingManagerVariableState::*;
         let index = bsp.bindings_index;
         let mut path = vec![variable];
-        while let Some(value) = self.value(path.last().unwrap(), index) {
+        let mut next = variable;
+        while let Some(value) = self.value(next, index) {
             match value.value() {
                 Value::Expression(e) => return Partial(e),
                 Value::Variable(v) | Value::RestVariable(v) => {
@@ -586,6 +602,7 @@ impl BindingManager {
                         return Cycle(path.into_iter().cloned().collect());
                     } else {
                         path.push(v);
+                        next = v;
                     }
                 }
                 _ => return Bound(value.clone()),
@@ -0,0 +1,53 @@
+//! Predicates implemented by the host in Rust, e.g., CIDR or glob matching.
+//!
+//! A built-in registered with [`Polar::register_builtin`](crate::polar::Polar::register_builtin)
+//! is called by the VM directly when a query calls a predicate of that name, without an
+//! external call to the host. Policies can't define rules or rule types with the same name.
+
+use std::fmt;
+use std::sync::Arc;
+
+use crate::terms::{Symbol, Term};
+
+/// A pure predicate over ground terms.
+pub trait BuiltinPredicate: Send + Sync {
+    /// Whether the predicate holds for `args`, or a message explaining why it can't be checked.
+    fn call(&self, args: &[Term]) -> Result<bool, String>;
+}
+
+impl<F> BuiltinPredicate for F
+where
+    F: Fn(&[Term]) -> Result<bool, String> + Send + Sync,
+{
+    fn call(&self, args: &[Term]) -> Result<bool, String> {
+        self(args)
+    }
+}
+
+/// A registered built-in predicate.
+#[derive(Clone)]
+pub struct Builtin {
+    pub name: Symbol,
+    pub arity: usize,
+    predicate: Arc<dyn BuiltinPredicate>,
+}
+
+impl Builtin {
+    pub fn new<P: BuiltinPredicate + 'static>(name: Symbol, arity: usize, predicate: P) -> Self {
+        Self {
+            name,
+            arity,
+            predicate: Arc::new(predicate),
+        }
+    }
+
+    pub fn call(&self, args: &[Term]) -> Result<bool, String> {
+        self.predicate.call(args)
+    }
+}
+
+impl fmt::Display for Builtin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.name, self.arity)
+    }
+}
@@ -0,0 +1,315 @@
+//! Compiled policy bundles.
+//!
+//! Loading a policy parses, rewrites and validates it. A bundle is a snapshot of a loaded
+//! [`KnowledgeBase`] that can be loaded again without doing any of that. Bundles are only valid
+//! for the version of Polar that built them and for the exact sources they were built from;
+//! anything else is rejected with `RuntimeError::InvalidBundle`.
+//!
+//! Registered constants and classes aren't part of a bundle, since they come from the host.
+//! They must be registered before loading a bundle, as before loading a policy.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{PolarResult, RuntimeError};
+use crate::inline_query::InlineQuery;
+use crate::resource_block::Declaration;
+use crate::rules::Rule;
+use crate::sources::{Origin, Source, SourceInfo};
+use crate::terms::*;
+
+/// Bumped whenever the layout of `Bundle` changes.
+const FORMAT_VERSION: u32 = 5;
+const POLAR_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+fn invalid_bundle<T>(msg: impl Into<String>) -> PolarResult<T> {
+    Err(RuntimeError::InvalidBundle { msg: msg.into() }.into())
+}
+
+/// A stable hash of `sources`, to tell whether a bundle was built from them. Uses FNV-1a, since
+/// `DefaultHasher` may change between Rust releases.
+fn fingerprint<'a, I>(sources: I) -> u64
+where
+    I: IntoIterator<Item = (Option<&'a str>, &'a str)>,
+{
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    let mut write = |bytes: &[u8]| {
+        for byte in bytes {
+            hash ^= u64::from(*byte);
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    };
+    for (filename, src) in sources {
+        // Length-prefix each part so that different splits of the same bytes hash differently.
+        let filename = filename.unwrap_or_default();
+        write(&(filename.len() as u64).to_le_bytes());
+        write(filename.as_bytes());
+        write(&(src.len() as u64).to_le_bytes());
+        write(src.as_bytes());
+    }
+    hash
+}
+
+/// The parts of a `KnowledgeBase` that are loaded from a policy, with maps flattened into
+/// lists so that the terms in them can be walked in a fixed order.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct BundledPolicy {
+    pub loaded_sources: Vec<(Option<String>, String)>,
+    pub loaded_content: Vec<(String, String)>,
+    /// Rules in the order they were added.
+    pub rules: Vec<Rule>,
+    pub rule_types: Vec<Rule>,
+    pub deprecated_rules: Vec<(Symbol, usize, Term, Rule)>,
+    /// The rules annotated with `@private`.
+    pub private_rules: Vec<Rule>,
+    pub inline_queries: Vec<InlineQuery>,
+    pub declarations: Vec<(Term, Vec<(Term, Declaration)>)>,
+    /// Shorthand rules by resource, as `(head, implier, relation)`.
+    #[allow(clippy::type_complexity)]
+    pub shorthand_rules: Vec<(Term, Vec<(Term, Term, Option<(Term, Term)>)>)>,
+    pub actors: Vec<Term>,
+    pub resources: Vec<Term>,
+    pub unions: Vec<(Symbol, Vec<Term>)>,
+    pub next_gensym: u64,
+}
+
+impl BundledPolicy {
+    /// Call `f` on the source info of every rule and term in the policy, in a fixed order.
+    fn each_source_info(&mut self, f: &mut dyn FnMut(&mut SourceInfo)) {
+        for rule in self
+            .rules
+            .iter_mut()
+            .chain(self.rule_types.iter_mut())
+            .chain(self.private_rules.iter_mut())
+        {
+            rule_source_infos(rule, f);
+        }
+        for (_, _, message, rule) in &mut self.deprecated_rules {
+            term_source_infos(message, f);
+            rule_source_infos(rule, f);
+        }
+        for query in &mut self.inline_queries {
+            term_source_infos(&mut query.term, f);
+            if let Some(expected) = &mut query.expected {
+                term_source_infos(expected, f);
+            }
+        }
+        for (resource, declarations) in &mut self.declarations {
+            term_source_infos(resource, f);
+            for (name, declaration) in declarations {
+                term_source_infos(name, f);
+                if let Declaration::Relation(related, _) = declaration {
+                    term_source_infos(related, f);
+                }
+            }
+        }
+        for (resource, rules) in &mut self.shorthand_rules {
+            term_source_infos(resource, f);
+            for (head, implier, relation) in rules {
+                term_source_infos(head, f);
+                term_source_infos(implier, f);
+                if let Some((related, name)) = relation {
+                    term_source_infos(related, f);
+                    term_source_infos(name, f);
+                }
+            }
+        }
+        let members = self.unions.iter_mut().flat_map(|(_, members)| members);
+        for term in self
+            .actors
+            .iter_mut()
+            .chain(&mut self.resources)
+            .chain(members)
+        {
+            term_source_infos(term, f);
+        }
+    }
+}
+
+fn rule_source_infos(rule: &mut Rule, f: &mut dyn FnMut(&mut SourceInfo)) {
+    f(&mut rule.source_info);
+    for param in &mut rule.params {
+        term_source_infos(&mut param.parameter, f);
+        if let Some(specializer) = &mut param.specializer {
+            term_source_infos(specializer, f);
+        }
+    }
+    term_source_infos(&mut rule.body, f);
+}
+
+fn term_source_infos(term: &mut Term, f: &mut dyn FnMut(&mut SourceInfo)) {
+    f(term.source_info_mut());
+    match term.mut_value() {
+        Value::ExternalInstance(ExternalInstance {
+            constructor: Some(constructor),
+            ..
+        }) => term_source_infos(constructor, f),
+        Value::Dictionary(Dictionary { fields })
+        | Value::Pattern(Pattern::Dictionary(Dictionary { fields }))
+        | Value::Pattern(Pattern::Instance(InstanceLiteral {
+            fields: Dictionary { fields },
+            ..
+        })) => fields.values_mut().for_each(|t| term_source_infos(t, f)),
+        Value::Call(Call { args, kwargs, .. }) => {
+            args.iter_mut().for_each(|t| term_source_infos(t, f));
+            kwargs
+                .iter_mut()
+                .flat_map(|kwargs| kwargs.values_mut())
+                .for_each(|t| term_source_infos(t, f));
+        }
+        Value::List(terms) | Value::Expression(Operation { args: terms, .. }) => {
+            terms.iter_mut().for_each(|t| term_source_infos(t, f))
+        }
+        // Rebuild the set, since its index holds copies of the elements.
+        Value::Set(set) => {
+            let mut elements = TermList::from(std::mem::take(set));
+            elements.iter_mut().for_each(|t| term_source_infos(t, f));
+            *set = elements.into();
+        }
+        _ => (),
+    }
+}
+
+/// `SourceInfo` without the source itself, which is stored once in `Bundle::sources`.
+#[derive(Serialize, Deserialize)]
+enum BundledSourceInfo {
+    Parser {
+        source: usize,
+        left: usize,
+        right: usize,
+        generated_from: Option<Origin>,
+    },
+    TemporaryVariable,
+    Ffi,
+    Test,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Bundle {
+    format_version: u32,
+    polar_version: String,
+    /// The fingerprint of `policy.loaded_sources`.
+    fingerprint: u64,
+    policy: BundledPolicy,
+    /// Sources that terms were parsed from.
+    sources: Vec<Source>,
+    /// The source info of every term in `policy`, in the order of
+    /// `BundledPolicy::each_source_info`.
+    source_infos: Vec<BundledSourceInfo>,
+}
+
+/// Serialize `policy` to a bundle.
+pub(crate) fn save(mut policy: BundledPolicy) -> PolarResult<Vec<u8>> {
+    let mut sources = vec![];
+    let mut source_indices = HashMap::new();
+    let mut source_infos = vec![];
+    policy.each_source_info(&mut |source_info| {
+        source_infos.push(match source_info {
+            SourceInfo::Parser(context) => {
+                let source = *source_indices
+                    .entry(Arc::as_ptr(&context.source))
+                    .or_insert_with(|| {
+                        sources.push(context.source.clone());
+                        sources.len() - 1
+                    });
+                BundledSourceInfo::Parser {
+                    source,
+                    left: context.left,
+                    right: context.right,
+                    generated_from: context.generated_from.clone(),
+                }
+            }
+            SourceInfo::TemporaryVariable => BundledSourceInfo::TemporaryVariable,
+            SourceInfo::Ffi => BundledSourceInfo::Ffi,
+            SourceInfo::Test => BundledSourceInfo::Test,
+        })
+    });
+    let bundle = Bundle {
+        format_version: FORMAT_VERSION,
+        polar_version: POLAR_VERSION.to_owned(),
+        fingerprint: fingerprint(
+            policy
+                .loaded_sources
+                .iter()
+                .map(|(filename, src)| (filename.as_deref(), src.as_str())),
+        ),
+        policy,
+        sources: sources
+            .into_iter()
+            .map(|source| Source {
+                filename: source.filename.clone(),
+                src: source.src.clone(),
+            })
+            .collect(),
+        source_infos,
+    };
+    serde_cbor::to_vec(&bundle).or_else(|e| invalid_bundle(e.to_string()))
+}
+
+/// Deserialize the policy in `bundle`, checking that it was built by this version of Polar
+/// from `sources`.
+pub(crate) fn load(bundle: &[u8], sources: &[Source]) -> PolarResult<BundledPolicy> {
+    let bundle: Bundle = match serde_cbor::from_slice(bundle) {
+        Ok(bundle) => bundle,
+        Err(e) => return invalid_bundle(format!("could not be decoded: {}", e)),
+    };
+    if bundle.format_version != FORMAT_VERSION || bundle.polar_version != POLAR_VERSION {
+        return invalid_bundle(format!(
+            "built by Polar {}, but this is Polar {}",
+            bundle.polar_version, POLAR_VERSION
+        ));
+    }
+    let expected = fingerprint(
+        sources
+            .iter()
+            .map(|source| (source.filename.as_deref(), source.src.as_str())),
+    );
+    if bundle.fingerprint != expected {
+        return invalid_bundle("built from different sources than the ones given");
+    }
+
+    let Bundle {
+        mut policy,
+        sources,
+        source_infos,
+        ..
+    } = bundle;
+    let sources = sources.into_iter().map(Arc::new).collect::<Vec<_>>();
+    let mut source_infos = source_infos.into_iter();
+    let mut corrupt = false;
+    policy.each_source_info(&mut |source_info| {
+        *source_info = match source_infos.next() {
+            Some(BundledSourceInfo::Parser {
+                source,
+                left,
+                right,
+                generated_from,
+            }) => match sources.get(source) {
+                Some(source) => {
+                    let mut source_info = SourceInfo::parser(source.clone(), left, right);
+                    if let Some(origin) = generated_from {
+                        source_info.mark_generated(&origin);
+                    }
+                    source_info
+                }
+                None => {
+                    corrupt = true;
+                    return;
+                }
+            },
+            Some(BundledSourceInfo::TemporaryVariable) => SourceInfo::TemporaryVariable,
+            Some(BundledSourceInfo::Ffi) => SourceInfo::Ffi,
+            Some(BundledSourceInfo::Test) => SourceInfo::Test,
+            None => {
+                corrupt = true;
+                return;
+            }
+        }
+    });
+    if corrupt || source_infos.next().is_some() {
+        return invalid_bundle("source locations don't match its policy");
+    }
+    Ok(policy)
+}
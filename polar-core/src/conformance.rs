@@ -0,0 +1,292 @@
+//! Behavioral test cases for the engine, packaged as data along with a runner.
+//!
+//! Bindings built on top of `polar-core` (directly or over FFI) implement [`Engine`] and call
+//! [`run`] to check that they behave exactly like the version of the core they embed. The cases
+//! live in the `conformance/` directory of this crate as JSON files, which are compiled into the
+//! library so that the data always matches the engine version.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use serde::Deserialize;
+use serde_json::Value as Json;
+
+use crate::data_filtering::{PartialResults, Types};
+use crate::events::{QueryEvent, ResultEvent};
+use crate::kb::Bindings;
+use crate::parser::parse_query;
+use crate::polar::Polar;
+use crate::terms::Symbol;
+
+const SUITES: &[&str] = &[
+    include_str!("../conformance/parse.json"),
+    include_str!("../conformance/evaluate.json"),
+    include_str!("../conformance/data_filter.json"),
+];
+
+/// A named group of test cases.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Suite {
+    pub name: String,
+    pub description: String,
+    pub cases: Vec<Case>,
+}
+
+/// A single test case.
+///
+/// `load` is loaded first, if present. Then either `query` is run and must produce `results`, or
+/// `filter` is built and must match its expected filter. If `error` is set, the first step that
+/// fails must fail with an error whose message contains it.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Case {
+    pub description: String,
+    pub load: Option<String>,
+    pub query: Option<String>,
+    /// Expected bindings for each result, with values written as Polar terms.
+    #[serde(default)]
+    pub results: Vec<HashMap<String, String>>,
+    pub filter: Option<FilterCase>,
+    pub error: Option<String>,
+}
+
+/// Inputs to `Polar::build_data_filter` and the filter it must produce.
+#[derive(Clone, Debug, Deserialize)]
+pub struct FilterCase {
+    pub types: Types,
+    /// Bindings for each partial result, with values written as Polar terms.
+    pub partial_results: Vec<HashMap<String, String>>,
+    pub variable: String,
+    pub class_tag: String,
+    /// The serialized filter.
+    pub expected: Json,
+}
+
+/// The operations exercised by the conformance cases.
+///
+/// Each case runs against a fresh engine.
+pub trait Engine {
+    fn load(&mut self, src: &str) -> Result<(), String>;
+    /// Run a query that makes no calls to the host, returning the bindings of every result.
+    fn query(&mut self, src: &str) -> Result<Vec<Bindings>, String>;
+    /// Build a data filter, returning it serialized as JSON.
+    fn build_data_filter(
+        &mut self,
+        types: Types,
+        partial_results: PartialResults,
+        variable: &str,
+        class_tag: &str,
+    ) -> Result<Json, String>;
+}
+
+impl Engine for Polar {
+    fn load(&mut self, src: &str) -> Result<(), String> {
+        self.load_str(src).map_err(|e| e.to_string())
+    }
+
+    fn query(&mut self, src: &str) -> Result<Vec<Bindings>, String> {
+        let mut query = self.new_query(src, false).map_err(|e| e.to_string())?;
+        let mut results = vec![];
+        loop {
+            match query.next_event().map_err(|e| e.to_string())? {
+                QueryEvent::Done { .. } => return Ok(results),
+                QueryEvent::Result { bindings, .. } => results.push(bindings),
+                event => return Err(format!("unexpected event {:?}", event)),
+            }
+        }
+    }
+
+    fn build_data_filter(
+        &mut self,
+        types: Types,
+        partial_results: PartialResults,
+        variable: &str,
+        class_tag: &str,
+    ) -> Result<Json, String> {
+        Polar::build_data_filter(self, types, partial_results, variable, class_tag)
+            .map_err(|e| e.to_string())
+            .and_then(|filter| serde_json::to_value(filter).map_err(|e| e.to_string()))
+    }
+}
+
+/// A case that didn't behave as expected.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Failure {
+    pub suite: String,
+    pub case: String,
+    pub message: String,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} / {}: {}", self.suite, self.case, self.message)
+    }
+}
+
+/// All conformance suites shipped with this version of the core.
+pub fn suites() -> Vec<Suite> {
+    SUITES
+        .iter()
+        .map(|suite| serde_json::from_str(suite).expect("invalid conformance suite"))
+        .collect()
+}
+
+/// Run every conformance case against engines created by `new_engine`, returning the failures.
+pub fn run<E, F>(mut new_engine: F) -> Vec<Failure>
+where
+    E: Engine,
+    F: FnMut() -> E,
+{
+    let mut failures = vec![];
+    for suite in suites() {
+        for case in &suite.cases {
+            if let Err(message) = run_case(&mut new_engine(), case) {
+                failures.push(Failure {
+                    suite: suite.name.clone(),
+                    case: case.description.clone(),
+                    message,
+                });
+            }
+        }
+    }
+    failures
+}
+
+fn parse_bindings(bindings: &HashMap<String, String>) -> Result<Bindings, String> {
+    bindings
+        .iter()
+        .map(|(name, src)| {
+            parse_query(src)
+                .map(|term| (Symbol::new(name), term))
+                .map_err(|e| format!("invalid expected term {}: {}", src, e))
+        })
+        .collect()
+}
+
+/// Run `case` against `engine`, returning a description of the mismatch if it fails.
+pub fn run_case<E: Engine>(engine: &mut E, case: &Case) -> Result<(), String> {
+    match (execute(engine, case), &case.error) {
+        (Ok(()), None) => Ok(()),
+        (Ok(()), Some(expected)) => Err(format!("expected an error containing {:?}", expected)),
+        (Err(Mismatch(message)), _) => Err(message),
+        (Err(Error(message)), Some(expected)) if message.contains(expected) => Ok(()),
+        (Err(Error(message)), Some(expected)) => Err(format!(
+            "expected an error containing {:?}, got {:?}",
+            expected, message
+        )),
+        (Err(Error(message)), None) => Err(format!("unexpected error: {}", message)),
+    }
+}
+
+enum Outcome {
+    /// The engine returned an error.
+    Error(String),
+    /// The engine succeeded with the wrong answer.
+    Mismatch(String),
+}
+use Outcome::*;
+
+fn execute<E: Engine>(engine: &mut E, case: &Case) -> Result<(), Outcome> {
+    if let Some(src) = &case.load {
+        engine.load(src).map_err(Error)?;
+    }
+
+    if let Some(src) = &case.query {
+        let expected = case
+            .results
+            .iter()
+            .map(parse_bindings)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(Mismatch)?;
+        let results = engine.query(src).map_err(Error)?;
+        if results != expected {
+            return Err(Mismatch(format!(
+                "expected results {}, got {}",
+                format_results(&expected),
+                format_results(&results)
+            )));
+        }
+    }
+
+    if let Some(filter) = &case.filter {
+        let partial_results = filter
+            .partial_results
+            .iter()
+            .map(|bindings| parse_bindings(bindings).map(ResultEvent::new))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(Mismatch)?;
+        let actual = engine
+            .build_data_filter(
+                filter.types.clone(),
+                partial_results,
+                &filter.variable,
+                &filter.class_tag,
+            )
+            .map_err(Error)?;
+        if normalize_filter(actual.clone()) != normalize_filter(filter.expected.clone()) {
+            return Err(Mismatch(format!(
+                "expected filter {}, got {}",
+                filter.expected, actual
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+fn format_results(results: &[Bindings]) -> String {
+    let results = results
+        .iter()
+        .map(|bindings| {
+            let mut bindings = bindings
+                .iter()
+                .map(|(name, value)| format!("{}: {}", name, value))
+                .collect::<Vec<_>>();
+            bindings.sort();
+            format!("{{{}}}", bindings.join(", "))
+        })
+        .collect::<Vec<_>>();
+    format!("[{}]", results.join(", "))
+}
+
+/// Conditions within a filter are sets, so compare them irrespective of order.
+fn normalize_filter(mut filter: Json) -> Json {
+    fn sort(values: &mut [Json]) {
+        values.sort_by_cached_key(|value| value.to_string());
+    }
+
+    if let Some(Json::Array(conditions)) = filter.get_mut("conditions") {
+        for condition in conditions.iter_mut() {
+            if let Json::Array(condition) = condition {
+                sort(condition);
+            }
+        }
+        sort(conditions);
+    }
+    if let Some(Json::Array(relations)) = filter.get_mut("relations") {
+        sort(relations);
+    }
+    filter
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_conformance() {
+        let failures = run(|| {
+            let mut polar = Polar::new();
+            polar.set_ignore_no_allow_warning(true);
+            polar
+        });
+        assert!(
+            failures.is_empty(),
+            "{}",
+            failures
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join("\n")
+        );
+    }
+}
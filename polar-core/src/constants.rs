@@ -1,7 +1,8 @@
 use crate::terms::{Symbol, Term};
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
+use std::sync::{Arc, PoisonError, RwLock};
 
-#[derive(Default, Debug)]
+#[derive(Clone, Default, Debug)]
 pub(crate) struct Constants {
     // Symbol -> Term (populated by *all* constants)
     pub symbol_to_term: HashMap<Symbol, Term>,
@@ -9,13 +10,53 @@ pub(crate) struct Constants {
     class_symbol_to_id: HashMap<Symbol, u64>,
     // class_id -> Symbol (populated by class constants)
     class_id_to_symbol: HashMap<u64, Symbol>,
+    // Lazy constants, whether or not the host has supplied a value for them yet
+    lazy: BTreeSet<Symbol>,
+    // Symbol -> Term (populated by lazy constants once the host supplies a value). Shared by
+    // snapshots of the KB, so a value fetched by a query against one is cached for all of them.
+    lazy_values: Arc<RwLock<HashMap<Symbol, Term>>>,
 }
 
 impl Constants {
     pub(crate) fn insert(&mut self, name: Symbol, value: Term) {
+        self.lazy.remove(&name);
         self.symbol_to_term.insert(name, value);
     }
 
+    pub(crate) fn insert_lazy(&mut self, name: Symbol) {
+        self.symbol_to_term.remove(&name);
+        // Snapshots taken before the constant was registered again keep their cached value.
+        let mut lazy_values = self
+            .lazy_values
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+        lazy_values.remove(&name);
+        self.lazy_values = Arc::new(RwLock::new(lazy_values));
+        self.lazy.insert(name);
+    }
+
+    pub(crate) fn resolve_lazy(&self, name: Symbol, value: Term) {
+        if self.lazy.contains(&name) {
+            self.lazy_values
+                .write()
+                .unwrap_or_else(PoisonError::into_inner)
+                .insert(name, value);
+        }
+    }
+
+    pub(crate) fn get_lazy(&self, name: &Symbol) -> Option<Term> {
+        self.lazy_values
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(name)
+            .cloned()
+    }
+
+    pub(crate) fn lazy(&self) -> &BTreeSet<Symbol> {
+        &self.lazy
+    }
+
     pub(crate) fn insert_class(&mut self, name: Symbol, value: Term, class_id: u64) {
         self.insert(name.clone(), value);
         self.class_symbol_to_id.insert(name.clone(), class_id);
@@ -23,7 +64,7 @@ impl Constants {
     }
 
     pub(crate) fn contains_key(&self, name: &Symbol) -> bool {
-        self.symbol_to_term.contains_key(name)
+        self.symbol_to_term.contains_key(name) || self.lazy.contains(name)
     }
 
     pub(crate) fn get(&self, name: &Symbol) -> Option<&Term> {
@@ -20,13 +20,17 @@ impl Default for Counter {
 
 impl Counter {
     /// Create a new counter starting at `start`.
-    #[cfg(test)]
     pub fn with_start(start: u64) -> Self {
         Self {
             next: Arc::new(AtomicU64::new(start)),
         }
     }
 
+    /// The ID that `next` will return, without taking it.
+    pub fn peek(&self) -> u64 {
+        self.next.load(Ordering::SeqCst)
+    }
+
     /// Return a monotonically increasing integer ID.
     ///
     /// Wraps around at 52 bits of precision so that it can be safely
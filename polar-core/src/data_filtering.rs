@@ -60,6 +60,34 @@ pub enum ConstraintKind {
     Contains, // The field is a collection that contains the value.
     Neq,
     Nin,
+    Lt,  // The field is less than the value.
+    Leq, // The field is less than or equal to the value.
+    Gt,  // The field is greater than the value.
+    Geq, // The field is greater than or equal to the value.
+}
+
+impl ConstraintKind {
+    /// The kind for `x op value`, if `op` compares values by their order.
+    fn ordering(op: Operator) -> Option<Self> {
+        match op {
+            Operator::Lt => Some(Self::Lt),
+            Operator::Leq => Some(Self::Leq),
+            Operator::Gt => Some(Self::Gt),
+            Operator::Geq => Some(Self::Geq),
+            _ => None,
+        }
+    }
+
+    /// The kind for `value op x`, given the kind for `x op value`.
+    fn flip(self) -> Self {
+        match self {
+            Self::Lt => Self::Gt,
+            Self::Leq => Self::Geq,
+            Self::Gt => Self::Lt,
+            Self::Geq => Self::Leq,
+            kind => kind,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq, Hash)]
@@ -106,11 +134,21 @@ struct VarInfo {
     types: Vec<(VarName, TypeName)>,                         // x matches XClass
     eq_values: Vec<(VarName, Term)>,                         // x = 1
     contained_values: Vec<(Term, VarName)>,                  // 1 in x
+    in_values: Vec<(VarName, Term)>,                         // x in [1, 2]
+    comparisons: Vec<(VarName, ConstraintKind, Term)>,       // x < 1
     field_relationships: Vec<(VarName, FieldName, VarName)>, // x.a = y
     in_relationships: Vec<(VarName, VarName)>,               // x in y
     counter: Counter,
 }
 
+/// One disjunct of a partial result: constraints that must hold, and groups of constraints
+/// that must not all hold at once.
+#[derive(Clone, Debug, Default)]
+struct Conjunction {
+    positive: Vec<Term>,
+    negated: Vec<Vec<Term>>,
+}
+
 #[derive(Debug)]
 struct Vars {
     variables: Map<VarId, Set<VarName>>,
@@ -119,6 +157,8 @@ struct Vars {
     in_relationships: Set<(VarId, VarId)>,
     eq_values: Map<VarId, Term>,
     contained_values: Map<VarId, Set<Term>>,
+    in_values: Map<VarId, Set<Term>>,
+    comparisons: Map<VarId, Set<(ConstraintKind, Term)>>,
     types: Map<VarId, TypeName>,
     this_id: VarId,
 }
@@ -185,7 +225,7 @@ impl VarInfo {
             _ => {
                 let new_var = sym!(&format!(
                     "_{}_dot_{}_{}",
-                    sym.0,
+                    sym.as_str(),
                     field_str,
                     self.counter.next()
                 ));
@@ -225,7 +265,7 @@ impl VarInfo {
         match rhs.as_pattern() {
             Ok(Pattern::Instance(i)) if i.fields.fields.is_empty() => {
                 let lhs = self.symbolize(lhs);
-                self.types.push((lhs, i.tag.0.clone()));
+                self.types.push((lhs, i.tag.as_str().to_string()));
                 Ok(self)
             }
             _ => df_unsupported_op(Operation {
@@ -276,6 +316,12 @@ impl VarInfo {
         match (self.undot(left), self.undot(right)) {
             (Value::Variable(l), Value::Variable(r)) => self.in_relationships.push((l, r)),
             (val, Value::Variable(var)) => self.contained_values.push((Term::from(val), var)),
+            // A list from the host or the policy, e.g., `post.status in ["draft", "review"]`.
+            (Value::Variable(var), Value::List(list))
+                if right.is_ground() && !has_rest_var(&list) =>
+            {
+                self.in_values.push((var, right.clone()))
+            }
             _ => df_unsupported_op(Operation {
                 operator: Operator::In,
                 args: vec![left.clone(), right.clone()],
@@ -284,6 +330,25 @@ impl VarInfo {
         Ok(self)
     }
 
+    fn do_compare(mut self, op: Operator, left: &Term, right: &Term) -> PolarResult<Self> {
+        let kind = ConstraintKind::ordering(op).expect("an ordering operator");
+        match (self.undot(left), self.undot(right)) {
+            (Value::Variable(_), Value::Variable(_)) => df_unsupported_op(Operation {
+                operator: op,
+                args: vec![left.clone(), right.clone()],
+            })?,
+            (Value::Variable(var), val) => self.comparisons.push((var, kind, Term::from(val))),
+            (val, Value::Variable(var)) => {
+                self.comparisons.push((var, kind.flip(), Term::from(val)))
+            }
+            _ => df_unsupported_op(Operation {
+                operator: op,
+                args: vec![left.clone(), right.clone()],
+            })?,
+        }
+        Ok(self)
+    }
+
     /// Process an expression in the context of this VarInfo. Just does side effects.
     fn process_exp(self, exp: &Operation) -> PolarResult<Self> {
         use Operator::*;
@@ -294,12 +359,203 @@ impl VarInfo {
             Isa if args.len() == 2 => self.do_isa(&args[0], &args[1]),
             Neq if args.len() == 2 => self.do_neq(&args[0], &args[1]),
             In if args.len() == 2 => self.do_in(&args[0], &args[1]),
+            Lt | Leq | Gt | Geq if args.len() == 2 => {
+                self.do_compare(exp.operator, &args[0], &args[1])
+            }
             Unify | Eq | Assign if args.len() == 2 => self.do_unify(&args[0], &args[1]),
             _ => df_unsupported_op(exp.clone()),
         }
     }
 }
 
+impl Conjunction {
+    /// Expand a conjunction of constraints, which may contain `or`s and `not`s, into a
+    /// disjunction of `Conjunction`s. `outer_vars` are the variables constrained outside of
+    /// `terms`.
+    fn expand(terms: &[Term], outer_vars: &HashSet<Symbol>) -> PolarResult<Vec<Self>> {
+        let mut disjuncts = vec![Self::default()];
+        for (i, term) in terms.iter().enumerate() {
+            let exp = term.as_expression()?;
+            match exp.operator {
+                Operator::And => {
+                    let outer_vars = vars_except(terms, i, outer_vars);
+                    let inner = Self::expand(&exp.args, &outer_vars)?;
+                    disjuncts = Self::product(disjuncts, inner);
+                }
+                Operator::Not if exp.args.len() == 1 => disjuncts
+                    .iter_mut()
+                    .for_each(|d| d.negated.push(exp.args.clone())),
+                Operator::Or => {
+                    let outer_vars = vars_except(terms, i, outer_vars);
+                    let mut or_vars = HashSet::new();
+                    term.variables(&mut or_vars);
+                    let quantified = or_vars
+                        .iter()
+                        .any(|v| v.as_str() != "_this" && !outer_vars.contains(v));
+                    if quantified {
+                        // A variable that only occurs here comes from a negated query, like
+                        // `not (tag in post.tags and tag.is_secret)`, which the partial
+                        // evaluator inverts to `not tag in post.tags or not tag.is_secret` for
+                        // all `tag`. Invert it back to get the group that must not hold.
+                        let group = exp
+                            .args
+                            .iter()
+                            .map(invert)
+                            .collect::<PolarResult<Vec<_>>>()?;
+                        disjuncts
+                            .iter_mut()
+                            .for_each(|d| d.negated.push(group.clone()));
+                    } else {
+                        let mut alternatives = vec![];
+                        for arg in &exp.args {
+                            alternatives
+                                .extend(Self::expand(std::slice::from_ref(arg), &outer_vars)?);
+                        }
+                        disjuncts = Self::product(disjuncts, alternatives);
+                    }
+                }
+                _ => disjuncts
+                    .iter_mut()
+                    .for_each(|d| d.positive.push(term.clone())),
+            }
+        }
+        Ok(disjuncts)
+    }
+
+    /// Conjoin every disjunct in `left` with every disjunct in `right`.
+    fn product(left: Vec<Self>, right: Vec<Self>) -> Vec<Self> {
+        left.into_iter()
+            .flat_map(|l| {
+                right.iter().map(move |r| {
+                    let mut l = l.clone();
+                    l.positive.extend(r.positive.iter().cloned());
+                    l.negated.extend(r.negated.iter().cloned());
+                    l
+                })
+            })
+            .collect()
+    }
+
+    /// Build a result set for this conjunction, or `None` if it can't be satisfied.
+    fn build(
+        self,
+        types: &Types,
+        this_type: &str,
+        explain: bool,
+    ) -> PolarResult<Option<ResultSet>> {
+        let vars = Vars::from_op(&Operation {
+            operator: Operator::And,
+            args: self.positive,
+        })?;
+        if explain {
+            vars.explain();
+        }
+        if vars.is_unsatisfiable() {
+            return Ok(None);
+        }
+        let mut result_set = ResultSet::build(types, &vars, this_type)?;
+
+        for group in self.negated {
+            match negated_isa(types, this_type, &group) {
+                // Negating a type check that always holds.
+                Some(true) => return Ok(None),
+                // Negating a type check that never holds.
+                Some(false) => continue,
+                None => (),
+            }
+
+            let operation = Operation {
+                operator: Operator::And,
+                args: group,
+            };
+            let mut group_vars = HashSet::new();
+            term!(operation.clone()).variables(&mut group_vars);
+            if !group_vars.contains(&sym!("_this")) {
+                return df_unsupported_op(op!(Not, term!(operation)));
+            }
+
+            let vars = Vars::from_op(&operation)?;
+            if explain {
+                eprintln!("    excluding");
+                vars.explain();
+            }
+            if !vars.is_unsatisfiable() {
+                result_set.exclude(ResultSet::build(types, &vars, this_type)?);
+            }
+        }
+        Ok(Some(result_set))
+    }
+}
+
+/// The variables in `terms`, except for those in `terms[skip]`, and `outer`.
+fn vars_except(terms: &[Term], skip: usize, outer: &HashSet<Symbol>) -> HashSet<Symbol> {
+    let mut vars = outer.clone();
+    for (i, term) in terms.iter().enumerate() {
+        if i != skip {
+            term.variables(&mut vars);
+        }
+    }
+    vars
+}
+
+/// Invert a single constraint.
+fn invert(term: &Term) -> PolarResult<Term> {
+    let exp = term.as_expression()?;
+    let inverted = match exp.operator {
+        Operator::Not if exp.args.len() == 1 => return Ok(exp.args[0].clone()),
+        Operator::Neq => Operator::Unify,
+        Operator::Unify | Operator::Eq => Operator::Neq,
+        Operator::Lt => Operator::Geq,
+        Operator::Leq => Operator::Gt,
+        Operator::Gt => Operator::Leq,
+        Operator::Geq => Operator::Lt,
+        _ => return Ok(term!(op!(Not, term.clone()))),
+    };
+    Ok(term.clone_with_value(Value::Expression(Operation {
+        operator: inverted,
+        args: exp.args.clone(),
+    })))
+}
+
+/// Decide `x matches Class` for a negated group consisting of just that check, from the type
+/// of `x` in `types`. Returns `None` if the group isn't a type check or the type is unknown.
+fn negated_isa(types: &Types, this_type: &str, group: &[Term]) -> Option<bool> {
+    fn type_of(types: &Types, this_type: &str, term: &Term) -> Option<TypeName> {
+        match term.value() {
+            Value::Variable(v) if v.as_str() == "_this" => Some(this_type.to_string()),
+            Value::Expression(Operation {
+                operator: Operator::Dot,
+                args,
+            }) if args.len() == 2 => {
+                let base = type_of(types, this_type, &args[0])?;
+                match types.get(&base)?.get(args[1].as_string().ok()?)? {
+                    Type::Base { class_tag } => Some(class_tag.clone()),
+                    Type::Relation {
+                        other_class_tag, ..
+                    } => Some(other_class_tag.clone()),
+                }
+            }
+            _ => None,
+        }
+    }
+
+    match group {
+        [term] => match term.as_expression().ok()? {
+            Operation {
+                operator: Operator::Isa,
+                args,
+            } if args.len() == 2 => match args[1].as_pattern().ok()? {
+                Pattern::Instance(InstanceLiteral { tag, fields }) if fields.fields.is_empty() => {
+                    Some(type_of(types, this_type, &args[0])? == *tag.as_str())
+                }
+                _ => None,
+            },
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
 impl FilterPlan {
     fn build(
         types: Types,
@@ -317,29 +573,29 @@ impl FilterPlan {
             eprintln!("\n==Bindings==")
         }
 
-        let result_sets = partial_results
-            .into_iter()
-            .enumerate()
+        let mut result_sets = vec![];
+        for (i, result) in partial_results.into_iter().enumerate() {
             // if the result doesn't include a binding for this variable,
             // or if the binding isn't an expression, then just ignore it.
-            .filter_map(|(i, result)| {
-                result
-                    .bindings
-                    .get(&Symbol::new(var))
-                    .map(|term| match term.as_expression() {
-                        Ok(exp) if exp.operator == Operator::And => {
-                            let vars = Vars::from_op(exp)?;
-                            if explain {
-                                eprintln!("  {}: {}", i, term);
-                                vars.explain()
-                            }
-
-                            ResultSet::build(&types, &vars, class_tag)
+            let term = match result.bindings.get(&Symbol::new(var)) {
+                Some(term) => term,
+                None => continue,
+            };
+            match term.as_expression() {
+                Ok(exp) if exp.operator == Operator::And => {
+                    if explain {
+                        eprintln!("  {}: {}", i, term);
+                    }
+                    // Each disjunct gets its own result set in the union.
+                    for conjunction in Conjunction::expand(&exp.args, &HashSet::new())? {
+                        if let Some(result_set) = conjunction.build(&types, class_tag, explain)? {
+                            result_sets.push(result_set);
                         }
-                        _ => Ok(ResultSet::immediate(term.clone(), class_tag)),
-                    })
-            })
-            .collect::<PolarResult<Vec<_>>>()?;
+                    }
+                }
+                _ => result_sets.push(ResultSet::immediate(term.clone(), class_tag)),
+            }
+        }
 
         Ok(FilterPlan { result_sets }.optimize(explain))
     }
@@ -401,6 +657,10 @@ impl FilterPlan {
                         ConstraintKind::Nin => "not in",
                         ConstraintKind::Neq => "!=",
                         ConstraintKind::Contains => "contains",
+                        ConstraintKind::Lt => "<",
+                        ConstraintKind::Leq => "<=",
+                        ConstraintKind::Gt => ">",
+                        ConstraintKind::Geq => ">=",
                     };
                     let field = &constraint.field;
                     let value = match &constraint.value {
@@ -439,6 +699,36 @@ impl ResultSet {
         }
     }
 
+    /// Exclude the results of `other`, which must have the same type, from this result set.
+    fn exclude(&mut self, other: ResultSet) {
+        // Give the other result set's requests ids that don't clash with ours.
+        let offset = self.requests.keys().max().map_or(0, |id| id + 1);
+        for (id, mut request) in other.requests {
+            for constraint in request.constraints.iter_mut() {
+                if let ConstraintValue::Ref(Ref { result_id, .. }) = &mut constraint.value {
+                    *result_id += offset;
+                }
+            }
+            self.requests.insert(id + offset, request);
+        }
+        self.resolve_order = other
+            .resolve_order
+            .into_iter()
+            .map(|id| id + offset)
+            .chain(self.resolve_order.drain(..))
+            .collect();
+        if let Some(request) = self.requests.get_mut(&self.result_id) {
+            request.constrain(
+                ConstraintKind::Nin,
+                None,
+                ConstraintValue::Ref(Ref {
+                    field: None,
+                    result_id: other.result_id + offset,
+                }),
+            );
+        }
+    }
+
     fn build(types: &Types, vars: &Vars, this_type: &str) -> PolarResult<Self> {
         let result_set = ResultSet {
             requests: HashMap::new(),
@@ -547,6 +837,7 @@ impl<'a> ResultSetBuilder<'a> {
                 .constrain_in_vars(id, var_type)?
                 .constrain_eq_vars(id)
                 .constrain_neq_vars(id)
+                .constrain_compared(id, None, id)
                 .result_set
                 .resolve_order
                 .push(id);
@@ -653,6 +944,27 @@ impl<'a> ResultSetBuilder<'a> {
         self
     }
 
+    /// Constrain `field` of `id`, or `id` itself if `field` is `None`, by the `in` lists and
+    /// comparisons of `child`.
+    fn constrain_compared(&mut self, id: Id, field: Option<&str>, child: Id) -> &mut Self {
+        let request = self.result_set.requests.get_mut(&id).unwrap();
+        for list in self.vars.in_values.get(&child).into_iter().flatten() {
+            request.constrain(
+                ConstraintKind::In,
+                field.map(str::to_string),
+                ConstraintValue::Term(list.clone()),
+            );
+        }
+        for (kind, value) in self.vars.comparisons.get(&child).into_iter().flatten() {
+            request.constrain(
+                kind.clone(),
+                field.map(str::to_string),
+                ConstraintValue::Term(value.clone()),
+            );
+        }
+        self
+    }
+
     fn constrain_field_contained(&mut self, id: Id, field: &str, child: Id) -> &mut Self {
         let request = self.result_set.requests.get_mut(&id).unwrap();
         self.vars
@@ -775,10 +1087,11 @@ impl<'a> ResultSetBuilder<'a> {
         } else {
             invalid_state(format!(
                 "Unsupported field access: {}.{} = {}",
-                self.var_name(id).unwrap_or_else(|| Symbol(id.to_string())),
+                self.var_name(id)
+                    .unwrap_or_else(|| Symbol::new(&id.to_string())),
                 field,
                 self.var_name(child)
-                    .unwrap_or_else(|| Symbol(child.to_string())),
+                    .unwrap_or_else(|| Symbol::new(&child.to_string())),
             ))
         }
     }
@@ -812,6 +1125,7 @@ impl<'a> ResultSetBuilder<'a> {
                         this.constrain_field_eq(id, field, *child)
                             .constrain_field_neq(id, field, *child)
                             .constrain_field_contained(id, field, *child)
+                            .constrain_compared(id, Some(field), *child)
                             .constrain_field_others_with_same_parent(id, field, *child)
                             .constrain_field_others(id, field, *child)
                             .ensure_added_constraint(id, field, *child, before)
@@ -878,6 +1192,20 @@ impl Vars {
                 hash_map_set_add(map, assign_id(var), val)
             });
 
+        let in_values = info
+            .in_values
+            .into_iter()
+            .fold(HashMap::new(), |map, (var, list)| {
+                hash_map_set_add(map, assign_id(var), list)
+            });
+
+        let comparisons = info
+            .comparisons
+            .into_iter()
+            .fold(HashMap::new(), |map, (var, kind, val)| {
+                hash_map_set_add(map, assign_id(var), (kind, val))
+            });
+
         let field_relationships = fields.into_iter().fold(HashMap::new(), |map, (p, f, c)| {
             hash_map_set_add(map, assign_id(p), (f, assign_id(c)))
         });
@@ -892,6 +1220,8 @@ impl Vars {
                     in_relationships,
                     eq_values,
                     contained_values,
+                    in_values,
+                    comparisons,
                     types,
                     this_id,
                 })
@@ -899,12 +1229,21 @@ impl Vars {
         )
     }
 
+    /// True if some variable must both equal and not equal the same value.
+    fn is_unsatisfiable(&self) -> bool {
+        self.uncycles.iter().any(|(x, ys)| {
+            ys.iter().any(|y| {
+                x == y || matches!((self.eq_values.get(x), self.eq_values.get(y)), (Some(a), Some(b)) if a == b)
+            })
+        })
+    }
+
     fn explain(&self) {
         eprintln!("    variables");
         for (id, set) in &self.variables {
             let values = set
                 .iter()
-                .map(|sym| sym.0.clone())
+                .map(|sym| sym.as_str().to_string())
                 .collect::<Vec<String>>()
                 .join(", ");
             eprintln!("      {}:  vars: {{{}}}", id, values);
@@ -915,7 +1254,10 @@ impl Vars {
                     Value::Boolean(_) => "Bool",
                     Value::String(_) => "String",
                     Value::Number(_) => "Number",
+                    Value::DateTime(_) => "DateTime",
+                    Value::Duration(_) => "Duration",
                     Value::List(_) => "List",
+                    Value::Set(_) => "Set",
                     Value::Dictionary(_) => "Dictionary",
                     Value::ExternalInstance(_) => "ExternalInstance",
                     Value::Call(_) => "Call",
@@ -936,6 +1278,16 @@ impl Vars {
                     eprintln!("          value contains: {}", val);
                 }
             }
+            if let Some(lists) = self.in_values.get(id) {
+                for list in lists {
+                    eprintln!("          value in: {}", list);
+                }
+            }
+            if let Some(comparisons) = self.comparisons.get(id) {
+                for (kind, val) in comparisons {
+                    eprintln!("          value {:?} {}", kind, val);
+                }
+            }
         }
         eprintln!("    field relationships");
         for (x, fs) in self.field_relationships.iter() {
@@ -1144,4 +1496,143 @@ mod test {
             _ => panic!("unexpected"),
         }
     }
+
+    #[test]
+    fn test_negated_constraints() -> PolarResult<()> {
+        let types = hashmap! {
+            "Post".to_owned() => hashmap! {
+                "author".to_owned() => Type::Base {
+                    class_tag: "String".to_owned()
+                },
+                "is_banned".to_owned() => Type::Base {
+                    class_tag: "Boolean".to_owned()
+                },
+                "tags".to_owned() => Type::Relation {
+                    kind: "many".to_owned(),
+                    other_class_tag: "Tag".to_owned(),
+                    my_field: "id".to_owned(),
+                    other_field: "post_id".to_owned(),
+                }
+            },
+            "Tag".to_owned() => hashmap! {
+                "is_secret".to_owned() => Type::Base {
+                    class_tag: "Boolean".to_owned()
+                }
+            }
+        };
+        let plan = |partial: &str| {
+            let bindings = ResultEvent::from(hashmap! {
+                sym!("resource") => crate::parser::parse_query(partial).unwrap()
+            });
+            build_filter_plan(types.clone(), vec![bindings], "resource", "Post")
+        };
+        let root = |result_set: &ResultSet| result_set.requests[&result_set.result_id].clone();
+
+        // `not post.is_banned`. Negating the type check can't succeed.
+        let negated_field =
+            plan("_this matches Post and (not _this matches Post or true != _this.is_banned)")?;
+        assert_eq!(negated_field.result_sets.len(), 1);
+        assert_eq!(
+            root(&negated_field.result_sets[0]).constraints,
+            vec![Constraint {
+                kind: ConstraintKind::Neq,
+                field: Some("is_banned".to_owned()),
+                value: ConstraintValue::Term(term!(true)),
+            }]
+        );
+
+        // `not (tag in post.tags and tag.is_secret = true)` excludes posts with a secret tag.
+        let not_exists =
+            plan("_this matches Post and (not _tag in _this.tags or true != _tag.is_secret)")?;
+        assert_eq!(not_exists.result_sets.len(), 1);
+        let result_set = &not_exists.result_sets[0];
+        assert_eq!(result_set.resolve_order.last(), Some(&result_set.result_id));
+        let excluded = match &root(result_set).constraints[..] {
+            [Constraint {
+                kind: ConstraintKind::Nin,
+                field: None,
+                value:
+                    ConstraintValue::Ref(Ref {
+                        field: None,
+                        result_id,
+                    }),
+            }] => result_set.requests[result_id].clone(),
+            constraints => panic!("unexpected constraints: {:?}", constraints),
+        };
+        assert_eq!(excluded.class_tag, "Post");
+        assert_eq!(excluded.constraints[0].kind, ConstraintKind::In);
+        assert_eq!(excluded.constraints[0].field, Some("id".to_owned()));
+
+        // Contradictory disjuncts are dropped.
+        let contradiction = plan(
+            r#"_this matches Post and "x" = _this.author and ("x" != _this.author or false = _this.is_banned)"#,
+        )?;
+        assert_eq!(contradiction.result_sets.len(), 1);
+        assert_eq!(root(&contradiction.result_sets[0]).len(), 2);
+
+        // Negated constraints have to be about the filtered variable.
+        let err = plan("_this matches Post and not 1 in _x").expect_err("should've failed");
+        assert!(matches!(
+            err.0,
+            ErrorKind::Runtime(DataFilteringUnsupportedOp { .. })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_comparison_constraints() -> PolarResult<()> {
+        let types = hashmap! {
+            "Post".to_owned() => hashmap! {
+                "size".to_owned() => Type::Base {
+                    class_tag: "Integer".to_owned()
+                },
+                "status".to_owned() => Type::Base {
+                    class_tag: "String".to_owned()
+                }
+            }
+        };
+        let plan = |partial: &str| {
+            let bindings = ResultEvent::from(hashmap! {
+                sym!("resource") => crate::parser::parse_query(partial).unwrap()
+            });
+            build_filter_plan(types.clone(), vec![bindings], "resource", "Post")
+        };
+
+        let ranged = plan(
+            r#"_this matches Post and _this.size > 10 and 100 >= _this.size and
+               _this.status in ["draft", "review"]"#,
+        )?;
+        assert_eq!(ranged.result_sets.len(), 1);
+        let result_set = &ranged.result_sets[0];
+        let constraints = &result_set.requests[&result_set.result_id].constraints;
+        let constraint = |kind, field: &str, value| Constraint {
+            kind,
+            field: Some(field.to_owned()),
+            value: ConstraintValue::Term(value),
+        };
+        assert_eq!(constraints.len(), 3);
+        for expected in [
+            constraint(ConstraintKind::Gt, "size", term!(10)),
+            constraint(ConstraintKind::Leq, "size", term!(100)),
+            constraint(ConstraintKind::In, "status", term!(["draft", "review"])),
+        ] {
+            assert!(constraints.contains(&expected), "missing {:?}", expected);
+        }
+
+        // A value on the left flips the comparison.
+        let negated = plan("_this matches Post and (not _this matches Post or 10 > _this.size)")?;
+        let result_set = &negated.result_sets[0];
+        assert_eq!(
+            result_set.requests[&result_set.result_id].constraints,
+            vec![constraint(ConstraintKind::Lt, "size", term!(10))]
+        );
+
+        // Comparing two fields can't be pushed into a fetch.
+        let err = plan("_this matches Post and _this.size < _this.status").unwrap_err();
+        assert!(matches!(
+            err.0,
+            ErrorKind::Runtime(DataFilteringUnsupportedOp { .. })
+        ));
+        Ok(())
+    }
 }
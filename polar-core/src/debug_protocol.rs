@@ -0,0 +1,97 @@
+//! A structured protocol for the debugger, for hosts that drive it from a UI, e.g., a Debug
+//! Adapter Protocol server, rather than from a terminal.
+//!
+//! Hosts send [`DebugRequest`]s with `Query::debug_request` and receive [`DebuggerEvent`]s as
+//! `QueryEvent::Debugger` events. Once a query has received a request, the debugger reports where
+//! it stops with [`DebuggerEvent::Stopped`] instead of `QueryEvent::Debug` messages.
+
+use serde::{Deserialize, Serialize};
+
+use crate::bindings::Bindings;
+use crate::introspection::Span;
+use crate::terms::Symbol;
+
+/// A place for the debugger to stop.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Breakpoint {
+    /// Stop before evaluating any rule named `name`.
+    Rule { name: Symbol },
+    /// Stop before evaluating a query on `line` of `filename`. Lines start at 1.
+    Line { filename: String, line: usize },
+    /// Stop when evaluation raises an error, before the error is returned.
+    Error,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DebugRequest {
+    SetBreakpoint {
+        breakpoint: Breakpoint,
+    },
+    RemoveBreakpoint {
+        breakpoint: Breakpoint,
+    },
+    /// Run to the next breakpoint.
+    Continue,
+    /// Step to the next query at the same level of the stack, stepping over rules.
+    Next,
+    /// Step to the next query, stepping into rules.
+    StepIn,
+    /// Step out to the next query at the level above the current one.
+    StepOut,
+    /// Report the stack of queries being evaluated.
+    StackTrace,
+    /// Report the bindings of the variables in the query of stack frame `frame`.
+    Variables {
+        frame: usize,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum StopReason {
+    /// A step request finished.
+    Step,
+    Breakpoint {
+        breakpoint: Breakpoint,
+    },
+    /// The policy called `debug()`.
+    DebugCall,
+    Error {
+        message: String,
+    },
+}
+
+/// A query being evaluated.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct StackFrame {
+    /// The frame's depth in the stack. The innermost frame is 0.
+    pub id: usize,
+    pub query: String,
+    /// The rule whose body contains the query, or `None` for the query being run.
+    pub rule: Option<Symbol>,
+    pub span: Option<Span>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum DebuggerEvent {
+    /// Evaluation paused. `frame` is the innermost stack frame, if there is one.
+    Stopped {
+        reason: StopReason,
+        frame: Option<StackFrame>,
+    },
+    /// The breakpoints that are set, in response to setting or removing one.
+    Breakpoints {
+        breakpoints: Vec<Breakpoint>,
+    },
+    /// The stack frames, innermost first.
+    StackTrace {
+        frames: Vec<StackFrame>,
+    },
+    Variables {
+        frame: usize,
+        bindings: Bindings,
+    },
+    /// A request couldn't be carried out, e.g., because it named a frame that doesn't exist.
+    Error {
+        message: String,
+    },
+}
@@ -1,9 +1,13 @@
 use std::rc::Rc;
+use std::sync::Arc;
 
 use super::bindings::Binding;
+use super::debug_protocol::*;
 use super::error::{PolarError, PolarResult};
 use super::formatting::source_lines;
+use super::introspection::Span;
 use super::kb::KnowledgeBase;
+use super::lexer::loc_to_pos;
 use super::partial::simplify_bindings;
 use super::terms::*;
 use super::traces::*;
@@ -14,12 +18,48 @@ impl PolarVirtualMachine {
         let relevant_bindings = self.relevant_bindings(&[query]);
         let bindings_str = relevant_bindings
             .iter()
-            .map(|(var, val)| format!("{} = {}", var.0, val))
+            .map(|(var, val)| format!("{} = {}", var.as_str(), val))
             .collect::<Vec<_>>()
             .join(", ");
         format!("QUERY: {}, BINDINGS: {{{}}}", query, bindings_str)
     }
 
+    /// The queries being evaluated, innermost first, each with the rule whose body it's in.
+    fn frame_queries(&self) -> Vec<(Term, Option<Symbol>)> {
+        // The last trace at each level of the trace stack is the one being evaluated.
+        let mut stack = std::iter::once(&self.trace)
+            .chain(self.trace_stack.iter().rev().map(|trace| trace.as_ref()))
+            .map_while(|trace| trace.last())
+            .collect::<Vec<_>>();
+        stack.reverse();
+
+        let mut frames = vec![];
+        let mut rule = None;
+        for trace in stack {
+            match &trace.node {
+                Node::Rule(r) => rule = Some(r.name.clone()),
+                Node::Term(t) if !is_single_and(t) => frames.push((t.clone(), rule.clone())),
+                Node::Term(_) => (),
+            }
+        }
+        frames.reverse();
+        frames
+    }
+
+    /// The stack frames reported to hosts using the structured debug protocol.
+    pub fn stack_frames(&self) -> Vec<StackFrame> {
+        self.frame_queries()
+            .into_iter()
+            .enumerate()
+            .map(|(id, (query, rule))| StackFrame {
+                id,
+                query: query.to_string(),
+                rule,
+                span: Span::of_term(&query),
+            })
+            .collect()
+    }
+
     /// If the inner [`Debugger`](struct.Debugger.html) returns a [`Goal`](../vm/enum.Goal.html),
     /// push it onto the goal stack.
     pub fn maybe_break(&mut self, event: DebugEvent) -> PolarResult<bool> {
@@ -54,6 +94,27 @@ enum Step {
     Rule,
 }
 
+/// Conjunctions of one query are just wrappers, so the debugger doesn't stop on them.
+fn is_single_and(term: &Term) -> bool {
+    matches!(term.value(), Value::Expression(Operation { operator: Operator::And, args }) if args.len() == 1)
+}
+
+/// Whether the query being evaluated is the previous query rewritten, e.g., with its arguments
+/// dereferenced. Rewritten queries keep the source of the original.
+fn is_rewritten_query(vm: &PolarVirtualMachine) -> bool {
+    let mut queries = vm.trace.iter().rev().map(|trace| match &trace.node {
+        Node::Term(t) => t.parsed_context(),
+        Node::Rule(_) => None,
+    });
+    match (queries.next().flatten(), queries.next().flatten()) {
+        (Some(current), Some(previous)) => {
+            Arc::ptr_eq(&current.source, &previous.source)
+                && (current.left, current.right) == (previous.left, previous.right)
+        }
+        _ => false,
+    }
+}
+
 /// VM breakpoints.
 ///
 /// There are currently two breakpoints in the VM, one that fires after every
@@ -81,6 +142,11 @@ pub struct Debugger {
     ///   [`maybe_break`](struct.Debugger.html#method.maybe_break).
     step: Option<Step>,
     last: Option<String>,
+    /// Breakpoints set with [`DebugRequest::SetBreakpoint`].
+    breakpoints: Vec<Breakpoint>,
+    /// Whether the host drives the debugger with [`DebugRequest`]s, in which case it reports
+    /// stops as [`DebuggerEvent`]s rather than as messages.
+    structured: bool,
 }
 
 impl Debugger {
@@ -108,52 +174,172 @@ impl Debugger {
     /// - `Some(Goal::Debug { message })` -> Pause evaluation.
     /// - `None` -> Continue evaluation.
     fn maybe_break(&self, event: DebugEvent, vm: &PolarVirtualMachine) -> Option<Goal> {
+        if let Some(breakpoint) = self.breakpoint_hit(&event, vm) {
+            return self.break_query(vm, StopReason::Breakpoint { breakpoint });
+        }
+        if let DebugEvent::Error(ref error) = event {
+            if self.breakpoints.contains(&Breakpoint::Error) {
+                return self.break_error(error, vm);
+            }
+        }
         self.step.as_ref().and_then(|step| match (step, event) {
             (Step::Goal, DebugEvent::Goal(goal)) => Some(Goal::Debug {
                 message: goal.to_string(),
             }),
-            (Step::Into, DebugEvent::Query) => self.break_query(vm),
+            (Step::Into, DebugEvent::Query) => self.break_query(vm, StopReason::Step),
             (Step::Out { level }, DebugEvent::Query)
                 if vm.trace_stack.is_empty() || vm.trace_stack.len() < *level =>
             {
-                self.break_query(vm)
+                self.break_query(vm, StopReason::Step)
             }
             (Step::Over { level }, DebugEvent::Query) if vm.trace_stack.len() == *level => {
-                self.break_query(vm)
-            }
-            (Step::Error, DebugEvent::Error(error)) => {
-                let context = error
-                    .get_context()
-                    .map_or_else(|| "".into(), |c| c.source_position());
-                self.break_msg(vm).map(|message| Goal::Debug {
-                    message: format!("{}\nERROR: {}{}\n", message, error.0, context),
-                })
+                self.break_query(vm, StopReason::Step)
             }
-            (Step::Rule, DebugEvent::Rule) => self.break_query(vm),
+            (Step::Error, DebugEvent::Error(error)) => self.break_error(&error, vm),
+            (Step::Rule, DebugEvent::Rule) => self.break_query(vm, StopReason::Step),
             _ => None,
         })
     }
 
+    /// The rule or line breakpoint, if any, that `event` hits.
+    fn breakpoint_hit(&self, event: &DebugEvent, vm: &PolarVirtualMachine) -> Option<Breakpoint> {
+        let node = &vm.trace.last()?.node;
+        self.breakpoints
+            .iter()
+            .find(|breakpoint| match (breakpoint, event, node) {
+                (Breakpoint::Rule { name }, DebugEvent::Rule, Node::Rule(rule)) => {
+                    &rule.name == name
+                }
+                (Breakpoint::Line { filename, line }, DebugEvent::Query, Node::Term(query)) => {
+                    // Stop on the queries in a conjunction rather than on the conjunction.
+                    !matches!(
+                        query.value(),
+                        Value::Expression(Operation {
+                            operator: Operator::And,
+                            ..
+                        })
+                    ) && !is_rewritten_query(vm)
+                        && query.parsed_context().is_some_and(|context| {
+                            context.source.filename.as_ref() == Some(filename)
+                                && loc_to_pos(&context.source.src, context.left).0 + 1 == *line
+                        })
+                }
+                _ => false,
+            })
+            .cloned()
+    }
+
     pub fn break_msg(&self, vm: &PolarVirtualMachine) -> Option<String> {
         vm.trace.last().and_then(|trace| match trace.node {
-            Node::Term(ref q) => match q.value() {
-                Value::Expression(Operation {
-                    operator: Operator::And,
-                    args,
-                }) if args.len() == 1 => None,
-                _ => {
-                    let source = self.query_source(q, 3);
-                    Some(format!("{}\n\n{}\n", vm.query_summary(q), source))
-                }
-            },
+            Node::Term(ref q) if is_single_and(q) => None,
+            Node::Term(ref q) => {
+                let source = self.query_source(q, 3);
+                Some(format!("{}\n\n{}\n", vm.query_summary(q), source))
+            }
             Node::Rule(ref r) => Some(r.to_string()),
         })
     }
 
     /// Produce the `Goal::Debug` for breaking on a Query (as opposed to breaking on a Goal).
     /// This is used to implement the `step`, `over`, and `out` debug commands.
-    fn break_query(&self, vm: &PolarVirtualMachine) -> Option<Goal> {
-        self.break_msg(vm).map(|message| Goal::Debug { message })
+    fn break_query(&self, vm: &PolarVirtualMachine, reason: StopReason) -> Option<Goal> {
+        if self.structured {
+            match vm.trace.last()?.node {
+                Node::Term(ref q) if is_single_and(q) => None,
+                _ => Some(self.stop(reason, vm)),
+            }
+        } else {
+            self.break_msg(vm).map(|message| Goal::Debug { message })
+        }
+    }
+
+    fn break_error(&self, error: &PolarError, vm: &PolarVirtualMachine) -> Option<Goal> {
+        if self.structured {
+            let message = error.0.to_string();
+            return Some(self.stop(StopReason::Error { message }, vm));
+        }
+        let context = error
+            .get_context()
+            .map_or_else(|| "".into(), |c| c.source_position());
+        self.break_msg(vm).map(|message| Goal::Debug {
+            message: format!("{}\nERROR: {}{}\n", message, error.0, context),
+        })
+    }
+
+    pub fn is_structured(&self) -> bool {
+        self.structured
+    }
+
+    /// Produce the `Goal::Debugger` that reports a stop to the host.
+    pub fn stop(&self, reason: StopReason, vm: &PolarVirtualMachine) -> Goal {
+        Goal::Debugger {
+            event: DebuggerEvent::Stopped {
+                reason,
+                frame: vm.stack_frames().into_iter().next(),
+            },
+        }
+    }
+
+    /// Process a [`DebugRequest`] from the host.
+    ///
+    /// Movement requests set the [`Option<Step>`](struct.Debugger.html#structfield.step) like the
+    /// equivalent debugging commands and return `None`. Other requests return a
+    /// [`Goal::Debugger`](../vm/enum.Goal.html) with the answer.
+    pub fn debug_request(
+        &mut self,
+        request: DebugRequest,
+        vm: &PolarVirtualMachine,
+    ) -> Option<Goal> {
+        self.structured = true;
+        let event = match request {
+            DebugRequest::SetBreakpoint { breakpoint } => {
+                if !self.breakpoints.contains(&breakpoint) {
+                    self.breakpoints.push(breakpoint);
+                }
+                DebuggerEvent::Breakpoints {
+                    breakpoints: self.breakpoints.clone(),
+                }
+            }
+            DebugRequest::RemoveBreakpoint { breakpoint } => {
+                self.breakpoints.retain(|b| b != &breakpoint);
+                DebuggerEvent::Breakpoints {
+                    breakpoints: self.breakpoints.clone(),
+                }
+            }
+            DebugRequest::Continue => {
+                self.step = None;
+                return None;
+            }
+            DebugRequest::Next => {
+                self.step = Some(Step::Over {
+                    level: vm.trace_stack.len(),
+                });
+                return None;
+            }
+            DebugRequest::StepIn => {
+                self.step = Some(Step::Into);
+                return None;
+            }
+            DebugRequest::StepOut => {
+                self.step = Some(Step::Out {
+                    level: vm.trace_stack.len(),
+                });
+                return None;
+            }
+            DebugRequest::StackTrace => DebuggerEvent::StackTrace {
+                frames: vm.stack_frames(),
+            },
+            DebugRequest::Variables { frame } => match vm.frame_queries().get(frame) {
+                Some((query, _)) => DebuggerEvent::Variables {
+                    frame,
+                    bindings: vm.relevant_bindings(&[query]),
+                },
+                None => DebuggerEvent::Error {
+                    message: format!("There is no stack frame {}.", frame),
+                },
+            },
+        };
+        Some(Goal::Debugger { event })
     }
 
     /// Process debugging commands from the user.
@@ -269,7 +455,7 @@ impl Debugger {
                     let mut vars = vm
                         .bindings(true)
                         .keys()
-                        .map(|k| k.0.as_ref())
+                        .map(|k| k.as_str())
                         .collect::<Vec<_>>()
                         .join(", ");
                     if vars.is_empty() {
@@ -319,7 +505,8 @@ pub fn get_binding_for_var(name: &str, vm: &PolarVirtualMachine) -> Binding {
             bindings
                 .keys()
                 .filter_map(|k| {
-                    k.0.strip_prefix(&prefix)
+                    k.as_str()
+                        .strip_prefix(&prefix)
                         .and_then(|i| i.parse::<i64>().map_or(None, |i| Some((k, i))))
                 })
                 .max_by(|a, b| a.1.cmp(&b.1))
@@ -327,7 +514,7 @@ pub fn get_binding_for_var(name: &str, vm: &PolarVirtualMachine) -> Binding {
                     || Binding(sym!(name), Term::from(sym!("<unbound>"))),
                     |b| {
                         Binding(
-                            sym!(format!("{}@{}", name, b.0 .0).as_str()),
+                            sym!(format!("{}@{}", name, b.0).as_str()),
                             bindings.get(b.0).unwrap().clone(),
                         )
                     },
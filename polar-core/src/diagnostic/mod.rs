@@ -1,5 +1,8 @@
 use std::fmt;
 
+pub mod sarif;
+pub mod structured;
+
 use super::{error::PolarError, sources::Context, warning::PolarWarning};
 
 #[derive(Debug)]
@@ -0,0 +1,189 @@
+//! Diagnostics in the [SARIF 2.1.0](https://docs.oasis-open.org/sarif/sarif/v2.1.0/sarif-v2.1.0.html)
+//! format, for code-scanning tools.
+//!
+//! Only the subset of the format needed to report diagnostics against policy files is modeled.
+
+use serde::Serialize;
+
+use super::Diagnostic;
+use crate::introspection::Span;
+
+const SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+const VERSION: &str = "2.1.0";
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct SarifLog {
+    #[serde(rename = "$schema")]
+    pub schema: String,
+    pub version: String,
+    pub runs: Vec<Run>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Run {
+    pub tool: Tool,
+    pub results: Vec<SarifResult>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Tool {
+    pub driver: Driver,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Driver {
+    pub name: String,
+    pub version: String,
+    pub information_uri: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SarifResult {
+    /// The diagnostic's kind, e.g., `ValidationWarning::DuplicateRule`.
+    pub rule_id: String,
+    /// Either `error` or `warning`.
+    pub level: String,
+    pub message: Message,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub locations: Vec<Location>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Message {
+    pub text: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Location {
+    pub physical_location: PhysicalLocation,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PhysicalLocation {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub artifact_location: Option<ArtifactLocation>,
+    pub region: Region,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ArtifactLocation {
+    pub uri: String,
+}
+
+/// The lines and columns of a [`Span`], as SARIF expects them.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Region {
+    pub start_line: usize,
+    pub start_column: usize,
+    pub end_line: usize,
+    pub end_column: usize,
+}
+
+impl From<Span> for Location {
+    fn from(span: Span) -> Self {
+        Self {
+            physical_location: PhysicalLocation {
+                artifact_location: span.filename.map(|uri| ArtifactLocation { uri }),
+                region: Region {
+                    start_line: span.start_line,
+                    start_column: span.start_column,
+                    end_line: span.end_line,
+                    end_column: span.end_column,
+                },
+            },
+        }
+    }
+}
+
+impl From<&Diagnostic> for SarifResult {
+    fn from(diagnostic: &Diagnostic) -> Self {
+        Self {
+            rule_id: diagnostic.kind(),
+            level: if diagnostic.is_error() {
+                "error".to_owned()
+            } else {
+                "warning".to_owned()
+            },
+            message: Message {
+                text: diagnostic.to_string().trim_end().to_owned(),
+            },
+            locations: diagnostic
+                .get_context()
+                .iter()
+                .map(|context| Location::from(Span::from(context)))
+                .collect(),
+        }
+    }
+}
+
+impl SarifLog {
+    /// A log with a single run containing `diagnostics`.
+    pub fn new(diagnostics: &[Diagnostic]) -> Self {
+        Self {
+            schema: SCHEMA.to_owned(),
+            version: VERSION.to_owned(),
+            runs: vec![Run {
+                tool: Tool {
+                    driver: Driver {
+                        name: "oso".to_owned(),
+                        version: env!("CARGO_PKG_VERSION").to_owned(),
+                        information_uri: "https://docs.osohq.com".to_owned(),
+                    },
+                },
+                results: diagnostics.iter().map(SarifResult::from).collect(),
+            }],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::polar::Polar;
+    use crate::sources::Source;
+
+    #[test]
+    fn test_sarif_log() {
+        let mut polar = Polar::new();
+        polar.set_ignore_no_allow_warning(true);
+        let src = "f(x) if g(x);\nh(1);\nh(1);\n";
+        let log = polar.diagnostics_sarif(vec![Source::new_with_name("policy.polar", src)]);
+        assert_eq!(log.version, "2.1.0");
+        let results = &log.runs[0].results;
+        assert_eq!(results.len(), 2, "{:#?}", results);
+
+        assert_eq!(results[0].rule_id, "ValidationError::UndefinedRuleCall");
+        assert_eq!(results[0].level, "error");
+        let location = &results[0].locations[0].physical_location;
+        assert_eq!(
+            location.artifact_location,
+            Some(ArtifactLocation {
+                uri: "policy.polar".to_owned()
+            })
+        );
+        assert_eq!(
+            location.region,
+            Region {
+                start_line: 1,
+                start_column: 9,
+                end_line: 1,
+                end_column: 13,
+            }
+        );
+
+        assert_eq!(results[1].rule_id, "ValidationWarning::DuplicateRule");
+        assert_eq!(results[1].level, "warning");
+        assert_eq!(
+            results[1].locations[0].physical_location.region.start_line,
+            3
+        );
+
+        // Checking a policy doesn't load it.
+        assert!(!polar.kb.snapshot().has_rules());
+    }
+}
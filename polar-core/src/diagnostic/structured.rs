@@ -0,0 +1,185 @@
+//! Diagnostics as plain data, for editors and other tools that would rather not parse the
+//! human-readable `Display` output.
+
+use serde::Serialize;
+
+use super::Diagnostic;
+use crate::error::{ErrorKind, PolarError, RuntimeError};
+use crate::introspection::Span;
+use crate::sources::Context;
+use crate::warning::{PolarWarning, ValidationWarning};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct StructuredDiagnostic {
+    /// Stable code for the kind of diagnostic, e.g., `W003`. See `PolarError::code`.
+    pub code: String,
+    /// The diagnostic's kind, e.g., `ValidationWarning::DuplicateRule`.
+    pub kind: String,
+    pub severity: Severity,
+    /// The message, without any source context.
+    pub message: String,
+    /// Where the diagnostic applies. The first label is the primary location; any others are
+    /// related locations, described by `notes`.
+    pub labels: Vec<Span>,
+    pub notes: Vec<String>,
+}
+
+impl From<&Diagnostic> for StructuredDiagnostic {
+    fn from(diagnostic: &Diagnostic) -> Self {
+        match diagnostic {
+            Diagnostic::Error(e) => e.into(),
+            Diagnostic::Warning(w) => w.into(),
+        }
+    }
+}
+
+impl From<&PolarError> for StructuredDiagnostic {
+    fn from(error: &PolarError) -> Self {
+        let mut message = error.0.to_string();
+        let mut notes = vec![];
+        // Move stack traces out of the message and into a note.
+        if let ErrorKind::Runtime(
+            RuntimeError::TypeError { stack_trace, .. }
+            | RuntimeError::BudgetExceeded { stack_trace, .. }
+            | RuntimeError::Application { stack_trace, .. },
+        ) = &error.0
+        {
+            if let Some(rest) = message.strip_prefix(&format!("{}\n", stack_trace)) {
+                message = rest.to_owned();
+                if !stack_trace.is_empty() {
+                    notes.push(stack_trace.clone());
+                }
+            }
+        }
+        Self {
+            code: error.code().to_owned(),
+            kind: error.kind(),
+            severity: Severity::Error,
+            message: message.trim_end().to_owned(),
+            labels: error.get_context().iter().map(Span::from).collect(),
+            notes,
+        }
+    }
+}
+
+impl From<&PolarWarning> for StructuredDiagnostic {
+    fn from(warning: &PolarWarning) -> Self {
+        use ValidationWarning::*;
+
+        let mut message = warning.0.to_string();
+        let mut labels: Vec<Span> = warning.get_context().iter().map(Span::from).collect();
+        let mut notes = vec![];
+
+        let related = match &warning.0 {
+            DeprecatedRuleCall { deprecation, .. } => {
+                Some(("Deprecated", deprecation.rule.parsed_context()))
+            }
+            DuplicateRule { original, .. } => Some(("First defined", original.parsed_context())),
+            OrderedEffectfulCalls { previous, .. } => {
+                Some(("Previous call", previous.parsed_context()))
+            }
+            SubsumedRule { fact, .. } => Some(("Fact defined", fact.parsed_context())),
+            _ => None,
+        };
+        if let Some((description, context)) = related {
+            // The `Display` impl describes the related location after the first line; use a
+            // label and a note instead.
+            message = message.lines().next().unwrap_or_default().to_owned();
+            if let Some(context) = context {
+                labels.push(Span::from(context));
+                notes.push(related_note(description, context));
+            }
+        }
+
+        Self {
+            code: warning.code().to_owned(),
+            kind: warning.kind(),
+            severity: Severity::Warning,
+            message: message.trim_end().to_owned(),
+            labels,
+            notes,
+        }
+    }
+}
+
+fn related_note(description: &str, context: &Context) -> String {
+    format!("{}{}", description, context.source_position())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::polar::Polar;
+    use crate::sources::Source;
+
+    #[test]
+    fn test_structured_diagnostics() {
+        let mut polar = Polar::new();
+        polar.set_ignore_no_allow_warning(true);
+        let src = "f(x) if g(x);\nh(1);\nh(1);\n";
+        let diagnostics =
+            polar.diagnostics_structured(vec![Source::new_with_name("policy.polar", src)]);
+        assert_eq!(diagnostics.len(), 2, "{:#?}", diagnostics);
+
+        let error = &diagnostics[0];
+        assert_eq!(error.code, "V005");
+        assert_eq!(error.severity, Severity::Error);
+        assert_eq!(
+            error.labels,
+            vec![Span {
+                filename: Some("policy.polar".to_owned()),
+                start: 8,
+                end: 12,
+                start_line: 1,
+                start_column: 9,
+                end_line: 1,
+                end_column: 13,
+            }]
+        );
+        assert!(!error.message.contains("line 1"), "{}", error.message);
+
+        let warning = &diagnostics[1];
+        assert_eq!(warning.code, "W003");
+        assert_eq!(warning.severity, Severity::Warning);
+        assert_eq!(warning.message, "Duplicate rule: h(1);");
+        let lines = warning
+            .labels
+            .iter()
+            .map(|span| span.start_line)
+            .collect::<Vec<_>>();
+        assert_eq!(lines, vec![3, 2]);
+        assert_eq!(
+            warning.notes,
+            vec!["First defined at line 2, column 1 of file policy.polar"]
+        );
+
+        let json = serde_json::to_value(warning).unwrap();
+        assert_eq!(json["code"], "W003");
+        assert_eq!(json["severity"], "warning");
+        assert_eq!(json["labels"][0]["start"], 20);
+        assert_eq!(json["labels"][1]["end_column"], 5);
+    }
+
+    #[test]
+    fn test_stack_trace_note() {
+        let error = PolarError::from(RuntimeError::Application {
+            msg: "oops".to_owned(),
+            stack_trace: "trace".to_owned(),
+            term: None,
+        });
+        let diagnostic = StructuredDiagnostic::from(&error);
+        assert_eq!(diagnostic.code, "R007");
+        assert_eq!(diagnostic.message, "Application error: oops");
+        assert_eq!(diagnostic.notes, vec!["trace"]);
+        assert!(diagnostic.labels.is_empty());
+        // The human-readable form is unchanged.
+        assert_eq!(error.to_string(), "trace\nApplication error: oops");
+    }
+}
@@ -0,0 +1,505 @@
+//! What changed between two versions of a policy, for reviewing a change before deploying it.
+//!
+//! [`Polar::diff_policies`](crate::polar::Polar::diff_policies) loads both versions, aligns their
+//! rules by signature — name, then each parameter's specializer or value — and compares the
+//! resource blocks they declare. Besides the changes themselves, a [`PolicyDiff`] lists the
+//! resource/action pairs the changes could affect, following shorthand rules from changed roles
+//! and relations to the permissions they imply.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::Serialize;
+
+use crate::introspection::{PolicyAst, ResourceBlockAst, RuleAst};
+use crate::parser::Line;
+use crate::rules::{Parameter, Rule};
+use crate::terms::{InstanceLiteral, Pattern, Symbol, Value};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Modified,
+}
+
+/// A rule or rule type that was added, removed or modified.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct RuleChange {
+    pub kind: ChangeKind,
+    /// The signature the rules were aligned by, e.g., `allow(_: User{}, "read", _: Repo{})`.
+    pub signature: String,
+    /// The rule in the old policy, unless it was added.
+    pub old: Option<RuleAst>,
+    /// The rule in the new policy, unless it was removed.
+    pub new: Option<RuleAst>,
+}
+
+/// Changes to the declarations for one resource or actor type. For a block that was added or
+/// removed, everything it declares is listed as added or removed.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ResourceBlockChange {
+    pub resource: Symbol,
+    pub roles_added: Vec<String>,
+    pub roles_removed: Vec<String>,
+    pub permissions_added: Vec<String>,
+    pub permissions_removed: Vec<String>,
+    pub relations_added: Vec<String>,
+    pub relations_removed: Vec<String>,
+    /// Shorthand rules as written, e.g., `"read" if "member" on "parent"`.
+    pub shorthand_rules_added: Vec<String>,
+    pub shorthand_rules_removed: Vec<String>,
+}
+
+impl ResourceBlockChange {
+    fn is_empty(&self) -> bool {
+        self.roles_added.is_empty()
+            && self.roles_removed.is_empty()
+            && self.permissions_added.is_empty()
+            && self.permissions_removed.is_empty()
+            && self.relations_added.is_empty()
+            && self.relations_removed.is_empty()
+            && self.shorthand_rules_added.is_empty()
+            && self.shorthand_rules_removed.is_empty()
+    }
+}
+
+/// A resource/action pair whose authorization a change could affect.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct AffectedPermission {
+    /// The resource type, or `None` if the change could affect any resource.
+    pub resource: Option<Symbol>,
+    /// The action or permission, or `None` if the change could affect any action.
+    pub action: Option<String>,
+}
+
+/// Everything that changed between two policies.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct PolicyDiff {
+    /// Rule changes sorted by signature.
+    pub rules: Vec<RuleChange>,
+    pub rule_types: Vec<RuleChange>,
+    /// Changed resource blocks sorted by resource name.
+    pub resource_blocks: Vec<ResourceBlockChange>,
+    /// Sorted, with `None`s first.
+    pub affected: Vec<AffectedPermission>,
+}
+
+impl PolicyDiff {
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty() && self.rule_types.is_empty() && self.resource_blocks.is_empty()
+    }
+}
+
+/// The rules and rule types written in a policy, and the resource blocks it declares.
+pub(crate) struct PolicyVersion {
+    pub rules: Vec<Rule>,
+    pub rule_types: Vec<Rule>,
+    pub ast: PolicyAst,
+}
+
+impl PolicyVersion {
+    /// Collect the rules written in `lines`, leaving out those generated from resource blocks.
+    pub fn new(lines: Vec<Line>, ast: PolicyAst) -> Self {
+        let mut rules = vec![];
+        let mut rule_types = vec![];
+        for line in lines {
+            match line {
+                Line::Rule(rule) | Line::DeprecatedRule { rule, .. } | Line::PrivateRule(rule) => {
+                    rules.push(rule)
+                }
+                Line::RuleType(rule_type) => rule_types.push(rule_type),
+                _ => {}
+            }
+        }
+        Self {
+            rules,
+            rule_types,
+            ast,
+        }
+    }
+}
+
+pub(crate) fn diff(old: &PolicyVersion, new: &PolicyVersion) -> PolicyDiff {
+    let rules = diff_rules(&old.rules, &new.rules);
+    let rule_types = diff_rules(&old.rule_types, &new.rule_types);
+    let resource_blocks = diff_resource_blocks(&old.ast, &new.ast);
+
+    let mut affected = BTreeSet::new();
+    for change in &rules {
+        for rule in change.old.iter().chain(&change.new) {
+            if let Some(permission) = affected_by_rule(rule) {
+                affected.insert(permission);
+            }
+        }
+    }
+    for (resource, action) in affected_by_blocks(&resource_blocks, old, new) {
+        affected.insert(AffectedPermission {
+            resource: Some(resource),
+            action: Some(action),
+        });
+    }
+
+    PolicyDiff {
+        rules,
+        rule_types,
+        resource_blocks,
+        affected: affected.into_iter().collect(),
+    }
+}
+
+/// How rules are aligned between versions: the rule name, then each parameter's specializer, or
+/// its value if it's ground.
+fn signature(rule: &Rule) -> String {
+    let params = rule
+        .params
+        .iter()
+        .map(
+            |Parameter {
+                 parameter,
+                 specializer,
+             }| match specializer {
+                Some(specializer) => format!("_: {}", specializer),
+                None if parameter.is_ground() => parameter.to_string(),
+                None => "_".to_owned(),
+            },
+        )
+        .collect::<Vec<_>>();
+    format!("{}({})", rule.name, params.join(", "))
+}
+
+fn diff_rules(old: &[Rule], new: &[Rule]) -> Vec<RuleChange> {
+    fn by_signature(rules: &[Rule]) -> BTreeMap<String, Vec<&Rule>> {
+        let mut by_signature = BTreeMap::<_, Vec<_>>::new();
+        for rule in rules {
+            by_signature.entry(signature(rule)).or_default().push(rule);
+        }
+        by_signature
+    }
+
+    let (old, new) = (by_signature(old), by_signature(new));
+    let signatures = old.keys().chain(new.keys()).collect::<BTreeSet<_>>();
+    let mut changes = vec![];
+    for signature in signatures {
+        // Rules that are written the same way in both versions are unchanged.
+        let mut removed = old.get(signature).cloned().unwrap_or_default();
+        let mut added = new.get(signature).cloned().unwrap_or_default();
+        removed.retain(|rule| {
+            let text = rule.to_string();
+            match added.iter().position(|other| other.to_string() == text) {
+                Some(index) => {
+                    added.remove(index);
+                    false
+                }
+                None => true,
+            }
+        });
+
+        let change = |kind, old: Option<&&Rule>, new: Option<&&Rule>| RuleChange {
+            kind,
+            signature: signature.clone(),
+            old: old.map(|rule| RuleAst::from(*rule)),
+            new: new.map(|rule| RuleAst::from(*rule)),
+        };
+        if removed.len() == 1 && added.len() == 1 {
+            changes.push(change(ChangeKind::Modified, removed.first(), added.first()));
+        } else {
+            changes.extend(
+                removed
+                    .iter()
+                    .map(|rule| change(ChangeKind::Removed, Some(rule), None)),
+            );
+            changes.extend(
+                added
+                    .iter()
+                    .map(|rule| change(ChangeKind::Added, None, Some(rule))),
+            );
+        }
+    }
+    changes
+}
+
+fn shorthand_rules(block: &ResourceBlockAst) -> Vec<String> {
+    block
+        .shorthand_rules
+        .iter()
+        .map(|rule| match &rule.relation {
+            Some(relation) => format!("{:?} if {:?} on {:?}", rule.head, rule.implier, relation),
+            None => format!("{:?} if {:?}", rule.head, rule.implier),
+        })
+        .collect()
+}
+
+fn relations(block: &ResourceBlockAst) -> Vec<String> {
+    block
+        .relations
+        .iter()
+        .map(|relation| format!("{}: {}", relation.name, relation.related_type))
+        .collect()
+}
+
+/// The items in `new` that aren't in `old`, and the items in `old` that aren't in `new`.
+fn delta(old: &[String], new: &[String]) -> (Vec<String>, Vec<String>) {
+    let added = new.iter().filter(|item| !old.contains(item)).cloned();
+    let removed = old.iter().filter(|item| !new.contains(item)).cloned();
+    (added.collect(), removed.collect())
+}
+
+fn diff_resource_blocks(old: &PolicyAst, new: &PolicyAst) -> Vec<ResourceBlockChange> {
+    let find = |ast: &PolicyAst, resource: &Symbol| {
+        ast.resource_blocks
+            .iter()
+            .find(|block| &block.resource == resource)
+            .cloned()
+    };
+    let resources = old
+        .resource_blocks
+        .iter()
+        .chain(&new.resource_blocks)
+        .map(|block| block.resource.clone())
+        .collect::<BTreeSet<_>>();
+
+    let mut changes = vec![];
+    for resource in resources {
+        let empty = ResourceBlockAst {
+            kind: crate::introspection::ResourceBlockKind::Resource,
+            resource: resource.clone(),
+            roles: vec![],
+            permissions: vec![],
+            relations: vec![],
+            shorthand_rules: vec![],
+            span: None,
+        };
+        let old_block = find(old, &resource).unwrap_or_else(|| empty.clone());
+        let new_block = find(new, &resource).unwrap_or(empty);
+
+        let (roles_added, roles_removed) = delta(&old_block.roles, &new_block.roles);
+        let (permissions_added, permissions_removed) =
+            delta(&old_block.permissions, &new_block.permissions);
+        let (relations_added, relations_removed) =
+            delta(&relations(&old_block), &relations(&new_block));
+        let (shorthand_rules_added, shorthand_rules_removed) =
+            delta(&shorthand_rules(&old_block), &shorthand_rules(&new_block));
+        let change = ResourceBlockChange {
+            resource,
+            roles_added,
+            roles_removed,
+            permissions_added,
+            permissions_removed,
+            relations_added,
+            relations_removed,
+            shorthand_rules_added,
+            shorthand_rules_removed,
+        };
+        if !change.is_empty() {
+            changes.push(change);
+        }
+    }
+    changes
+}
+
+/// The resource/action pair an `allow` or `has_permission` rule grants, with unspecialized
+/// resources and non-literal actions left open.
+fn affected_by_rule(rule: &RuleAst) -> Option<AffectedPermission> {
+    let (action, resource) = match (rule.name.as_str(), rule.params.as_slice()) {
+        ("allow" | "has_permission", [_, action, resource]) => (action, resource),
+        _ => return None,
+    };
+    Some(AffectedPermission {
+        resource: match resource.specializer.as_ref().map(|s| s.value()) {
+            Some(Value::Pattern(Pattern::Instance(InstanceLiteral { tag, .. }))) => {
+                Some(tag.clone())
+            }
+            _ => None,
+        },
+        action: match action.parameter.value() {
+            Value::String(action) => Some(action.clone()),
+            _ => None,
+        },
+    })
+}
+
+/// The permissions of each resource that could be granted differently because of `changes`:
+/// permissions that were added or removed, heads of shorthand rules that were added or removed,
+/// and, transitively, permissions implied by those roles and permissions in either version.
+fn affected_by_blocks(
+    changes: &[ResourceBlockChange],
+    old: &PolicyVersion,
+    new: &PolicyVersion,
+) -> BTreeSet<(Symbol, String)> {
+    let mut affected = BTreeSet::new();
+    for change in changes {
+        let resource = &change.resource;
+        let names = change
+            .roles_added
+            .iter()
+            .chain(&change.roles_removed)
+            .chain(&change.permissions_added)
+            .chain(&change.permissions_removed);
+        affected.extend(names.map(|name| (resource.clone(), name.clone())));
+    }
+    for ast in [&old.ast, &new.ast] {
+        for block in &ast.resource_blocks {
+            let changed = match changes.iter().find(|c| c.resource == block.resource) {
+                Some(change) => change,
+                None => continue,
+            };
+            let rules = shorthand_rules(block);
+            for (rule, text) in block.shorthand_rules.iter().zip(rules) {
+                if changed.shorthand_rules_added.contains(&text)
+                    || changed.shorthand_rules_removed.contains(&text)
+                {
+                    affected.insert((block.resource.clone(), rule.head.clone()));
+                }
+            }
+        }
+    }
+
+    // Follow shorthand rules from affected impliers to their heads, across relations.
+    loop {
+        let mut implied = vec![];
+        for ast in [&old.ast, &new.ast] {
+            for block in &ast.resource_blocks {
+                for rule in &block.shorthand_rules {
+                    let implier_resource = match &rule.relation {
+                        Some(relation) => match block
+                            .relations
+                            .iter()
+                            .find(|candidate| &candidate.name == relation)
+                        {
+                            Some(relation) => relation.related_type.clone(),
+                            None => continue,
+                        },
+                        None => block.resource.clone(),
+                    };
+                    let head = (block.resource.clone(), rule.head.clone());
+                    if affected.contains(&(implier_resource, rule.implier.clone()))
+                        && !affected.contains(&head)
+                    {
+                        implied.push(head);
+                    }
+                }
+            }
+        }
+        if implied.is_empty() {
+            break;
+        }
+        affected.extend(implied);
+    }
+
+    // Only permissions are checked by `allow` rules.
+    affected
+        .into_iter()
+        .filter(|(resource, name)| {
+            [&old.ast, &new.ast].iter().any(|ast| {
+                ast.resource_blocks
+                    .iter()
+                    .any(|block| &block.resource == resource && block.permissions.contains(name))
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::polar::Polar;
+    use crate::sources::Source;
+
+    #[test]
+    fn test_diff_policies() {
+        let p = Polar::new();
+        p.register_constant(sym!("User"), term!("unimportant"))
+            .unwrap();
+        p.register_constant(sym!("Org"), term!("unimportant"))
+            .unwrap();
+        p.register_constant(sym!("Repo"), term!("unimportant"))
+            .unwrap();
+        let old = r#"
+            actor User {}
+            resource Org {
+                roles = ["member", "owner"];
+                "member" if "owner";
+            }
+            resource Repo {
+                permissions = ["read", "push"];
+                relations = { parent: Org };
+                "read" if "member" on "parent";
+                "push" if "owner" on "parent";
+            }
+            allow(actor, action, resource) if has_permission(actor, action, resource);
+            has_relation(org: Org, "parent", repo: Repo) if org = repo.org;
+            has_role(_: User, "owner", _: Org);
+            allow(_: User, "read", _: Repo) if false;
+        "#;
+        let new = r#"
+            actor User {}
+            resource Org {
+                roles = ["member", "owner", "admin"];
+                "member" if "owner";
+                "owner" if "admin";
+            }
+            resource Repo {
+                permissions = ["read", "push"];
+                relations = { parent: Org };
+                "read" if "member" on "parent";
+                "push" if "owner" on "parent";
+            }
+            allow(actor, action, resource) if has_permission(actor, action, resource);
+            has_relation(org: Org, "parent", repo: Repo) if org = repo.org;
+            has_role(_: User, "admin", _: Org);
+            allow(_: User, "read", _: Repo) if true;
+        "#;
+        let diff = p
+            .diff_policies(vec![Source::new(old)], vec![Source::new(new)])
+            .unwrap();
+
+        let rules = diff
+            .rules
+            .iter()
+            .map(|change| (change.kind, change.signature.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            rules,
+            vec![
+                (
+                    ChangeKind::Modified,
+                    r#"allow(_: User{}, "read", _: Repo{})"#
+                ),
+                (
+                    ChangeKind::Added,
+                    r#"has_role(_: User{}, "admin", _: Org{})"#
+                ),
+                (
+                    ChangeKind::Removed,
+                    r#"has_role(_: User{}, "owner", _: Org{})"#
+                ),
+            ]
+        );
+
+        assert_eq!(diff.resource_blocks.len(), 1);
+        let org = &diff.resource_blocks[0];
+        assert_eq!(org.resource, sym!("Org"));
+        assert_eq!(org.roles_added, vec!["admin".to_owned()]);
+        assert_eq!(
+            org.shorthand_rules_added,
+            vec![r#""owner" if "admin""#.to_owned()]
+        );
+        assert!(org.roles_removed.is_empty() && org.shorthand_rules_removed.is_empty());
+
+        // The new role implies "owner", which grants "push" and, through "member", "read".
+        let affected = diff
+            .affected
+            .iter()
+            .map(|p| (p.resource.as_ref().map(|r| r.as_str()), p.action.as_deref()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            affected,
+            vec![(Some("Repo"), Some("push")), (Some("Repo"), Some("read"))]
+        );
+
+        assert!(p
+            .diff_policies(vec![Source::new(old)], vec![Source::new(old)])
+            .unwrap()
+            .is_empty());
+    }
+}
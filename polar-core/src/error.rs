@@ -5,6 +5,7 @@ use serde::Serialize;
 use strum_macros::AsRefStr;
 
 use super::{
+    inline_query::ExpectedResult,
     resource_block::Declaration,
     rules::Rule,
     sources::{Context, Source},
@@ -64,6 +65,74 @@ impl PolarError {
         }
     }
 
+    /// A short, stable code for the kind of error, e.g., `V005` for
+    /// `ValidationError::UndefinedRuleCall`. Codes are never reused: new variants get the next
+    /// unused code in their category.
+    pub fn code(&self) -> &'static str {
+        use ErrorKind::*;
+        use OperationalError::*;
+        use ParseErrorKind::*;
+        use RuntimeError::*;
+        use ValidationError::*;
+
+        match &self.0 {
+            Parse(e) => match e.kind {
+                IntegerOverflow { .. } => "P001",
+                InvalidTokenCharacter { .. } => "P002",
+                InvalidToken { .. } => "P003",
+                UnrecognizedEOF { .. } => "P004",
+                UnrecognizedToken { .. } => "P005",
+                ExtraToken { .. } => "P006",
+                ReservedWord { .. } => "P007",
+                InvalidFloat { .. } => "P008",
+                InvalidLiteral { .. } => "P009",
+                WrongValueType { .. } => "P010",
+                DuplicateKey { .. } => "P011",
+                UnknownAggregate { .. } => "P012",
+            },
+            Runtime(e) => match e {
+                ArithmeticError { .. } => "R001",
+                Unsupported { .. } => "R002",
+                TypeError { .. } => "R003",
+                StackOverflow { .. } => "R004",
+                QueryTimeout { .. } => "R005",
+                BudgetExceeded { .. } => "R006",
+                Application { .. } => "R007",
+                IncompatibleBindings { .. } => "R008",
+                UnhandledPartial { .. } => "R009",
+                DataFilteringFieldMissing { .. } => "R010",
+                DataFilteringUnsupportedOp { .. } => "R011",
+                InvalidRegistration { .. } => "R012",
+                MultipleLoadError => "R013",
+                InvalidBundle { .. } => "R014",
+                QueryForUndefinedRule { .. } => "R015",
+                InvalidQueryParameter { .. } => "R016",
+                UnresolvedConstant { .. } => "R017",
+            },
+            Operational(e) => match e {
+                InvalidState { .. } => "O001",
+                Serialization { .. } => "O002",
+                UnexpectedValue { .. } => "O003",
+                Unknown => "O004",
+            },
+            Validation(e) => match e {
+                FileLoading { .. } => "V001",
+                MissingRequiredRule { .. } => "V002",
+                InvalidRule { .. } => "V003",
+                InvalidRuleType { .. } => "V004",
+                UndefinedRuleCall { .. } => "V005",
+                ResourceBlock { .. } => "V006",
+                SingletonVariable { .. } => "V007",
+                UnionType { .. } => "V008",
+                UnregisteredClass { .. } => "V009",
+                DuplicateResourceBlockDeclaration { .. } => "V010",
+                TypeMismatch { .. } => "V011",
+                InlineQueryMismatch { .. } => "V012",
+                PrivateRuleCall { .. } => "V013",
+            },
+        }
+    }
+
     pub fn get_context(&self) -> Option<Context> {
         use ErrorKind::*;
         use OperationalError::*;
@@ -79,7 +148,9 @@ impl PolarError {
                 | ExtraToken { token, loc }
                 | IntegerOverflow { token, loc }
                 | InvalidFloat { token, loc }
+                | InvalidLiteral { token, loc, .. }
                 | ReservedWord { token, loc }
+                | UnknownAggregate { name: token, loc }
                 | UnrecognizedToken { token, loc } => {
                     Some(Context::new(e.source.clone(), *loc, loc + token.len()))
                 }
@@ -107,18 +178,26 @@ impl PolarError {
                 // These errors never have context.
                 StackOverflow { .. }
                 | QueryTimeout { .. }
+                | BudgetExceeded { .. }
                 | IncompatibleBindings { .. }
                 | DataFilteringFieldMissing { .. }
                 | DataFilteringUnsupportedOp { .. }
                 | InvalidRegistration { .. }
                 | QueryForUndefinedRule { .. }
-                | MultipleLoadError => None,
+                | InvalidQueryParameter { .. }
+                | UnresolvedConstant { .. }
+                | MultipleLoadError
+                | InvalidBundle { .. } => None,
             },
 
             Validation(e) => match e {
                 // These errors track `term`, from which we calculate the context.
                 ResourceBlock { term, .. }
                 | SingletonVariable { term, .. }
+                | UnionType { term, .. }
+                | TypeMismatch { term, .. }
+                | InlineQueryMismatch { term, .. }
+                | PrivateRuleCall { term, .. }
                 | UndefinedRuleCall { term }
                 | DuplicateResourceBlockDeclaration {
                     declaration: term, ..
@@ -133,7 +212,7 @@ impl PolarError {
 
                 // These errors track `rule_type`, from which we sometimes calculate the context.
                 MissingRequiredRule { rule_type } => {
-                    if rule_type.name.0 == "has_relation" {
+                    if rule_type.name.as_str() == "has_relation" {
                         rule_type.parsed_context().cloned()
                     } else {
                         // TODO(gj): copy source info from the appropriate resource block term for
@@ -263,6 +342,11 @@ pub enum ParseErrorKind {
         token: String,
         loc: usize,
     },
+    InvalidLiteral {
+        token: String,
+        loc: usize,
+        msg: String,
+    },
     WrongValueType {
         loc: usize,
         term: Term,
@@ -272,6 +356,12 @@ pub enum ParseErrorKind {
         loc: usize,
         key: String,
     },
+    /// A call with a comprehension argument, like `count([x in xs: x > 1])`, to something other
+    /// than an aggregate.
+    UnknownAggregate {
+        loc: usize,
+        name: String,
+    },
 }
 
 impl fmt::Display for ParseErrorKind {
@@ -311,16 +401,47 @@ impl fmt::Display for ParseErrorKind {
                 "{} was parsed as a float, but is invalid",
                 token.escape_debug()
             ),
+            Self::InvalidLiteral { token, msg, .. } => {
+                write!(f, "{} is invalid: {}", token.escape_debug(), msg)
+            }
             Self::WrongValueType { term, expected, .. } => {
                 write!(f, "Wrong value type: {}. Expected a {}", term, expected)
             }
             Self::DuplicateKey { key, .. } => {
                 write!(f, "Duplicate key: {}", key)
             }
+            Self::UnknownAggregate { name, .. } => write!(
+                f,
+                "Unknown aggregate '{}': expected one of count, sum, min or max",
+                name
+            ),
         }
     }
 }
 
+/// A limit on the work done by a query. See `vm::QueryLimits`.
+#[derive(AsRefStr, Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum QueryLimit {
+    Goals,
+    ChoicePoints,
+    ExternalCalls,
+    RuleDepth,
+    Timeout,
+}
+
+impl fmt::Display for QueryLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let unit = match self {
+            Self::Goals => "goals executed",
+            Self::ChoicePoints => "choice points",
+            Self::ExternalCalls => "external calls",
+            Self::RuleDepth => "nested rule calls",
+            Self::Timeout => "milliseconds elapsed",
+        };
+        write!(f, "{}", unit)
+    }
+}
+
 #[derive(AsRefStr, Clone, Debug, Serialize)]
 pub enum RuntimeError {
     ArithmeticError {
@@ -345,6 +466,12 @@ pub enum RuntimeError {
         elapsed: u64,
         timeout: u64,
     },
+    /// The query tripped one of the limits set with `Polar::set_query_limits`.
+    BudgetExceeded {
+        limit: QueryLimit,
+        max: u64,
+        stack_trace: String,
+    },
     Application {
         msg: String,
         stack_trace: String,
@@ -372,11 +499,27 @@ pub enum RuntimeError {
         msg: String,
     },
     MultipleLoadError,
+    /// A policy bundle couldn't be loaded because it's corrupt, was built by a different version
+    /// of Polar, or was built from different sources.
+    InvalidBundle {
+        msg: String,
+    },
     /// The user queried for an undefined rule. This is the runtime analogue of
     /// `ValidationError::UndefinedRuleCall`.
     QueryForUndefinedRule {
         name: String,
     },
+    /// A parameterized query was missing a value for a `$param` placeholder or was given a value
+    /// for a variable that doesn't appear in the query.
+    InvalidQueryParameter {
+        name: String,
+        msg: String,
+    },
+    /// The application didn't supply a value for a constant registered with
+    /// `Polar::register_lazy_constant`.
+    UnresolvedConstant {
+        name: Symbol,
+    },
 }
 
 impl From<RuntimeError> for PolarError {
@@ -400,6 +543,14 @@ impl fmt::Display for RuntimeError {
                 write!(f, "{}", msg)
             }
             Self::QueryTimeout { elapsed, timeout } => write!(f, "Query timeout: Query running for {}ms, which exceeds the timeout of {}ms. To disable timeouts, set the POLAR_TIMEOUT_MS environment variable to 0.", elapsed, timeout),
+            Self::BudgetExceeded {
+                limit,
+                max,
+                stack_trace,
+            } => {
+                writeln!(f, "{}", stack_trace)?;
+                write!(f, "Query budget exceeded: more than {} {}", max, limit)
+            }
             Self::Application {
                 msg, stack_trace, ..
             } => {
@@ -470,7 +621,14 @@ The expression is: {expr}
                 write!(f, "Invalid attempt to register '{}': {}", sym, msg)
             }
             Self::MultipleLoadError => write!(f, "Cannot load additional Polar code -- all Polar code must be loaded at the same time."),
+            Self::InvalidBundle { msg } => write!(f, "Invalid policy bundle: {}", msg),
             Self::QueryForUndefinedRule { name } => write!(f, "Query for undefined rule `{}`", name),
+            Self::InvalidQueryParameter { name, msg } => {
+                write!(f, "Invalid query parameter `{}`: {}", name, msg)
+            }
+            Self::UnresolvedConstant { name } => {
+                write!(f, "The application didn't supply a value for constant `{}`", name)
+            }
         }
     }
 }
@@ -556,6 +714,11 @@ pub enum ValidationError {
         /// Term<Symbol> where the error arose, tracked for lexical context.
         term: Term,
     },
+    UnionType {
+        /// Term where the error arose, tracked for lexical context.
+        term: Term,
+        msg: String,
+    },
     UnregisteredClass {
         /// Term<Symbol> where the error arose, tracked for lexical context.
         term: Term,
@@ -568,6 +731,26 @@ pub enum ValidationError {
         existing: Declaration,
         new: Declaration,
     },
+    /// A condition that can never succeed given the types of its values. See `TypeChecking`.
+    TypeMismatch {
+        /// Term where the error arose, tracked for lexical context.
+        term: Term,
+        msg: String,
+    },
+    /// An inline query's results aren't the ones it expects. See [`crate::inline_query`].
+    InlineQueryMismatch {
+        /// Term<Query> where the error arose, tracked for lexical context.
+        term: Term,
+        missing: Vec<ExpectedResult>,
+        unexpected: Vec<ExpectedResult>,
+    },
+    /// The policy calls a rule annotated with `@private` from a different source file.
+    PrivateRuleCall {
+        /// Term<Call> where the error arose, tracked for lexical context.
+        term: Term,
+        /// The annotated rule.
+        rule: Rule,
+    },
 }
 
 impl From<ValidationError> for PolarError {
@@ -589,10 +772,25 @@ impl fmt::Display for ValidationError {
             Self::UndefinedRuleCall { term } => {
                 write!(f, "Call to undefined rule: {}", term)
             }
+            Self::PrivateRuleCall { term, rule } => {
+                write!(
+                    f,
+                    "Call to private rule {}/{} from outside the file that defines it",
+                    rule.name,
+                    rule.params.len(),
+                )?;
+                if let Some(context) = rule.parsed_context() {
+                    write!(f, "\nDefined{}", context)?;
+                }
+                if term.parsed_context().is_some() {
+                    write!(f, "\nCalled")?;
+                }
+                Ok(())
+            }
             Self::MissingRequiredRule { rule_type } => {
                 write!(f, "Missing implementation for required rule {}", rule_type)
             }
-            Self::ResourceBlock { msg, .. } => {
+            Self::ResourceBlock { msg, .. } | Self::UnionType { msg, .. } => {
                 write!(f, "{}", msg)
             }
             Self::SingletonVariable { term } => {
@@ -613,6 +811,24 @@ impl fmt::Display for ValidationError {
                     existing, declaration, resource, new
                 )
             }
+            Self::TypeMismatch { msg, .. } => write!(f, "Type mismatch: {}", msg),
+            Self::InlineQueryMismatch {
+                term,
+                missing,
+                unexpected,
+            } => {
+                write!(f, "Inline query `{}` has unexpected results", term)?;
+                for (label, results) in [("missing", missing), ("unexpected", unexpected)] {
+                    for result in results {
+                        let bindings = result
+                            .iter()
+                            .map(|(var, value)| format!("{} = {}", var, value))
+                            .collect::<Vec<_>>();
+                        write!(f, "\n\t{}: {}", label, bindings.join(", "))?;
+                    }
+                }
+                Ok(())
+            }
         }
     }
 }
@@ -3,6 +3,7 @@ use std::collections::BTreeMap;
 use serde::{Deserialize, Serialize};
 
 use super::bindings::Bindings;
+use super::debug_protocol::DebuggerEvent;
 use super::runnable::Runnable;
 use super::terms::*;
 use super::traces::*;
@@ -30,6 +31,11 @@ pub enum QueryEvent {
         message: String,
     },
 
+    /// The debugger's answer to a `DebugRequest`, or where it stopped.
+    Debugger {
+        event: DebuggerEvent,
+    },
+
     MakeExternal {
         instance_id: u64,
         constructor: Term,
@@ -95,6 +101,42 @@ pub enum QueryEvent {
         call_id: u64,
         iterable: Term,
     },
+
+    /// A value collected by an aggregate, e.g., each active `x` in
+    /// `count([x in user.sessions: x.active])`, yielded as it's collected if the query has
+    /// `QueryFlags::STREAM_AGGREGATES`. Needs no answer.
+    AggregateValue {
+        /// The aggregate, e.g., `count`.
+        aggregate: Operator,
+        value: Term,
+    },
+
+    /// Supply the value of `name`, a constant registered with
+    /// [`Polar::register_lazy_constant`](crate::polar::Polar::register_lazy_constant), by calling
+    /// `call_result`. The value is cached for later queries; answering with no value fails the
+    /// query.
+    ExternalConstant {
+        call_id: u64,
+        name: Symbol,
+    },
+}
+
+impl QueryEvent {
+    /// Whether the host must call into the application to answer this event, such as to look
+    /// up an attribute or check an `isa`.
+    pub fn is_external(&self) -> bool {
+        matches!(
+            self,
+            Self::ExternalCall { .. }
+                | Self::ExternalIsa { .. }
+                | Self::ExternalIsaWithPath { .. }
+                | Self::ExternalIsSubSpecializer { .. }
+                | Self::ExternalIsSubclass { .. }
+                | Self::ExternalOp { .. }
+                | Self::NextExternal { .. }
+                | Self::ExternalConstant { .. }
+        )
+    }
 }
 
 // A struct for just Result Events. Used to pass data back into
@@ -0,0 +1,94 @@
+//! Machine-readable reports of how a query was evaluated, for finding slow policies.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use serde::Serialize;
+
+use crate::introspection::Span;
+use crate::metrics::QueryMetrics;
+use crate::rules::Rule;
+use crate::terms::Symbol;
+
+/// How often a rule was tried while evaluating a query.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct RuleReport {
+    pub name: Symbol,
+    /// The rule head, e.g., `allow(actor: User, "read", resource: Repo)`.
+    pub head: String,
+    pub span: Option<Span>,
+    /// Number of times the rule's body was entered.
+    pub attempts: usize,
+    /// Number of times the rule's body succeeded. An attempt can succeed more than once.
+    pub successes: usize,
+    /// Number of attempts that never succeeded.
+    pub failures: usize,
+}
+
+/// What a query did while it ran.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct ExplainReport {
+    /// The counts of goals, choice points, backtracks and external calls, as returned by
+    /// [`Query::metrics`](crate::query::Query::metrics).
+    pub metrics: QueryMetrics,
+    /// Rules that were tried, in the order they were first tried.
+    pub rules: Vec<RuleReport>,
+}
+
+/// Collects the rules in an `ExplainReport` as a query runs. Shared between a VM and the VMs it
+/// spawns.
+#[derive(Debug, Default)]
+pub(crate) struct Explainer {
+    rules: Vec<RuleReport>,
+    /// Index into `report.rules` by rule.
+    rule_indices: HashMap<*const Rule, usize>,
+    /// The ID of the next attempt.
+    next_attempt: u64,
+    /// Attempts that haven't succeeded yet, by ID, with the index of their rule.
+    open_attempts: HashMap<u64, usize>,
+}
+
+impl Explainer {
+    /// Record that the body of `rule` was entered, returning the ID of the attempt.
+    pub fn rule_tried(&mut self, rule: &Arc<Rule>) -> u64 {
+        let rules = &mut self.rules;
+        let index = *self
+            .rule_indices
+            .entry(Arc::as_ptr(rule))
+            .or_insert_with(|| {
+                rules.push(RuleReport {
+                    name: rule.name.clone(),
+                    head: rule.head_as_string(),
+                    span: Span::of_rule(rule),
+                    attempts: 0,
+                    successes: 0,
+                    failures: 0,
+                });
+                rules.len() - 1
+            });
+        rules[index].attempts += 1;
+        let attempt = self.next_attempt;
+        self.next_attempt += 1;
+        self.open_attempts.insert(attempt, index);
+        attempt
+    }
+
+    /// Record that the body of `rule` succeeded in the given attempt. Backtracking into the body
+    /// can succeed again in the same attempt.
+    pub fn rule_succeeded(&mut self, attempt: u64, rule: &Arc<Rule>) {
+        self.open_attempts.remove(&attempt);
+        if let Some(&index) = self.rule_indices.get(&Arc::as_ptr(rule)) {
+            self.rules[index].successes += 1;
+        }
+    }
+
+    /// The report so far, with the query's `metrics`. Attempts that haven't succeeded, whether
+    /// still in progress or abandoned on backtracking, count as failures.
+    pub fn report(&self, metrics: QueryMetrics) -> ExplainReport {
+        let mut rules = self.rules.clone();
+        for &index in self.open_attempts.values() {
+            rules[index].failures += 1;
+        }
+        ExplainReport { metrics, rules }
+    }
+}
@@ -49,11 +49,15 @@ pub struct Filter {
 /// from the `Foo` type to the `Bar` type, accessed using the `bar` field
 /// on `Foo`.
 #[derive(PartialEq, Eq, Debug, Serialize, Clone, Hash)]
-pub struct Relation(TypeName, FieldName, TypeName);
+pub struct Relation(
+    pub(crate) TypeName,
+    pub(crate) FieldName,
+    pub(crate) TypeName,
+);
 
 /// A constraint that must hold for a record in the data source.
 #[derive(PartialEq, Eq, Debug, Serialize, Clone, Hash)]
-pub struct Condition(Datum, Comparison, Datum);
+pub struct Condition(pub(crate) Datum, pub(crate) Comparison, pub(crate) Datum);
 
 /// The left or right side of a Condition.
 #[derive(PartialEq, Eq, Debug, Serialize, Clone, Hash)]
@@ -77,7 +81,7 @@ pub enum Comparison {
 
 /// An abstract "field reference" on a record from a named data source.
 #[derive(PartialEq, Eq, Debug, Serialize, Clone, Hash)]
-pub struct Projection(TypeName, Option<FieldName>);
+pub struct Projection(pub(crate) TypeName, pub(crate) Option<FieldName>);
 
 type TypeInfo = Map<TypeName, Map<FieldName, Type>>;
 type VarTypes = Map<PathVar, TypeName>;
@@ -125,7 +129,7 @@ impl PathVar {
                 pv.path.push(dot);
                 Ok(pv)
             }
-            Variable(Symbol(var)) => Ok(var.clone().into()),
+            Variable(var) => Ok(var.to_string().into()),
             _ => invalid_state(format!("PathVar::from_term({})", t)),
         }
     }
@@ -145,7 +149,7 @@ impl Filter {
             eprintln!("\n==Bindings==")
         }
 
-        let sym = Symbol(var.to_string());
+        let sym = Symbol::new(var);
         let filter = partials
             .into_iter()
             .filter_map(|opt| opt.bindings.get(&sym).cloned())
@@ -35,6 +35,12 @@ pub trait Folder: Sized {
     fn fold_boolean(&mut self, b: bool) -> bool {
         fold_boolean(b, self)
     }
+    fn fold_datetime(&mut self, t: DateTime) -> DateTime {
+        fold_datetime(t, self)
+    }
+    fn fold_duration(&mut self, d: Duration) -> Duration {
+        fold_duration(d, self)
+    }
     fn fold_instance_id(&mut self, i: u64) -> u64 {
         fold_instance_id(i, self)
     }
@@ -78,6 +84,9 @@ pub trait Folder: Sized {
     fn fold_list(&mut self, l: TermList) -> TermList {
         fold_list(l, self)
     }
+    fn fold_set(&mut self, s: Set) -> Set {
+        fold_set(s, self)
+    }
     fn fold_operation(&mut self, o: Operation) -> Operation {
         fold_operation(o, self)
     }
@@ -114,11 +123,14 @@ pub fn fold_value<T: Folder>(v: Value, fld: &mut T) -> Value {
         Value::Number(n) => Value::Number(fld.fold_number(n)),
         Value::String(s) => Value::String(fld.fold_string(s)),
         Value::Boolean(b) => Value::Boolean(fld.fold_boolean(b)),
+        Value::DateTime(t) => Value::DateTime(fld.fold_datetime(t)),
+        Value::Duration(d) => Value::Duration(fld.fold_duration(d)),
         Value::ExternalInstance(e) => Value::ExternalInstance(fld.fold_external_instance(e)),
         Value::Dictionary(d) => Value::Dictionary(fld.fold_dictionary(d)),
         Value::Pattern(p) => Value::Pattern(fld.fold_pattern(p)),
         Value::Call(c) => Value::Call(fld.fold_call(c)),
         Value::List(l) => Value::List(fld.fold_list(l)),
+        Value::Set(s) => Value::Set(fld.fold_set(s)),
         Value::Variable(v) => Value::Variable(fld.fold_variable(v)),
         Value::RestVariable(r) => Value::RestVariable(fld.fold_rest_variable(r)),
         Value::Expression(o) => Value::Expression(fld.fold_operation(o)),
@@ -137,6 +149,14 @@ pub fn fold_boolean<T: Folder>(b: bool, _fld: &mut T) -> bool {
     b
 }
 
+pub fn fold_datetime<T: Folder>(t: DateTime, _fld: &mut T) -> DateTime {
+    t
+}
+
+pub fn fold_duration<T: Folder>(d: Duration, _fld: &mut T) -> Duration {
+    d
+}
+
 pub fn fold_instance_id<T: Folder>(id: u64, _fld: &mut T) -> u64 {
     id
 }
@@ -209,6 +229,15 @@ pub fn fold_list<T: Folder>(l: TermList, fld: &mut T) -> TermList {
         .collect::<TermList>()
 }
 
+/// Folding the elements of a set can make some of them equal, e.g., when binding variables, so the
+/// folded elements are collected into a new set.
+pub fn fold_set<T: Folder>(s: Set, fld: &mut T) -> Set {
+    TermList::from(s)
+        .into_iter()
+        .map(|t| fld.fold_term(t))
+        .collect()
+}
+
 pub fn fold_operator<T: Folder>(o: Operator, _fld: &mut T) -> Operator {
     o
 }
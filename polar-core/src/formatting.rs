@@ -9,7 +9,14 @@
 //! In addition, there are special cases like traces and sources that have their own
 //! formatting requirements.
 
-use super::{lexer::loc_to_pos, rules::*, sources::*, terms::*, traces::*};
+use super::{
+    folder::{fold_list, fold_term, Folder},
+    lexer::loc_to_pos,
+    rules::*,
+    sources::*,
+    terms::*,
+    traces::*,
+};
 
 impl Trace {
     /// Return the string representation of this `Trace`
@@ -92,6 +99,138 @@ pub(crate) fn source_lines(source: &Source, offset: usize, context_lines: usize)
     lines.join("\n")
 }
 
+/// How a [`TermFormatter`] prints external instances.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum InstanceRepr {
+    /// The host's repr of the instance and its class, e.g. ``User("alice") TYPE `User` ``.
+    #[default]
+    Full,
+    /// Only the instance id, e.g. `^{id: 123}`, which host libraries can enrich.
+    Id,
+}
+
+/// Formats terms as Polar strings, eliding whatever exceeds the configured limits so that
+/// large terms in logs, traces and error messages stay readable.
+///
+/// The default formatter imposes no limits and matches the `Display` output of terms.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TermFormatter {
+    max_depth: Option<usize>,
+    max_list_elements: Option<usize>,
+    float_precision: Option<usize>,
+    instance_repr: InstanceRepr,
+}
+
+impl TermFormatter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Print compound terms (lists, dictionaries, calls, expressions...) nested more than
+    /// `depth` levels deep as `...`.
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = Some(depth);
+        self
+    }
+
+    /// Print at most `elements` elements of each list, followed by a count of the rest.
+    pub fn max_list_elements(mut self, elements: usize) -> Self {
+        self.max_list_elements = Some(elements);
+        self
+    }
+
+    /// Round floats to `precision` decimal places.
+    pub fn float_precision(mut self, precision: usize) -> Self {
+        self.float_precision = Some(precision);
+        self
+    }
+
+    pub fn instance_repr(mut self, style: InstanceRepr) -> Self {
+        self.instance_repr = style;
+        self
+    }
+
+    /// Return the Polar string for `term` under this formatter's limits.
+    pub fn to_polar_string(&self, term: &Term) -> String {
+        if *self == Self::default() {
+            return term.to_string();
+        }
+        Elider {
+            formatter: self,
+            depth: 0,
+        }
+        .fold_term(term.clone())
+        .to_string()
+    }
+}
+
+/// Replaces the parts of a term a [`TermFormatter`] leaves out with placeholder variables,
+/// which print verbatim.
+struct Elider<'a> {
+    formatter: &'a TermFormatter,
+    depth: usize,
+}
+
+impl Elider<'_> {
+    fn placeholder(term: &Term, name: String) -> Term {
+        term.clone_with_value(Value::Variable(Symbol::new(&name)))
+    }
+}
+
+impl Folder for Elider<'_> {
+    fn fold_term(&mut self, t: Term) -> Term {
+        let compound = matches!(
+            t.value(),
+            Value::List(_)
+                | Value::Dictionary(_)
+                | Value::Call(_)
+                | Value::Expression(_)
+                | Value::Pattern(_)
+        );
+        match t.value() {
+            Value::ExternalInstance(instance)
+                if self.formatter.instance_repr == InstanceRepr::Id =>
+            {
+                Self::placeholder(&t, format!("^{{id: {}}}", instance.instance_id))
+            }
+            _ if compound && matches!(self.formatter.max_depth, Some(max) if self.depth >= max) => {
+                Self::placeholder(&t, "...".to_owned())
+            }
+            _ if compound => {
+                self.depth += 1;
+                let folded = fold_term(t, self);
+                self.depth -= 1;
+                folded
+            }
+            _ => fold_term(t, self),
+        }
+    }
+
+    fn fold_list(&mut self, mut l: TermList) -> TermList {
+        if let Some(max) = self.formatter.max_list_elements {
+            if l.len() > max {
+                let rest = l.len() - max;
+                let placeholder = Self::placeholder(&l[max], format!("...{} more", rest));
+                l.truncate(max);
+                l = fold_list(l, self);
+                l.push(placeholder);
+                return l;
+            }
+        }
+        fold_list(l, self)
+    }
+
+    fn fold_number(&mut self, n: Numeric) -> Numeric {
+        match (n, self.formatter.float_precision) {
+            (Numeric::Float(f), Some(precision)) => {
+                let rounded = format!("{:.*}", precision, f);
+                Numeric::Float(rounded.parse().unwrap_or(f))
+            }
+            (n, _) => n,
+        }
+    }
+}
+
 /// Formats a vector of terms as a string-separated list
 /// When providing an operator, parentheses are applied suitably
 /// (see: to_polar_parens)
@@ -117,6 +256,10 @@ fn precedence(o: &Operator) -> i32 {
         Operator::New => 10,
         Operator::Cut => 10,
         Operator::ForAll => 10,
+        Operator::Count => 10,
+        Operator::Sum => 10,
+        Operator::Min => 10,
+        Operator::Max => 10,
         Operator::Dot => 9,
         Operator::In => 8,
         Operator::Isa => 8,
@@ -179,7 +322,7 @@ mod display {
 
     impl fmt::Display for Symbol {
         fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
-            write!(fmt, "{}", self.0)
+            write!(fmt, "{}", self.as_str())
         }
     }
 
@@ -334,6 +477,8 @@ mod display {
             match self {
                 Self::Integer(i) => write!(f, "{}", i),
                 Self::Float(float) => write!(f, "{}", float),
+                Self::BigInteger(i) => write!(f, "{}", i),
+                Self::Decimal(d) => write!(f, "{}d", d),
             }
         }
     }
@@ -343,7 +488,7 @@ mod display {
             match self {
                 Self::Role => write!(f, "role"),
                 Self::Permission => write!(f, "permission"),
-                Self::Relation(_) => write!(f, "relation"),
+                Self::Relation(..) => write!(f, "relation"),
             }
         }
     }
@@ -373,6 +518,7 @@ mod display {
 
 mod to_polar {
     use crate::formatting::{format_args, format_params, to_polar_parens};
+    use crate::parser::INTERPOLATE;
     use crate::resource_block::{BlockType, ResourceBlock, ShorthandRule};
     use crate::rules::*;
     use crate::terms::*;
@@ -448,6 +594,10 @@ mod to_polar {
                 In => "in",
                 Cut => "cut",
                 ForAll => "forall",
+                Count => "count",
+                Sum => "sum",
+                Min => "min",
+                Max => "max",
                 Debug => "debug",
                 Print => "print",
                 Isa => "matches",
@@ -456,6 +606,22 @@ mod to_polar {
         }
     }
 
+    /// Format the arguments of the `INTERPOLATE` method as the interpolated string they were
+    /// parsed from.
+    fn interpolated_string(args: &[Term]) -> String {
+        let segments = match args.first().map(Term::value) {
+            Some(Value::List(list)) => list.iter(),
+            _ => [].iter(),
+        };
+        let segments = segments
+            .map(|segment| match segment.value() {
+                Value::String(s) => s.replace('{', "\\{"),
+                _ => format!("{{{}}}", segment.to_polar()),
+            })
+            .collect::<String>();
+        format!("f\"{}\"", segments)
+    }
+
     impl ToPolarString for Operation {
         fn to_polar(&self) -> String {
             use Operator::*;
@@ -470,6 +636,19 @@ mod to_polar {
                     self.args[0].to_polar(),
                     self.args[1].to_polar()
                 ),
+                Count | Sum | Min | Max => {
+                    let aggregate = format!(
+                        "{}([{} in {}: {}])",
+                        self.operator.to_polar(),
+                        self.args[0].to_polar(),
+                        to_polar_parens(In, &self.args[1]),
+                        self.args[2].to_polar()
+                    );
+                    match self.args.get(3) {
+                        Some(result) => format!("{} = {}", aggregate, result.to_polar()),
+                        None => aggregate,
+                    }
+                }
                 New => {
                     if self.args.len() == 1 {
                         format!("new {}", to_polar_parens(self.operator, &self.args[0]))
@@ -483,19 +662,16 @@ mod to_polar {
                 }
                 // Lookup operator
                 Dot => {
-                    let call_term = if let Value::String(s) = self.args[1].value() {
-                        s.to_string()
-                    } else {
-                        self.args[1].to_polar()
+                    let lookup = match self.args[1].value() {
+                        Value::Call(Call { name, args, .. }) if name.as_str() == INTERPOLATE => {
+                            interpolated_string(args)
+                        }
+                        Value::String(s) => format!("{}.{}", self.args[0].to_polar(), s),
+                        _ => format!("{}.{}", self.args[0].to_polar(), self.args[1].to_polar()),
                     };
                     match self.args.len() {
-                        2 => format!("{}.{}", self.args[0].to_polar(), call_term),
-                        3 => format!(
-                            "{}.{} = {}",
-                            self.args[0].to_polar(),
-                            call_term,
-                            self.args[2].to_polar()
-                        ),
+                        2 => lookup,
+                        3 => format!("{} = {}", lookup, self.args[2].to_polar()),
                         // Invalid
                         _ => format!(".({})", format_args(self.operator, &self.args, ", ")),
                     }
@@ -609,7 +785,7 @@ mod to_polar {
 
     impl ToPolarString for Symbol {
         fn to_polar(&self) -> String {
-            self.0.to_string()
+            self.as_str().to_string()
         }
     }
 
@@ -633,6 +809,8 @@ mod to_polar {
             match self {
                 Value::Number(i) => format!("{}", i),
                 Value::String(s) => format!("\"{}\"", s),
+                Value::DateTime(t) => format!("datetime\"{}\"", t),
+                Value::Duration(d) => format!("duration\"{}\"", d),
                 Value::Boolean(b) => {
                     if *b {
                         "true".to_string()
@@ -645,6 +823,7 @@ mod to_polar {
                 Value::ExternalInstance(i) => i.to_polar(),
                 Value::Call(c) => c.to_polar(),
                 Value::List(l) => format!("[{}]", format_args(Operator::And, l, ", "),),
+                Value::Set(s) => format!("{{{}}}", format_args(Operator::And, s.elements(), ", ")),
                 Value::Variable(s) => s.to_polar(),
                 Value::RestVariable(s) => format!("*{}", s.to_polar()),
                 Value::Expression(e) => e.to_polar(),
@@ -697,6 +876,9 @@ mod to_polar {
             if let Some(ref relations) = self.relations {
                 s += &format!("  relations = {};\n", relations.to_polar());
             }
+            if let Some(ref fields) = self.fields {
+                s += &format!("  fields = {};\n", fields.to_polar());
+            }
             for rule in &self.shorthand_rules {
                 s += &format!("  {}\n", rule.to_polar());
             }
@@ -712,6 +894,45 @@ mod tests {
 
     use super::*;
 
+    #[test]
+    fn test_term_formatter() {
+        let term = crate::parser::parse_query("[1, [2, [3, [4]]], 3.14159, 4, 5]").unwrap();
+        assert_eq!(
+            TermFormatter::default().to_polar_string(&term),
+            term.to_string()
+        );
+
+        let formatter = TermFormatter::new().max_depth(2);
+        assert_eq!(
+            formatter.to_polar_string(&term),
+            "[1, [2, ...], 3.14159, 4, 5]"
+        );
+
+        let formatter = TermFormatter::new().max_list_elements(3).float_precision(2);
+        assert_eq!(
+            formatter.to_polar_string(&term),
+            "[1, [2, [3, [4]]], 3.14, ...2 more]"
+        );
+
+        let instance = term!(Value::ExternalInstance(ExternalInstance {
+            instance_id: 7,
+            constructor: None,
+            repr: Some("User(\"alice\")".to_owned()),
+            class_repr: Some("User".to_owned()),
+            class_id: None,
+        }));
+        assert_eq!(
+            TermFormatter::new().to_polar_string(&instance),
+            "User(\"alice\") TYPE `User`"
+        );
+        assert_eq!(
+            TermFormatter::new()
+                .instance_repr(InstanceRepr::Id)
+                .to_polar_string(&instance),
+            "^{id: 7}"
+        );
+    }
+
     #[test]
     fn test_source_lines() {
         let source = Source::new("hi");
@@ -0,0 +1,271 @@
+//! Inline queries with expected results, e.g.,
+//!
+//! ```polar
+//! ?= grants(alice, action) => action in ["read", "write"];
+//! ```
+//!
+//! The part after `=>` lists the results the query must have, as `var = value` and
+//! `var in [values]` conditions combined with `and` and `or`. The query succeeds if its results,
+//! restricted to the variables the expectation names, are exactly the expected ones, in any
+//! order. Otherwise it fails with an [`InlineQueryMismatch`](ValidationError::InlineQueryMismatch)
+//! error listing the results that are missing and those that weren't expected.
+
+use std::collections::{BTreeMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+
+use super::bindings::Bindings;
+use super::error::{unsupported, PolarResult, ValidationError};
+use super::terms::{Operation, Operator, Symbol, Term, Value};
+
+/// One result of an inline query, restricted to the variables its expectation names.
+pub type ExpectedResult = BTreeMap<Symbol, Term>;
+
+/// An inline query loaded from a policy.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct InlineQuery {
+    pub term: Term,
+    /// The results the query must have, if any are given after `=>`.
+    pub expected: Option<Term>,
+}
+
+impl InlineQuery {
+    /// Check that the expected results are ones this module can compare against.
+    pub fn new(term: Term, expected: Option<Term>) -> PolarResult<Self> {
+        let query = Self { term, expected };
+        query.expected_results()?;
+        Ok(query)
+    }
+
+    pub(crate) fn expected_results(&self) -> PolarResult<Option<ExpectedResults>> {
+        let expected = match &self.expected {
+            Some(expected) => expected,
+            None => return Ok(None),
+        };
+        let mut results = vec![];
+        for result in alternatives(expected)? {
+            if !results.contains(&result) {
+                results.push(result);
+            }
+        }
+
+        let vars = results
+            .first()
+            .map(|result| result.keys().cloned().collect::<Vec<_>>())
+            .unwrap_or_default();
+        if results.iter().any(|result| !result.keys().eq(vars.iter())) {
+            return unsupported(
+                "expected results that don't all bind the same variables",
+                expected,
+            );
+        }
+        let mut query_vars = HashSet::new();
+        self.term.variables(&mut query_vars);
+        if let Some(var) = vars.iter().find(|var| !query_vars.contains(var)) {
+            return unsupported(
+                format!(
+                    "expected results for `{}`, which the query doesn't use",
+                    var
+                ),
+                expected,
+            );
+        }
+
+        Ok(Some(ExpectedResults {
+            query: self.term.clone(),
+            vars,
+            expected: results,
+            actual: vec![],
+        }))
+    }
+}
+
+/// The results an expectation allows, each as a map from variable to value.
+fn alternatives(term: &Term) -> PolarResult<Vec<ExpectedResult>> {
+    let (operator, args) = match term.value() {
+        Value::Expression(Operation { operator, args }) => (*operator, args),
+        _ => return unsupported_expectation(term),
+    };
+    match operator {
+        Operator::And => {
+            let mut results = vec![ExpectedResult::new()];
+            for arg in args {
+                let alternatives = alternatives(arg)?;
+                results = results
+                    .iter()
+                    .flat_map(|result| alternatives.iter().filter_map(|alt| merge(result, alt)))
+                    .collect();
+            }
+            Ok(results)
+        }
+        Operator::Or => {
+            let mut results = vec![];
+            for arg in args {
+                results.append(&mut alternatives(arg)?);
+            }
+            Ok(results)
+        }
+        Operator::Unify | Operator::Eq => match (args[0].value(), args[1].value()) {
+            (Value::Variable(var), _) if args[1].is_ground() => {
+                Ok(vec![ExpectedResult::from([(var.clone(), args[1].clone())])])
+            }
+            (_, Value::Variable(var)) if args[0].is_ground() => {
+                Ok(vec![ExpectedResult::from([(var.clone(), args[0].clone())])])
+            }
+            _ => unsupported_expectation(term),
+        },
+        Operator::In => match (args[0].value(), args[1].value()) {
+            (Value::Variable(var), Value::List(values)) if args[1].is_ground() => Ok(values
+                .iter()
+                .map(|value| ExpectedResult::from([(var.clone(), value.clone())]))
+                .collect()),
+            _ => unsupported_expectation(term),
+        },
+        _ => unsupported_expectation(term),
+    }
+}
+
+/// Combine two partial results, or `None` if they give a variable different values.
+fn merge(left: &ExpectedResult, right: &ExpectedResult) -> Option<ExpectedResult> {
+    let mut merged = left.clone();
+    for (var, value) in right {
+        match merged.get(var) {
+            Some(existing) if existing != value => return None,
+            _ => merged.insert(var.clone(), value.clone()),
+        };
+    }
+    Some(merged)
+}
+
+fn unsupported_expectation<T>(term: &Term) -> PolarResult<T> {
+    unsupported(
+        "expected results other than `var = value` and `var in [values]` conditions combined with `and` and `or`",
+        term,
+    )
+}
+
+/// The results of a running inline query, checked against its expectation once it's done.
+pub(crate) struct ExpectedResults {
+    query: Term,
+    vars: Vec<Symbol>,
+    expected: Vec<ExpectedResult>,
+    actual: Vec<ExpectedResult>,
+}
+
+impl ExpectedResults {
+    pub fn record(&mut self, bindings: &Bindings) {
+        let result = self
+            .vars
+            .iter()
+            .map(|var| {
+                let value = bindings
+                    .get(var)
+                    .cloned()
+                    .unwrap_or_else(|| Term::from(Value::Variable(var.clone())));
+                (var.clone(), value)
+            })
+            .collect();
+        if !self.actual.contains(&result) {
+            self.actual.push(result);
+        }
+    }
+
+    pub fn check(self) -> PolarResult<()> {
+        let missing = self
+            .expected
+            .iter()
+            .filter(|result| !self.actual.contains(result))
+            .cloned()
+            .collect::<Vec<_>>();
+        let unexpected = self
+            .actual
+            .iter()
+            .filter(|result| !self.expected.contains(result))
+            .cloned()
+            .collect::<Vec<_>>();
+        if missing.is_empty() && unexpected.is_empty() {
+            Ok(())
+        } else {
+            Err(ValidationError::InlineQueryMismatch {
+                term: self.query,
+                missing,
+                unexpected,
+            }
+            .into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::{ErrorKind, PolarError};
+    use crate::events::QueryEvent;
+    use crate::polar::Polar;
+
+    fn run_inline_query(src: &str) -> PolarResult<bool> {
+        let p = Polar::new();
+        p.load_str(src)?;
+        let mut query = p.next_inline_query(false).unwrap();
+        let mut succeeded = false;
+        loop {
+            match query.next_event()? {
+                QueryEvent::Result { .. } => succeeded = true,
+                QueryEvent::Done { .. } => return Ok(succeeded),
+                event => panic!("unexpected event {:?}", event),
+            }
+        }
+    }
+
+    #[test]
+    fn test_expected_results() {
+        let policy = r#"
+            grants("alice", "read");
+            grants("alice", "write");
+            grants("bob", "read");
+        "#;
+        assert!(run_inline_query(&format!(
+            r#"{} ?= grants("alice", action) => action in ["write", "read"];"#,
+            policy
+        ))
+        .unwrap());
+        assert!(run_inline_query(&format!(
+            r#"{} ?= grants(user, "read") => user = "alice" or user = "bob";"#,
+            policy
+        ))
+        .unwrap());
+
+        let err = run_inline_query(&format!(
+            r#"{} ?= grants("alice", action) => action in ["read", "delete"];"#,
+            policy
+        ))
+        .unwrap_err();
+        assert!(err.to_string().starts_with(
+            "Inline query `grants(\"alice\", action)` has unexpected results\n\tmissing: action = \"delete\"\n\tunexpected: action = \"write\""
+        ));
+        match err {
+            PolarError(ErrorKind::Validation(ValidationError::InlineQueryMismatch {
+                missing,
+                unexpected,
+                ..
+            })) => {
+                assert_eq!(
+                    missing,
+                    vec![ExpectedResult::from([(sym!("action"), term!("delete"))])]
+                );
+                assert_eq!(
+                    unexpected,
+                    vec![ExpectedResult::from([(sym!("action"), term!("write"))])]
+                );
+            }
+            err => panic!("unexpected error {}", err),
+        }
+    }
+
+    #[test]
+    fn test_unsupported_expected_results() {
+        assert!(run_inline_query("f(1); ?= f(x) => x > 0;").is_err());
+        assert!(run_inline_query("f(1); ?= f(x) => y = 1;").is_err());
+        assert!(run_inline_query("f(1); ?= f(x) => x = y;").is_err());
+    }
+}
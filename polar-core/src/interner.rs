@@ -0,0 +1,174 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use crate::folder::{fold_term, Folder};
+use crate::rules::Rule;
+use crate::terms::{Symbol, Term};
+use crate::visitor::{walk_term, Visitor};
+
+/// Deduplicates the names of symbols, so that the equal symbols in a loaded policy share one
+/// allocation. Short names are stored inline in the symbol and never allocated, so only longer
+/// names are interned. Cloning a `Symbol` at most bumps a reference count, so interning the rules
+/// once at load time means the VM doesn't copy the names in them while it evaluates them.
+#[derive(Clone, Default)]
+pub struct Interner {
+    names: HashSet<Arc<str>>,
+}
+
+impl Interner {
+    /// A symbol named `name`, allocating only if the name is long and hasn't been seen before.
+    pub fn intern_str(&mut self, name: &str) -> Symbol {
+        let symbol = Symbol::new(name);
+        match symbol.shared() {
+            Some(_) => self.intern(symbol),
+            None => symbol,
+        }
+    }
+
+    pub fn intern(&mut self, symbol: Symbol) -> Symbol {
+        let name = match symbol.shared() {
+            Some(name) => name,
+            None => return symbol,
+        };
+        match self.names.get(name) {
+            Some(interned) => interned.clone().into(),
+            None => {
+                self.names.insert(name.clone());
+                symbol
+            }
+        }
+    }
+
+    /// Forget the names that none of `rules` mention, e.g., after unloading a source.
+    pub fn retain_used<'a>(&mut self, rules: impl IntoIterator<Item = &'a Rule>) {
+        let mut used = Used::default();
+        for rule in rules {
+            used.visit_rule(rule);
+        }
+        self.names.retain(|name| used.names.contains(name));
+    }
+}
+
+impl Folder for Interner {
+    fn fold_term(&mut self, t: Term) -> Term {
+        // Only copy terms that mention a name that isn't shared yet.
+        let mut uninterned = Uninterned {
+            names: &self.names,
+            found: false,
+        };
+        uninterned.visit_term(&t);
+        if uninterned.found {
+            fold_term(t, self)
+        } else {
+            t
+        }
+    }
+
+    fn fold_name(&mut self, n: Symbol) -> Symbol {
+        self.intern(n)
+    }
+
+    fn fold_variable(&mut self, v: Symbol) -> Symbol {
+        self.intern(v)
+    }
+
+    fn fold_rest_variable(&mut self, v: Symbol) -> Symbol {
+        self.intern(v)
+    }
+}
+
+/// Finds names that the interner doesn't share.
+struct Uninterned<'a> {
+    names: &'a HashSet<Arc<str>>,
+    found: bool,
+}
+
+impl Uninterned<'_> {
+    fn check(&mut self, symbol: &Symbol) {
+        if let Some(name) = symbol.shared() {
+            if !matches!(self.names.get(name), Some(interned) if Arc::ptr_eq(interned, name)) {
+                self.found = true;
+            }
+        }
+    }
+}
+
+impl Visitor for Uninterned<'_> {
+    fn visit_term(&mut self, t: &Term) {
+        if !self.found {
+            walk_term(self, t)
+        }
+    }
+
+    fn visit_symbol(&mut self, s: &Symbol) {
+        self.check(s)
+    }
+
+    fn visit_variable(&mut self, v: &Symbol) {
+        self.check(v)
+    }
+
+    fn visit_rest_variable(&mut self, r: &Symbol) {
+        self.check(r)
+    }
+}
+
+/// Collects the shared names of the symbols it visits.
+#[derive(Default)]
+struct Used {
+    names: HashSet<Arc<str>>,
+}
+
+impl Used {
+    fn add(&mut self, symbol: &Symbol) {
+        if let Some(name) = symbol.shared() {
+            self.names.insert(name.clone());
+        }
+    }
+}
+
+impl Visitor for Used {
+    fn visit_symbol(&mut self, s: &Symbol) {
+        self.add(s)
+    }
+
+    fn visit_variable(&mut self, v: &Symbol) {
+        self.add(v)
+    }
+
+    fn visit_rest_variable(&mut self, r: &Symbol) {
+        self.add(r)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_rules;
+
+    #[test]
+    fn test_intern_rule() {
+        let mut interner = Interner::default();
+        let rule = parse_rules(
+            "f(first_long_variable_name, y) if g(first_long_variable_name, second_long_variable_name);",
+        )
+        .unwrap()
+        .remove(0);
+        let rule = interner.fold_rule(rule);
+        // Short names like `f` and `y` are stored inline.
+        assert_eq!(interner.names.len(), 2);
+
+        let x = interner.intern(sym!("first_long_variable_name"));
+        let param = rule.params[0].parameter.as_symbol().unwrap();
+        assert!(Arc::ptr_eq(x.shared().unwrap(), param.shared().unwrap()));
+
+        let other = parse_rules("g(second_long_variable_name);")
+            .unwrap()
+            .remove(0);
+        let other = interner.fold_rule(other);
+        interner.retain_used([&other]);
+        assert_eq!(interner.names.len(), 1);
+        interner.retain_used([]);
+        assert!(interner.names.is_empty());
+    }
+}
@@ -0,0 +1,309 @@
+//! A stable, serializable view of the policy loaded into a knowledge base.
+//!
+//! [`PolicyAst`] is meant for tooling built on top of Polar policies, such as linters,
+//! visualizers and documentation generators. Rule heads and bodies are exported as [`Term`]s,
+//! which can be walked with [`crate::visitor::Visitor`] and located in their source with
+//! [`Span::of_term`].
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::kb::KnowledgeBase;
+use crate::lexer::loc_to_pos;
+use crate::resource_block::{Declaration, RelationKind};
+use crate::rules::{Parameter, Rule};
+use crate::sources::Context;
+use crate::terms::{Symbol, Term, Value};
+
+/// A span of source text. Lines and columns start at 1, and `end_column` is exclusive.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Span {
+    pub filename: Option<String>,
+    /// Byte offset of the start of the span.
+    pub start: usize,
+    /// Byte offset of the end of the span, exclusive.
+    pub end: usize,
+    pub start_line: usize,
+    pub start_column: usize,
+    pub end_line: usize,
+    pub end_column: usize,
+}
+
+impl Span {
+    /// The span `term` was parsed from, or `None` if it wasn't parsed from a policy.
+    pub fn of_term(term: &Term) -> Option<Self> {
+        term.parsed_context().map(Self::from)
+    }
+
+    /// The span `rule` was parsed from, or `None` if it wasn't parsed from a policy.
+    pub fn of_rule(rule: &Rule) -> Option<Self> {
+        rule.parsed_context().map(Self::from)
+    }
+}
+
+impl From<&Context> for Span {
+    fn from(context: &Context) -> Self {
+        let (start_line, start_column) = loc_to_pos(&context.source.src, context.left);
+        let (end_line, end_column) = loc_to_pos(&context.source.src, context.right);
+        Self {
+            filename: context.source.filename.clone(),
+            start: context.left,
+            end: context.right,
+            start_line: start_line + 1,
+            start_column: start_column + 1,
+            end_line: end_line + 1,
+            end_column: end_column + 1,
+        }
+    }
+}
+
+/// A rule or rule type.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct RuleAst {
+    pub name: Symbol,
+    pub params: Vec<Parameter>,
+    /// The rule body. Facts and rule types have an empty `And` body.
+    pub body: Term,
+    /// Whether a rule type must be implemented by some rule. Always false for rules.
+    pub required: bool,
+    pub span: Option<Span>,
+}
+
+impl From<&Rule> for RuleAst {
+    fn from(rule: &Rule) -> Self {
+        Self {
+            name: rule.name.clone(),
+            params: rule.params.clone(),
+            body: rule.body.clone(),
+            required: rule.required,
+            span: Span::of_rule(rule),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResourceBlockKind {
+    Actor,
+    Resource,
+}
+
+/// A relation declared in a resource block, e.g., `parent: Org`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct RelationAst {
+    pub name: String,
+    pub related_type: Symbol,
+    pub kind: RelationKind,
+}
+
+/// A shorthand rule declared in a resource block, e.g., `"member" if "owner" on "parent";`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ShorthandRuleAst {
+    pub head: String,
+    pub implier: String,
+    /// The relation the implier is looked up on, if any.
+    pub relation: Option<String>,
+    pub span: Option<Span>,
+}
+
+/// The declarations for a resource or actor type, merged across every block that declares it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ResourceBlockAst {
+    pub kind: ResourceBlockKind,
+    pub resource: Symbol,
+    pub roles: Vec<String>,
+    pub permissions: Vec<String>,
+    pub relations: Vec<RelationAst>,
+    pub shorthand_rules: Vec<ShorthandRuleAst>,
+    /// Where the resource was named in the first block that declared it.
+    pub span: Option<Span>,
+}
+
+/// Everything loaded into a `KnowledgeBase`, in a deterministic order.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct PolicyAst {
+    /// Rules sorted by name, then in the order they were loaded.
+    pub rules: Vec<RuleAst>,
+    /// Rule types sorted by name, then in the order they were declared, including the built-in
+    /// rule types.
+    pub rule_types: Vec<RuleAst>,
+    /// Resource blocks sorted by resource name.
+    pub resource_blocks: Vec<ResourceBlockAst>,
+}
+
+fn string(term: &Term) -> String {
+    match term.value() {
+        Value::String(s) => s.clone(),
+        _ => term.to_string(),
+    }
+}
+
+fn symbol(term: &Term) -> Symbol {
+    match term.value() {
+        Value::Variable(s) => s.clone(),
+        _ => Symbol::new(&term.to_string()),
+    }
+}
+
+impl PolicyAst {
+    pub(crate) fn new(kb: &KnowledgeBase) -> Self {
+        let generic_rules = kb.get_rules().iter().collect::<BTreeMap<_, _>>();
+        let rules = generic_rules
+            .into_values()
+            .flat_map(|generic_rule| {
+                let rules = generic_rule.rules.iter().collect::<BTreeMap<_, _>>();
+                rules.into_values().map(|rule| RuleAst::from(rule.as_ref()))
+            })
+            .collect();
+
+        let mut rule_types = kb.rule_types().iter().collect::<Vec<_>>();
+        rule_types.sort_by(|a, b| a.name.cmp(&b.name));
+        let rule_types = rule_types.into_iter().map(RuleAst::from).collect();
+
+        let blocks = &kb.resource_blocks;
+        let mut resource_blocks = blocks
+            .resources
+            .iter()
+            .map(|resource| {
+                let mut block = ResourceBlockAst {
+                    kind: if blocks.actors.contains(resource) {
+                        ResourceBlockKind::Actor
+                    } else {
+                        ResourceBlockKind::Resource
+                    },
+                    resource: symbol(resource),
+                    roles: vec![],
+                    permissions: vec![],
+                    relations: vec![],
+                    shorthand_rules: vec![],
+                    span: Span::of_term(resource),
+                };
+                for (name, declaration) in blocks.declarations().get(resource).into_iter().flatten()
+                {
+                    let name = string(name);
+                    match declaration {
+                        Declaration::Role => block.roles.push(name),
+                        Declaration::Permission => block.permissions.push(name),
+                        Declaration::Relation(related, kind) => block.relations.push(RelationAst {
+                            name,
+                            related_type: symbol(related),
+                            kind: *kind,
+                        }),
+                    }
+                }
+                block.roles.sort();
+                block.permissions.sort();
+                block.relations.sort_by(|a, b| a.name.cmp(&b.name));
+                let shorthand_rules = blocks.shorthand_rules.get(resource).into_iter().flatten();
+                block.shorthand_rules = shorthand_rules
+                    .map(|rule| ShorthandRuleAst {
+                        head: string(&rule.head),
+                        implier: string(&rule.body.0),
+                        relation: rule.body.1.as_ref().map(|(_, relation)| string(relation)),
+                        span: Span::of_term(&rule.head),
+                    })
+                    .collect();
+                block
+            })
+            .collect::<Vec<_>>();
+        resource_blocks.sort_by(|a, b| a.resource.cmp(&b.resource));
+
+        Self {
+            rules,
+            rule_types,
+            resource_blocks,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::polar::Polar;
+    use crate::sources::Source;
+    use crate::visitor::{walk_term, Visitor};
+
+    #[test]
+    fn test_introspect_policy() {
+        let polar = Polar::new();
+        polar.register_constant(sym!("User"), term!(true)).unwrap();
+        polar.register_constant(sym!("Org"), term!(true)).unwrap();
+        let src = r#"actor User {}
+resource Org {
+  roles = ["member", "owner"];
+  permissions = ["read"];
+  "read" if "member";
+  "member" if "owner";
+}
+allow(actor, action, resource) if has_permission(actor, action, resource);
+has_role(_: User, "owner", _: Org);"#;
+        polar
+            .load(vec![Source::new_with_name("policy.polar", src)])
+            .unwrap();
+
+        let ast = polar.introspect();
+
+        let names = ast
+            .rules
+            .iter()
+            .map(|r| r.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec!["allow", "has_permission", "has_role", "has_role"]
+        );
+        let allow = &ast.rules[0];
+        assert_eq!(
+            allow.span,
+            Some(Span {
+                filename: Some("policy.polar".to_owned()),
+                start: 133,
+                end: 163,
+                start_line: 8,
+                start_column: 1,
+                end_line: 8,
+                end_column: 31,
+            })
+        );
+        assert!(ast
+            .rule_types
+            .iter()
+            .any(|t| t.name.as_str() == "has_role" && t.required));
+
+        let kinds = ast
+            .resource_blocks
+            .iter()
+            .map(|b| (b.resource.as_str(), b.kind))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            vec![
+                ("Org", ResourceBlockKind::Resource),
+                ("User", ResourceBlockKind::Actor)
+            ]
+        );
+        let org = &ast.resource_blocks[0];
+        assert_eq!(org.roles, vec!["member", "owner"]);
+        assert_eq!(org.permissions, vec!["read"]);
+        assert_eq!(org.shorthand_rules[1].head, "member");
+        assert_eq!(org.shorthand_rules[1].implier, "owner");
+        assert_eq!(org.span.as_ref().unwrap().start_line, 2);
+
+        // Bodies can be walked with the public visitor, and their terms located in the source.
+        struct Calls(Vec<(Symbol, Option<Span>)>);
+        impl Visitor for Calls {
+            fn visit_term(&mut self, term: &Term) {
+                if let Value::Call(call) = term.value() {
+                    self.0.push((call.name.clone(), Span::of_term(term)));
+                }
+                walk_term(self, term)
+            }
+        }
+        let mut calls = Calls(vec![]);
+        calls.visit_term(&allow.body);
+        assert_eq!(calls.0.len(), 1);
+        assert_eq!(calls.0[0].0, sym!("has_permission"));
+        assert_eq!(calls.0[0].1.as_ref().unwrap().start_column, 35);
+    }
+}
@@ -5,6 +5,7 @@ use std::sync::atomic::{AtomicU64, Ordering};
 
 use crate::bindings::{BindingManager, Bsp, FollowerId, VariableState};
 use crate::counter::Counter;
+use crate::debug_protocol::DebugRequest;
 use crate::error::{PolarError, PolarResult};
 use crate::events::QueryEvent;
 use crate::kb::Bindings;
@@ -245,6 +246,10 @@ impl Runnable for Inverter {
         self.vm.debug_command(command)
     }
 
+    fn debug_request(&mut self, request: DebugRequest) -> PolarResult<()> {
+        self.vm.debug_request(request)
+    }
+
     fn clone_runnable(&self) -> Box<dyn Runnable> {
         Box::new(self.clone())
     }
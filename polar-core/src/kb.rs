@@ -1,29 +1,49 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::sync::Arc;
 
 pub use super::bindings::Bindings;
+use super::builtins::{Builtin, BuiltinPredicate};
+#[cfg(feature = "bundle")]
+use super::bundle::{self, BundledPolicy};
 use super::constants::Constants;
 use super::counter::Counter;
 use super::diagnostic::Diagnostic;
 use super::error::{invalid_state, PolarError, PolarResult, RuntimeError, ValidationError};
-use super::resource_block::{ResourceBlocks, ACTOR_UNION_NAME, RESOURCE_UNION_NAME};
+use super::folder::Folder;
+use super::inline_query::InlineQuery;
+use super::interner::Interner;
+use super::introspection::PolicyAst;
+use super::parser;
+use super::reachable::is_builtin_predicate;
+#[cfg(feature = "bundle")]
+use super::resource_block::ShorthandRule;
+use super::resource_block::{
+    resource_block_from_productions, transitive_relation_rules, ResourceBlocks, ACTOR_UNION_NAME,
+    DIRECT_RELATION_RULE_NAME, RESOURCE_UNION_NAME,
+};
+use super::rewrites::{optimize_rule, rewrite_rule, OptimizationLevel};
 use super::rules::*;
+use super::sources::{Context, Source};
+use super::stats::KnowledgeBaseStats;
 use super::terms::*;
-use super::validations::check_undefined_rule_calls;
+use super::validations::{
+    check_ambiguous_precedence, check_private_rule_calls, check_singletons,
+    check_undefined_rule_calls,
+};
+use super::warning::PolarWarning;
 
 enum RuleParamMatch {
     True,
     False(String),
 }
 
-#[cfg(test)]
 impl RuleParamMatch {
     fn is_true(&self) -> bool {
         matches!(self, RuleParamMatch::True)
     }
 }
 
-#[derive(Default)]
+#[derive(Clone, Default)]
 pub struct KnowledgeBase {
     /// A map of bindings: variable name → value. The VM uses a stack internally,
     /// but can translate to and from this type.
@@ -31,19 +51,85 @@ pub struct KnowledgeBase {
     /// Map of class name -> MRO list where the MRO list is a list of class instance IDs
     pub mro: HashMap<Symbol, Vec<u64>>,
 
+    // The loaded policy is shared between copies of the KB until one of them changes it, so that
+    // registering a constant or class doesn't copy every rule.
     /// Map from contents to filename for files loaded into the KB.
-    loaded_content: HashMap<String, String>,
-
-    rules: HashMap<Symbol, GenericRule>,
-    rule_types: RuleTypes,
+    loaded_content: Arc<HashMap<String, String>>,
+    /// Filenames and contents of the sources that make up the currently loaded policy.
+    loaded_sources: Arc<Vec<(Option<String>, String)>>,
+
+    rules: Arc<HashMap<Symbol, GenericRule>>,
+    rule_types: Arc<RuleTypes>,
+    /// Rules annotated with `@deprecated`, by name and arity.
+    deprecated_rules: HashMap<(Symbol, usize), Deprecation>,
+    /// Rules annotated with `@private`, by name and arity.
+    private_rules: HashMap<(Symbol, usize), Rule>,
     /// For symbols returned from gensym.
     gensym_counter: Counter,
     /// For call IDs, instance IDs, symbols, etc.
     id_counter: Counter,
-    pub inline_queries: Vec<Term>,
+    pub inline_queries: Vec<InlineQuery>,
 
     /// Resource block bookkeeping.
     pub resource_blocks: ResourceBlocks,
+    /// Union types declared with `type Name = A | B;`, by name. Members are class names.
+    unions: HashMap<Symbol, HashSet<Term>>,
+    /// Predicates implemented by the host, by name. Kept when rules are cleared.
+    builtins: HashMap<Symbol, Builtin>,
+    /// Names of host methods whose calls have side effects.
+    effectful_methods: HashSet<Symbol>,
+    /// Names of host methods that can return different results for the same arguments.
+    nondeterministic_methods: HashSet<Symbol>,
+    /// Shares the names of the symbols in loaded rules.
+    interner: Interner,
+    /// How much to optimize rules as they're loaded.
+    optimization_level: OptimizationLevel,
+}
+
+/// The name of the class or union that `term` specializes on.
+fn specializer_tag(term: &Term) -> Option<&Symbol> {
+    match term.value() {
+        Value::Pattern(Pattern::Instance(InstanceLiteral { tag, .. })) | Value::Variable(tag) => {
+            Some(tag)
+        }
+        _ => None,
+    }
+}
+
+/// Generate a new symbol numbered by `counter`. See `KnowledgeBase::gensym`.
+pub(crate) fn gensym_from(counter: &Counter, prefix: &str) -> Symbol {
+    let next = counter.next();
+    let name = match prefix {
+        "_" => format!("_{}", next),
+        _ => format!("_{}_{}", prefix, next),
+    };
+    Symbol::new(&name)
+}
+
+/// True if `context` is in the source named `filename`.
+fn is_from(context: Option<&Context>, filename: &str) -> bool {
+    matches!(context, Some(context) if context.source.filename.as_deref() == Some(filename))
+}
+
+fn file_loading_error(filename: &str, contents: &str, msg: String) -> PolarError {
+    ValidationError::FileLoading {
+        filename: filename.into(),
+        contents: contents.into(),
+        msg,
+    }
+    .into()
+}
+
+/// Split `diagnostics` into the first error, if there is one, and warnings.
+pub(crate) fn into_warnings(diagnostics: Vec<Diagnostic>) -> PolarResult<Vec<PolarWarning>> {
+    let mut warnings = vec![];
+    for diagnostic in diagnostics {
+        match diagnostic {
+            Diagnostic::Error(e) => return Err(e),
+            Diagnostic::Warning(w) => warnings.push(w),
+        }
+    }
+    Ok(warnings)
 }
 
 impl KnowledgeBase {
@@ -51,6 +137,26 @@ impl KnowledgeBase {
         Self::default()
     }
 
+    /// Create an empty KB for a namespace's policy, which extends the policy loaded into `self`.
+    ///
+    /// The overlay gets a copy of the registered constants and classes, union types and rule
+    /// types of `self` as they are now, and draws IDs from the same counters.
+    pub fn new_overlay(&self) -> Self {
+        Self {
+            constants: self.constants.clone(),
+            mro: self.mro.clone(),
+            rule_types: self.rule_types.clone(),
+            gensym_counter: self.gensym_counter.clone(),
+            id_counter: self.id_counter.clone(),
+            unions: self.unions.clone(),
+            builtins: self.builtins.clone(),
+            effectful_methods: self.effectful_methods.clone(),
+            nondeterministic_methods: self.nondeterministic_methods.clone(),
+            optimization_level: self.optimization_level,
+            ..Self::default()
+        }
+    }
+
     /// Return a monotonically increasing integer ID.
     ///
     /// Wraps around at 52 bits of precision so that it can be safely
@@ -73,25 +179,66 @@ impl KnowledgeBase {
 
     /// Generate a new symbol.
     pub fn gensym(&self, prefix: &str) -> Symbol {
-        let next = self.gensym_counter.next();
-        Symbol(format!("{}{}", Self::temp_prefix(prefix), next))
+        gensym_from(&self.gensym_counter, prefix)
+    }
+
+    /// The counter that `gensym` numbers symbols with.
+    pub fn gensym_counter(&self) -> Counter {
+        self.gensym_counter.clone()
     }
 
     /// Add a generic rule to the knowledge base.
     #[cfg(test)]
     pub fn add_generic_rule(&mut self, rule: GenericRule) {
-        self.rules.insert(rule.name.clone(), rule);
+        Arc::make_mut(&mut self.rules).insert(rule.name.clone(), rule);
     }
 
     pub fn add_rule(&mut self, rule: Rule) {
-        let generic_rule = self
-            .rules
+        let rule = self.interner.fold_rule(rule);
+        let generic_rule = Arc::make_mut(&mut self.rules)
             .entry(rule.name.clone())
             .or_insert_with(|| GenericRule::new(rule.name.clone(), vec![]));
         generic_rule.add_rule(Arc::new(rule));
     }
 
+    /// Mark rules named `rule.name` with the same arity as `rule` as deprecated. The first
+    /// annotation wins.
+    pub fn deprecate_rule(&mut self, rule: &Rule, message: Term) {
+        self.deprecated_rules
+            .entry((rule.name.clone(), rule.params.len()))
+            .or_insert_with(|| Deprecation {
+                message,
+                rule: rule.clone(),
+            });
+    }
+
+    pub fn get_deprecation(&self, name: &Symbol, arity: usize) -> Option<&Deprecation> {
+        self.deprecated_rules.get(&(name.clone(), arity))
+    }
+
+    /// Mark rules named `rule.name` with the same arity as `rule` as callable only from the
+    /// source `rule` is in. The first annotation wins.
+    pub fn make_rule_private(&mut self, rule: &Rule) {
+        self.private_rules
+            .entry((rule.name.clone(), rule.params.len()))
+            .or_insert_with(|| rule.clone());
+    }
+
+    /// The annotated rule, if rules named `name` with `arity` parameters are private.
+    pub fn get_private_rule(&self, name: &Symbol, arity: usize) -> Option<&Rule> {
+        self.private_rules.get(&(name.clone(), arity))
+    }
+
     pub fn validate_rules(&self) -> Vec<Diagnostic> {
+        self.validate_rules_named(|_| true)
+    }
+
+    /// Like `validate_rules`, but only check the rules whose names satisfy `check_rule_type`
+    /// against their rule types.
+    fn validate_rules_named<F>(&self, check_rule_type: F) -> Vec<Diagnostic>
+    where
+        F: Fn(&Symbol) -> bool,
+    {
         // Prior to #1310 these validations were not order dependent due to the
         // use of static default rule types.
         // Now that rule types are dynamically generated based on policy
@@ -100,19 +247,150 @@ impl KnowledgeBase {
         // errors
         let mut diagnostics = vec![];
 
-        if let Err(e) = self.validate_rule_types() {
+        if let Err(e) = self.validate_rule_types(check_rule_type) {
             diagnostics.push(e.into());
         }
 
         diagnostics.append(&mut check_undefined_rule_calls(self));
+        diagnostics.append(&mut check_private_rule_calls(self));
 
         diagnostics
     }
 
+    /// Validate that no rule or rule type has the name of a built-in predicate.
+    fn validate_builtin_names(&self) -> PolarResult<()> {
+        for (name, builtin) in &self.builtins {
+            if let Some(rule_type) = self.rule_types.get(name).and_then(|types| types.first()) {
+                return Err(ValidationError::InvalidRuleType {
+                    rule_type: rule_type.clone(),
+                    msg: format!("{} is a built-in predicate registered by the host", builtin),
+                }
+                .into());
+            }
+            if let Some(generic_rule) = self.rules.get(name) {
+                if let Some(rule) = generic_rule.rules.values().next() {
+                    return Err(ValidationError::InvalidRule {
+                        rule: Rule::clone(rule),
+                        msg: format!("{} is a built-in predicate registered by the host", builtin),
+                    }
+                    .into());
+                }
+            }
+        }
+        Ok(())
+    }
+
     /// Validate that all rules loaded into the knowledge base are valid based on rule types.
-    fn validate_rule_types(&self) -> PolarResult<()> {
+    fn validate_rule_types<F>(&self, check_rule_type: F) -> PolarResult<()>
+    where
+        F: Fn(&Symbol) -> bool,
+    {
+        self.validate_builtin_names()?;
+        self.validate_rule_shapes(check_rule_type)?;
+
+        // For every rule type that is *required*, see that there is at least one corresponding
+        // implementation.
+        for rule_type in self.rule_types.required_rule_types() {
+            if let Some(GenericRule { rules, .. }) = self.rules.get(&rule_type.name) {
+                let mut found_match = false;
+                for rule in rules.values() {
+                    found_match = self
+                        .rule_params_match(rule.as_ref(), rule_type)
+                        .map(|r| matches!(r, RuleParamMatch::True))?;
+                    if found_match {
+                        break;
+                    }
+                }
+                if !found_match {
+                    let rule_type = rule_type.clone();
+                    return Err(ValidationError::MissingRequiredRule { rule_type }.into());
+                }
+            } else {
+                let rule_type = rule_type.clone();
+                return Err(ValidationError::MissingRequiredRule { rule_type }.into());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Return true if every class that `rule`'s union specializers stand for matches one of
+    /// `types`, even though no single rule type covers a whole union.
+    fn union_members_match(&self, rule: &Rule, types: &[Rule]) -> PolarResult<bool> {
+        for expanded in self.expand_union_specializers(rule) {
+            let mut found_match = false;
+            for rule_type in types {
+                if self.rule_params_match(&expanded, rule_type)?.is_true() {
+                    found_match = true;
+                    break;
+                }
+            }
+            if !found_match {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// One copy of `rule` for each combination of members of the declared unions it's
+    /// specialized on, with each union specializer replaced by the member.
+    fn expand_union_specializers(&self, rule: &Rule) -> Vec<Rule> {
+        let mut expanded = vec![rule.clone()];
+        for (index, param) in rule.params.iter().enumerate() {
+            let (specializer, fields, members) = match &param.specializer {
+                Some(specializer) => match specializer.value() {
+                    Value::Pattern(Pattern::Instance(InstanceLiteral { tag, fields })) => {
+                        match self.unions.get(tag) {
+                            Some(members) => (specializer, fields, members),
+                            None => continue,
+                        }
+                    }
+                    _ => continue,
+                },
+                None => continue,
+            };
+            let mut members = members
+                .iter()
+                .filter_map(|member| member.as_symbol().ok())
+                .collect::<Vec<_>>();
+            members.sort();
+            expanded = expanded
+                .into_iter()
+                .flat_map(|rule| {
+                    members.iter().map(move |&tag| {
+                        let mut rule = rule.clone();
+                        let instance = InstanceLiteral {
+                            tag: tag.clone(),
+                            fields: fields.clone(),
+                        };
+                        rule.params[index].specializer = Some(
+                            specializer
+                                .clone_with_value(Value::Pattern(Pattern::Instance(instance))),
+                        );
+                        rule
+                    })
+                })
+                .collect();
+        }
+        expanded
+    }
+
+    /// Check the rules of a namespace overlay against the rule types it shares with the policy
+    /// it extends. See [`KnowledgeBase::new_overlay`].
+    ///
+    /// Required rules aren't checked, since the policy the overlay extends may define them.
+    pub(crate) fn validate_overlay_rules(&self) -> PolarResult<()> {
+        self.validate_rule_shapes(|_| true)
+    }
+
+    /// Validate that the rules whose names satisfy `check_rule_type` match one of their rule types.
+    fn validate_rule_shapes<F>(&self, check_rule_type: F) -> PolarResult<()>
+    where
+        F: Fn(&Symbol) -> bool,
+    {
         // For every rule, if there *is* a rule type, check that the rule matches the rule type.
-        for (rule_name, generic_rule) in &self.rules {
+        let rules = self.rules.iter().filter(|(name, _)| check_rule_type(name));
+        for (rule_name, generic_rule) in rules {
             if let Some(types) = self.rule_types.get(rule_name) {
                 // If a type with the same name exists, then the parameters must match for each rule
                 for rule in generic_rule.rules.values() {
@@ -135,37 +413,13 @@ impl KnowledgeBase {
                             false
                         }
                     });
-                    if !found_match {
+                    if !found_match && !self.union_members_match(rule, types)? {
                         let rule = Rule::clone(rule);
                         return Err(ValidationError::InvalidRule { rule, msg }.into());
                     }
                 }
             }
         }
-
-        // For every rule type that is *required*, see that there is at least one corresponding
-        // implementation.
-        for rule_type in self.rule_types.required_rule_types() {
-            if let Some(GenericRule { rules, .. }) = self.rules.get(&rule_type.name) {
-                let mut found_match = false;
-                for rule in rules.values() {
-                    found_match = self
-                        .rule_params_match(rule.as_ref(), rule_type)
-                        .map(|r| matches!(r, RuleParamMatch::True))?;
-                    if found_match {
-                        break;
-                    }
-                }
-                if !found_match {
-                    let rule_type = rule_type.clone();
-                    return Err(ValidationError::MissingRequiredRule { rule_type }.into());
-                }
-            } else {
-                let rule_type = rule_type.clone();
-                return Err(ValidationError::MissingRequiredRule { rule_type }.into());
-            }
-        }
-
         Ok(())
     }
 
@@ -248,10 +502,13 @@ impl KnowledgeBase {
                     } else {
                         RuleParamMatch::False(format!("Rule specializer {} on parameter {} did not match rule type specializer {} because the specializer fields did not match.", rule_instance, index, rule_type_instance))
                     }
-                } else if self.is_union(&term!(sym!(&rule_type_instance.tag.0))) {
-                    if self.is_union(&term!(sym!(&rule_instance.tag.0))) {
-                        // If both specializers are the same union, check fields.
-                        if rule_instance.tag == rule_type_instance.tag {
+                } else if self.is_union(&term!(sym!(rule_type_instance.tag.as_str()))) {
+                    let rule_union = term!(sym!(rule_instance.tag.as_str()));
+                    let rule_type_union = term!(sym!(rule_type_instance.tag.as_str()));
+                    if self.is_union(&rule_union) {
+                        // If the rule specializer's union is contained in the rule type
+                        // specializer's union, check fields.
+                        if self.is_subunion(&rule_union, &rule_type_union) {
                             if self.param_fields_match(
                                 &rule_type_instance.fields,
                                 &rule_instance.fields,
@@ -261,16 +518,17 @@ impl KnowledgeBase {
                                 return Ok(RuleParamMatch::False(format!("Rule specializer {} on parameter {} did not match rule type specializer {} because the specializer fields did not match.", rule_instance, index, rule_type_instance)));
                             }
                         } else {
-                            // TODO(gj): revisit when we have unions beyond Actor & Resource. Union
-                            // A matches union B if union A is a member of union B.
                             return Ok(RuleParamMatch::False(format!("Rule specializer {} on parameter {} does not match rule type specializer {}", rule_instance.tag, index, rule_type_instance.tag)));
                         }
                     }
 
-                    let members = self.get_union_members(&term!(sym!(&rule_type_instance.tag.0)));
+                    let members = match self.get_union_members(&rule_type_union) {
+                        Some(members) => members,
+                        None => return invalid_state(format!("{} is not a union", rule_type_union)),
+                    };
                     // If the rule specializer is not a direct member of the union, we still need
                     // to check if it's a subclass of any member of the union.
-                    if !members.contains(&term!(sym!(&rule_instance.tag.0))) {
+                    if !members.contains(&term!(sym!(rule_instance.tag.as_str()))) {
                         let mut success = false;
                         for member in members {
                             // Turn `member` into an `InstanceLiteral` by copying fields from
@@ -287,13 +545,13 @@ impl KnowledgeBase {
                         }
                         if !success {
                             let mut err = format!("Rule specializer {} on parameter {} must be a member of rule type specializer {}", rule_instance.tag,index, rule_type_instance.tag);
-                            if rule_type_instance.tag.0 == ACTOR_UNION_NAME {
+                            if rule_type_instance.tag.as_str() == ACTOR_UNION_NAME {
                                 err.push_str(&format!("
 
 \tPerhaps you meant to add an actor block to the top of your policy, like this:
 
 \t  actor {} {{}}", rule_instance.tag));
-                            } else if rule_type_instance.tag.0 == RESOURCE_UNION_NAME {
+                            } else if rule_type_instance.tag.as_str() == RESOURCE_UNION_NAME {
                                 err.push_str(&format!("
 
 \tPerhaps you meant to add a resource block to your policy, like this:
@@ -309,6 +567,21 @@ impl KnowledgeBase {
                     } else {
                         RuleParamMatch::True
                     }
+                // A declared union matches a class if every member of the union does, e.g., an
+                // alias like `type Id = Repo;` matches `Repo`.
+                } else if self.unions.contains_key(&rule_instance.tag) {
+                    let mut members = self.unions[&rule_instance.tag].iter().filter_map(|member| member.as_symbol().ok()).collect::<Vec<_>>();
+                    members.sort();
+                    for member in members {
+                        let member_instance = InstanceLiteral {
+                            tag: member.clone(),
+                            fields: rule_instance.fields.clone(),
+                        };
+                        if let RuleParamMatch::False(msg) = self.check_rule_instance_is_subclass_of_rule_type_instance(&member_instance, rule_type_instance, index)? {
+                            return Ok(RuleParamMatch::False(format!("Rule specializer {} on parameter {} is a union with a member that doesn't match rule type specializer {}. {}", rule_instance.tag, index, rule_type_instance.tag, msg)));
+                        }
+                    }
+                    RuleParamMatch::True
                 // If tags don't match, then rule specializer must be a subclass of rule type specializer
                 } else {
                     self.check_rule_instance_is_subclass_of_rule_type_instance(rule_instance, rule_type_instance, index)?
@@ -443,9 +716,14 @@ impl KnowledgeBase {
                         Pattern::Instance(InstanceLiteral { .. }) => {
                             let rule_spec = match rule_value {
                                 Value::String(_) => instance!(sym!("String")),
-                                Value::Number(Numeric::Integer(_)) => instance!(sym!("Integer")),
+                                Value::Number(Numeric::Integer(_) | Numeric::BigInteger(_)) => {
+                                    instance!(sym!("Integer"))
+                                }
                                 Value::Number(Numeric::Float(_)) => instance!(sym!("Float")),
+                                Value::Number(Numeric::Decimal(_)) => instance!(sym!("Decimal")),
                                 Value::Boolean(_) => instance!(sym!("Boolean")),
+                                Value::DateTime(_) => instance!(sym!("DateTime")),
+                                Value::Duration(_) => instance!(sym!("Duration")),
                                 Value::List(_) => instance!(sym!("List")),
                                 Value::Dictionary(rule_fields) => {
                                     instance!(sym!("Dictionary"), rule_fields.clone().fields)
@@ -546,10 +824,84 @@ impl KnowledgeBase {
             })
     }
 
+    /// Check the values bound to the arguments of a call to `name` against the rule types for
+    /// `name`. Unbound arguments, and values whose type can't be decided without the host, match
+    /// any parameter. Returns why no rule type matched, or `None` if one did or there are no rule
+    /// types with the call's arity.
+    pub(crate) fn check_call_arg_types(
+        &self,
+        name: &Symbol,
+        args: &[Option<Term>],
+    ) -> PolarResult<Option<String>> {
+        let rule_types = match self.rule_types.get(name) {
+            Some(rule_types) => rule_types,
+            None => return Ok(None),
+        };
+        let mut failure = None;
+        for rule_type in rule_types.iter().filter(|t| t.params.len() == args.len()) {
+            let mut matched = RuleParamMatch::True;
+            for (i, (arg, param)) in args.iter().zip(rule_type.params.iter()).enumerate() {
+                let arg = match arg {
+                    Some(arg) => arg,
+                    None => continue,
+                };
+                matched = match arg.value() {
+                    Value::ExternalInstance(ExternalInstance {
+                        class_id: Some(class_id),
+                        ..
+                    }) => match param.specializer.as_ref().map(Term::value) {
+                        Some(Value::Pattern(Pattern::Instance(InstanceLiteral {
+                            tag,
+                            fields,
+                        }))) if fields.fields.is_empty()
+                            && self.get_symbol_for_class_id(class_id).is_some()
+                            && self.get_class_id_for_symbol(tag).is_some()
+                            && !self.class_id_isa(class_id, tag) =>
+                        {
+                            RuleParamMatch::False(format!(
+                                "Invalid parameter {}. Rule type expected {}, got {}.",
+                                i + 1,
+                                tag,
+                                arg
+                            ))
+                        }
+                        _ => RuleParamMatch::True,
+                    },
+                    Value::String(_)
+                    | Value::Number(_)
+                    | Value::Boolean(_)
+                    | Value::DateTime(_)
+                    | Value::Duration(_)
+                    | Value::List(_)
+                    | Value::Dictionary(_) => {
+                        let arg = Parameter {
+                            parameter: arg.clone(),
+                            specializer: None,
+                        };
+                        self.check_param(i + 1, &arg, param, rule_type)?
+                    }
+                    _ => RuleParamMatch::True,
+                };
+                if let RuleParamMatch::False(_) = matched {
+                    break;
+                }
+            }
+            match matched {
+                RuleParamMatch::True => return Ok(None),
+                RuleParamMatch::False(msg) => failure = Some(msg),
+            }
+        }
+        Ok(failure)
+    }
+
     pub fn get_rules(&self) -> &HashMap<Symbol, GenericRule> {
         &self.rules
     }
 
+    pub(crate) fn rule_types(&self) -> &RuleTypes {
+        &self.rule_types
+    }
+
     #[cfg(test)]
     pub fn get_rule_types(&self, name: &Symbol) -> Option<&Vec<Rule>> {
         self.rule_types.get(name)
@@ -559,8 +911,20 @@ impl KnowledgeBase {
         self.rules.get(name)
     }
 
+    /// Remove `rule` from the generic rule it belongs to, as a session does to commit a retracted
+    /// fact. Returns false if the KB doesn't have it.
+    ///
+    /// The generic rule is kept even if it becomes empty, so queries for it fail rather than
+    /// raising an error.
+    pub fn remove_rule(&mut self, rule: &Rule) -> bool {
+        match Arc::make_mut(&mut self.rules).get_mut(&rule.name) {
+            Some(generic_rule) => generic_rule.remove_rule(rule),
+            None => false,
+        }
+    }
+
     pub fn add_rule_type(&mut self, rule_type: Rule) {
-        self.rule_types.add(rule_type);
+        Arc::make_mut(&mut self.rule_types).add(rule_type);
     }
 
     /// Define a constant variable.
@@ -568,7 +932,7 @@ impl KnowledgeBase {
     /// Error on attempts to register the "union" types (Actor & Resource) since those types have
     /// special meaning in policies that use resource blocks.
     pub fn register_constant(&mut self, name: Symbol, value: Term) -> PolarResult<()> {
-        if name.0 == ACTOR_UNION_NAME || name.0 == RESOURCE_UNION_NAME {
+        if name.as_str() == ACTOR_UNION_NAME || name.as_str() == RESOURCE_UNION_NAME {
             return Err(RuntimeError::InvalidRegistration {
                 msg: format!("'{}' is a built-in specializer.", name),
                 sym: name,
@@ -598,6 +962,77 @@ impl KnowledgeBase {
         Ok(())
     }
 
+    /// Register a predicate implemented by the host. See
+    /// [`Polar::register_builtin`](crate::polar::Polar::register_builtin).
+    ///
+    /// Error if the name is taken by another built-in or by a rule or rule type.
+    pub fn register_builtin<P: BuiltinPredicate + 'static>(
+        &mut self,
+        name: Symbol,
+        arity: usize,
+        predicate: P,
+    ) -> PolarResult<()> {
+        let taken_by = if is_builtin_predicate(&name) {
+            Some("a built-in predicate".to_owned())
+        } else if let Some(builtin) = self.builtins.get(&name) {
+            Some(format!("the built-in predicate {}", builtin))
+        } else if self.rules.contains_key(&name) {
+            Some("a rule".to_owned())
+        } else if self.rule_types.get(&name).is_some() {
+            Some("a rule type".to_owned())
+        } else {
+            None
+        };
+        if let Some(taken_by) = taken_by {
+            return Err(RuntimeError::InvalidRegistration {
+                msg: format!("'{}' is already the name of {}.", name, taken_by),
+                sym: name,
+            }
+            .into());
+        }
+        let builtin = Builtin::new(name.clone(), arity, predicate);
+        self.builtins.insert(name, builtin);
+        Ok(())
+    }
+
+    pub fn get_builtin(&self, name: &Symbol) -> Option<&Builtin> {
+        self.builtins.get(name)
+    }
+
+    pub(crate) fn builtins(&self) -> &HashMap<Symbol, Builtin> {
+        &self.builtins
+    }
+
+    /// Define a constant whose value the host supplies the first time a query uses it. See
+    /// [`Polar::register_lazy_constant`](crate::polar::Polar::register_lazy_constant).
+    pub fn register_lazy_constant(&mut self, name: Symbol) -> PolarResult<()> {
+        if name.as_str() == ACTOR_UNION_NAME || name.as_str() == RESOURCE_UNION_NAME {
+            return Err(RuntimeError::InvalidRegistration {
+                msg: format!("'{}' is a built-in specializer.", name),
+                sym: name,
+            }
+            .into());
+        }
+        self.constants.insert_lazy(name);
+        Ok(())
+    }
+
+    /// Cache the value the host supplied for a lazy constant, unless the constant has since
+    /// been registered again. The cache is shared with other snapshots of the KB.
+    pub(crate) fn resolve_lazy_constant(&self, name: Symbol, value: Term) {
+        self.constants.resolve_lazy(name, value)
+    }
+
+    /// The cached value of a lazy constant, if a query has fetched it already.
+    pub(crate) fn lazy_constant_value(&self, name: &Symbol) -> Option<Term> {
+        self.constants.get_lazy(name)
+    }
+
+    /// Lazy constants, including ones whose value has been cached.
+    pub(crate) fn lazy_constants(&self) -> &BTreeSet<Symbol> {
+        self.constants.lazy()
+    }
+
     /// Return true if a constant with the given name has been defined.
     pub fn is_constant(&self, name: &Symbol) -> bool {
         self.constants.contains_key(name)
@@ -616,6 +1051,43 @@ impl KnowledgeBase {
         self.constants.get_class_id_for_symbol(symbol)
     }
 
+    /// Whether an instance of the class with id `class_id` is an instance of the class
+    /// registered as `tag`, according to the registered MROs.
+    pub(crate) fn class_id_isa(&self, class_id: &u64, tag: &Symbol) -> bool {
+        match (
+            self.get_class_id_for_symbol(tag),
+            self.get_symbol_for_class_id(class_id),
+        ) {
+            (Some(tag_id), Some(class)) => {
+                matches!(self.mro.get(class), Some(mro) if mro.contains(tag_id))
+            }
+            // Instances of unregistered classes can't match registered ones.
+            _ => false,
+        }
+    }
+
+    /// The rules of `generic_rule` that may apply to `args`.
+    ///
+    /// When the first argument is an instance of a known class, rules whose first parameter is
+    /// specialized on a class it isn't an instance of are skipped without being unified.
+    #[allow(clippy::ptr_arg)]
+    pub(crate) fn get_applicable_rules(
+        &self,
+        generic_rule: &GenericRule,
+        args: &TermList,
+    ) -> Rules {
+        match args.first().map(Term::value) {
+            Some(Value::ExternalInstance(ExternalInstance {
+                class_id: Some(class_id),
+                ..
+            })) => generic_rule.dispatch(args, |tag| {
+                let tag_term = Term::from(Value::Variable(tag.clone()));
+                self.is_union(&tag_term) || self.class_id_isa(class_id, tag)
+            }),
+            _ => generic_rule.get_applicable_rules(args),
+        }
+    }
+
     // TODO(gj): currently no way to distinguish classes from other registered constants in the
     // core, so it's up to callers to ensure this is only called with terms we expect to be
     // registered as a _class_.
@@ -639,18 +1111,196 @@ impl KnowledgeBase {
         Ok(())
     }
 
+    /// Record that calling the host method `name` has side effects.
+    ///
+    /// Methods are identified by name alone, since the class of a method's receiver usually
+    /// isn't known until a query runs.
+    pub fn register_effectful_method(&mut self, name: Symbol) {
+        self.effectful_methods.insert(name);
+    }
+
+    pub fn is_effectful_method(&self, name: &Symbol) -> bool {
+        self.effectful_methods.contains(name)
+    }
+
+    /// Record that calling the host method `name` can return different results for the same
+    /// arguments, so queries don't memoize its results.
+    pub fn register_nondeterministic_method(&mut self, name: Symbol) {
+        self.nondeterministic_methods.insert(name);
+    }
+
+    pub fn is_nondeterministic_method(&self, name: &Symbol) -> bool {
+        self.nondeterministic_methods.contains(name)
+    }
+
+    /// Set how much to optimize rules loaded from now on.
+    pub fn set_optimization_level(&mut self, level: OptimizationLevel) {
+        self.optimization_level = level;
+    }
+
+    pub fn optimization_level(&self) -> OptimizationLevel {
+        self.optimization_level
+    }
+
     pub fn clear_rules(&mut self) {
-        self.rules.clear();
-        self.rule_types.reset();
+        self.rules = Default::default();
+        self.rule_types = Default::default();
+        self.deprecated_rules.clear();
+        self.private_rules.clear();
         self.inline_queries.clear();
-        self.loaded_content.clear();
+        self.loaded_content = Default::default();
+        self.loaded_sources = Default::default();
         self.resource_blocks.clear();
+        self.unions.clear();
+        self.interner = Interner::default();
+    }
+
+    /// Serialize the loaded policy to a bundle, which [`KnowledgeBase::load_bundle`] can load
+    /// without parsing or validating it again.
+    #[cfg(feature = "bundle")]
+    pub fn save_bundle(&self) -> PolarResult<Vec<u8>> {
+        let mut generic_rules = self.rules.values().collect::<Vec<_>>();
+        generic_rules.sort_by(|a, b| a.name.cmp(&b.name));
+        let rules = generic_rules
+            .into_iter()
+            .flat_map(|generic_rule| {
+                let mut rules = generic_rule.rules.iter().collect::<Vec<_>>();
+                rules.sort_by_key(|(id, _)| **id);
+                rules.into_iter().map(|(_, rule)| rule.as_ref().clone())
+            })
+            .collect();
+        let blocks = &self.resource_blocks;
+        let policy = BundledPolicy {
+            loaded_sources: self.loaded_sources.to_vec(),
+            loaded_content: self
+                .loaded_content
+                .iter()
+                .map(|(content, filename)| (content.clone(), filename.clone()))
+                .collect(),
+            rules,
+            rule_types: self.rule_types.iter().cloned().collect(),
+            deprecated_rules: self
+                .deprecated_rules
+                .iter()
+                .map(|((name, arity), deprecation)| {
+                    let Deprecation { message, rule } = deprecation.clone();
+                    (name.clone(), *arity, message, rule)
+                })
+                .collect(),
+            private_rules: self.private_rules.values().cloned().collect(),
+            inline_queries: self.inline_queries.clone(),
+            declarations: blocks
+                .declarations
+                .iter()
+                .map(|(resource, declarations)| {
+                    let declarations = declarations.clone().into_iter().collect();
+                    (resource.clone(), declarations)
+                })
+                .collect(),
+            shorthand_rules: blocks
+                .shorthand_rules
+                .iter()
+                .map(|(resource, rules)| {
+                    let rules = rules
+                        .iter()
+                        .map(|ShorthandRule { head, body }| {
+                            (head.clone(), body.0.clone(), body.1.clone())
+                        })
+                        .collect();
+                    (resource.clone(), rules)
+                })
+                .collect(),
+            actors: blocks.actors.iter().cloned().collect(),
+            resources: blocks.resources.iter().cloned().collect(),
+            unions: self
+                .unions
+                .iter()
+                .map(|(name, members)| (name.clone(), members.iter().cloned().collect()))
+                .collect(),
+            next_gensym: self.gensym_counter.peek(),
+        };
+        bundle::save(policy)
+    }
+
+    /// Load the policy in `bundle`, replacing the loaded policy.
+    ///
+    /// The bundle must have been saved by this version of Polar after loading exactly
+    /// `sources`. Registered constants and classes are kept, and aren't checked against the
+    /// policy.
+    #[cfg(feature = "bundle")]
+    pub fn load_bundle(&mut self, bundle: &[u8], sources: &[Source]) -> PolarResult<()> {
+        let policy = bundle::load(bundle, sources)?;
+        self.clear_rules();
+        self.loaded_sources = Arc::new(policy.loaded_sources);
+        self.loaded_content = Arc::new(policy.loaded_content.into_iter().collect());
+        for rule in policy.rules {
+            self.add_rule(rule);
+        }
+        self.rule_types = Arc::new(policy.rule_types.into_iter().collect());
+        self.deprecated_rules = policy
+            .deprecated_rules
+            .into_iter()
+            .map(|(name, arity, message, rule)| ((name, arity), Deprecation { message, rule }))
+            .collect();
+        for rule in &policy.private_rules {
+            self.make_rule_private(rule);
+        }
+        self.inline_queries = policy.inline_queries;
+        let blocks = &mut self.resource_blocks;
+        blocks.declarations = policy
+            .declarations
+            .into_iter()
+            .map(|(resource, declarations)| (resource, declarations.into_iter().collect()))
+            .collect();
+        blocks.shorthand_rules = policy
+            .shorthand_rules
+            .into_iter()
+            .map(|(resource, rules)| {
+                let rules = rules
+                    .into_iter()
+                    .map(|(head, implier, relation)| ShorthandRule {
+                        head,
+                        body: (implier, relation),
+                    })
+                    .collect();
+                (resource, rules)
+            })
+            .collect();
+        blocks.actors = policy.actors.into_iter().collect();
+        blocks.resources = policy.resources.into_iter().collect();
+        self.unions = policy
+            .unions
+            .into_iter()
+            .map(|(name, members)| (name, members.into_iter().collect()))
+            .collect();
+        self.gensym_counter = Counter::with_start(policy.next_gensym);
+        Ok(())
+    }
+
+    /// Return true if `sources` are exactly the sources of the currently loaded policy.
+    pub(crate) fn is_loaded(&self, sources: &[Source]) -> bool {
+        !self.loaded_sources.is_empty()
+            && self.loaded_sources.len() == sources.len()
+            && self
+                .loaded_sources
+                .iter()
+                .zip(sources)
+                .all(|((filename, src), source)| filename == &source.filename && src == &source.src)
+    }
+
+    pub(crate) fn set_loaded_sources(&mut self, sources: &[Source]) {
+        self.loaded_sources = Arc::new(
+            sources
+                .iter()
+                .map(|source| (source.filename.clone(), source.src.clone()))
+                .collect(),
+        );
     }
 
     // TODO(gj): Remove this fn & `FileLoading` error variant. These checks don't spark joy.
     pub(crate) fn add_source(&mut self, filename: &str, contents: &str) -> PolarResult<()> {
         let seen_filename = self.loaded_content.values().any(|name| name == filename);
-        match self.loaded_content.insert(contents.into(), filename.into()) {
+        match Arc::make_mut(&mut self.loaded_content).insert(contents.into(), filename.into()) {
             Some(other_file) if other_file == filename => {
                 Err(format!("File {} has already been loaded.", filename))
             }
@@ -664,14 +1314,204 @@ impl KnowledgeBase {
             )),
             _ => Ok(()),
         }
-        .map_err(|msg| {
-            ValidationError::FileLoading {
-                filename: filename.into(),
-                contents: contents.into(),
-                msg,
+        .map_err(|msg| file_loading_error(filename, contents, msg))
+    }
+
+    /// Parse `source` and add its contents to the KB, returning diagnostics for the rules in it.
+    ///
+    /// Policy-wide steps like rewriting shorthand rules and validating rules against rule types
+    /// are left to the caller.
+    pub(crate) fn load_source(&mut self, source: Source) -> PolarResult<Vec<Diagnostic>> {
+        if let Some(ref filename) = source.filename {
+            self.add_source(filename, &source.src)?;
+        }
+        // TODO(gj): we still bomb out at the first ParseError.
+        let mut lines = parser::parse_lines(source)?;
+        // Declare unions before anything else so that they can be used as specializers
+        // regardless of where in the file they're declared.
+        lines.sort_by_key(|line| !matches!(line, parser::Line::UnionType { .. }));
+        lines.reverse();
+        let mut diagnostics = vec![];
+        while let Some(line) = lines.pop() {
+            match line {
+                parser::Line::Rule(rule) => {
+                    diagnostics.append(&mut check_singletons(&rule, self));
+                    diagnostics.append(&mut check_ambiguous_precedence(&rule));
+                    let rule = rewrite_rule(rule, self);
+                    let rule = optimize_rule(rule, self);
+                    self.add_rule(rule);
+                }
+                parser::Line::DeprecatedRule { message, rule } => {
+                    self.deprecate_rule(&rule, message);
+                    lines.push(parser::Line::Rule(rule));
+                }
+                parser::Line::PrivateRule(rule) => {
+                    self.make_rule_private(&rule);
+                    lines.push(parser::Line::Rule(rule));
+                }
+                parser::Line::Query { term, expected } => {
+                    self.inline_queries.push(InlineQuery::new(term, expected)?);
+                }
+                parser::Line::RuleType(rule_type) => {
+                    // make sure rule_type doesn't have anything that needs to be rewritten in the head
+                    let rule_type = rewrite_rule(rule_type, self);
+                    if !matches!(
+                        rule_type.body.value(),
+                        Value::Expression(
+                            Operation {
+                                operator: Operator::And,
+                                args
+                            }
+                        ) if args.is_empty()
+                    ) {
+                        diagnostics.push(Diagnostic::Error(
+                            ValidationError::InvalidRuleType {
+                                rule_type,
+                                msg: "Rule types cannot contain dot lookups.".into(),
+                            }
+                            .into(),
+                        ));
+                    } else {
+                        self.add_rule_type(rule_type);
+                    }
+                }
+                parser::Line::UnionType { name, members } => {
+                    if let Err(e) = self.add_union(name, members) {
+                        diagnostics.push(Diagnostic::Error(e));
+                    }
+                }
+                parser::Line::ResourceBlock {
+                    keyword,
+                    resource,
+                    productions,
+                } => {
+                    let (block, mut errors) =
+                        resource_block_from_productions(keyword, resource, productions);
+                    errors.append(&mut block.add_to_kb(self));
+                    diagnostics.extend(errors.into_iter().map(Into::into));
+                }
             }
-            .into()
-        })
+        }
+        Ok(diagnostics)
+    }
+
+    /// Load `source` into a KB that may already hold a policy, replacing the previously loaded
+    /// source with the same filename, if any.
+    ///
+    /// Only the new source's rules are checked for singletons and against their rule types, so
+    /// reloading one file of a large policy doesn't revalidate the rest. Sources that declare
+    /// resource blocks or union types affect the whole policy, so they're rejected and have to
+    /// be loaded with the rest of it instead. Other sources can still be loaded incrementally
+    /// alongside them.
+    ///
+    /// If the source doesn't load cleanly, the first error is returned and the KB may be left
+    /// partly changed, so only call this on a staged copy of the KB, as
+    /// [`Polar::load_incremental`](crate::polar::Polar::load_incremental) does. Otherwise,
+    /// returns the warnings for the new source.
+    pub(crate) fn load_source_incremental(
+        &mut self,
+        source: Source,
+    ) -> PolarResult<Vec<PolarWarning>> {
+        let filename = match &source.filename {
+            Some(filename) => filename.clone(),
+            None => {
+                return Err(file_loading_error(
+                    "",
+                    &source.src,
+                    "Only sources with a filename can be loaded incrementally.".to_owned(),
+                ))
+            }
+        };
+
+        if self.is_source_loaded(&filename) {
+            self.remove_source(&filename)?;
+        }
+        Arc::make_mut(&mut self.loaded_sources).push((source.filename.clone(), source.src.clone()));
+        let mut diagnostics = self.load_source(source)?;
+        self.check_incremental(&filename)?;
+
+        // Rules named like any rule or rule type in the new source need to be rechecked against
+        // their rule types.
+        let from_source = |rule: &Rule| is_from(rule.parsed_context(), &filename);
+        let names = self
+            .rules
+            .values()
+            .flat_map(|generic_rule| generic_rule.rules.values())
+            .map(AsRef::as_ref)
+            .chain(self.rule_types.iter())
+            .filter(|rule| from_source(rule))
+            .map(|rule| rule.name.clone())
+            .collect::<HashSet<_>>();
+        diagnostics.append(&mut self.validate_rules_named(|name| names.contains(name)));
+        into_warnings(diagnostics)
+    }
+
+    /// Remove everything loaded from the source named `filename` from the KB.
+    ///
+    /// Fails if the rest of the policy doesn't validate without the source, e.g., because it
+    /// calls rules that were only defined there. Like
+    /// [`load_source_incremental`](Self::load_source_incremental), the KB may be left partly
+    /// changed on failure, so only call this on a staged copy of the KB.
+    pub(crate) fn unload_source(&mut self, filename: &str) -> PolarResult<()> {
+        self.remove_source(filename)?;
+        let diagnostics = self.validate_rules_named(|_| false);
+        into_warnings(diagnostics)?;
+        Ok(())
+    }
+
+    fn is_source_loaded(&self, filename: &str) -> bool {
+        self.loaded_sources
+            .iter()
+            .any(|(name, _)| name.as_deref() == Some(filename))
+    }
+
+    /// Error if the source named `filename` declares resource blocks or union types, which
+    /// can't be loaded or unloaded on their own.
+    fn check_incremental(&self, filename: &str) -> PolarResult<()> {
+        let blocks = self.resource_blocks.resource_terms();
+        let unions = self.unions.values().flatten();
+        let mut terms = blocks.chain(unions);
+        if terms.any(|term| is_from(term.parsed_context(), filename)) {
+            let msg = format!(
+                "{} declares resource blocks or union types, so it can only be loaded together \
+                 with the rest of the policy.",
+                filename
+            );
+            let contents = self
+                .loaded_sources
+                .iter()
+                .find(|(name, _)| name.as_deref() == Some(filename))
+                .map_or("", |(_, src)| src);
+            return Err(file_loading_error(filename, contents, msg));
+        }
+        Ok(())
+    }
+
+    fn remove_source(&mut self, filename: &str) -> PolarResult<()> {
+        if !self.is_source_loaded(filename) {
+            let msg = format!("File {} is not loaded.", filename);
+            return Err(file_loading_error(filename, "", msg));
+        }
+        self.check_incremental(filename)?;
+
+        let from_other_source = |rule: &Rule| !is_from(rule.parsed_context(), filename);
+        let rules = Arc::make_mut(&mut self.rules);
+        for generic_rule in rules.values_mut() {
+            generic_rule.retain(from_other_source);
+        }
+        rules.retain(|_, generic_rule| !generic_rule.rules.is_empty());
+        Arc::make_mut(&mut self.rule_types).retain(from_other_source);
+        self.deprecated_rules
+            .retain(|_, deprecation| from_other_source(&deprecation.rule));
+        self.private_rules.retain(|_, rule| from_other_source(rule));
+        self.inline_queries
+            .retain(|query| !is_from(query.term.parsed_context(), filename));
+        Arc::make_mut(&mut self.loaded_content).retain(|_, name| name != filename);
+        Arc::make_mut(&mut self.loaded_sources)
+            .retain(|(name, _)| name.as_deref() != Some(filename));
+        let rules = self.rules.values().flat_map(|rule| rule.rules.values());
+        self.interner.retain_used(rules.map(AsRef::as_ref));
+        Ok(())
     }
 
     /// Check that all relations declared across all resource blocks have been registered as
@@ -689,8 +1529,17 @@ impl KnowledgeBase {
 
         errors.append(&mut self.check_that_resource_block_relations_are_registered());
 
+        // Generate rules for one resource block at a time, in a stable order, so that rules from
+        // different blocks are evaluated in the same order every time the policy is loaded.
+        let mut blocks = self
+            .resource_blocks
+            .shorthand_rules
+            .iter()
+            .collect::<Vec<_>>();
+        blocks.sort_by_key(|(resource_name, _)| resource_name.to_string());
+
         let mut rules = vec![];
-        for (resource_name, shorthand_rules) in &self.resource_blocks.shorthand_rules {
+        for (resource_name, shorthand_rules) in blocks {
             for shorthand_rule in shorthand_rules {
                 match shorthand_rule.as_rule(resource_name, &self.resource_blocks) {
                     Ok(rule) => rules.push(rule),
@@ -699,6 +1548,14 @@ impl KnowledgeBase {
             }
         }
 
+        // Generate the `has_relation` rules for transitive relations.
+        for (resource_name, relation) in self.resource_blocks.transitive_relations() {
+            match transitive_relation_rules(relation, resource_name) {
+                Ok(mut transitive_rules) => rules.append(&mut transitive_rules),
+                Err(error) => errors.push(error),
+            }
+        }
+
         // Add the rewritten rules to the KB.
         for rule in rules {
             self.add_rule(rule);
@@ -800,9 +1657,9 @@ impl KnowledgeBase {
         }
 
         let mut rule_types = rule_types_to_create.into_iter().map(|((subject, relation, object), required)| {
-            let subject_specializer = pattern!(instance!(&subject.as_symbol()?.0));
+            let subject_specializer = pattern!(instance!(subject.as_symbol()?.as_str()));
             let relation_name = relation.as_string()?;
-            let object_specializer = pattern!(instance!(&object.as_symbol()?.0));
+            let object_specializer = pattern!(instance!(object.as_symbol()?.as_str()));
 
             let name = sym!("has_relation");
             let mut params = args!("subject"; subject_specializer, relation_name, "object"; object_specializer);
@@ -813,6 +1670,22 @@ impl KnowledgeBase {
             Ok(Rule { name, params, body, source_info, required })
         }).collect::<PolarResult<Vec<_>>>()?;
 
+        // The rules generated for a transitive relation are built from its `has_direct_relation`
+        // rules, so at least one of them is required.
+        for (resource, relation) in self.resource_blocks.transitive_relations() {
+            let specializer = pattern!(instance!(resource.as_symbol()?.as_str()));
+            let mut params =
+                args!("subject"; specializer, relation.as_string()?, "object"; specializer.clone());
+            params.reverse();
+            rule_types.push(Rule {
+                name: sym!(DIRECT_RELATION_RULE_NAME),
+                params,
+                body: term!(op!(And)),
+                source_info: relation.source_info().clone(),
+                required: true,
+            });
+        }
+
         // If there are any Relation::Role declarations in *any* of our resource
         // blocks then we want to add the `has_role` rule type.
         if self.resource_blocks.has_roles() {
@@ -831,23 +1704,126 @@ impl KnowledgeBase {
         Ok(())
     }
 
+    /// Declare the union type `name` with `members`, each of which must be a registered class or
+    /// a previously declared union. Declaring the built-in `Actor` or `Resource` unions adds
+    /// `members` to them alongside the types declared via resource blocks.
+    pub fn add_union(&mut self, name: Term, members: Vec<Term>) -> PolarResult<()> {
+        let error = |term: &Term, msg: String| {
+            Err(ValidationError::UnionType {
+                term: term.clone(),
+                msg,
+            }
+            .into())
+        };
+
+        let symbol = name.as_symbol()?.clone();
+        if self.is_constant(&symbol) {
+            return error(
+                &name,
+                format!(
+                    "Cannot declare union {symbol}: a constant with the same name is registered."
+                ),
+            );
+        } else if self.unions.contains_key(&symbol) {
+            return error(&name, format!("Union {symbol} is already declared."));
+        }
+
+        let mut flattened = HashSet::new();
+        for member in members {
+            if member.is_actor_union() || member.is_resource_union() {
+                return error(
+                    &member,
+                    format!("Built-in union {member} cannot be a member of union {symbol}."),
+                );
+            } else if member.as_symbol()? == &symbol {
+                return error(&member, format!("Union {symbol} cannot contain itself."));
+            } else if self.is_union(&member) {
+                flattened.extend(
+                    self.get_union_members(&member)
+                        .into_iter()
+                        .flatten()
+                        .cloned(),
+                );
+            } else {
+                self.get_registered_class(&member)?;
+                flattened.insert(member);
+            }
+        }
+
+        if name.is_actor_union() {
+            self.resource_blocks.actors.extend(flattened);
+        } else if name.is_resource_union() {
+            self.resource_blocks.resources.extend(flattened);
+        } else {
+            self.unions.insert(symbol, flattened);
+        }
+        Ok(())
+    }
+
     pub fn is_union(&self, maybe_union: &Term) -> bool {
-        (maybe_union.is_actor_union()) || (maybe_union.is_resource_union())
+        (maybe_union.is_actor_union())
+            || (maybe_union.is_resource_union())
+            || matches!(specializer_tag(maybe_union), Some(tag) if self.unions.contains_key(tag))
     }
 
-    pub fn get_union_members(&self, union: &Term) -> &HashSet<Term> {
+    /// The members of `union`, or `None` if it isn't a union.
+    pub fn get_union_members(&self, union: &Term) -> Option<&HashSet<Term>> {
         if union.is_actor_union() {
-            &self.resource_blocks.actors
+            Some(&self.resource_blocks.actors)
         } else if union.is_resource_union() {
-            &self.resource_blocks.resources
+            Some(&self.resource_blocks.resources)
         } else {
-            unreachable!()
+            specializer_tag(union).and_then(|tag| self.unions.get(tag))
+        }
+    }
+
+    /// For a declared union with a single member, e.g., `type Id = Repo;`, the specializer for
+    /// that member with the same fields as `specializer`. Such a union is an alias for its member.
+    pub fn resolve_alias(&self, specializer: &Term) -> Option<Term> {
+        let members = self.unions.get(specializer_tag(specializer)?)?;
+        let member = match members.iter().collect::<Vec<_>>()[..] {
+            [member] => member.as_symbol().ok()?,
+            _ => return None,
+        };
+        let fields = match specializer.value() {
+            Value::Pattern(Pattern::Instance(InstanceLiteral { fields, .. })) => fields.clone(),
+            _ => Dictionary::default(),
+        };
+        let instance = InstanceLiteral {
+            tag: member.clone(),
+            fields,
+        };
+        Some(specializer.clone_with_value(Value::Pattern(Pattern::Instance(instance))))
+    }
+
+    /// Return true if every member of the union `left` is a member of the union `right`.
+    ///
+    /// The built-in `Actor` and `Resource` unions are only ever subunions of themselves.
+    pub fn is_subunion(&self, left: &Term, right: &Term) -> bool {
+        let builtin = |term: &Term| term.is_actor_union() || term.is_resource_union();
+        match (specializer_tag(left), specializer_tag(right)) {
+            (Some(l), Some(r)) if l == r => true,
+            _ if builtin(left) || builtin(right) => false,
+            _ => match (self.get_union_members(left), self.get_union_members(right)) {
+                (Some(left), Some(right)) => left.is_subset(right),
+                _ => false,
+            },
         }
     }
 
     pub fn has_rules(&self) -> bool {
         !self.rules.is_empty()
     }
+
+    /// Summarize the rules in the KB.
+    pub fn stats(&self) -> KnowledgeBaseStats {
+        KnowledgeBaseStats::new(self)
+    }
+
+    /// Export the rules, rule types and resource blocks in the KB, with their source spans.
+    pub fn export_ast(&self) -> PolicyAst {
+        PolicyAst::new(self)
+    }
 }
 
 #[cfg(test)]
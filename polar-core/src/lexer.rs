@@ -5,7 +5,14 @@ use std::{
     str::{CharIndices, FromStr},
 };
 
-use super::{error::ParseErrorKind, terms::Symbol};
+use num_bigint::BigInt;
+
+use super::{
+    error::ParseErrorKind,
+    interner::Interner,
+    numerics::Decimal,
+    terms::{DateTime, Duration, Symbol},
+};
 
 pub type SrcPos = (usize, usize);
 
@@ -32,6 +39,11 @@ pub struct Lexer<'input> {
     c: Option<(usize, char)>,
     chars: Peekable<CharIndices<'input>>,
     buf: String,
+    /// One entry per unclosed `{`: true if it opened an interpolation in a string, false if it
+    /// opened a dictionary.
+    braces: Vec<bool>,
+    /// Shares the names of the symbols in the input, which repeat a lot in large policies.
+    interner: Interner,
 }
 
 impl<'input> Lexer<'input> {
@@ -39,7 +51,13 @@ impl<'input> Lexer<'input> {
         let mut chars = input.char_indices().peekable();
         let c = chars.next();
         let buf = String::new();
-        Lexer { c, chars, buf }
+        Lexer {
+            c,
+            chars,
+            buf,
+            braces: vec![],
+            interner: Interner::default(),
+        }
     }
 }
 
@@ -48,10 +66,24 @@ pub type Spanned<Tok, Loc, Error> = Result<(Loc, Tok, Loc), Error>;
 #[derive(Clone, Debug)]
 pub enum Token {
     Integer(i64),
+    /// An integer literal too large for an `i64`.
+    BigInteger(BigInt),
     Float(f64),
+    /// A decimal literal, written with a `d` suffix: `1.50d`.
+    Decimal(Decimal),
     String(String),
+    /// The part of an interpolated string before its first `{`.
+    StringStart(String),
+    /// The part of an interpolated string between a `}` and the next `{`.
+    StringMiddle(String),
+    /// The part of an interpolated string after its last `}`.
+    StringEnd(String),
+    DateTime(DateTime), // datetime"2024-01-31T12:00:00Z"
+    Duration(Duration), // duration"P30D"
     Boolean(bool),
     Symbol(Symbol),
+    /// A query parameter, e.g., `$name`.
+    Param(Symbol),
     Colon,     // :
     Comma,     // ,
     LB,        // [
@@ -80,6 +112,7 @@ pub enum Token {
     Pipe,      // |
     SemiColon, // ;
     Query,     // ?=
+    Arrow,     // =>
     In,        // in
     Cut,       // cut
     Debug,     // debug()
@@ -92,16 +125,24 @@ pub enum Token {
     Not,       // not
     Matches,   // matches
     Type,      // type
+    At,        // @
 }
 
 impl ToString for Token {
     fn to_string(&self) -> String {
         match self {
             Token::Integer(i) => i.to_string(),
+            Token::BigInteger(i) => i.to_string(),
             Token::Float(f) => f.to_string(),
-            Token::String(s) => s.clone(),
+            Token::Decimal(d) => format!("{}d", d),
+            Token::String(s)
+            | Token::StringStart(s)
+            | Token::StringMiddle(s)
+            | Token::StringEnd(s) => s.clone(),
+            Token::DateTime(t) => format!("datetime\"{}\"", t),
+            Token::Duration(d) => format!("duration\"{}\"", d),
             Token::Boolean(b) => b.to_string(),
-            Token::Symbol(sym) => sym.0.clone(),
+            Token::Symbol(sym) | Token::Param(sym) => sym.as_str().to_string(),
             Token::Colon => ":".to_owned(),         // :
             Token::Comma => ",".to_owned(),         // ,
             Token::LB => "[".to_owned(),            // [
@@ -130,6 +171,7 @@ impl ToString for Token {
             Token::Pipe => "|".to_owned(),          // |
             Token::SemiColon => ";".to_owned(),     // ;
             Token::Query => "?=".to_owned(),        // ?=
+            Token::Arrow => "=>".to_owned(),        // =>
             Token::In => "in".to_owned(),           // in
             Token::Cut => "cut".to_owned(),         // cut
             Token::Debug => "debug".to_owned(),     // debug
@@ -142,6 +184,7 @@ impl ToString for Token {
             Token::Not => "not".to_owned(),         // not
             Token::Matches => "matches".to_owned(), // matches
             Token::Type => "type".to_owned(),       // type
+            Token::At => "@".to_owned(),            // @
         }
     }
 }
@@ -241,16 +284,85 @@ impl<'input> Lexer<'input> {
             "type" => Token::Type,
             "mod" => Token::Mod,
             "rem" => Token::Rem,
-            _ => Token::Symbol(Symbol::new(&self.buf)),
+            "datetime" | "duration" if matches!(self.c, Some((_, '"'))) => {
+                return self.scan_temporal(start);
+            }
+            "f" if matches!(self.c, Some((_, '"'))) => {
+                return match self.scan_string(self.c?.0, false, true)? {
+                    Ok((_, token, end)) => Some(Ok((start, token, end))),
+                    Err(e) => Some(Err(e)),
+                };
+            }
+            _ => Token::Symbol(self.interner.intern_str(&self.buf)),
         };
         Some(Ok((start, token, last + 1)))
     }
 
+    /// Scan a query parameter, e.g., `$name`, which is a `$` directly followed by a symbol.
+    fn scan_param(&mut self, start: usize) -> Option<Spanned<Token, usize, ParseErrorKind>> {
+        let invalid = Some(Err(ParseErrorKind::InvalidTokenCharacter {
+            token: "".to_owned(),
+            c: '$',
+            loc: start,
+        }));
+        self.c = self.chars.next();
+        match self.c {
+            Some((i, x))
+                if x == '_'
+                    || (!x.is_ascii_punctuation()
+                        && !x.is_ascii_whitespace()
+                        && !x.is_ascii_digit()) =>
+            {
+                match self.scan_symbol(i, x)? {
+                    Ok((_, Token::Symbol(sym), end)) => Some(Ok((
+                        start,
+                        Token::Param(Symbol::new(&format!("${}", sym))),
+                        end,
+                    ))),
+                    Ok(_) => invalid,
+                    Err(e) => Some(Err(e)),
+                }
+            }
+            _ => invalid,
+        }
+    }
+
+    /// Scan the string after a `datetime` or `duration` prefix in `self.buf` into a literal.
+    fn scan_temporal(&mut self, start: usize) -> Option<Spanned<Token, usize, ParseErrorKind>> {
+        let prefix = self.buf.clone();
+        let (token, end) = match self.scan_string(self.c?.0, false, false)? {
+            Ok((_, token, end)) => (token.to_string(), end),
+            Err(e) => return Some(Err(e)),
+        };
+        let parsed = if prefix == "datetime" {
+            token.parse().map(Token::DateTime)
+        } else {
+            token.parse().map(Token::Duration)
+        };
+        Some(match parsed {
+            Ok(token) => Ok((start, token, end)),
+            Err(msg) => Err(ParseErrorKind::InvalidLiteral {
+                token: format!("{}\"{}\"", prefix, token),
+                loc: start,
+                msg,
+            }),
+        })
+    }
+
+    /// Scan a string starting at the `"` at `i`, or continue an interpolated string from the `}`
+    /// at `i` if `continued`. In an `interpolated` string, i.e., one prefixed with `f`, an
+    /// unescaped `{` ends the segment and starts an interpolation.
     #[inline]
     #[allow(clippy::unnecessary_wraps)]
-    fn scan_string(&mut self, i: usize) -> Option<Spanned<Token, usize, ParseErrorKind>> {
+    fn scan_string(
+        &mut self,
+        i: usize,
+        continued: bool,
+        interpolated: bool,
+    ) -> Option<Spanned<Token, usize, ParseErrorKind>> {
         let start = i;
         let last;
+        let mut interpolation = false;
         self.buf.clear();
         self.c = self.chars.next();
         loop {
@@ -268,6 +380,13 @@ impl<'input> Lexer<'input> {
                         last = i;
                         break;
                     }
+                    '{' if interpolated => {
+                        self.c = self.chars.next();
+                        self.braces.push(true);
+                        interpolation = true;
+                        last = i;
+                        break;
+                    }
                     '\\' => {
                         self.c = self.chars.next();
                         if let Some((_, char)) = self.c {
@@ -301,7 +420,14 @@ impl<'input> Lexer<'input> {
                 }));
             }
         }
-        Some(Ok((start, Token::String(self.buf.clone()), last + 1)))
+        let buf = self.buf.clone();
+        let token = match (continued, interpolation) {
+            (false, false) => Token::String(buf),
+            (false, true) => Token::StringStart(buf),
+            (true, true) => Token::StringMiddle(buf),
+            (true, false) => Token::StringEnd(buf),
+        };
+        Some(Ok((start, token, last + 1)))
     }
 
     #[inline]
@@ -373,6 +499,22 @@ impl<'input> Lexer<'input> {
             }
         }
 
+        if let Some((i, 'd')) = self.c {
+            // A `d` suffix makes a decimal, unless it starts a name.
+            let suffix =
+                !matches!(self.chars.peek(), Some((_, c)) if c.is_alphanumeric() || *c == '_');
+            if suffix && !self.buf.contains(['e', 'E']) {
+                self.c = self.chars.next();
+                return match Decimal::from_str(&self.buf) {
+                    Ok(d) => Some(Ok((start, Token::Decimal(d), i + 1))),
+                    Err(_) => Some(Err(ParseErrorKind::InvalidFloat {
+                        token: self.buf.clone(),
+                        loc: start,
+                    })),
+                };
+            }
+        }
+
         if parse_as_float {
             if let Ok(f) = f64::from_str(&self.buf) {
                 Some(Ok((start, Token::Float(f), last + 1)))
@@ -384,6 +526,8 @@ impl<'input> Lexer<'input> {
             }
         } else if let Ok(int) = i64::from_str(&self.buf) {
             Some(Ok((start, Token::Integer(int), last + 1)))
+        } else if let Ok(int) = BigInt::from_str(&self.buf) {
+            Some(Ok((start, Token::BigInteger(int), last + 1)))
         } else {
             Some(Err(ParseErrorKind::IntegerOverflow {
                 token: self.buf.clone(),
@@ -466,9 +610,12 @@ impl<'input> Iterator for Lexer<'input> {
                 x if x == '_' || (!x.is_ascii_punctuation() && !x.is_ascii_digit()) => {
                     self.scan_symbol(i, char)
                 }
-                '"' => self.scan_string(i),
+                '"' => self.scan_string(i, false, false),
                 '0'..='9' => self.scan_number(i, char),
                 ':' => self.scan_1c_or_2c_op(i, Token::Colon, '=', Token::Assign),
+                '=' if matches!(self.chars.peek(), Some((_, '>'))) => {
+                    self.scan_2c_op(i, '>', Token::Arrow)
+                }
                 '=' => self.scan_1c_or_2c_op(i, Token::Unify, '=', Token::Eq),
                 '<' => self.scan_1c_or_2c_op(i, Token::Lt, '=', Token::Leq),
                 '>' => self.scan_1c_or_2c_op(i, Token::Gt, '=', Token::Geq),
@@ -478,8 +625,14 @@ impl<'input> Iterator for Lexer<'input> {
                 ',' => self.scan_1c_op(i, Token::Comma),
                 '[' => self.scan_1c_op(i, Token::LB),
                 ']' => self.scan_1c_op(i, Token::RB),
-                '{' => self.scan_1c_op(i, Token::LCB),
-                '}' => self.scan_1c_op(i, Token::RCB),
+                '{' => {
+                    self.braces.push(false);
+                    self.scan_1c_op(i, Token::LCB)
+                }
+                '}' => match self.braces.pop() {
+                    Some(true) => self.scan_string(i, true, true),
+                    _ => self.scan_1c_op(i, Token::RCB),
+                },
                 '(' => self.scan_1c_op(i, Token::LP),
                 ')' => self.scan_1c_op(i, Token::RP),
                 '.' => self.scan_1c_op(i, Token::Dot),
@@ -488,6 +641,8 @@ impl<'input> Iterator for Lexer<'input> {
                 '*' => self.scan_1c_op(i, Token::Mul),
                 '/' => self.scan_1c_op(i, Token::Div),
                 ';' => self.scan_1c_op(i, Token::SemiColon),
+                '@' => self.scan_1c_op(i, Token::At),
+                '$' => self.scan_param(i),
                 _ => Some(Err(ParseErrorKind::InvalidTokenCharacter {
                     token: "".to_owned(),
                     c: char,
@@ -553,6 +708,42 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_interpolated_strings() {
+        let s = r#"f"a{x}b{ {c: "d"}.c }e" f"{}" "{x}" f"y""#;
+        let tokens = Lexer::new(s)
+            .map(|t| t.unwrap().1.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            tokens,
+            vec!["a", "x", "b", "{", "c", ":", "d", "}", ".", "c", "e", "", "", "{x}", "y"]
+        );
+        let mut lexer = Lexer::new(s);
+        assert!(matches!(
+            lexer.next(),
+            Some(Ok((0, Token::StringStart(_), 4)))
+        ));
+        lexer.next();
+        assert!(matches!(
+            lexer.next(),
+            Some(Ok((5, Token::StringMiddle(_), 8)))
+        ));
+        assert!(matches!(
+            lexer.nth(7),
+            Some(Ok((20, Token::StringEnd(_), 23)))
+        ));
+
+        // Braces are only interpolated in strings prefixed with `f`.
+        assert!(matches!(
+            lexer.nth(2),
+            Some(Ok((30, Token::String(s), 35))) if s == "{x}"
+        ));
+        assert!(matches!(
+            lexer.next(),
+            Some(Ok((36, Token::String(s), 40))) if s == "y"
+        ));
+    }
+
     #[test]
     fn test_emoji() {
         let s = r#"
@@ -567,6 +758,22 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_param() {
+        let mut lexer = Lexer::new("$user_id $ $in");
+        assert!(
+            matches!(lexer.next(), Some(Ok((0, Token::Param(param), 8))) if param == Symbol::new("$user_id"))
+        );
+        assert!(matches!(
+            lexer.next(),
+            Some(Err(ParseErrorKind::InvalidTokenCharacter {
+                c: '$',
+                loc: 9,
+                ..
+            }))
+        ));
+    }
+
     #[test]
     fn test_symbol_with_trailing_question_mark() {
         let s = "foo??";
@@ -692,5 +899,27 @@ mod tests {
         let f = "1.1e-1";
         let mut lexer = Lexer::new(f);
         assert!(matches!(lexer.next(), Some(Ok((_, Token::Float(f), _))) if f == 1.1e-1));
+
+        let f = "9223372036854775808";
+        let mut lexer = Lexer::new(f);
+        assert!(
+            matches!(lexer.next(), Some(Ok((0, Token::BigInteger(i), 19))) if i.to_string() == f)
+        );
+
+        let f = "1.50d 2d dx";
+        let mut lexer = Lexer::new(f);
+        assert!(
+            matches!(lexer.next(), Some(Ok((0, Token::Decimal(d), 5))) if d.to_string() == "1.50")
+        );
+        assert!(
+            matches!(lexer.next(), Some(Ok((6, Token::Decimal(d), 8))) if d.to_string() == "2")
+        );
+        assert!(matches!(lexer.next(), Some(Ok((9, Token::Symbol(_), 11)))));
+
+        // A `d` that starts a name isn't a suffix.
+        let f = "1dx";
+        let mut lexer = Lexer::new(f);
+        assert!(matches!(lexer.next(), Some(Ok((0, Token::Integer(1), 1)))));
+        assert!(matches!(lexer.next(), Some(Ok((1, Token::Symbol(_), 3)))));
     }
 }
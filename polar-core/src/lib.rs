@@ -7,37 +7,109 @@ extern crate maplit;
 #[macro_use]
 pub mod macros;
 
+// A panic in the VM takes down the host thread running the query, so `deny-panics` builds
+// reject code that can panic in the VM and the modules it drives.
+#[cfg_attr(
+    all(feature = "deny-panics", not(test)),
+    deny(
+        clippy::expect_used,
+        clippy::panic,
+        clippy::todo,
+        clippy::unimplemented,
+        clippy::unreachable,
+        clippy::unwrap_used
+    )
+)]
+mod aggregate;
+pub mod analysis;
+pub mod authorization;
+pub mod batch;
 mod bindings;
+pub mod builtins;
+#[cfg(feature = "bundle")]
+mod bundle;
+#[cfg(feature = "conformance")]
+pub mod conformance;
 mod constants;
 mod counter;
 pub mod data_filtering;
+pub mod debug_protocol;
 mod debugger;
 pub mod diagnostic;
+pub mod diff;
 pub mod error;
 pub mod events;
+pub mod explain;
 pub mod filter;
 mod folder;
 mod formatting;
+pub mod inline_query;
+mod interner;
+pub mod introspection;
 mod inverter;
 pub mod kb;
 mod lexer;
 pub mod messages;
+pub mod metrics;
 pub mod normalize;
 mod numerics;
 pub mod parser;
 mod partial;
+pub mod plan_cache;
 pub mod polar;
+#[cfg_attr(
+    all(feature = "deny-panics", not(test)),
+    deny(
+        clippy::expect_used,
+        clippy::panic,
+        clippy::todo,
+        clippy::unimplemented,
+        clippy::unreachable,
+        clippy::unwrap_used
+    )
+)]
 pub mod query;
+mod reachable;
+pub mod repl;
 pub mod resource_block;
 mod rewrites;
 pub mod rules;
 mod runnable;
+pub mod session;
+mod sets;
+pub mod snapshot;
 pub mod sources;
+#[cfg(feature = "sql")]
+pub mod sql;
+pub mod stats;
+#[cfg(feature = "stream")]
+pub mod stream;
+pub mod temporal;
 pub mod terms;
+pub mod trace_spans;
 pub mod traces;
+mod type_check;
 mod validations;
-mod visitor;
+pub mod visitor;
+#[cfg_attr(
+    all(feature = "deny-panics", not(test)),
+    deny(
+        clippy::expect_used,
+        clippy::panic,
+        clippy::todo,
+        clippy::unimplemented,
+        clippy::unreachable,
+        clippy::unwrap_used
+    )
+)]
 mod vm;
 pub mod warning;
+#[cfg(feature = "wasm")]
+pub mod wasm;
 
+pub use formatting::{InstanceRepr, TermFormatter};
 pub use lexer::loc_to_pos;
+pub use query::QueryFlags;
+pub use rewrites::OptimizationLevel;
+pub use type_check::TypeChecking;
+pub use vm::QueryLimits;
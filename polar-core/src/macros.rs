@@ -103,7 +103,8 @@ macro_rules! str {
     };
 }
 
-// TODO: support kwargs
+/// Keyword arguments are written as a map of names to values, e.g.,
+/// `call!("f", [1], {"x" => 2, "y" => "z"})`, or passed as a `BTreeMap<Symbol, Term>`.
 #[macro_export]
 macro_rules! call {
     ($name:expr) => {
@@ -122,6 +123,17 @@ macro_rules! call {
             kwargs: None
         }
     };
+    ($name:expr, [$($args:expr),*], {$($key:expr => $value:expr),* $(,)?}) => {
+        $crate::terms::Call {
+            name: $crate::sym!($name),
+            args: vec![
+                $($crate::term!($args)),*
+            ],
+            kwargs: Some(vec![
+                $(($crate::sym!($key), $crate::term!($value))),*
+            ].into_iter().collect())
+        }
+    };
     ($name:expr, [$($args:expr),*], $fields:expr) => {
         $crate::terms::Call {
             name: $crate::sym!($name),
@@ -275,7 +287,7 @@ impl From<Value> for TestHelper<Parameter> {
 
 impl<S: AsRef<str>> From<S> for TestHelper<Symbol> {
     fn from(other: S) -> Self {
-        Self(Symbol(other.as_ref().to_string()))
+        Self(Symbol::new(other.as_ref()))
     }
 }
 
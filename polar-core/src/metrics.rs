@@ -0,0 +1,154 @@
+//! Counters of the work queries do, for monitoring the cost of policies in production.
+//!
+//! Every query collects [`QueryMetrics`] as it runs, which
+//! [`Query::metrics`](crate::query::Query::metrics) returns. Once a query is dropped, its metrics
+//! are added to the totals of the [`Polar`](crate::polar::Polar) instance that started it, which
+//! [`Polar::metrics_snapshot`](crate::polar::Polar::metrics_snapshot) returns. Comparing totals
+//! from before and after a deploy shows whether a policy change made queries more expensive.
+//!
+//! The totals are the counters the VM keeps for query limits anyway. Counts by goal type and by
+//! rule cost a map update per goal, so they're only kept for queries that opt in with
+//! [`Query::detailed_metrics`](crate::query::Query::detailed_metrics).
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::terms::Symbol;
+use crate::vm::Goal;
+
+/// What one or more queries did while they ran.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct QueryMetrics {
+    /// Number of queries counted.
+    pub queries: u64,
+    /// Number of goals the VM executed.
+    pub goals: u64,
+    /// Number of goals the VM executed, by type, e.g., `Query` or `Isa`. Detailed.
+    pub goals_by_type: BTreeMap<&'static str, u64>,
+    /// Number of choice points pushed.
+    pub choice_points: u64,
+    /// Number of times two terms were unified, including rule arguments with parameters.
+    pub unifications: u64,
+    /// Number of times the VM backtracked to a choice point.
+    pub backtracks: u64,
+    /// Number of events the host had to answer, such as attribute lookups and `isa` checks.
+    pub external_calls: u64,
+    /// External calls by the name of the innermost rule being evaluated when they were made.
+    /// Calls made by the query itself, outside of any rule, aren't included. Detailed.
+    pub external_calls_by_rule: BTreeMap<Symbol, u64>,
+    /// Number of times the body of a rule was entered, by rule name. Detailed.
+    pub rule_attempts: BTreeMap<Symbol, u64>,
+    /// Number of external calls and lazy constants answered from a cache instead of by the host.
+    pub cache_hits: u64,
+}
+
+impl QueryMetrics {
+    pub(crate) fn goal(&mut self, goal: &Goal) {
+        *self.goals_by_type.entry(goal.into()).or_default() += 1;
+    }
+
+    pub(crate) fn external_call_in(&mut self, rule: Symbol) {
+        *self.external_calls_by_rule.entry(rule).or_default() += 1;
+    }
+
+    pub(crate) fn rule_attempt(&mut self, rule: &Symbol) {
+        *self.rule_attempts.entry(rule.clone()).or_default() += 1;
+    }
+
+    /// Add the counts in `other` to these.
+    pub fn merge(&mut self, other: &Self) {
+        fn add<K: Clone + Ord>(into: &mut BTreeMap<K, u64>, from: &BTreeMap<K, u64>) {
+            for (key, count) in from {
+                *into.entry(key.clone()).or_default() += count;
+            }
+        }
+        self.queries += other.queries;
+        self.goals += other.goals;
+        add(&mut self.goals_by_type, &other.goals_by_type);
+        self.choice_points += other.choice_points;
+        self.unifications += other.unifications;
+        self.backtracks += other.backtracks;
+        self.external_calls += other.external_calls;
+        add(
+            &mut self.external_calls_by_rule,
+            &other.external_calls_by_rule,
+        );
+        add(&mut self.rule_attempts, &other.rule_attempts);
+        self.cache_hits += other.cache_hits;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::QueryEvent;
+    use crate::polar::Polar;
+    use crate::terms::{ExternalInstance, Value};
+
+    #[test]
+    fn test_metrics_count_per_query_and_in_total() {
+        let p = Polar::new();
+        let obj = ExternalInstance {
+            instance_id: 1,
+            constructor: None,
+            repr: None,
+            class_repr: None,
+            class_id: None,
+        };
+        p.register_constant(sym!("obj"), term!(Value::ExternalInstance(obj)))
+            .unwrap();
+        p.load_str(
+            r#"f(x) if g(x) and obj.ok = x;
+               g(1);
+               g(2);"#,
+        )
+        .unwrap();
+
+        let mut query = p.new_query("f(x)", false).unwrap();
+        query.detailed_metrics();
+        let mut results = 0;
+        loop {
+            match query.next_event().unwrap() {
+                QueryEvent::Done { .. } => break,
+                QueryEvent::Result { .. } => results += 1,
+                QueryEvent::ExternalCall { call_id, .. } => {
+                    query.call_result(call_id, Some(term!(2))).unwrap();
+                }
+                event => panic!("unexpected event {:?}", event),
+            }
+        }
+        assert_eq!(results, 1);
+
+        let metrics = query.metrics();
+        assert_eq!(metrics.queries, 1);
+        assert_eq!(metrics.rule_attempts[&sym!("f")], 1);
+        assert_eq!(metrics.rule_attempts[&sym!("g")], 2);
+        assert!(metrics.unifications > 0);
+        assert!(metrics.backtracks > 0);
+        assert!(metrics.choice_points > 0);
+        assert!(metrics.goals_by_type["Query"] >= 3);
+        assert_eq!(metrics.goals, metrics.goals_by_type.values().sum::<u64>());
+        // The second lookup of `obj.ok` is memoized.
+        assert_eq!(metrics.external_calls, 1);
+        assert_eq!(metrics.external_calls_by_rule[&sym!("f")], 1);
+        assert_eq!(metrics.cache_hits, 1);
+
+        assert_eq!(p.metrics_snapshot(), QueryMetrics::default());
+        drop(query);
+
+        // Without detailed metrics, only the totals are counted.
+        let mut query = p.new_query("g(1)", false).unwrap();
+        while !matches!(query.next_event().unwrap(), QueryEvent::Done { .. }) {}
+        let metrics = query.metrics();
+        assert!(metrics.goals > 0);
+        assert!(metrics.goals_by_type.is_empty());
+        assert!(metrics.rule_attempts.is_empty());
+        drop(query);
+
+        let totals = p.metrics_snapshot();
+        assert_eq!(totals.queries, 2);
+        assert_eq!(totals.rule_attempts[&sym!("g")], 2);
+        assert_eq!(totals.rule_attempts[&sym!("f")], 1);
+    }
+}
@@ -1,5 +1,28 @@
 use crate::terms::*;
 
+/// The shape of the constraints in partial results. See `QueryFlags`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PartialForm {
+    /// As simplified, with constraints in the order they were added.
+    #[default]
+    Simplified,
+    /// See [`Term::canonical_form`].
+    Canonical,
+    /// See [`Term::flat_disjunctive_normal_form`].
+    Dnf,
+}
+
+impl PartialForm {
+    /// Reshape `term` if it's a partial.
+    pub fn apply(self, term: Term) -> Term {
+        match (self, term.value()) {
+            (Self::Canonical, Value::Expression(_)) => term.canonical_form(),
+            (Self::Dnf, Value::Expression(_)) => term.flat_disjunctive_normal_form(),
+            _ => term,
+        }
+    }
+}
+
 impl Term {
     /// convert expression to disjunctive normal form
     pub fn disjunctive_normal_form(&self) -> Self {
@@ -11,6 +34,92 @@ impl Term {
         self.pre_normalize().distribute(is_or, or_, is_and, and_)
     }
 
+    /// Canonical form for partial results:
+    /// - nested and/or nodes flattened into their parents
+    /// - double negatives removed
+    /// - duplicate arguments of and/or nodes removed
+    /// - arguments of and/or nodes sorted by their polar representation
+    pub fn canonical_form(&self) -> Self {
+        use Operator::*;
+        match self.as_expression() {
+            Err(_) => self.clone(),
+            Ok(Operation {
+                operator: Not,
+                args,
+            }) if args.len() == 1 && is_op(&args[0], Not) => args[0].lhs().canonical_form(),
+            Ok(Operation { operator, args }) if *operator == And || *operator == Or => {
+                let mut flattened: Vec<Term> = vec![];
+                for arg in args.iter().map(|a| a.canonical_form()) {
+                    match arg.as_expression() {
+                        Ok(Operation {
+                            operator: inner,
+                            args: inner_args,
+                        }) if inner == operator
+                            || (inner_args.len() == 1 && (*inner == And || *inner == Or)) =>
+                        {
+                            flattened.extend(inner_args.iter().cloned())
+                        }
+                        _ => flattened.push(arg),
+                    }
+                }
+                let mut args = vec![];
+                for arg in flattened {
+                    if !args.contains(&arg) {
+                        args.push(arg);
+                    }
+                }
+                args.sort_by_cached_key(|a| a.to_string());
+                self.clone_with_value(Value::Expression(Operation {
+                    operator: *operator,
+                    args,
+                }))
+            }
+            Ok(Operation { operator, args }) => {
+                self.clone_with_value(Value::Expression(Operation {
+                    operator: *operator,
+                    args: args.iter().map(|a| a.canonical_form()).collect(),
+                }))
+            }
+        }
+    }
+
+    /// Disjunctive normal form in a predictable shape for hosts: an or of ands whose arguments
+    /// contain no and/or nodes, in canonical form. An expression that always holds is an or of
+    /// a single empty and; one that never holds is an empty or.
+    pub fn flat_disjunctive_normal_form(&self) -> Self {
+        use {Operator::*, Value::*};
+        let dnf = self.disjunctive_normal_form().canonical_form();
+        let disjuncts = match dnf.value() {
+            Expression(Operation { operator: Or, args }) => args.clone(),
+            _ => vec![dnf.clone()],
+        };
+        let conjunctions = disjuncts
+            .into_iter()
+            .filter(|d| !matches!(d.value(), Boolean(false)))
+            .map(|d| {
+                let args = match d.value() {
+                    Boolean(true) => vec![],
+                    Expression(Operation {
+                        operator: And,
+                        args,
+                    }) => args.clone(),
+                    _ => vec![d.clone()],
+                };
+                d.clone_with_value(Expression(Operation {
+                    operator: And,
+                    args: args
+                        .into_iter()
+                        .filter(|a| !matches!(a.value(), Boolean(true)))
+                        .collect(),
+                }))
+            })
+            .collect::<Vec<_>>();
+        self.clone_with_value(Expression(Operation {
+            operator: Or,
+            args: conjunctions,
+        }))
+    }
+
     /// Condition input for dnf/cnf transformation
     /// - negations fully nested
     /// - double negatives removed
@@ -207,6 +316,37 @@ mod test {
         )
     }
 
+    #[test]
+    fn test_canonical_form() {
+        let ex = and_(
+            var!("b"),
+            and_(
+                not_(not_(var!("a"))),
+                or_(var!("d"), or_(var!("c"), var!("d"))),
+            ),
+        );
+        let canonical = term!(op!(
+            And,
+            var!("a"),
+            var!("b"),
+            term!(op!(Or, var!("c"), var!("d")))
+        ));
+        assert_eq!(ex.canonical_form(), canonical);
+        assert_eq!(canonical.canonical_form(), canonical);
+
+        let dnf = term!(op!(
+            Or,
+            term!(op!(And, not_(var!("p")))),
+            term!(op!(And, not_(var!("s")), var!("q"))),
+            term!(op!(And, var!("q"), var!("r")))
+        ));
+        assert_eq!(ex1().flat_disjunctive_normal_form(), dnf);
+        assert_eq!(
+            term!(true).flat_disjunctive_normal_form(),
+            term!(op!(Or, term!(op!(And))))
+        );
+    }
+
     #[test]
     fn test_pre_normalize() {
         let ex = ex1();
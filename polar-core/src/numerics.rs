@@ -1,13 +1,18 @@
+use num_bigint::BigInt;
+use num_integer::Integer as _;
+use num_traits::{FromPrimitive, Signed, ToPrimitive, Zero};
 use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 
 use std::cmp::Ordering;
+use std::convert::TryFrom;
 use std::fmt;
 use std::hash::{Hash, Hasher};
 use std::mem::discriminant;
 use std::num::FpCategory;
-use std::ops::{Add, Div, Mul, Rem, Sub};
+use std::ops::{Add, Div, Mul, Neg, Rem, Sub};
+use std::str::FromStr;
 
-#[derive(Copy, Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub enum Numeric {
     Integer(i64),
 
@@ -16,6 +21,268 @@ pub enum Numeric {
         deserialize_with = "deserialize_float"
     )]
     Float(f64),
+
+    /// An integer that doesn't fit in an `i64`, e.g., the result of arithmetic on snowflake IDs.
+    /// Integer arithmetic that overflows produces one instead of failing. Serialized as a string
+    /// of digits, since not every host can parse large JSON numbers losslessly.
+    #[serde(
+        serialize_with = "serialize_big_integer",
+        deserialize_with = "deserialize_big_integer"
+    )]
+    BigInteger(BigInt),
+
+    /// An exact decimal, written `1.50d` in policies.
+    Decimal(Decimal),
+}
+
+fn serialize_big_integer<S>(i: &BigInt, s: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    s.collect_str(i)
+}
+
+fn deserialize_big_integer<'de, D>(deserializer: D) -> Result<BigInt, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    String::deserialize(deserializer)?
+        .parse()
+        .map_err(|_| de::Error::custom("invalid big integer"))
+}
+
+/// Digits kept after the decimal point when the quotient of two decimals doesn't terminate.
+pub const DECIMAL_DIVISION_SCALE: u32 = 28;
+
+fn pow10(exp: u32) -> BigInt {
+    num_traits::pow(BigInt::from(10), exp as usize)
+}
+
+/// An exact decimal number, `mantissa / 10^scale`. The scale is kept as written, so `1.50d` is
+/// shown as `1.50d`, but it's equal to `1.5d`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Decimal {
+    mantissa: BigInt,
+    scale: u32,
+}
+
+impl Decimal {
+    pub fn new(mantissa: BigInt, scale: u32) -> Self {
+        Self { mantissa, scale }
+    }
+
+    /// The mantissa for this number with `scale` digits after the decimal point, which must be
+    /// at least as many as it has.
+    fn rescale(&self, scale: u32) -> BigInt {
+        &self.mantissa * pow10(scale - self.scale)
+    }
+
+    /// The mantissas of `self` and `other` with the same number of digits after the decimal
+    /// point, and that number.
+    fn align(&self, other: &Self) -> (BigInt, BigInt, u32) {
+        let scale = self.scale.max(other.scale);
+        (self.rescale(scale), other.rescale(scale), scale)
+    }
+
+    /// Drop trailing zeros after the decimal point, keeping at least `min_scale` digits.
+    fn reduce(mut self, min_scale: u32) -> Self {
+        while self.scale > min_scale && (&self.mantissa % 10u32).is_zero() {
+            self.mantissa /= 10u32;
+            self.scale -= 1;
+        }
+        self
+    }
+
+    pub fn is_integer(&self) -> bool {
+        (&self.mantissa % pow10(self.scale)).is_zero()
+    }
+
+    /// The integer part, rounded towards zero.
+    pub fn trunc(&self) -> BigInt {
+        &self.mantissa / pow10(self.scale)
+    }
+
+    /// The nearest float.
+    pub fn to_f64(&self) -> f64 {
+        self.to_string().parse().unwrap_or(f64::NAN)
+    }
+
+    /// The exact value of `f`, or `None` if it's infinite or NaN.
+    pub fn from_f64(f: f64) -> Option<Self> {
+        if !f.is_finite() {
+            return None;
+        }
+        let bits = f.to_bits();
+        let biased_exponent = ((bits >> 52) & 0x7ff) as i32;
+        let fraction = bits & ((1 << 52) - 1);
+        // f = significand * 2^exponent
+        let (significand, exponent) = if biased_exponent == 0 {
+            (fraction, -1074)
+        } else {
+            (fraction | (1 << 52), biased_exponent - 1075)
+        };
+        let mut significand = BigInt::from(significand);
+        if f.is_sign_negative() {
+            significand = -significand;
+        }
+        let decimal = if exponent >= 0 {
+            Self::new(significand << exponent as usize, 0)
+        } else {
+            // 2^-n = 5^n / 10^n
+            let scale = exponent.unsigned_abs();
+            Self::new(
+                significand * num_traits::pow(BigInt::from(5), scale as usize),
+                scale,
+            )
+        };
+        Some(decimal.reduce(0))
+    }
+
+    /// The quotient, rounded half to even to `DECIMAL_DIVISION_SCALE` digits after the decimal
+    /// point if it doesn't terminate before then, or `None` when dividing by zero.
+    pub fn div(&self, other: &Self) -> Option<Self> {
+        if other.mantissa.is_zero() {
+            return None;
+        }
+        let min_scale = self.scale.max(other.scale);
+        let scale = min_scale.max(DECIMAL_DIVISION_SCALE);
+        let numerator = &self.mantissa * pow10(scale + other.scale - self.scale);
+        let (quotient, remainder) = numerator.div_rem(&other.mantissa);
+        let away_from_zero = numerator.signum() * other.mantissa.signum();
+        let quotient = match (remainder.abs() * 2u32).cmp(&other.mantissa.abs()) {
+            Ordering::Greater => quotient + away_from_zero,
+            Ordering::Equal if quotient.is_odd() => quotient + away_from_zero,
+            _ => quotient,
+        };
+        Some(Self::new(quotient, scale).reduce(min_scale))
+    }
+
+    /// The remainder of truncating division, or `None` when dividing by zero.
+    pub fn rem(&self, other: &Self) -> Option<Self> {
+        let (left, right, scale) = self.align(other);
+        (!right.is_zero()).then(|| Self::new(left % right, scale))
+    }
+
+    /// The remainder of flooring division, or `None` when dividing by zero.
+    pub fn modulo(&self, other: &Self) -> Option<Self> {
+        let (left, right, scale) = self.align(other);
+        (!right.is_zero()).then(|| Self::new(left.mod_floor(&right), scale))
+    }
+}
+
+impl Add for &Decimal {
+    type Output = Decimal;
+
+    fn add(self, other: Self) -> Decimal {
+        let (left, right, scale) = self.align(other);
+        Decimal::new(left + right, scale)
+    }
+}
+
+impl Sub for &Decimal {
+    type Output = Decimal;
+
+    fn sub(self, other: Self) -> Decimal {
+        let (left, right, scale) = self.align(other);
+        Decimal::new(left - right, scale)
+    }
+}
+
+impl Mul for &Decimal {
+    type Output = Decimal;
+
+    fn mul(self, other: Self) -> Decimal {
+        Decimal::new(&self.mantissa * &other.mantissa, self.scale + other.scale)
+    }
+}
+
+impl Neg for Decimal {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self::new(-self.mantissa, self.scale)
+    }
+}
+
+impl PartialEq for Decimal {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Decimal {}
+
+impl PartialOrd for Decimal {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Decimal {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let (left, right, _) = self.align(other);
+        left.cmp(&right)
+    }
+}
+
+impl From<BigInt> for Decimal {
+    fn from(mantissa: BigInt) -> Self {
+        Self::new(mantissa, 0)
+    }
+}
+
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.mantissa.is_negative() { "-" } else { "" };
+        let digits = format!(
+            "{:0>width$}",
+            self.mantissa.abs(),
+            width = self.scale as usize + 1
+        );
+        let (integer, fraction) = digits.split_at(digits.len() - self.scale as usize);
+        if fraction.is_empty() {
+            write!(f, "{}{}", sign, integer)
+        } else {
+            write!(f, "{}{}.{}", sign, integer, fraction)
+        }
+    }
+}
+
+impl FromStr for Decimal {
+    type Err = String;
+
+    /// Parse digits with an optional sign and decimal point, e.g., `-1.50`.
+    fn from_str(s: &str) -> Result<Self, String> {
+        let invalid = || format!("invalid decimal `{}`", s);
+        let (integer, fraction) = s.split_once('.').unwrap_or((s, ""));
+        let unsigned = integer.trim_start_matches(['-', '+']);
+        if unsigned.len() + 1 < integer.len()
+            || !(unsigned.chars().chain(fraction.chars())).all(|c| c.is_ascii_digit())
+            || unsigned.len() + fraction.len() == 0
+        {
+            return Err(invalid());
+        }
+        let mantissa = format!("{}{}", integer, fraction)
+            .parse()
+            .map_err(|_| invalid())?;
+        let scale = u32::try_from(fraction.len()).map_err(|_| invalid())?;
+        Ok(Self::new(mantissa, scale))
+    }
+}
+
+impl TryFrom<String> for Decimal {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, String> {
+        s.parse()
+    }
+}
+
+impl From<Decimal> for String {
+    fn from(d: Decimal) -> Self {
+        d.to_string()
+    }
 }
 
 /// Since JSON does not support ±∞ or NaN (RFC 8259 §6),
@@ -86,46 +353,108 @@ where
     deserializer.deserialize_any(FloatVisitor)
 }
 
-impl Add for Numeric {
-    type Output = Option<Self>;
+impl Numeric {
+    /// The nearest float.
+    pub fn to_f64(&self) -> f64 {
+        match self {
+            Numeric::Integer(i) => *i as f64,
+            Numeric::Float(f) => *f,
+            Numeric::BigInteger(i) => i.to_f64().unwrap_or(f64::NAN),
+            Numeric::Decimal(d) => d.to_f64(),
+        }
+    }
 
-    fn add(self, other: Self) -> Option<Self> {
-        match (self, other) {
-            (Numeric::Integer(a), Numeric::Integer(b)) => a.checked_add(b).map(Numeric::Integer),
-            (Numeric::Integer(a), Numeric::Float(b)) => Some(Numeric::Float(a as f64 + b)),
-            (Numeric::Float(a), Numeric::Integer(b)) => Some(Numeric::Float(a + b as f64)),
-            (Numeric::Float(a), Numeric::Float(b)) => Some(Numeric::Float(a + b)),
+    fn to_big_integer(&self) -> Option<BigInt> {
+        match self {
+            Numeric::Integer(i) => Some(BigInt::from(*i)),
+            Numeric::BigInteger(i) => Some(i.clone()),
+            Numeric::Float(_) | Numeric::Decimal(_) => None,
         }
     }
-}
 
-impl Sub for Numeric {
-    type Output = Option<Self>;
+    /// The exact value as a decimal, or `None` for infinite and NaN floats.
+    fn to_decimal(&self) -> Option<Decimal> {
+        match self {
+            Numeric::Float(f) => Decimal::from_f64(*f),
+            Numeric::Decimal(d) => Some(d.clone()),
+            _ => self.to_big_integer().map(Decimal::from),
+        }
+    }
 
-    fn sub(self, other: Self) -> Option<Self> {
-        match (self, other) {
-            (Numeric::Integer(a), Numeric::Integer(b)) => a.checked_sub(b).map(Numeric::Integer),
-            (Numeric::Integer(a), Numeric::Float(b)) => Some(Numeric::Float(a as f64 - b)),
-            (Numeric::Float(a), Numeric::Integer(b)) => Some(Numeric::Float(a - b as f64)),
-            (Numeric::Float(a), Numeric::Float(b)) => Some(Numeric::Float(a - b)),
+    /// Apply an arithmetic operation at the precision of the less precise operand: as floats if
+    /// either is a float, exactly if either is a decimal, and as integers otherwise.
+    fn arithmetic(
+        &self,
+        other: &Self,
+        integer: impl FnOnce(BigInt, BigInt) -> Option<Numeric>,
+        decimal: impl FnOnce(&Decimal, &Decimal) -> Option<Decimal>,
+        float: impl FnOnce(f64, f64) -> f64,
+    ) -> Option<Self> {
+        if matches!(self, Numeric::Float(_)) || matches!(other, Numeric::Float(_)) {
+            Some(Numeric::Float(float(self.to_f64(), other.to_f64())))
+        } else if let (Some(left), Some(right)) = (self.to_big_integer(), other.to_big_integer()) {
+            integer(left, right)
+        } else {
+            decimal(&self.to_decimal()?, &other.to_decimal()?).map(Numeric::Decimal)
         }
     }
-}
 
-impl Numeric {
     pub fn modulo(self, modulus: Self) -> Option<Self> {
         fn modulo(a: f64, b: f64) -> f64 {
             ((a % b) + b) % b
         }
 
-        match (self, modulus) {
-            (Numeric::Integer(a), Numeric::Integer(b)) => {
-                a.checked_rem(b).map(|c| (c + b) % b).map(Numeric::Integer)
+        if let (Numeric::Integer(a), Numeric::Integer(b)) = (&self, &modulus) {
+            if let Some(c) = a
+                .checked_rem(*b)
+                .and_then(|c| c.checked_add(*b))
+                .and_then(|c| c.checked_rem(*b))
+            {
+                return Some(Numeric::Integer(c));
+            }
+        }
+        self.arithmetic(
+            &modulus,
+            |a, b| (!b.is_zero()).then(|| a.mod_floor(&b).into()),
+            Decimal::modulo,
+            modulo,
+        )
+    }
+}
+
+impl Add for Numeric {
+    type Output = Option<Self>;
+
+    fn add(self, other: Self) -> Option<Self> {
+        if let (Numeric::Integer(a), Numeric::Integer(b)) = (&self, &other) {
+            if let Some(c) = a.checked_add(*b) {
+                return Some(Numeric::Integer(c));
+            }
+        }
+        self.arithmetic(
+            &other,
+            |a, b| Some((a + b).into()),
+            |a, b| Some(a + b),
+            |a, b| a + b,
+        )
+    }
+}
+
+impl Sub for Numeric {
+    type Output = Option<Self>;
+
+    fn sub(self, other: Self) -> Option<Self> {
+        if let (Numeric::Integer(a), Numeric::Integer(b)) = (&self, &other) {
+            if let Some(c) = a.checked_sub(*b) {
+                return Some(Numeric::Integer(c));
             }
-            (Numeric::Integer(a), Numeric::Float(b)) => Some(Numeric::Float(modulo(a as f64, b))),
-            (Numeric::Float(a), Numeric::Integer(b)) => Some(Numeric::Float(modulo(a, b as f64))),
-            (Numeric::Float(a), Numeric::Float(b)) => Some(Numeric::Float(modulo(a, b))),
         }
+        self.arithmetic(
+            &other,
+            |a, b| Some((a - b).into()),
+            |a, b| Some(a - b),
+            |a, b| a - b,
+        )
     }
 }
 
@@ -133,12 +462,17 @@ impl Rem for Numeric {
     type Output = Option<Self>;
 
     fn rem(self, other: Self) -> Option<Self> {
-        match (self, other) {
-            (Numeric::Integer(a), Numeric::Integer(b)) => a.checked_rem(b).map(Numeric::Integer),
-            (Numeric::Integer(a), Numeric::Float(b)) => Some(Numeric::Float((a as f64) % b)),
-            (Numeric::Float(a), Numeric::Integer(b)) => Some(Numeric::Float(a % (b as f64))),
-            (Numeric::Float(a), Numeric::Float(b)) => Some(Numeric::Float(a % b)),
+        if let (Numeric::Integer(a), Numeric::Integer(b)) = (&self, &other) {
+            if let Some(c) = a.checked_rem(*b) {
+                return Some(Numeric::Integer(c));
+            }
         }
+        self.arithmetic(
+            &other,
+            |a, b| (!b.is_zero()).then(|| (a % b).into()),
+            Decimal::rem,
+            |a, b| a % b,
+        )
     }
 }
 
@@ -146,25 +480,36 @@ impl Mul for Numeric {
     type Output = Option<Self>;
 
     fn mul(self, other: Self) -> Option<Self> {
-        match (self, other) {
-            (Numeric::Integer(a), Numeric::Integer(b)) => a.checked_mul(b).map(Numeric::Integer),
-            (Numeric::Integer(a), Numeric::Float(b)) => Some(Numeric::Float(a as f64 * b)),
-            (Numeric::Float(a), Numeric::Integer(b)) => Some(Numeric::Float(a * b as f64)),
-            (Numeric::Float(a), Numeric::Float(b)) => Some(Numeric::Float(a * b)),
+        if let (Numeric::Integer(a), Numeric::Integer(b)) = (&self, &other) {
+            if let Some(c) = a.checked_mul(*b) {
+                return Some(Numeric::Integer(c));
+            }
         }
+        self.arithmetic(
+            &other,
+            |a, b| Some((a * b).into()),
+            |a, b| Some(a * b),
+            |a, b| a * b,
+        )
     }
 }
 
 impl Div for Numeric {
     type Output = Option<Self>;
 
+    /// Dividing integers gives a float, as it always has; dividing decimals is exact up to
+    /// `DECIMAL_DIVISION_SCALE` digits.
     fn div(self, other: Self) -> Option<Self> {
-        match (self, other) {
-            (Numeric::Integer(a), Numeric::Integer(b)) => Some(Numeric::Float(a as f64 / b as f64)),
-            (Numeric::Integer(a), Numeric::Float(b)) => Some(Numeric::Float(a as f64 / b)),
-            (Numeric::Float(a), Numeric::Integer(b)) => Some(Numeric::Float(a / b as f64)),
-            (Numeric::Float(a), Numeric::Float(b)) => Some(Numeric::Float(a / b)),
-        }
+        self.arithmetic(
+            &other,
+            |a, b| {
+                Some(Numeric::Float(
+                    Numeric::from(a).to_f64() / Numeric::from(b).to_f64(),
+                ))
+            },
+            Decimal::div,
+            |a, b| a / b,
+        )
     }
 }
 
@@ -195,6 +540,29 @@ impl Hash for Numeric {
                 discriminant(self).hash(state);
                 *i as u64
             }
+            Numeric::BigInteger(i) => {
+                // Hash big integers the same as numerically equal floats.
+                let f = self.to_f64();
+                if BigInt::from_f64(f).as_ref() == Some(i) {
+                    return Numeric::Float(f).hash(state);
+                }
+                discriminant(self).hash(state);
+                return i.hash(state);
+            }
+            Numeric::Decimal(d) => {
+                // Hash decimals the same as numerically equal integers and floats.
+                if d.is_integer() {
+                    return Numeric::from(d.trunc()).hash(state);
+                }
+                let f = d.to_f64();
+                if Decimal::from_f64(f).as_ref() == Some(d) {
+                    return Numeric::Float(f).hash(state);
+                }
+                discriminant(self).hash(state);
+                let Decimal { mantissa, scale } = d.clone().reduce(0);
+                mantissa.hash(state);
+                return scale.hash(state);
+            }
             Numeric::Float(f) => match f.classify() {
                 FpCategory::Zero => {
                     // Canonicalize zero representations.
@@ -251,11 +619,16 @@ impl PartialOrd for Numeric {
                 i.partial_cmp(&(f as i64))
             }
         };
-        match (*self, *other) {
-            (Self::Integer(left), Self::Integer(right)) => left.partial_cmp(&right),
-            (Self::Integer(i), Self::Float(f)) => partial_cmp(i, f),
-            (Self::Float(f), Self::Integer(i)) => partial_cmp(i, f).map(Ordering::reverse),
-            (Self::Float(left), Self::Float(right)) => left.partial_cmp(&right),
+        match (self, other) {
+            (Self::Integer(left), Self::Integer(right)) => left.partial_cmp(right),
+            (Self::Integer(i), Self::Float(f)) => partial_cmp(*i, *f),
+            (Self::Float(f), Self::Integer(i)) => partial_cmp(*i, *f).map(Ordering::reverse),
+            (Self::Float(left), Self::Float(right)) => left.partial_cmp(right),
+            // Compare big integers and decimals exactly, unless one side is infinite or NaN.
+            _ => match (self.to_decimal(), other.to_decimal()) {
+                (Some(left), Some(right)) => left.partial_cmp(&right),
+                _ => self.to_f64().partial_cmp(&other.to_f64()),
+            },
         }
     }
 }
@@ -270,6 +643,20 @@ impl From<f64> for Numeric {
         Self::Float(other)
     }
 }
+/// An `Integer` if it fits, otherwise a `BigInteger`.
+impl From<BigInt> for Numeric {
+    fn from(other: BigInt) -> Self {
+        match other.to_i64() {
+            Some(i) => Self::Integer(i),
+            None => Self::BigInteger(other),
+        }
+    }
+}
+impl From<Decimal> for Numeric {
+    fn from(other: Decimal) -> Self {
+        Self::Decimal(other)
+    }
+}
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -535,4 +922,74 @@ mod tests {
             _ => panic!("expected a float"),
         });
     }
+
+    fn decimal(s: &str) -> Numeric {
+        Numeric::Decimal(s.parse().unwrap())
+    }
+
+    #[test]
+    fn big_integer_promotion() {
+        let max = Numeric::Integer(i64::MAX);
+        let big = (max.clone() + Numeric::Integer(1)).unwrap();
+        assert_eq!(big.to_string(), "9223372036854775808");
+        assert!(matches!(big, Numeric::BigInteger(_)));
+        // Results that fit in 64 bits are integers again.
+        assert_eq!((big.clone() - Numeric::Integer(1)).unwrap(), max);
+        assert!(matches!(
+            (big.clone() - Numeric::Integer(1)).unwrap(),
+            Numeric::Integer(i64::MAX)
+        ));
+        assert!(big > max);
+        assert_eq!(hash(&big), hash(&Numeric::Float(9223372036854775808.0)));
+        assert_eq!(big, Numeric::Float(9223372036854775808.0));
+    }
+
+    #[test]
+    fn decimal_arithmetic() {
+        assert_eq!((decimal("0.10") + decimal("0.20")).unwrap(), decimal("0.3"));
+        assert_eq!(
+            (decimal("1.50") * Numeric::Integer(2)).unwrap(),
+            Numeric::Integer(3)
+        );
+        assert_eq!(hash(&decimal("3.00")), hash(&Numeric::Integer(3)));
+        assert_eq!(hash(&decimal("0.5")), hash(&Numeric::Float(0.5)));
+        assert_eq!(hash(&decimal("0.10")), hash(&decimal("0.1")));
+        assert_eq!(
+            (decimal("1") / decimal("3")).unwrap().to_string(),
+            "0.3333333333333333333333333333d"
+        );
+        assert_eq!(
+            (decimal("2") / decimal("3")).unwrap().to_string(),
+            "0.6666666666666666666666666667d"
+        );
+        assert_eq!((decimal("1") / Numeric::Integer(0)), None);
+        assert_eq!((decimal("-7.5") % decimal("2")).unwrap(), decimal("-1.5"));
+        assert_eq!(
+            decimal("-7.5").modulo(decimal("2")).unwrap(),
+            decimal("0.5")
+        );
+        // Floats win, since a float can't be represented exactly as a decimal.
+        assert!(matches!(
+            (decimal("0.1") + Numeric::Float(0.2)).unwrap(),
+            Numeric::Float(_)
+        ));
+        assert!(decimal("0.1") != Numeric::Float(0.1));
+        assert!(decimal("0.1") < Numeric::Float(0.1));
+    }
+
+    #[test]
+    fn big_integer_and_decimal_json() {
+        let big = (Numeric::Integer(i64::MAX) + Numeric::Integer(1)).unwrap();
+        assert_eq!(
+            to_json(&big).unwrap(),
+            r#"{"BigInteger":"9223372036854775808"}"#
+        );
+        assert_eq!(from_json::<Numeric>(&to_json(&big).unwrap()).unwrap(), big);
+        assert_eq!(to_json(&decimal("1.50")).unwrap(), r#"{"Decimal":"1.50"}"#);
+        assert_eq!(
+            from_json::<Numeric>(r#"{"Decimal":"-1.50"}"#).unwrap(),
+            decimal("-1.5")
+        );
+        assert!(from_json::<Numeric>(r#"{"Decimal":"1.5.0"}"#).is_err());
+    }
 }
@@ -11,6 +11,11 @@ use super::{
     terms::*,
 };
 
+/// The method that joins the segments of an interpolated string, e.g., `f"user-{id}"` is parsed as
+/// `"".<interpolate>(["user-", id])`. It can't be called from a policy, so it never shadows a host
+/// method, and it's formatted back as an interpolated string.
+pub const INTERPOLATE: &str = "<interpolate>";
+
 /// Used to denote whether an enclosed value is a value or a logical operator
 pub enum ValueOrLogical {
     Value(Term),
@@ -27,7 +32,21 @@ lalrpop_mod!(
 pub enum Line {
     Rule(Rule),
     RuleType(Rule),
-    Query(Term),
+    DeprecatedRule {
+        message: Term,
+        rule: Rule,
+    },
+    /// A rule annotated with `@private`, which may only be called from its own source file.
+    PrivateRule(Rule),
+    Query {
+        term: Term,
+        /// The results the query must have. See [`crate::inline_query`].
+        expected: Option<Term>,
+    },
+    UnionType {
+        name: Term,
+        members: Vec<Term>,
+    },
     ResourceBlock {
         keyword: Option<Term>,
         resource: Term,
@@ -218,7 +237,13 @@ mod tests {
         let f = "?= f(1);";
         let line = parse_lines(f);
 
-        assert_eq!(line[0], Line::Query(term!(call!("f", [1]))));
+        assert_eq!(
+            line[0],
+            Line::Query {
+                term: term!(call!("f", [1])),
+                expected: None
+            }
+        );
 
         let rule_type = "type f(x: String);";
         let line = parse_lines(rule_type);
@@ -228,6 +253,49 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_deprecated_rule() {
+        let line = parse_lines(r#"@deprecated("use g/1") f(x) if x = 1;"#);
+        assert_eq!(
+            line[0],
+            Line::DeprecatedRule {
+                message: term!("use g/1"),
+                rule: rule!("f", [sym!("x")] => op!(Unify, term!(sym!("x")), term!(1))),
+            }
+        );
+        super::parse_lines(Source::new(r#"@experimental("x") f(x);"#)).unwrap_err();
+        super::parse_lines(Source::new(r#"@deprecated("x") type f(x);"#)).unwrap_err();
+    }
+
+    #[test]
+    fn test_parse_private_rule() {
+        let line = parse_lines("@private f(x) if x = 1;");
+        assert_eq!(
+            line[0],
+            Line::PrivateRule(rule!("f", [sym!("x")] => op!(Unify, term!(sym!("x")), term!(1))))
+        );
+        super::parse_lines(Source::new("@internal f(x);")).unwrap_err();
+        super::parse_lines(Source::new("@private type f(x);")).unwrap_err();
+    }
+
+    #[test]
+    fn test_parse_union_type() {
+        let line = parse_lines("type Resource = Repo | Issue | Org;");
+        assert_eq!(
+            line[0],
+            Line::UnionType {
+                name: term!(sym!("Resource")),
+                members: vec![
+                    term!(sym!("Repo")),
+                    term!(sym!("Issue")),
+                    term!(sym!("Org"))
+                ],
+            }
+        );
+        super::parse_lines(Source::new("type Resource = ;")).unwrap_err();
+        super::parse_lines(Source::new("type Resource = Repo | 1;")).unwrap_err();
+    }
+
     #[test]
     fn test_rule_type_error() {
         let rule_type = r#"type f(x: String) if x = "bad";"#;
@@ -321,6 +389,31 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_string_interpolation() {
+        let q = r#"f"user-{x.id}" = f"a{1}{2}""#;
+        assert_eq!(parse_term(q).to_string(), q);
+
+        // Braces are literal in strings without the prefix, and when escaped.
+        let q = r#""{x}" = f"{"{"}\{}""#;
+        assert_eq!(
+            parse_term(q),
+            term!(op!(
+                Unify,
+                term!("{x}"),
+                term!(op!(
+                    Dot,
+                    term!(""),
+                    term!(call!(INTERPOLATE, [vec![term!("{"), term!("{}")]]))
+                ))
+            ))
+        );
+        assert_eq!(parse_term(q).to_string(), r#""{x}" = f"\{\{}""#);
+
+        assert!(super::parse_query(r#"f"{x""#).is_err());
+        assert!(super::parse_query(r#"f"{}""#).is_err());
+    }
+
     #[test]
     fn test_catching_wrong_types() {
         for bad_query in &[
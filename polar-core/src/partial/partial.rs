@@ -299,7 +299,7 @@ mod test {
     use crate::error::{ErrorKind, PolarError, PolarResult, RuntimeError};
     use crate::events::QueryEvent;
     use crate::polar::Polar;
-    use crate::query::Query;
+    use crate::query::{Query, QueryFlags};
     use crate::terms::Dictionary;
 
     macro_rules! assert_partial_expression {
@@ -353,7 +353,7 @@ mod test {
                         "Bindings: {}",
                         bindings
                             .iter()
-                            .map(|(k, v)| format!("{}: {}", k.0, v))
+                            .map(|(k, v)| format!("{}: {}", k.as_str(), v))
                             .collect::<Vec<_>>()
                             .join("\n")
                     )
@@ -486,7 +486,7 @@ mod test {
         // NOTE(gj): only one permutation remains parse-able.
         p.load_str("m(x) if [_y] matches [x];")?;
         let mut q = p.new_query_from_term(term!(call!("m", [sym!("x")])), false);
-        assert_partial_expression!(next_binding(&mut q)?, "x", "__y_36 matches _this");
+        assert_partial_expression!(next_binding(&mut q)?, "x", "_1 matches _this");
         assert_query_done!(q);
 
         // TODO(gj): Make the below work.
@@ -516,8 +516,7 @@ mod test {
         assert_eq!(next[&sym!("x")], term!(sym!("x")));
         assert_eq!(
             next[&sym!("y")],
-            // TODO(gj): do something with the x <-> _x_5 cycle?
-            term!(btreemap! { sym!("x") => term!(sym!("_x0_5")) })
+            term!(btreemap! { sym!("x") => term!(sym!("x")) })
         );
         assert_query_done!(q);
 
@@ -702,8 +701,13 @@ mod test {
                     left_class_tag,
                     right_class_tag,
                 } => {
-                    q.question_result(call_id, left_class_tag.0.starts_with(&right_class_tag.0))
-                        .unwrap();
+                    q.question_result(
+                        call_id,
+                        left_class_tag
+                            .as_str()
+                            .starts_with(right_class_tag.as_str()),
+                    )
+                    .unwrap();
                 }
                 QueryEvent::Done { .. } => return None,
                 _ => panic!("not bindings"),
@@ -821,8 +825,13 @@ mod test {
                     left_class_tag,
                     right_class_tag,
                 } => {
-                    q.question_result(call_id, left_class_tag.0.starts_with(&right_class_tag.0))
-                        .unwrap();
+                    q.question_result(
+                        call_id,
+                        left_class_tag
+                            .as_str()
+                            .starts_with(right_class_tag.as_str()),
+                    )
+                    .unwrap();
                 }
                 e => panic!("unexpected event: {:?}", e),
             }
@@ -867,8 +876,13 @@ mod test {
                     left_class_tag,
                     right_class_tag,
                 } => {
-                    q.question_result(call_id, left_class_tag.0.starts_with(&right_class_tag.0))
-                        .unwrap();
+                    q.question_result(
+                        call_id,
+                        left_class_tag
+                            .as_str()
+                            .starts_with(right_class_tag.as_str()),
+                    )
+                    .unwrap();
                 }
                 _ => panic!("not bindings"),
             }
@@ -1052,7 +1066,7 @@ mod test {
                         let last_segment = path.last().unwrap();
                         q.question_result(
                             call_id,
-                            last_segment.as_string().unwrap().to_uppercase() == class_tag.0,
+                            last_segment.as_string().unwrap().to_uppercase() == *class_tag.as_str(),
                         )
                         .unwrap();
                     }
@@ -1100,7 +1114,7 @@ mod test {
         assert_partial_expression!(
             next_binding(&mut q),
             "x",
-            "_this matches A{} and _b_81 in _this.b and _b_81 matches B{} and 1 = _b_81.foo"
+            "_this matches A{} and _1 in _this.b and _1 matches B{} and 1 = _1.foo"
         );
         assert_query_done!(q);
 
@@ -1108,7 +1122,7 @@ mod test {
         assert_partial_expression!(
             next_binding(&mut q),
             "x",
-            "_this matches A{} and _b_92 in _this.b and _b_92 matches B{} and _b_92.c matches C{} and 1 = _b_92.c.bar"
+            "_this matches A{} and _1 in _this.b and _1 matches B{} and _1.c matches C{} and 1 = _1.c.bar"
         );
         assert_query_done!(q);
 
@@ -1116,15 +1130,15 @@ mod test {
         assert_partial_expression!(
             next_binding(&mut q),
             "x",
-            "_this matches A{} and _b_115 in _this.b and _b_115.c matches C{} and 1 = _b_115.c.bar"
+            "_this matches A{} and _1 in _this.b and _1.c matches C{} and 1 = _1.c.bar"
         );
         // @TODO(sam): this result is incorrect. We *could* know
-        // that `_b_104` matches B{} by checking `a.b` first
+        // that `_1` matches B{} by checking `a.b` first
         // or perhaps by also traversing `in` and checking whether a.b.c matches D
         assert_partial_expression!(
             next_binding(&mut q),
             "x",
-            "_this matches A{} and _b_115 in _this.b and _b_115.c matches D{} and 2 = _b_115.c.bar"
+            "_this matches A{} and _1 in _this.b and _1.c matches D{} and 2 = _1.c.bar"
         );
         assert_query_done!(q);
         Ok(())
@@ -1563,7 +1577,7 @@ mod test {
                 And,
                 term!(op!(
                     Neq,
-                    var!("__y_9"),
+                    var!("_1"),
                     term!(op!(Dot, var!("_this"), str!("foo")))
                 ))
             ))
@@ -1577,7 +1591,7 @@ mod test {
                 And,
                 term!(op!(
                     Neq,
-                    var!("__y_17"),
+                    var!("_1"),
                     term!(op!(
                         Dot,
                         term!(op!(Dot, var!("_this"), str!("foo"))),
@@ -1697,7 +1711,7 @@ mod test {
         let mut q = p.new_query_from_term(term!(call!("f", [sym!("x")])), false);
         assert_partial_expressions!(
             next_binding(&mut q)?,
-            "x" => "__y_12 in _this.values"
+            "x" => "_1 in _this.values"
         );
         assert_query_done!(q);
 
@@ -1710,11 +1724,11 @@ mod test {
         let mut q = p.new_query_from_term(term!(call!("h", [sym!("x")])), false);
         assert_partial_expressions!(
             next_binding(&mut q)?,
-            "x" => "_y_27 in _this.values and 1 = _y_27.bar and 2 = _y_27.baz"
+            "x" => "_1 in _this.values and 1 = _1.bar and 2 = _1.baz"
         );
         assert_partial_expressions!(
             next_binding(&mut q)?,
-            "x" => "_y_27 in _this.values and 3 = _y_27.bar"
+            "x" => "_1 in _this.values and 3 = _1.bar"
         );
         assert_query_done!(q);
 
@@ -1724,7 +1738,7 @@ mod test {
         assert_query_done!(q);
 
         let mut q = p.new_query_from_term(term!(call!("l", [sym!("x")])), false);
-        assert_partial_expressions!(next_binding(&mut q)?, "x" => "__y_36 in _this");
+        assert_partial_expressions!(next_binding(&mut q)?, "x" => "_1 in _this");
         assert_query_done!(q);
 
         let mut q = p.new_query_from_term(term!(call!("m", [sym!("x")])), false);
@@ -1733,6 +1747,33 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_anonymous_vars_in_partials() -> TestResult {
+        let p = Polar::new();
+        p.load_str(
+            r#"f(x) if y in x.a and z in x.b and y.c = z.c;
+               g(x, y) if z in x.a and z in y.b;"#,
+        )?;
+
+        // Distinct variables get distinct names...
+        let mut q = p.new_query_from_term(term!(call!("f", [sym!("x")])), false);
+        assert_partial_expressions!(
+            next_binding(&mut q)?,
+            "x" => "_1 in _this.a and _2 in _this.b and _2.c = _1.c"
+        );
+        assert_query_done!(q);
+
+        // ...and the same variable gets the same name in every binding.
+        let mut q = p.new_query_from_term(term!(call!("g", [sym!("x"), sym!("y")])), false);
+        assert_partial_expressions!(
+            next_binding(&mut q)?,
+            "x" => "_1 in _this.a and _1 in y.b",
+            "y" => "_1 in x.a and _1 in _this.b"
+        );
+        assert_query_done!(q);
+        Ok(())
+    }
+
     #[test]
     fn test_in_partial_2() -> TestResult {
         let p = Polar::new();
@@ -2348,7 +2389,7 @@ mod test {
         // well, this is semi-successful!
         assert_partial_expressions!(
             next_binding(&mut q)?,
-            "x" => "_this = _y_26.foo and 1 = _y_26.bar"
+            "x" => "_this = _1.foo and 1 = _1.bar"
         );
 
         assert_query_done!(q);
@@ -2376,4 +2417,35 @@ mod test {
         assert_query_done!(q);
         Ok(())
     }
+
+    #[test]
+    fn test_partial_output_forms() -> TestResult {
+        let p = Polar::new();
+        p.load_str(
+            r#"f(x) if x.b > 0 and x.a = 1;
+               g(x) if not (x.a = 1 and x.b = 2) and x.c = 3;"#,
+        )?;
+        let partial = |src, flags| -> PolarResult<Term> {
+            let mut q = p.new_query(src, false)?;
+            q.set_flags(flags);
+            Ok(next_binding(&mut q)?[&sym!("x")].clone())
+        };
+
+        let simplified = partial("f(x)", QueryFlags::NONE)?;
+        assert_eq!(simplified.to_string(), "_this.b > 0 and 1 = _this.a");
+        let canonical = partial("f(x)", QueryFlags::CANONICAL_PARTIALS)?;
+        assert_eq!(canonical.to_string(), "1 = _this.a and _this.b > 0");
+
+        // In DNF, even a single conjunction is wrapped in a disjunction.
+        let dnf = partial("f(x)", QueryFlags::DNF_PARTIALS)?;
+        assert_eq!(dnf.as_expression()?.operator, Operator::Or);
+        assert_eq!(dnf.as_expression()?.args, vec![canonical]);
+
+        let dnf = partial("g(x)", QueryFlags::DNF_PARTIALS)?;
+        assert_eq!(
+            dnf.to_string(),
+            "1 != _this.a and 3 = _this.c or 2 != _this.b and 3 = _this.c"
+        );
+        Ok(())
+    }
 }
@@ -79,6 +79,54 @@ pub fn sub_this(this: Symbol, term: Term) -> Term {
     fold_term(term, &mut VariableSubber::new(this))
 }
 
+/// Renames anonymous variables in result values to `_1`, `_2`, etc.
+struct AnonymousVariableHider<F> {
+    named_alias: F,
+    names: HashMap<Symbol, Symbol>,
+}
+
+impl<F: FnMut(&Symbol) -> Option<Symbol>> Folder for AnonymousVariableHider<F> {
+    fn fold_variable(&mut self, v: Symbol) -> Symbol {
+        if !v.is_temporary_var() || v.is_this_var() {
+            return v;
+        }
+        if let Some(alias) = (self.named_alias)(&v) {
+            return alias;
+        }
+        let next = self.names.len() + 1;
+        self.names
+            .entry(v)
+            .or_insert_with(|| Symbol::new(&format!("_{}", next)))
+            .clone()
+    }
+
+    fn fold_rest_variable(&mut self, v: Symbol) -> Symbol {
+        self.fold_variable(v)
+    }
+}
+
+/// Rename unbound anonymous variables in the values of `bindings`, including those in partials,
+/// so that the names generated for them, e.g., `_x_5` for a `_x` in a rule, don't leak into query
+/// results. Each variable gets the same new name everywhere it appears in the bindings, and
+/// distinct variables get distinct names. Anonymous variables bound together with a named
+/// variable are replaced by that variable, as given by `named_alias`.
+pub fn hide_anonymous_vars<F>(bindings: Bindings, named_alias: F) -> Bindings
+where
+    F: FnMut(&Symbol) -> Option<Symbol>,
+{
+    let mut hider = AnonymousVariableHider {
+        named_alias,
+        names: HashMap::new(),
+    };
+    // Name variables in the same order for every run of a query.
+    let mut bindings = bindings.into_iter().collect::<Vec<_>>();
+    bindings.sort_by(|a, b| a.0.cmp(&b.0));
+    bindings
+        .into_iter()
+        .map(|(var, value)| (var, hider.fold_term(value)))
+        .collect()
+}
+
 /// Turn `_this = x` into `x` when it's ground.
 fn simplify_trivial_constraint(this: Symbol, term: Term) -> Term {
     use {Operator::*, Value::*};
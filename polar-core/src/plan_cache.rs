@@ -0,0 +1,335 @@
+//! Reusing the work of partial queries that only differ in their parameters.
+//!
+//! Data filtering runs the same partial query, e.g., `allow(actor, "read", resource)`, for every
+//! request, and only the actor changes. Most of the work is in finding the applicable rules and
+//! simplifying the constraints they put on `resource`. A [`CachedPartialQuery`] runs the query
+//! once with its parameters left unbound and keeps the constraints of its results as a template,
+//! with one conjunction of constraints per result. Queries of the same shape then only evaluate
+//! the template with their parameters bound, which leaves the constraints on the partials.
+//!
+//! Templates belong to the version of the KB they were made from, so loading a policy
+//! invalidates them.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use crate::bindings::Bindings;
+use crate::error::PolarResult;
+use crate::events::QueryEvent;
+use crate::folder::{fold_term, Folder};
+use crate::kb::KnowledgeBase;
+use crate::parser;
+use crate::polar::Polar;
+use crate::query::Query;
+use crate::terms::{Operation, Operator, Symbol, Term, Value};
+
+/// What a template is for: the query, the partials it filters and the names of its parameters.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct PlanKey {
+    /// The version of the KB the template was made from.
+    epoch: u64,
+    query: Term,
+    partials: Vec<(Symbol, Term)>,
+    params: Vec<Symbol>,
+}
+
+/// Templates of partial queries, shared by the queries of a `Polar` instance.
+#[derive(Default)]
+pub struct PlanCache {
+    templates: Mutex<HashMap<PlanKey, Term>>,
+}
+
+impl PlanCache {
+    fn get(&self, key: &PlanKey) -> Option<Term> {
+        self.lock().get(key).cloned()
+    }
+
+    /// Add a template, dropping those made from older versions of the KB.
+    fn insert(&self, key: PlanKey, template: Term) {
+        let mut templates = self.lock();
+        templates.retain(|other, _| other.epoch >= key.epoch);
+        templates.insert(key, template);
+    }
+
+    /// The number of cached templates.
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<PlanKey, Term>> {
+        self.templates
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// A partial query that evaluates a cached template when there is one, and makes one first
+/// otherwise. Answer its events through [`CachedPartialQuery::query_mut`].
+pub struct CachedPartialQuery<'polar> {
+    polar: &'polar Polar,
+    kb: Arc<KnowledgeBase>,
+    key: PlanKey,
+    bindings: Bindings,
+    query: Query,
+    /// The results of the query with its parameters unbound, while the template is being made.
+    recording: Option<Vec<Bindings>>,
+}
+
+impl<'polar> CachedPartialQuery<'polar> {
+    /// Bindings to partials, i.e., expressions, are part of the shape of the query. All other
+    /// bindings are parameters, which may differ between queries using the same template.
+    pub(crate) fn new(polar: &'polar Polar, src: &str, bindings: Bindings) -> PolarResult<Self> {
+        let term = parser::parse_query(src)?;
+        let (epoch, kb) = polar.kb.versioned_snapshot();
+        let mut partials = vec![];
+        let mut params = vec![];
+        for (name, value) in &bindings {
+            if matches!(value.value(), Value::Expression(_)) {
+                partials.push((name.clone(), value.clone()));
+            } else {
+                params.push(name.clone());
+            }
+        }
+        partials.sort_by(|a, b| a.0.cmp(&b.0));
+        params.sort();
+        let key = PlanKey {
+            epoch,
+            query: term.clone(),
+            partials,
+            params,
+        };
+
+        if let Some(template) = polar.plan_cache.get(&key) {
+            let query = polar.new_query_against(kb.clone(), template, false, None, None);
+            let mut cached = Self {
+                polar,
+                kb,
+                key,
+                bindings,
+                query,
+                recording: None,
+            };
+            cached.bind_all()?;
+            return Ok(cached);
+        }
+
+        let mut query = polar.new_query_against(kb.clone(), term, false, None, None);
+        for (name, value) in &bindings {
+            let value = match value.value() {
+                Value::Expression(_) => value.clone(),
+                _ => Term::from(Operation {
+                    operator: Operator::And,
+                    args: vec![],
+                }),
+            };
+            query.bind(name.clone(), value)?;
+        }
+        Ok(Self {
+            polar,
+            kb,
+            key,
+            bindings,
+            query,
+            recording: Some(vec![]),
+        })
+    }
+
+    fn bind_all(&mut self) -> PolarResult<()> {
+        for (name, value) in &self.bindings {
+            self.query.bind(name.clone(), value.clone())?;
+        }
+        Ok(())
+    }
+
+    /// Run the query until the host has to answer an event, it has a result, or it is done.
+    /// While the template is being made, only the events the host has to answer are returned.
+    pub fn next_event(&mut self) -> PolarResult<QueryEvent> {
+        loop {
+            let event = self.query.next_event()?;
+            let results = match &mut self.recording {
+                Some(results) => results,
+                None => return Ok(event),
+            };
+            match event {
+                QueryEvent::Result { bindings, .. } => results.push(bindings),
+                QueryEvent::Done { .. } => {
+                    let template = template(std::mem::take(results));
+                    self.recording = None;
+                    self.polar
+                        .plan_cache
+                        .insert(self.key.clone(), template.clone());
+                    self.query =
+                        self.polar
+                            .new_query_against(self.kb.clone(), template, false, None, None);
+                    self.bind_all()?;
+                }
+                event => return Ok(event),
+            }
+        }
+    }
+
+    /// The query being run: the one recording the template until it's made, then the one
+    /// against the cached template.
+    pub fn query_mut(&mut self) -> &mut Query {
+        &mut self.query
+    }
+}
+
+/// Substitutes a variable for `_this` in a partial, the inverse of
+/// [`sub_this`](crate::partial::sub_this).
+struct ThisSubber(Symbol);
+
+impl Folder for ThisSubber {
+    fn fold_variable(&mut self, v: Symbol) -> Symbol {
+        if v.is_this_var() {
+            self.0.clone()
+        } else {
+            v
+        }
+    }
+}
+
+/// A query equivalent to the results of a partial query: a disjunction with the constraints
+/// each result puts on the query's variables.
+fn template(results: Vec<Bindings>) -> Term {
+    let mut disjuncts = results
+        .into_iter()
+        .map(|bindings| {
+            let mut bindings = bindings.into_iter().collect::<Vec<_>>();
+            bindings.sort_by(|a, b| a.0.cmp(&b.0));
+            let mut constraints = vec![];
+            for (var, value) in bindings {
+                if !matches!(value.value(), Value::Expression(_)) {
+                    constraints.push(Term::from(Operation {
+                        operator: Operator::Unify,
+                        args: vec![Term::from(var), value],
+                    }));
+                    continue;
+                }
+                let value = fold_term(value, &mut ThisSubber(var));
+                match value.value() {
+                    Value::Expression(Operation {
+                        operator: Operator::And,
+                        args,
+                    }) => constraints.extend(args.iter().cloned()),
+                    _ => constraints.push(value),
+                }
+            }
+            Term::from(Operation {
+                operator: Operator::And,
+                args: constraints,
+            })
+        })
+        .collect::<Vec<_>>();
+    match disjuncts.len() {
+        0 => Term::from(false),
+        1 => disjuncts.remove(0),
+        _ => Term::from(Operation {
+            operator: Operator::Or,
+            args: disjuncts,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terms::Dictionary;
+
+    fn user(id: i64, admin: bool) -> Term {
+        Term::from(Value::Dictionary(Dictionary {
+            fields: btreemap! {
+                sym!("id") => term!(id),
+                sym!("admin") => term!(admin),
+            },
+        }))
+    }
+
+    fn filters(query: &mut CachedPartialQuery) -> Vec<String> {
+        let mut filters = vec![];
+        loop {
+            match query.next_event().unwrap() {
+                QueryEvent::Result { bindings, .. } => {
+                    filters.push(bindings[&sym!("post")].to_string())
+                }
+                QueryEvent::Done { .. } => break,
+                event => panic!("unexpected event {:?}", event),
+            }
+        }
+        filters.sort();
+        filters
+    }
+
+    #[test]
+    fn test_templates_are_reused_until_the_policy_changes() {
+        let p = Polar::new();
+        let policy = r#"
+            allow(user, "read", post) if post.author_id = user.id;
+            allow(user, "read", post) if user.admin = true and post.draft = true;
+            allow(_user, "read", post) if post.public = true;
+        "#;
+        p.load_str(policy).unwrap();
+        let src = r#"allow(user, "read", post)"#;
+        let bindings = |user| hashmap! {sym!("user") => user, sym!("post") => term!(op!(And))};
+
+        let mut query = p
+            .new_cached_partial_query(src, bindings(user(1, false)))
+            .unwrap();
+        assert!(query.recording.is_some());
+        assert_eq!(
+            filters(&mut query),
+            vec!["1 = _this.author_id", "true = _this.public"]
+        );
+        assert_eq!(p.plan_cache.len(), 1);
+
+        let mut query = p
+            .new_cached_partial_query(src, bindings(user(2, true)))
+            .unwrap();
+        assert!(query.recording.is_none());
+        assert_eq!(
+            filters(&mut query),
+            vec![
+                "2 = _this.author_id",
+                "true = _this.draft",
+                "true = _this.public"
+            ]
+        );
+
+        // Partials are part of the shape of the query.
+        let other = hashmap! {
+            sym!("user") => user(2, true),
+            sym!("post") => term!(op!(And, term!(op!(Unify, term!(1), var!("_this")))))
+        };
+        let query = p.new_cached_partial_query(src, other).unwrap();
+        assert!(query.recording.is_some());
+
+        p.clear_rules();
+        p.load_str(policy).unwrap();
+        let query = p
+            .new_cached_partial_query(src, bindings(user(1, false)))
+            .unwrap();
+        assert!(query.recording.is_some());
+    }
+
+    #[test]
+    fn test_templates_without_results() {
+        let p = Polar::new();
+        p.load_str("allow(user, _, _) if user.admin = true;")
+            .unwrap();
+        let src = "allow(user, action, post)";
+        let bindings = hashmap! {
+            sym!("user") => user(1, false),
+            sym!("action") => term!("read"),
+            sym!("post") => term!(op!(And)),
+        };
+        let mut query = p.new_cached_partial_query(src, bindings.clone()).unwrap();
+        assert!(filters(&mut query).is_empty());
+        let mut query = p.new_cached_partial_query(src, bindings).unwrap();
+        assert!(query.recording.is_none());
+        assert!(filters(&mut query).is_empty());
+    }
+}
@@ -1,26 +1,59 @@
-use std::sync::{Arc, RwLock};
+use std::collections::{hash_map::Entry, HashMap, HashSet};
+use std::sync::{Arc, Mutex, PoisonError, RwLock};
 
+use super::analysis::{parse_without_loading, Analysis};
+use super::authorization::Authorization;
+use super::batch::BatchQuery;
+use super::builtins::BuiltinPredicate;
+use super::counter::Counter;
 use super::data_filtering::{build_filter_plan, FilterPlan, PartialResults, Types};
-use super::diagnostic::Diagnostic;
-use super::error::{PolarResult, RuntimeError, ValidationError};
+use super::diagnostic::{sarif::SarifLog, structured::StructuredDiagnostic, Diagnostic};
+use super::diff::{self, PolicyDiff, PolicyVersion};
+use super::error::{unsupported, PolarResult, RuntimeError};
 use super::filter::Filter;
+use super::formatting::TermFormatter;
+use super::inline_query::InlineQuery;
+use super::introspection::PolicyAst;
 use super::kb::*;
 use super::messages::*;
+use super::metrics::QueryMetrics;
 use super::parser;
+use super::plan_cache::{CachedPartialQuery, PlanCache};
 use super::query::Query;
-use super::resource_block::resource_block_from_productions;
 use super::rewrites::*;
+use super::session::{Session, SessionFacts};
+use super::snapshot::SharedKnowledgeBase;
 use super::sources::*;
 use super::terms::*;
+use super::type_check::{check_types, TypeChecking};
 use super::validations::{
-    check_ambiguous_precedence, check_no_allow_rule, check_resource_blocks_missing_has_permission,
-    check_singletons,
+    check_deprecated_rule_calls, check_effectful_call_ordering, check_no_allow_rule,
+    check_redundant_rules, check_resource_blocks_missing_has_permission,
 };
+use super::vm::QueryLimits;
 
 pub struct Polar {
-    pub kb: Arc<RwLock<KnowledgeBase>>,
+    /// Queries run against a snapshot of the KB taken when they start, so loads don't wait for
+    /// running queries and vice versa.
+    pub kb: SharedKnowledgeBase,
     messages: MessageQueue,
     ignore_no_allow_warning: bool,
+    warn_on_cycles: bool,
+    native_string_methods: bool,
+    deterministic_seed: Option<u64>,
+    type_checking: TypeChecking,
+    query_rewriter: Option<Arc<dyn QueryRewriter>>,
+    term_formatter: TermFormatter,
+    query_limits: QueryLimits,
+    /// Policies loaded into namespaces, e.g., one per tenant, each extending the policy in `kb`.
+    namespaces: RwLock<HashMap<String, Arc<KnowledgeBase>>>,
+    /// Metrics of the queries that have finished, added when each query is dropped.
+    metrics: Arc<Mutex<QueryMetrics>>,
+    /// Templates of partial queries, for the version of `kb` they were made from.
+    pub(crate) plan_cache: PlanCache,
+    /// Inline queries taken out of `kb` that haven't been run yet. They're all taken at once so
+    /// that running them doesn't publish a new KB per query.
+    inline_queries: Mutex<Vec<InlineQuery>>,
 }
 
 impl Default for Polar {
@@ -38,77 +71,68 @@ impl Polar {
         // variables for new configuration use-cases.
         let ignore_no_allow_warning = std::env::var("POLAR_IGNORE_NO_ALLOW_WARNING").is_ok();
         Self {
-            kb: Arc::new(RwLock::new(KnowledgeBase::new())),
+            kb: SharedKnowledgeBase::new(KnowledgeBase::new()),
             messages: MessageQueue::new(),
             ignore_no_allow_warning,
+            warn_on_cycles: false,
+            native_string_methods: false,
+            deterministic_seed: None,
+            type_checking: TypeChecking::default(),
+            query_rewriter: None,
+            term_formatter: TermFormatter::default(),
+            namespaces: RwLock::new(HashMap::new()),
+            query_limits: QueryLimits::default(),
+            metrics: Arc::default(),
+            plan_cache: PlanCache::default(),
+            inline_queries: Mutex::default(),
         }
     }
 
     /// Load `sources` into the KB, returning compile-time diagnostics accumulated during the load.
     pub fn diagnostic_load(&self, sources: Vec<Source>) -> Vec<Diagnostic> {
-        // Separate function so that errors returned with `?` are captured.
-        fn load_source(source: Source, kb: &mut KnowledgeBase) -> PolarResult<Vec<Diagnostic>> {
-            if let Some(ref filename) = source.filename {
-                kb.add_source(filename, &source.src)?;
-            }
-            // TODO(gj): we still bomb out at the first ParseError.
-            let mut lines = parser::parse_lines(source)?;
-            lines.reverse();
-            let mut diagnostics = vec![];
-            while let Some(line) = lines.pop() {
-                match line {
-                    parser::Line::Rule(rule) => {
-                        diagnostics.append(&mut check_singletons(&rule, kb));
-                        diagnostics.append(&mut check_ambiguous_precedence(&rule));
-                        let rule = rewrite_rule(rule, kb);
-                        kb.add_rule(rule);
-                    }
-                    parser::Line::Query(term) => {
-                        kb.inline_queries.push(term);
-                    }
-                    parser::Line::RuleType(rule_type) => {
-                        // make sure rule_type doesn't have anything that needs to be rewritten in the head
-                        let rule_type = rewrite_rule(rule_type, kb);
-                        if !matches!(
-                            rule_type.body.value(),
-                            Value::Expression(
-                                Operation {
-                                    operator: Operator::And,
-                                    args
-                                }
-                            ) if args.is_empty()
-                        ) {
-                            diagnostics.push(Diagnostic::Error(
-                                ValidationError::InvalidRuleType {
-                                    rule_type,
-                                    msg: "Rule types cannot contain dot lookups.".into(),
-                                }
-                                .into(),
-                            ));
-                        } else {
-                            kb.add_rule_type(rule_type);
-                        }
-                    }
-                    parser::Line::ResourceBlock {
-                        keyword,
-                        resource,
-                        productions,
-                    } => {
-                        let (block, mut errors) =
-                            resource_block_from_productions(keyword, resource, productions);
-                        errors.append(&mut block.add_to_kb(kb));
-                        diagnostics.extend(errors.into_iter().map(Into::into));
-                    }
-                }
-            }
-            Ok(diagnostics)
-        }
+        self.kb.update(|kb| self.diagnostic_load_into(kb, sources))
+    }
+
+    /// Check `sources` without loading them, returning their diagnostics as a SARIF log.
+    ///
+    /// Registered constants and classes are taken into account, but the currently loaded policy
+    /// is not.
+    pub fn diagnostics_sarif(&self, sources: Vec<Source>) -> SarifLog {
+        let mut kb = KnowledgeBase::clone(&self.kb.snapshot());
+        kb.clear_rules();
+        SarifLog::new(&self.diagnostic_load_into(&mut kb, sources))
+    }
+
+    /// Parse `sources` for editor tooling without loading them. See `analysis`.
+    ///
+    /// Registered constants are offered as completions.
+    pub fn analyze(&self, sources: Vec<Source>) -> Analysis {
+        let mut analysis = parse_without_loading(sources);
+        let kb = self.kb.snapshot();
+        analysis.set_constants(kb.get_registered_constants().keys().cloned().collect());
+        analysis
+    }
+
+    /// Check `sources` without loading them, like `diagnostics_sarif`, returning their
+    /// diagnostics as structured data.
+    pub fn diagnostics_structured(&self, sources: Vec<Source>) -> Vec<StructuredDiagnostic> {
+        let mut kb = KnowledgeBase::clone(&self.kb.snapshot());
+        kb.clear_rules();
+        self.diagnostic_load_into(&mut kb, sources)
+            .iter()
+            .map(StructuredDiagnostic::from)
+            .collect()
+    }
 
-        let mut kb = self.kb.write().unwrap();
+    fn diagnostic_load_into(
+        &self,
+        kb: &mut KnowledgeBase,
+        sources: Vec<Source>,
+    ) -> Vec<Diagnostic> {
         let mut diagnostics = vec![];
 
         for source in sources {
-            match load_source(source, &mut kb) {
+            match kb.load_source(source) {
                 Ok(mut ds) => diagnostics.append(&mut ds),
                 Err(e) => diagnostics.push(Diagnostic::Error(e)),
             }
@@ -146,13 +170,18 @@ impl Polar {
 
         // Perform validation checks against the whole policy
         if !self.ignore_no_allow_warning {
-            if let Some(w) = check_no_allow_rule(&kb) {
+            if let Some(w) = check_no_allow_rule(kb) {
                 diagnostics.push(w)
             }
         }
 
+        diagnostics.append(&mut check_deprecated_rule_calls(kb));
+        diagnostics.append(&mut check_effectful_call_ordering(kb));
+        diagnostics.append(&mut check_redundant_rules(kb));
+        diagnostics.append(&mut check_types(kb, self.type_checking));
+
         // Check for has_permission calls alongside resource block definitions
-        if let Some(w) = check_resource_blocks_missing_has_permission(&kb) {
+        if let Some(w) = check_resource_blocks_missing_has_permission(kb) {
             diagnostics.push(Diagnostic::Warning(w.into()))
         };
 
@@ -160,32 +189,195 @@ impl Polar {
     }
 
     /// Load `Source`s into the KB.
+    ///
+    /// Loading the same sources as the currently loaded policy again is a no-op. If the load
+    /// fails, the KB is left untouched.
     pub fn load(&self, sources: Vec<Source>) -> PolarResult<()> {
-        if let Ok(kb) = self.kb.read() {
-            if kb.has_rules() {
-                return Err(RuntimeError::MultipleLoadError.into());
-            }
+        let warnings = self.kb.try_update(|kb| self.load_into(kb, sources))?;
+        self.messages.extend(warnings);
+        Ok(())
+    }
+
+    /// Load `source` alongside the currently loaded policy, replacing the loaded source with the
+    /// same filename, if any. Only the rules in `source` are revalidated. Sources with resource
+    /// blocks or union types can't be loaded this way, but can be loaded alongside them.
+    ///
+    /// `source` must have a filename. If it fails to load, the KB is left untouched.
+    pub fn load_incremental(&self, source: Source) -> PolarResult<()> {
+        let warnings = self
+            .kb
+            .try_update(|kb| kb.load_source_incremental(source))?;
+        self.messages
+            .extend(warnings.into_iter().map(Message::warning));
+        Ok(())
+    }
+
+    /// Remove the source named `filename` from the currently loaded policy.
+    ///
+    /// Fails, leaving the KB untouched, if the rest of the policy doesn't validate without the
+    /// source, e.g., because it calls rules that were only defined there.
+    pub fn unload(&self, filename: &str) -> PolarResult<()> {
+        self.kb.try_update(|kb| kb.unload_source(filename))
+    }
+
+    /// Load `sources` into `namespace`, e.g., one tenant's policy, on top of the policy loaded
+    /// into the KB.
+    ///
+    /// Queries started with [`Polar::new_query_in_namespace`] see the rules of both, while other
+    /// namespaces and the KB itself are unaffected. Rules in the namespace are checked against
+    /// the KB's rule types, and can call rules that only the KB defines. As with [`Polar::load`],
+    /// a namespace can only be loaded once until it's cleared.
+    pub fn load_in_namespace(&self, namespace: &str, sources: Vec<Source>) -> PolarResult<()> {
+        if self.namespaces.read().unwrap().contains_key(namespace) {
+            return Err(RuntimeError::MultipleLoadError.into());
+        }
+
+        let mut overlay = self.kb.snapshot().new_overlay();
+        let mut diagnostics = vec![];
+        for source in sources {
+            diagnostics.append(&mut overlay.load_source(source)?);
+        }
+        diagnostics.extend(
+            overlay
+                .rewrite_shorthand_rules()
+                .into_iter()
+                .map(Into::into),
+        );
+        if let Err(e) = overlay.validate_overlay_rules() {
+            diagnostics.push(e.into());
+        }
+        let warnings = into_warnings(diagnostics)?;
+        if let Some(query) = overlay.inline_queries.first() {
+            return unsupported("inline queries in namespaced policies", &query.term);
+        }
+
+        match self.namespaces.write().unwrap().entry(namespace.to_owned()) {
+            Entry::Occupied(_) => return Err(RuntimeError::MultipleLoadError.into()),
+            Entry::Vacant(entry) => entry.insert(Arc::new(overlay)),
+        };
+        self.messages
+            .extend(warnings.into_iter().map(Message::warning));
+        Ok(())
+    }
+
+    /// Remove the policy loaded into `namespace`. Queries already running in the namespace keep
+    /// the rules they started with.
+    pub fn clear_namespace(&self, namespace: &str) {
+        self.namespaces.write().unwrap().remove(namespace);
+    }
+
+    /// Load `sources` into `kb`, returning the warnings to emit if the load succeeds.
+    fn load_into(&self, kb: &mut KnowledgeBase, sources: Vec<Source>) -> PolarResult<Vec<Message>> {
+        if kb.is_loaded(&sources) {
+            return Ok(vec![]);
+        }
+        if kb.has_rules() {
+            return Err(RuntimeError::MultipleLoadError.into());
         }
 
+        kb.set_loaded_sources(&sources);
+
         let (mut errors, mut warnings) = (vec![], vec![]);
-        for diagnostic in self.diagnostic_load(sources) {
+        for diagnostic in self.diagnostic_load_into(kb, sources) {
             match diagnostic {
                 Diagnostic::Error(e) => errors.push(e),
                 Diagnostic::Warning(w) => warnings.push(w),
             }
         }
 
-        self.messages
-            .extend(warnings.into_iter().map(Message::warning));
-
+        // Callers discard `kb` on error, so there's nothing to clean up.
         if let Some(e) = errors.into_iter().next() {
-            // If we've encountered any errors, clear the KB.
-            self.clear_rules();
+            self.messages
+                .extend(warnings.into_iter().map(Message::warning));
             return Err(e);
         }
+        Ok(warnings.into_iter().map(Message::warning).collect())
+    }
+
+    /// Serialize the loaded policy to a bundle, which [`Polar::load_bundle`] can load without
+    /// parsing or validating it again.
+    #[cfg(feature = "bundle")]
+    pub fn save_bundle(&self) -> PolarResult<Vec<u8>> {
+        self.kb.snapshot().save_bundle()
+    }
+
+    /// Load a policy from a bundle saved by [`Polar::save_bundle`] after loading `sources`.
+    ///
+    /// Bundles built by another version of Polar or from other sources are rejected. As with
+    /// [`Polar::load`], constants and classes must be registered first.
+    #[cfg(feature = "bundle")]
+    pub fn load_bundle(&self, bundle: &[u8], sources: &[Source]) -> PolarResult<()> {
+        self.kb.try_update(|kb| {
+            if kb.is_loaded(sources) {
+                return Ok(());
+            }
+            if kb.has_rules() {
+                return Err(RuntimeError::MultipleLoadError.into());
+            }
+            kb.load_bundle(bundle, sources)
+        })
+    }
+
+    /// Apply the changes made by `f` to a [`Transaction`] atomically.
+    ///
+    /// Changes are applied in order to a copy of the KB, which only replaces the KB if every
+    /// change succeeds. Otherwise the first error is returned and the KB is left untouched.
+    pub fn transaction<F>(&self, f: F) -> PolarResult<()>
+    where
+        F: FnOnce(&mut Transaction),
+    {
+        let mut txn = Transaction::default();
+        f(&mut txn);
+
+        let warnings = self.kb.try_update(|staged| {
+            let mut warnings = vec![];
+            for change in txn.changes {
+                match change {
+                    Change::Load(sources) => warnings.append(&mut self.load_into(staged, sources)?),
+                    Change::RegisterConstant(name, value) => {
+                        staged.register_constant(name, value)?
+                    }
+                    Change::RegisterMro(name, mro) => staged.add_mro(name, mro)?,
+                }
+            }
+            Ok(warnings)
+        })?;
+        self.messages.extend(warnings);
         Ok(())
     }
 
+    /// Compare two versions of a policy, e.g., before deploying a change. See [`PolicyDiff`].
+    ///
+    /// Each version is loaded, and so validated, against the registered constants and classes
+    /// without touching the loaded policy.
+    pub fn diff_policies(
+        &self,
+        old_sources: Vec<Source>,
+        new_sources: Vec<Source>,
+    ) -> PolarResult<PolicyDiff> {
+        let old = self.policy_version(old_sources)?;
+        let new = self.policy_version(new_sources)?;
+        Ok(diff::diff(&old, &new))
+    }
+
+    fn policy_version(&self, sources: Vec<Source>) -> PolarResult<PolicyVersion> {
+        let copies = sources
+            .iter()
+            .map(|Source { filename, src }| Source {
+                filename: filename.clone(),
+                src: src.clone(),
+            })
+            .collect::<Vec<_>>();
+        let mut staged = KnowledgeBase::clone(&self.kb.snapshot());
+        staged.clear_rules();
+        self.load_into(&mut staged, sources)?;
+        let mut lines = vec![];
+        for source in copies {
+            lines.append(&mut parser::parse_lines(source)?);
+        }
+        Ok(PolicyVersion::new(lines, PolicyAst::new(&staged)))
+    }
+
     // Used in integration tests
     pub fn load_str(&self, src: &str) -> PolarResult<()> {
         self.load(vec![Source::new(src)])
@@ -193,39 +385,276 @@ impl Polar {
 
     /// Clear rules from the knowledge base
     pub fn clear_rules(&self) {
-        let mut kb = self.kb.write().unwrap();
-        kb.clear_rules();
+        self.kb.update(KnowledgeBase::clear_rules);
+        self.inline_queries
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clear();
     }
 
+    /// Start the next inline query that hasn't been run yet. If the query lists the results it
+    /// expects, the query only has a single, empty result if its results are exactly those, and
+    /// fails with an `InlineQueryMismatch` error otherwise.
     pub fn next_inline_query(&self, trace: bool) -> Option<Query> {
-        let term = { self.kb.write().unwrap().inline_queries.pop() };
-        term.map(|t| self.new_query_from_term(t, trace))
+        let query = {
+            let mut pending = self
+                .inline_queries
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            if pending.is_empty() && !self.kb.snapshot().inline_queries.is_empty() {
+                *pending = self.kb.update(|kb| std::mem::take(&mut kb.inline_queries));
+            }
+            pending.pop()?
+        };
+        // Expectations are checked when the query is loaded.
+        let expected = query.expected_results().unwrap_or_default();
+        let mut inline_query = self.new_query_from_term(query.term, trace);
+        inline_query.expected = expected;
+        Some(inline_query)
     }
 
     pub fn new_query(&self, src: &str, trace: bool) -> PolarResult<Query> {
         parser::parse_query(src).map(|term| self.new_query_from_term(term, trace))
     }
 
-    pub fn new_query_from_term(&self, mut term: Term, trace: bool) -> Query {
-        use crate::vm::{Goal, PolarVirtualMachine};
+    pub fn new_query_from_term(&self, term: Term, trace: bool) -> Query {
+        self.new_query_with_facts(term, trace, None, None)
+    }
+
+    /// Start a query that sees the rules loaded into `namespace` as well as those in the KB. See
+    /// [`Polar::load_in_namespace`]. A namespace that nothing has been loaded into has no rules of
+    /// its own.
+    pub fn new_query_in_namespace(
+        &self,
+        namespace: &str,
+        src: &str,
+        trace: bool,
+    ) -> PolarResult<Query> {
+        let term = parser::parse_query(src)?;
+        let overlay = self.namespaces.read().unwrap().get(namespace).cloned();
+        Ok(self.new_query_with_facts(term, trace, None, overlay))
+    }
+
+    /// Start a query with values bound to some of its variables.
+    ///
+    /// Every `$param` placeholder in the query must be given a value, and every binding must name
+    /// a variable in the query. Values bound to the arguments of rule calls that the query
+    /// requires to succeed are checked against the rule types for those rules before the query
+    /// starts.
+    pub fn new_query_with_bindings(
+        &self,
+        src: &str,
+        bindings: Bindings,
+        trace: bool,
+    ) -> PolarResult<Query> {
+        let term = parser::parse_query(src)?;
+
+        let mut variables = HashSet::new();
+        term.variables(&mut variables);
+        let mut missing = variables
+            .iter()
+            .filter(|var| var.as_str().starts_with('$') && !bindings.contains_key(var))
+            .collect::<Vec<_>>();
+        missing.sort();
+        if let Some(var) = missing.first() {
+            return Err(RuntimeError::InvalidQueryParameter {
+                name: var.as_str().to_string(),
+                msg: "no value was given".to_owned(),
+            }
+            .into());
+        }
+        let mut unknown = bindings
+            .keys()
+            .filter(|var| !variables.contains(var))
+            .collect::<Vec<_>>();
+        unknown.sort();
+        if let Some(var) = unknown.first() {
+            return Err(RuntimeError::InvalidQueryParameter {
+                name: var.as_str().to_string(),
+                msg: "the query has no such variable".to_owned(),
+            }
+            .into());
+        }
+
         {
-            let mut kb = self.kb.write().unwrap();
-            term = rewrite_term(term, &mut kb);
+            let kb = self.kb.snapshot();
+            let mut goals = vec![&term];
+            while let Some(goal) = goals.pop() {
+                match goal.value() {
+                    Value::Expression(Operation {
+                        operator: Operator::And,
+                        args,
+                    }) => goals.extend(args.iter()),
+                    Value::Call(call) => {
+                        let args = call
+                            .args
+                            .iter()
+                            .map(|arg| match arg.value() {
+                                Value::Variable(var) => bindings.get(var).cloned(),
+                                _ => None,
+                            })
+                            .collect::<Vec<_>>();
+                        if let Some(msg) = kb.check_call_arg_types(&call.name, &args)? {
+                            return Err(RuntimeError::TypeError {
+                                msg: format!(
+                                    "Query parameters for `{}` don't match its rule types. {}",
+                                    call.name, msg
+                                ),
+                                stack_trace: String::new(),
+                                term: goal.clone(),
+                            }
+                            .into());
+                        }
+                    }
+                    _ => (),
+                }
+            }
+        }
+
+        let mut query = self.new_query_from_term(term, trace);
+        for (name, value) in bindings {
+            query.bind(name, value)?;
+        }
+        Ok(query)
+    }
+
+    /// Find the actions `actor` may take on `resource` by querying `allow(actor, action, resource)`
+    /// with `action` unbound.
+    pub fn authorized_actions(&self, actor: Term, resource: Term, trace: bool) -> Authorization {
+        let action = Symbol::new("action");
+        let query = CallBuilder::new("allow")
+            .arg(actor)
+            .arg(action.clone())
+            .arg(resource)
+            .build_term();
+        Authorization::new(self.new_query_from_term(query, trace), action)
+    }
+
+    /// Find the fields of `resource` that `actor` may take `action` on by querying
+    /// `allow_field(actor, action, resource, field)` with `field` unbound.
+    pub fn authorized_fields(
+        &self,
+        actor: Term,
+        action: Term,
+        resource: Term,
+        trace: bool,
+    ) -> Authorization {
+        let field = Symbol::new("field");
+        let query = CallBuilder::new("allow_field")
+            .arg(actor)
+            .arg(action)
+            .arg(resource)
+            .arg(field.clone())
+            .build_term();
+        Authorization::new(self.new_query_from_term(query, trace), field)
+    }
+
+    /// Start a session whose fact changes are only visible to its own queries until committed.
+    pub fn session(&self) -> Session<'_> {
+        Session::new(self)
+    }
+
+    pub(crate) fn new_query_with_facts(
+        &self,
+        term: Term,
+        trace: bool,
+        session_facts: Option<Arc<SessionFacts>>,
+        namespace: Option<Arc<KnowledgeBase>>,
+    ) -> Query {
+        self.new_query_against(self.kb.snapshot(), term, trace, session_facts, namespace)
+    }
+
+    /// Start a batch of queries that run one after another against a snapshot of the KB taken
+    /// now, sharing memoized external calls. See [`BatchQuery`].
+    pub fn new_batch_query(&self, terms: Vec<Term>) -> BatchQuery {
+        let kb = self.kb.snapshot();
+        BatchQuery::new(
+            terms
+                .into_iter()
+                .map(|term| self.new_query_against(kb.clone(), term, false, None, None))
+                .collect(),
+        )
+    }
+
+    /// Start a partial query that reuses the constraints found by earlier queries of the same
+    /// shape against the same policy. See [`CachedPartialQuery`].
+    pub fn new_cached_partial_query(
+        &self,
+        src: &str,
+        bindings: Bindings,
+    ) -> PolarResult<CachedPartialQuery<'_>> {
+        CachedPartialQuery::new(self, src, bindings)
+    }
+
+    pub(crate) fn new_query_against(
+        &self,
+        kb: Arc<KnowledgeBase>,
+        mut term: Term,
+        trace: bool,
+        session_facts: Option<Arc<SessionFacts>>,
+        namespace: Option<Arc<KnowledgeBase>>,
+    ) -> Query {
+        use crate::vm::{Goal, PolarVirtualMachine};
+        if let Some(rewriter) = &self.query_rewriter {
+            term = rewriter.rewrite(term);
         }
+        let gensym_counter = match self.deterministic_seed {
+            Some(seed) => Counter::with_start(seed),
+            None => kb.gensym_counter(),
+        };
+        term = rewrite_term(term, gensym_counter.clone());
         let query = Goal::Query { term: term.clone() };
-        let vm =
-            PolarVirtualMachine::new(self.kb.clone(), trace, vec![query], self.messages.clone());
+        let mut vm = PolarVirtualMachine::new(kb, trace, vec![query], self.messages.clone());
+        vm.gensym_counter = gensym_counter;
+        vm.warn_on_cycles = self.warn_on_cycles;
+        vm.native_string_methods = self.native_string_methods;
+        vm.session_facts = session_facts;
+        vm.namespace = namespace;
+        vm.term_formatter = self.term_formatter.clone();
+        vm.limits = self.query_limits;
+        vm.metrics_totals = Some(self.metrics.clone());
         Query::new(vm, term)
     }
 
     // @TODO: Direct load_rules endpoint.
 
     pub fn get_external_id(&self) -> u64 {
-        self.kb.read().unwrap().new_id()
+        self.kb.snapshot().new_id()
     }
 
+    /// Register `value` as the constant `name`.
+    ///
+    /// Every registration publishes a new copy of the KB, which shares the loaded policy with the
+    /// old one. Use [`Polar::transaction`] to register many constants or MROs at once.
     pub fn register_constant(&self, name: Symbol, value: Term) -> PolarResult<()> {
-        self.kb.write().unwrap().register_constant(name, value)
+        self.kb.try_update(|kb| kb.register_constant(name, value))
+    }
+
+    /// Register a constant whose value is fetched from the host the first time a query uses it,
+    /// for values that are expensive to build or depend on the environment.
+    ///
+    /// The query emits a [`QueryEvent::ExternalConstant`](crate::events::QueryEvent), which the
+    /// host answers with [`Query::call_result`]. The value is cached in the KB for later queries.
+    /// If the host answers with no value, the query fails with an error.
+    pub fn register_lazy_constant(&self, name: Symbol) -> PolarResult<()> {
+        self.kb.try_update(|kb| kb.register_lazy_constant(name))
+    }
+
+    /// Register a predicate implemented in Rust, which queries call directly instead of making
+    /// an external call to the host.
+    ///
+    /// Calls to `name` with `arity` arguments succeed if `predicate` returns true for the values
+    /// of the arguments, which must all be bound. If it returns an error, the query fails with
+    /// an application error. Policies can't define rules or rule types named `name`, and the
+    /// name can't already be taken by a rule, rule type or another built-in.
+    pub fn register_builtin<P: BuiltinPredicate + 'static>(
+        &self,
+        name: Symbol,
+        arity: usize,
+        predicate: P,
+    ) -> PolarResult<()> {
+        self.kb
+            .try_update(|kb| kb.register_builtin(name, arity, predicate))
     }
 
     /// Register MRO for `name` with `mro`.
@@ -235,7 +664,34 @@ impl Polar {
     /// - `mro`: Should go from `name`, `name`'s next superclass, `name's furthest away superclass.
     ///          `mro` is a list of class ids.
     pub fn register_mro(&self, name: Symbol, mro: Vec<u64>) -> PolarResult<()> {
-        self.kb.write().unwrap().add_mro(name, mro)
+        self.kb.try_update(|kb| kb.add_mro(name, mro))
+    }
+
+    /// Record that calling the host method `name` has side effects, so that loading warns about
+    /// policies whose behavior depends on the order such calls are evaluated in.
+    pub fn register_effectful_method(&self, name: Symbol) {
+        self.kb.update(|kb| kb.register_effectful_method(name))
+    }
+
+    /// Record that the host method `name` can return different results when called again with
+    /// the same arguments, e.g., because it reads the clock.
+    ///
+    /// Within a query, the results of attribute lookups and method calls on external instances are
+    /// memoized, so that repeating a call doesn't go back to the host. Calls to nondeterministic
+    /// and effectful methods always go to the host.
+    pub fn register_nondeterministic_method(&self, name: Symbol) {
+        self.kb
+            .update(|kb| kb.register_nondeterministic_method(name))
+    }
+
+    /// Optimize the rules in policies loaded from now on. See [`OptimizationLevel`].
+    pub fn set_optimization_level(&self, level: OptimizationLevel) {
+        self.kb.update(|kb| kb.set_optimization_level(level))
+    }
+
+    /// Export the loaded policy for tooling. See [`PolicyAst`].
+    pub fn introspect(&self) -> PolicyAst {
+        self.kb.snapshot().export_ast()
     }
 
     pub fn next_message(&self) -> Option<Message> {
@@ -268,12 +724,127 @@ impl Polar {
     pub fn set_ignore_no_allow_warning(&mut self, ignore: bool) {
         self.ignore_no_allow_warning = ignore;
     }
+
+    /// Emit a warning message whenever a query abandons a branch that re-derives one of its own
+    /// ancestor goals. Cyclic branches are abandoned regardless of this setting.
+    pub fn set_warn_on_cycles(&mut self, warn: bool) {
+        self.warn_on_cycles = warn;
+    }
+
+    /// Evaluate `lower`, `upper`, `contains`, `starts_with`, `ends_with`, `split` and `join` on
+    /// strings without a round trip to the host. Off by default, since these take the place of any
+    /// host methods of the same names, which may behave differently, e.g., Python's `str.join`
+    /// only joins strings where the native `join` also formats numbers and booleans. Interpolated
+    /// strings are always joined natively.
+    pub fn set_native_string_methods(&mut self, enabled: bool) {
+        self.native_string_methods = enabled;
+    }
+
+    /// Make queries reproducible, e.g., for golden-file tests of policies.
+    ///
+    /// Rules are always tried in a stable order, so the same policy and inputs yield the same
+    /// results in the same order. But the temporary variables that show up in partial results and
+    /// error messages are numbered by a counter shared by every query, so their names depend on
+    /// which queries ran before. In deterministic mode, each query numbers them from `seed`.
+    pub fn set_deterministic_mode(&mut self, seed: u64) {
+        self.deterministic_seed = Some(seed);
+    }
+
+    /// Check the types of the values in rules when loading policies. See `TypeChecking`.
+    pub fn set_type_checking(&mut self, level: TypeChecking) {
+        self.type_checking = level;
+    }
+
+    /// Rewrite every query with `rewriter` before evaluating it.
+    pub fn set_query_rewriter<R: QueryRewriter + 'static>(&mut self, rewriter: R) {
+        self.query_rewriter = Some(Arc::new(rewriter));
+    }
+
+    /// Format the terms in query logs, traces and error stack traces with `formatter`.
+    pub fn set_term_formatter(&mut self, formatter: TermFormatter) {
+        self.term_formatter = formatter;
+    }
+
+    /// Fail queries with `RuntimeError::BudgetExceeded` once they exceed any of `limits`.
+    pub fn set_query_limits(&mut self, limits: QueryLimits) {
+        self.query_limits = limits;
+    }
+
+    /// The metrics of all queries dropped so far. Queries still running aren't included.
+    pub fn metrics_snapshot(&self) -> QueryMetrics {
+        self.metrics
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Return the Polar string for `term`, formatted the same way as in query logs and traces.
+    pub fn to_polar_string(&self, term: &Term) -> String {
+        self.term_formatter.to_polar_string(term)
+    }
+}
+
+/// Rewrites queries before they're evaluated, e.g., to add tenant constraints or map legacy
+/// action names.
+///
+/// The rewriter sees every query made through `Polar`, including the partial queries made to
+/// build data filters, so both paths enforce the same policy.
+pub trait QueryRewriter: Send + Sync {
+    /// Return the term to evaluate in place of the parsed query `term`.
+    fn rewrite(&self, term: Term) -> Term;
+}
+
+impl<F> QueryRewriter for F
+where
+    F: Fn(Term) -> Term + Send + Sync,
+{
+    fn rewrite(&self, term: Term) -> Term {
+        self(term)
+    }
+}
+
+enum Change {
+    Load(Vec<Source>),
+    RegisterConstant(Symbol, Term),
+    RegisterMro(Symbol, Vec<u64>),
+}
+
+/// A batch of changes to apply with [`Polar::transaction`].
+#[derive(Default)]
+pub struct Transaction {
+    changes: Vec<Change>,
+}
+
+impl Transaction {
+    pub fn load(&mut self, sources: Vec<Source>) {
+        self.changes.push(Change::Load(sources));
+    }
+
+    pub fn load_str(&mut self, src: &str) {
+        self.load(vec![Source::new(src)])
+    }
+
+    pub fn register_constant(&mut self, name: Symbol, value: Term) {
+        self.changes.push(Change::RegisterConstant(name, value));
+    }
+
+    pub fn register_mro(&mut self, name: Symbol, mro: Vec<u64>) {
+        self.changes.push(Change::RegisterMro(name, mro));
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::error::{RuntimeError::MultipleLoadError, ValidationError::FileLoading};
+    use crate::error::{
+        RuntimeError::{
+            Application, InvalidRegistration, MultipleLoadError, TypeError, Unsupported,
+        },
+        ValidationError::{
+            FileLoading, InvalidRule, InvalidRuleType, PrivateRuleCall, UndefinedRuleCall,
+        },
+    };
+    use crate::events::QueryEvent;
 
     #[test]
     fn can_load_and_query() {
@@ -282,6 +853,67 @@ mod tests {
         let _ = polar.load_str("f(_);");
     }
 
+    #[test]
+    fn query_rewriter_rewrites_queries() {
+        let mut polar = Polar::new();
+        polar.set_query_rewriter(|term: Term| match term.value() {
+            Value::Call(call) if call.name.as_str() == "allow" => {
+                let resource = &call.args[2];
+                let src = format!("{} and {}.tenant = \"acme\"", term, resource);
+                parser::parse_query(&src).unwrap()
+            }
+            _ => term.clone(),
+        });
+        polar
+            .load_str("allow(_actor, \"read\", _resource);")
+            .unwrap();
+
+        let count = |src| {
+            let mut query = polar.new_query(src, false).unwrap();
+            let mut results = 0;
+            while let QueryEvent::Result { .. } = query.next_event().unwrap() {
+                results += 1;
+            }
+            results
+        };
+        assert_eq!(count("allow(\"alice\", \"read\", {tenant: \"acme\"})"), 1);
+        assert_eq!(count("allow(\"alice\", \"read\", {tenant: \"other\"})"), 0);
+        assert_eq!(count("1 = 1"), 1);
+    }
+
+    #[test]
+    fn deterministic_mode_numbers_variables_per_query() {
+        // The error names the temporary variable that `y` is renamed to.
+        let error = |polar: &Polar| {
+            let mut query = polar.new_query("f(x)", false).unwrap();
+            query.next_event().unwrap_err().to_string()
+        };
+        let mut polar = Polar::new();
+        polar.load_str("f([y]) if y > 1;").unwrap();
+        assert_ne!(error(&polar), error(&polar));
+
+        polar.set_deterministic_mode(7);
+        let first = error(&polar);
+        let _ = polar
+            .new_query("f(x) and f(z)", false)
+            .unwrap()
+            .next_event();
+        assert_eq!(error(&polar), first);
+    }
+
+    #[test]
+    fn term_formatter_truncates_stack_traces() {
+        let mut polar = Polar::new();
+        polar.set_term_formatter(TermFormatter::new().max_list_elements(2));
+        let list = parser::parse_query("[1, 2, 3, 4, 5]").unwrap();
+        assert_eq!(polar.to_polar_string(&list), "[1, 2, ...3 more]");
+
+        let term = term!(op!(Assign, list, term!(1)));
+        let mut query = polar.new_query_from_term(term, false);
+        let error = query.next_event().unwrap_err().to_string();
+        assert!(error.contains("000: [1, 2, ...3 more] := 1"), "{}", error);
+    }
+
     #[test]
     fn loading_a_second_time_fails() {
         let polar = Polar::new();
@@ -290,17 +922,130 @@ mod tests {
         // Loading once is fine.
         polar.load(vec![Source::new(src)]).unwrap();
 
-        // Loading twice is not.
-        let e = polar.load(vec![Source::new(src)]).unwrap_err();
+        // Loading different sources a second time is not.
+        let e = polar.load(vec![Source::new("g();")]).unwrap_err();
         assert!(matches!(e.unwrap_runtime(), MultipleLoadError));
 
         // Even with load_str().
         assert!(matches!(
-            polar.load_str(src).unwrap_err().unwrap_runtime(),
+            polar.load_str("g();").unwrap_err().unwrap_runtime(),
+            MultipleLoadError
+        ));
+    }
+
+    #[test]
+    fn loading_the_same_sources_again_is_a_noop() {
+        let polar = Polar::new();
+        let sources = || vec![Source::new_with_name("file", "f();")];
+        polar.load(sources()).unwrap();
+        polar.load(sources()).unwrap();
+        assert_eq!(polar.kb.snapshot().get_rules().len(), 1);
+
+        // Once the rules are cleared, the same sources can be loaded from scratch.
+        polar.clear_rules();
+        polar.load(sources()).unwrap();
+        assert!(matches!(
+            polar.load_str("f();").unwrap_err().unwrap_runtime(),
             MultipleLoadError
         ));
     }
 
+    #[test]
+    fn running_queries_keep_their_snapshot_across_reloads() {
+        let polar = Polar::new();
+        polar.load_str("f(1);").unwrap();
+        let mut query = polar.new_query("f(x)", false).unwrap();
+
+        polar.clear_rules();
+        polar.load_str("f(2);").unwrap();
+
+        let x = match query.next_event().unwrap() {
+            QueryEvent::Result { bindings, .. } => bindings[&sym!("x")].clone(),
+            event => panic!("unexpected event {:?}", event),
+        };
+        assert_eq!(x, term!(1));
+        let mut query = polar.new_query("f(2)", false).unwrap();
+        assert!(matches!(
+            query.next_event().unwrap(),
+            QueryEvent::Result { .. }
+        ));
+    }
+
+    #[test]
+    fn transaction_applies_all_changes() {
+        let polar = Polar::new();
+        polar
+            .transaction(|txn| {
+                txn.register_constant(sym!("x"), term!(1));
+                txn.load_str("f(y) if y = x;");
+            })
+            .unwrap();
+        assert!(polar.kb.snapshot().is_constant(&sym!("x")));
+        assert!(polar.kb.snapshot().has_rules());
+
+        // Repeating the transaction is a no-op.
+        polar
+            .transaction(|txn| {
+                txn.register_constant(sym!("x"), term!(1));
+                txn.load_str("f(y) if y = x;");
+            })
+            .unwrap();
+        assert_eq!(polar.kb.snapshot().get_rules().len(), 1);
+    }
+
+    #[test]
+    fn inline_queries_are_taken_from_the_kb_at_once() {
+        let polar = Polar::new();
+        polar.load_str("?= 1 = 1;\n?= 2 = 2;\n?= 3 = 3;").unwrap();
+        let (epoch, _) = polar.kb.versioned_snapshot();
+        let mut count = 0;
+        while polar.next_inline_query(false).is_some() {
+            count += 1;
+        }
+        assert_eq!(count, 3);
+        assert_eq!(polar.kb.versioned_snapshot().0, epoch + 1);
+
+        // Clearing the rules drops queries that haven't been run.
+        polar.clear_rules();
+        polar.load_str("?= 1 = 1;\n?= 2 = 2;").unwrap();
+        assert!(polar.next_inline_query(false).is_some());
+        polar.clear_rules();
+        assert!(polar.next_inline_query(false).is_none());
+    }
+
+    #[test]
+    fn failed_transaction_leaves_the_kb_untouched() {
+        let polar = Polar::new();
+        polar.load_str("f();").unwrap();
+
+        let e = polar
+            .transaction(|txn| {
+                txn.register_constant(sym!("x"), term!(1));
+                txn.load_str("g();");
+            })
+            .unwrap_err();
+        assert!(matches!(e.unwrap_runtime(), MultipleLoadError));
+
+        let kb = polar.kb.snapshot();
+        assert!(!kb.is_constant(&sym!("x")));
+        assert!(kb.get_rules().contains_key(&sym!("f")));
+        assert!(!kb.get_rules().contains_key(&sym!("g")));
+    }
+
+    #[test]
+    fn failed_load_leaves_the_kb_untouched() {
+        let polar = Polar::new();
+        polar.register_constant(sym!("x"), term!(1)).unwrap();
+        let (version, _) = polar.kb.versioned_snapshot();
+
+        assert!(polar.load_str("f(); g() if h();").is_err());
+        let (after, kb) = polar.kb.versioned_snapshot();
+        assert_eq!(after, version);
+        assert!(kb.is_constant(&sym!("x")));
+        assert!(!kb.has_rules());
+        polar.load_str("f();").unwrap();
+    }
+
     #[test]
     fn loading_duplicate_files_errors_and_leaves_the_kb_empty() {
         let polar = Polar::new();
@@ -318,7 +1063,7 @@ mod tests {
         };
         assert_eq!(msg, "File file has already been loaded.");
 
-        assert!(!polar.kb.read().unwrap().has_rules());
+        assert!(!polar.kb.snapshot().has_rules());
     }
 
     #[test]
@@ -346,6 +1091,273 @@ mod tests {
         );
     }
 
+    #[test]
+    fn calls_to_deprecated_rules_warn() {
+        let mut polar = Polar::new();
+        polar.set_ignore_no_allow_warning(true);
+        let src = r#"@deprecated("use has_permission/3")
+can(actor, resource) if can(actor, resource.parent);
+can(_actor, "public");
+can(actor, resource, _action) if can(actor, resource);
+?= can("alice", "public");
+"#;
+        let diagnostics = polar.diagnostic_load(vec![Source::new_with_name("file", src)]);
+        assert_eq!(diagnostics.len(), 2, "{:#?}", diagnostics);
+        for (diagnostic, line) in diagnostics.iter().zip([4, 5]) {
+            assert!(matches!(diagnostic, Diagnostic::Warning(_)));
+            let message = diagnostic.to_string();
+            assert!(
+                message.starts_with("Call to deprecated rule can/2: use has_permission/3"),
+                "{}",
+                message
+            );
+            assert!(
+                message.contains("Deprecated at line 2, column 1 of file file"),
+                "{}",
+                message
+            );
+            let called = format!("Called at line {}", line);
+            assert!(message.contains(&called), "{}", message);
+        }
+        assert!(polar.load_str(r#"@unknown("x") f();"#).is_err());
+    }
+
+    #[test]
+    fn private_rules_are_only_callable_from_their_file() {
+        let mut polar = Polar::new();
+        polar.set_ignore_no_allow_warning(true);
+        let helpers = || {
+            Source::new_with_name(
+                "helpers.polar",
+                "@private\nis_admin(user) if user = \"root\";\ncan_admin(user) if is_admin(user);",
+            )
+        };
+        let uses_public =
+            Source::new_with_name("app.polar", "can_deploy(user) if can_admin(user);");
+        polar.load(vec![helpers(), uses_public]).unwrap();
+        polar.clear_rules();
+
+        let uses_private =
+            Source::new_with_name("other.polar", "can_deploy(user) if is_admin(user);");
+        let err = polar.load(vec![helpers(), uses_private]).unwrap_err();
+        let message = err.to_string();
+        assert!(
+            message.starts_with("Call to private rule is_admin/1 from outside the file"),
+            "{}",
+            message
+        );
+        assert!(message.contains("Defined at line 2, column 1 of file helpers.polar"));
+        assert!(message.contains("Called at line 1, column 21 of file other.polar"));
+        assert!(matches!(err.unwrap_validation(), PrivateRuleCall { .. }));
+    }
+
+    #[test]
+    fn builtins_are_called_by_the_vm() {
+        let mut polar = Polar::new();
+        polar.set_ignore_no_allow_warning(true);
+        let prefix = |args: &[Term]| match (args[0].value(), args[1].value()) {
+            (Value::String(s), Value::String(prefix)) => Ok(s.starts_with(prefix.as_str())),
+            _ => Err("prefix() takes two strings".to_owned()),
+        };
+        polar.register_builtin(sym!("prefix"), 2, prefix).unwrap();
+        let e = polar
+            .register_builtin(sym!("prefix"), 2, prefix)
+            .unwrap_err();
+        assert!(matches!(e.unwrap_runtime(), InvalidRegistration { .. }));
+        let e = polar
+            .register_builtin(sym!("allow"), 3, prefix)
+            .unwrap_err();
+        assert!(matches!(e.unwrap_runtime(), InvalidRegistration { .. }));
+
+        polar
+            .load_str(r#"internal(host) if prefix(host, "10.");"#)
+            .unwrap();
+        let count = |src| {
+            let mut query = polar.new_query(src, false).unwrap();
+            let mut results = 0;
+            loop {
+                match query.next_event() {
+                    Ok(QueryEvent::Result { .. }) => results += 1,
+                    Ok(_) => return Ok(results),
+                    Err(e) => return Err(e),
+                }
+            }
+        };
+        assert_eq!(count(r#"internal("10.0.0.1")"#).unwrap(), 1);
+        assert_eq!(count(r#"internal("192.168.0.1")"#).unwrap(), 0);
+        let e = count("internal(1)").unwrap_err();
+        assert!(matches!(e.unwrap_runtime(), Application { .. }));
+        let e = count("internal(x)").unwrap_err();
+        assert!(matches!(e.unwrap_runtime(), Unsupported { .. }));
+        let e = count(r#"prefix("a")"#).unwrap_err();
+        assert!(matches!(e.unwrap_runtime(), TypeError { .. }));
+
+        // Builtins outlive the policy, which can't redefine them.
+        polar.clear_rules();
+        let e = polar.load_str(r#"prefix(_, "");"#).unwrap_err();
+        assert!(matches!(e.unwrap_validation(), InvalidRule { .. }));
+        polar.clear_rules();
+        let e = polar.load_str("type prefix(x, y);").unwrap_err();
+        assert!(matches!(e.unwrap_validation(), InvalidRuleType { .. }));
+    }
+
+    #[test]
+    fn incremental_loading() {
+        let polar = Polar::new();
+        let rule_names = || {
+            let kb = polar.kb.snapshot();
+            let mut names = kb
+                .get_rules()
+                .keys()
+                .map(|n| n.as_str().to_string())
+                .collect::<Vec<_>>();
+            names.sort();
+            names
+        };
+        let source = |filename: &str, src: &str| Source::new_with_name(filename, src);
+
+        polar
+            .load(vec![source("base", "allow(x, _, _) if f(x); f(1);")])
+            .unwrap();
+        polar
+            .load_incremental(source("extra", "g(x) if f(x); type h(x);"))
+            .unwrap();
+        assert_eq!(rule_names(), ["allow", "f", "g"]);
+
+        // Reloading a source replaces it.
+        polar
+            .load_incremental(source("extra", "h(1); h(2);"))
+            .unwrap();
+        assert_eq!(rule_names(), ["allow", "f", "h"]);
+        let count = |src| {
+            let mut query = polar.new_query(src, false).unwrap();
+            let mut results = 0;
+            while let QueryEvent::Result { .. } = query.next_event().unwrap() {
+                results += 1;
+            }
+            results
+        };
+        assert_eq!(count("h(x)"), 2);
+
+        // Rules in the new source are checked against all rule types...
+        let err = polar
+            .load_incremental(source("more", "type h(x, y);"))
+            .unwrap_err();
+        assert!(matches!(err.unwrap_validation(), InvalidRule { .. }));
+        // ...and the KB is left untouched if they don't match.
+        assert_eq!(count("h(x)"), 2);
+
+        // Unloading fails if other sources depend on the unloaded one.
+        polar
+            .load_incremental(source("extra", "g(x) if f(x);"))
+            .unwrap();
+        let err = polar.unload("base").unwrap_err();
+        assert!(matches!(err.unwrap_validation(), UndefinedRuleCall { .. }));
+        assert_eq!(rule_names(), ["allow", "f", "g"]);
+        polar.unload("extra").unwrap();
+        assert_eq!(rule_names(), ["allow", "f"]);
+        assert!(polar.unload("extra").is_err());
+
+        // Resource blocks and unions can't be loaded incrementally.
+        let err = polar
+            .load_incremental(source("blocks", "resource Repo {}"))
+            .unwrap_err();
+        assert!(matches!(err.unwrap_validation(), FileLoading { .. }));
+        for (id, class) in (1..).zip(["Repo", "Issue"]) {
+            let class_instance = ExternalInstance {
+                instance_id: id,
+                constructor: None,
+                repr: None,
+                class_repr: None,
+                class_id: Some(id),
+            };
+            polar
+                .register_constant(sym!(class), term!(Value::ExternalInstance(class_instance)))
+                .unwrap();
+            polar.register_mro(sym!(class), vec![id]).unwrap();
+        }
+        let unions = "type Repository = Repo | Issue;";
+        let err = polar
+            .load_incremental(source("unions", unions))
+            .unwrap_err();
+        assert!(matches!(err.unwrap_validation(), FileLoading { .. }));
+        assert_eq!(rule_names(), ["allow", "f"]);
+
+        // Other sources can be loaded and unloaded alongside them, but the sources declaring them
+        // can't be unloaded.
+        polar.clear_rules();
+        polar
+            .load(vec![
+                source("base", "allow(x, _, _) if f(x); f(1);"),
+                source("unions", unions),
+            ])
+            .unwrap();
+        polar
+            .load_incremental(source("extra", "public(_: Repository);"))
+            .unwrap();
+        assert_eq!(rule_names(), ["allow", "f", "public"]);
+        polar.unload("extra").unwrap();
+        let err = polar.unload("unions").unwrap_err();
+        assert!(matches!(err.unwrap_validation(), FileLoading { .. }));
+        assert_eq!(rule_names(), ["allow", "f"]);
+    }
+
+    #[test]
+    fn order_dependent_effectful_calls_warn() {
+        let mut polar = Polar::new();
+        polar.set_ignore_no_allow_warning(true);
+        polar.register_effectful_method(sym!("audit"));
+        polar.register_effectful_method(sym!("charge"));
+        let src = r#"f(x) if x.audit() and x.name = "alice";
+g(x) if x.name = "alice" or x.audit();
+h(x) if x.audit() and x.charge(1);
+"#;
+        let diagnostics = polar.diagnostic_load(vec![Source::new_with_name("file", src)]);
+        let messages = diagnostics
+            .iter()
+            .map(|diagnostic| {
+                assert!(matches!(diagnostic, Diagnostic::Warning(_)));
+                diagnostic.to_string()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(messages.len(), 2, "{:#?}", messages);
+        assert!(
+            messages[0].starts_with("Effectful call x.audit() in an `or` branch")
+                && messages[0].contains("at line 2, column 29 of file file"),
+            "{}",
+            messages[0]
+        );
+        assert!(
+            messages[1]
+                .starts_with("Effectful call x.charge(1) runs after effectful call x.audit()")
+                && messages[1].contains("Previous call at line 3, column 9 of file file")
+                && messages[1].contains("Called at line 3, column 23 of file file"),
+            "{}",
+            messages[1]
+        );
+    }
+
+    #[test]
+    fn duplicate_rules_warn_with_both_spans() {
+        let mut polar = Polar::new();
+        polar.set_ignore_no_allow_warning(true);
+        let src = "f(x) if x.a = 1;\ng(1);\nf(y) if y.a = 1;\n";
+        let diagnostics = polar.diagnostic_load(vec![Source::new_with_name("file", src)]);
+        assert_eq!(diagnostics.len(), 1, "{:#?}", diagnostics);
+        let message = diagnostics[0].to_string();
+        assert!(message.starts_with("Duplicate rule: f(y)"), "{}", message);
+        assert!(
+            message.contains("First defined at line 1, column 1 of file file"),
+            "{}",
+            message
+        );
+        assert!(
+            message.contains("Duplicated at line 3, column 1 of file file"),
+            "{}",
+            message
+        );
+    }
+
     #[test]
     fn test_valid_shorthand_rules_still_rewritten_in_presence_of_invalid_shorthand_rules() {
         let polar = Polar::new();
@@ -385,13 +1397,98 @@ mod tests {
 
         // After loading, we expect to have rewritten the `has_permission(_, "till", _) if ...;`
         // shorthand rule into the KB but not the `has_permission(_, "burn", _) if ...;` rule.
-        let kb = polar.kb.read().unwrap();
+        let kb = polar.kb.snapshot();
         let rules = kb.get_rules().values().flat_map(|g| g.rules.values());
         let has_permission_rules = rules
-            .filter(|r| r.name.0 == "has_permission")
+            .filter(|r| r.name.as_str() == "has_permission")
             .collect::<Vec<_>>();
         assert_eq!(has_permission_rules.len(), 1, "{:#?}", has_permission_rules);
         let has_permission_rule = has_permission_rules.into_iter().next().unwrap();
         assert_eq!(has_permission_rule.params[1].parameter, term!("till"));
     }
+
+    #[test]
+    fn namespaces_layer_over_the_kb() {
+        let polar = Polar::new();
+        polar
+            .load_str(
+                r#"type role(user, resource);
+                   can(user, resource) if role(user, resource);
+                   role("admin", _resource);"#,
+            )
+            .unwrap();
+        polar
+            .load_in_namespace("acme", vec![Source::new(r#"role("alice", "doc");"#)])
+            .unwrap();
+        polar
+            .load_in_namespace("initech", vec![Source::new(r#"role("bob", "doc");"#)])
+            .unwrap();
+
+        let count = |namespace: Option<&str>, src| {
+            let mut query = match namespace {
+                Some(ns) => polar.new_query_in_namespace(ns, src, false).unwrap(),
+                None => polar.new_query(src, false).unwrap(),
+            };
+            let mut results = 0;
+            while let QueryEvent::Result { .. } = query.next_event().unwrap() {
+                results += 1;
+            }
+            results
+        };
+        assert_eq!(count(Some("acme"), r#"can("alice", "doc")"#), 1);
+        assert_eq!(count(Some("acme"), r#"can("admin", "doc")"#), 1);
+        assert_eq!(count(Some("acme"), r#"can("bob", "doc")"#), 0);
+        assert_eq!(count(Some("initech"), r#"can("bob", "doc")"#), 1);
+        assert_eq!(count(None, r#"can("alice", "doc")"#), 0);
+        assert_eq!(count(Some("unknown"), r#"can("admin", "doc")"#), 1);
+
+        // Namespaces are loaded and cleared independently.
+        let e = polar
+            .load_in_namespace("acme", vec![Source::new(r#"role("carol", "doc");"#)])
+            .unwrap_err();
+        assert!(matches!(e.unwrap_runtime(), MultipleLoadError));
+        polar.clear_namespace("acme");
+        assert_eq!(count(Some("acme"), r#"can("alice", "doc")"#), 0);
+        assert_eq!(count(Some("initech"), r#"can("bob", "doc")"#), 1);
+
+        // Rules in a namespace are checked against the KB's rule types.
+        let e = polar
+            .load_in_namespace("acme", vec![Source::new(r#"role("carol");"#)])
+            .unwrap_err();
+        assert!(matches!(e.unwrap_validation(), InvalidRule { .. }));
+        assert_eq!(count(Some("acme"), r#"can("admin", "doc")"#), 1);
+    }
+    #[cfg(feature = "bundle")]
+    #[test]
+    fn test_bundles_keep_source_locations_in_sets() {
+        use crate::visitor::{walk_term, Visitor};
+
+        /// The spans of the parsed terms in a rule body.
+        struct Spans(Vec<(usize, usize)>);
+        impl Visitor for Spans {
+            fn visit_term(&mut self, term: &Term) {
+                if let Some(context) = term.parsed_context() {
+                    self.0.push((context.left, context.right));
+                }
+                walk_term(self, term)
+            }
+        }
+        let spans = |polar: &Polar| {
+            let kb = polar.kb.snapshot();
+            let rule = &kb.get_generic_rule(&sym!("s")).unwrap().rules[&0];
+            let mut spans = Spans(vec![]);
+            spans.visit_term(&rule.body);
+            spans.0
+        };
+
+        let sources = || vec![Source::new_with_name("sets.polar", "s(x) if x = {1, [2]};")];
+        let polar = Polar::new();
+        polar.load(sources()).unwrap();
+        let bundle = polar.save_bundle().unwrap();
+        let loaded = Polar::new();
+        loaded.load_bundle(&bundle, &sources()).unwrap();
+
+        assert!(spans(&polar).contains(&(13, 14)));
+        assert_eq!(spans(&loaded), spans(&polar));
+    }
 }
@@ -1,15 +1,66 @@
+use std::cell::RefCell;
+use std::ops::BitOr;
+use std::rc::Rc;
+use std::sync::PoisonError;
+
+use super::bindings::Bindings;
+use super::debug_protocol::DebugRequest;
+#[cfg(feature = "suspend")]
+use super::error::unsupported;
 use super::error::PolarResult;
 use super::events::*;
+use super::explain::{ExplainReport, Explainer};
+use super::inline_query::ExpectedResults;
 use super::messages::*;
+use super::metrics::QueryMetrics;
+use super::normalize::PartialForm;
 use super::runnable::Runnable;
 use super::terms::*;
+use super::trace_spans::{SpanRecorder, TraceSpan};
+use super::traces::TraceFilter;
 use super::vm::*;
 
+/// Options that change which results a query yields. Combine them with `|`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct QueryFlags(u32);
+
+impl QueryFlags {
+    pub const NONE: Self = Self(0);
+    /// Yield each unique set of bindings once, even if the query succeeds in several ways.
+    /// Once the query's variables are bound to values without variables, other ways of
+    /// reaching a result that was already yielded aren't tried.
+    pub const DISTINCT: Self = Self(1);
+    /// Canonicalize the constraints of partial results: nested conjunctions and disjunctions are
+    /// flattened, double negatives and duplicate constraints are removed, and constraints are
+    /// sorted, so that equivalent results look the same.
+    pub const CANONICAL_PARTIALS: Self = Self(2);
+    /// Return the constraints of partial results canonicalized and in disjunctive normal form:
+    /// an `or` of `and`s, whose constraints contain no `and`s or `or`s.
+    pub const DNF_PARTIALS: Self = Self(4);
+    /// Yield a `QueryEvent::AggregateValue` for each value an aggregate like `count` collects, as
+    /// it's collected.
+    pub const STREAM_AGGREGATES: Self = Self(8);
+
+    pub fn contains(self, flags: Self) -> bool {
+        self.0 & flags.0 == flags.0
+    }
+}
+
+impl BitOr for QueryFlags {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
 pub struct Query {
     runnable_stack: Vec<(Box<dyn Runnable>, u64)>, // Tuple of Runnable + call_id.
     vm: PolarVirtualMachine,
     term: Term,
     done: bool,
+    /// The results an inline query must have, collected instead of returned.
+    pub(crate) expected: Option<ExpectedResults>,
 }
 
 impl Query {
@@ -19,6 +70,7 @@ impl Query {
             vm,
             term,
             done: false,
+            expected: None,
         }
     }
 
@@ -58,9 +110,23 @@ impl Query {
                 } else {
                     // VM is done.
                     assert!(self.runnable_stack.is_empty());
+                    if let Some(expected) = self.expected.take() {
+                        // Once the results are checked, the VM returns `Done` again.
+                        expected.check()?;
+                        return Ok(QueryEvent::Result {
+                            bindings: Bindings::new(),
+                            trace: None,
+                        });
+                    }
                     Ok(QueryEvent::Done { result })
                 }
             }
+            QueryEvent::Result { bindings, .. } if self.expected.is_some() => {
+                if let Some(expected) = &mut self.expected {
+                    expected.record(&bindings);
+                }
+                self.next_event()
+            }
             ev => Ok(ev),
         }
     }
@@ -97,6 +163,11 @@ impl Query {
         self.top_runnable().debug_command(command)
     }
 
+    /// Send a request to the debugger. Answers arrive as `QueryEvent::Debugger` events.
+    pub fn debug_request(&mut self, request: DebugRequest) -> PolarResult<()> {
+        self.top_runnable().debug_request(request)
+    }
+
     pub fn next_message(&self) -> Option<Message> {
         self.vm.messages.next()
     }
@@ -105,9 +176,150 @@ impl Query {
         self.vm.term_source(&self.term, true)
     }
 
+    /// Only include traces of rules matching `patterns` in results, e.g.,
+    /// `["has_role", "billing::*"]`. Has no effect unless the query was created with tracing on.
+    pub fn trace_filter<I, S>(&mut self, patterns: I)
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.vm.trace_filter = Some(TraceFilter::new(patterns));
+    }
+
+    /// Record which rules are tried while evaluating the query. Call before running the query,
+    /// then read the report, which also includes the query's `metrics`, with `explain_report`.
+    pub fn explain(&mut self) {
+        self.vm.explainer = Some(Rc::new(RefCell::new(Explainer::default())));
+    }
+
+    /// The report for a query being explained, covering the events produced so far.
+    pub fn explain_report(&self) -> Option<ExplainReport> {
+        self.vm
+            .explainer
+            .as_ref()
+            .map(|explainer| explainer.borrow().report(self.metrics()))
+    }
+
+    /// Record each attempt to evaluate a rule as a [`TraceSpan`]. Call before running the query,
+    /// then collect spans with `take_spans`.
+    pub fn record_spans(&mut self) {
+        self.vm.span_recorder = Some(Rc::new(RefCell::new(SpanRecorder::default())));
+    }
+
+    /// The spans that have ended since the last call, if the query is recording spans.
+    pub fn take_spans(&mut self) -> Vec<TraceSpan> {
+        self.vm
+            .span_recorder
+            .as_ref()
+            .map(|recorder| recorder.borrow_mut().take_spans())
+            .unwrap_or_default()
+    }
+
+    /// Counts of the work the query has done so far.
+    pub fn metrics(&self) -> QueryMetrics {
+        self.vm.metrics()
+    }
+
+    /// Also count goals by type, and rule attempts and external calls by rule, in `metrics`.
+    /// Call before running the query.
+    pub fn detailed_metrics(&mut self) {
+        self.vm.detailed_metrics = Some(Rc::new(RefCell::new(QueryMetrics::default())));
+    }
+
+    /// Set the query's flags. Call before running the query.
+    pub fn set_flags(&mut self, flags: QueryFlags) {
+        self.vm.seen_results = if flags.contains(QueryFlags::DISTINCT) {
+            Some(DistinctResults::new(&self.term))
+        } else {
+            None
+        };
+        self.vm.partial_form = if flags.contains(QueryFlags::DNF_PARTIALS) {
+            PartialForm::Dnf
+        } else if flags.contains(QueryFlags::CANONICAL_PARTIALS) {
+            PartialForm::Canonical
+        } else {
+            PartialForm::Simplified
+        };
+        self.vm.stream_aggregates = flags.contains(QueryFlags::STREAM_AGGREGATES);
+    }
+
     pub fn bind(&mut self, name: Symbol, value: Term) -> PolarResult<()> {
         self.vm.bind(&name, value)
     }
+
+    /// Memoize external calls along with `other`, so either query reuses results the other got.
+    pub(crate) fn share_external_call_memo(&mut self, other: &Query) {
+        self.vm.external_call_memo = other.vm.external_call_memo.clone();
+    }
+}
+
+/// A query parked with [`Query::suspend`], to pick up with [`Query::resume`]. It holds nothing
+/// tied to the thread that suspended it.
+#[cfg(feature = "suspend")]
+pub struct SuspendedQuery {
+    vm: SuspendedVm,
+    term: Term,
+    done: bool,
+    expected: Option<ExpectedResults>,
+}
+
+#[cfg(feature = "suspend")]
+impl Query {
+    /// Whether the query can be suspended now. It can't while it evaluates a negation, an
+    /// aggregate or `reachable`, or if it's being explained or recording spans.
+    pub fn can_suspend(&self) -> bool {
+        self.runnable_stack.is_empty() && self.vm.can_suspend()
+    }
+
+    /// Park the query between events, e.g., while the host waits for the answer to a slow
+    /// external call, so that it doesn't pin a worker thread. The query's goals, bindings and
+    /// choice points are moved into the returned token, which can be resumed on any thread.
+    /// Answers to events the query emitted before it was suspended are given to the resumed
+    /// query.
+    ///
+    /// Fails, dropping the query, unless [`Query::can_suspend`].
+    pub fn suspend(mut self) -> PolarResult<SuspendedQuery> {
+        if !self.can_suspend() {
+            return unsupported(
+                "queries can't be suspended while evaluating a negation, an aggregate or \
+                 `reachable`, or while being explained or recording spans",
+                &self.term,
+            );
+        }
+        // The metrics move with the VM, and are added to the totals when the resumed query is
+        // dropped.
+        let vm = std::mem::take(&mut self.vm).suspend()?;
+        Ok(SuspendedQuery {
+            vm,
+            term: self.term.clone(),
+            done: self.done,
+            expected: self.expected.take(),
+        })
+    }
+
+    /// Pick up a query where [`Query::suspend`] left it.
+    pub fn resume(state: SuspendedQuery) -> PolarResult<Self> {
+        Ok(Self {
+            runnable_stack: vec![],
+            vm: state.vm.resume()?,
+            term: state.term,
+            done: state.done,
+            expected: state.expected,
+        })
+    }
+}
+
+// Add the query's metrics to the totals of the `Polar` instance that started it.
+impl Drop for Query {
+    fn drop(&mut self) {
+        if let Some(totals) = &self.vm.metrics_totals {
+            let metrics = self.metrics();
+            totals
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .merge(&metrics);
+        }
+    }
 }
 
 // Query as an iterator returns `None` after the first time `Done` is seen
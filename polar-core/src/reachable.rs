@@ -0,0 +1,180 @@
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::rc::Rc;
+
+use crate::counter::Counter;
+use crate::debug_protocol::DebugRequest;
+use crate::error::{PolarError, PolarResult};
+use crate::events::QueryEvent;
+use crate::runnable::Runnable;
+use crate::terms::{Call, Symbol, Term, TermList, Value};
+use crate::vm::{Goal, PolarVirtualMachine};
+
+/// Name of the built-in graph traversal predicate.
+pub const REACHABLE: &str = "reachable";
+
+/// Return true if `name` refers to a predicate implemented by the VM rather than by rules.
+pub(crate) fn is_builtin_predicate(name: &Symbol) -> bool {
+    name.as_str() == REACHABLE
+}
+
+/// `Reachable` implements the `reachable(start, relation, target, max_depth)` built-in.
+///
+/// It is a `Runnable` that performs a breadth-first traversal of the graph described by the
+/// two-argument rule named `relation`, starting at `start`. Each edge is discovered by querying
+/// `relation(node, next)` in a child VM. Nodes are expanded at most once, so cyclic and
+/// densely-connected graphs are traversed in time linear in the number of edges instead of the
+/// number of paths, and the goal stack does not grow with the depth of the graph.
+///
+/// Every node found at a distance between 1 and `max_depth` from `start` is appended to
+/// `reachable`, in the order it was discovered, for the parent VM to unify with the target.
+#[derive(Clone)]
+pub struct Reachable {
+    /// Template VM used to spawn one child VM per expanded node.
+    vm: PolarVirtualMachine,
+
+    relation: Symbol,
+    start: Term,
+    max_depth: u64,
+
+    /// Distance from `start` of the nodes in `frontier`.
+    depth: u64,
+    /// Nodes at the current depth that have yet to be expanded.
+    frontier: Vec<Term>,
+    /// Nodes discovered while expanding the current depth.
+    next_frontier: Vec<Term>,
+    /// Nodes that have already been discovered.
+    visited: HashSet<Term>,
+
+    /// Child VM for the node currently being expanded and the variable bound to its successors.
+    current: Option<(PolarVirtualMachine, Term)>,
+
+    /// Nodes to return to the parent VM.
+    reachable: Rc<RefCell<TermList>>,
+}
+
+impl Reachable {
+    pub fn new(
+        vm: &PolarVirtualMachine,
+        relation: Symbol,
+        start: Term,
+        max_depth: u64,
+        reachable: Rc<RefCell<TermList>>,
+    ) -> Self {
+        Self {
+            vm: vm.clone_with_goals(vec![]),
+            relation,
+            frontier: vec![start.clone()],
+            start,
+            max_depth,
+            depth: 0,
+            next_frontier: vec![],
+            visited: HashSet::new(),
+            current: None,
+            reachable,
+        }
+    }
+
+    /// Spawn a child VM that enumerates the successors of `node`.
+    fn expand(&mut self, node: Term) {
+        let next = Term::from(self.vm.gensym("reachable_next"));
+        let term = node.clone_with_value(Value::Call(Call {
+            name: self.relation.clone(),
+            args: vec![node.clone(), next.clone()],
+            kwargs: None,
+        }));
+        let vm = self.vm.clone_with_goals(vec![Goal::Query { term }]);
+        self.current = Some((vm, next));
+    }
+
+    /// Record a successor, queueing it for expansion if it has not been seen before.
+    fn visit(&mut self, node: Term) {
+        // Successors that the relation leaves unbound can't be traversed.
+        if matches!(node.value(), Value::Variable(_) | Value::RestVariable(_)) {
+            return;
+        }
+        if self.visited.insert(node.clone()) {
+            self.reachable.borrow_mut().push(node.clone());
+            if node != self.start {
+                self.next_frontier.push(node);
+            }
+        }
+    }
+
+    fn current_vm(&mut self) -> Option<&mut PolarVirtualMachine> {
+        self.current.as_mut().map(|(vm, _)| vm)
+    }
+}
+
+impl Runnable for Reachable {
+    fn run(&mut self, _: Option<&mut Counter>) -> PolarResult<QueryEvent> {
+        loop {
+            if let Some((vm, next)) = self.current.as_mut() {
+                match vm.run(None)? {
+                    QueryEvent::Result { .. } => {
+                        let node = vm.relevant_bindings(&[next]).remove(next.as_symbol()?);
+                        if let Some(node) = node {
+                            self.visit(node);
+                        }
+                    }
+                    QueryEvent::Done { .. } => self.current = None,
+                    event => return Ok(event),
+                }
+                continue;
+            }
+
+            if self.depth < self.max_depth {
+                if let Some(node) = self.frontier.pop() {
+                    self.expand(node);
+                    continue;
+                }
+            }
+
+            // The current depth is exhausted; descend another level if there's anything left.
+            self.depth += 1;
+            if self.depth >= self.max_depth || self.next_frontier.is_empty() {
+                return Ok(QueryEvent::Done { result: true });
+            }
+            self.frontier = self.next_frontier.drain(..).rev().collect();
+        }
+    }
+
+    fn external_question_result(&mut self, call_id: u64, answer: bool) -> PolarResult<()> {
+        match self.current_vm() {
+            Some(vm) => vm.external_question_result(call_id, answer),
+            None => self.vm.external_question_result(call_id, answer),
+        }
+    }
+
+    fn external_call_result(&mut self, call_id: u64, term: Option<Term>) -> PolarResult<()> {
+        match self.current_vm() {
+            Some(vm) => vm.external_call_result(call_id, term),
+            None => self.vm.external_call_result(call_id, term),
+        }
+    }
+
+    fn debug_command(&mut self, command: &str) -> PolarResult<()> {
+        match self.current_vm() {
+            Some(vm) => vm.debug_command(command),
+            None => self.vm.debug_command(command),
+        }
+    }
+
+    fn debug_request(&mut self, request: DebugRequest) -> PolarResult<()> {
+        match self.current_vm() {
+            Some(vm) => vm.debug_request(request),
+            None => self.vm.debug_request(request),
+        }
+    }
+
+    fn clone_runnable(&self) -> Box<dyn Runnable> {
+        Box::new(self.clone())
+    }
+
+    fn handle_error(&mut self, error: PolarError) -> PolarResult<QueryEvent> {
+        match self.current_vm() {
+            Some(vm) => vm.handle_error(error),
+            None => Err(error),
+        }
+    }
+}
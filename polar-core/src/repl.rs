@@ -0,0 +1,256 @@
+//! Support for defining rules interactively, e.g., at a REPL prompt, rather than in policy files.
+//!
+//! Rules defined in a [`ReplSession`] are loaded into a `Polar` as the source named
+//! [`REPL_FILENAME`], alongside any policy files, and can be undefined again or saved back out
+//! as Polar source with [`ReplSession::dump`].
+
+use crate::error::{unsupported, ErrorKind, ParseError, ParseErrorKind, PolarError, PolarResult};
+use crate::introspection::Span;
+use crate::lexer::{Lexer, Token};
+use crate::parser::{self, Line};
+use crate::polar::Polar;
+use crate::sources::Source;
+use crate::terms::Symbol;
+
+/// The filename that rules defined at the REPL are loaded under.
+pub const REPL_FILENAME: &str = "<repl>";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ReplInput {
+    /// A query to run, e.g., `f(x)` or `?= f(x);`.
+    Query(String),
+    /// Rules or rule types to define, e.g., `f(x) if x > 1;`.
+    Definitions(String),
+    /// The start of a query or definition that continues on the next line.
+    Incomplete,
+}
+
+fn is_unexpected_eof(error: &PolarError) -> bool {
+    matches!(
+        error,
+        PolarError(ErrorKind::Parse(ParseError {
+            kind: ParseErrorKind::UnrecognizedEOF { .. },
+            ..
+        }))
+    )
+}
+
+/// Decide what to do with `input` typed at the prompt.
+///
+/// Input ending in `;` defines rules, unless it's an inline query (`?= f(x);`). Other input is
+/// a query, unless it stops in the middle of one or of a definition, e.g., after `f(x) if`.
+pub fn classify_input(input: &str) -> ReplInput {
+    let input = input.trim();
+    let inline_query = input.strip_prefix("?=").map(str::trim);
+    if input.ends_with(';') {
+        return match inline_query {
+            Some(query) => ReplInput::Query(query.trim_end_matches(';').trim_end().to_owned()),
+            None => ReplInput::Definitions(input.to_owned()),
+        };
+    }
+
+    let query = inline_query.unwrap_or(input);
+    if query.is_empty() {
+        return ReplInput::Query(String::new());
+    }
+    match parser::parse_query(query) {
+        Err(e) if is_unexpected_eof(&e) => ReplInput::Incomplete,
+        Err(_) => match parser::parse_lines(Source::new(input)) {
+            Err(e) if is_unexpected_eof(&e) => ReplInput::Incomplete,
+            _ => ReplInput::Query(query.to_owned()),
+        },
+        Ok(_) => ReplInput::Query(query.to_owned()),
+    }
+}
+
+/// Split `src` into its top-level statements, each ending in `;`.
+fn statements(src: &str) -> Vec<&str> {
+    let mut statements = vec![];
+    let (mut start, mut depth) = (0, 0);
+    for (_, token, end) in Lexer::new(src).flatten() {
+        match token {
+            Token::LCB => depth += 1,
+            Token::RCB => depth -= 1,
+            Token::SemiColon if depth == 0 => {
+                statements.push(src[start..end].trim());
+                start = end;
+            }
+            _ => (),
+        }
+    }
+    statements
+}
+
+/// The rules defined at a REPL, in the order they were defined.
+#[derive(Clone, Debug, Default)]
+pub struct ReplSession {
+    /// The source of each rule or rule type, and the name of the rule it defines.
+    definitions: Vec<(Symbol, String)>,
+}
+
+impl ReplSession {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Define the rules and rule types in `src` alongside the rules already loaded into `polar`.
+    ///
+    /// If any of them fails to load, none of them are defined.
+    pub fn define(&mut self, polar: &Polar, src: &str) -> PolarResult<()> {
+        parser::parse_lines(Source::new(src))?;
+
+        let mut definitions = self.definitions.clone();
+        for statement in statements(src) {
+            let name = match parser::parse_lines(Source::new(statement))?.pop() {
+                Some(Line::Rule(rule))
+                | Some(Line::RuleType(rule))
+                | Some(Line::DeprecatedRule { rule, .. })
+                | Some(Line::PrivateRule(rule)) => rule.name,
+                Some(Line::Query { term, .. }) => {
+                    return unsupported("inline queries among REPL definitions", term)
+                }
+                Some(Line::UnionType { name: term, .. })
+                | Some(Line::ResourceBlock { resource: term, .. }) => {
+                    return unsupported(
+                        "union types and resource blocks at the REPL; load them from a file",
+                        term,
+                    )
+                }
+                None => continue,
+            };
+            definitions.push((name, statement.to_owned()));
+        }
+        self.load(polar, definitions)
+    }
+
+    /// Remove the rules and rule types named `name` that were defined at the REPL. Returns
+    /// `false` if there weren't any. Rules loaded from files are left alone.
+    pub fn undefine(&mut self, polar: &Polar, name: &str) -> PolarResult<bool> {
+        let definitions = self
+            .definitions
+            .iter()
+            .filter(|(defined, _)| defined.as_str() != name)
+            .cloned()
+            .collect::<Vec<_>>();
+        if definitions.len() == self.definitions.len() {
+            return Ok(false);
+        }
+        self.load(polar, definitions)?;
+        Ok(true)
+    }
+
+    fn load(&mut self, polar: &Polar, definitions: Vec<(Symbol, String)>) -> PolarResult<()> {
+        if definitions.is_empty() {
+            if !self.definitions.is_empty() {
+                polar.unload(REPL_FILENAME)?;
+            }
+        } else {
+            polar.load_incremental(Source::new_with_name(
+                REPL_FILENAME,
+                definitions
+                    .iter()
+                    .map(|(_, src)| src.as_str())
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            ))?;
+        }
+        self.definitions = definitions;
+        Ok(())
+    }
+
+    /// The rules defined at the REPL as Polar source, e.g., to save them to a policy file.
+    pub fn dump(&self) -> String {
+        self.definitions
+            .iter()
+            .map(|(_, src)| format!("{}\n", src))
+            .collect()
+    }
+}
+
+/// The heads of the rules loaded into `polar`, sorted by name, and where each was defined.
+pub fn loaded_rules(polar: &Polar) -> Vec<String> {
+    let kb = polar.kb.snapshot();
+    let mut generic_rules = kb.get_rules().values().collect::<Vec<_>>();
+    generic_rules.sort_by(|a, b| a.name.cmp(&b.name));
+    generic_rules
+        .into_iter()
+        .flat_map(|generic_rule| generic_rule.sorted_rules())
+        .map(|rule| match Span::of_rule(&rule) {
+            Some(Span {
+                filename,
+                start_line,
+                ..
+            }) => format!(
+                "{} at {}:{}",
+                rule.head_as_string(),
+                filename.as_deref().unwrap_or("<unknown>"),
+                start_line
+            ),
+            None => rule.head_as_string(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::QueryEvent;
+
+    #[test]
+    fn test_classify_input() {
+        let query = |q: &str| ReplInput::Query(q.to_owned());
+        assert_eq!(classify_input("f(x)"), query("f(x)"));
+        assert_eq!(classify_input(" ?= f(x) and g(x);"), query("f(x) and g(x)"));
+        assert_eq!(classify_input("f(x) and"), ReplInput::Incomplete);
+        assert_eq!(classify_input("f(x) if"), ReplInput::Incomplete);
+        assert_eq!(classify_input("f(x) if\n  x > 1"), ReplInput::Incomplete);
+        assert_eq!(
+            classify_input("f(x) if\n  x > 1;"),
+            ReplInput::Definitions("f(x) if\n  x > 1;".to_owned())
+        );
+        // Errors are reported when the input is run.
+        assert_eq!(classify_input("f(x))"), query("f(x))"));
+        assert_eq!(classify_input(""), query(""));
+    }
+
+    #[test]
+    fn test_repl_session() {
+        let polar = Polar::new();
+        polar
+            .load(vec![Source::new_with_name("policy.polar", "g(1);")])
+            .unwrap();
+        let mut session = ReplSession::new();
+        session
+            .define(&polar, "f(x) if x > 1 and g(1);\nh(\"a;b\");")
+            .unwrap();
+        session.define(&polar, "f(0);").unwrap();
+        let mut query = polar.new_query("f(0) and f(2) and h(_)", false).unwrap();
+        assert!(matches!(
+            query.next_event().unwrap(),
+            QueryEvent::Result { .. }
+        ));
+        assert_eq!(
+            loaded_rules(&polar),
+            vec![
+                "f(x) at <repl>:1",
+                "f(0) at <repl>:3",
+                "g(1) at policy.polar:1",
+                "h(\"a;b\") at <repl>:2"
+            ]
+        );
+
+        // Nothing is defined if any definition fails.
+        assert!(session.define(&polar, "i(1); allow(1);").is_err());
+        assert!(session.define(&polar, "type Res = A | B;").is_err());
+        assert_eq!(
+            session.dump(),
+            "f(x) if x > 1 and g(1);\nh(\"a;b\");\nf(0);\n"
+        );
+
+        assert!(session.undefine(&polar, "f").unwrap());
+        assert!(!session.undefine(&polar, "g").unwrap());
+        assert_eq!(session.dump(), "h(\"a;b\");\n");
+        assert!(session.undefine(&polar, "h").unwrap());
+        assert_eq!(loaded_rules(&polar), vec!["g(1) at policy.polar:1"]);
+    }
+}
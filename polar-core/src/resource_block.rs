@@ -2,14 +2,24 @@ use serde::{Deserialize, Serialize};
 
 use std::collections::{HashMap, HashSet};
 
-use super::error::{invalid_state, PolarError, PolarResult, ValidationError};
+use super::error::{invalid_state, unexpected_value, PolarError, PolarResult, ValidationError};
 use super::kb::KnowledgeBase;
+use super::rewrites::mark_generated;
 use super::rules::*;
+use super::sources::Origin;
 use super::terms::*;
 
 pub const ACTOR_UNION_NAME: &str = "Actor";
 pub const RESOURCE_UNION_NAME: &str = "Resource";
 
+/// Name of the rules that implement a single step of a transitive relation.
+pub const DIRECT_RELATION_RULE_NAME: &str = "has_direct_relation";
+
+/// Maximum number of `has_direct_relation` steps followed by the rules generated for a transitive
+/// relation. The rules are unrolled rather than recursive so that partially evaluating them, e.g.,
+/// for data filtering, terminates with one set of constraints per depth.
+pub const MAX_TRANSITIVE_RELATION_DEPTH: usize = 8;
+
 // TODO(gj): round up longhand `has_permission/3` and `has_role/3` rules to incorporate their
 // referenced permissions & roles (implied & implier side) into the exhaustiveness checks.
 
@@ -27,7 +37,7 @@ pub enum Production {
 }
 
 fn validate_relation_keyword(keyword: &Term) -> PolarResult<()> {
-    if keyword.as_symbol()?.0 != "on" {
+    if keyword.as_symbol()?.as_str() != "on" {
         return Err(ValidationError::ResourceBlock {
             msg: format!(
                 "Unexpected relation keyword '{}'. Did you mean 'on'?",
@@ -45,20 +55,22 @@ pub enum ParsedDeclaration {
     Roles(Term),       // List<String>
     Permissions(Term), // List<String>
     Relations(Term),   // Dict<Symbol, Symbol>
+    Fields(Term),      // Dict<Symbol, Symbol>
 }
 
 pub fn validate_parsed_declaration((name, term): (Term, Term)) -> PolarResult<ParsedDeclaration> {
-    match (name.as_symbol()?.0.as_ref(), term.value()) {
+    match (name.as_symbol()?.as_str(), term.value()) {
         ("roles", Value::List(_)) => Ok(ParsedDeclaration::Roles(term)),
         ("permissions", Value::List(_)) => Ok(ParsedDeclaration::Permissions(term)),
         ("relations", Value::Dictionary(_)) => Ok(ParsedDeclaration::Relations(term)),
+        ("fields", Value::Dictionary(_)) => Ok(ParsedDeclaration::Fields(term)),
 
         ("roles", Value::Dictionary(_)) | ("permissions", Value::Dictionary(_)) => Err(ValidationError::ResourceBlock {
             msg: format!("Expected '{}' declaration to be a list of strings; found a dictionary", name),
             term
         }),
-        ("relations", Value::List(_)) => Err(ValidationError::ResourceBlock {
-            msg: "Expected 'relations' declaration to be a dictionary; found a list".to_owned(),
+        ("relations", Value::List(_)) | ("fields", Value::List(_)) => Err(ValidationError::ResourceBlock {
+            msg: format!("Expected '{}' declaration to be a dictionary; found a list", name),
             term
         }),
 
@@ -67,7 +79,7 @@ pub fn validate_parsed_declaration((name, term): (Term, Term)) -> PolarResult<Pa
             term
         }),
         (_, Value::Dictionary(_)) => Err(ValidationError::ResourceBlock {
-            msg: format!("Unexpected declaration '{}'. Did you mean for this to be 'relations = {{ ... }};'?", name),
+            msg: format!("Unexpected declaration '{}'. Did you mean for this to be 'relations = {{ ... }};' or 'fields = {{ ... }};'?", name),
             term
         }),
         _ => unreachable!(),
@@ -76,7 +88,7 @@ pub fn validate_parsed_declaration((name, term): (Term, Term)) -> PolarResult<Pa
 
 pub fn block_type_from_keyword(keyword: Option<Term>, resource: &Term) -> PolarResult<BlockType> {
     if let Some(keyword) = keyword {
-        match keyword.as_symbol()?.0.as_ref() {
+        match keyword.as_symbol()?.as_str() {
             "actor" => Ok(BlockType::Actor),
             "resource" => Ok(BlockType::Resource),
             other => Err(ValidationError::ResourceBlock {
@@ -121,6 +133,7 @@ pub fn resource_block_from_productions(
     let mut roles: Option<Term> = None;
     let mut permissions: Option<Term> = None;
     let mut relations: Option<Term> = None;
+    let mut fields: Option<Term> = None;
     let mut shorthand_rules = vec![];
 
     // TODO(gj): attach 'previous' to error via `related_info` section.
@@ -160,6 +173,12 @@ pub fn resource_block_from_productions(
                         }
                         relations = Some(new);
                     }
+                    Ok(ParsedDeclaration::Fields(new)) => {
+                        if let Some(previous) = fields {
+                            errors.push(make_error("fields", &previous, &new));
+                        }
+                        fields = Some(new);
+                    }
                     Err(e) => errors.push(e),
                 }
             }
@@ -177,6 +196,7 @@ pub fn resource_block_from_productions(
             roles,
             permissions,
             relations,
+            fields,
             shorthand_rules,
         },
         errors,
@@ -188,7 +208,17 @@ pub enum Declaration {
     Role,
     Permission,
     /// `Term` is a `Symbol` that is the (registered) type of the relation. E.g., `Org` in `parent: Org`.
-    Relation(Term),
+    Relation(Term, RelationKind),
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum RelationKind {
+    /// Implemented by `has_relation` rules. E.g., `parent: Org`.
+    Direct,
+    /// Every resource reachable by following the relation one or more times, e.g., all ancestors
+    /// of a folder in `parent: Folder transitive`. A single step is implemented by
+    /// `has_direct_relation` rules, and the `has_relation` rules are generated from them.
+    Transitive,
 }
 
 #[derive(Clone, Debug, Hash, PartialEq, Eq)]
@@ -204,14 +234,16 @@ pub struct ShorthandRule {
 impl ShorthandRule {
     pub fn as_rule(&self, resource_name: &Term, blocks: &ResourceBlocks) -> PolarResult<Rule> {
         let Self { head, body } = self;
-        Ok(Rule {
+        let rule = Rule {
             name: blocks.get_rule_name_for_declaration_in_resource_block(head, resource_name)?,
             params: shorthand_rule_head_to_params(head, resource_name)?,
             body: shorthand_rule_body_to_rule_body(body, resource_name, blocks)?,
             // Copy SourceInfo from head of shorthand rule.
             source_info: head.source_info().clone(),
             required: false,
-        })
+        };
+        let origin = Origin::ResourceBlock(resource_name.to_string());
+        Ok(mark_generated(rule, &origin))
     }
 }
 
@@ -239,15 +271,19 @@ pub struct ResourceBlock {
     pub roles: Option<Term>,
     pub permissions: Option<Term>,
     pub relations: Option<Term>,
+    pub fields: Option<Term>,
     pub shorthand_rules: Vec<ShorthandRule>,
 }
 
 #[derive(Clone, Default)]
 pub struct ResourceBlocks {
     /// Map from resource (`Symbol`) to the declarations in that resource's block.
-    declarations: HashMap<Term, Declarations>,
+    pub(crate) declarations: HashMap<Term, Declarations>,
     /// Map from resource (`Symbol`) to the shorthand rules declared in that resource's block.
     pub shorthand_rules: HashMap<Term, Vec<ShorthandRule>>,
+    /// Map from resource (`Symbol`) to the types of the fields declared in that resource's block,
+    /// e.g., `fields = { name: String };`.
+    pub(crate) fields: HashMap<Term, HashMap<Symbol, Term>>,
     /// Set of all resource block types declared as actors. Internally treated like a union type
     /// where all declared types are members of the union.
     pub actors: HashSet<Term>,
@@ -261,6 +297,7 @@ impl ResourceBlocks {
         Self {
             declarations: HashMap::new(),
             shorthand_rules: HashMap::new(),
+            fields: HashMap::new(),
             actors: HashSet::new(),
             resources: HashSet::new(),
         }
@@ -269,15 +306,26 @@ impl ResourceBlocks {
     pub fn clear(&mut self) {
         self.declarations.clear();
         self.shorthand_rules.clear();
+        self.fields.clear();
         self.actors.clear();
         self.resources.clear();
     }
 
+    /// The resources of every block, and every member of the `Actor` and `Resource` unions.
+    pub(crate) fn resource_terms(&self) -> impl Iterator<Item = &Term> {
+        self.declarations
+            .keys()
+            .chain(self.shorthand_rules.keys())
+            .chain(&self.actors)
+            .chain(&self.resources)
+    }
+
     fn add(
         &mut self,
         block_type: BlockType,
         resource: Term,
         declarations: Declarations,
+        fields: HashMap<Symbol, Term>,
         shorthand_rules: Vec<ShorthandRule>,
     ) -> Vec<PolarError> {
         let mut errors = vec![];
@@ -304,6 +352,26 @@ impl ResourceBlocks {
             self.declarations.insert(resource.clone(), declarations);
         }
 
+        // Merge field types if we are reopening a resource block.
+        let existing = self.fields.entry(resource.clone()).or_default();
+        for (field, new) in fields {
+            match existing.get(&field) {
+                Some(previous) if previous != &new => errors.push(
+                    ValidationError::ResourceBlock {
+                        msg: format!(
+                            "Field '{}' in resource {} is declared as both {} and {}.",
+                            field, resource, previous, new
+                        ),
+                        term: new,
+                    }
+                    .into(),
+                ),
+                _ => {
+                    existing.insert(field, new);
+                }
+            }
+        }
+
         // Merge existing shorthand rules if we are reopening a resource block, otherwise add new
         self.shorthand_rules
             .entry(resource.clone())
@@ -341,6 +409,13 @@ impl ResourceBlocks {
         }
     }
 
+    /// The declared type of `field` on instances of `resource`, if any.
+    pub(crate) fn field_type(&self, resource: &Term, field: &Symbol) -> Option<&Term> {
+        self.fields
+            .get(resource)
+            .and_then(|fields| fields.get(field))
+    }
+
     /// Look up `relation` in `resource` block and return its type.
     pub fn get_relation_type_in_resource_block(
         &self,
@@ -348,7 +423,7 @@ impl ResourceBlocks {
         resource: &Term,
     ) -> PolarResult<&Term> {
         let declaration = self.get_declaration_in_resource_block(relation, resource)?;
-        if let Declaration::Relation(related_type) = declaration {
+        if let Declaration::Relation(related_type, _) = declaration {
             Ok(related_type)
         } else {
             invalid_state(format!("Expected Relation; got: {:?}", declaration))
@@ -407,13 +482,28 @@ impl ResourceBlocks {
         let mut tuples = vec![];
         for (object, declarations) in self.declarations() {
             for (name, declaration) in declarations {
-                if let Declaration::Relation(subject) = declaration {
+                if let Declaration::Relation(subject, _) = declaration {
                     tuples.push((subject, name, object));
                 }
             }
         }
         tuples
     }
+
+    /// Pairs of (resource, relation) for every transitive relation. A transitive relation always
+    /// relates its resource to itself.
+    pub fn transitive_relations(&self) -> Vec<(&Term, &Term)> {
+        let mut relations = vec![];
+        for (resource, declarations) in self.declarations() {
+            for (name, declaration) in declarations {
+                if let Declaration::Relation(_, RelationKind::Transitive) = declaration {
+                    relations.push((resource, name));
+                }
+            }
+        }
+        relations.sort_by_key(|(resource, name)| (resource.to_string(), name.to_string()));
+        relations
+    }
 }
 
 // TODO(gj): build up errors but keep on truckin'.
@@ -461,8 +551,19 @@ fn index_declarations(
             // `"creator" => Relation(User)` so that when we encounter a shorthand rule
             // `"admin" if "creator";` we can easily look up what type of declaration `"creator"`
             // is.
-            let stringified_relation = relation_type.clone_with_value(value!(relation.0.as_str()));
-            let declaration = Declaration::Relation(relation_type.clone());
+            let stringified_relation = relation_type.clone_with_value(value!(relation.as_str()));
+            let (relation_type, kind) = relation_type_and_kind(relation_type)?;
+            if kind == RelationKind::Transitive && &relation_type != resource {
+                return Err(ValidationError::ResourceBlock {
+                    msg: format!(
+                        "Transitive relation '{}' must relate '{}' to itself; found '{}'.",
+                        relation, resource, relation_type
+                    ),
+                    term: relation_type,
+                }
+                .into());
+            }
+            let declaration = Declaration::Relation(relation_type, kind);
 
             if let Some(existing) =
                 declarations.insert(stringified_relation.clone(), declaration.clone())
@@ -480,14 +581,51 @@ fn index_declarations(
     Ok(declarations)
 }
 
+/// Map each declared field to its type, e.g., `name` to `String` in `fields = { name: String };`.
+fn index_fields(fields: Option<Term>) -> PolarResult<HashMap<Symbol, Term>> {
+    let mut types = HashMap::new();
+    if let Some(fields) = fields {
+        for (field, field_type) in &fields.as_dict()?.fields {
+            field_type.as_symbol()?;
+            types.insert(field.clone(), field_type.clone());
+        }
+    }
+    Ok(types)
+}
+
+/// Split a declared relation, e.g., `Org` in `parent: Org` or `Folder transitive` in
+/// `parent: Folder transitive`, into its type and kind.
+fn relation_type_and_kind(relation: &Term) -> PolarResult<(Term, RelationKind)> {
+    match relation.value() {
+        Value::List(terms) => {
+            let (relation_type, kind) = match &terms[..] {
+                [relation_type, kind] => (relation_type, kind),
+                _ => return unexpected_value("relation", relation.clone()),
+            };
+            if kind.as_symbol()?.as_str() != "transitive" {
+                return Err(ValidationError::ResourceBlock {
+                    msg: format!(
+                        "Unexpected relation kind '{}'. Did you mean 'transitive'?",
+                        kind
+                    ),
+                    term: kind.clone(),
+                }
+                .into());
+            }
+            Ok((relation_type.clone(), RelationKind::Transitive))
+        }
+        _ => Ok((relation.clone(), RelationKind::Direct)),
+    }
+}
+
 fn resource_name_as_var(resource_name: &Term, related: bool) -> PolarResult<Value> {
-    let name = &resource_name.as_symbol()?.0;
+    let name: &str = resource_name.as_symbol()?.as_str();
     let mut lowercased = name.to_lowercase();
 
     // If the resource's name is already lowercase, append "_instance" to distinguish the variable
     // name from the resource's name. In most cases, the resource name will not be lowercase (e.g.,
     // `Organization` or `RepositorySettings`).
-    if &lowercased == name {
+    if lowercased == name {
         lowercased += "_instance";
     }
 
@@ -574,9 +712,75 @@ fn shorthand_rule_body_to_rule_body(
     }
 }
 
+/// Generate the `has_relation` rules for the transitive `relation` of `resource` by chaining its
+/// `has_direct_relation` rules. E.g., for `parent: Folder transitive` the rule for a depth of 2 is:
+///
+/// ```polar
+/// has_relation(related_folder: Folder, "parent", folder: Folder) if
+///     has_direct_relation(folder_1, "parent", folder) and
+///     has_direct_relation(related_folder, "parent", folder_1);
+/// ```
+pub fn transitive_relation_rules(relation: &Term, resource: &Term) -> PolarResult<Vec<Rule>> {
+    let resource_name = resource.as_symbol()?.as_str();
+    let specializer = resource.clone_with_value(value!(pattern!(instance!(resource_name))));
+    let object = relation.clone_with_value(resource_name_as_var(resource, false)?);
+    let subject = relation.clone_with_value(resource_name_as_var(resource, true)?);
+    let params = vec![
+        Parameter {
+            parameter: subject.clone(),
+            specializer: Some(specializer.clone()),
+        },
+        Parameter {
+            parameter: relation.clone(),
+            specializer: None,
+        },
+        Parameter {
+            parameter: object.clone(),
+            specializer: Some(specializer),
+        },
+    ];
+
+    let object_name = object.as_symbol()?.as_str();
+    let origin = Origin::ResourceBlock(resource_name.to_string());
+    (1..=MAX_TRANSITIVE_RELATION_DEPTH)
+        .map(|depth| {
+            // The resources along the path, from `object` to `subject`.
+            let intermediates = (1..depth).map(|i| {
+                relation.clone_with_value(value!(sym!(&format!("{}_{}", object_name, i))))
+            });
+            let path = std::iter::once(object.clone())
+                .chain(intermediates)
+                .chain(std::iter::once(subject.clone()))
+                .collect::<Vec<_>>();
+            let steps = path
+                .windows(2)
+                .map(|step| {
+                    relation.clone_with_value(value!(Call {
+                        name: sym!(DIRECT_RELATION_RULE_NAME),
+                        args: vec![step[1].clone(), relation.clone(), step[0].clone()],
+                        kwargs: None
+                    }))
+                })
+                .collect();
+            let rule = Rule {
+                name: sym!("has_relation"),
+                params: params.clone(),
+                body: relation.clone_with_value(value!(Operation {
+                    operator: Operator::And,
+                    args: steps
+                })),
+                // Copy SourceInfo from the relation's declaration.
+                source_info: relation.source_info().clone(),
+                required: false,
+            };
+            Ok(mark_generated(rule, &origin))
+        })
+        .collect()
+}
+
 /// Turn a shorthand rule head into a trio of params that go in the head of the rewritten rule.
 fn shorthand_rule_head_to_params(head: &Term, resource: &Term) -> PolarResult<Vec<Parameter>> {
-    let resource_name = &resource.as_symbol()?.0;
+    let resource_name = resource.as_symbol()?.as_str();
     let params = vec![
         Parameter {
             parameter: head.clone_with_value(value!(sym!("actor"))),
@@ -608,15 +812,19 @@ impl ResourceBlock {
             roles,
             permissions,
             relations,
+            fields,
             shorthand_rules,
         } = self;
 
-        match index_declarations(roles, permissions, relations, &resource) {
-            Ok(declarations) => {
+        let indexed = index_declarations(roles, permissions, relations, &resource)
+            .and_then(|declarations| Ok((declarations, index_fields(fields)?)));
+        match indexed {
+            Ok((declarations, fields)) => {
                 errors.extend(kb.resource_blocks.add(
                     block_type,
                     resource,
                     declarations,
+                    fields,
                     shorthand_rules,
                 ));
             }
@@ -672,12 +880,14 @@ mod tests {
             BlockType::Resource,
             repo_resource,
             repo_declarations.unwrap(),
+            HashMap::new(),
             vec![],
         );
         blocks.add(
             BlockType::Resource,
             org_resource,
             org_declarations.unwrap(),
+            HashMap::new(),
             vec![],
         );
         let shorthand_rule = ShorthandRule {
@@ -705,6 +915,7 @@ mod tests {
             BlockType::Resource,
             resource.clone(),
             declarations.unwrap(),
+            HashMap::new(),
             vec![],
         );
 
@@ -727,7 +938,13 @@ mod tests {
         let permissions = term!(["invite", "create_repo"]);
         let declarations = index_declarations(Some(roles), Some(permissions), None, &resource);
         let mut blocks = ResourceBlocks::new();
-        blocks.add(BlockType::Resource, resource, declarations.unwrap(), vec![]);
+        blocks.add(
+            BlockType::Resource,
+            resource,
+            declarations.unwrap(),
+            HashMap::new(),
+            vec![],
+        );
         let shorthand_rule = ShorthandRule {
             head: term!("member"),
             body: (term!("owner"), None),
@@ -780,12 +997,14 @@ mod tests {
             BlockType::Resource,
             repo_resource,
             repo_declarations.unwrap(),
+            HashMap::new(),
             vec![],
         );
         blocks.add(
             BlockType::Resource,
             org_resource,
             org_declarations.unwrap(),
+            HashMap::new(),
             vec![],
         );
         let shorthand_rule = ShorthandRule {
@@ -856,10 +1075,10 @@ mod tests {
         "#;
 
         p.load_str(valid_policy).unwrap();
-        // Create explicit scope to allow the RWLock obtained from kb.read() to
+        // Create explicit scope to allow the snapshot obtained from kb.snapshot() to
         // be dropped explicitly and independently of the function scope.
         {
-            let blocks = &p.kb.read().unwrap().resource_blocks;
+            let blocks = &p.kb.snapshot().resource_blocks;
             let declarations = blocks.declarations.get(&term!(sym!("Repo"))).unwrap();
             assert_eq!(declarations.len(), 6);
             let shorthand_rules = blocks.shorthand_rules.get(&term!(sym!("Repo"))).unwrap();
@@ -916,10 +1135,10 @@ mod tests {
         "#;
 
         p.load_str(valid_policy).unwrap();
-        // Create explicit scope to allow the RWLock obtained from kb.read() to
+        // Create explicit scope to allow the snapshot obtained from kb.snapshot() to
         // be dropped explicitly and independently of the function scope.
         {
-            let blocks = &p.kb.read().unwrap().resource_blocks;
+            let blocks = &p.kb.snapshot().resource_blocks;
             let declarations = blocks.declarations.get(&term!(sym!("Repo"))).unwrap();
             assert_eq!(declarations.len(), 6);
             let shorthand_rules = blocks.shorthand_rules.get(&term!(sym!("Repo"))).unwrap();
@@ -960,7 +1179,7 @@ mod tests {
         "#;
         p.load_str(valid_policy).unwrap();
         {
-            let blocks = &p.kb.read().unwrap().resource_blocks;
+            let blocks = &p.kb.snapshot().resource_blocks;
             let declarations = blocks.declarations.get(&term!(sym!("Repo"))).unwrap();
             assert_eq!(declarations.len(), 6);
             let shorthand_rules = blocks.shorthand_rules.get(&term!(sym!("Repo"))).unwrap();
@@ -1208,6 +1427,75 @@ mod tests {
         p.load_str(policy).unwrap();
     }
 
+    #[test]
+    fn test_resource_block_transitive_relation_rules() {
+        let resource = term!(sym!("Folder"));
+        let rules = transitive_relation_rules(&term!("parent"), &resource).unwrap();
+        assert_eq!(rules.len(), MAX_TRANSITIVE_RELATION_DEPTH);
+        assert_eq!(
+            rules[0].to_string(),
+            r#"has_relation(related_folder: Folder{}, "parent", folder: Folder{}) if has_direct_relation(related_folder, "parent", folder);"#
+        );
+        assert_eq!(
+            rules[2].to_string(),
+            r#"has_relation(related_folder: Folder{}, "parent", folder: Folder{}) if has_direct_relation(folder_1, "parent", folder) and has_direct_relation(folder_2, "parent", folder_1) and has_direct_relation(related_folder, "parent", folder_2);"#
+        );
+    }
+
+    #[test]
+    fn test_resource_block_with_transitive_relation() {
+        let p = Polar::new();
+        p.register_constant(sym!("Folder"), term!("unimportant"))
+            .unwrap();
+        p.register_constant(sym!("Org"), term!("unimportant"))
+            .unwrap();
+
+        let policy = r#"
+            resource Folder {
+                roles = ["reader"];
+                relations = { parent: Folder transitive, org: Org };
+            }
+            has_role(_: Actor, _: String, _: Resource);
+        "#;
+        let error = p.load_str(policy).unwrap_err();
+        assert!(error.to_string().contains(
+            r#"Missing implementation for required rule has_direct_relation(subject: Folder{}, "parent", object: Folder{});"#
+        ));
+
+        let policy = format!(
+            "{}{}",
+            policy,
+            r#"has_direct_relation(parent: Folder, "parent", folder: Folder) if folder.parent = parent;"#
+        );
+        p.load_str(&policy).unwrap();
+        {
+            let kb = p.kb.snapshot();
+            let has_relation = kb.get_generic_rule(&sym!("has_relation")).unwrap();
+            assert_eq!(has_relation.rules.len(), MAX_TRANSITIVE_RELATION_DEPTH);
+            let block = &kb.resource_blocks.declarations[&term!(sym!("Folder"))];
+            assert_eq!(
+                block[&term!("parent")],
+                Declaration::Relation(term!(sym!("Folder")), RelationKind::Transitive)
+            );
+            assert_eq!(
+                block[&term!("org")],
+                Declaration::Relation(term!(sym!("Org")), RelationKind::Direct)
+            );
+        }
+        p.clear_rules();
+
+        expect_error(
+            &p,
+            "resource Folder { relations = { parent: Folder recursive }; }",
+            "Unexpected relation kind 'recursive'. Did you mean 'transitive'?",
+        );
+        expect_error(
+            &p,
+            "resource Folder { relations = { org: Org transitive }; }",
+            "Transitive relation 'org' must relate 'Folder' to itself; found 'Org'.",
+        );
+    }
+
     #[test]
     fn test_resource_block_with_clashing_declarations() {
         let p = Polar::new();
@@ -1268,6 +1556,7 @@ mod tests {
                 sym!("creator") => term!(sym!("User")),
                 sym!("parent") => term!(sym!("Org")),
             })),
+            fields: None,
             shorthand_rules: vec![
                 // TODO(gj): shorthand_rule! macro
                 ShorthandRule {
@@ -1420,7 +1709,7 @@ mod tests {
         expect_error(
             &p,
             r#"resource Org{foo={};}"#,
-            r#"Unexpected declaration 'foo'. Did you mean for this to be 'relations = { ... };'?"#,
+            r#"Unexpected declaration 'foo'. Did you mean for this to be 'relations = { ... };' or 'fields = { ... };'?"#,
         );
     }
 
@@ -1732,7 +2021,7 @@ mod tests {
 
         polar.load_str(policy)?;
 
-        let kb = polar.kb.read().unwrap();
+        let kb = polar.kb.snapshot();
 
         let has_role_rule_types = kb.get_rule_types(&sym!("has_role")).unwrap();
         // has_role(actor: Actor, role: String, resource: Resource)
@@ -1777,7 +2066,7 @@ mod tests {
 
         polar.load_str(policy)?;
 
-        let kb = polar.kb.read().unwrap();
+        let kb = polar.kb.snapshot();
 
         let has_role_rule_types = kb.get_rule_types(&sym!("has_role")).unwrap();
         // has_role(actor: Actor, role: String, resource: Resource)
@@ -1787,4 +2076,47 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_generated_rules_point_back_at_their_declarations() {
+        let p = Polar::new();
+        p.register_constant(sym!("Repo"), term!("unimportant"))
+            .unwrap();
+        let policy = r#"resource Repo {
+  relations = { parent: Repo };
+  roles = ["reader"];
+  permissions = ["read"];
+  "read" if "reader" on "parent";
+}
+has_role(_: Actor, _: String, _: Resource);
+has_relation(subject: Repo, "parent", object: Repo) if object.parent_id = subject.id;"#;
+        p.load(vec![Source::new_with_name("repo.polar", policy)])
+            .unwrap();
+
+        let kb = p.kb.snapshot();
+        let generic_rule = &kb.get_rules()[&sym!("has_permission")];
+        let rule = generic_rule.rules.values().next().unwrap();
+        let context = rule.parsed_context().unwrap();
+        assert_eq!(
+            context.source_position(),
+            " generated from resource block Repo, line 5, column 3 of file repo.polar"
+        );
+        assert!(context
+            .to_string()
+            .contains(r#""read" if "reader" on "parent";"#));
+        // Terms in the body point at the part of the shorthand rule they were generated from.
+        assert_eq!(
+            rule.body.parsed_context().unwrap().source_position(),
+            " generated from resource block Repo, line 5, column 13 of file repo.polar"
+        );
+
+        let generic_rule = &kb.get_rules()[&sym!("has_relation")];
+        let rule = generic_rule.rules.values().next().unwrap();
+        let context = rule.parsed_context().unwrap();
+        assert!(context.generated_from.is_none());
+        assert_eq!(
+            context.source_position(),
+            " at line 8, column 1 of file repo.polar"
+        );
+    }
 }
@@ -1,58 +1,132 @@
-use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fmt::Write;
 
+use super::counter::Counter;
 use super::folder::*;
 use super::kb::*;
 use super::rules::*;
+use super::sources::Origin;
 use super::terms::*;
+use super::visitor::{walk_call, walk_operation, walk_term, Visitor};
+use super::vm::compare;
 
 /// Rename each non-constant variable in a term or rule to a fresh variable.
+///
+/// The VM renames the variables of every rule it calls, so this avoids allocating more than it
+/// has to: terms without variables are shared with the rule, every occurrence of a variable
+/// shares one term for the fresh variable, and the fresh names are written into a reused buffer
+/// and stored inline in their symbols.
 pub struct Renamer<'kb> {
     kb: &'kb KnowledgeBase,
-    renames: HashMap<Symbol, Symbol>,
+    counter: Counter,
+    /// Rules have few variables, so a list is faster than a map.
+    renames: Vec<Rename>,
+    name: String,
+}
+
+struct Rename {
+    from: Symbol,
+    to: Symbol,
+    /// A variable term for `to`, whose value every occurrence of `from` shares.
+    term: Term,
 }
 
 impl<'kb> Renamer<'kb> {
-    pub fn new(kb: &'kb KnowledgeBase) -> Self {
+    /// Number fresh variables with `counter`.
+    pub fn new(kb: &'kb KnowledgeBase, counter: Counter) -> Self {
         Self {
             kb,
-            renames: HashMap::new(),
+            counter,
+            renames: vec![],
+            name: String::new(),
         }
     }
+
+    fn is_renamed(&self, v: &Symbol) -> bool {
+        self.renames.iter().any(|rename| &rename.from == v)
+    }
+
+    /// Rename `v`, like `gensym_from(&self.counter, v)`, unless it was renamed already.
+    fn rename(&mut self, v: &Symbol) -> &Rename {
+        if let Some(i) = self.renames.iter().position(|rename| &rename.from == v) {
+            return &self.renames[i];
+        }
+        let next = self.counter.next();
+        self.name.clear();
+        // Writing to a `String` can't fail.
+        let _ = match v.as_str() {
+            "_" => write!(self.name, "_{}", next),
+            prefix => write!(self.name, "_{}_{}", prefix, next),
+        };
+        let to = Symbol::new(&self.name);
+        self.renames.push(Rename {
+            from: v.clone(),
+            term: Term::new_temporary(Value::Variable(to.clone())),
+            to,
+        });
+        self.renames.last().unwrap()
+    }
 }
 
 impl<'kb> Folder for Renamer<'kb> {
-    fn fold_variable(&mut self, v: Symbol) -> Symbol {
-        if self.kb.is_constant(&v) {
-            v
-        } else if let Some(w) = self.renames.get(&v) {
-            w.clone()
-        } else {
-            let w = self.kb.gensym(&v.0);
-            self.renames.insert(v, w.clone());
-            w
+    fn fold_term(&mut self, t: Term) -> Term {
+        match t.value() {
+            Value::Variable(v) if self.kb.is_constant(v) && !self.is_renamed(v) => t,
+            Value::Variable(v) => t.clone_with_value_of(&self.rename(v).term),
+            Value::RestVariable(r) => {
+                let to = self.rename(r).to.clone();
+                t.clone_with_value(Value::RestVariable(to))
+            }
+            _ => {
+                // Terms without variables are shared with the rule rather than copied.
+                let mut variables = HasVariables(false);
+                variables.visit_term(&t);
+                if variables.0 {
+                    fold_term(t, self)
+                } else {
+                    t
+                }
+            }
         }
     }
+}
 
-    fn fold_rest_variable(&mut self, r: Symbol) -> Symbol {
-        if let Some(s) = self.renames.get(&r) {
-            s.clone()
-        } else {
-            let s = self.kb.gensym(&r.0);
-            self.renames.insert(r, s.clone());
-            s
+/// Finds variables, stopping at the first one.
+struct HasVariables(bool);
+
+impl Visitor for HasVariables {
+    fn visit_term(&mut self, t: &Term) {
+        if !self.0 {
+            walk_term(self, t)
         }
     }
+
+    fn visit_variable(&mut self, _: &Symbol) {
+        self.0 = true;
+    }
+
+    fn visit_rest_variable(&mut self, _: &Symbol) {
+        self.0 = true;
+    }
 }
 
 /// Rewrite expressions, etc.
-pub struct Rewriter<'kb> {
-    kb: &'kb KnowledgeBase,
+pub struct Rewriter {
+    counter: Counter,
     stack: Vec<Vec<Term>>,
 }
 
-impl<'kb> Rewriter<'kb> {
-    pub fn new(kb: &'kb KnowledgeBase) -> Self {
-        Self { kb, stack: vec![] }
+impl Rewriter {
+    pub fn new(kb: &KnowledgeBase) -> Self {
+        Self::with_counter(kb.gensym_counter())
+    }
+
+    /// Number temporary variables with `counter` rather than the KB's own counter.
+    pub fn with_counter(counter: Counter) -> Self {
+        Self {
+            counter,
+            stack: vec![],
+        }
     }
 
     /// Return true if the expression should be rewritten.
@@ -70,6 +144,11 @@ impl<'kb> Rewriter<'kb> {
                 true
             }
             Operator::New if o.args.len() == 1 => true,
+            Operator::Count | Operator::Sum | Operator::Min | Operator::Max
+                if o.args.len() == 3 =>
+            {
+                true
+            }
             _ => false,
         }
     }
@@ -80,6 +159,7 @@ fn temp_name(o: &Operator) -> &'static str {
         Operator::Add | Operator::Div | Operator::Mul | Operator::Sub => "op",
         Operator::Dot => "value",
         Operator::New => "instance",
+        Operator::Count | Operator::Sum | Operator::Min | Operator::Max => "aggregate",
         _ => "temp",
     }
 }
@@ -87,7 +167,7 @@ fn temp_name(o: &Operator) -> &'static str {
 /// Replace `o(a, b)` with `_c`, where `_c = o(a, b)`.
 /// The lookup is hoisted to the nearest enclosing
 /// conjunction, creating one if necessary.
-impl<'kb> Folder for Rewriter<'kb> {
+impl Folder for Rewriter {
     /// Rewrite a rule, pushing expressions in the head into the body.
     fn fold_rule(
         &mut self,
@@ -133,9 +213,9 @@ impl<'kb> Folder for Rewriter<'kb> {
             }
             Value::Expression(o) if self.needs_rewrite(o) => {
                 // Rewrite sub-expressions, then push a temp onto the args.
-                let mut new = fold_operation(o.clone(), self);
-                let temp = Value::Variable(self.kb.gensym(temp_name(&o.operator)));
-                new.args.push(Term::from(temp.clone()));
+                let mut new = self.fold_operation(o.clone());
+                let temp = Value::Variable(gensym_from(&self.counter, temp_name(&o.operator)));
+                new.args.push(t.clone_with_value(temp.clone()));
 
                 // Push the rewritten expression into the top stack frame.
                 self.stack
@@ -209,21 +289,43 @@ impl<'kb> Folder for Rewriter<'kb> {
                 },
             },
 
+            // Temporary variables from the condition of an aggregate stay in the condition,
+            // since they depend on the aggregated variable.
+            Count | Sum | Min | Max => Operation {
+                operator: o.operator,
+                args: {
+                    let mut args = o.args.into_iter();
+                    let var = args.next().map(|arg| self.fold_term(arg));
+                    let collection = args.next().map(|arg| self.fold_term(arg));
+                    let condition = args.next().map(|arg| {
+                        self.stack.push(vec![]);
+                        let condition = self.fold_term(arg);
+                        let rewrites = self.stack.pop().unwrap();
+                        rewrites.into_iter().rfold(condition, and_op_)
+                    });
+                    var.into_iter()
+                        .chain(collection)
+                        .chain(condition)
+                        .chain(args.map(|arg| self.fold_term(arg)))
+                        .collect()
+                },
+            },
+
             _ => fold_operation(o, self),
         }
     }
 
     fn fold_rest_variable(&mut self, v: Symbol) -> Symbol {
-        if v.0 == "_" {
-            self.kb.gensym("_")
+        if v.as_str() == "_" {
+            gensym_from(&self.counter, "_")
         } else {
             v
         }
     }
 
     fn fold_variable(&mut self, v: Symbol) -> Symbol {
-        if v.0 == "_" {
-            self.kb.gensym("_")
+        if v.as_str() == "_" {
+            gensym_from(&self.counter, "_")
         } else {
             v
         }
@@ -262,9 +364,9 @@ pub fn unwrap_and(term: &Term) -> TermList {
     }
 }
 
-/// Rewrite a term.
-pub fn rewrite_term(term: Term, kb: &mut KnowledgeBase) -> Term {
-    let mut fld = Rewriter::new(kb);
+/// Rewrite a term, numbering its temporary variables with `counter`.
+pub fn rewrite_term(term: Term, counter: Counter) -> Term {
+    let mut fld = Rewriter::with_counter(counter);
     fld.fold_term(term)
 }
 
@@ -274,6 +376,284 @@ pub fn rewrite_rule(rule: Rule, kb: &mut KnowledgeBase) -> Rule {
     fld.fold_rule(rule)
 }
 
+/// Marks the source info of every term in a generated rule with its origin.
+struct GeneratedMarker<'origin>(&'origin Origin);
+
+impl<'origin> Folder for GeneratedMarker<'origin> {
+    fn fold_term(&mut self, t: Term) -> Term {
+        let mut t = fold_term(t, self);
+        t.source_info_mut().mark_generated(self.0);
+        t
+    }
+}
+
+/// Record that `rule` was generated by `origin`, so that errors and traces in the rule point
+/// back at the declaration it was generated from.
+pub fn mark_generated(mut rule: Rule, origin: &Origin) -> Rule {
+    rule.source_info.mark_generated(origin);
+    GeneratedMarker(origin).fold_rule(rule)
+}
+
+/// How much [`optimize_rule`] simplifies rules as they're loaded.
+///
+/// Optimizations never change which results a query has, but `Reorder` can change the order
+/// they're returned in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum OptimizationLevel {
+    /// Load rules as written.
+    #[default]
+    None,
+    /// Evaluate conditions on constants, e.g., `1 < 2` or `x in []`, and remove branches
+    /// that can never succeed.
+    Fold,
+    /// Also move comparisons ahead of host lookups and rule calls that don't share their
+    /// variables, so that a rule fails before making calls it doesn't need.
+    Reorder,
+}
+
+/// What a condition in a rule body does, for deciding how it can be moved.
+#[derive(Default)]
+struct ConditionInfo {
+    variables: HashSet<Symbol>,
+    /// Looks up an attribute, calls a method or constructor, or calls a rule.
+    calls_out: bool,
+    /// Cuts, prints, or calls an effectful method, so it has to stay where it is.
+    pinned: bool,
+}
+
+impl ConditionInfo {
+    fn new(condition: &Term, kb: &KnowledgeBase) -> Self {
+        struct InfoVisitor<'kb> {
+            kb: &'kb KnowledgeBase,
+            info: ConditionInfo,
+        }
+
+        impl<'kb> Visitor for InfoVisitor<'kb> {
+            fn visit_variable(&mut self, v: &Symbol) {
+                self.info.variables.insert(v.clone());
+            }
+
+            fn visit_rest_variable(&mut self, r: &Symbol) {
+                self.info.variables.insert(r.clone());
+            }
+
+            fn visit_call(&mut self, c: &Call) {
+                self.info.calls_out = true;
+                walk_call(self, c);
+            }
+
+            fn visit_operation(&mut self, o: &Operation) {
+                match o.operator {
+                    Operator::Dot => {
+                        self.info.calls_out = true;
+                        if let Some(Value::Call(Call { name, .. })) = o.args.get(1).map(Term::value)
+                        {
+                            self.info.pinned |= self.kb.is_effectful_method(name);
+                        }
+                    }
+                    Operator::New => self.info.calls_out = true,
+                    Operator::Cut | Operator::Print | Operator::Debug => self.info.pinned = true,
+                    _ => (),
+                }
+                walk_operation(self, o);
+            }
+        }
+
+        let mut visitor = InfoVisitor {
+            kb,
+            info: Self::default(),
+        };
+        walk_term(&mut visitor, condition);
+        visitor.info
+    }
+}
+
+/// Whether `term` is a value that doesn't depend on variables or the host.
+fn is_constant(term: &Term) -> bool {
+    match term.value() {
+        Value::Number(_)
+        | Value::String(_)
+        | Value::Boolean(_)
+        | Value::DateTime(_)
+        | Value::Duration(_) => true,
+        Value::List(elements) => elements.iter().all(is_constant),
+        Value::Set(set) => set.iter().all(is_constant),
+        Value::Dictionary(dict) => dict.fields.values().all(is_constant),
+        _ => false,
+    }
+}
+
+/// Whether `condition` is a comparison that's cheap to evaluate before the conditions around it.
+fn is_cheap(condition: &Term, info: &ConditionInfo) -> bool {
+    use Operator::*;
+    if info.calls_out || info.pinned {
+        return false;
+    }
+    match condition.value() {
+        Value::Expression(Operation { operator, args }) => match operator {
+            Unify | Eq | Neq | Lt | Leq | Gt | Geq => true,
+            // Iterating over a variable may iterate over a host collection.
+            In => matches!(
+                args.get(1).map(Term::value),
+                Some(Value::List(_) | Value::Set(_) | Value::Dictionary(_) | Value::String(_))
+            ),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Simplify rule bodies. See [`OptimizationLevel`].
+struct Optimizer<'kb> {
+    kb: &'kb KnowledgeBase,
+    level: OptimizationLevel,
+}
+
+impl<'kb> Optimizer<'kb> {
+    /// Optimize a condition, replacing it with `true` or `false` if its result is known.
+    fn optimize(&self, condition: Term) -> Term {
+        use Operator::*;
+        let Operation { operator, args } = match condition.value() {
+            Value::Expression(o) => o.clone(),
+            _ => return condition,
+        };
+        let value = match operator {
+            And => {
+                let args = self.optimize_conjunction(args);
+                match args.as_slice() {
+                    [] => Value::Boolean(true),
+                    [arg] if matches!(arg.value(), Value::Boolean(false)) => Value::Boolean(false),
+                    _ => Value::Expression(Operation { operator, args }),
+                }
+            }
+            Or => {
+                let mut args: TermList = args
+                    .into_iter()
+                    .map(|arg| self.optimize(arg))
+                    .filter(|arg| !matches!(arg.value(), Value::Boolean(false)))
+                    .collect();
+                match args.len() {
+                    0 => Value::Boolean(false),
+                    1 => return args.remove(0),
+                    _ => Value::Expression(Operation { operator, args }),
+                }
+            }
+            Not | ForAll => {
+                let args: TermList = args.into_iter().map(|arg| self.optimize(arg)).collect();
+                match (operator, args.as_slice()) {
+                    (Not, [arg]) => match arg.value() {
+                        Value::Boolean(b) => Value::Boolean(!b),
+                        _ => Value::Expression(Operation { operator, args }),
+                    },
+                    _ => Value::Expression(Operation { operator, args }),
+                }
+            }
+            _ => match fold_constant(operator, &args) {
+                Some(result) => Value::Boolean(result),
+                None => return condition,
+            },
+        };
+        condition.clone_with_value(value)
+    }
+
+    /// Optimize the conditions of a conjunction, dropping those that are always true and
+    /// everything after one that's always false.
+    fn optimize_conjunction(&self, conditions: TermList) -> TermList {
+        let mut optimized = vec![];
+        for condition in conditions {
+            let condition = self.optimize(condition);
+            match condition.value() {
+                Value::Boolean(true) => (),
+                Value::Boolean(false) => {
+                    // Keep the conditions before it only if running them has side effects.
+                    if !optimized
+                        .iter()
+                        .any(|c| ConditionInfo::new(c, self.kb).pinned)
+                    {
+                        optimized.clear();
+                    }
+                    optimized.push(condition);
+                    return optimized;
+                }
+                _ => optimized.push(condition),
+            }
+        }
+        if self.level >= OptimizationLevel::Reorder {
+            optimized = self.reorder(optimized);
+        }
+        optimized
+    }
+
+    /// Move each cheap condition ahead of the calls before it, as long as it doesn't share any
+    /// variables with them. The calls themselves are never reordered.
+    fn reorder(&self, conditions: TermList) -> TermList {
+        let mut ordered: Vec<(Term, ConditionInfo)> = vec![];
+        for condition in conditions {
+            let info = ConditionInfo::new(&condition, self.kb);
+            let mut position = ordered.len();
+            if is_cheap(&condition, &info) {
+                while let Some((_, previous)) = position.checked_sub(1).map(|i| &ordered[i]) {
+                    if !previous.calls_out
+                        || previous.pinned
+                        || !previous.variables.is_disjoint(&info.variables)
+                    {
+                        break;
+                    }
+                    position -= 1;
+                }
+            }
+            ordered.insert(position, (condition, info));
+        }
+        ordered
+            .into_iter()
+            .map(|(condition, _)| condition)
+            .collect()
+    }
+}
+
+/// The result of `operator` applied to `args`, if they're constants.
+fn fold_constant(operator: Operator, args: &[Term]) -> Option<bool> {
+    use Operator::*;
+    match (operator, args) {
+        (Eq | Neq | Lt | Leq | Gt | Geq, [left, right])
+            if is_constant(left) && is_constant(right) =>
+        {
+            // Leave comparisons between incomparable types to fail at runtime.
+            compare(operator, left, right, None).ok()
+        }
+        (Unify, [left, right]) if is_constant(left) && is_constant(right) => Some(left == right),
+        (In, [item, collection]) => match collection.value() {
+            Value::List(elements) if elements.is_empty() => Some(false),
+            Value::Set(set) if set.is_empty() => Some(false),
+            Value::Dictionary(dict) if dict.fields.is_empty() => Some(false),
+            Value::List(elements) if is_constant(item) && is_constant(collection) => {
+                Some(elements.contains(item))
+            }
+            Value::Set(set) if is_constant(item) && is_constant(collection) => {
+                Some(set.contains(item))
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Optimize the body of a rule that has already been rewritten by [`rewrite_rule`], as much as
+/// the KB's [`OptimizationLevel`] allows.
+pub fn optimize_rule(rule: Rule, kb: &KnowledgeBase) -> Rule {
+    let level = kb.optimization_level();
+    if level == OptimizationLevel::None {
+        return rule;
+    }
+    let optimizer = Optimizer { kb, level };
+    let mut body = rule.body.clone();
+    body.replace_value(Value::Expression(Operation {
+        operator: Operator::And,
+        args: optimizer.optimize_conjunction(unwrap_and(&rule.body)),
+    }));
+    Rule { body, ..rule }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -289,10 +669,10 @@ mod tests {
 
     #[test]
     fn rewrite_anonymous_vars() {
-        let mut kb = KnowledgeBase::new();
+        let kb = KnowledgeBase::new();
         let query = parse_query("[1, 2, 3] = [_, _, _]");
         assert_eq!(
-            rewrite_term(query, &mut kb).to_string(),
+            rewrite_term(query, kb.gensym_counter()).to_string(),
             "[1, 2, 3] = [_1, _2, _3]"
         );
     }
@@ -334,7 +714,7 @@ mod tests {
             "foo(z, y) if forall(x in y, _value_1 < z and x.n = _value_1);"
         );
 
-        let query = rewrite_term(parse_query("forall(x in y, x.n < z)"), &mut kb);
+        let query = rewrite_term(parse_query("forall(x in y, x.n < z)"), kb.gensym_counter());
         assert_eq!(
             query.to_string(),
             "forall(x in y, _value_2 < z and x.n = _value_2)"
@@ -368,30 +748,30 @@ mod tests {
 
     #[test]
     fn rewrite_terms() {
-        let mut kb = KnowledgeBase::new();
+        let kb = KnowledgeBase::new();
         let term = parse_query("x and a.b");
         assert_eq!(term.to_string(), "x and a.b");
         assert_eq!(
-            rewrite_term(term, &mut kb).to_string(),
+            rewrite_term(term, kb.gensym_counter()).to_string(),
             "x and a.b = _value_1 and _value_1"
         );
 
         let query = parse_query("f(a.b().c)");
         assert_eq!(query.to_string(), "f(a.b().c)");
         assert_eq!(
-            rewrite_term(query, &mut kb).to_string(),
+            rewrite_term(query, kb.gensym_counter()).to_string(),
             "a.b() = _value_2 and _value_2.c = _value_3 and f(_value_3)"
         );
 
         let term = parse_query("a.b = 1");
         assert_eq!(
-            rewrite_term(term, &mut kb).to_string(),
+            rewrite_term(term, kb.gensym_counter()).to_string(),
             "a.b = _value_4 and _value_4 = 1"
         );
         let term = parse_query("{x: 1}.x = 1");
         assert_eq!(term.to_string(), "{x: 1}.x = 1");
         assert_eq!(
-            rewrite_term(term, &mut kb).to_string(),
+            rewrite_term(term, kb.gensym_counter()).to_string(),
             "{x: 1}.x = _value_5 and _value_5 = 1"
         );
     }
@@ -403,7 +783,7 @@ mod tests {
         let term = parse_query("0 - 0 = 0");
         assert_eq!(term.to_string(), "0 - 0 = 0");
         assert_eq!(
-            rewrite_term(term, &mut kb).to_string(),
+            rewrite_term(term, kb.gensym_counter()).to_string(),
             "0 - 0 = _op_1 and _op_1 = 0"
         );
 
@@ -421,43 +801,43 @@ mod tests {
 
     #[test]
     fn rewrite_nested_literal() {
-        let mut kb = KnowledgeBase::new();
+        let kb = KnowledgeBase::new();
         let term = parse_query("new Foo(x: bar.y)");
         assert_eq!(term.to_string(), "new Foo(x: bar.y)");
         assert_eq!(
-            rewrite_term(term, &mut kb).to_string(),
+            rewrite_term(term, kb.gensym_counter()).to_string(),
             "bar.y = _value_1 and new (Foo(x: _value_1), _instance_2) and _instance_2"
         );
 
         let term = parse_query("f(new Foo(x: bar.y))");
         assert_eq!(term.to_string(), "f(new Foo(x: bar.y))");
         assert_eq!(
-            rewrite_term(term, &mut kb).to_string(),
+            rewrite_term(term, kb.gensym_counter()).to_string(),
             "bar.y = _value_3 and new (Foo(x: _value_3), _instance_4) and f(_instance_4)"
         );
     }
 
     #[test]
     fn rewrite_class_constructor() {
-        let mut kb = KnowledgeBase::new();
+        let kb = KnowledgeBase::new();
         let term = parse_query("new Foo(a: 1, b: 2)");
         assert_eq!(term.to_string(), "new Foo(a: 1, b: 2)");
 
         // @ means external constructor
         assert_eq!(
-            rewrite_term(term, &mut kb).to_string(),
+            rewrite_term(term, kb.gensym_counter()).to_string(),
             "new (Foo(a: 1, b: 2), _instance_1) and _instance_1"
         );
     }
 
     #[test]
     fn rewrite_nested_class_constructor() {
-        let mut kb = KnowledgeBase::new();
+        let kb = KnowledgeBase::new();
         let term = parse_query("new Foo(a: 1, b: new Foo(a: 2, b: 3))");
         assert_eq!(term.to_string(), "new Foo(a: 1, b: new Foo(a: 2, b: 3))");
 
         assert_eq!(
-            rewrite_term(term, &mut kb).to_string(),
+            rewrite_term(term, kb.gensym_counter()).to_string(),
             "new (Foo(a: 2, b: 3), _instance_1) and \
              new (Foo(a: 1, b: _instance_1), _instance_2) and _instance_2"
         );
@@ -480,13 +860,84 @@ mod tests {
 
     #[test]
     fn rewrite_not_with_lookup() {
-        let mut kb = KnowledgeBase::new();
+        let kb = KnowledgeBase::new();
         let term = parse_query("not foo.x = 1");
         assert_eq!(term.to_string(), "not foo.x = 1");
 
         pretty_assertions::assert_eq!(
-            rewrite_term(term, &mut kb).to_string(),
+            rewrite_term(term, kb.gensym_counter()).to_string(),
             "not (_value_1 = 1 and foo.x = _value_1)"
         )
     }
+
+    fn optimize(src: &str, level: OptimizationLevel) -> String {
+        let mut kb = KnowledgeBase::new();
+        kb.register_effectful_method(sym!("charge"));
+        kb.set_optimization_level(level);
+        let rule = rewrite_rule(parse_rules(src)[0].clone(), &mut kb);
+        optimize_rule(rule, &kb).to_string()
+    }
+
+    #[test]
+    fn optimize_constants() {
+        use OptimizationLevel::*;
+        assert_eq!(
+            optimize("f(x) if 1 < 2 and x = 1;", None),
+            "f(x) if 1 < 2 and x = 1;"
+        );
+        assert_eq!(optimize("f(x) if 1 < 2 and x = 1;", Fold), "f(x) if x = 1;");
+        assert_eq!(
+            optimize("f(x) if x = 1 and [1, 2] = [1, 3] and x > 0;", Fold),
+            "f(x) if false;"
+        );
+        assert_eq!(
+            optimize("f(x) if x in [] or x = 1;", Fold),
+            "f(x) if x = 1;"
+        );
+        assert_eq!(
+            optimize(r#"f(x) if not 2 in {1, 3} and x = "a";"#, Fold),
+            r#"f(x) if x = "a";"#
+        );
+        // Incomparable constants still fail at runtime.
+        assert_eq!(
+            optimize(r#"f(x) if x = 1 and 1 < "a";"#, Fold),
+            r#"f(x) if x = 1 and 1 < "a";"#
+        );
+        // Conditions with side effects before a failure still run.
+        assert_eq!(
+            optimize("f(x) if print(x) and 1 = 2 and x = 1;", Fold),
+            "f(x) if print(x) and false;"
+        );
+    }
+
+    #[test]
+    fn optimize_condition_order() {
+        use OptimizationLevel::*;
+        assert_eq!(
+            optimize("f(a, x) if a.b = 1 and x > 0;", Fold),
+            "f(a, x) if _value_1 = 1 and a.b = _value_1 and x > 0;"
+        );
+        assert_eq!(
+            optimize("f(a, x) if a.b = 1 and x > 0;", Reorder),
+            "f(a, x) if x > 0 and _value_1 = 1 and a.b = _value_1;"
+        );
+        assert_eq!(
+            optimize("f(a, x) if g(a) and x in [1, 2];", Reorder),
+            "f(a, x) if x in [1, 2] and g(a);"
+        );
+        // Conditions that depend on a call stay after it.
+        assert_eq!(
+            optimize("f(a, x) if g(a, x) and x > 0;", Reorder),
+            "f(a, x) if g(a, x) and x > 0;"
+        );
+        // Nothing moves across cuts or effectful calls.
+        assert_eq!(
+            optimize("f(a, x) if g(a) and cut and x > 0;", Reorder),
+            "f(a, x) if g(a) and cut and x > 0;"
+        );
+        assert_eq!(
+            optimize("f(a, x) if a.charge() and x > 0;", Reorder),
+            "f(a, x) if a.charge() = _value_1 and _value_1 and x > 0;"
+        );
+    }
 }
@@ -81,7 +81,26 @@ impl Rule {
     }
 }
 
+/// A `@deprecated("message")` annotation on a rule.
+#[derive(Clone, Debug)]
+pub struct Deprecation {
+    /// The message, a string term.
+    pub message: Term,
+    /// The annotated rule.
+    pub rule: Rule,
+}
+
+impl Deprecation {
+    pub fn message(&self) -> &str {
+        match self.message.value() {
+            Value::String(message) => message,
+            _ => "",
+        }
+    }
+}
+
 // TODO: should this be a Set of Rules? Do we currently check for duplicate rules?
+#[derive(Clone)]
 pub struct RuleTypes(HashMap<Symbol, Vec<Rule>>);
 
 impl Default for RuleTypes {
@@ -92,6 +111,17 @@ impl Default for RuleTypes {
     }
 }
 
+impl FromIterator<Rule> for RuleTypes {
+    /// Rule types made up of exactly `rule_types`, without the default ones.
+    fn from_iter<I: IntoIterator<Item = Rule>>(rule_types: I) -> Self {
+        let mut result = Self(HashMap::new());
+        for rule_type in rule_types {
+            result.add(rule_type);
+        }
+        result
+    }
+}
+
 impl RuleTypes {
     fn add_default_rule_types(&mut self) {
         // type has_permission(actor: Actor, permission: String, resource: Resource);
@@ -131,6 +161,18 @@ impl RuleTypes {
         self.add_default_rule_types()
     }
 
+    pub fn iter(&self) -> impl Iterator<Item = &Rule> {
+        self.0.values().flatten()
+    }
+
+    /// Keep only the rule types for which `f` returns true.
+    pub fn retain<F: FnMut(&Rule) -> bool>(&mut self, mut f: F) {
+        for rule_types in self.0.values_mut() {
+            rule_types.retain(&mut f);
+        }
+        self.0.retain(|_, rule_types| !rule_types.is_empty());
+    }
+
     pub fn required_rule_types(&self) -> Vec<&Rule> {
         self.0
             .values()
@@ -205,11 +247,59 @@ impl RuleIndex {
     }
 }
 
+/// Rule ids grouped by arity and by the class tag their first parameter is specialized on,
+/// e.g., `User` for `allow(actor: User, action, resource)`.
+#[derive(Clone, Default, Debug)]
+struct DispatchIndex {
+    /// Rules whose first parameter isn't specialized on a class are under `None`.
+    by_arity: HashMap<usize, HashMap<Option<Symbol>, RuleSet>>,
+}
+
+impl DispatchIndex {
+    fn index_rule(&mut self, rule_id: u64, params: &[Parameter]) {
+        let tag = params
+            .first()
+            .and_then(|param| match param.specializer.as_ref()?.value() {
+                Value::Pattern(Pattern::Instance(InstanceLiteral { tag, .. }))
+                    if !param.is_ground() =>
+                {
+                    Some(tag.clone())
+                }
+                _ => None,
+            });
+        self.by_arity
+            .entry(params.len())
+            .or_default()
+            .entry(tag)
+            .or_default()
+            .insert(rule_id);
+    }
+
+    /// Rules of arity `arity`, leaving out those whose first parameter is specialized on a tag
+    /// for which `may_match` returns false.
+    fn get_applicable_rules<F>(&self, arity: usize, may_match: F) -> RuleSet
+    where
+        F: Fn(&Symbol) -> bool,
+    {
+        self.by_arity
+            .get(&arity)
+            .into_iter()
+            .flatten()
+            .filter(|(tag, _)| match tag {
+                Some(tag) => may_match(tag),
+                None => true,
+            })
+            .flat_map(|(_, rules)| rules.iter().copied())
+            .collect()
+    }
+}
+
 #[derive(Clone)]
 pub struct GenericRule {
     pub name: Symbol,
     pub rules: HashMap<u64, Arc<Rule>>,
     index: RuleIndex,
+    dispatch_index: DispatchIndex,
     next_rule_id: u64,
 }
 
@@ -219,6 +309,7 @@ impl GenericRule {
             name,
             rules: Default::default(),
             index: Default::default(),
+            dispatch_index: Default::default(),
             next_rule_id: 0,
         };
 
@@ -237,13 +328,84 @@ impl GenericRule {
             "Rule id already used."
         );
         self.index.index_rule(rule_id, &rule.params[..], 0);
+        self.dispatch_index.index_rule(rule_id, &rule.params[..]);
+    }
+
+    /// Remove the earliest added of the rules equal to `rule` and reindex the rest. Returns false
+    /// if none are equal.
+    pub fn remove_rule(&mut self, rule: &Rule) -> bool {
+        let id = match self
+            .rules
+            .iter()
+            .filter(|(_, r)| r.as_ref() == rule)
+            .map(|(id, _)| *id)
+            .min()
+        {
+            Some(id) => id,
+            None => return false,
+        };
+        self.rules.remove(&id);
+        self.reindex();
+        true
+    }
+
+    /// Keep only the rules for which `f` returns true.
+    pub fn retain<F: FnMut(&Rule) -> bool>(&mut self, mut f: F) {
+        let len = self.rules.len();
+        self.rules.retain(|_, rule| f(rule));
+        if self.rules.len() != len {
+            self.reindex();
+        }
+    }
+
+    fn reindex(&mut self) {
+        self.index = RuleIndex::default();
+        self.dispatch_index = DispatchIndex::default();
+        for (id, rule) in self.sorted_rules_with_ids() {
+            self.index.index_rule(id, &rule.params[..], 0);
+            self.dispatch_index.index_rule(id, &rule.params[..]);
+        }
+    }
+
+    /// Return the rules in the order they were added.
+    pub fn sorted_rules(&self) -> Rules {
+        self.sorted_rules_with_ids()
+            .into_iter()
+            .map(|(_, rule)| rule)
+            .collect()
+    }
+
+    fn sorted_rules_with_ids(&self) -> Vec<(u64, Arc<Rule>)> {
+        let mut rules = self
+            .rules
+            .iter()
+            .map(|(id, rule)| (*id, rule.clone()))
+            .collect::<Vec<_>>();
+        rules.sort_by_key(|(id, _)| *id);
+        rules
     }
 
     #[allow(clippy::ptr_arg)]
     pub fn get_applicable_rules(&self, args: &TermList) -> Rules {
-        self.index
-            .get_applicable_rules(args, 0)
-            .iter()
+        self.rules_by_id(&self.index.get_applicable_rules(args, 0))
+    }
+
+    /// Like `get_applicable_rules`, but also skip rules whose first parameter is specialized on
+    /// a class tag for which `first_arg_may_match` returns false.
+    #[allow(clippy::ptr_arg)]
+    pub fn dispatch<F>(&self, args: &TermList, first_arg_may_match: F) -> Rules
+    where
+        F: Fn(&Symbol) -> bool,
+    {
+        let dispatched = self
+            .dispatch_index
+            .get_applicable_rules(args.len(), first_arg_may_match);
+        let applicable = self.index.get_applicable_rules(args, 0);
+        self.rules_by_id(&applicable.intersection(&dispatched).copied().collect())
+    }
+
+    fn rules_by_id(&self, ids: &RuleSet) -> Rules {
+        ids.iter()
             .map(|id| self.rules.get(id).expect("Rule missing"))
             .cloned()
             .collect()
@@ -278,7 +440,7 @@ mod tests {
             )
             .unwrap();
 
-        let kb = polar.kb.read().unwrap();
+        let kb = polar.kb.snapshot();
         let generic_rule = kb.get_generic_rule(&sym!("f")).unwrap();
         let index = &generic_rule.index;
         assert!(index.rules.is_empty());
@@ -321,4 +483,60 @@ mod tests {
         let index13 = index1.index.get(&Some(value!(3))).unwrap();
         assert_eq!(args, keys(index13));
     }
+
+    #[test]
+    fn test_dispatch_index() {
+        let polar = Polar::new();
+        let instance = |instance_id, class_id| {
+            term!(Value::ExternalInstance(ExternalInstance {
+                instance_id,
+                constructor: None,
+                repr: None,
+                class_repr: None,
+                class_id,
+            }))
+        };
+        for (id, class, mro) in [
+            (1, "User", vec![1]),
+            (2, "Admin", vec![2, 1]),
+            (3, "Repo", vec![3]),
+        ] {
+            polar
+                .register_constant(sym!(class), instance(id, Some(id)))
+                .unwrap();
+            polar.register_mro(sym!(class), mro).unwrap();
+        }
+        polar
+            .load_str(
+                r#"
+            f(_: User, "user");
+            f(_: Admin, "admin");
+            f(_: Repo, "repo");
+            f(_, "any");
+            f(_: {x: 1}, "dict");
+            f(_: Repo);
+        "#,
+            )
+            .unwrap();
+
+        let kb = polar.kb.snapshot();
+        let generic_rule = kb.get_generic_rule(&sym!("f")).unwrap();
+        let applicable = |first_arg: Term| -> Vec<String> {
+            kb.get_applicable_rules(generic_rule, &vec![first_arg, term!(sym!("kind"))])
+                .iter()
+                .map(|rule| rule.params[1].parameter.to_string())
+                .collect()
+        };
+
+        let user = instance(10, Some(1));
+        assert_eq!(applicable(user), [r#""user""#, r#""any""#, r#""dict""#]);
+        let admin = instance(11, Some(2));
+        assert_eq!(
+            applicable(admin),
+            [r#""user""#, r#""admin""#, r#""any""#, r#""dict""#]
+        );
+        // Without class information, every rule of the right arity may apply.
+        assert_eq!(applicable(instance(12, None)).len(), 5);
+        assert_eq!(applicable(term!(sym!("x"))).len(), 5);
+    }
 }
@@ -1,4 +1,5 @@
 use crate::counter::Counter;
+use crate::debug_protocol::DebugRequest;
 use crate::error::{invalid_state, PolarError, PolarResult};
 use crate::events::QueryEvent;
 use crate::terms::Term;
@@ -26,6 +27,10 @@ pub trait Runnable {
         invalid_state("Unexpected debug command")
     }
 
+    fn debug_request(&mut self, _request: DebugRequest) -> PolarResult<()> {
+        invalid_state("Unexpected debug request")
+    }
+
     fn handle_error(&mut self, err: PolarError) -> PolarResult<QueryEvent> {
         Err(err)
     }
@@ -0,0 +1,250 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::error::{unsupported, PolarResult};
+use crate::parser;
+use crate::polar::Polar;
+use crate::query::Query;
+use crate::rules::{GenericRule, Parameter, Rule, Rules};
+use crate::sources::SourceInfo;
+use crate::terms::{Operation, Operator, Symbol, Term, TermList, Value};
+
+/// Facts told and retracted within a `Session` that haven't been committed to the KB.
+#[derive(Clone, Default)]
+pub struct SessionFacts {
+    told: HashMap<Symbol, GenericRule>,
+    retracted: Vec<Rule>,
+}
+
+impl SessionFacts {
+    /// Return true if the session has told any facts named `name`.
+    pub(crate) fn defines(&self, name: &Symbol) -> bool {
+        self.told.contains_key(name)
+    }
+
+    /// Layer the session's changes over `rules`, the rules from the KB applicable to `args`.
+    /// Each retracted fact hides one copy of it from the KB.
+    pub(crate) fn applicable_rules(&self, name: &Symbol, rules: Rules, args: &TermList) -> Rules {
+        let mut retracted = self.retracted.iter().collect::<Vec<_>>();
+        let mut rules = rules
+            .into_iter()
+            .filter(
+                |rule| match retracted.iter().position(|r| *r == rule.as_ref()) {
+                    Some(i) => {
+                        retracted.swap_remove(i);
+                        false
+                    }
+                    None => true,
+                },
+            )
+            .collect::<Rules>();
+        if let Some(told) = self.told.get(name) {
+            rules.append(&mut told.get_applicable_rules(args));
+        }
+        rules
+    }
+}
+
+/// Parse `src` into a fact: a rule with ground arguments and no body.
+fn parse_fact(src: &str) -> PolarResult<Rule> {
+    let term = parser::parse_query(src)?;
+    let call = match term.value() {
+        Value::Call(call)
+            if call.kwargs.is_none() && call.args.iter().all(|arg| arg.value().is_ground()) =>
+        {
+            call
+        }
+        _ => return unsupported("facts must be calls with ground arguments", term),
+    };
+    Ok(Rule {
+        name: call.name.clone(),
+        params: call
+            .args
+            .iter()
+            .map(|arg| Parameter {
+                parameter: arg.clone(),
+                specializer: None,
+            })
+            .collect(),
+        body: Term::from(Value::Expression(Operation {
+            operator: Operator::And,
+            args: vec![],
+        })),
+        source_info: SourceInfo::ffi(),
+        required: false,
+    })
+}
+
+/// A request-scoped view of a `Polar` instance.
+///
+/// Facts told and retracted through a session are visible to the session's own queries
+/// immediately, and to everyone else only once the session is committed. Dropping a session
+/// without committing it discards its changes.
+pub struct Session<'polar> {
+    polar: &'polar Polar,
+    facts: SessionFacts,
+}
+
+impl<'polar> Session<'polar> {
+    pub(crate) fn new(polar: &'polar Polar) -> Self {
+        Self {
+            polar,
+            facts: SessionFacts::default(),
+        }
+    }
+
+    /// Add the fact `src`, e.g. `has_role(user, "owner", repo)`, to the session.
+    pub fn tell(&mut self, src: &str) -> PolarResult<()> {
+        let fact = parse_fact(src)?;
+        if let Some(i) = self.facts.retracted.iter().position(|r| r == &fact) {
+            self.facts.retracted.remove(i);
+        } else {
+            self.facts
+                .told
+                .entry(fact.name.clone())
+                .or_insert_with(|| GenericRule::new(fact.name.clone(), vec![]))
+                .add_rule(Arc::new(fact));
+        }
+        Ok(())
+    }
+
+    /// Remove one copy of the fact `src` from the session, returning false if there was no
+    /// such fact.
+    pub fn retract(&mut self, src: &str) -> PolarResult<bool> {
+        let fact = parse_fact(src)?;
+        if let Some(told) = self.facts.told.get_mut(&fact.name) {
+            if told.remove_rule(&fact) {
+                if told.rules.is_empty() {
+                    self.facts.told.remove(&fact.name);
+                }
+                return Ok(true);
+            }
+        }
+
+        let retracted = self.facts.retracted.iter().filter(|r| *r == &fact).count();
+        let in_kb = self
+            .polar
+            .kb
+            .snapshot()
+            .get_generic_rule(&fact.name)
+            .map_or(0, |rule| {
+                rule.rules.values().filter(|r| r.as_ref() == &fact).count()
+            });
+        if in_kb > retracted {
+            self.facts.retracted.push(fact);
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    pub fn new_query(&self, src: &str, trace: bool) -> PolarResult<Query> {
+        let term = parser::parse_query(src)?;
+        Ok(self.new_query_from_term(term, trace))
+    }
+
+    pub fn new_query_from_term(&self, term: Term, trace: bool) -> Query {
+        self.polar
+            .new_query_with_facts(term, trace, Some(Arc::new(self.facts.clone())), None)
+    }
+
+    /// Apply the session's changes to the shared KB.
+    pub fn commit(self) {
+        let facts = self.facts;
+        self.polar.kb.update(|kb| {
+            for fact in &facts.retracted {
+                kb.remove_rule(fact);
+            }
+            let mut told = facts.told.into_values().collect::<Vec<_>>();
+            told.sort_by(|a, b| a.name.cmp(&b.name));
+            for generic_rule in told {
+                for rule in generic_rule.sorted_rules() {
+                    kb.add_rule(rule.as_ref().clone());
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::QueryEvent;
+
+    fn count_results(mut query: Query) -> usize {
+        let mut results = 0;
+        loop {
+            match query.next_event().unwrap() {
+                QueryEvent::Done { .. } => return results,
+                QueryEvent::Result { .. } => results += 1,
+                event => panic!("unexpected event {:?}", event),
+            }
+        }
+    }
+
+    #[test]
+    fn test_session_facts_are_private_until_committed() {
+        let polar = Polar::new();
+        polar
+            .load_str("owner(\"alice\", 1); can(u, r) if owner(u, r);")
+            .unwrap();
+
+        let mut session = polar.session();
+        session.tell("owner(\"bob\", 2)").unwrap();
+        assert!(session.retract("owner(\"alice\", 1)").unwrap());
+        assert!(!session.retract("owner(\"alice\", 1)").unwrap());
+
+        let q = |session: &Session, src| count_results(session.new_query(src, false).unwrap());
+        assert_eq!(q(&session, "can(\"bob\", 2)"), 1);
+        assert_eq!(q(&session, "can(\"alice\", 1)"), 0);
+
+        let p = |src| count_results(polar.new_query(src, false).unwrap());
+        assert_eq!(p("can(\"bob\", 2)"), 0);
+        assert_eq!(p("can(\"alice\", 1)"), 1);
+
+        session.commit();
+        assert_eq!(p("can(\"bob\", 2)"), 1);
+        assert_eq!(p("can(\"alice\", 1)"), 0);
+    }
+
+    #[test]
+    fn test_session_facts_for_undefined_rules() {
+        let polar = Polar::new();
+        let mut session = polar.session();
+        session.tell("member(\"alice\", \"eng\")").unwrap();
+        let query = session.new_query("member(\"alice\", x)", false).unwrap();
+        assert_eq!(count_results(query), 1);
+
+        // Retracting a told fact removes it from the session.
+        assert!(session.retract("member(\"alice\", \"eng\")").unwrap());
+        drop(session);
+        assert!(!polar.kb.snapshot().has_rules());
+    }
+
+    #[test]
+    fn test_retracting_one_copy_of_a_fact() {
+        let polar = Polar::new();
+        polar.load_str("f(1); f(1);").unwrap();
+
+        let mut session = polar.session();
+        assert!(session.retract("f(1)").unwrap());
+        let q = |session: &Session| count_results(session.new_query("f(1)", false).unwrap());
+        assert_eq!(q(&session), 1);
+        assert!(session.retract("f(1)").unwrap());
+        assert_eq!(q(&session), 0);
+        assert!(!session.retract("f(1)").unwrap());
+
+        session.tell("f(1)").unwrap();
+        assert_eq!(q(&session), 1);
+        session.commit();
+        assert_eq!(count_results(polar.new_query("f(1)", false).unwrap()), 1);
+    }
+
+    #[test]
+    fn test_facts_must_be_ground() {
+        let polar = Polar::new();
+        let mut session = polar.session();
+        assert!(session.tell("f(x)").is_err());
+        assert!(session.tell("1 = 1").is_err());
+    }
+}
@@ -0,0 +1,186 @@
+//! Sets of terms, written in policies as `{1, 2, 3}`.
+//!
+//! A set keeps its elements in the order they were first added, so that printing a set or
+//! iterating over it with `in` is deterministic, and indexes them for constant-time membership
+//! tests. Across the FFI a set is serialized as the list of its elements, e.g., `{"Set": [1, 2]}`.
+
+use std::cmp::Ordering;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
+
+use serde::{Deserialize, Serialize};
+
+use super::terms::{Term, TermList};
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(from = "TermList", into = "TermList")]
+pub struct Set {
+    elements: TermList,
+    index: HashSet<Term>,
+}
+
+impl Set {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `element` to the set. Returns `false` if it was already present.
+    pub fn insert(&mut self, element: Term) -> bool {
+        let inserted = self.index.insert(element.clone());
+        if inserted {
+            self.elements.push(element);
+        }
+        inserted
+    }
+
+    pub fn contains(&self, element: &Term) -> bool {
+        self.index.contains(element)
+    }
+
+    pub fn len(&self) -> usize {
+        self.elements.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.elements.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Term> {
+        self.elements.iter()
+    }
+
+    /// The elements in the order they were added.
+    pub fn elements(&self) -> &[Term] {
+        &self.elements
+    }
+
+    pub fn is_ground(&self) -> bool {
+        self.elements.iter().all(Term::is_ground)
+    }
+
+    pub fn is_subset(&self, other: &Self) -> bool {
+        self.len() <= other.len() && self.iter().all(|element| other.contains(element))
+    }
+
+    /// The elements of `self` followed by the elements of `other` that aren't in `self`.
+    pub fn union(&self, other: &Self) -> Self {
+        let mut union = self.clone();
+        for element in other.iter() {
+            union.insert(element.clone());
+        }
+        union
+    }
+
+    /// The elements of `self` that are also in `other`.
+    pub fn intersection(&self, other: &Self) -> Self {
+        self.iter()
+            .filter(|element| other.contains(element))
+            .cloned()
+            .collect()
+    }
+
+    /// The elements of `self` that aren't in `other`.
+    pub fn difference(&self, other: &Self) -> Self {
+        self.iter()
+            .filter(|element| !other.contains(element))
+            .cloned()
+            .collect()
+    }
+}
+
+impl FromIterator<Term> for Set {
+    fn from_iter<I: IntoIterator<Item = Term>>(elements: I) -> Self {
+        let mut set = Self::new();
+        for element in elements {
+            set.insert(element);
+        }
+        set
+    }
+}
+
+impl From<TermList> for Set {
+    fn from(elements: TermList) -> Self {
+        elements.into_iter().collect()
+    }
+}
+
+impl From<Set> for TermList {
+    fn from(set: Set) -> Self {
+        set.elements
+    }
+}
+
+/// Sets are equal if they have the same elements, in any order.
+impl PartialEq for Set {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.is_subset(other)
+    }
+}
+
+impl Eq for Set {}
+
+/// Sets are ordered by inclusion.
+impl PartialOrd for Set {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match (self.is_subset(other), other.is_subset(self)) {
+            (true, true) => Some(Ordering::Equal),
+            (true, false) => Some(Ordering::Less),
+            (false, true) => Some(Ordering::Greater),
+            (false, false) => None,
+        }
+    }
+}
+
+impl Hash for Set {
+    /// Combine the hashes of the elements so that the hash doesn't depend on their order.
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let combined = self.iter().fold(0u64, |combined, element| {
+            let mut hasher = DefaultHasher::new();
+            element.hash(&mut hasher);
+            combined.wrapping_add(hasher.finish())
+        });
+        self.len().hash(state);
+        combined.hash(state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(elements: &[i64]) -> Set {
+        elements.iter().map(|&i| term!(i)).collect()
+    }
+
+    fn hash(set: &Set) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        set.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn test_set_operations() {
+        let a = set(&[1, 2, 3, 2]);
+        let b = set(&[3, 4]);
+        assert_eq!(a.len(), 3);
+        assert!(a.contains(&term!(2)));
+        assert!(!a.contains(&term!(4)));
+
+        assert_eq!(TermList::from(a.union(&b)), set(&[1, 2, 3, 4]).elements);
+        assert_eq!(a.intersection(&b), set(&[3]));
+        assert_eq!(a.difference(&b), set(&[1, 2]));
+        assert!(b.difference(&set(&[4, 3])).is_empty());
+    }
+
+    #[test]
+    fn test_set_equality_and_order() {
+        let a = set(&[1, 2, 3]);
+        let b = set(&[3, 1, 2]);
+        assert_eq!(a, b);
+        assert_eq!(hash(&a), hash(&b));
+        assert!(set(&[1, 2]) < a);
+        assert!(a >= b);
+        assert_eq!(set(&[1, 4]).partial_cmp(&a), None);
+    }
+}
@@ -0,0 +1,110 @@
+//! Sharing a knowledge base between queries and loads without a global lock.
+//!
+//! Queries read from an immutable snapshot of the KB taken when they start. Loads and other
+//! changes are made to a copy of the current KB, off to the side, which then replaces it in one
+//! step. Queries already running finish against the snapshot they started with, and a slow load
+//! never stops new queries from starting.
+
+use std::sync::{Arc, Mutex, PoisonError, RwLock};
+
+use crate::error::PolarResult;
+use crate::kb::KnowledgeBase;
+
+#[derive(Default)]
+pub struct SharedKnowledgeBase {
+    /// The latest published KB, and how many KBs were published before it. Only locked to take
+    /// or replace the pointer.
+    current: RwLock<(u64, Arc<KnowledgeBase>)>,
+    /// Held while a new KB is built, so that concurrent changes don't overwrite each other.
+    writer: Mutex<()>,
+}
+
+impl SharedKnowledgeBase {
+    pub fn new(kb: KnowledgeBase) -> Self {
+        Self {
+            current: RwLock::new((0, Arc::new(kb))),
+            writer: Mutex::new(()),
+        }
+    }
+
+    /// The latest published KB. Later changes don't affect it.
+    pub fn snapshot(&self) -> Arc<KnowledgeBase> {
+        self.versioned_snapshot().1
+    }
+
+    /// The latest published KB along with its version, which changes every time a KB is
+    /// published.
+    pub fn versioned_snapshot(&self) -> (u64, Arc<KnowledgeBase>) {
+        self.current
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Apply `f` to a copy of the KB and publish the copy, whatever `f` returns.
+    pub fn update<T>(&self, f: impl FnOnce(&mut KnowledgeBase) -> T) -> T {
+        let _writer = self.writer.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut staged = KnowledgeBase::clone(&self.snapshot());
+        let result = f(&mut staged);
+        self.publish(staged);
+        result
+    }
+
+    /// Apply `f` to a copy of the KB and publish the copy if `f` succeeds. Otherwise the KB is
+    /// left untouched.
+    pub fn try_update<T>(
+        &self,
+        f: impl FnOnce(&mut KnowledgeBase) -> PolarResult<T>,
+    ) -> PolarResult<T> {
+        let _writer = self.writer.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut staged = KnowledgeBase::clone(&self.snapshot());
+        let result = f(&mut staged)?;
+        self.publish(staged);
+        Ok(result)
+    }
+
+    fn publish(&self, kb: KnowledgeBase) {
+        let mut current = self.current.write().unwrap_or_else(PoisonError::into_inner);
+        *current = (current.0 + 1, Arc::new(kb));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::RuntimeError;
+    use crate::rules::Rule;
+
+    #[test]
+    fn snapshots_are_unaffected_by_later_changes() {
+        let shared = SharedKnowledgeBase::default();
+        let before = shared.snapshot();
+        shared
+            .update(|kb| kb.register_constant(sym!("x"), term!(1)))
+            .unwrap();
+        assert!(!before.is_constant(&sym!("x")));
+        assert_eq!(shared.versioned_snapshot().0, 1);
+        assert!(shared.snapshot().is_constant(&sym!("x")));
+
+        let result = shared.try_update(|kb| {
+            kb.register_constant(sym!("y"), term!(2))?;
+            Err::<(), _>(RuntimeError::MultipleLoadError.into())
+        });
+        assert!(result.is_err());
+        assert!(!shared.snapshot().is_constant(&sym!("y")));
+    }
+
+    #[test]
+    fn registrations_share_the_loaded_rules() {
+        let shared = SharedKnowledgeBase::default();
+        shared.update(|kb| kb.add_rule(rule!("f", [1])));
+        let before = shared.snapshot();
+        shared
+            .update(|kb| kb.register_constant(sym!("x"), term!(1)))
+            .unwrap();
+        assert!(std::ptr::eq(
+            before.get_rules(),
+            shared.snapshot().get_rules()
+        ));
+    }
+}
@@ -12,6 +12,25 @@ pub struct Context {
     pub left: usize,
     /// End location within `source`.
     pub right: usize,
+    /// Set for terms and rules that aren't written out in `source`, but were generated from the
+    /// declaration at this location.
+    pub generated_from: Option<Origin>,
+}
+
+/// What generated a term or rule from a declaration in a policy.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Origin {
+    /// A resource block, by the name of its resource. Shorthand rules and relations are expanded
+    /// into rules.
+    ResourceBlock(String),
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ResourceBlock(resource) => write!(f, "resource block {}", resource),
+        }
+    }
 }
 
 impl fmt::Display for Context {
@@ -29,13 +48,18 @@ impl Context {
             source,
             left,
             right,
+            generated_from: None,
         }
     }
 
     pub(crate) fn source_position(&self) -> String {
         let mut f = String::new();
         let (row, column) = loc_to_pos(&self.source.src, self.left);
-        f += &format!(" at line {}, column {}", row + 1, column + 1);
+        match &self.generated_from {
+            Some(origin) => f += &format!(" generated from {}, line {}", origin, row + 1),
+            None => f += &format!(" at line {}", row + 1),
+        }
+        f += &format!(", column {}", column + 1);
         if let Some(ref filename) = self.source.filename {
             f += &format!(" of file {}", filename);
         }
@@ -78,6 +102,16 @@ impl SourceInfo {
     pub(crate) fn parser(source: Arc<Source>, left: usize, right: usize) -> Self {
         Self::Parser(Context::new(source, left, right))
     }
+
+    /// Record that the term or rule with this source info was generated by `origin` from the
+    /// declaration it points at, unless it already records an origin.
+    pub(crate) fn mark_generated(&mut self, origin: &Origin) {
+        if let Self::Parser(context) = self {
+            if context.generated_from.is_none() {
+                context.generated_from = Some(origin.clone());
+            }
+        }
+    }
 }
 
 // TODO(gj): `Serialize` makes some `polar-wasm-api` tests easier to write. We could look into
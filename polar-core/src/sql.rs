@@ -0,0 +1,373 @@
+//! Lowering data filters to parameterized SQL.
+//!
+//! A [`Filter`] names types, fields and relations but leaves it to the host to map them onto
+//! its data source. [`SqlSchema`] does that mapping for relational databases: each type is a
+//! table, each field a column, and each relation a join on the columns the host registered
+//! for it. Values from the policy are never spliced into the query text; they are returned
+//! as parameters to be bound by the database driver.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+
+use serde::Serialize;
+
+use crate::data_filtering::{Type, Types};
+use crate::error::{df_field_missing, unsupported, PolarResult};
+use crate::filter::{Comparison, Condition, Datum, Filter, Projection, Relation};
+use crate::terms::{Term, Value};
+
+/// The flavor of SQL to generate. Dialects differ in how they quote identifiers and write
+/// parameter placeholders.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Dialect {
+    /// `"ident"` and `$1`.
+    Postgres,
+    /// `"ident"` and `?`.
+    Sqlite,
+    /// `` `ident` `` and `?`.
+    MySql,
+}
+
+impl Dialect {
+    fn quote(self, ident: &str) -> String {
+        match self {
+            Self::Postgres | Self::Sqlite => format!("\"{}\"", ident.replace('"', "\"\"")),
+            Self::MySql => format!("`{}`", ident.replace('`', "``")),
+        }
+    }
+
+    /// The placeholder for the `n`th parameter, counting from 1.
+    fn placeholder(self, n: usize) -> String {
+        match self {
+            Self::Postgres => format!("${}", n),
+            Self::Sqlite | Self::MySql => "?".to_owned(),
+        }
+    }
+}
+
+/// The table a type is stored in.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Table {
+    pub name: String,
+    /// The column that identifies a record, used when a filter compares whole records.
+    pub primary_key: String,
+}
+
+/// A query and the parameters to bind to its placeholders, in order.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct SqlQuery {
+    pub sql: String,
+    pub params: Vec<Value>,
+}
+
+/// Maps the types in a [`Filter`] onto database tables.
+///
+/// `types` are the same field and relation declarations passed to
+/// [`crate::polar::Polar::build_data_filter`]. A type without a registered [`Table`] is stored
+/// in a table named after the type, with an `id` primary key.
+#[derive(Clone, Debug)]
+pub struct SqlSchema {
+    dialect: Dialect,
+    types: Types,
+    tables: HashMap<String, Table>,
+}
+
+impl SqlSchema {
+    pub fn new(dialect: Dialect, types: Types) -> Self {
+        Self {
+            dialect,
+            types,
+            tables: HashMap::new(),
+        }
+    }
+
+    pub fn set_table(&mut self, class_tag: &str, table: Table) {
+        self.tables.insert(class_tag.to_owned(), table);
+    }
+
+    /// A `SELECT` of the records of `filter.root` that pass `filter`.
+    ///
+    /// Each type is aliased to its name, so the query reads
+    /// `SELECT DISTINCT "Repo".* FROM "repos" AS "Repo" JOIN ...`.
+    pub fn select(&self, filter: &Filter) -> PolarResult<SqlQuery> {
+        let mut lowering = Lowering {
+            schema: self,
+            params: vec![],
+        };
+        let root = self.dialect.quote(&filter.root);
+        let mut sql = format!(
+            "SELECT DISTINCT {}.* FROM {}",
+            root,
+            lowering.table_as(&filter.root)
+        );
+        for relation in &filter.relations {
+            lowering.join(&mut sql, relation)?;
+        }
+        let condition = lowering.disjunction(&filter.conditions)?;
+        write!(sql, " WHERE {}", condition).unwrap();
+        Ok(SqlQuery {
+            sql,
+            params: lowering.params,
+        })
+    }
+
+    fn table(&self, class_tag: &str) -> Table {
+        self.tables
+            .get(class_tag)
+            .cloned()
+            .unwrap_or_else(|| Table {
+                name: class_tag.to_owned(),
+                primary_key: "id".to_owned(),
+            })
+    }
+}
+
+struct Lowering<'a> {
+    schema: &'a SqlSchema,
+    params: Vec<Value>,
+}
+
+impl Lowering<'_> {
+    fn quote(&self, ident: &str) -> String {
+        self.schema.dialect.quote(ident)
+    }
+
+    fn table_as(&self, class_tag: &str) -> String {
+        let table = self.schema.table(class_tag);
+        format!("{} AS {}", self.quote(&table.name), self.quote(class_tag))
+    }
+
+    fn column(&self, class_tag: &str, column: &str) -> String {
+        format!("{}.{}", self.quote(class_tag), self.quote(column))
+    }
+
+    fn join(&self, sql: &mut String, Relation(from, field, to): &Relation) -> PolarResult<()> {
+        let (my_field, other_field) = match self.schema.types.get(from).and_then(|t| t.get(field)) {
+            Some(Type::Relation {
+                my_field,
+                other_field,
+                ..
+            }) => (my_field, other_field),
+            _ => return df_field_missing(from, field),
+        };
+        write!(
+            sql,
+            " JOIN {} ON {} = {}",
+            self.table_as(to),
+            self.column(from, my_field),
+            self.column(to, other_field)
+        )
+        .unwrap();
+        Ok(())
+    }
+
+    fn disjunction(&mut self, conditions: &[HashSet<Condition>]) -> PolarResult<String> {
+        if conditions.is_empty() {
+            return Ok("1 = 0".to_owned());
+        }
+        let conjunctions = conditions
+            .iter()
+            .map(|conjunction| {
+                // Sets iterate in an arbitrary order; sort them so the same filter always
+                // lowers to the same query.
+                let mut conjunction = conjunction.iter().collect::<Vec<_>>();
+                conjunction.sort_by_cached_key(|condition| format!("{:?}", condition));
+                let conditions = conjunction
+                    .into_iter()
+                    .map(|condition| self.condition(condition))
+                    .collect::<PolarResult<Vec<_>>>()?;
+                Ok(match conditions.len() {
+                    0 => "1 = 1".to_owned(),
+                    _ => format!("({})", conditions.join(" AND ")),
+                })
+            })
+            .collect::<PolarResult<Vec<_>>>()?;
+        Ok(conjunctions.join(" OR "))
+    }
+
+    fn condition(&mut self, Condition(left, op, right): &Condition) -> PolarResult<String> {
+        let left = self.datum(left)?;
+        let op = match op {
+            Comparison::In => return self.membership(left, right, false),
+            Comparison::Nin => return self.membership(left, right, true),
+            Comparison::Eq => "=",
+            Comparison::Neq => "<>",
+            Comparison::Lt => "<",
+            Comparison::Leq => "<=",
+            Comparison::Gt => ">",
+            Comparison::Geq => ">=",
+        };
+        let right = self.datum(right)?;
+        Ok(format!("{} {} {}", left, op, right))
+    }
+
+    /// `left IN (...)` for a list of values.
+    fn membership(&mut self, left: String, right: &Datum, negated: bool) -> PolarResult<String> {
+        let items = match right {
+            Datum::Immediate(Value::List(items)) => items,
+            Datum::Immediate(value) => {
+                let term = Term::new_temporary(value.clone());
+                return unsupported("SQL membership tests require a list of values", term);
+            }
+            Datum::Field(Projection(class_tag, field)) => {
+                let term = Term::new_temporary(Value::String(format!(
+                    "{}.{}",
+                    class_tag,
+                    field.as_deref().unwrap_or("")
+                )));
+                return unsupported("SQL membership tests on fields are not supported", term);
+            }
+        };
+        if items.is_empty() {
+            return Ok((if negated { "1 = 1" } else { "1 = 0" }).to_owned());
+        }
+        let items = items
+            .iter()
+            .map(|item| self.param(item.value()))
+            .collect::<PolarResult<Vec<_>>>()?;
+        let op = if negated { "NOT IN" } else { "IN" };
+        Ok(format!("{} {} ({})", left, op, items.join(", ")))
+    }
+
+    fn datum(&mut self, datum: &Datum) -> PolarResult<String> {
+        match datum {
+            Datum::Field(Projection(class_tag, field)) => {
+                let column = match field {
+                    Some(field) => field.clone(),
+                    None => self.schema.table(class_tag).primary_key,
+                };
+                Ok(self.column(class_tag, &column))
+            }
+            Datum::Immediate(value) => self.param(value),
+        }
+    }
+
+    /// Bind `value` as the next parameter and return its placeholder.
+    fn param(&mut self, value: &Value) -> PolarResult<String> {
+        match value {
+            Value::Number(_)
+            | Value::String(_)
+            | Value::Boolean(_)
+            | Value::DateTime(_)
+            | Value::Duration(_) => {
+                self.params.push(value.clone());
+                Ok(self.schema.dialect.placeholder(self.params.len()))
+            }
+            _ => {
+                let term = Term::new_temporary(value.clone());
+                unsupported(
+                    "only numbers, strings, booleans and times can be SQL parameters",
+                    term,
+                )
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::ResultEvent;
+
+    fn types() -> Types {
+        let s = String::from;
+        hashmap! {
+            s("Repo") => hashmap!{
+                s("name") => Type::Base {
+                    class_tag: s("String")
+                },
+                s("org") => Type::Relation {
+                    kind: s("one"),
+                    my_field: s("org_id"),
+                    other_field: s("id"),
+                    other_class_tag: s("Org")
+                }
+            },
+            s("Org") => hashmap!{
+                s("name") => Type::Base {
+                    class_tag: s("String")
+                },
+            }
+        }
+    }
+
+    fn filter() -> Filter {
+        let ors = vec![
+            ResultEvent::new(hashmap! {
+                sym!("resource") => term!(op!(And,
+                    term!(op!(Isa, var!("_this"), term!(pattern!(instance!("Repo"))))),
+                    term!(op!(Isa, term!(op!(Dot, var!("_this"), str!("org"))), term!(pattern!(instance!("Org"))))),
+                    term!(op!(Unify, str!("acme"), term!(op!(Dot, term!(op!(Dot, var!("_this"), str!("org"))), str!("name")))))
+                ))
+            }),
+            ResultEvent::new(hashmap! {
+                sym!("resource") => term!(op!(And,
+                    term!(op!(Isa, var!("_this"), term!(pattern!(instance!("Repo"))))),
+                    term!(op!(Unify, str!("public"), term!(op!(Dot, var!("_this"), str!("name")))))
+                ))
+            }),
+        ];
+        Filter::build(types(), ors, "resource", "Repo").unwrap()
+    }
+
+    #[test]
+    fn test_select_with_join() -> PolarResult<()> {
+        let mut schema = SqlSchema::new(Dialect::Postgres, types());
+        schema.set_table(
+            "Repo",
+            Table {
+                name: "repos".to_owned(),
+                primary_key: "id".to_owned(),
+            },
+        );
+        let SqlQuery { sql, params } = schema.select(&filter())?;
+        assert_eq!(
+            sql,
+            r#"SELECT DISTINCT "Repo".* FROM "repos" AS "Repo" JOIN "Org" AS "Org" ON "Repo"."org_id" = "Org"."id" WHERE ($1 = "Org"."name") OR ($2 = "Repo"."name")"#
+        );
+        assert_eq!(params, vec![value!("acme"), value!("public")]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_dialects() -> PolarResult<()> {
+        let sql = SqlSchema::new(Dialect::MySql, types())
+            .select(&filter())?
+            .sql;
+        assert!(sql.starts_with("SELECT DISTINCT `Repo`.* FROM `Repo` AS `Repo` JOIN"));
+        assert!(sql.ends_with("WHERE (? = `Org`.`name`) OR (? = `Repo`.`name`)"));
+
+        let sql = SqlSchema::new(Dialect::Sqlite, types())
+            .select(&filter())?
+            .sql;
+        assert!(sql.ends_with(r#"WHERE (? = "Org"."name") OR (? = "Repo"."name")"#));
+        Ok(())
+    }
+
+    #[test]
+    fn test_membership_and_empty_filters() -> PolarResult<()> {
+        let schema = SqlSchema::new(Dialect::Postgres, types());
+        let mut filter = Filter {
+            root: "Repo".to_owned(),
+            relations: vec![],
+            conditions: vec![],
+        };
+        let SqlQuery { sql, .. } = schema.select(&filter)?;
+        assert!(sql.ends_with("WHERE 1 = 0"));
+
+        let name = || Datum::Field(Projection("Repo".to_owned(), Some("name".to_owned())));
+        filter.conditions = vec![hashset! {
+            Condition(name(), Comparison::In, Datum::Immediate(value!(["a", "b"]))),
+            Condition(Datum::Field(Projection("Repo".to_owned(), None)), Comparison::Nin, Datum::Immediate(value!([]))),
+        }];
+        let SqlQuery { sql, params } = schema.select(&filter)?;
+        assert!(sql.ends_with(r#"WHERE (1 = 1 AND "Repo"."name" IN ($1, $2))"#));
+        assert_eq!(params, vec![value!("a"), value!("b")]);
+
+        filter.conditions = vec![hashset! {
+            Condition(name(), Comparison::In, Datum::Immediate(value!("a"))),
+        }];
+        assert!(schema.select(&filter).is_err());
+        Ok(())
+    }
+}
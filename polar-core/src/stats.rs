@@ -0,0 +1,257 @@
+//! Summary statistics about the rules loaded into a knowledge base.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::mem::size_of;
+
+use serde::Serialize;
+
+use crate::kb::KnowledgeBase;
+use crate::rules::Rule;
+use crate::terms::{Operation, Operator, Symbol, Term, Value};
+use crate::visitor::{walk_term, Visitor};
+
+/// Number of rules sharing a name and arity.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct RuleCount {
+    pub name: Symbol,
+    pub arity: usize,
+    /// Rules with a body, not counting facts.
+    pub rules: usize,
+    /// Rules without a body.
+    pub facts: usize,
+}
+
+/// Statistics about the rules in a `KnowledgeBase`, for tracking policy growth over time.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct KnowledgeBaseStats {
+    /// Total number of rules, including facts.
+    pub rules: usize,
+    /// Number of rules without a body.
+    pub facts: usize,
+    /// Rule counts grouped by name and arity, sorted by name and then arity.
+    pub rules_by_signature: Vec<RuleCount>,
+    /// Average number of top-level conjuncts in a rule body. Facts count as zero.
+    pub average_body_size: f64,
+    /// Names of the largest group of mutually recursive rules, sorted by name.
+    pub deepest_recursion_group: Vec<Symbol>,
+    /// Number of terms across all rule heads and bodies.
+    pub terms: usize,
+    /// Rough estimate of the memory occupied by those terms, in bytes.
+    pub estimated_bytes: usize,
+}
+
+fn body_size(rule: &Rule) -> usize {
+    match rule.body.value() {
+        Value::Expression(Operation {
+            operator: Operator::And,
+            args,
+        }) => args.len(),
+        _ => 1,
+    }
+}
+
+/// Counts terms, their string payloads, and the rules they call.
+#[derive(Default)]
+struct TermCounter {
+    terms: usize,
+    string_bytes: usize,
+    calls: HashSet<Symbol>,
+}
+
+impl Visitor for TermCounter {
+    fn visit_term(&mut self, term: &Term) {
+        self.terms += 1;
+        match term.value() {
+            Value::String(s) => self.string_bytes += s.len(),
+            Value::Variable(s) | Value::RestVariable(s) => self.string_bytes += s.as_str().len(),
+            Value::Call(call) => {
+                self.string_bytes += call.name.as_str().len();
+                self.calls.insert(call.name.clone());
+            }
+            _ => {}
+        }
+        walk_term(self, term)
+    }
+}
+
+/// Tarjan's algorithm, returning every strongly connected component of `graph` that contains a
+/// cycle.
+fn recursive_groups(graph: &BTreeMap<&Symbol, Vec<&Symbol>>) -> Vec<Vec<Symbol>> {
+    struct State<'a> {
+        graph: &'a BTreeMap<&'a Symbol, Vec<&'a Symbol>>,
+        index: HashMap<&'a Symbol, usize>,
+        lowlink: HashMap<&'a Symbol, usize>,
+        stack: Vec<&'a Symbol>,
+        on_stack: HashSet<&'a Symbol>,
+        groups: Vec<Vec<Symbol>>,
+    }
+
+    fn connect<'a>(state: &mut State<'a>, node: &'a Symbol) {
+        let index = state.index.len();
+        state.index.insert(node, index);
+        state.lowlink.insert(node, index);
+        state.stack.push(node);
+        state.on_stack.insert(node);
+
+        let graph = state.graph;
+        let successors = graph.get(node).map(Vec::as_slice).unwrap_or(&[]);
+        for &next in successors {
+            if !state.index.contains_key(next) {
+                connect(state, next);
+                let low = state.lowlink[node].min(state.lowlink[next]);
+                state.lowlink.insert(node, low);
+            } else if state.on_stack.contains(next) {
+                let low = state.lowlink[node].min(state.index[next]);
+                state.lowlink.insert(node, low);
+            }
+        }
+
+        if state.lowlink[node] == state.index[node] {
+            let mut group = vec![];
+            while let Some(member) = state.stack.pop() {
+                state.on_stack.remove(member);
+                group.push(member.clone());
+                if member == node {
+                    break;
+                }
+            }
+            if group.len() > 1 || successors.contains(&node) {
+                group.sort();
+                state.groups.push(group);
+            }
+        }
+    }
+
+    let mut state = State {
+        graph,
+        index: HashMap::new(),
+        lowlink: HashMap::new(),
+        stack: vec![],
+        on_stack: HashSet::new(),
+        groups: vec![],
+    };
+    for node in graph.keys() {
+        if !state.index.contains_key(node) {
+            connect(&mut state, node);
+        }
+    }
+    state.groups
+}
+
+impl KnowledgeBaseStats {
+    pub(crate) fn new(kb: &KnowledgeBase) -> Self {
+        let mut counts: BTreeMap<(Symbol, usize), RuleCount> = BTreeMap::new();
+        let mut calls: BTreeMap<&Symbol, Vec<&Symbol>> = BTreeMap::new();
+        let (mut rules, mut facts, mut conjuncts) = (0, 0, 0);
+        let mut counter = TermCounter::default();
+
+        for (name, generic_rule) in kb.get_rules() {
+            let calls_before = std::mem::take(&mut counter.calls);
+            for rule in generic_rule.rules.values() {
+                let size = body_size(rule);
+                let count = counts
+                    .entry((name.clone(), rule.params.len()))
+                    .or_insert_with(|| RuleCount {
+                        name: name.clone(),
+                        arity: rule.params.len(),
+                        rules: 0,
+                        facts: 0,
+                    });
+                rules += 1;
+                if size == 0 {
+                    facts += 1;
+                    count.facts += 1;
+                } else {
+                    count.rules += 1;
+                }
+                conjuncts += size;
+                counter.visit_rule(rule);
+            }
+            let called = std::mem::replace(&mut counter.calls, calls_before);
+            let callees = kb
+                .get_rules()
+                .keys()
+                .filter(|callee| called.contains(*callee))
+                .collect();
+            calls.insert(name, callees);
+        }
+
+        let deepest_recursion_group =
+            recursive_groups(&calls)
+                .into_iter()
+                .fold(vec![], |deepest, group| {
+                    if group.len() > deepest.len() {
+                        group
+                    } else {
+                        deepest
+                    }
+                });
+
+        Self {
+            rules,
+            facts,
+            rules_by_signature: counts.into_values().collect(),
+            average_body_size: if rules == 0 {
+                0.0
+            } else {
+                conjuncts as f64 / rules as f64
+            },
+            deepest_recursion_group,
+            terms: counter.terms,
+            estimated_bytes: counter.terms * (size_of::<Term>() + size_of::<Value>())
+                + counter.string_bytes,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kb_stats() {
+        let mut kb = KnowledgeBase::new();
+        kb.add_rule(rule!("edge", [1, 2]));
+        kb.add_rule(rule!("edge", [2, 3]));
+        kb.add_rule(rule!("path", [sym!("x"), sym!("y")] => call!("edge", [sym!("x"), sym!("y")])));
+        kb.add_rule(rule!("path", [sym!("x"), sym!("y")] =>
+            call!("edge", [sym!("x"), sym!("z")]),
+            call!("path", [sym!("z"), sym!("y")])));
+        kb.add_rule(rule!("even", [sym!("x")] => call!("odd", [sym!("x")])));
+        kb.add_rule(rule!("odd", [sym!("x")] => call!("even", [sym!("x")])));
+        kb.add_rule(rule!("odd", [sym!("x"), sym!("y")]));
+
+        let stats = kb.stats();
+        assert_eq!(stats.rules, 7);
+        assert_eq!(stats.facts, 3);
+        assert_eq!(
+            stats
+                .rules_by_signature
+                .iter()
+                .map(|c| (c.name.as_str(), c.arity, c.rules, c.facts))
+                .collect::<Vec<_>>(),
+            vec![
+                ("edge", 2, 0, 2),
+                ("even", 1, 1, 0),
+                ("odd", 1, 1, 0),
+                ("odd", 2, 0, 1),
+                ("path", 2, 2, 0),
+            ]
+        );
+        assert!((stats.average_body_size - 5.0 / 7.0).abs() < f64::EPSILON);
+        assert_eq!(
+            stats.deepest_recursion_group,
+            vec![sym!("even"), sym!("odd")]
+        );
+        assert!(stats.terms > 0);
+        assert!(stats.estimated_bytes > stats.terms);
+    }
+
+    #[test]
+    fn test_empty_kb_stats() {
+        let stats = KnowledgeBase::new().stats();
+        assert_eq!(stats.rules, 0);
+        assert_eq!(stats.average_body_size, 0.0);
+        assert!(stats.deepest_recursion_group.is_empty());
+    }
+}
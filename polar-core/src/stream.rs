@@ -0,0 +1,290 @@
+//! Driving a query from async hosts.
+//!
+//! [`Query::next_event`] is a pull loop: the host asks for an event, answers it, and asks for
+//! the next one. A [`QueryStream`] wraps a query and exposes its events through
+//! [`QueryStream::poll_next`], which has the signature of `futures::Stream::poll_next`, so
+//! hosts can adapt it to their executor's stream type. External calls can be answered by a
+//! handler registered with [`QueryStream::register_external_call_handler`] instead of by the
+//! consumer of the stream, and the handler may be async.
+
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use super::error::PolarResult;
+use super::events::QueryEvent;
+use super::query::Query;
+use super::terms::{Symbol, Term};
+
+/// An external call the VM needs answered: look up `attribute` on `instance`, calling it with
+/// `args` and `kwargs` if they are present.
+#[derive(Clone, Debug)]
+pub struct ExternalCall {
+    pub call_id: u64,
+    pub instance: Term,
+    pub attribute: Symbol,
+    pub args: Option<Vec<Term>>,
+    pub kwargs: Option<BTreeMap<Symbol, Term>>,
+}
+
+/// The answer to an [`ExternalCall`]. `Ok(None)` means the call has no (more) results, and
+/// `Err(message)` is reported to the query as an application error.
+pub type ExternalCallResult = Result<Option<Term>, String>;
+
+type CallFuture = Pin<Box<dyn Future<Output = ExternalCallResult>>>;
+type CallHandler = Box<dyn FnMut(ExternalCall) -> CallFuture>;
+
+/// A [`Query`] driven as a stream of [`QueryEvent`]s.
+///
+/// External calls are answered by the registered handler, if there is one, and never reach
+/// the consumer. All other events are yielded and must be answered through
+/// [`QueryStream::query_mut`] before polling for the next one, as with [`Query::next_event`].
+/// The stream ends after yielding `QueryEvent::Done` or an error.
+pub struct QueryStream {
+    query: Query,
+    handler: Option<CallHandler>,
+    pending: Option<(u64, CallFuture)>,
+    done: bool,
+}
+
+impl QueryStream {
+    pub fn new(query: Query) -> Self {
+        Self {
+            query,
+            handler: None,
+            pending: None,
+            done: false,
+        }
+    }
+
+    /// Answer external calls with `handler`. The query is suspended until the future it
+    /// returns completes. Synchronous handlers can return `std::future::ready(result)`.
+    pub fn register_external_call_handler<F, Fut>(&mut self, mut handler: F)
+    where
+        F: FnMut(ExternalCall) -> Fut + 'static,
+        Fut: Future<Output = ExternalCallResult> + 'static,
+    {
+        self.handler = Some(Box::new(move |call| Box::pin(handler(call))));
+    }
+
+    /// The wrapped query, for answering yielded events.
+    pub fn query_mut(&mut self) -> &mut Query {
+        &mut self.query
+    }
+
+    pub fn into_query(self) -> Query {
+        self.query
+    }
+
+    /// Attempt to pull out the next event, registering the current task for wakeup if an
+    /// external call handler is still running.
+    pub fn poll_next(&mut self, cx: &mut Context<'_>) -> Poll<Option<PolarResult<QueryEvent>>> {
+        loop {
+            if self.done {
+                return Poll::Ready(None);
+            }
+
+            if let Some((call_id, future)) = self.pending.as_mut() {
+                let result = match future.as_mut().poll(cx) {
+                    Poll::Pending => return Poll::Pending,
+                    Poll::Ready(result) => result,
+                };
+                let call_id = *call_id;
+                self.pending = None;
+                let answered = match result {
+                    Ok(value) => self.query.call_result(call_id, value),
+                    Err(message) => self.query.application_error(message),
+                };
+                if let Err(e) = answered {
+                    return Poll::Ready(Some(Err(self.finish(e))));
+                }
+            }
+
+            let event = match self.query.next_event() {
+                Ok(event) => event,
+                Err(e) => return Poll::Ready(Some(Err(self.finish(e)))),
+            };
+            match (event, self.handler.as_mut()) {
+                (
+                    QueryEvent::ExternalCall {
+                        call_id,
+                        instance,
+                        attribute,
+                        args,
+                        kwargs,
+                    },
+                    Some(handler),
+                ) => {
+                    let future = handler(ExternalCall {
+                        call_id,
+                        instance,
+                        attribute,
+                        args,
+                        kwargs,
+                    });
+                    self.pending = Some((call_id, future));
+                }
+                (event, _) => {
+                    self.done = matches!(event, QueryEvent::Done { .. });
+                    return Poll::Ready(Some(Ok(event)));
+                }
+            }
+        }
+    }
+
+    /// A future resolving to the next event, or `None` once the stream has ended.
+    pub fn next_event(&mut self) -> NextEvent<'_> {
+        NextEvent { stream: self }
+    }
+
+    fn finish<E>(&mut self, error: E) -> E {
+        self.done = true;
+        self.pending = None;
+        error
+    }
+}
+
+impl From<Query> for QueryStream {
+    fn from(query: Query) -> Self {
+        Self::new(query)
+    }
+}
+
+/// Future returned by [`QueryStream::next_event`].
+pub struct NextEvent<'a> {
+    stream: &'a mut QueryStream,
+}
+
+impl Future for NextEvent<'_> {
+    type Output = Option<PolarResult<QueryEvent>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.stream.poll_next(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::task::{Wake, Waker};
+
+    use super::*;
+    use crate::polar::Polar;
+
+    struct NoopWaker;
+
+    impl Wake for NoopWaker {
+        fn wake(self: Arc<Self>) {}
+    }
+
+    /// Poll `future` to completion, counting how many times it was pending.
+    fn block_on<F: Future>(future: F) -> (F::Output, usize) {
+        let waker = Waker::from(Arc::new(NoopWaker));
+        let mut cx = Context::from_waker(&waker);
+        let mut future = Box::pin(future);
+        let mut pending = 0;
+        loop {
+            match future.as_mut().poll(&mut cx) {
+                Poll::Ready(output) => return (output, pending),
+                Poll::Pending => pending += 1,
+            }
+        }
+    }
+
+    /// Pending on its first poll, then ready with the given result.
+    struct Later(Option<ExternalCallResult>, bool);
+
+    impl Future for Later {
+        type Output = ExternalCallResult;
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<ExternalCallResult> {
+            if std::mem::replace(&mut self.1, true) {
+                Poll::Ready(self.0.take().unwrap())
+            } else {
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+        }
+    }
+
+    fn events(stream: &mut QueryStream) -> (Vec<PolarResult<QueryEvent>>, usize) {
+        block_on(async {
+            let mut events = vec![];
+            while let Some(event) = stream.next_event().await {
+                events.push(event);
+            }
+            events
+        })
+    }
+
+    #[test]
+    fn test_async_external_call_handler() {
+        let polar = Polar::new();
+        polar.register_constant(sym!("Foo"), term!(true)).unwrap();
+        let query = polar
+            .new_query("x = (new Foo()).bar(1) and x = 2", false)
+            .unwrap();
+
+        let mut stream = QueryStream::from(query);
+        stream.register_external_call_handler(|call: ExternalCall| {
+            assert_eq!(call.attribute, sym!("bar"));
+            assert_eq!(call.args, Some(vec![term!(1)]));
+            Later(Some(Ok(Some(term!(2)))), false)
+        });
+
+        let (events, pending) = events(&mut stream);
+        assert_eq!(pending, 1);
+        let events = events.into_iter().map(Result::unwrap).collect::<Vec<_>>();
+        assert!(matches!(events[0], QueryEvent::MakeExternal { .. }));
+        assert!(
+            matches!(&events[1], QueryEvent::Result { bindings, .. } if bindings[&sym!("x")] == term!(2))
+        );
+        assert!(matches!(events[2], QueryEvent::Done { .. }));
+        assert_eq!(events.len(), 3);
+    }
+
+    #[test]
+    fn test_stream_without_handler() {
+        let polar = Polar::new();
+        polar.register_constant(sym!("Foo"), term!(true)).unwrap();
+        let query = polar.new_query("(new Foo()).bar = 1", false).unwrap();
+
+        // Without a handler, external calls are yielded to the consumer.
+        let mut stream = QueryStream::new(query);
+        let (event, _) = block_on(async {
+            stream.next_event().await; // MakeExternal
+            stream.next_event().await
+        });
+        let call_id = match event.unwrap().unwrap() {
+            QueryEvent::ExternalCall { call_id, .. } => call_id,
+            e => panic!("unexpected event: {:?}", e),
+        };
+        stream
+            .query_mut()
+            .call_result(call_id, Some(term!(1)))
+            .unwrap();
+        let (events, _) = events(&mut stream);
+        assert_eq!(events.len(), 2);
+    }
+
+    #[test]
+    fn test_handler_errors() {
+        let polar = Polar::new();
+        polar.register_constant(sym!("Foo"), term!(true)).unwrap();
+        let query = polar.new_query("(new Foo()).bar = 1", false).unwrap();
+
+        let mut stream = QueryStream::new(query);
+        stream.register_external_call_handler(|_| {
+            std::future::ready(Err("bar is unavailable".to_string()))
+        });
+        let (events, _) = events(&mut stream);
+        let error = events.last().unwrap().as_ref().unwrap_err();
+        assert!(
+            error.to_string().contains("bar is unavailable"),
+            "{}",
+            error
+        );
+        assert!(block_on(stream.next_event()).0.is_none());
+    }
+}
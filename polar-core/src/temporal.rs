@@ -0,0 +1,639 @@
+//! Date-times and durations, so that time-based rules can be evaluated without calling into the
+//! host for every comparison.
+//!
+//! Both are written as ISO-8601 strings: in policies as `datetime"2024-01-31T12:00:00Z"` and
+//! `duration"P30D"` literals, and in terms passed across the FFI as plain strings, e.g.,
+//! `{"DateTime": "2024-01-31T12:00:00Z"}`.
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::ops::{Add, Sub};
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+const NANOS_PER_SEC: i128 = 1_000_000_000;
+const SECS_PER_DAY: i64 = 86_400;
+/// 0000-01-01T00:00:00Z, the earliest `DateTime`.
+const MIN_SECS: i64 = -62_167_219_200;
+/// 9999-12-31T23:59:59Z, the second of the latest `DateTime`. Later years don't fit in the four
+/// digits of an ISO-8601 year, so they couldn't be parsed back.
+const MAX_SECS: i64 = 253_402_300_799;
+
+/// Split `nanos` into whole seconds and non-negative subsecond nanoseconds, or `None` if the
+/// seconds don't fit in an `i64`.
+fn split_nanos(nanos: i128) -> Option<(i64, u32)> {
+    let secs = i64::try_from(nanos.div_euclid(NANOS_PER_SEC)).ok()?;
+    Some((secs, nanos.rem_euclid(NANOS_PER_SEC) as u32))
+}
+
+/// An instant in time between the years 0000 and 9999 with nanosecond precision. Always formatted
+/// in UTC.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct DateTime {
+    /// Seconds since the Unix epoch.
+    secs: i64,
+    /// Nanoseconds past `secs`, less than one second.
+    nanos: u32,
+}
+
+/// A signed, fixed-length span of time with nanosecond precision.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Duration {
+    /// Whole seconds, rounded towards negative infinity.
+    secs: i64,
+    /// Nanoseconds past `secs`, less than one second.
+    nanos: u32,
+}
+
+impl DateTime {
+    /// The instant `secs` seconds and `nanos` nanoseconds after the Unix epoch, or `None` if
+    /// it's outside of the years 0000 to 9999.
+    pub fn from_unix(secs: i64, nanos: u32) -> Option<Self> {
+        Self::from_nanos(secs as i128 * NANOS_PER_SEC + nanos as i128)
+    }
+
+    fn from_nanos(nanos: i128) -> Option<Self> {
+        split_nanos(nanos)
+            .filter(|(secs, _)| (MIN_SECS..=MAX_SECS).contains(secs))
+            .map(|(secs, nanos)| Self { secs, nanos })
+    }
+
+    /// Seconds since the Unix epoch.
+    pub fn unix_secs(&self) -> i64 {
+        self.secs
+    }
+
+    pub fn subsec_nanos(&self) -> u32 {
+        self.nanos
+    }
+
+    fn as_nanos(&self) -> i128 {
+        self.secs as i128 * NANOS_PER_SEC + self.nanos as i128
+    }
+}
+
+impl Duration {
+    pub fn from_secs(secs: i64) -> Self {
+        Self { secs, nanos: 0 }
+    }
+
+    pub fn from_nanos(nanos: i128) -> Option<Self> {
+        split_nanos(nanos).map(|(secs, nanos)| Self { secs, nanos })
+    }
+
+    pub fn as_nanos(&self) -> i128 {
+        self.secs as i128 * NANOS_PER_SEC + self.nanos as i128
+    }
+}
+
+impl From<SystemTime> for DateTime {
+    /// Instants outside of the years 0000 to 9999 saturate at the earliest or latest `DateTime`.
+    fn from(time: SystemTime) -> Self {
+        let nanos = match time.duration_since(UNIX_EPOCH) {
+            Ok(after) => after.as_nanos() as i128,
+            Err(before) => -(before.duration().as_nanos() as i128),
+        };
+        Self::from_nanos(nanos).unwrap_or(if nanos < 0 {
+            Self {
+                secs: MIN_SECS,
+                nanos: 0,
+            }
+        } else {
+            Self {
+                secs: MAX_SECS,
+                nanos: NANOS_PER_SEC as u32 - 1,
+            }
+        })
+    }
+}
+
+impl From<DateTime> for SystemTime {
+    fn from(time: DateTime) -> Self {
+        let nanos = time.as_nanos();
+        let offset = std::time::Duration::new(
+            (nanos.unsigned_abs() / NANOS_PER_SEC as u128) as u64,
+            (nanos.unsigned_abs() % NANOS_PER_SEC as u128) as u32,
+        );
+        if nanos < 0 {
+            UNIX_EPOCH - offset
+        } else {
+            UNIX_EPOCH + offset
+        }
+    }
+}
+
+impl From<std::time::Duration> for Duration {
+    fn from(duration: std::time::Duration) -> Self {
+        // A `std::time::Duration` holds at most `u64::MAX` seconds.
+        Self::from_nanos(duration.as_nanos() as i128).unwrap_or(Self {
+            secs: i64::MAX,
+            nanos: 0,
+        })
+    }
+}
+
+impl Add<Duration> for DateTime {
+    type Output = Option<DateTime>;
+
+    fn add(self, other: Duration) -> Option<DateTime> {
+        Self::from_nanos(self.as_nanos() + other.as_nanos())
+    }
+}
+
+impl Sub<Duration> for DateTime {
+    type Output = Option<DateTime>;
+
+    fn sub(self, other: Duration) -> Option<DateTime> {
+        Self::from_nanos(self.as_nanos() - other.as_nanos())
+    }
+}
+
+impl Sub for DateTime {
+    type Output = Option<Duration>;
+
+    fn sub(self, other: Self) -> Option<Duration> {
+        Duration::from_nanos(self.as_nanos() - other.as_nanos())
+    }
+}
+
+impl Add for Duration {
+    type Output = Option<Duration>;
+
+    fn add(self, other: Self) -> Option<Duration> {
+        Duration::from_nanos(self.as_nanos() + other.as_nanos())
+    }
+}
+
+impl Sub for Duration {
+    type Output = Option<Duration>;
+
+    fn sub(self, other: Self) -> Option<Duration> {
+        Duration::from_nanos(self.as_nanos() - other.as_nanos())
+    }
+}
+
+/// Days since 1970-01-01 of a date in the proleptic Gregorian calendar.
+///
+/// From http://howardhinnant.github.io/date_algorithms.html#days_from_civil.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let month = month as i64;
+    let day_of_year =
+        (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// The inverse of `days_from_civil`.
+///
+/// From http://howardhinnant.github.io/date_algorithms.html#civil_from_days.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// A cursor over the bytes of an ISO-8601 string.
+struct Scanner<'a> {
+    s: &'a [u8],
+    i: usize,
+}
+
+impl<'a> Scanner<'a> {
+    fn new(s: &'a str) -> Self {
+        Self {
+            s: s.as_bytes(),
+            i: 0,
+        }
+    }
+
+    fn done(&self) -> bool {
+        self.i == self.s.len()
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.s.get(self.i).copied()
+    }
+
+    fn eat(&mut self, c: u8) -> bool {
+        let found = self.peek().map(|p| p.eq_ignore_ascii_case(&c)) == Some(true);
+        if found {
+            self.i += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, c: u8) -> Option<()> {
+        self.eat(c).then_some(())
+    }
+
+    /// Exactly `n` digits.
+    fn digits(&mut self, n: usize) -> Option<u32> {
+        let digits = self.s.get(self.i..self.i + n)?;
+        if !digits.iter().all(u8::is_ascii_digit) {
+            return None;
+        }
+        self.i += n;
+        Some(digits.iter().fold(0, |acc, d| acc * 10 + (d - b'0') as u32))
+    }
+
+    /// One or more digits, with an optional fraction. Returns the whole part and the fraction
+    /// in nanoseconds.
+    fn number(&mut self) -> Option<(i64, u32)> {
+        let start = self.i;
+        while matches!(self.peek(), Some(c) if c.is_ascii_digit()) {
+            self.i += 1;
+        }
+        let whole = std::str::from_utf8(&self.s[start..self.i])
+            .ok()?
+            .parse()
+            .ok()?;
+        Some((whole, self.fraction()?))
+    }
+
+    /// An optional `.` or `,` followed by up to nine digits, in nanoseconds.
+    fn fraction(&mut self) -> Option<u32> {
+        if !(self.eat(b'.') || self.eat(b',')) {
+            return Some(0);
+        }
+        let start = self.i;
+        while matches!(self.peek(), Some(c) if c.is_ascii_digit()) {
+            self.i += 1;
+        }
+        let digits = &self.s[start..self.i];
+        if digits.is_empty() || digits.len() > 9 {
+            return None;
+        }
+        let nanos = digits.iter().fold(0, |acc, d| acc * 10 + (d - b'0') as u32);
+        Some(nanos * 10u32.pow(9 - digits.len() as u32))
+    }
+}
+
+impl FromStr for DateTime {
+    type Err = String;
+
+    /// Parse a date, `YYYY-MM-DD`, or a date and time with a UTC offset,
+    /// `YYYY-MM-DDTHH:MM[:SS[.fff]](Z|±HH:MM)`. A date alone is midnight UTC.
+    fn from_str(s: &str) -> Result<Self, String> {
+        let invalid = || format!("'{}' is not a valid ISO-8601 date-time", s);
+        let mut scanner = Scanner::new(s);
+        let mut parse = || -> Option<Self> {
+            let year = scanner.digits(4)? as i64;
+            scanner.expect(b'-')?;
+            let month = scanner.digits(2)?;
+            scanner.expect(b'-')?;
+            let day = scanner.digits(2)?;
+            if !(1..=12).contains(&month) || !(1..=days_in_month(year, month)).contains(&day) {
+                return None;
+            }
+            let days = days_from_civil(year, month, day);
+            if scanner.done() {
+                return Self::from_unix(days * SECS_PER_DAY, 0);
+            }
+
+            if !(scanner.eat(b'T') || scanner.eat(b' ')) {
+                return None;
+            }
+            let hour = scanner.digits(2)?;
+            scanner.expect(b':')?;
+            let minute = scanner.digits(2)?;
+            let (second, nanos) = if scanner.eat(b':') {
+                (scanner.digits(2)?, scanner.fraction()?)
+            } else {
+                (0, 0)
+            };
+            if hour > 23 || minute > 59 || second > 59 {
+                return None;
+            }
+
+            let offset = if scanner.eat(b'Z') {
+                0
+            } else {
+                let sign = if scanner.eat(b'+') {
+                    1
+                } else {
+                    scanner.expect(b'-')?;
+                    -1
+                };
+                let hours = scanner.digits(2)?;
+                scanner.expect(b':')?;
+                let minutes = scanner.digits(2)?;
+                if hours > 23 || minutes > 59 {
+                    return None;
+                }
+                sign * (hours * 3600 + minutes * 60) as i64
+            };
+            if !scanner.done() {
+                return None;
+            }
+
+            let secs = days * SECS_PER_DAY + (hour * 3600 + minute * 60 + second) as i64 - offset;
+            Self::from_unix(secs, nanos)
+        };
+        parse().ok_or_else(invalid)
+    }
+}
+
+impl FromStr for Duration {
+    type Err = String;
+
+    /// Parse a duration, `[-]P[nW][nD][T[nH][nM][n[.f]S]]`. Years and months aren't supported
+    /// because their length varies.
+    fn from_str(s: &str) -> Result<Self, String> {
+        let invalid = || format!("'{}' is not a valid ISO-8601 duration", s);
+        let mut scanner = Scanner::new(s);
+        let mut parse = || -> Option<Self> {
+            let negative = scanner.eat(b'-');
+            scanner.expect(b'P')?;
+            let mut nanos: i128 = 0;
+            let mut components = 0;
+            let mut time = false;
+            // Unit designators in the order they must appear, with their length in seconds.
+            let mut units: &[(u8, bool, i64)] = &[
+                (b'W', false, 7 * SECS_PER_DAY),
+                (b'D', false, SECS_PER_DAY),
+                (b'H', true, 3600),
+                (b'M', true, 60),
+                (b'S', true, 1),
+            ];
+            while !scanner.done() {
+                if !time && scanner.eat(b'T') {
+                    time = true;
+                    if scanner.done() {
+                        return None;
+                    }
+                    continue;
+                }
+                let (whole, fraction) = scanner.number()?;
+                let designator = scanner.peek()?.to_ascii_uppercase();
+                scanner.i += 1;
+                let position = units
+                    .iter()
+                    .position(|&(unit, is_time, _)| unit == designator && is_time == time)?;
+                let secs = units[position].2 as i128;
+                nanos += whole as i128 * secs * NANOS_PER_SEC + fraction as i128 * secs;
+                units = &units[position + 1..];
+                components += 1;
+            }
+            if components == 0 {
+                return None;
+            }
+            Self::from_nanos(if negative { -nanos } else { nanos })
+        };
+        parse().ok_or_else(invalid)
+    }
+}
+
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (year, month, day) = civil_from_days(self.secs.div_euclid(SECS_PER_DAY));
+        let secs = self.secs.rem_euclid(SECS_PER_DAY);
+        write!(
+            f,
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+            year,
+            month,
+            day,
+            secs / 3600,
+            secs % 3600 / 60,
+            secs % 60
+        )?;
+        if self.nanos > 0 {
+            let fraction = format!("{:09}", self.nanos);
+            write!(f, ".{}", fraction.trim_end_matches('0'))?;
+        }
+        write!(f, "Z")
+    }
+}
+
+impl fmt::Display for Duration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let nanos = self.as_nanos();
+        if nanos < 0 {
+            write!(f, "-")?;
+        }
+        let nanos = nanos.unsigned_abs();
+        let (secs, subsec) = (nanos / NANOS_PER_SEC as u128, nanos % NANOS_PER_SEC as u128);
+        let (days, secs) = (secs / SECS_PER_DAY as u128, secs % SECS_PER_DAY as u128);
+        write!(f, "P")?;
+        if days > 0 {
+            write!(f, "{}D", days)?;
+        }
+        if secs == 0 && subsec == 0 {
+            return if days == 0 { write!(f, "T0S") } else { Ok(()) };
+        }
+        write!(f, "T")?;
+        if secs >= 3600 {
+            write!(f, "{}H", secs / 3600)?;
+        }
+        if secs % 3600 >= 60 {
+            write!(f, "{}M", secs % 3600 / 60)?;
+        }
+        if secs % 60 > 0 || subsec > 0 {
+            write!(f, "{}", secs % 60)?;
+            if subsec > 0 {
+                let fraction = format!("{:09}", subsec);
+                write!(f, ".{}", fraction.trim_end_matches('0'))?;
+            }
+            write!(f, "S")?;
+        }
+        Ok(())
+    }
+}
+
+impl TryFrom<String> for DateTime {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, String> {
+        s.parse()
+    }
+}
+
+impl From<DateTime> for String {
+    fn from(time: DateTime) -> Self {
+        time.to_string()
+    }
+}
+
+impl TryFrom<String> for Duration {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, String> {
+        s.parse()
+    }
+}
+
+impl From<Duration> for String {
+    fn from(duration: Duration) -> Self {
+        duration.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[track_caller]
+    fn datetime(s: &str) -> DateTime {
+        s.parse().unwrap()
+    }
+
+    #[track_caller]
+    fn duration(s: &str) -> Duration {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse_and_format_datetimes() {
+        assert_eq!(datetime("1970-01-01"), DateTime::from_unix(0, 0).unwrap());
+        assert_eq!(datetime("1970-01-01T00:00:01Z").unix_secs(), 1);
+        assert_eq!(datetime("1969-12-31T23:59:59Z").unix_secs(), -1);
+        assert_eq!(
+            datetime("2024-02-29T12:30:00+02:00"),
+            datetime("2024-02-29T10:30:00Z")
+        );
+        assert_eq!(datetime("2000-03-01T00:00Z").unix_secs(), 951_868_800);
+        assert_eq!(
+            datetime("2021-06-01t12:00:00.25z").subsec_nanos(),
+            250_000_000
+        );
+
+        for s in [
+            "2024-01-31T12:00:00Z",
+            "1969-07-20T20:17:40Z",
+            "2021-06-01T12:00:00.000000001Z",
+            "0001-01-01T00:00:00Z",
+        ] {
+            assert_eq!(datetime(s).to_string(), s);
+        }
+        assert_eq!(
+            datetime("2024-02-29T12:30:00-01:30").to_string(),
+            "2024-02-29T14:00:00Z"
+        );
+
+        for s in [
+            "",
+            "2024",
+            "2024-1-31",
+            "2023-02-29",
+            "2024-13-01",
+            "2024-01-31T12:00:00",
+            "2024-01-31T24:00:00Z",
+            "2024-01-31T12:00:00.Z",
+            "2024-01-31T12:00:00Zjunk",
+        ] {
+            assert!(s.parse::<DateTime>().is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn test_parse_and_format_durations() {
+        assert_eq!(duration("P30D"), Duration::from_secs(30 * SECS_PER_DAY));
+        assert_eq!(duration("P1W"), duration("P7D"));
+        assert_eq!(duration("PT1H30M"), Duration::from_secs(5400));
+        assert_eq!(duration("PT0.5S").as_nanos(), 500_000_000);
+        assert_eq!(duration("PT1.5H"), Duration::from_secs(5400));
+        assert_eq!(duration("-PT1S").as_nanos(), -1_000_000_000);
+
+        assert_eq!(duration("P1DT0S").to_string(), "P1D");
+        assert_eq!(duration("PT0S").to_string(), "PT0S");
+        assert_eq!(duration("P2W").to_string(), "P14D");
+        assert_eq!(duration("PT90M").to_string(), "PT1H30M");
+        assert_eq!(duration("-PT0.25S").to_string(), "-PT0.25S");
+        assert_eq!(duration("P1DT1H1M1.5S").to_string(), "P1DT1H1M1.5S");
+
+        for s in [
+            "", "P", "PT", "P1Y", "P1M", "PT1D", "P1H", "P1D2W", "PT1S1M", "30D",
+        ] {
+            assert!(s.parse::<Duration>().is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let start = datetime("2024-01-31T00:00:00Z");
+        let month = duration("P30D");
+        assert_eq!(start + month, Some(datetime("2024-03-01T00:00:00Z")));
+        assert_eq!(start - month, Some(datetime("2024-01-01T00:00:00Z")));
+        assert_eq!(datetime("2024-03-01") - start, Some(month));
+        assert_eq!(
+            start - datetime("2024-03-01"),
+            Some(Duration::from_secs(-30 * SECS_PER_DAY))
+        );
+        assert_eq!(month + month, Some(duration("P60D")));
+        assert_eq!(month - month, Some(duration("PT0S")));
+        assert!(datetime("2024-01-01T00:00:00.5Z") > datetime("2024-01-01"));
+        assert!(duration("-PT1S") < duration("PT0S"));
+        assert_eq!(DateTime::from_unix(i64::MAX, 0), None);
+    }
+
+    #[test]
+    fn test_datetimes_stay_in_range() {
+        let min = datetime("0000-01-01T00:00:00Z");
+        let max = datetime("9999-12-31T23:59:59.999999999Z");
+        let nanosecond = Duration::from_nanos(1).unwrap();
+        assert_eq!(min - nanosecond, None);
+        assert_eq!(max + nanosecond, None);
+        assert_eq!(
+            max - min,
+            Duration::from_nanos(max.as_nanos() - min.as_nanos())
+        );
+        assert!("0000-01-01T00:00:00+00:01".parse::<DateTime>().is_err());
+        assert!("9999-12-31T23:59:59-00:01".parse::<DateTime>().is_err());
+
+        // Every `DateTime` can be formatted and parsed back, including through serde.
+        for time in [
+            min,
+            max,
+            (min + nanosecond).unwrap(),
+            (max - nanosecond).unwrap(),
+        ] {
+            assert_eq!(datetime(&time.to_string()), time);
+            let json = serde_json::to_string(&time).unwrap();
+            assert_eq!(serde_json::from_str::<DateTime>(&json).unwrap(), time);
+        }
+
+        let far_future = SystemTime::from(max) + std::time::Duration::from_secs(1);
+        assert_eq!(DateTime::from(far_future), max);
+    }
+
+    #[test]
+    fn test_system_time_and_serde() {
+        let time = datetime("2024-01-31T12:00:00.5Z");
+        assert_eq!(DateTime::from(SystemTime::from(time)), time);
+        let before = datetime("1960-01-01T00:00:00.5Z");
+        assert_eq!(DateTime::from(SystemTime::from(before)), before);
+        assert_eq!(
+            Duration::from(std::time::Duration::from_millis(1500)),
+            duration("PT1.5S")
+        );
+
+        let json = serde_json::to_string(&time).unwrap();
+        assert_eq!(json, "\"2024-01-31T12:00:00.5Z\"");
+        assert_eq!(serde_json::from_str::<DateTime>(&json).unwrap(), time);
+        assert!(serde_json::from_str::<Duration>("\"P1Y\"").is_err());
+    }
+}
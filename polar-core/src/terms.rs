@@ -8,9 +8,11 @@ use std::{
 use serde::{Deserialize, Serialize};
 
 use super::error::{unexpected_value, PolarResult};
-pub use super::numerics::Numeric;
+pub use super::numerics::{Decimal, Numeric};
 use super::resource_block::{ACTOR_UNION_NAME, RESOURCE_UNION_NAME};
+pub use super::sets::Set;
 use super::sources::{Context, Source, SourceInfo};
+pub use super::temporal::{DateTime, Duration};
 use super::visitor::{walk_operation, walk_term, Visitor};
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default, Eq, PartialEq, Hash)]
@@ -53,24 +55,121 @@ pub fn has_rest_var(list: &TermList) -> bool {
     !list.is_empty() && matches!(list.last().unwrap().value(), Value::RestVariable(_))
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq, Ord, PartialOrd, Hash)]
-pub struct Symbol(pub String);
+/// A name, e.g., of a variable, rule or class. Short names are stored inline and longer ones are
+/// reference counted, so symbols are cheap to create and to clone.
+#[derive(Clone, Eq, PartialEq, Hash)]
+pub struct Symbol(Name);
+
+/// The longest name stored inline, which keeps a `Symbol` as small as an `Arc<str>` plus a word.
+const INLINE_LEN: usize = 22;
+
+/// Names that fit are always stored inline, padded with zeros, so equal names have equal
+/// representations.
+#[derive(Clone, Eq, PartialEq, Hash)]
+enum Name {
+    Inline { len: u8, bytes: [u8; INLINE_LEN] },
+    Shared(Arc<str>),
+}
 
 impl Symbol {
     pub fn new(name: &str) -> Self {
-        Self(name.to_string())
+        if name.len() <= INLINE_LEN {
+            let mut bytes = [0; INLINE_LEN];
+            bytes[..name.len()].copy_from_slice(name.as_bytes());
+            Self(Name::Inline {
+                len: name.len() as u8,
+                bytes,
+            })
+        } else {
+            Self(Name::Shared(name.into()))
+        }
     }
 
+    pub fn as_str(&self) -> &str {
+        match &self.0 {
+            Name::Inline { len, bytes } => {
+                // Only ever copied from a `&str`, and never cut short.
+                std::str::from_utf8(&bytes[..*len as usize]).unwrap()
+            }
+            Name::Shared(name) => name,
+        }
+    }
+
+    /// Used instead of the name where possible, since that is checked to be UTF-8 each time.
+    fn as_bytes(&self) -> &[u8] {
+        match &self.0 {
+            Name::Inline { len, bytes } => &bytes[..*len as usize],
+            Name::Shared(name) => name.as_bytes(),
+        }
+    }
+
+    /// The reference counted name, if the name is too long to be stored inline.
+    pub(crate) fn shared(&self) -> Option<&Arc<str>> {
+        match &self.0 {
+            Name::Inline { .. } => None,
+            Name::Shared(name) => Some(name),
+        }
+    }
+
+    /// Variables starting with `_` are anonymous: they're never reported in query results and
+    /// are exempt from singleton warnings. Each occurrence of a bare `_` is a fresh variable,
+    /// while every occurrence of a `_name` variable within a rule or query is the same variable.
+    /// Variables generated by the VM and the rewriter are anonymous too.
     pub fn is_temporary_var(&self) -> bool {
-        self.0.starts_with('_')
+        self.as_bytes().starts_with(b"_")
     }
 
     pub fn is_namespaced_var(&self) -> bool {
-        self.0.contains("::")
+        self.as_str().contains("::")
     }
 
     pub fn is_this_var(&self) -> bool {
-        self.0 == "_this"
+        self.as_bytes() == b"_this"
+    }
+}
+
+impl From<Arc<str>> for Symbol {
+    fn from(name: Arc<str>) -> Self {
+        if name.len() <= INLINE_LEN {
+            Self::new(&name)
+        } else {
+            Self(Name::Shared(name))
+        }
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("Symbol").field(&self.as_str()).finish()
+    }
+}
+
+impl PartialOrd for Symbol {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Symbol {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        // UTF-8 sorts bytewise in the same order as the characters.
+        self.as_bytes().cmp(other.as_bytes())
+    }
+}
+
+impl Serialize for Symbol {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_newtype_struct("Symbol", self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Symbol {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(rename = "Symbol")]
+        struct Repr(String);
+
+        Repr::deserialize(deserializer).map(|Repr(name)| Self::new(&name))
     }
 }
 
@@ -108,6 +207,10 @@ pub enum Operator {
     And,
     ForAll,
     Assign,
+    Count,
+    Sum,
+    Min,
+    Max,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq, Hash)]
@@ -128,11 +231,14 @@ pub enum Value {
     Number(Numeric),
     String(String),
     Boolean(bool),
+    DateTime(DateTime),
+    Duration(Duration),
     ExternalInstance(ExternalInstance),
     Dictionary(Dictionary),
     Pattern(Pattern),
     Call(Call),
     List(TermList),
+    Set(Set),
     Variable(Symbol),
     RestVariable(Symbol),
     Expression(Operation),
@@ -145,10 +251,15 @@ impl Value {
             | Value::ExternalInstance(_)
             | Value::Variable(_)
             | Value::RestVariable(_) => false,
-            Value::Number(_) | Value::String(_) | Value::Boolean(_) => true,
+            Value::Number(_)
+            | Value::String(_)
+            | Value::Boolean(_)
+            | Value::DateTime(_)
+            | Value::Duration(_) => true,
             Value::Pattern(_) => panic!("unexpected value type"),
             Value::Dictionary(Dictionary { fields }) => fields.values().all(|t| t.is_ground()),
             Value::List(terms) => terms.iter().all(|t| t.is_ground()),
+            Value::Set(set) => set.is_ground(),
             Value::Expression(Operation { operator: _, args }) => {
                 args.iter().all(|t| t.is_ground())
             }
@@ -224,12 +335,24 @@ impl From<TermList> for Value {
     }
 }
 
+impl From<Set> for Value {
+    fn from(other: Set) -> Self {
+        Self::Set(other)
+    }
+}
+
 impl From<String> for Value {
     fn from(other: String) -> Self {
         Self::String(other)
     }
 }
 
+impl From<&str> for Value {
+    fn from(other: &str) -> Self {
+        Self::String(other.to_owned())
+    }
+}
+
 impl From<ExternalInstance> for Value {
     fn from(other: ExternalInstance) -> Self {
         Self::ExternalInstance(other)
@@ -311,6 +434,15 @@ impl Term {
         }
     }
 
+    /// Create a new term with the source info of `self` and the value of `other`, which the two
+    /// share rather than copy.
+    pub fn clone_with_value_of(&self, other: &Term) -> Self {
+        Self {
+            source_info: self.source_info.clone(),
+            value: other.value.clone(),
+        }
+    }
+
     /// Replace the `value` of self
     pub fn replace_value(&mut self, value: Value) {
         self.value = Arc::new(value);
@@ -320,6 +452,10 @@ impl Term {
         &self.source_info
     }
 
+    pub(crate) fn source_info_mut(&mut self) -> &mut SourceInfo {
+        &mut self.source_info
+    }
+
     // TODO(gj): Parsed<T> type (or something) so we can remove this meaningless distinction
     // between terms & rules.
     pub(crate) fn parsed_context(&self) -> Option<&Context> {
@@ -462,11 +598,138 @@ impl Term {
     }
 
     pub fn is_actor_union(&self) -> bool {
-        matches!(self.value(), Value::Pattern(Pattern::Instance(InstanceLiteral { tag, .. })) | Value::Variable(tag) if tag.0 == ACTOR_UNION_NAME)
+        matches!(self.value(), Value::Pattern(Pattern::Instance(InstanceLiteral { tag, .. })) | Value::Variable(tag) if tag.as_str() == ACTOR_UNION_NAME)
     }
 
     pub fn is_resource_union(&self) -> bool {
-        matches!(self.value(), Value::Pattern(Pattern::Instance(InstanceLiteral { tag, .. })) | Value::Variable(tag) if tag.0 == RESOURCE_UNION_NAME)
+        matches!(self.value(), Value::Pattern(Pattern::Instance(InstanceLiteral { tag, .. })) | Value::Variable(tag) if tag.as_str() == RESOURCE_UNION_NAME)
+    }
+}
+
+/// Builds a [`Call`] argument by argument, so that hosts constructing queries and constructor
+/// calls don't have to assemble the keyword arguments by hand.
+///
+/// ```
+/// use polar_core::terms::{CallBuilder, TermBuilder};
+///
+/// let call = CallBuilder::new("allow")
+///     .arg("alice")
+///     .arg(1)
+///     .kwarg("context", TermBuilder::dict().field("ip", "10.0.0.1"))
+///     .build();
+/// assert_eq!(call.args.len(), 2);
+/// assert_eq!(call.kwargs.unwrap().len(), 1);
+/// ```
+#[derive(Debug, Clone)]
+pub struct CallBuilder {
+    name: Symbol,
+    args: TermList,
+    kwargs: BTreeMap<Symbol, Term>,
+}
+
+impl CallBuilder {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: Symbol::new(name),
+            args: vec![],
+            kwargs: BTreeMap::new(),
+        }
+    }
+
+    /// Add a positional argument after the ones already added.
+    pub fn arg(mut self, arg: impl Into<Term>) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+
+    /// Add a keyword argument, replacing any earlier one with the same name.
+    pub fn kwarg(mut self, name: &str, value: impl Into<Term>) -> Self {
+        self.kwargs.insert(Symbol::new(name), value.into());
+        self
+    }
+
+    /// The call, with `kwargs` set to `None` if no keyword arguments were added.
+    pub fn build(self) -> Call {
+        Call {
+            name: self.name,
+            args: self.args,
+            kwargs: if self.kwargs.is_empty() {
+                None
+            } else {
+                Some(self.kwargs)
+            },
+        }
+    }
+
+    pub fn build_term(self) -> Term {
+        Term::from(self.build())
+    }
+}
+
+impl From<CallBuilder> for Value {
+    fn from(builder: CallBuilder) -> Self {
+        Self::Call(builder.build())
+    }
+}
+
+/// Builds compound terms, e.g., dictionaries nested in other dictionaries or in the arguments
+/// of a [`CallBuilder`]. Builders convert into terms wherever a term is expected.
+#[derive(Debug, Clone)]
+pub enum TermBuilder {
+    Dictionary(Dictionary),
+    List(TermList),
+}
+
+impl TermBuilder {
+    /// Start an empty dictionary.
+    pub fn dict() -> Self {
+        Self::Dictionary(Dictionary::new())
+    }
+
+    /// Start an empty list.
+    pub fn list() -> Self {
+        Self::List(vec![])
+    }
+
+    /// Set a field of a dictionary.
+    ///
+    /// # Panics
+    ///
+    /// If the builder is building a list.
+    pub fn field(mut self, name: &str, value: impl Into<Term>) -> Self {
+        match &mut self {
+            Self::Dictionary(dict) => {
+                dict.fields.insert(Symbol::new(name), value.into());
+            }
+            Self::List(_) => panic!("can't set field `{}` of a list", name),
+        }
+        self
+    }
+
+    /// Append an element to a list.
+    ///
+    /// # Panics
+    ///
+    /// If the builder is building a dictionary.
+    pub fn element(mut self, value: impl Into<Term>) -> Self {
+        match &mut self {
+            Self::List(list) => list.push(value.into()),
+            Self::Dictionary(_) => panic!("can't append an element to a dictionary"),
+        }
+        self
+    }
+
+    pub fn build(self) -> Term {
+        Term::from(self)
+    }
+}
+
+impl From<TermBuilder> for Value {
+    fn from(builder: TermBuilder) -> Self {
+        match builder {
+            TermBuilder::Dictionary(dict) => Self::Dictionary(dict),
+            TermBuilder::List(list) => Self::List(list),
+        }
     }
 }
 
@@ -474,6 +737,8 @@ impl Term {
 mod tests {
     use std::collections::HashMap;
 
+    use super::*;
+
     #[test]
     fn test_value_hash() {
         let mut table = HashMap::new();
@@ -499,4 +764,51 @@ mod tests {
             "b:2"
         );
     }
+
+    #[test]
+    fn test_call_builder() {
+        let call = CallBuilder::new("f")
+            .arg(1)
+            .arg("two")
+            .arg(sym!("x"))
+            .kwarg(
+                "options",
+                TermBuilder::dict()
+                    .field("nested", TermBuilder::dict().field("on", true))
+                    .field("tags", TermBuilder::list().element("a").element("b")),
+            )
+            .kwarg("limit", 10)
+            .build();
+        assert_eq!(
+            call,
+            call!("f", [1, "two", sym!("x")], {
+                "options" => btreemap! {
+                    sym!("nested") => term!(btreemap! {sym!("on") => term!(true)}),
+                    sym!("tags") => term!(["a", "b"]),
+                },
+                "limit" => 10,
+            })
+        );
+        assert_eq!(CallBuilder::new("g").build(), call!("g"));
+    }
+
+    #[test]
+    fn test_symbol_representations() {
+        let short = Symbol::new("short");
+        let long = Symbol::new("a_name_too_long_to_store_inline");
+        assert!(short.shared().is_none());
+        assert!(long.shared().is_some());
+        assert_eq!(short.as_str(), "short");
+        assert_eq!(long.as_str(), "a_name_too_long_to_store_inline");
+
+        // Converting a short shared name stores it inline, so it equals the same name.
+        assert_eq!(Symbol::from(Arc::<str>::from("short")), short);
+        assert!(short > long);
+        assert!(Symbol::new("") < Symbol::new("\u{e9}"));
+
+        let json = serde_json::to_string(&vec![&short, &long]).unwrap();
+        assert_eq!(json, r#"["short","a_name_too_long_to_store_inline"]"#);
+        let symbols: Vec<Symbol> = serde_json::from_str(&json).unwrap();
+        assert_eq!(symbols, vec![short, long]);
+    }
 }
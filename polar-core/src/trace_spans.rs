@@ -0,0 +1,267 @@
+//! Structured traces of query evaluation, for shipping to tracing backends.
+//!
+//! Where [`crate::traces`] formats the evaluation tree as text, a query started with
+//! [`Query::record_spans`](crate::query::Query::record_spans) records one [`TraceSpan`] per
+//! attempt to evaluate a rule. Spans follow OpenTelemetry span semantics: each has an ID, the ID
+//! of the attempt it was evaluated in, start and end times and a status, so a host can turn each
+//! of them into a span in its own tracer.
+
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+use std::sync::Arc;
+
+use serde::Serialize;
+
+use crate::introspection::Span;
+use crate::rules::Rule;
+use crate::terms::{Symbol, Term};
+use crate::traces::{Node, Trace};
+use crate::vm::{micros_since, now, Timestamp};
+
+/// How an attempt to evaluate a rule ended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum SpanStatus {
+    /// The rule's body succeeded. Later successes of the same attempt, after backtracking, aren't
+    /// recorded again.
+    Ok,
+    /// The rule's body failed, or the query was done before it succeeded.
+    Failed,
+}
+
+/// One attempt to evaluate a rule.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct TraceSpan {
+    /// Unique within the query, starting from 1.
+    pub span_id: u64,
+    /// The attempt whose body called the rule, if any.
+    pub parent_span_id: Option<u64>,
+    pub name: Symbol,
+    /// The rule head, e.g., `allow(actor: User, "read", resource: Repo)`.
+    pub head: String,
+    pub source: Option<Span>,
+    /// The arguments the rule was called with, as bound when the attempt ended.
+    pub args: Vec<Term>,
+    /// Microseconds from when recording started to when the attempt started.
+    pub start_us: u64,
+    /// Microseconds from when recording started to when the attempt ended.
+    pub end_us: u64,
+    pub duration_us: u64,
+    pub status: SpanStatus,
+}
+
+#[derive(Debug)]
+struct Attempt {
+    /// The VM evaluating the attempt.
+    owner: u64,
+    span_id: u64,
+    parent_span_id: Option<u64>,
+    rule: Arc<Rule>,
+    args: Vec<Term>,
+    start_us: u64,
+    ended: bool,
+}
+
+/// Collects `TraceSpan`s as a query runs. Shared between a VM and the VMs it spawns, which each
+/// get their own owner ID, so that one VM backtracking doesn't end another's attempts.
+#[derive(Debug)]
+pub(crate) struct SpanRecorder {
+    epoch: Timestamp,
+    next_span_id: u64,
+    /// The attempt each VM was spawned from, by owner ID.
+    owner_parents: Vec<Option<u64>>,
+    /// Attempts by the trace pushed when they started.
+    attempts: HashMap<*const Trace, Attempt>,
+    spans: Vec<TraceSpan>,
+}
+
+impl Default for SpanRecorder {
+    fn default() -> Self {
+        Self {
+            epoch: now(),
+            next_span_id: 1,
+            owner_parents: vec![None],
+            attempts: HashMap::new(),
+            spans: vec![],
+        }
+    }
+}
+
+impl SpanRecorder {
+    /// A new owner ID for a VM spawned while evaluating `parent_span_id`.
+    pub fn spawn(&mut self, parent_span_id: Option<u64>) -> u64 {
+        self.owner_parents.push(parent_span_id);
+        self.owner_parents.len() as u64 - 1
+    }
+
+    /// The span of the innermost attempt traced by one of `traces`, innermost first.
+    pub fn innermost<'a>(
+        &self,
+        owner: u64,
+        traces: impl Iterator<Item = &'a Rc<Trace>>,
+    ) -> Option<u64> {
+        // Attempts that have ended are skipped, since their traces may have been freed and the
+        // memory reused.
+        traces
+            .filter_map(|trace| self.attempts.get(&Rc::as_ptr(trace)))
+            .find(|attempt| !attempt.ended)
+            .map(|attempt| attempt.span_id)
+            .or(self.owner_parents[owner as usize])
+    }
+
+    /// Record that the body of the rule traced by `trace` was entered with `args`, as they were
+    /// bound at the time.
+    pub fn rule_tried(
+        &mut self,
+        owner: u64,
+        trace: &Rc<Trace>,
+        parent_span_id: Option<u64>,
+        args: Vec<Term>,
+    ) {
+        let rule = match &trace.node {
+            Node::Rule(rule) => rule.clone(),
+            Node::Term(_) => return,
+        };
+        let span_id = self.next_span_id;
+        self.next_span_id += 1;
+        let attempt = Attempt {
+            owner,
+            span_id,
+            parent_span_id,
+            rule,
+            args,
+            start_us: micros_since(self.epoch),
+            ended: false,
+        };
+        // A trace is only reallocated at the same address once the attempt that owned it has
+        // been abandoned.
+        if let Some(abandoned) = self.attempts.insert(Rc::as_ptr(trace), attempt) {
+            self.fail(&abandoned);
+        }
+    }
+
+    /// Record that the body of the rule traced by `trace` succeeded, with `deref` giving the
+    /// current values of its arguments.
+    pub fn rule_succeeded(&mut self, trace: &Rc<Trace>, deref: impl Fn(&Term) -> Term) {
+        let attempt = match self.attempts.get_mut(&Rc::as_ptr(trace)) {
+            Some(attempt) if !attempt.ended => attempt,
+            _ => return,
+        };
+        attempt.ended = true;
+        let args = attempt.args.iter().map(deref).collect();
+        let span = span(self.epoch, attempt, args, SpanStatus::Ok);
+        self.spans.push(span);
+    }
+
+    /// End the attempts of `owner` that it's no longer evaluating after backtracking past them.
+    /// `live` holds the traces the VM is still working under.
+    pub fn abandon(&mut self, owner: u64, live: &HashSet<*const Trace>) {
+        let abandoned = self
+            .attempts
+            .iter()
+            .filter(|(trace, attempt)| attempt.owner == owner && !live.contains(*trace))
+            .map(|(trace, _)| *trace)
+            .collect::<Vec<_>>();
+        for trace in abandoned {
+            if let Some(attempt) = self.attempts.remove(&trace) {
+                self.fail(&attempt);
+            }
+        }
+    }
+
+    /// End every attempt, once the query is done.
+    pub fn finish(&mut self) {
+        for (_, attempt) in std::mem::take(&mut self.attempts) {
+            self.fail(&attempt);
+        }
+    }
+
+    fn fail(&mut self, attempt: &Attempt) {
+        if !attempt.ended {
+            let span = span(
+                self.epoch,
+                attempt,
+                attempt.args.clone(),
+                SpanStatus::Failed,
+            );
+            self.spans.push(span);
+        }
+    }
+
+    /// The spans that have ended since the last call, in the order they ended.
+    pub fn take_spans(&mut self) -> Vec<TraceSpan> {
+        std::mem::take(&mut self.spans)
+    }
+}
+
+fn span(epoch: Timestamp, attempt: &Attempt, args: Vec<Term>, status: SpanStatus) -> TraceSpan {
+    let end_us = micros_since(epoch);
+    TraceSpan {
+        span_id: attempt.span_id,
+        parent_span_id: attempt.parent_span_id,
+        name: attempt.rule.name.clone(),
+        head: attempt.rule.head_as_string(),
+        source: Span::of_rule(&attempt.rule),
+        args,
+        start_us: attempt.start_us,
+        end_us,
+        duration_us: end_us.saturating_sub(attempt.start_us),
+        status,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::QueryEvent;
+    use crate::polar::Polar;
+
+    #[test]
+    fn test_spans_nest_and_record_failures() {
+        let p = Polar::new();
+        p.load_str(
+            r#"f(x) if g(x) and h(x);
+               g(1);
+               g(2);
+               h(x) if x = 2;"#,
+        )
+        .unwrap();
+        let mut query = p.new_query("f(x)", false).unwrap();
+        query.record_spans();
+        loop {
+            match query.next_event().unwrap() {
+                QueryEvent::Result { .. } => (),
+                QueryEvent::Done { .. } => break,
+                event => panic!("unexpected event {:?}", event),
+            }
+        }
+
+        let spans = query.take_spans();
+        let summary = spans
+            .iter()
+            .map(|span| {
+                (
+                    span.head.as_str(),
+                    span.args.clone(),
+                    span.status,
+                    span.parent_span_id.is_some(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            vec![
+                ("g(1)", vec![term!(1)], SpanStatus::Ok, true),
+                ("h(x)", vec![term!(1)], SpanStatus::Failed, true),
+                ("g(2)", vec![term!(2)], SpanStatus::Ok, true),
+                ("h(x)", vec![term!(2)], SpanStatus::Ok, true),
+                ("f(x)", vec![term!(2)], SpanStatus::Ok, false),
+            ]
+        );
+        let f = spans.iter().find(|span| span.name.as_str() == "f").unwrap();
+        assert!(spans
+            .iter()
+            .filter(|span| span.parent_span_id.is_some())
+            .all(|span| span.parent_span_id == Some(f.span_id)));
+        assert!(query.take_spans().is_empty());
+    }
+}
@@ -26,6 +26,37 @@ impl Trace {
     }
 }
 
+/// Rule names and namespaces whose traces should be recorded in query results.
+///
+/// A pattern matches a rule with exactly that name, or, if it ends in `*`, any rule whose name
+/// starts with the rest of the pattern, e.g., `billing::*`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TraceFilter(Vec<String>);
+
+impl TraceFilter {
+    pub fn new<I, S>(patterns: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self(patterns.into_iter().map(Into::into).collect())
+    }
+
+    pub fn matches(&self, name: &Symbol) -> bool {
+        self.0
+            .iter()
+            .any(|pattern| match pattern.strip_suffix('*') {
+                Some(prefix) => name.as_str().starts_with(prefix),
+                None => *name.as_str() == **pattern,
+            })
+    }
+
+    /// Whether `trace` is of a rule matching the filter.
+    pub fn matches_trace(&self, trace: &Trace) -> bool {
+        matches!(&trace.node, Node::Rule(rule) if self.matches(&rule.name))
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct TraceResult {
     pub trace: Rc<Trace>,
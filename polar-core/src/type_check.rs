@@ -0,0 +1,483 @@
+//! Optional static type checking of the rules in a policy. See [`TypeChecking`].
+
+use std::collections::HashMap;
+
+use super::diagnostic::Diagnostic;
+use super::error::ValidationError;
+use super::kb::KnowledgeBase;
+use super::numerics::Numeric;
+use super::rules::{Parameter, Rule};
+use super::terms::*;
+use super::visitor::{walk_call, walk_term, Visitor};
+use super::warning::ValidationWarning;
+
+/// Whether to check the types of the values in rules when a policy is loaded, and how to report
+/// mismatches.
+///
+/// Types are inferred from parameter specializers, literals, `matches` checks, and the field types
+/// declared in resource blocks, e.g., `fields = { name: String };`. A mismatch is a condition that
+/// can never succeed given those types, such as `user.name > 5` where `name` is a `String`, or a
+/// call passing a `String` to a rule whose parameter is always specialized on `User`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TypeChecking {
+    #[default]
+    Off,
+    /// Report mismatches as warnings.
+    Warn,
+    /// Report mismatches as errors, which stops the policy from loading.
+    Deny,
+}
+
+const PRIMITIVES: &[&str] = &[
+    "Boolean",
+    "Decimal",
+    "Dictionary",
+    "Float",
+    "Integer",
+    "List",
+    "String",
+];
+
+fn is_primitive(class: &Symbol) -> bool {
+    PRIMITIVES.contains(&class.as_str())
+}
+
+fn is_numeric(class: &Symbol) -> bool {
+    matches!(class.as_str(), "Integer" | "Float" | "Decimal")
+}
+
+/// The class of a literal value.
+fn literal_type(term: &Term) -> Option<Symbol> {
+    let class = match term.value() {
+        Value::Number(Numeric::Integer(_) | Numeric::BigInteger(_)) => "Integer",
+        Value::Number(Numeric::Float(_)) => "Float",
+        Value::Number(Numeric::Decimal(_)) => "Decimal",
+        Value::Boolean(_) => "Boolean",
+        Value::String(_) => "String",
+        Value::List(_) => "List",
+        Value::Dictionary(_) => "Dictionary",
+        _ => return None,
+    };
+    Some(Symbol::new(class))
+}
+
+/// The text `term` was parsed from, if any, e.g., `user.name` for the temporary variable that
+/// holds the result of the lookup.
+fn describe(term: &Term) -> String {
+    term.parsed_context()
+        .and_then(|context| context.source.src.get(context.left..context.right))
+        .map_or_else(|| term.to_string(), str::to_owned)
+}
+
+/// Whether a value of class `left` can ever equal a value of class `right`. Host classes are
+/// assumed compatible with each other, since one may be a subclass of the other.
+fn can_unify(left: &Symbol, right: &Symbol) -> bool {
+    left == right
+        || (is_numeric(left) && is_numeric(right))
+        || (!is_primitive(left) && !is_primitive(right))
+}
+
+/// Whether values of classes `left` and `right` can be compared with `<`, `>=`, etc. Host
+/// instances are compared by the host, so they're assumed comparable.
+fn can_compare(left: &Symbol, right: &Symbol) -> bool {
+    let group = |class: &Symbol| match class.as_str() {
+        "Boolean" | "Integer" | "Float" => Some("number"),
+        "String" => Some("String"),
+        _ => None,
+    };
+    match (is_primitive(left), is_primitive(right)) {
+        (true, true) => matches!((group(left), group(right)), (Some(l), Some(r)) if l == r),
+        _ => true,
+    }
+}
+
+struct TypeChecker<'kb> {
+    kb: &'kb KnowledgeBase,
+    /// The class of each variable in the rule being checked, where it's known.
+    types: HashMap<Symbol, Symbol>,
+    mismatches: Vec<(Term, String)>,
+}
+
+impl<'kb> TypeChecker<'kb> {
+    fn new(kb: &'kb KnowledgeBase) -> Self {
+        Self {
+            kb,
+            types: HashMap::new(),
+            mismatches: vec![],
+        }
+    }
+
+    fn is_union(&self, class: &Symbol) -> bool {
+        self.kb
+            .is_union(&Term::from(Value::Variable(class.clone())))
+    }
+
+    /// Whether a value of class `value` can match `class`, which may be a union.
+    fn can_match(&self, value: &Symbol, class: &Symbol) -> bool {
+        if self.is_union(class) {
+            let union = Term::from(Value::Variable(class.clone()));
+            return self
+                .kb
+                .get_union_members(&union)
+                .into_iter()
+                .flatten()
+                .filter_map(|member| member.as_symbol().ok())
+                .any(|member| self.can_match(value, member));
+        }
+        can_unify(value, class)
+    }
+
+    fn type_of(&self, term: &Term) -> Option<Symbol> {
+        match term.value() {
+            Value::Variable(var) => self.types.get(var).cloned(),
+            _ => literal_type(term),
+        }
+    }
+
+    /// Record that `term` has class `class`, if it's a variable whose class isn't known yet.
+    fn set_type(&mut self, term: &Term, class: Option<Symbol>) -> bool {
+        match (term.value(), class) {
+            (Value::Variable(var), Some(class))
+                if !self.kb.is_constant(var)
+                    && !self.is_union(&class)
+                    && !self.types.contains_key(var) =>
+            {
+                self.types.insert(var.clone(), class);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Infer the classes of variables from the rule's parameters and the conditions that every
+    /// result must satisfy, i.e., those that aren't under `or` or `not`.
+    fn infer(&mut self, rule: &Rule) {
+        self.types.clear();
+        for Parameter {
+            parameter,
+            specializer,
+        } in &rule.params
+        {
+            if let Some(Value::Pattern(Pattern::Instance(InstanceLiteral { tag, .. }))) =
+                specializer.as_ref().map(Term::value)
+            {
+                self.set_type(parameter, Some(tag.clone()));
+            }
+        }
+        while self.infer_condition(&rule.body) {}
+    }
+
+    fn infer_condition(&mut self, term: &Term) -> bool {
+        let Operation { operator, args } = match term.value() {
+            Value::Expression(operation) => operation,
+            _ => return false,
+        };
+        match (operator, &args[..]) {
+            (Operator::And, args) => args
+                .iter()
+                .fold(false, |changed, arg| self.infer_condition(arg) | changed),
+            (Operator::Unify | Operator::Assign, [left, right]) => {
+                self.set_type(left, self.type_of(right)) | self.set_type(right, self.type_of(left))
+            }
+            (Operator::Isa, [value, pattern]) => match pattern.value() {
+                Value::Pattern(Pattern::Instance(InstanceLiteral { tag, .. })) => {
+                    self.set_type(value, Some(tag.clone()))
+                }
+                _ => false,
+            },
+            (Operator::Dot, [object, field, result]) => {
+                let field_type = match (self.type_of(object), field.value()) {
+                    (Some(class), Value::String(field)) => self
+                        .kb
+                        .resource_blocks
+                        .field_type(&Term::from(Value::Variable(class)), &Symbol::new(field))
+                        .and_then(|field_type| field_type.as_symbol().ok())
+                        .cloned(),
+                    _ => None,
+                };
+                self.set_type(result, field_type)
+            }
+            (Operator::In, [item, list]) => {
+                let element_type = match list.value() {
+                    Value::List(list) if !list.is_empty() => {
+                        let types = list.iter().map(literal_type).collect::<Vec<_>>();
+                        match &types[0] {
+                            Some(class) if types.iter().all(|t| t.as_ref() == Some(class)) => {
+                                Some(class.clone())
+                            }
+                            _ => None,
+                        }
+                    }
+                    _ => None,
+                };
+                self.set_type(item, element_type)
+            }
+            _ => false,
+        }
+    }
+
+    fn mismatch(&mut self, term: &Term, msg: String) {
+        self.mismatches.push((term.clone(), msg));
+    }
+
+    fn check_operation(&mut self, term: &Term, Operation { operator, args }: &Operation) {
+        let typed = |checker: &Self, arg: &Term| checker.type_of(arg).map(|t| (describe(arg), t));
+        match (operator, &args[..]) {
+            (Operator::Lt | Operator::Gt | Operator::Leq | Operator::Geq, [left, right]) => {
+                if let (Some((l, lt)), Some((r, rt))) = (typed(self, left), typed(self, right)) {
+                    if !can_compare(&lt, &rt) {
+                        let msg = format!(
+                            "`{}` has type {} and `{}` has type {}, so they can't be compared with `{}`",
+                            l, lt, r, rt, operator
+                        );
+                        self.mismatch(term, msg);
+                    }
+                }
+            }
+            (Operator::Unify | Operator::Eq | Operator::Neq, [left, right]) => {
+                if let (Some((l, lt)), Some((r, rt))) = (typed(self, left), typed(self, right)) {
+                    if !can_unify(&lt, &rt) {
+                        let msg = format!(
+                            "`{}` has type {} and `{}` has type {}, so they can never be equal",
+                            l, lt, r, rt
+                        );
+                        self.mismatch(term, msg);
+                    }
+                }
+            }
+            (
+                Operator::Add
+                | Operator::Sub
+                | Operator::Mul
+                | Operator::Div
+                | Operator::Mod
+                | Operator::Rem,
+                [left, right, ..],
+            ) => {
+                let operand = [left, right].into_iter().find_map(|arg| {
+                    typed(self, arg).filter(|(_, t)| is_primitive(t) && !is_numeric(t))
+                });
+                if let Some((arg, class)) = operand {
+                    let msg = format!(
+                        "`{}` has type {}, so it can't be used with `{}`",
+                        arg, class, operator
+                    );
+                    self.mismatch(term, msg);
+                }
+            }
+            (Operator::Isa, [value, pattern]) => {
+                if let (
+                    Some((v, class)),
+                    Value::Pattern(Pattern::Instance(InstanceLiteral { tag, .. })),
+                ) = (typed(self, value), pattern.value())
+                {
+                    if !self.can_match(&class, tag) {
+                        let msg =
+                            format!("`{}` has type {}, so it never matches {}", v, class, tag);
+                        self.mismatch(term, msg);
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// The class that every value passed as `param` must match, if any.
+    fn parameter_type(param: &Parameter) -> Option<Symbol> {
+        match param.specializer.as_ref().map(Term::value) {
+            Some(Value::Pattern(Pattern::Instance(InstanceLiteral { tag, .. }))) => {
+                Some(tag.clone())
+            }
+            Some(_) => None,
+            None => literal_type(&param.parameter),
+        }
+    }
+
+    /// Check each argument of a rule call against the parameters of the rules it could call.
+    fn check_call(&mut self, term: &Term, call: &Call) {
+        let generic_rule = match self.kb.get_rules().get(&call.name) {
+            Some(generic_rule) => generic_rule,
+            None => return,
+        };
+        let rules = generic_rule
+            .rules
+            .values()
+            .filter(|rule| rule.params.len() == call.args.len())
+            .collect::<Vec<_>>();
+        if rules.is_empty() {
+            return;
+        }
+
+        for (i, arg) in call.args.iter().enumerate() {
+            let class = match self.type_of(arg) {
+                Some(class) => class,
+                None => continue,
+            };
+            let mut accepted = vec![];
+            for rule in &rules {
+                match Self::parameter_type(&rule.params[i]) {
+                    Some(param_type) if !accepted.contains(&param_type) => {
+                        accepted.push(param_type)
+                    }
+                    Some(_) => (),
+                    None => return,
+                }
+            }
+            if accepted.iter().all(|param| !self.can_match(&class, param)) {
+                accepted.sort();
+                let accepted = accepted
+                    .iter()
+                    .map(|param| param.as_str())
+                    .collect::<Vec<_>>()
+                    .join(" or ");
+                let msg = format!(
+                    "`{}` has type {}, but argument {} of every {}/{} rule has type {}",
+                    describe(arg),
+                    class,
+                    i + 1,
+                    call.name,
+                    call.args.len(),
+                    accepted
+                );
+                self.mismatch(term, msg);
+            }
+        }
+    }
+}
+
+impl<'kb> Visitor for TypeChecker<'kb> {
+    fn visit_term(&mut self, term: &Term) {
+        match term.value() {
+            // The calls in lookups and constructors are method calls, not rule calls.
+            Value::Expression(
+                operation @ Operation {
+                    operator: Operator::Dot | Operator::New,
+                    args,
+                },
+            ) => {
+                self.check_operation(term, operation);
+                for arg in args {
+                    match arg.value() {
+                        Value::Call(call) => walk_call(self, call),
+                        _ => self.visit_term(arg),
+                    }
+                }
+            }
+            Value::Expression(operation) => {
+                self.check_operation(term, operation);
+                walk_term(self, term);
+            }
+            Value::Call(call) => {
+                self.check_call(term, call);
+                walk_term(self, term);
+            }
+            _ => walk_term(self, term),
+        }
+    }
+}
+
+/// Check the types of the values in every rule and inline query in `kb`.
+pub fn check_types(kb: &KnowledgeBase, level: TypeChecking) -> Vec<Diagnostic> {
+    if level == TypeChecking::Off {
+        return vec![];
+    }
+
+    let mut checker = TypeChecker::new(kb);
+    let mut rules = kb
+        .get_rules()
+        .values()
+        .flat_map(|generic_rule| generic_rule.rules.values())
+        .collect::<Vec<_>>();
+    rules.sort_by_key(|rule| {
+        rule.parsed_context()
+            .map(|context| (context.source.filename.clone(), context.left))
+    });
+    for rule in rules {
+        checker.infer(rule);
+        checker.visit_term(&rule.body);
+    }
+    for query in kb.inline_queries.iter().map(|query| &query.term) {
+        checker.types.clear();
+        while checker.infer_condition(query) {}
+        checker.visit_term(query);
+    }
+
+    checker
+        .mismatches
+        .into_iter()
+        .map(|(term, msg)| match level {
+            TypeChecking::Deny => {
+                Diagnostic::Error(ValidationError::TypeMismatch { term, msg }.into())
+            }
+            _ => Diagnostic::Warning(ValidationWarning::TypeMismatch { term, msg }.into()),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::polar::Polar;
+    use crate::sources::Source;
+
+    fn mismatches(polar: &Polar, src: &str) -> Vec<String> {
+        polar
+            .diagnostic_load(vec![Source::new(src)])
+            .into_iter()
+            .filter_map(|diagnostic| match diagnostic {
+                Diagnostic::Warning(warning) if warning.code() == "W010" => {
+                    Some(warning.0.to_string())
+                }
+                Diagnostic::Error(error) if error.code() == "V011" => Some(error.0.to_string()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn polar(level: TypeChecking) -> Polar {
+        let mut polar = Polar::new();
+        polar.set_ignore_no_allow_warning(true);
+        polar.set_type_checking(level);
+        polar
+            .register_constant(sym!("User"), term!("unimportant"))
+            .unwrap();
+        polar
+            .register_constant(sym!("Org"), term!("unimportant"))
+            .unwrap();
+        polar
+    }
+
+    #[test]
+    fn test_type_mismatches() {
+        let polar = polar(TypeChecking::Warn);
+        let src = r#"
+            resource User { fields = { name: String, age: Integer }; }
+            named(user: User) if user.name > 5;
+            adult(user: User) if user.age >= 18 and user.age + 1 > 0;
+            same(user: User, org: Org) if user.name = org.name;
+            admin(x: Integer) if x matches User;
+            admin_of(user: User, _org: Org) if admin(user);
+            tagged(x) if x in ["a", "b"] and x * 2 = 4;
+            unchecked(x) if x > 5 and x.name = 1;
+        "#;
+        assert_eq!(
+            mismatches(&polar, src),
+            vec![
+                "Type mismatch: `user.name` has type String and `5` has type Integer, so they \
+                 can't be compared with `>`",
+                "Type mismatch: `x` has type Integer, so it never matches User",
+                "Type mismatch: `user` has type User, but argument 1 of every admin/1 rule has \
+                 type Integer",
+                "Type mismatch: `x` has type String, so it can't be used with `*`",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_type_checking_levels() {
+        let src = r#"f(x: Integer) if x = "one";"#;
+        assert!(mismatches(&polar(TypeChecking::Off), src).is_empty());
+        assert!(polar(TypeChecking::Warn).load_str(src).is_ok());
+        assert!(polar(TypeChecking::Deny).load_str(src).is_err());
+    }
+}
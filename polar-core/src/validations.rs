@@ -1,12 +1,15 @@
 use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
 
 use super::diagnostic::Diagnostic;
 use super::error::{PolarError, ValidationError};
+use super::folder::{fold_variable, Folder};
 use super::kb::*;
+use super::reachable::is_builtin_predicate;
 use super::rules::*;
 use super::terms::*;
 use super::visitor::{walk_call, walk_rule, walk_term, Visitor};
-use super::warning::ValidationWarning;
+use super::warning::{PolarWarning, ValidationWarning};
 
 /// Record singleton variables and unknown specializers in a rule.
 struct SingletonVisitor<'kb> {
@@ -140,7 +143,7 @@ struct ResourceBlocksMissingHasPermissionVisitor {
 
 impl Visitor for ResourceBlocksMissingHasPermissionVisitor {
     fn visit_call(&mut self, call: &Call) {
-        if call.name.0 == "has_permission" {
+        if call.name.as_str() == "has_permission" {
             self.calls_has_permission = true;
         }
         walk_call(self, call)
@@ -193,8 +196,9 @@ impl<'kb> UndefinedRuleCallVisitor<'kb> {
         self.call_terms
             .into_iter()
             .filter(|term| {
-                term.as_call()
-                    .map_or(false, |call| !self.defined_rules.contains(&call.name))
+                term.as_call().is_ok_and(|call| {
+                    !self.defined_rules.contains(&call.name) && !is_builtin_predicate(&call.name)
+                })
             })
             .map(|term| PolarError::from(ValidationError::UndefinedRuleCall { term }).into())
             .collect()
@@ -217,13 +221,328 @@ impl<'kb> Visitor for UndefinedRuleCallVisitor<'kb> {
 }
 
 pub fn check_undefined_rule_calls(kb: &KnowledgeBase) -> Vec<Diagnostic> {
-    let mut visitor = UndefinedRuleCallVisitor::new(kb.get_rules().keys().collect());
+    let defined_rules = kb.get_rules().keys().chain(kb.builtins().keys());
+    let mut visitor = UndefinedRuleCallVisitor::new(defined_rules.collect());
     for rule in kb.get_rules().values() {
         visitor.visit_generic_rule(rule);
     }
     visitor.errors()
 }
 
+/// Record calls to rules annotated with `@private` from other sources.
+struct PrivateRuleCallVisitor<'kb> {
+    kb: &'kb KnowledgeBase,
+    errors: Vec<Diagnostic>,
+}
+
+impl<'kb> Visitor for PrivateRuleCallVisitor<'kb> {
+    fn visit_term(&mut self, term: &Term) {
+        match term.value() {
+            Value::Expression(op)
+                if op.operator == Operator::Dot || op.operator == Operator::New =>
+            {
+                return
+            }
+            Value::Call(call) => {
+                if let Some(rule) = self.kb.get_private_rule(&call.name, call.args.len()) {
+                    // Calls without a source, e.g., generated ones, aren't checked.
+                    let from_elsewhere = matches!(
+                        (term.parsed_context(), rule.parsed_context()),
+                        (Some(call), Some(rule)) if call.source != rule.source
+                    );
+                    if from_elsewhere {
+                        let error = ValidationError::PrivateRuleCall {
+                            term: term.clone(),
+                            rule: rule.clone(),
+                        };
+                        self.errors.push(PolarError::from(error).into());
+                    }
+                }
+            }
+            _ => {}
+        }
+        walk_term(self, term)
+    }
+}
+
+/// Reject calls to rules annotated with `@private` from outside the source they're in.
+pub fn check_private_rule_calls(kb: &KnowledgeBase) -> Vec<Diagnostic> {
+    let mut visitor = PrivateRuleCallVisitor { kb, errors: vec![] };
+    for rule in kb.get_rules().values() {
+        visitor.visit_generic_rule(rule);
+    }
+    for query in &kb.inline_queries {
+        visitor.visit_term(&query.term);
+    }
+    visitor.errors
+}
+
+/// Record calls to rules annotated with `@deprecated`.
+struct DeprecatedRuleCallVisitor<'kb> {
+    kb: &'kb KnowledgeBase,
+    /// Name and arity of the rule being visited, whose recursive calls are not reported.
+    caller: Option<(&'kb Symbol, usize)>,
+    warnings: Vec<(Term, Deprecation)>,
+}
+
+impl<'kb> Visitor for DeprecatedRuleCallVisitor<'kb> {
+    fn visit_term(&mut self, term: &Term) {
+        match term.value() {
+            Value::Expression(op)
+                if op.operator == Operator::Dot || op.operator == Operator::New =>
+            {
+                return
+            }
+            Value::Call(call) if self.caller != Some((&call.name, call.args.len())) => {
+                if let Some(deprecation) = self.kb.get_deprecation(&call.name, call.args.len()) {
+                    self.warnings.push((term.clone(), deprecation.clone()));
+                }
+            }
+            _ => {}
+        }
+        walk_term(self, term)
+    }
+}
+
+/// Warn about every rule body and inline query that calls a deprecated rule.
+pub fn check_deprecated_rule_calls(kb: &KnowledgeBase) -> Vec<Diagnostic> {
+    let mut visitor = DeprecatedRuleCallVisitor {
+        kb,
+        caller: None,
+        warnings: vec![],
+    };
+    for (name, generic_rule) in kb.get_rules() {
+        for rule in generic_rule.rules.values() {
+            visitor.caller = Some((name, rule.params.len()));
+            visitor.visit_term(&rule.body);
+        }
+    }
+    visitor.caller = None;
+    for query in &kb.inline_queries {
+        visitor.visit_term(&query.term);
+    }
+
+    let mut warnings = visitor.warnings;
+    warnings.sort_by_key(|(term, _)| {
+        term.parsed_context()
+            .map(|context| (context.source.filename.clone(), context.left))
+    });
+    warnings
+        .into_iter()
+        .map(|(term, deprecation)| {
+            Diagnostic::Warning(ValidationWarning::DeprecatedRuleCall { term, deprecation }.into())
+        })
+        .collect()
+}
+
+/// Record calls to effectful host methods whose effects depend on evaluation order.
+struct EffectfulCallVisitor<'kb> {
+    kb: &'kb KnowledgeBase,
+    /// Number of `or` expressions enclosing the visited term.
+    disjunctions: usize,
+    /// The last effectful call in the rule body being visited.
+    previous: Option<Term>,
+    warnings: Vec<ValidationWarning>,
+}
+
+impl<'kb> EffectfulCallVisitor<'kb> {
+    fn is_effectful_call(&self, op: &Operation) -> bool {
+        matches!(op.args.get(1).map(Term::value), Some(Value::Call(call))
+            if op.operator == Operator::Dot && self.kb.is_effectful_method(&call.name))
+    }
+}
+
+impl<'kb> Visitor for EffectfulCallVisitor<'kb> {
+    fn visit_term(&mut self, term: &Term) {
+        match term.value() {
+            Value::Expression(op) if op.operator == Operator::Or => {
+                self.disjunctions += 1;
+                walk_term(self, term);
+                self.disjunctions -= 1;
+                return;
+            }
+            Value::Expression(op) if self.is_effectful_call(op) => {
+                // Drop the result variable added by the rewriter: `x.f() = _value_1` → `x.f()`.
+                let term = &term.clone_with_value(Value::Expression(Operation {
+                    operator: Operator::Dot,
+                    args: op.args[..2].to_vec(),
+                }));
+                let warning = match self.previous.replace(term.clone()) {
+                    _ if self.disjunctions > 0 => {
+                        Some(ValidationWarning::EffectfulCallInDisjunction { term: term.clone() })
+                    }
+                    Some(previous) => Some(ValidationWarning::OrderedEffectfulCalls {
+                        term: term.clone(),
+                        previous,
+                    }),
+                    None => None,
+                };
+                self.warnings.extend(warning);
+            }
+            _ => {}
+        }
+        walk_term(self, term)
+    }
+}
+
+/// Warn about calls to effectful host methods in `or` branches and about rule bodies and inline
+/// queries that make more than one effectful call, since reordering their evaluation would
+/// change which effects happen and when.
+pub fn check_effectful_call_ordering(kb: &KnowledgeBase) -> Vec<Diagnostic> {
+    let mut visitor = EffectfulCallVisitor {
+        kb,
+        disjunctions: 0,
+        previous: None,
+        warnings: vec![],
+    };
+    let bodies = kb
+        .get_rules()
+        .values()
+        .flat_map(|generic_rule| generic_rule.rules.values().map(|rule| &rule.body));
+    for body in bodies.chain(kb.inline_queries.iter().map(|query| &query.term)) {
+        visitor.previous = None;
+        visitor.visit_term(body);
+    }
+
+    let mut warnings = visitor
+        .warnings
+        .into_iter()
+        .map(PolarWarning::from)
+        .collect::<Vec<_>>();
+    warnings.sort_by_key(|warning| {
+        warning
+            .get_context()
+            .map(|context| (context.source.filename.clone(), context.left))
+    });
+    warnings.into_iter().map(Diagnostic::Warning).collect()
+}
+
+/// Rename non-constant variables to `_0`, `_1`, ... in order of appearance, so that
+/// alpha-equivalent rules become equal.
+struct AlphaRenamer<'kb> {
+    kb: &'kb KnowledgeBase,
+    names: HashMap<Symbol, Symbol>,
+}
+
+impl<'kb> Folder for AlphaRenamer<'kb> {
+    fn fold_variable(&mut self, v: Symbol) -> Symbol {
+        if self.kb.is_constant(&v) {
+            return fold_variable(v, self);
+        }
+        let next = Symbol::new(&format!("_{}", self.names.len()));
+        self.names.entry(v).or_insert(next).clone()
+    }
+}
+
+fn is_fact(rule: &Rule) -> bool {
+    matches!(rule.body.value(), Value::Expression(Operation { operator: Operator::And, args }) if args.is_empty())
+}
+
+/// Match `general` against `specific`, binding variables in `general` only.
+fn subsumes_term(
+    general: &Term,
+    specific: &Term,
+    bindings: &mut HashMap<Symbol, Value>,
+    kb: &KnowledgeBase,
+) -> bool {
+    match (general.value(), specific.value()) {
+        (Value::Variable(v), value) if !kb.is_constant(v) => match bindings.get(v) {
+            Some(bound) => bound == value,
+            None => {
+                bindings.insert(v.clone(), value.clone());
+                true
+            }
+        },
+        (Value::List(general), Value::List(specific)) => {
+            general.len() == specific.len()
+                && !general
+                    .iter()
+                    .chain(specific)
+                    .any(|t| matches!(t.value(), Value::RestVariable(_)))
+                && general
+                    .iter()
+                    .zip(specific)
+                    .all(|(g, s)| subsumes_term(g, s, bindings, kb))
+        }
+        (Value::Dictionary(general), Value::Dictionary(specific)) => {
+            general.fields.len() == specific.fields.len()
+                && general.fields.iter().all(|(k, g)| {
+                    matches!(specific.fields.get(k), Some(s) if subsumes_term(g, s, bindings, kb))
+                })
+        }
+        (general, specific) => general == specific,
+    }
+}
+
+/// Return true if every result of `specific` is also a result of the fact `general`.
+fn subsumes(general: &Rule, specific: &Rule, kb: &KnowledgeBase) -> bool {
+    let mut bindings = HashMap::new();
+    general.params.len() == specific.params.len()
+        && general.params.iter().zip(&specific.params).all(|(g, s)| {
+            (g.specializer.is_none() || g.specializer == s.specializer)
+                && subsumes_term(&g.parameter, &s.parameter, &mut bindings, kb)
+        })
+}
+
+/// Warn about rules that duplicate an earlier rule up to variable names, or that are subsumed by
+/// a fact with the same name, either of which produces duplicate results.
+pub fn check_redundant_rules(kb: &KnowledgeBase) -> Vec<Diagnostic> {
+    let mut warnings = vec![];
+    for generic_rule in kb.get_rules().values() {
+        let rules = generic_rule.sorted_rules();
+        let mut seen: HashMap<String, &Arc<Rule>> = HashMap::new();
+        let mut duplicates = HashSet::new();
+        for rule in &rules {
+            let mut renamer = AlphaRenamer {
+                kb,
+                names: HashMap::new(),
+            };
+            let key = renamer.fold_rule(rule.as_ref().clone()).to_string();
+            match seen.get(&key) {
+                Some(original) => {
+                    duplicates.insert(Arc::as_ptr(rule));
+                    warnings.push(ValidationWarning::DuplicateRule {
+                        rule: rule.as_ref().clone(),
+                        original: original.as_ref().clone(),
+                    });
+                }
+                None => {
+                    seen.insert(key, rule);
+                }
+            }
+        }
+
+        let general_facts = rules.iter().filter(|rule| {
+            is_fact(rule) && !rule.is_ground() && !duplicates.contains(&Arc::as_ptr(rule))
+        });
+        for fact in general_facts {
+            for rule in &rules {
+                if !Arc::ptr_eq(fact, rule)
+                    && !duplicates.contains(&Arc::as_ptr(rule))
+                    && subsumes(fact, rule, kb)
+                {
+                    warnings.push(ValidationWarning::SubsumedRule {
+                        rule: rule.as_ref().clone(),
+                        fact: fact.as_ref().clone(),
+                    });
+                }
+            }
+        }
+    }
+
+    warnings.sort_by_key(|warning| match warning {
+        ValidationWarning::DuplicateRule { rule, .. }
+        | ValidationWarning::SubsumedRule { rule, .. } => rule
+            .parsed_context()
+            .map(|context| (context.source.filename.clone(), context.left)),
+        _ => None,
+    });
+    warnings
+        .into_iter()
+        .map(|warning| Diagnostic::Warning(warning.into()))
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -304,6 +623,16 @@ mod tests {
             .contains("Call to undefined rule: no_such_rule(y)"));
     }
 
+    #[test]
+    fn test_builtin_predicate_is_not_undefined() {
+        let mut kb = KnowledgeBase::new();
+        kb.add_rule(
+            rule!("f", [sym!("x")] => call!("reachable", [sym!("x"), "edge", sym!("y"), 3])),
+        );
+        kb.add_rule(rule!("edge", [sym!("x"), sym!("y")]));
+        assert!(check_undefined_rule_calls(&kb).is_empty());
+    }
+
     #[test]
     fn test_undefined_rule_error_clean() {
         let mut kb = KnowledgeBase::new();
@@ -311,4 +640,36 @@ mod tests {
         kb.add_rule(rule!("defined_rule", [sym!("x")]));
         assert!(check_undefined_rule_calls(&kb).is_empty());
     }
+
+    #[test]
+    fn test_duplicate_rules() {
+        let mut kb = KnowledgeBase::new();
+        kb.add_rule(rule!("f", [sym!("x")] => call!("g", [sym!("x"), sym!("y")])));
+        kb.add_rule(rule!("f", [sym!("z")] => call!("g", [sym!("y"), sym!("z")])));
+        kb.add_rule(rule!("f", [sym!("a")] => call!("g", [sym!("a"), sym!("b")])));
+        let warnings = check_redundant_rules(&kb);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0]
+            .to_string()
+            .starts_with("Duplicate rule: f(a) if g(a, b);"));
+    }
+
+    #[test]
+    fn test_subsumed_rules() {
+        let mut kb = KnowledgeBase::new();
+        kb.add_rule(rule!("g", [sym!("x"), 1]));
+        kb.add_rule(rule!("g", [2, 1]));
+        kb.add_rule(rule!("g", [2, 2]));
+        kb.add_rule(rule!("g", [sym!("x"), sym!("y")] => call!("h", [sym!("x"), sym!("y")])));
+        kb.add_rule(rule!("g", [sym!("y"), 1] => call!("h", [sym!("y")])));
+        let warnings = check_redundant_rules(&kb)
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        assert_eq!(warnings.len(), 2, "{:#?}", warnings);
+        assert!(warnings.contains(&"Rule g(2, 1); is subsumed by the fact g(x, 1);".to_owned()));
+        assert!(
+            warnings.contains(&"Rule g(y, 1) if h(y); is subsumed by the fact g(x, 1);".to_owned())
+        );
+    }
 }
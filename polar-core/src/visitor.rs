@@ -27,6 +27,8 @@ pub trait Visitor: Sized {
     fn visit_number(&mut self, _n: &Numeric) {}
     fn visit_string(&mut self, _s: &str) {}
     fn visit_boolean(&mut self, _b: &bool) {}
+    fn visit_datetime(&mut self, _t: &DateTime) {}
+    fn visit_duration(&mut self, _d: &Duration) {}
     fn visit_instance_id(&mut self, _i: &u64) {}
     fn visit_symbol(&mut self, _s: &Symbol) {}
     fn visit_variable(&mut self, _v: &Symbol) {}
@@ -65,6 +67,9 @@ pub trait Visitor: Sized {
     fn visit_list(&mut self, l: &TermList) {
         walk_list(self, l)
     }
+    fn visit_set(&mut self, s: &Set) {
+        walk_set(self, s)
+    }
     fn visit_operation(&mut self, o: &Operation) {
         walk_operation(self, o)
     }
@@ -106,11 +111,14 @@ pub fn walk_term<V: Visitor>(visitor: &mut V, term: &Term) {
         Value::Number(n) => visitor.visit_number(n),
         Value::String(s) => visitor.visit_string(s),
         Value::Boolean(b) => visitor.visit_boolean(b),
+        Value::DateTime(t) => visitor.visit_datetime(t),
+        Value::Duration(d) => visitor.visit_duration(d),
         Value::ExternalInstance(e) => visitor.visit_external_instance(e),
         Value::Dictionary(d) => visitor.visit_dictionary(d),
         Value::Pattern(p) => visitor.visit_pattern(p),
         Value::Call(c) => visitor.visit_call(c),
         Value::List(l) => visitor.visit_list(l),
+        Value::Set(s) => visitor.visit_set(s),
         Value::Variable(v) => visitor.visit_variable(v),
         Value::RestVariable(r) => visitor.visit_rest_variable(r),
         Value::Expression(o) => visitor.visit_operation(o),
@@ -155,6 +163,10 @@ pub fn walk_list<V: Visitor>(visitor: &mut V, list: &TermList) {
     walk_elements!(visitor, visit_term, list);
 }
 
+pub fn walk_set<V: Visitor>(visitor: &mut V, set: &Set) {
+    walk_elements!(visitor, visit_term, set.iter());
+}
+
 pub fn walk_operation<V: Visitor>(visitor: &mut V, expr: &Operation) {
     visitor.visit_operator(&expr.operator);
     walk_elements!(visitor, visit_term, &expr.args);
@@ -186,7 +198,7 @@ mod tests {
 
     impl Visitor for TestVisitor {
         fn visit_number(&mut self, n: &Numeric) {
-            self.push(Value::Number(*n));
+            self.push(Value::Number(n.clone()));
         }
         fn visit_string(&mut self, s: &str) {
             self.push(Value::String(s.to_string()));
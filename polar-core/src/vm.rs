@@ -1,39 +1,127 @@
 use std::cell::RefCell;
-use std::collections::BTreeMap;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet};
 use std::collections::{HashMap, HashSet};
 use std::fmt::Write;
 use std::rc::Rc;
 use std::string::ToString;
-use std::sync::{Arc, RwLock, RwLockReadGuard};
+use std::sync::{Arc, Mutex};
 
+use serde::{Deserialize, Serialize};
+use strum_macros::IntoStaticStr;
 #[cfg(target_arch = "wasm32")]
 use wasm_bindgen::prelude::*;
 
+use crate::aggregate::Aggregator;
 use crate::bindings::{
     Binding, BindingManager, BindingStack, Bindings, Bsp, FollowerId, VariableState,
 };
+use crate::builtins::Builtin;
 use crate::counter::Counter;
 use crate::data_filtering::partition_equivs;
+use crate::debug_protocol::{DebugRequest, DebuggerEvent, StopReason};
 use crate::debugger::{get_binding_for_var, DebugEvent, Debugger};
-use crate::error::{invalid_state, unsupported, PolarError, PolarResult, RuntimeError};
+use crate::error::{invalid_state, unsupported, PolarError, PolarResult, QueryLimit, RuntimeError};
 use crate::events::*;
-use crate::folder::Folder;
+use crate::explain::Explainer;
+use crate::folder::{fold_term, Folder};
+use crate::formatting::{InstanceRepr, TermFormatter};
 use crate::inverter::Inverter;
 use crate::kb::*;
 use crate::messages::*;
+use crate::metrics::QueryMetrics;
+use crate::normalize::PartialForm;
 use crate::numerics::*;
-use crate::partial::{simplify_bindings_opt, simplify_partial, sub_this, IsaConstraintCheck};
+use crate::parser::INTERPOLATE;
+use crate::partial::{
+    hide_anonymous_vars, simplify_bindings_opt, simplify_partial, sub_this, IsaConstraintCheck,
+};
+use crate::reachable::{is_builtin_predicate, Reachable, REACHABLE};
 use crate::rewrites::Renamer;
 use crate::rules::*;
 use crate::runnable::Runnable;
+use crate::session::SessionFacts;
 use crate::sources::Context;
 use crate::terms::*;
+use crate::trace_spans::SpanRecorder;
 use crate::traces::*;
 use crate::visitor::{walk_term, Visitor};
 
 pub const MAX_STACK_SIZE: usize = 10_000;
 pub const DEFAULT_TIMEOUT_MS: u64 = 30_000;
 
+/// Limits on the work a query may do before it fails with `RuntimeError::BudgetExceeded`.
+/// `None` means unlimited. Work done by inverted and negated subqueries counts towards the
+/// limits of the query that spawned them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct QueryLimits {
+    pub max_goals: Option<u64>,
+    pub max_choice_points: Option<u64>,
+    /// Events the host must answer by calling into the application.
+    pub max_external_calls: Option<u64>,
+    /// Rules being evaluated at once, e.g., how deep a recursive rule may go.
+    pub max_rule_depth: Option<u64>,
+    /// Wall-clock time in milliseconds. Independent of the `POLAR_TIMEOUT_MS` timeout.
+    pub timeout_ms: Option<u64>,
+}
+
+/// Counts of the work done by a query, for checking its limits and reporting its metrics.
+#[derive(Debug, Default)]
+struct QueryUsage {
+    goals: u64,
+    choice_points: u64,
+    external_calls: u64,
+    backtracks: u64,
+    unifications: u64,
+    cache_hits: u64,
+}
+
+impl QueryUsage {
+    fn record_goal(&mut self) -> u64 {
+        self.goals += 1;
+        self.goals
+    }
+
+    fn record_choice_point(&mut self) -> u64 {
+        self.choice_points += 1;
+        self.choice_points
+    }
+
+    fn record_external_call(&mut self) -> u64 {
+        self.external_calls += 1;
+        self.external_calls
+    }
+
+    fn record_backtrack(&mut self) {
+        self.backtracks += 1;
+    }
+
+    fn record_unification(&mut self) {
+        self.unifications += 1;
+    }
+
+    fn record_cache_hit(&mut self) {
+        self.cache_hits += 1;
+    }
+}
+
+/// An external instance ID, the name of the attribute or method, and its arguments.
+type ExternalCallKey = (
+    u64,
+    Symbol,
+    Option<Vec<Term>>,
+    Option<BTreeMap<Symbol, Term>>,
+);
+
+/// Results of attribute lookups and method calls on external instances, so that repeating one
+/// within a query, e.g., on another branch, doesn't take another round trip to the host.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct ExternalCallMemo {
+    results: HashMap<ExternalCallKey, Term>,
+    /// Calls waiting on the host whose results will be memoized, by call ID.
+    pending: HashMap<u64, ExternalCallKey>,
+}
+
 #[derive(Debug, Copy, Clone, PartialOrd, PartialEq)]
 pub enum LogLevel {
     Trace,
@@ -47,7 +135,9 @@ impl LogLevel {
     }
 }
 
-#[derive(Debug, Clone)]
+/// Goals that share state with a runnable, or hold an error, can't be serialized, so queries
+/// can't be suspended while they're pending. See `PolarVirtualMachine::can_suspend`.
+#[derive(Debug, Clone, IntoStaticStr, Serialize, Deserialize)]
 #[must_use = "ignored goals are never accomplished"]
 #[allow(clippy::large_enum_variant)]
 pub enum Goal {
@@ -58,6 +148,11 @@ pub enum Goal {
     Debug {
         message: String,
     },
+    /// Report a structured debugger event to the host.
+    Debugger {
+        event: DebuggerEvent,
+    },
+    #[serde(skip)]
     Error {
         error: PolarError,
     },
@@ -99,6 +194,9 @@ pub enum Goal {
         call_id: u64,
         iterable: Term,
     },
+    ResolveConstant {
+        name: Symbol,
+    },
     CheckError,
     Noop,
     Query {
@@ -129,6 +227,7 @@ pub enum Goal {
     },
 
     /// Run the `runnable`.
+    #[serde(skip)]
     Run {
         runnable: Box<dyn Runnable>,
     },
@@ -140,12 +239,29 @@ pub enum Goal {
 
     /// TODO hack.
     /// Add a new constraint
+    #[serde(skip)]
     AddConstraintsBatch {
         add_constraints: Rc<RefCell<Bindings>>,
     },
+
+    /// Unify `target` with each node collected by a `Reachable` runnable.
+    #[serde(skip)]
+    UnifyReachable {
+        target: Term,
+        reachable: Rc<RefCell<TermList>>,
+    },
+
+    /// Aggregate the values collected by an `Aggregator` runnable and unify `result` with the
+    /// aggregate. `term` is the aggregate operation, tracked for errors.
+    #[serde(skip)]
+    UnifyAggregate {
+        term: Term,
+        values: Rc<RefCell<TermList>>,
+        result: Term,
+    },
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Choice {
     pub alternatives: Vec<GoalStack>,
     bsp: Bsp,              // binding stack pointer
@@ -153,6 +269,7 @@ pub struct Choice {
     queries: Queries,      // query stack snapshot
     trace: Vec<Rc<Trace>>, // trace snapshot
     trace_stack: TraceStack,
+    rule_attempts: Vec<Option<u64>>,
 }
 
 pub type Choices = Vec<Choice>;
@@ -160,7 +277,7 @@ pub type Choices = Vec<Choice>;
 pub type Goals = Vec<Goal>;
 pub type TraceStack = Vec<Rc<Vec<Rc<Trace>>>>;
 
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct GoalStack(Vec<Rc<Goal>>);
 
 impl GoalStack {
@@ -216,13 +333,41 @@ pub fn compare(
         (Number(l), Boolean(r)) => compare(op, l, &to_int(*r)),
         (Number(l), Number(r)) => compare(op, l, r),
         (String(l), String(r)) => compare(op, l, r),
-        _ => {
-            let context = context.expect("should only be None in Grounder, where we unwrap anyway");
-            unsupported(context.to_string(), context)
-        }
+        (DateTime(l), DateTime(r)) => compare(op, l, r),
+        (Duration(l), Duration(r)) => compare(op, l, r),
+        // Sets are ordered by inclusion, e.g., `{1} < {1, 2}`.
+        (Set(l), Set(r)) => compare(op, l, r),
+        _ => match context {
+            Some(context) => unsupported(context.to_string(), context),
+            None => invalid_state(format!("cannot compare {} {} {}", left, op, right)),
+        },
     }
 }
 
+/// Whether `field` calls a set method that the VM evaluates itself instead of asking the host.
+fn is_set_method(field: &Term) -> bool {
+    matches!(
+        field.value(),
+        Value::Call(Call { name, kwargs: None, .. })
+            if matches!(name.as_str(), "union" | "intersect" | "difference")
+    )
+}
+
+/// Whether `field` calls a string method that the VM evaluates itself instead of asking the host:
+/// the one interpolated strings are parsed into, and the native ones if they're enabled.
+fn is_string_method(field: &Term, native_string_methods: bool) -> bool {
+    matches!(
+        field.value(),
+        Value::Call(Call { name, kwargs: None, .. })
+            if name.as_str() == INTERPOLATE
+                || native_string_methods
+                    && matches!(
+                        name.as_str(),
+                        "lower" | "upper" | "contains" | "starts_with" | "ends_with" | "split" | "join"
+                    )
+    )
+}
+
 #[derive(Clone)]
 pub struct PolarVirtualMachine {
     /// Stacks.
@@ -232,33 +377,69 @@ pub struct PolarVirtualMachine {
     pub queries: Queries,
 
     pub tracing: bool,
+    /// Rules whose traces are included in results, if not all of them.
+    pub trace_filter: Option<TraceFilter>,
+    /// Records rule traversal and costs for `Query::explain`.
+    pub(crate) explainer: Option<Rc<RefCell<Explainer>>>,
+    /// Records rule attempts as spans for `Query::record_spans`.
+    pub(crate) span_recorder: Option<Rc<RefCell<SpanRecorder>>>,
+    /// Which VM sharing the span recorder this is.
+    span_owner: u64,
     pub trace_stack: TraceStack, // Stack of traces higher up the tree.
     pub trace: Vec<Rc<Trace>>,   // Traces for the current level of the trace tree.
+    /// The rules being evaluated, innermost last, with their explain attempt IDs if the query is
+    /// being explained. Its length is the rule depth. Restored with the trace stack on
+    /// backtracking.
+    rule_attempts: Vec<Option<u64>>,
 
     // Errors from outside the vm.
     pub external_error: Option<String>,
 
-    #[cfg(not(target_arch = "wasm32"))]
-    query_start_time: Option<std::time::Instant>,
-    #[cfg(target_arch = "wasm32")]
-    query_start_time: Option<f64>,
+    query_start_time: Option<Timestamp>,
     query_timeout_ms: u64,
 
     /// Maximum size of goal stack
     stack_limit: usize,
 
+    /// Limits on the work done by the query, set with `Polar::set_query_limits`.
+    pub limits: QueryLimits,
+    /// Work done so far, shared with the VMs this one spawns.
+    usage: Rc<RefCell<QueryUsage>>,
+    /// Results of external calls, shared with the VMs this one spawns.
+    pub(crate) external_call_memo: Rc<RefCell<ExternalCallMemo>>,
+    /// The counts by goal type and by rule for `Query::metrics`, if enabled with
+    /// `Query::detailed_metrics`. Shared with the VMs this one spawns.
+    pub(crate) detailed_metrics: Option<Rc<RefCell<QueryMetrics>>>,
+    /// Totals of the `Polar` instance that started the query, which the query's metrics are
+    /// added to when it's dropped.
+    pub(crate) metrics_totals: Option<Arc<Mutex<QueryMetrics>>>,
+
     /// Binding stack constant below here.
     csp: Bsp,
 
     /// Interactive debugger.
     pub debugger: Debugger,
+    /// Set while re-raising an error the debugger stopped on, so that it doesn't stop again.
+    reraising_error: bool,
 
     /// Rules and types.
-    pub kb: Arc<RwLock<KnowledgeBase>>,
+    pub kb: Arc<KnowledgeBase>,
+
+    /// Numbers the temporary variables the VM creates. Shared with the KB unless the query runs
+    /// in deterministic mode.
+    pub gensym_counter: Counter,
 
     /// Call ID -> result variable name table.
     call_id_symbols: HashMap<u64, Symbol>,
 
+    /// Lazy constants the query hasn't been given a value for yet.
+    unresolved_constants: BTreeSet<Symbol>,
+    /// Lazy constants given a value while the query ran. They're substituted into goals instead
+    /// of being bound, since bindings made mid-query are undone on backtracking.
+    resolved_constants: Bindings,
+    /// Call ID -> lazy constant table for `ExternalConstant` events.
+    constant_calls: HashMap<u64, Symbol>,
+
     /// Logging flag.
     log_level: Option<LogLevel>,
 
@@ -269,6 +450,30 @@ pub struct PolarVirtualMachine {
     pub query_contains_partial: bool,
     pub inverting: bool,
 
+    /// Emit a warning message when a branch is abandoned because it re-derives one of its own
+    /// ancestor goals.
+    pub warn_on_cycles: bool,
+
+    /// Evaluate the built-in string methods instead of looking them up on the host. See
+    /// `Polar::set_native_string_methods`.
+    pub native_string_methods: bool,
+
+    /// Uncommitted facts of the session this query belongs to.
+    pub session_facts: Option<Arc<SessionFacts>>,
+    /// Rules of the namespace this query runs in, layered over the KB's.
+    pub namespace: Option<Arc<KnowledgeBase>>,
+
+    /// The results yielded so far, if the query only yields distinct results. See
+    /// `QueryFlags::DISTINCT`.
+    pub(crate) seen_results: Option<DistinctResults>,
+    /// The shape of the constraints in partial results.
+    pub(crate) partial_form: PartialForm,
+    /// Yield each value an aggregate collects. See `QueryFlags::STREAM_AGGREGATES`.
+    pub(crate) stream_aggregates: bool,
+
+    /// Formats terms in logs, traces and error stack traces.
+    pub term_formatter: TermFormatter,
+
     /// Output messages.
     pub messages: MessageQueue,
 }
@@ -276,7 +481,7 @@ pub struct PolarVirtualMachine {
 impl Default for PolarVirtualMachine {
     fn default() -> Self {
         PolarVirtualMachine::new(
-            Arc::new(RwLock::new(KnowledgeBase::default())),
+            Arc::new(KnowledgeBase::default()),
             false,
             vec![],
             // Messages will not be exposed, only use default() for testing.
@@ -292,12 +497,96 @@ extern "C" {
     fn console_error(a: &str);
 }
 
+// `std::time::Instant::now` panics on `wasm32-unknown-unknown`, so WASM builds time queries
+// with the JS host's clock instead.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) type Timestamp = std::time::Instant;
+#[cfg(target_arch = "wasm32")]
+pub(crate) type Timestamp = f64;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn now() -> Timestamp {
+    std::time::Instant::now()
+}
+
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn now() -> Timestamp {
+    js_sys::Date::now()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn millis_since(start: Timestamp) -> u64 {
+    start.elapsed().as_millis() as u64
+}
+
+#[cfg(target_arch = "wasm32")]
+fn millis_since(start: Timestamp) -> u64 {
+    (now() - start) as u64
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn micros_since(start: Timestamp) -> u64 {
+    start.elapsed().as_micros() as u64
+}
+
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn micros_since(start: Timestamp) -> u64 {
+    ((now() - start) * 1000.0) as u64
+}
+
+/// The list a parameter destructures, from its specializer or from the parameter itself, e.g.,
+/// `[first, *rest]` in `f(_: [first, *rest])` or in `f([first, *rest])`.
+fn list_pattern(param: &Parameter) -> Option<&TermList> {
+    match param.specializer.as_ref().map(Term::value) {
+        Some(Value::List(list)) => Some(list),
+        Some(_) => None,
+        None => match param.parameter.value() {
+            Value::List(list) => Some(list),
+            _ => None,
+        },
+    }
+}
+
+/// Compare how specific two list patterns are. A pattern with more elements before its rest
+/// variable is more specific, then one without a rest variable, then the one with a value where
+/// the other first has a variable.
+fn compare_list_patterns(left: &TermList, right: &TermList) -> Ordering {
+    let shape = |list: &TermList| {
+        let rest = has_rest_var(list);
+        (list.len() - usize::from(rest), !rest)
+    };
+    let is_value = |term: &Term| !matches!(term.value(), Value::Variable(_));
+    shape(left).cmp(&shape(right)).then_with(|| {
+        left.iter()
+            .zip(right)
+            .map(|(l, r)| is_value(l).cmp(&is_value(r)))
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or(Ordering::Equal)
+    })
+}
+
+/// Replace the variables in `term` that name constants in `values` with their values.
+fn substitute_constants(term: &Term, values: &Bindings) -> Term {
+    struct Substituter<'a>(&'a Bindings);
+
+    impl Folder for Substituter<'_> {
+        fn fold_term(&mut self, t: Term) -> Term {
+            match t.value() {
+                Value::Variable(v) => self.0.get(v).cloned().unwrap_or(t),
+                _ => fold_term(t, self),
+            }
+        }
+    }
+
+    Substituter(values).fold_term(term.clone())
+}
+
 // Methods which aren't goals/instructions.
 impl PolarVirtualMachine {
     /// Make a new virtual machine with an initial list of goals.
     /// Reverse the goal list for the sanity of callers.
     pub fn new(
-        kb: Arc<RwLock<KnowledgeBase>>,
+        kb: Arc<KnowledgeBase>,
         tracing: bool,
         goals: Goals,
         messages: MessageQueue,
@@ -306,11 +595,9 @@ impl PolarVirtualMachine {
             .ok()
             .and_then(|timeout_str| timeout_str.parse::<u64>().ok())
             .unwrap_or(DEFAULT_TIMEOUT_MS);
-        let constants = kb
-            .read()
-            .expect("cannot acquire KB read lock")
-            .get_registered_constants()
-            .clone();
+        let constants = kb.get_registered_constants().clone();
+        let unresolved_constants = kb.lazy_constants().clone();
+        let gensym_counter = kb.gensym_counter();
 
         let mut vm = Self {
             goals: GoalStack::new_reversed(goals),
@@ -318,16 +605,31 @@ impl PolarVirtualMachine {
             query_start_time: None,
             query_timeout_ms,
             stack_limit: MAX_STACK_SIZE,
+            limits: QueryLimits::default(),
+            usage: Rc::new(RefCell::new(QueryUsage::default())),
+            external_call_memo: Rc::new(RefCell::new(ExternalCallMemo::default())),
+            detailed_metrics: None,
+            metrics_totals: None,
             csp: Bsp::default(),
             choices: vec![],
             queries: vec![],
             tracing,
+            trace_filter: None,
+            explainer: None,
+            span_recorder: None,
+            span_owner: 0,
             trace_stack: vec![],
             trace: vec![],
+            rule_attempts: vec![],
             external_error: None,
             debugger: Debugger::default(),
+            reraising_error: false,
             kb,
+            gensym_counter,
             call_id_symbols: HashMap::new(),
+            unresolved_constants,
+            resolved_constants: Bindings::new(),
+            constant_calls: HashMap::new(),
             // `log` controls internal VM logging
             log_level: None,
             // `polar_log_stderr` prints things immediately to stderr
@@ -335,6 +637,14 @@ impl PolarVirtualMachine {
             polar_trace_mute: false,
             query_contains_partial: false,
             inverting: false,
+            warn_on_cycles: false,
+            native_string_methods: false,
+            session_facts: None,
+            namespace: None,
+            seen_results: None,
+            stream_aggregates: false,
+            partial_form: PartialForm::default(),
+            term_formatter: TermFormatter::default(),
             messages,
         };
         vm.bind_constants(constants);
@@ -407,32 +717,137 @@ impl PolarVirtualMachine {
     }
 
     #[cfg(test)]
-    pub fn new_test(kb: Arc<RwLock<KnowledgeBase>>, tracing: bool, goals: Goals) -> Self {
+    pub fn new_test(kb: Arc<KnowledgeBase>, tracing: bool, goals: Goals) -> Self {
         PolarVirtualMachine::new(kb, tracing, goals, MessageQueue::new())
     }
 
     /// Clone self, replacing the goal stack and retaining only the current bindings.
     pub fn clone_with_goals(&self, goals: Goals) -> Self {
         let mut vm = Self::new(self.kb.clone(), self.tracing, goals, self.messages.clone());
+        vm.gensym_counter = self.gensym_counter.clone();
         vm.binding_manager.clone_from(&self.binding_manager);
         vm.query_contains_partial = self.query_contains_partial;
+        vm.warn_on_cycles = self.warn_on_cycles;
+        vm.native_string_methods = self.native_string_methods;
+        vm.session_facts = self.session_facts.clone();
+        vm.namespace = self.namespace.clone();
+        vm.term_formatter = self.term_formatter.clone();
+        vm.trace_filter = self.trace_filter.clone();
+        vm.stream_aggregates = self.stream_aggregates;
+        vm.explainer = self.explainer.clone();
+        if let Some(recorder) = &self.span_recorder {
+            let parent = self.current_span();
+            vm.span_owner = recorder.borrow_mut().spawn(parent);
+            vm.span_recorder = Some(recorder.clone());
+        }
         vm.debugger = self.debugger.clone();
+        vm.limits = self.limits;
+        vm.usage = self.usage.clone();
+        vm.external_call_memo = self.external_call_memo.clone();
+        vm.detailed_metrics = self.detailed_metrics.clone();
+        // The copied bindings only hold the constants resolved before this query started.
+        vm.unresolved_constants = self.unresolved_constants.clone();
+        vm.resolved_constants = self.resolved_constants.clone();
         vm
     }
 
+    /// Record something in the explain report, if the query is being explained.
+    fn explain<F: FnOnce(&mut Explainer)>(&self, f: F) {
+        if let Some(explainer) = &self.explainer {
+            f(&mut explainer.borrow_mut())
+        }
+    }
+
+    /// Update the query's detailed metrics, if it's counting them.
+    fn count<F: FnOnce(&mut QueryMetrics)>(&self, f: F) {
+        if let Some(metrics) = &self.detailed_metrics {
+            f(&mut metrics.borrow_mut())
+        }
+    }
+
+    /// Counts of the work the query has done so far.
+    pub(crate) fn metrics(&self) -> QueryMetrics {
+        let usage = self.usage.borrow();
+        let detailed = self
+            .detailed_metrics
+            .as_ref()
+            .map(|metrics| metrics.borrow().clone())
+            .unwrap_or_default();
+        QueryMetrics {
+            queries: 1,
+            goals: usage.goals,
+            choice_points: usage.choice_points,
+            unifications: usage.unifications,
+            backtracks: usage.backtracks,
+            external_calls: usage.external_calls,
+            cache_hits: usage.cache_hits,
+            ..detailed
+        }
+    }
+
+    /// The name of the innermost rule being evaluated, if any.
+    fn current_rule_name(&self) -> Option<Symbol> {
+        self.trace_stack
+            .iter()
+            .rev()
+            .find_map(|level| match level.last().map(|t| &t.node) {
+                Some(Node::Rule(rule)) => Some(rule.name.clone()),
+                _ => None,
+            })
+    }
+
+    /// Record something about rule attempts, if the query is recording spans.
+    fn record_spans<F: FnOnce(&mut SpanRecorder)>(&self, f: F) {
+        if let Some(recorder) = &self.span_recorder {
+            f(&mut recorder.borrow_mut())
+        }
+    }
+
+    /// The span of the innermost rule attempt being evaluated.
+    fn current_span(&self) -> Option<u64> {
+        let recorder = self.span_recorder.as_ref()?;
+        let levels = self.trace_stack.iter().map(|level| level.as_ref());
+        let traces = std::iter::once(&self.trace)
+            .chain(levels.rev())
+            .flat_map(|level| level.iter().rev());
+        recorder.borrow().innermost(self.span_owner, traces)
+    }
+
+    /// Whether a finished trace node is recorded in full: always, unless there's a trace filter
+    /// and neither the node nor any rule it's nested in matches it.
+    fn records_trace(&self, trace: &Trace) -> bool {
+        match &self.trace_filter {
+            Some(filter) => {
+                filter.matches_trace(trace)
+                    || self
+                        .trace_stack
+                        .iter()
+                        .any(|level| level.last().is_some_and(|t| filter.matches_trace(t)))
+            }
+            None => true,
+        }
+    }
+
     #[cfg(test)]
     fn set_stack_limit(&mut self, limit: usize) {
         self.stack_limit = limit;
     }
 
-    fn kb(&self) -> RwLockReadGuard<'_, KnowledgeBase> {
-        self.kb.read().unwrap()
+    /// The snapshot of the KB this query runs against. Loads publish new snapshots without
+    /// affecting queries that are already running.
+    fn kb(&self) -> &KnowledgeBase {
+        &self.kb
     }
 
     fn new_id(&self) -> u64 {
         self.kb().new_id()
     }
 
+    /// Generate a new symbol for a temporary variable.
+    pub(crate) fn gensym(&self, prefix: &str) -> Symbol {
+        gensym_from(&self.gensym_counter, prefix)
+    }
+
     pub fn id_counter(&self) -> Counter {
         self.kb().id_counter()
     }
@@ -443,17 +858,18 @@ impl PolarVirtualMachine {
         call_id
     }
 
-    fn new_call_var(&mut self, var_prefix: &str, initial_value: Value) -> (u64, Term) {
-        let sym = self.kb().gensym(var_prefix);
-        self.bind(&sym, Term::from(initial_value)).unwrap();
+    fn new_call_var(&mut self, var_prefix: &str, initial_value: Value) -> PolarResult<(u64, Term)> {
+        let sym = self.gensym(var_prefix);
+        self.bind(&sym, Term::from(initial_value))?;
         let call_id = self.new_call_id(&sym);
-        (call_id, Term::from(sym))
+        Ok((call_id, Term::from(sym)))
     }
 
-    fn get_call_sym(&self, call_id: u64) -> &Symbol {
-        self.call_id_symbols
-            .get(&call_id)
-            .expect("unregistered external call ID")
+    fn get_call_sym(&self, call_id: u64) -> PolarResult<&Symbol> {
+        self.call_id_symbols.get(&call_id).map_or_else(
+            || invalid_state(format!("unregistered external call ID {}", call_id)),
+            Ok,
+        )
     }
 
     /// Try to achieve one goal. Return `Some(QueryEvent)` if an external
@@ -462,13 +878,27 @@ impl PolarVirtualMachine {
         self.log(LogLevel::Trace, || goal.to_string(), &[]);
 
         self.check_timeout()?;
+        self.check_goal_budget()?;
+        self.count(|m| m.goal(&goal));
+
+        if let Some(name) = self.unresolved_constant(&goal) {
+            // Try the goal again once the application has supplied the constant.
+            self.goals.push(goal);
+            self.push_goal(Goal::ResolveConstant { name })?;
+            return Ok(QueryEvent::None);
+        }
+        let goal = self.substitute_resolved_constants(goal);
 
         match goal.as_ref() {
             Goal::Backtrack => self.backtrack()?,
             Goal::Cut { choice_index } => self.cut(*choice_index),
             Goal::Debug { message } => return Ok(self.debug(message)),
+            Goal::Debugger { event } => return Ok(self.debugger_event(event)),
             Goal::Halt => return Ok(self.halt()),
-            Goal::Error { error } => return Err(error.clone()),
+            Goal::Error { error } => {
+                self.reraising_error = true;
+                return Err(error.clone());
+            }
             Goal::Isa { left, right } => self.isa(left, right)?,
             Goal::IsMoreSpecific { left, right, args } => {
                 self.is_more_specific(left, right, args)?
@@ -493,6 +923,7 @@ impl PolarVirtualMachine {
             Goal::NextExternal { call_id, iterable } => {
                 return self.next_external(*call_id, iterable)
             }
+            Goal::ResolveConstant { name } => return Ok(self.resolve_constant(name)),
             Goal::CheckError => return self.check_error(),
             Goal::Noop => {}
             Goal::Query { term } => {
@@ -518,18 +949,62 @@ impl PolarVirtualMachine {
             }
             Goal::TraceStackPop => {
                 let mut children = self.trace.clone();
-                self.trace = self.trace_stack.pop().unwrap().as_ref().clone();
-                let mut trace = self.trace.pop().unwrap();
-                let trace = Rc::make_mut(&mut trace);
-                trace.children.append(&mut children);
-                self.trace.push(Rc::new(trace.clone()));
+                self.trace = match self.trace_stack.pop() {
+                    Some(trace) => trace.as_ref().clone(),
+                    None => return invalid_state("trace stack underflow"),
+                };
+                let mut trace = match self.trace.pop() {
+                    Some(trace) => trace,
+                    None => return invalid_state("no trace to pop"),
+                };
+                if let Node::Rule(rule) = &trace.node {
+                    if let Some(Some(attempt)) = self.rule_attempts.pop() {
+                        self.explain(|e| e.rule_succeeded(attempt, rule));
+                    }
+                }
+                self.record_spans(|r| {
+                    r.rule_succeeded(&trace, |arg| self.binding_manager.deep_deref(arg))
+                });
+                // Outside of the rules matching the trace filter, only keep those rules, and
+                // only keep the node itself if it's the root.
+                let records = self.records_trace(&trace);
+                if !records {
+                    children.retain(|child| self.records_trace(child));
+                }
+                if records || self.trace_stack.is_empty() {
+                    let trace = Rc::make_mut(&mut trace);
+                    trace.children.append(&mut children);
+                    self.trace.push(Rc::new(trace.clone()));
+                } else {
+                    self.trace.append(&mut children);
+                }
                 self.maybe_break(DebugEvent::Pop)?;
             }
             Goal::TraceRule { trace } => {
                 if let Node::Rule(rule) = &trace.node {
                     self.log(LogLevel::Info, || format!("RULE: {}", rule), &[]);
+                    self.count(|m| m.rule_attempt(&rule.name));
+                    let attempt = self
+                        .explainer
+                        .as_ref()
+                        .map(|explainer| explainer.borrow_mut().rule_tried(rule));
+                    self.rule_attempts.push(attempt);
+                }
+                if self.span_recorder.is_some() {
+                    let parent = self.current_span();
+                    let args = match self.queries.last().map(Term::value) {
+                        Some(Value::Call(call)) => call
+                            .args
+                            .iter()
+                            .map(|arg| self.binding_manager.deep_deref(arg))
+                            .collect(),
+                        _ => vec![],
+                    };
+                    let owner = self.span_owner;
+                    self.record_spans(|r| r.rule_tried(owner, trace, parent, args));
                 }
                 self.trace.push(trace.clone());
+                self.check_rule_depth()?;
                 self.maybe_break(DebugEvent::Rule)?;
             }
             Goal::Unify { left, right } => self.unify(left, right)?,
@@ -540,6 +1015,30 @@ impl PolarVirtualMachine {
                     .drain()
                     .try_for_each(|(_, constraint)| self.add_constraint(&constraint))?
             }
+            Goal::UnifyReachable { target, reachable } => {
+                let nodes = reachable.borrow_mut().drain(..).collect::<Vec<_>>();
+                self.choose(nodes.into_iter().map(|node| {
+                    vec![Goal::Unify {
+                        left: target.clone(),
+                        right: node,
+                    }]
+                }))?
+            }
+            Goal::UnifyAggregate {
+                term,
+                values,
+                result,
+            } => {
+                let values = values.borrow_mut().drain(..).collect::<Vec<_>>();
+                match self.aggregate(term, values)? {
+                    Some(value) => self.push_goal(Goal::Unify {
+                        left: result.clone(),
+                        right: value,
+                    })?,
+                    // `min` and `max` of nothing fail.
+                    None => self.push_goal(Goal::Backtrack)?,
+                }
+            }
             Goal::Run { runnable } => return self.run_runnable(runnable.clone_runnable()),
         }
         Ok(QueryEvent::None)
@@ -551,7 +1050,7 @@ impl PolarVirtualMachine {
         if self.goals.len() >= self.stack_limit {
             let msg = format!("Goal stack overflow! MAX_GOALS = {}", self.stack_limit);
             Err(RuntimeError::StackOverflow { msg }.into())
-        } else if matches!(goal, LookupExternal { call_id, ..} | NextExternal { call_id, .. } if self.variable_state(self.get_call_sym(call_id)) != Unbound)
+        } else if matches!(goal, LookupExternal { call_id, ..} | NextExternal { call_id, .. } if !matches!(self.get_call_sym(call_id), Ok(sym) if self.variable_state(sym) == Unbound))
         {
             invalid_state("The call_id result variables for LookupExternal and NextExternal goals must be unbound.")
         } else {
@@ -585,6 +1084,12 @@ impl PolarVirtualMachine {
             let msg = "Too many choices.".to_owned();
             Err(RuntimeError::StackOverflow { msg }.into())
         } else {
+            let choice_points = self.usage.borrow_mut().record_choice_point();
+            self.check_limit(
+                QueryLimit::ChoicePoints,
+                self.limits.max_choice_points,
+                choice_points,
+            )?;
             self.choices.push(Choice {
                 alternatives,
                 bsp: self.bsp(),
@@ -592,6 +1097,7 @@ impl PolarVirtualMachine {
                 queries: self.queries.clone(),
                 trace: self.trace.clone(),
                 trace_stack: self.trace_stack.clone(),
+                rule_attempts: self.rule_attempts.clone(),
             });
             Ok(())
         }
@@ -665,7 +1171,10 @@ impl PolarVirtualMachine {
     pub fn bind(&mut self, var: &Symbol, val: Term) -> PolarResult<()> {
         self.log(
             LogLevel::Trace,
-            || format!("⇒ bind: {} ← {}", var, val),
+            || {
+                let val = self.term_formatter.to_polar_string(&val);
+                format!("⇒ bind: {} ← {}", var, val)
+            },
             &[],
         );
         if let Some(goal) = self.binding_manager.bind(var, val)? {
@@ -695,12 +1204,85 @@ impl PolarVirtualMachine {
         self.binding_manager.add_constraint(term)
     }
 
+    /// The terms of `goal` that lazy constants are substituted into.
+    fn constant_terms(goal: &Goal) -> Vec<&Term> {
+        match goal {
+            Goal::Query { term } => vec![term],
+            Goal::Unify { left, right } | Goal::Isa { left, right } => vec![left, right],
+            _ => vec![],
+        }
+    }
+
+    /// A lazy constant used by `goal` that the query doesn't have a value for yet.
+    fn unresolved_constant(&self, goal: &Goal) -> Option<Symbol> {
+        if self.unresolved_constants.is_empty() {
+            return None;
+        }
+        let terms = Self::constant_terms(goal);
+        self.unresolved_constants
+            .iter()
+            .find(|name| terms.iter().any(|term| term.contains_variable(name)))
+            .cloned()
+    }
+
+    fn substitute_resolved_constants(&self, goal: Rc<Goal>) -> Rc<Goal> {
+        if self.resolved_constants.is_empty() || Self::constant_terms(&goal).is_empty() {
+            return goal;
+        }
+        let substitute = |term: &Term| substitute_constants(term, &self.resolved_constants);
+        Rc::new(match goal.as_ref() {
+            Goal::Query { term } => Goal::Query {
+                term: substitute(term),
+            },
+            Goal::Unify { left, right } => Goal::Unify {
+                left: substitute(left),
+                right: substitute(right),
+            },
+            Goal::Isa { left, right } => Goal::Isa {
+                left: substitute(left),
+                right: substitute(right),
+            },
+            _ => return goal,
+        })
+    }
+
+    /// Ask the application for the value of a lazy constant, unless another query has already
+    /// cached it in the KB.
+    fn resolve_constant(&mut self, name: &Symbol) -> QueryEvent {
+        let cached = self.kb().lazy_constant_value(name);
+        if let Some(value) = cached {
+            self.usage.borrow_mut().record_cache_hit();
+            self.unresolved_constants.remove(name);
+            self.resolved_constants.insert(name.clone(), value);
+            return QueryEvent::None;
+        }
+        let call_id = self.new_id();
+        self.constant_calls.insert(call_id, name.clone());
+        QueryEvent::ExternalConstant {
+            call_id,
+            name: name.clone(),
+        }
+    }
+
+    /// Handle the application's answer to an `ExternalConstant` event.
+    fn constant_result(&mut self, name: Symbol, value: Option<Term>) -> PolarResult<()> {
+        let value = match value {
+            Some(value) => value,
+            None => return Err(RuntimeError::UnresolvedConstant { name }.into()),
+        };
+        self.kb.resolve_lazy_constant(name.clone(), value.clone());
+        self.unresolved_constants.remove(&name);
+        self.resolved_constants.insert(name, value);
+        Ok(())
+    }
+
     /// Augment the bindings stack with constants from a hash map.
     /// There must be no temporaries bound yet.
     fn bind_constants(&mut self, bindings: Bindings) {
-        assert_eq!(self.bsp(), self.csp);
-        for (var, value) in bindings.iter() {
-            self.bind(var, value.clone()).unwrap();
+        debug_assert_eq!(self.bsp(), self.csp);
+        for (var, value) in bindings {
+            // Constants are ground and bound to fresh variables, which can't fail.
+            self.binding_manager.unsafe_rebind(&var, value);
         }
         self.csp = self.bsp();
     }
@@ -747,8 +1329,8 @@ impl PolarVirtualMachine {
 
     /// Generate a fresh set of variables for a rule.
     fn rename_rule_vars(&self, rule: &Rule) -> Rule {
-        let kb = &*self.kb.read().unwrap();
-        let mut renamer = Renamer::new(kb);
+        let kb = self.kb();
+        let mut renamer = Renamer::new(kb, self.gensym_counter.clone());
         renamer.fold_rule(rule.clone())
     }
 
@@ -803,7 +1385,13 @@ impl PolarVirtualMachine {
                             ", BINDINGS: {{{}}}",
                             relevant_bindings
                                 .iter()
-                                .map(|(var, val)| format!("{} => {}", var.0, val))
+                                .map(|(var, val)| {
+                                    format!(
+                                        "{} => {}",
+                                        var.as_str(),
+                                        self.term_formatter.to_polar_string(val)
+                                    )
+                                })
                                 .collect::<Vec<String>>()
                                 .join(", ")
                         ));
@@ -869,18 +1457,8 @@ impl PolarVirtualMachine {
         st
     }
 
-    #[cfg(not(target_arch = "wasm32"))]
     fn query_duration(&self) -> u64 {
-        let now = std::time::Instant::now();
-        let start = self.query_start_time.expect("Query start not recorded");
-        (now - start).as_millis() as u64
-    }
-
-    #[cfg(target_arch = "wasm32")]
-    fn query_duration(&self) -> u64 {
-        let now: f64 = js_sys::Date::now();
-        let start = self.query_start_time.expect("Query start not recorded");
-        (now - start) as u64
+        self.query_start_time.map_or(0, millis_since)
     }
 
     fn is_query_timeout_disabled(&self) -> bool {
@@ -900,6 +1478,234 @@ impl PolarVirtualMachine {
         }
         Ok(())
     }
+
+    /// Count a goal against the query's budget, and check the budgeted timeout.
+    fn check_goal_budget(&self) -> PolarResult<()> {
+        let goals = self.usage.borrow_mut().record_goal();
+        self.check_limit(QueryLimit::Goals, self.limits.max_goals, goals)?;
+        if self.limits.timeout_ms.is_some() {
+            let elapsed = self.query_duration();
+            self.check_limit(QueryLimit::Timeout, self.limits.timeout_ms, elapsed)?;
+        }
+        Ok(())
+    }
+
+    /// Check the number of rules being evaluated before entering another one.
+    fn check_rule_depth(&self) -> PolarResult<()> {
+        if self.limits.max_rule_depth.is_none() {
+            return Ok(());
+        }
+        // Includes the rule just tried.
+        let depth = self.rule_attempts.len() as u64;
+        self.check_limit(QueryLimit::RuleDepth, self.limits.max_rule_depth, depth)
+    }
+
+    fn check_limit(&self, limit: QueryLimit, max: Option<u64>, used: u64) -> PolarResult<()> {
+        match max {
+            Some(max) if used > max => Err(RuntimeError::BudgetExceeded {
+                limit,
+                max,
+                stack_trace: self.stack_trace(),
+            }
+            .into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The stacks of a suspended VM, which share goals and traces through `Rc`s.
+#[cfg(feature = "suspend")]
+#[derive(Serialize, Deserialize)]
+struct Stacks {
+    goals: GoalStack,
+    choices: Choices,
+    trace: Vec<Rc<Trace>>,
+    trace_stack: TraceStack,
+    rule_attempts: Vec<Option<u64>>,
+}
+
+/// A VM that can be sent to another thread, with its stacks serialized.
+#[cfg(feature = "suspend")]
+pub(crate) struct SuspendedVm {
+    stacks: Vec<u8>,
+    binding_manager: BindingManager,
+    queries: Queries,
+    tracing: bool,
+    trace_filter: Option<TraceFilter>,
+    span_owner: u64,
+    external_error: Option<String>,
+    query_start_time: Option<Timestamp>,
+    query_timeout_ms: u64,
+    stack_limit: usize,
+    limits: QueryLimits,
+    usage: QueryUsage,
+    external_call_memo: ExternalCallMemo,
+    detailed_metrics: Option<QueryMetrics>,
+    metrics_totals: Option<Arc<Mutex<QueryMetrics>>>,
+    csp: Bsp,
+    debugger: Debugger,
+    reraising_error: bool,
+    kb: Arc<KnowledgeBase>,
+    gensym_counter: Counter,
+    call_id_symbols: HashMap<u64, Symbol>,
+    unresolved_constants: BTreeSet<Symbol>,
+    resolved_constants: Bindings,
+    constant_calls: HashMap<u64, Symbol>,
+    log_level: Option<LogLevel>,
+    polar_log_stderr: bool,
+    polar_trace_mute: bool,
+    query_contains_partial: bool,
+    inverting: bool,
+    warn_on_cycles: bool,
+    native_string_methods: bool,
+    session_facts: Option<Arc<SessionFacts>>,
+    namespace: Option<Arc<KnowledgeBase>>,
+    seen_results: Option<DistinctResults>,
+    stream_aggregates: bool,
+    partial_form: PartialForm,
+    term_formatter: TermFormatter,
+    messages: MessageQueue,
+}
+
+#[cfg(feature = "suspend")]
+impl PolarVirtualMachine {
+    /// Whether the VM can be suspended. It can't while it records explanations or spans, which
+    /// the host reads from the running query, or while a goal waits on a runnable, e.g., for a
+    /// negation or aggregate.
+    pub(crate) fn can_suspend(&self) -> bool {
+        fn serializable(goals: &GoalStack) -> bool {
+            goals.iter().all(|goal| {
+                !matches!(
+                    **goal,
+                    Goal::Error { .. }
+                        | Goal::Run { .. }
+                        | Goal::AddConstraintsBatch { .. }
+                        | Goal::UnifyReachable { .. }
+                        | Goal::UnifyAggregate { .. }
+                )
+            })
+        }
+        self.explainer.is_none()
+            && self.span_recorder.is_none()
+            && serializable(&self.goals)
+            && self.choices.iter().all(|choice| {
+                serializable(&choice.goals) && choice.alternatives.iter().all(serializable)
+            })
+    }
+
+    /// Serialize the stacks and move everything else as is. Check `can_suspend` first.
+    pub(crate) fn suspend(self) -> PolarResult<SuspendedVm> {
+        let stacks = Stacks {
+            goals: self.goals,
+            choices: self.choices,
+            trace: self.trace,
+            trace_stack: self.trace_stack,
+            rule_attempts: self.rule_attempts,
+        };
+        let stacks = match serde_cbor::to_vec(&stacks) {
+            Ok(stacks) => stacks,
+            Err(e) => return invalid_state(format!("could not suspend the query: {}", e)),
+        };
+        Ok(SuspendedVm {
+            stacks,
+            binding_manager: self.binding_manager,
+            queries: self.queries,
+            tracing: self.tracing,
+            trace_filter: self.trace_filter,
+            span_owner: self.span_owner,
+            external_error: self.external_error,
+            query_start_time: self.query_start_time,
+            query_timeout_ms: self.query_timeout_ms,
+            stack_limit: self.stack_limit,
+            limits: self.limits,
+            usage: self.usage.take(),
+            // Shared with the other queries of a batch, which keep their copy.
+            external_call_memo: self.external_call_memo.borrow().clone(),
+            detailed_metrics: self.detailed_metrics.map(|metrics| metrics.take()),
+            metrics_totals: self.metrics_totals,
+            csp: self.csp,
+            debugger: self.debugger,
+            reraising_error: self.reraising_error,
+            kb: self.kb,
+            gensym_counter: self.gensym_counter,
+            call_id_symbols: self.call_id_symbols,
+            unresolved_constants: self.unresolved_constants,
+            resolved_constants: self.resolved_constants,
+            constant_calls: self.constant_calls,
+            log_level: self.log_level,
+            polar_log_stderr: self.polar_log_stderr,
+            polar_trace_mute: self.polar_trace_mute,
+            query_contains_partial: self.query_contains_partial,
+            inverting: self.inverting,
+            warn_on_cycles: self.warn_on_cycles,
+            native_string_methods: self.native_string_methods,
+            session_facts: self.session_facts,
+            namespace: self.namespace,
+            seen_results: self.seen_results,
+            stream_aggregates: self.stream_aggregates,
+            partial_form: self.partial_form,
+            term_formatter: self.term_formatter,
+            messages: self.messages,
+        })
+    }
+}
+
+#[cfg(feature = "suspend")]
+impl SuspendedVm {
+    pub(crate) fn resume(self) -> PolarResult<PolarVirtualMachine> {
+        let stacks: Stacks = match serde_cbor::from_slice(&self.stacks) {
+            Ok(stacks) => stacks,
+            Err(e) => return invalid_state(format!("could not resume the query: {}", e)),
+        };
+        Ok(PolarVirtualMachine {
+            goals: stacks.goals,
+            binding_manager: self.binding_manager,
+            choices: stacks.choices,
+            queries: self.queries,
+            tracing: self.tracing,
+            trace_filter: self.trace_filter,
+            explainer: None,
+            span_recorder: None,
+            span_owner: self.span_owner,
+            trace_stack: stacks.trace_stack,
+            trace: stacks.trace,
+            rule_attempts: stacks.rule_attempts,
+            external_error: self.external_error,
+            query_start_time: self.query_start_time,
+            query_timeout_ms: self.query_timeout_ms,
+            stack_limit: self.stack_limit,
+            limits: self.limits,
+            usage: Rc::new(RefCell::new(self.usage)),
+            external_call_memo: Rc::new(RefCell::new(self.external_call_memo)),
+            detailed_metrics: self
+                .detailed_metrics
+                .map(|metrics| Rc::new(RefCell::new(metrics))),
+            metrics_totals: self.metrics_totals,
+            csp: self.csp,
+            debugger: self.debugger,
+            reraising_error: self.reraising_error,
+            kb: self.kb,
+            gensym_counter: self.gensym_counter,
+            call_id_symbols: self.call_id_symbols,
+            unresolved_constants: self.unresolved_constants,
+            resolved_constants: self.resolved_constants,
+            constant_calls: self.constant_calls,
+            log_level: self.log_level,
+            polar_log_stderr: self.polar_log_stderr,
+            polar_trace_mute: self.polar_trace_mute,
+            query_contains_partial: self.query_contains_partial,
+            inverting: self.inverting,
+            warn_on_cycles: self.warn_on_cycles,
+            native_string_methods: self.native_string_methods,
+            session_facts: self.session_facts,
+            namespace: self.namespace,
+            seen_results: self.seen_results,
+            stream_aggregates: self.stream_aggregates,
+            partial_form: self.partial_form,
+            term_formatter: self.term_formatter,
+            messages: self.messages,
+        })
+    }
 }
 
 /// Implementations of instructions.
@@ -908,6 +1714,7 @@ impl PolarVirtualMachine {
     /// next available alternative. If no choice is possible, halt.
     fn backtrack(&mut self) -> PolarResult<()> {
         self.log(LogLevel::Trace, || "BACKTRACK", &[]);
+        self.usage.borrow_mut().record_backtrack();
 
         loop {
             match self.choices.pop() {
@@ -919,19 +1726,25 @@ impl PolarVirtualMachine {
                     queries,
                     trace,
                     trace_stack,
+                    rule_attempts,
                 }) => {
                     self.binding_manager.backtrack(&bsp);
+                    if self.only_repeats_seen_results() {
+                        continue;
+                    }
                     if let Some(mut alternative) = alternatives.pop() {
                         if alternatives.is_empty() {
                             self.goals = goals;
                             self.queries = queries;
                             self.trace = trace;
                             self.trace_stack = trace_stack;
+                            self.rule_attempts = rule_attempts;
                         } else {
                             self.goals.clone_from(&goals);
                             self.queries.clone_from(&queries);
                             self.trace.clone_from(&trace);
                             self.trace_stack.clone_from(&trace_stack);
+                            self.rule_attempts.clone_from(&rule_attempts);
                             self.choices.push(Choice {
                                 alternatives,
                                 bsp,
@@ -939,6 +1752,7 @@ impl PolarVirtualMachine {
                                 queries,
                                 trace,
                                 trace_stack,
+                                rule_attempts,
                             })
                         }
                         self.goals.append(&mut alternative);
@@ -947,9 +1761,36 @@ impl PolarVirtualMachine {
                 }
             }
         }
+        if self.span_recorder.is_some() {
+            let live = std::iter::once(&self.trace)
+                .chain(self.trace_stack.iter().map(|level| level.as_ref()))
+                .flat_map(|level| level.iter().map(Rc::as_ptr))
+                .collect();
+            self.record_spans(|r| r.abandon(self.span_owner, &live));
+        }
         Ok(())
     }
 
+    /// Whether a `DISTINCT` query can only repeat a result it already yielded from here on, because
+    /// its variables are all bound to values with no variables left in them.
+    fn only_repeats_seen_results(&self) -> bool {
+        let distinct = match &self.seen_results {
+            Some(distinct) => distinct,
+            None => return false,
+        };
+        let mut bindings = Bindings::new();
+        for var in &distinct.vars {
+            let value = self.deref(&Term::from(Value::Variable(var.clone())));
+            let mut vars = HashSet::new();
+            value.variables(&mut vars);
+            if !vars.is_empty() {
+                return false;
+            }
+            bindings.insert(var.clone(), value);
+        }
+        distinct.seen.contains(&result_key(&bindings))
+    }
+
     /// Commit to the current choice.
     fn cut(&mut self, index: usize) {
         self.choices.truncate(index);
@@ -970,11 +1811,23 @@ impl PolarVirtualMachine {
         }
     }
 
+    fn debugger_event(&mut self, event: &DebuggerEvent) -> QueryEvent {
+        // Query start time is reset when a debug event occurs.
+        self.query_start_time.take();
+
+        QueryEvent::Debugger {
+            event: event.clone(),
+        }
+    }
+
     /// Halt the VM by clearing all goals and choices.
     fn halt(&mut self) -> QueryEvent {
         self.log(LogLevel::Trace, || "HALT", &[]);
         self.goals.clear();
         self.choices.clear();
+        if self.span_owner == 0 {
+            self.record_spans(SpanRecorder::finish);
+        }
         QueryEvent::Done { result: true }
     }
 
@@ -983,29 +1836,39 @@ impl PolarVirtualMachine {
     fn isa(&mut self, left: &Term, right: &Term) -> PolarResult<()> {
         self.log(
             LogLevel::Trace,
-            || format!("MATCHES: {} matches {}", left, right),
+            || {
+                let fmt = &self.term_formatter;
+                format!(
+                    "MATCHES: {} matches {}",
+                    fmt.to_polar_string(left),
+                    fmt.to_polar_string(right)
+                )
+            },
             &[left, right],
         );
 
         match (left.value(), right.value()) {
-            (_, Value::Dictionary(_)) => todo!("make this case unreachable"),
+            (_, Value::Dictionary(_)) => {
+                return invalid_state(format!("cannot match against a dictionary: {}", right))
+            }
             (Value::Expression(_), _) | (_, Value::Expression(_)) => {
-                unreachable!("encountered bare expression")
+                return invalid_state(format!(
+                    "encountered bare expression in {} matches {}",
+                    left, right
+                ))
             }
 
-            _ if self.kb.read().unwrap().is_union(left) => {
-                // A union (currently) only matches itself.
-                //
-                // TODO(gj): when we have unions beyond `Actor` and `Resource`, we'll need to be
-                // smarter about this check since UnionA is more specific than UnionB if UnionA is
-                // a member of UnionB.
-                let unions_match = (left.is_actor_union() && right.is_actor_union())
-                    || (left.is_resource_union() && right.is_resource_union());
+            _ if self.kb().is_union(left) => {
+                // A union matches another union if all of its members are members of the other.
+                let unions_match = {
+                    let kb = self.kb();
+                    kb.is_union(right) && kb.is_subunion(left, right)
+                };
                 if !unions_match {
                     return self.push_goal(Goal::Backtrack);
                 }
             }
-            _ if self.kb.read().unwrap().is_union(right) => self.isa_union(left, right)?,
+            _ if self.kb().is_union(right) => self.isa_union(left, right)?,
 
             // TODO(gj): (Var, Rest) + (Rest, Var) cases might be unreachable.
             (Value::Variable(l), Value::Variable(r))
@@ -1061,15 +1924,12 @@ impl PolarVirtualMachine {
                 // For each field on the right, isa its value against the corresponding value on
                 // the left.
                 for (k, v) in right.fields.iter() {
-                    let left = left
-                        .fields
-                        .get(k)
-                        .expect("left fields should be a superset of right fields")
-                        .clone();
-                    self.push_goal(Goal::Isa {
-                        left,
-                        right: v.clone(),
-                    })?;
+                    if let Some(left) = left.fields.get(k) {
+                        self.push_goal(Goal::Isa {
+                            left: left.clone(),
+                            right: v.clone(),
+                        })?;
+                    }
                 }
             }
 
@@ -1079,13 +1939,14 @@ impl PolarVirtualMachine {
                 for (field, right_value) in right.fields.iter() {
                     // Generate symbol for the lookup result and leave the variable unbound, so that unification with the result does not fail.
                     // Unification with the lookup result happens in `fn external_call_result()`.
-                    let answer = self.kb.read().unwrap().gensym("isa_value");
+                    let answer = self.gensym("isa_value");
                     let call_id = self.new_call_id(&answer);
 
                     let lookup = Goal::LookupExternal {
                         instance: left.clone(),
                         call_id,
-                        field: right_value.clone_with_value(Value::String(field.0.clone())),
+                        field: right_value
+                            .clone_with_value(Value::String(field.as_str().to_string())),
                     };
                     let isa = Goal::Isa {
                         left: Term::from(answer),
@@ -1111,21 +1972,7 @@ impl PolarVirtualMachine {
                     ..
                 }) = *left.value()
                 {
-                    let isa = {
-                        let kb = self.kb.read().unwrap();
-                        let right_id = kb
-                            .get_class_id_for_symbol(&right_literal.tag)
-                            .expect("no class ID for symbol");
-                        let left_symbol = kb
-                            .get_symbol_for_class_id(&class_id)
-                            .expect("no symbol for class ID");
-                        if let Some(mro) = kb.mro.get(left_symbol) {
-                            mro.contains(right_id)
-                        } else {
-                            false
-                        }
-                    };
-                    if !isa {
+                    if !self.kb().class_id_isa(&class_id, &right_literal.tag) {
                         self.push_goal(Goal::Backtrack)?;
                     }
                 // default to IsaExternal when no `class_id` information is available
@@ -1180,7 +2027,7 @@ impl PolarVirtualMachine {
                 // Produce a constraint like left.field = value
                 let to_unify = |(field, value): (&Symbol, &Term)| -> Term {
                     let value = self.deref(value);
-                    let field = right.clone_with_value(value!(field.0.as_ref()));
+                    let field = right.clone_with_value(value!(field.as_str()));
                     let left = left.clone_with_value(value!(op!(Dot, left.clone(), field)));
                     term!(op!(Unify, left, value))
                 };
@@ -1253,7 +2100,7 @@ impl PolarVirtualMachine {
                 // Construct field constraints.
                 let field_constraints = fields.fields.iter().rev().map(|(f, v)| {
                     let v = self.deref(v);
-                    let field = right.clone_with_value(value!(f.0.as_ref()));
+                    let field = right.clone_with_value(value!(f.as_str()));
                     let left = left.clone_with_value(value!(op!(Dot, left.clone(), field)));
                     op!(Unify, left, v)
                 });
@@ -1283,15 +2130,23 @@ impl PolarVirtualMachine {
     }
 
     /// To evaluate `left matches Union`, look up `Union`'s member classes and create a choicepoint
-    /// to check if `left` matches any of them.
+    /// to check if `left` matches any of them. Fields on the union pattern, as in
+    /// `left matches Union{owner: "alice"}`, are checked against each member.
     fn isa_union(&mut self, left: &Term, union: &Term) -> PolarResult<()> {
+        let fields = match union.value() {
+            Value::Pattern(Pattern::Instance(InstanceLiteral { fields, .. })) => fields.clone(),
+            _ => Dictionary::default(),
+        };
         let member_isas = {
-            let kb = self.kb.read().unwrap();
-            let members = kb.get_union_members(union).iter();
+            let kb = self.kb();
+            let members = kb.get_union_members(union).into_iter().flatten();
             members
-                .map(|member| {
-                    let tag = member.as_symbol().unwrap().0.as_str();
-                    member.clone_with_value(value!(pattern!(instance!(tag))))
+                .filter_map(|member| {
+                    let instance = InstanceLiteral {
+                        tag: member.as_symbol().ok()?.clone(),
+                        fields: fields.clone(),
+                    };
+                    Some(member.clone_with_value(Value::Pattern(Pattern::Instance(instance))))
                 })
                 .map(|pattern| {
                     vec![Goal::Isa {
@@ -1315,7 +2170,7 @@ impl PolarVirtualMachine {
                     // if `field` is bound, unification will only succeed for the matching key
                     // if `field` is unbound, unification will succeed for all keys
                     goals.push(Goal::Unify {
-                        left: field.clone_with_value(Value::String(k.clone().0)),
+                        left: field.clone_with_value(Value::String(k.as_str().to_string())),
                         right: field.clone(),
                     });
                     // attempt to unify dict value with result
@@ -1328,7 +2183,7 @@ impl PolarVirtualMachine {
                 self.choose(alternatives)
             }
             Value::String(field) => {
-                if let Some(retrieved) = dict.fields.get(&Symbol(field.clone())) {
+                if let Some(retrieved) = dict.fields.get(&Symbol::new(field)) {
                     self.push_goal(Goal::Unify {
                         left: retrieved.clone(),
                         right: value.clone(),
@@ -1368,7 +2223,7 @@ impl PolarVirtualMachine {
                         .collect()
                 }),
             ),
-            Value::String(field) => (Symbol(field.clone()), None, None),
+            Value::String(field) => (Symbol::new(field), None, None),
             v => {
                 return self.type_error(
                     field,
@@ -1377,10 +2232,6 @@ impl PolarVirtualMachine {
             }
         };
 
-        // add an empty choice point; lookups return only one value
-        // but we'll want to cut if we get back nothing
-        self.push_choice(vec![])?;
-
         self.log(
             LogLevel::Trace,
             || {
@@ -1403,21 +2254,92 @@ impl PolarVirtualMachine {
             &[],
         );
 
+        let instance = self.deref(instance);
+        if let Some(key) = self.external_call_key(&instance, &field_name, &args, &kwargs) {
+            let memoized = self.external_call_memo.borrow().results.get(&key).cloned();
+            if let Some(value) = memoized {
+                self.usage.borrow_mut().record_cache_hit();
+                self.log(
+                    LogLevel::Trace,
+                    || {
+                        format!(
+                            "=> {} (memoized)",
+                            self.term_formatter.to_polar_string(&value)
+                        )
+                    },
+                    &[],
+                );
+                let sym = match self.call_id_symbols.remove(&call_id) {
+                    Some(sym) => sym,
+                    None => {
+                        return invalid_state(format!("unregistered external call ID {}", call_id))
+                    }
+                };
+                self.push_goal(Goal::Unify {
+                    left: Term::from(sym),
+                    right: value,
+                })?;
+                return Ok(QueryEvent::None);
+            }
+            self.external_call_memo
+                .borrow_mut()
+                .pending
+                .insert(call_id, key);
+        }
+
+        // add an empty choice point; lookups return only one value
+        // but we'll want to cut if we get back nothing
+        self.push_choice(vec![])?;
+
         Ok(QueryEvent::ExternalCall {
             call_id,
-            instance: self.deref(instance),
+            instance,
             attribute: field_name,
             args,
             kwargs,
         })
     }
 
+    /// The key to memoize a call to `attribute` on `instance` under, unless its result might
+    /// change within the query: because `instance` isn't an external instance, its arguments
+    /// aren't bound yet, or the host registered the method as effectful or nondeterministic.
+    fn external_call_key(
+        &self,
+        instance: &Term,
+        attribute: &Symbol,
+        args: &Option<Vec<Term>>,
+        kwargs: &Option<BTreeMap<Symbol, Term>>,
+    ) -> Option<ExternalCallKey> {
+        let instance_id = match instance.value() {
+            Value::ExternalInstance(ExternalInstance { instance_id, .. }) => *instance_id,
+            _ => return None,
+        };
+        {
+            let kb = self.kb();
+            if kb.is_effectful_method(attribute) || kb.is_nondeterministic_method(attribute) {
+                return None;
+            }
+        }
+        let mut vars = HashSet::new();
+        for arg in args
+            .iter()
+            .flatten()
+            .chain(kwargs.iter().flat_map(|k| k.values()))
+        {
+            arg.variables(&mut vars);
+        }
+        if !vars.is_empty() {
+            return None;
+        }
+        Some((instance_id, attribute.clone(), args.clone(), kwargs.clone()))
+    }
+
     fn isa_external(
         &mut self,
         instance: &Term,
         literal: &InstanceLiteral,
     ) -> PolarResult<QueryEvent> {
-        let (call_id, answer) = self.new_call_var("isa", false.into());
+        let (call_id, answer) = self.new_call_var("isa", false.into())?;
         self.push_goal(Goal::Unify {
             left: answer,
             right: Term::from(true),
@@ -1475,6 +2397,27 @@ impl PolarVirtualMachine {
     /// consists of unifying the rule head with the arguments, then
     /// querying for each body clause.
     fn query(&mut self, term: &Term) -> PolarResult<QueryEvent> {
+        if let Some(cycle) = self.find_cycle(term) {
+            self.log(
+                LogLevel::Info,
+                || format!("CYCLE: {}", Self::format_cycle(&cycle)),
+                &[],
+            );
+            if self.warn_on_cycles {
+                self.messages.push(
+                    MessageKind::Warning,
+                    format!(
+                        "Cycle detected: {}\nThe repeated goal was abandoned{}.",
+                        Self::format_cycle(&cycle),
+                        term.parsed_context()
+                            .map_or_else(String::new, Context::source_position)
+                    ),
+                );
+            }
+            self.push_goal(Goal::Backtrack)?;
+            return Ok(QueryEvent::None);
+        }
+
         // - Print INFO event for queries for rules.
         // - Print TRACE (a superset of INFO) event for all other queries.
         // - We filter out single-element ANDs, which many rule bodies take the form of, to instead
@@ -1492,7 +2435,11 @@ impl PolarVirtualMachine {
                 args,
             }) if args.len() < 2 => (),
             _ => {
-                self.log(LogLevel::Trace, || format!("QUERY: {}", term), &[term]);
+                self.log(
+                    LogLevel::Trace,
+                    || format!("QUERY: {}", self.term_formatter.to_polar_string(term)),
+                    &[term],
+                );
             }
         };
 
@@ -1546,8 +2493,44 @@ impl PolarVirtualMachine {
         Ok(QueryEvent::None)
     }
 
+    /// Find an ancestor of the rule call `term` on the current derivation path that is identical
+    /// to it once bindings are applied.
+    ///
+    /// Re-deriving such a goal cannot produce any result that the ancestor won't produce on its
+    /// own, so it would only loop until the goal stack overflows or the query times out. Returns
+    /// the calls that make up the cycle, starting and ending with the repeated goal.
+    fn find_cycle(&self, term: &Term) -> Option<Vec<Term>> {
+        let name = &term.as_call().ok()?.name;
+        let mut derefed = None;
+        let start = self.queries.iter().rposition(|query| {
+            matches!(query.value(), Value::Call(call) if &call.name == name)
+                && self.deref(query) == *derefed.get_or_insert_with(|| self.deref(term))
+        })?;
+        let mut cycle = self.queries[start..]
+            .iter()
+            .filter(|query| matches!(query.value(), Value::Call(_)))
+            .map(|query| self.deref(query))
+            .collect::<Vec<_>>();
+        cycle.push(derefed?);
+        Some(cycle)
+    }
+
+    fn format_cycle(cycle: &[Term]) -> String {
+        cycle
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(" -> ")
+    }
+
     /// Select applicable rules for predicate.
     /// Sort applicable rules by specificity.
+    /// Whether the KB or the query's namespace defines a rule named `name`.
+    fn defines_rule(&self, name: &Symbol) -> bool {
+        self.kb().get_generic_rule(name).is_some()
+            || matches!(&self.namespace, Some(ns) if ns.get_generic_rule(name).is_some())
+    }
+
     /// Create a choice over the applicable rules.
     fn query_for_predicate(&mut self, predicate: Call) -> PolarResult<()> {
         if predicate.kwargs.is_some() {
@@ -1556,24 +2539,49 @@ impl PolarVirtualMachine {
                 predicate
             ));
         }
-        let goals = match self.kb.read().unwrap().get_generic_rule(&predicate.name) {
-            None => {
+        if let Some(builtin) = self.kb.get_builtin(&predicate.name).cloned() {
+            return self.query_for_builtin(&builtin, &predicate);
+        }
+        // Rules defined by the policy take precedence over built-in predicates.
+        if is_builtin_predicate(&predicate.name) && !self.defines_rule(&predicate.name) {
+            return self.query_for_reachable(&predicate);
+        }
+        let session_facts = self.session_facts.clone();
+        let defined_in_session =
+            matches!(&session_facts, Some(facts) if facts.defines(&predicate.name));
+        let namespace = self.namespace.clone();
+        let namespace_rule = namespace
+            .as_ref()
+            .and_then(|ns| ns.get_generic_rule(&predicate.name).map(|rule| (ns, rule)));
+        let kb = self.kb.clone();
+        let goals = match kb.get_generic_rule(&predicate.name) {
+            None if !defined_in_session && namespace_rule.is_none() => {
                 return Err(RuntimeError::QueryForUndefinedRule {
-                    name: predicate.name.0.clone(),
+                    name: predicate.name.as_str().to_string(),
                 }
                 .into())
             }
-            Some(generic_rule) => {
-                if generic_rule.name != predicate.name {
-                    return invalid_state(format!(
-                        "query_for_predicate: different rule names: {} != {}",
-                        generic_rule.name, predicate.name
-                    ));
+            generic_rule => {
+                if let Some(generic_rule) = generic_rule {
+                    if generic_rule.name != predicate.name {
+                        return invalid_state(format!(
+                            "query_for_predicate: different rule names: {} != {}",
+                            generic_rule.name, predicate.name
+                        ));
+                    }
                 }
 
                 // Pre-filter rules.
                 let args = predicate.args.iter().map(|t| self.deref(t)).collect();
-                let pre_filter = generic_rule.get_applicable_rules(&args);
+                let mut pre_filter = generic_rule
+                    .map(|generic_rule| kb.get_applicable_rules(generic_rule, &args))
+                    .unwrap_or_default();
+                if let Some((ns, generic_rule)) = namespace_rule {
+                    pre_filter.append(&mut ns.get_applicable_rules(generic_rule, &args));
+                }
+                if let Some(facts) = session_facts {
+                    pre_filter = facts.applicable_rules(&predicate.name, pre_filter, &args);
+                }
 
                 self.polar_trace_mute = true;
 
@@ -1589,12 +2597,128 @@ impl PolarVirtualMachine {
                 ]
             }
         };
+        drop(kb);
         self.append_goals(goals)
     }
 
+    /// Call a predicate registered by the host with the values of the arguments, and backtrack
+    /// if it doesn't hold.
+    fn query_for_builtin(&mut self, builtin: &Builtin, predicate: &Call) -> PolarResult<()> {
+        let term = Term::from(Value::Call(predicate.clone()));
+        if predicate.args.len() != builtin.arity {
+            return self.type_error(
+                &term,
+                format!(
+                    "{}() takes {} arguments; got {}",
+                    builtin.name,
+                    builtin.arity,
+                    predicate.args.len()
+                ),
+            );
+        }
+        let args = predicate
+            .args
+            .iter()
+            .map(|arg| self.deref(arg))
+            .collect::<Vec<_>>();
+        for (arg, value) in predicate.args.iter().zip(&args) {
+            let mut vars = HashSet::new();
+            value.variables(&mut vars);
+            if !vars.is_empty() {
+                return unsupported(
+                    format!("the arguments of the built-in {} must be bound", builtin),
+                    arg,
+                );
+            }
+        }
+        match builtin.call(&args) {
+            Ok(true) => Ok(()),
+            Ok(false) => self.push_goal(Goal::Backtrack),
+            Err(msg) => Err(RuntimeError::Application {
+                msg,
+                stack_trace: self.stack_trace(),
+                term: Some(term),
+            }
+            .into()),
+        }
+    }
+
+    /// Traverse the graph described by a two-argument relation rule, starting from a bound node.
+    ///
+    /// `reachable(start, relation, target, max_depth)` succeeds once for each distinct node
+    /// that can be reached from `start` by following between 1 and `max_depth` edges of the
+    /// rule named `relation`.
+    fn query_for_reachable(&mut self, predicate: &Call) -> PolarResult<()> {
+        let term = Term::from(Value::Call(predicate.clone()));
+        if predicate.args.len() != 4 {
+            return self.type_error(
+                &term,
+                format!(
+                    "{}() takes 4 arguments (start, relation, target, max_depth); got {}",
+                    REACHABLE,
+                    predicate.args.len()
+                ),
+            );
+        }
+        let start = self.deref(&predicate.args[0]);
+        let relation = self.deref(&predicate.args[1]);
+        let target = predicate.args[2].clone();
+        let max_depth = self.deref(&predicate.args[3]);
+
+        if matches!(start.value(), Value::Variable(_) | Value::RestVariable(_)) {
+            return unsupported(
+                format!("the start node of {}() must be bound", REACHABLE),
+                &predicate.args[0],
+            );
+        }
+        let relation = match relation.value() {
+            Value::String(name) => Symbol::new(name),
+            _ => {
+                return self.type_error(
+                    &predicate.args[1],
+                    format!(
+                        "the relation of {}() must be the name of a rule, got {}",
+                        REACHABLE, relation
+                    ),
+                )
+            }
+        };
+        let max_depth = match max_depth.value() {
+            Value::Number(Numeric::Integer(depth)) if *depth >= 0 => *depth as u64,
+            _ => {
+                return self.type_error(
+                    &predicate.args[3],
+                    format!(
+                        "the max_depth of {}() must be a non-negative integer, got {}",
+                        REACHABLE, max_depth
+                    ),
+                )
+            }
+        };
+        if !self.defines_rule(&relation) {
+            return Err(RuntimeError::QueryForUndefinedRule {
+                name: relation.as_str().to_string(),
+            }
+            .into());
+        }
+
+        let reachable = Rc::new(RefCell::new(vec![]));
+        let runnable = Box::new(Reachable::new(
+            self,
+            relation,
+            start,
+            max_depth,
+            reachable.clone(),
+        ));
+        self.append_goals(vec![
+            Goal::Run { runnable },
+            Goal::UnifyReachable { target, reachable },
+        ])
+    }
+
     fn query_for_operation(&mut self, term: &Term) -> PolarResult<QueryEvent> {
-        let operation = term.as_expression().unwrap();
-        let mut args = operation.args.clone();
+        let operation = term.as_expression()?;
+        let args = operation.args.clone();
         let wrong_arity = || invalid_state(format!("query_for_operation: wrong arity: {}", term));
         match operation.operator {
             Operator::And => {
@@ -1609,11 +2733,10 @@ impl PolarVirtualMachine {
             }
             Operator::Not => {
                 // Query in a sub-VM and invert the results.
-                if args.len() != 1 {
-                    return wrong_arity();
-                }
-
-                let term = args.pop().unwrap();
+                let term = match &args[..] {
+                    [term] => term.clone(),
+                    _ => return wrong_arity(),
+                };
                 let add_constraints = Rc::new(RefCell::new(Bindings::new()));
                 let inverter = Box::new(Inverter::new(
                     self,
@@ -1627,12 +2750,45 @@ impl PolarVirtualMachine {
                     vec![Goal::Backtrack],
                 )?;
             }
-            Operator::Assign => {
-                if args.len() != 2 {
-                    return wrong_arity();
+            Operator::Count | Operator::Sum | Operator::Min | Operator::Max => {
+                let (var, collection, condition, result) = match &args[..] {
+                    [var, collection, condition, result] => (
+                        var.clone(),
+                        collection.clone(),
+                        condition.clone(),
+                        result.clone(),
+                    ),
+                    _ => return wrong_arity(),
+                };
+                if !matches!(var.value(), Value::Variable(_)) {
+                    return self.type_error(
+                        &var,
+                        format!("can only aggregate over a variable, got {}", var),
+                    );
                 }
-                let right = args.pop().unwrap();
-                let left = args.pop().unwrap();
+                let values = Rc::new(RefCell::new(vec![]));
+                let runnable = Box::new(Aggregator::new(
+                    self,
+                    operation.operator,
+                    var,
+                    collection,
+                    condition,
+                    values.clone(),
+                ));
+                self.append_goals(vec![
+                    Goal::Run { runnable },
+                    Goal::UnifyAggregate {
+                        term: term.clone(),
+                        values,
+                        result,
+                    },
+                ])?;
+            }
+            Operator::Assign => {
+                let (left, right) = match &args[..] {
+                    [left, right] => (left.clone(), right.clone()),
+                    _ => return wrong_arity(),
+                };
                 match (left.value(), right.value()) {
                     (Value::Variable(var), _) => match self.variable_state(var) {
                         VariableState::Unbound => {
@@ -1654,11 +2810,10 @@ impl PolarVirtualMachine {
 
             Operator::Unify => {
                 // Push a `Unify` goal
-                if args.len() != 2 {
-                    return wrong_arity();
-                }
-                let right = args.pop().unwrap();
-                let left = args.pop().unwrap();
+                let (left, right) = match &args[..] {
+                    [left, right] => (left.clone(), right.clone()),
+                    _ => return wrong_arity(),
+                };
                 self.push_goal(Goal::Unify { left, right })?
             }
             Operator::Dot => {
@@ -1687,6 +2842,9 @@ impl PolarVirtualMachine {
                 return self.query_op_helper(term, Self::in_op_helper, false, true);
             }
 
+            Operator::Debug if self.debugger.is_structured() => {
+                self.push_goal(self.debugger.stop(StopReason::DebugCall, self))?;
+            }
             Operator::Debug => {
                 let message = self.debugger.break_msg(self).unwrap_or_else(|| {
                     format!(
@@ -1709,18 +2867,17 @@ impl PolarVirtualMachine {
                 );
             }
             Operator::New => {
-                if args.len() != 2 {
-                    return wrong_arity();
-                }
-                let result = args.pop().unwrap();
+                let (constructor, result) = match &args[..] {
+                    [constructor, result] => (constructor.clone(), result.clone()),
+                    _ => return wrong_arity(),
+                };
                 result.as_symbol()?; // Ensure `result` is a variable.
-                let constructor = args.pop().unwrap();
 
                 let instance_id = self.new_id();
 
                 let class = &constructor.as_call()?.name;
                 let class_repr = if self.kb().is_constant(class) {
-                    Some(class.0.clone())
+                    Some(class.as_str().to_string())
                 } else {
                     None
                 };
@@ -1770,19 +2927,17 @@ impl PolarVirtualMachine {
             }
             Operator::Isa => {
                 // TODO (dhatch): Use query op helper.
-                if args.len() != 2 {
-                    return wrong_arity();
-                }
-                let right = args.pop().unwrap();
-                let left = args.pop().unwrap();
+                let (left, right) = match &args[..] {
+                    [left, right] => (left.clone(), right.clone()),
+                    _ => return wrong_arity(),
+                };
                 self.push_goal(Goal::Isa { left, right })?
             }
             Operator::ForAll => {
-                if args.len() != 2 {
-                    return wrong_arity();
-                }
-                let action = args.pop().unwrap();
-                let condition = args.pop().unwrap();
+                let (condition, action) = match &args[..] {
+                    [condition, action] => (condition.clone(), action.clone()),
+                    _ => return wrong_arity(),
+                };
                 // For all is implemented as !(condition, !action).
                 let op = Operation {
                     operator: Operator::Not,
@@ -1827,7 +2982,7 @@ impl PolarVirtualMachine {
     where
         F: Fn(&mut Self, &Term) -> PolarResult<QueryEvent>,
     {
-        let Operation { operator: op, args } = term.as_expression().unwrap();
+        let Operation { operator: op, args } = term.as_expression()?;
 
         let mut args = args.clone();
         if args.len() < 2 {
@@ -1852,7 +3007,7 @@ impl PolarVirtualMachine {
                 }),
                 _,
             ) if args.len() == 2 => {
-                let var = term!(self.kb().gensym("rwdot"));
+                let var = term!(self.gensym("rwdot"));
                 let val = Value::Expression(Operation {
                     operator: *op,
                     args: vec![var.clone(), right.clone()],
@@ -1874,7 +3029,7 @@ impl PolarVirtualMachine {
                     args,
                 }),
             ) if args.len() == 2 => {
-                let var = term!(self.kb().gensym("rwdot"));
+                let var = term!(self.gensym("rwdot"));
                 let val = Value::Expression(Operation {
                     operator: *op,
                     args: vec![left.clone(), var.clone()],
@@ -1938,7 +3093,7 @@ impl PolarVirtualMachine {
 
     /// Evaluate comparison operations.
     fn comparison_op_helper(&mut self, term: &Term) -> PolarResult<QueryEvent> {
-        let Operation { operator: op, args } = term.as_expression().unwrap();
+        let Operation { operator: op, args } = term.as_expression()?;
 
         if args.len() != 2 {
             return invalid_state(format!("comparison_op_helper: wrong arity: {}", term));
@@ -1949,7 +3104,7 @@ impl PolarVirtualMachine {
         match (left.value(), right.value()) {
             (Value::ExternalInstance(_), _) | (_, Value::ExternalInstance(_)) => {
                 // Generate a symbol for the external result and bind to `false` (default).
-                let (call_id, answer) = self.new_call_var("external_op_result", false.into());
+                let (call_id, answer) = self.new_call_var("external_op_result", false.into())?;
 
                 // Check that the external result is `true` when we return.
                 self.push_goal(Goal::Unify {
@@ -1964,6 +3119,13 @@ impl PolarVirtualMachine {
                     args: vec![left.clone(), right.clone()],
                 })
             }
+            // Compare sets once their elements are bound.
+            (Value::Set(_), Value::Set(_)) => {
+                if !compare(*op, &self.deref(left), &self.deref(right), Some(term))? {
+                    self.push_goal(Goal::Backtrack)?;
+                }
+                Ok(QueryEvent::None)
+            }
             _ => {
                 if !compare(*op, left, right, Some(term))? {
                     self.push_goal(Goal::Backtrack)?;
@@ -1977,7 +3139,7 @@ impl PolarVirtualMachine {
     // like we do for dots; e.g., `+(a, b, c)` → `c = +(a, b)`.
     /// Evaluate arithmetic operations.
     fn arithmetic_op_helper(&mut self, term: &Term) -> PolarResult<QueryEvent> {
-        let Operation { operator: op, args } = term.as_expression().unwrap();
+        let Operation { operator: op, args } = term.as_expression()?;
 
         if args.len() != 3 {
             return invalid_state(format!("arithmetic_op_helper: wrong arity: {}", term));
@@ -1990,12 +3152,12 @@ impl PolarVirtualMachine {
         match (left.value(), right.value()) {
             (Value::Number(left), Value::Number(right)) => {
                 if let Some(answer) = match op {
-                    Operator::Add => *left + *right,
-                    Operator::Sub => *left - *right,
-                    Operator::Mul => *left * *right,
-                    Operator::Div => *left / *right,
-                    Operator::Mod => (*left).modulo(*right),
-                    Operator::Rem => *left % *right,
+                    Operator::Add => left.clone() + right.clone(),
+                    Operator::Sub => left.clone() - right.clone(),
+                    Operator::Mul => left.clone() * right.clone(),
+                    Operator::Div => left.clone() / right.clone(),
+                    Operator::Mod => left.clone().modulo(right.clone()),
+                    Operator::Rem => left.clone() % right.clone(),
                     _ => return unsupported(format!("numeric operation {}", op), term),
                 } {
                     self.push_goal(Goal::Unify {
@@ -2007,13 +3169,35 @@ impl PolarVirtualMachine {
                     Err(RuntimeError::ArithmeticError { term: term.clone() }.into())
                 }
             }
+            (Value::DateTime(_) | Value::Duration(_), Value::DateTime(_) | Value::Duration(_)) => {
+                use Value::{DateTime, Duration};
+                let answer = match (op, left.value(), right.value()) {
+                    (Operator::Add, DateTime(l), Duration(r)) => (*l + *r).map(DateTime),
+                    (Operator::Add, Duration(l), DateTime(r)) => (*r + *l).map(DateTime),
+                    (Operator::Add, Duration(l), Duration(r)) => (*l + *r).map(Duration),
+                    (Operator::Sub, DateTime(l), Duration(r)) => (*l - *r).map(DateTime),
+                    (Operator::Sub, DateTime(l), DateTime(r)) => (*l - *r).map(Duration),
+                    (Operator::Sub, Duration(l), Duration(r)) => (*l - *r).map(Duration),
+                    _ => return unsupported(format!("temporal operation {}", term), term),
+                };
+                match answer {
+                    Some(answer) => {
+                        self.push_goal(Goal::Unify {
+                            left: term.clone_with_value(answer),
+                            right: result.clone(),
+                        })?;
+                        Ok(QueryEvent::None)
+                    }
+                    None => Err(RuntimeError::ArithmeticError { term: term.clone() }.into()),
+                }
+            }
             (_, _) => unsupported(format!("unsupported arithmetic operands: {}", term), term),
         }
     }
 
     /// Push appropriate goals for lookups on dictionaries and instances.
     fn dot_op_helper(&mut self, term: &Term) -> PolarResult<QueryEvent> {
-        let Operation { args, .. } = term.as_expression().unwrap();
+        let Operation { args, .. } = term.as_expression()?;
 
         if args.len() != 3 {
             return invalid_state(format!("dot_op_helper: wrong arity: {}", term));
@@ -2034,13 +3218,29 @@ impl PolarVirtualMachine {
                     value: args.remove(2),
                 })?
             }
+            // Evaluate built-in string methods without a round trip to the host.
+            Value::String(string) if is_string_method(field, self.native_string_methods) => {
+                let result = self.string_method(string, field)?;
+                self.push_goal(Goal::Unify {
+                    left: value.clone(),
+                    right: object.clone_with_value(result),
+                })?
+            }
+            // Evaluate set operations without a round trip to the host.
+            Value::Set(_) if is_set_method(field) => {
+                let result = self.set_method(object, field)?;
+                self.push_goal(Goal::Unify {
+                    left: value.clone(),
+                    right: object.clone_with_value(result),
+                })?
+            }
             // Push an `ExternalLookup` goal for external instances and built-ins.
             Value::Dictionary(_)
             | Value::ExternalInstance(_)
             | Value::List(_)
             | Value::Number(_)
             | Value::String(_) => {
-                let answer = self.kb.read().unwrap().gensym("lookup_value");
+                let answer = self.gensym("lookup_value");
                 let call_id = self.new_call_id(&answer);
                 self.append_goals(vec![
                     Goal::LookupExternal {
@@ -2082,8 +3282,119 @@ impl PolarVirtualMachine {
         Ok(QueryEvent::None)
     }
 
+    /// Call one of the string methods for which `is_string_method` holds on `string`.
+    fn string_method(&self, string: &str, field: &Term) -> PolarResult<Value> {
+        let Call { name, args, .. } = match field.value() {
+            Value::Call(call) => call,
+            _ => return invalid_state(format!("string_method: not a call: {}", field)),
+        };
+        let args = args.iter().map(|arg| self.deref(arg)).collect::<Vec<_>>();
+        let string_arg = |i: usize| match args.get(i).map(Term::value) {
+            Some(Value::String(s)) => Ok(s.as_str()),
+            _ => self.type_error(
+                field,
+                format!("{} expects a string argument: {}", name, field),
+            ),
+        };
+        let arity = match name.as_str() {
+            "split" if args.is_empty() => 0,
+            "lower" | "upper" => 0,
+            _ => 1,
+        };
+        if args.len() != arity {
+            return self.type_error(
+                field,
+                format!("{} expects {} argument(s): {}", name, arity, field),
+            );
+        }
+
+        Ok(match name.as_str() {
+            "lower" => Value::String(string.to_lowercase()),
+            "upper" => Value::String(string.to_uppercase()),
+            "contains" => Value::Boolean(string.contains(string_arg(0)?)),
+            "starts_with" => Value::Boolean(string.starts_with(string_arg(0)?)),
+            "ends_with" => Value::Boolean(string.ends_with(string_arg(0)?)),
+            "split" => {
+                let parts: Vec<&str> = if args.is_empty() {
+                    string.split_whitespace().collect()
+                } else {
+                    string.split(string_arg(0)?).collect()
+                };
+                Value::List(
+                    parts
+                        .into_iter()
+                        .map(|s| field.clone_with_value(Value::String(s.to_owned())))
+                        .collect(),
+                )
+            }
+            "join" | INTERPOLATE => {
+                let items = match args[0].value() {
+                    Value::List(list) if !has_rest_var(list) => list,
+                    _ => {
+                        return self.type_error(
+                            &args[0],
+                            format!("join expects a list argument: {}", field),
+                        )
+                    }
+                };
+                let mut strings = vec![];
+                for item in items {
+                    strings.push(match item.value() {
+                        Value::String(s) => s.clone(),
+                        Value::Number(n) => n.to_string(),
+                        Value::Boolean(b) => b.to_string(),
+                        Value::Variable(v) | Value::RestVariable(v) => {
+                            return unsupported(
+                                format!("cannot join unbound variable {} into a string", v),
+                                item,
+                            )
+                        }
+                        _ => {
+                            return self.type_error(
+                                item,
+                                format!(
+                                    "can only join strings, numbers and booleans into a string, this is {}",
+                                    item
+                                ),
+                            )
+                        }
+                    });
+                }
+                Value::String(strings.join(string))
+            }
+            _ => return invalid_state(format!("string_method: unknown method: {}", field)),
+        })
+    }
+
+    /// Call one of the set methods for which `is_set_method` holds on the set `object`.
+    fn set_method(&self, object: &Term, field: &Term) -> PolarResult<Value> {
+        let Call { name, args, .. } = match field.value() {
+            Value::Call(call) => call,
+            _ => return invalid_state(format!("set_method: not a call: {}", field)),
+        };
+        let (object, arg) = match &args[..] {
+            [arg] => (self.deref(object), self.deref(arg)),
+            _ => {
+                return self.type_error(field, format!("{} expects 1 argument(s): {}", name, field))
+            }
+        };
+        let (set, other) = match (object.value(), arg.value()) {
+            (Value::Set(set), Value::Set(other)) => (set, other),
+            _ => {
+                return self.type_error(&arg, format!("{} expects a set argument: {}", name, field))
+            }
+        };
+
+        Ok(Value::Set(match name.as_str() {
+            "union" => set.union(other),
+            "intersect" => set.intersection(other),
+            "difference" => set.difference(other),
+            _ => return invalid_state(format!("set_method: unknown method: {}", field)),
+        }))
+    }
+
     fn in_op_helper(&mut self, term: &Term) -> PolarResult<QueryEvent> {
-        let Operation { args, .. } = term.as_expression().unwrap();
+        let Operation { args, .. } = term.as_expression()?;
 
         if args.len() != 2 {
             return invalid_state(format!("in_op_helper: wrong arity: {}", term));
@@ -2118,7 +3429,7 @@ impl PolarVirtualMachine {
                     .iter()
                     .map(|(k, v)| {
                         iterable.clone_with_value(Value::List(vec![
-                            v.clone_with_value(Value::String(k.0.clone())),
+                            v.clone_with_value(Value::String(k.as_str().to_string())),
                             v.clone(),
                         ]))
                     })
@@ -2133,6 +3444,23 @@ impl PolarVirtualMachine {
                     })
                     .collect::<Vec<Goals>>(),
             )?,
+            // Look ground items up in the set directly.
+            Value::Set(set) if item_is_ground && set.is_ground() => {
+                if !set.contains(item) {
+                    self.push_goal(Goal::Backtrack)?;
+                }
+            }
+            // Otherwise, unify item with each element of the set.
+            Value::Set(set) => self.choose(
+                set.iter()
+                    .map(|element| {
+                        vec![Goal::Unify {
+                            left: item.clone(),
+                            right: element.clone(),
+                        }]
+                    })
+                    .collect::<Vec<Goals>>(),
+            )?,
             // Unify item with each element of the string
             // FIXME (gw): this seems strange, wouldn't a substring search make more sense?
             Value::String(s) => self.choose(
@@ -2154,7 +3482,7 @@ impl PolarVirtualMachine {
                 // Unification of the `next_sym` variable with the result of `NextExternal` happens in `fn external_call_result()`
                 // `external_call_result` is the handler for results from both `LookupExternal` and `NextExternal`, so neither can bind the
                 // call ID variable to `false`.
-                let next_sym = self.kb.read().unwrap().gensym("next_value");
+                let next_sym = self.gensym("next_value");
                 let call_id = self.new_call_id(&next_sym);
 
                 // append unify goal to be evaluated after
@@ -2190,6 +3518,7 @@ impl PolarVirtualMachine {
     ///  - Recursive unification => more `Unify` goals are pushed onto the stack
     ///  - Failure => backtrack
     fn unify(&mut self, left: &Term, right: &Term) -> PolarResult<()> {
+        self.usage.borrow_mut().record_unification();
         match (left.value(), right.value()) {
             (Value::Expression(op), other) | (other, Value::Expression(op)) => {
                 match op {
@@ -2312,6 +3641,23 @@ impl PolarVirtualMachine {
                 right: r.clone(),
             })?,
 
+            // Unify sets by comparing their elements, which must be bound.
+            (Value::Set(_), Value::Set(_)) => {
+                let (left, right) = (self.deref(left), self.deref(right));
+                if !left.is_ground() || !right.is_ground() {
+                    return unsupported(
+                        format!(
+                            "cannot unify sets with unbound elements: {} = {}",
+                            left, right
+                        ),
+                        &left,
+                    );
+                }
+                if left != right {
+                    self.push_goal(Goal::Backtrack)?;
+                }
+            }
+
             (Value::Dictionary(left), Value::Dictionary(right)) => {
                 // Check that the set of keys are the same.
                 let left_fields: HashSet<&Symbol> = left.fields.keys().collect();
@@ -2323,7 +3669,10 @@ impl PolarVirtualMachine {
 
                 // For each value, push a unify goal.
                 for (k, v) in left.fields.iter() {
-                    let right = right.fields.get(k).expect("fields should be equal").clone();
+                    let right = match right.fields.get(k) {
+                        Some(right) => right.clone(),
+                        None => return invalid_state(format!("missing field {} in {}", k, right)),
+                    };
                     self.push_goal(Goal::Unify {
                         left: v.clone(),
                         right,
@@ -2351,6 +3700,18 @@ impl PolarVirtualMachine {
                 }
             }
 
+            (Value::DateTime(left), Value::DateTime(right)) => {
+                if left != right {
+                    self.push_goal(Goal::Backtrack)?;
+                }
+            }
+
+            (Value::Duration(left), Value::Duration(right)) => {
+                if left != right {
+                    self.push_goal(Goal::Backtrack)?;
+                }
+            }
+
             (
                 Value::ExternalInstance(ExternalInstance {
                     instance_id: left, ..
@@ -2498,7 +3859,10 @@ impl PolarVirtualMachine {
         } else {
             // Check one rule for applicability.
             let mut unfiltered_rules = unfiltered_rules.clone();
-            let rule = unfiltered_rules.pop().unwrap();
+            let rule = match unfiltered_rules.pop() {
+                Some(rule) => rule,
+                None => return invalid_state("no rules left to filter"),
+            };
 
             let inapplicable = Goal::FilterRules {
                 args: args.clone(),
@@ -2668,25 +4032,58 @@ impl PolarVirtualMachine {
     fn is_more_specific(&mut self, left: &Rule, right: &Rule, args: &TermList) -> PolarResult<()> {
         let zipped = left.params.iter().zip(right.params.iter()).zip(args.iter());
         for ((left_param, right_param), arg) in zipped {
-            match (&left_param.specializer, &right_param.specializer) {
-                // If both specs are unions, they have the same specificity regardless of whether
-                // they're the same or different unions.
-                //
-                // TODO(gj): when we have unions beyond `Actor` and `Resource`, we'll need to be
-                // smarter about this check since UnionA is more specific than UnionB if UnionA is
-                // a member of UnionB.
+            // A parameter that destructures a list is as specific as a specializer. Of two, the
+            // one that fixes more of the list's structure is more specific.
+            match (list_pattern(left_param), list_pattern(right_param)) {
+                (Some(left_list), Some(right_list)) => {
+                    match compare_list_patterns(left_list, right_list) {
+                        Ordering::Greater => return Ok(()),
+                        Ordering::Less => return self.push_goal(Goal::Backtrack),
+                        Ordering::Equal => continue,
+                    }
+                }
+                (Some(_), None) if right_param.specializer.is_none() => return Ok(()),
+                (None, Some(_)) if left_param.specializer.is_none() => {
+                    return self.push_goal(Goal::Backtrack)
+                }
+                _ => {}
+            }
+            // An alias is as specific as the class it stands for.
+            let resolve = |spec: &Option<Term>| {
+                let spec = spec.as_ref()?;
+                Some(
+                    self.kb()
+                        .resolve_alias(spec)
+                        .unwrap_or_else(|| spec.clone()),
+                )
+            };
+            let (left_spec, right_spec) = (
+                resolve(&left_param.specializer),
+                resolve(&right_param.specializer),
+            );
+            match (&left_spec, &right_spec) {
+                // If both specs are unions, left is more specific if its members are a strict
+                // subset of right's, less specific if right's are a strict subset of left's, and
+                // otherwise they have the same specificity.
                 (Some(left_spec), Some(right_spec))
-                    if self.kb.read().unwrap().is_union(left_spec)
-                        && self.kb.read().unwrap().is_union(right_spec) => {}
+                    if self.kb().is_union(left_spec) && self.kb().is_union(right_spec) =>
+                {
+                    let kb = self.kb();
+                    let left_in_right = kb.is_subunion(left_spec, right_spec);
+                    let right_in_left = kb.is_subunion(right_spec, left_spec);
+                    match (left_in_right, right_in_left) {
+                        (true, false) => return Ok(()),
+                        (false, true) => return self.push_goal(Goal::Backtrack),
+                        _ => {}
+                    }
+                }
                 // If left is a union and right is not, left cannot be more specific, so we
                 // backtrack.
-                (Some(left_spec), Some(_)) if self.kb.read().unwrap().is_union(left_spec) => {
+                (Some(left_spec), Some(_)) if self.kb().is_union(left_spec) => {
                     return self.push_goal(Goal::Backtrack)
                 }
                 // If right is a union and left is not, left IS more specific, so we return.
-                (Some(_), Some(right_spec)) if self.kb.read().unwrap().is_union(right_spec) => {
-                    return Ok(())
-                }
+                (Some(_), Some(right_spec)) if self.kb().is_union(right_spec) => return Ok(()),
 
                 (Some(left_spec), Some(right_spec)) => {
                     // If you find two non-equal specializers, that comparison determines the relative
@@ -2694,13 +4091,13 @@ impl PolarVirtualMachine {
                     // that aren't the same and you can compare them and ask which one is more specific
                     // to the relevant argument, you're done.
                     if left_spec != right_spec {
-                        let answer = self.kb.read().unwrap().gensym("is_subspecializer");
+                        let answer = self.gensym("is_subspecializer");
                         // Bind answer to false as a starting point in case is subspecializer doesn't
                         // bind any result.
                         // This is done here for safety to avoid a bug where `answer` is unbound by
                         // `IsSubspecializer` and the `Unify` Goal just assigns it to `true` instead
                         // of checking that is is equal to `true`.
-                        self.bind(&answer, Term::from(false)).unwrap();
+                        self.bind(&answer, Term::from(false))?;
 
                         return self.append_goals(vec![
                             Goal::IsSubspecializer {
@@ -2807,7 +4204,7 @@ impl PolarVirtualMachine {
             let chars = context.source.src.chars();
             chars.take(context.right).skip(context.left).collect()
         } else {
-            term.to_string()
+            self.term_formatter.to_polar_string(term)
         };
 
         if include_info {
@@ -2819,6 +4216,62 @@ impl PolarVirtualMachine {
         source_string
     }
 
+    /// Compute the aggregate `term` of `values`, or `None` for the `min` or `max` of nothing.
+    fn aggregate(&self, term: &Term, values: TermList) -> PolarResult<Option<Term>> {
+        let operator = term.as_expression()?.operator;
+        if let Some(value) = values
+            .iter()
+            .find(|v| matches!(v.value(), Value::Variable(_) | Value::Expression(_)))
+        {
+            return unsupported(
+                format!("cannot {} the unbound or partial value {}", operator, value),
+                term,
+            );
+        }
+        match operator {
+            Operator::Count => Ok(Some(Term::from(values.len() as i64))),
+            Operator::Sum => {
+                let mut sum = Numeric::Integer(0);
+                for value in &values {
+                    sum = match value.value() {
+                        Value::Number(n) => match sum + n.clone() {
+                            Some(sum) => sum,
+                            None => {
+                                return Err(
+                                    RuntimeError::ArithmeticError { term: term.clone() }.into()
+                                )
+                            }
+                        },
+                        _ => {
+                            return self
+                                .type_error(term, format!("can only sum numbers, got {}", value))
+                        }
+                    };
+                }
+                Ok(Some(term.clone_with_value(Value::Number(sum))))
+            }
+            Operator::Min | Operator::Max => {
+                let order = if operator == Operator::Min {
+                    Operator::Lt
+                } else {
+                    Operator::Gt
+                };
+                let mut values = values.into_iter();
+                let mut best = match values.next() {
+                    Some(value) => value,
+                    None => return Ok(None),
+                };
+                for value in values {
+                    if compare(order, &value, &best, Some(term))? {
+                        best = value;
+                    }
+                }
+                Ok(Some(best))
+            }
+            _ => invalid_state(format!("{} is not an aggregate", term)),
+        }
+    }
+
     fn type_error<T>(&self, term: &Term, msg: String) -> PolarResult<T> {
         Err(RuntimeError::TypeError {
             msg,
@@ -2829,7 +4282,7 @@ impl PolarVirtualMachine {
     }
 
     fn run_runnable(&mut self, runnable: Box<dyn Runnable>) -> PolarResult<QueryEvent> {
-        let (call_id, answer) = self.new_call_var("runnable_result", Value::Boolean(false));
+        let (call_id, answer) = self.new_call_var("runnable_result", Value::Boolean(false))?;
         self.push_goal(Goal::Unify {
             left: answer,
             right: Term::from(true),
@@ -2845,6 +4298,60 @@ impl PolarVirtualMachine {
     }
 }
 
+/// The results a query with `QueryFlags::DISTINCT` has yielded so far.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct DistinctResults {
+    /// The variables of the query, whose values make up its results.
+    vars: Vec<Symbol>,
+    /// Keys of the results yielded so far.
+    seen: HashSet<String>,
+}
+
+impl DistinctResults {
+    pub(crate) fn new(query: &Term) -> Self {
+        let mut vars = HashSet::new();
+        query.variables(&mut vars);
+        let mut vars = vars
+            .into_iter()
+            .filter(|var| !var.is_temporary_var())
+            .collect::<Vec<_>>();
+        vars.sort();
+        Self {
+            vars,
+            seen: HashSet::new(),
+        }
+    }
+}
+
+/// A key identifying a result for `QueryFlags::DISTINCT`. Partial results are compared with the
+/// constraints in a conjunction sorted, since the order they were added in doesn't matter.
+/// External instances are identified by their instance ID, since different instances can have
+/// the same repr.
+fn result_key(bindings: &Bindings) -> String {
+    fn normalize(term: &Term) -> String {
+        match term.value() {
+            Value::Expression(Operation {
+                operator: Operator::And,
+                args,
+            }) => {
+                let mut args = args.iter().map(normalize).collect::<Vec<_>>();
+                args.sort();
+                args.join(" and ")
+            }
+            _ => TermFormatter::new()
+                .instance_repr(InstanceRepr::Id)
+                .to_polar_string(term),
+        }
+    }
+
+    bindings
+        .iter()
+        .collect::<BTreeMap<_, _>>()
+        .into_iter()
+        .map(|(var, value)| format!("{}: {}\n", var, normalize(value)))
+        .collect()
+}
+
 impl Runnable for PolarVirtualMachine {
     /// Run the virtual machine. While there are goals on the stack,
     /// pop them off and execute them one at a time until we have a
@@ -2852,11 +4359,7 @@ impl Runnable for PolarVirtualMachine {
     /// the machine.
     fn run(&mut self, _: Option<&mut Counter>) -> PolarResult<QueryEvent> {
         if self.query_start_time.is_none() {
-            #[cfg(not(target_arch = "wasm32"))]
-            let query_start_time = Some(std::time::Instant::now());
-            #[cfg(target_arch = "wasm32")]
-            let query_start_time = Some(js_sys::Date::now());
-            self.query_start_time = query_start_time;
+            self.query_start_time = Some(now());
         }
 
         if self.goals.is_empty() {
@@ -2872,6 +4375,17 @@ impl Runnable for PolarVirtualMachine {
                 QueryEvent::None => (),
                 event => {
                     self.external_error = None;
+                    if event.is_external() {
+                        let calls = self.usage.borrow_mut().record_external_call();
+                        self.check_limit(
+                            QueryLimit::ExternalCalls,
+                            self.limits.max_external_calls,
+                            calls,
+                        )?;
+                        if let Some(rule) = self.current_rule_name() {
+                            self.count(|m| m.external_call_in(rule));
+                        }
+                    }
                     return Ok(event);
                 }
             }
@@ -2909,7 +4423,8 @@ impl Runnable for PolarVirtualMachine {
 
                 Err(RuntimeError::UnhandledPartial { term, ref var }) => {
                     // use the debugger to get the nicest possible version of this binding
-                    let Binding(original_var_name, simplified) = get_binding_for_var(&var.0, self);
+                    let Binding(original_var_name, simplified) =
+                        get_binding_for_var(var.as_str(), self);
 
                     // TODO(gj): `t` is a partial constructed in the VM, so we don't have any
                     // source context for it. We make a best effort to track down some relevant
@@ -2955,15 +4470,28 @@ impl Runnable for PolarVirtualMachine {
                     }
                     .into());
                 }
-                Err(e) => unreachable!("unexpected error: {}", e.to_string()),
+                Err(e) => return Err(e.into()),
             }
 
             bindings = bindings
-                .clone()
                 .into_iter()
                 .filter(|(var, _)| !var.is_temporary_var())
-                .map(|(var, value)| (var.clone(), sub_this(var, value)))
+                .map(|(var, value)| {
+                    let value = sub_this(var.clone(), value);
+                    (var, value)
+                })
                 .collect();
+            bindings = hide_anonymous_vars(bindings, |v| self.binding_manager.named_alias(v))
+                .into_iter()
+                .map(|(var, value)| (var, self.partial_form.apply(value)))
+                .collect();
+        }
+
+        if let Some(distinct) = self.seen_results.as_mut() {
+            if !distinct.seen.insert(result_key(&bindings)) {
+                // Already yielded; backtrack for the next result.
+                return Ok(QueryEvent::None);
+            }
         }
 
         self.log(
@@ -2988,10 +4516,14 @@ impl Runnable for PolarVirtualMachine {
 
     fn handle_error(&mut self, error: PolarError) -> PolarResult<QueryEvent> {
         // if we pushed a debug goal, push an error goal underneath it.
-        if self.maybe_break(DebugEvent::Error(error.clone()))? {
-            let g = self.goals.pop().unwrap();
+        if !std::mem::take(&mut self.reraising_error)
+            && self.maybe_break(DebugEvent::Error(error.clone()))?
+        {
+            let g = self.goals.pop();
             self.push_goal(Goal::Error { error })?;
-            self.goals.push(g);
+            if let Some(g) = g {
+                self.goals.push(g);
+            }
             Ok(QueryEvent::None)
         } else {
             Err(error)
@@ -3000,7 +4532,10 @@ impl Runnable for PolarVirtualMachine {
 
     /// Handle response to a predicate posed to the application, e.g., `ExternalIsa`.
     fn external_question_result(&mut self, call_id: u64, answer: bool) -> PolarResult<()> {
-        let var = self.call_id_symbols.remove(&call_id).expect("bad call id");
+        let var = match self.call_id_symbols.remove(&call_id) {
+            Some(var) => var,
+            None => return invalid_state(format!("unregistered external call ID {}", call_id)),
+        };
         self.rebind_external_answer(&var, Term::from(answer));
         Ok(())
     }
@@ -3015,11 +4550,25 @@ impl Runnable for PolarVirtualMachine {
         // TODO: Open question if we need to pass errors back down to rust.
         // For example what happens if the call asked for a field that doesn't exist?
 
+        if let Some(name) = self.constant_calls.remove(&call_id) {
+            return self.constant_result(name, term);
+        }
+
         if let Some(value) = term {
-            self.log(LogLevel::Trace, || format!("=> {}", value), &[]);
+            self.log(
+                LogLevel::Trace,
+                || format!("=> {}", self.term_formatter.to_polar_string(&value)),
+                &[],
+            );
 
             // Fetch variable to unify with call result.
-            let sym = self.get_call_sym(call_id).to_owned();
+            let sym = self.get_call_sym(call_id)?.to_owned();
+
+            let mut memo = self.external_call_memo.borrow_mut();
+            if let Some(key) = memo.pending.remove(&call_id) {
+                memo.results.insert(key, value.clone());
+            }
+            drop(memo);
 
             self.push_goal(Goal::Unify {
                 left: Term::from(sym),
@@ -3030,7 +4579,13 @@ impl Runnable for PolarVirtualMachine {
 
             // No more results. Clean up, cut out the retry alternative,
             // and backtrack.
-            self.call_id_symbols.remove(&call_id).expect("bad call ID");
+            self.external_call_memo
+                .borrow_mut()
+                .pending
+                .remove(&call_id);
+            if self.call_id_symbols.remove(&call_id).is_none() {
+                return invalid_state(format!("unregistered external call ID {}", call_id));
+            }
 
             let check_error = if let Some(goal) = self.goals.last() {
                 matches!(*(*goal), Goal::CheckError)
@@ -3061,6 +4616,16 @@ impl Runnable for PolarVirtualMachine {
         Ok(())
     }
 
+    fn debug_request(&mut self, request: DebugRequest) -> PolarResult<()> {
+        let mut debugger = self.debugger.clone();
+        let maybe_goal = debugger.debug_request(request, self);
+        if let Some(goal) = maybe_goal {
+            self.push_goal(goal)?;
+        }
+        self.debugger = debugger;
+        Ok(())
+    }
+
     fn clone_runnable(&self) -> Box<dyn Runnable> {
         Box::new(self.clone())
     }
@@ -3152,7 +4717,7 @@ mod tests {
 
         let goal = query!(op!(And));
 
-        let mut vm = PolarVirtualMachine::new_test(Arc::new(RwLock::new(kb)), false, vec![goal]);
+        let mut vm = PolarVirtualMachine::new_test(Arc::new(kb), false, vec![goal]);
         assert_query_events!(vm, [
             QueryEvent::Result{hashmap!()},
             QueryEvent::Done { result: true }
@@ -3543,7 +5108,7 @@ mod tests {
     #[test]
     fn debug() {
         let mut vm = PolarVirtualMachine::new_test(
-            Arc::new(RwLock::new(KnowledgeBase::new())),
+            Arc::new(KnowledgeBase::new()),
             false,
             vec![Goal::Debug {
                 message: "Hello".to_string(),
@@ -3557,11 +5122,8 @@ mod tests {
 
     #[test]
     fn halt() {
-        let mut vm = PolarVirtualMachine::new_test(
-            Arc::new(RwLock::new(KnowledgeBase::new())),
-            false,
-            vec![Goal::Halt],
-        );
+        let mut vm =
+            PolarVirtualMachine::new_test(Arc::new(KnowledgeBase::new()), false, vec![Goal::Halt]);
         let _ = vm.run(None).unwrap();
         assert_eq!(vm.goals.len(), 0);
         assert_eq!(vm.bindings(true).len(), 0);
@@ -3576,7 +5138,7 @@ mod tests {
         let one = value!(1);
         let vals = term!([zero.clone(), one.clone()]);
         let mut vm = PolarVirtualMachine::new_test(
-            Arc::new(RwLock::new(KnowledgeBase::new())),
+            Arc::new(KnowledgeBase::new()),
             false,
             vec![Goal::Unify {
                 left: vars,
@@ -3640,10 +5202,10 @@ mod tests {
                 operator: Operator::And,
                 args: vec![
                     term!(1),
-                    Term::new_from_test(Value::Variable(Symbol("x".to_string()))),
-                    Term::new_from_test(Value::Variable(Symbol("x".to_string()))),
+                    Term::new_from_test(Value::Variable(Symbol::new("x"))),
+                    Term::new_from_test(Value::Variable(Symbol::new("x"))),
                     Term::new_from_test(Value::List(vec![Term::new_from_test(Value::Variable(
-                        Symbol("y".to_string()),
+                        Symbol::new("y"),
                     ))])),
                 ],
             })),
@@ -3653,14 +5215,14 @@ mod tests {
         let renamed_terms = unwrap_and(&renamed_rule.body);
         assert_eq!(renamed_terms[1].value(), renamed_terms[2].value());
         let x_value = match &renamed_terms[1].value() {
-            Value::Variable(sym) => Some(sym.0.clone()),
+            Value::Variable(sym) => Some(sym.as_str().to_string()),
             _ => None,
         };
         assert_eq!(x_value.unwrap(), "_x_1");
 
         let y_value = match &renamed_terms[3].value() {
             Value::List(terms) => match &terms[0].value() {
-                Value::Variable(sym) => Some(sym.0.clone()),
+                Value::Variable(sym) => Some(sym.as_str().to_string()),
                 _ => None,
             },
             _ => None,
@@ -3679,7 +5241,7 @@ mod tests {
         let mut kb = KnowledgeBase::new();
         kb.add_generic_rule(gen_rule);
 
-        let kb = Arc::new(RwLock::new(kb));
+        let kb = Arc::new(kb);
 
         let external_instance = Value::ExternalInstance(ExternalInstance {
             instance_id: 1,
@@ -3703,7 +5265,7 @@ mod tests {
                 } => {
                     external_isas.push(class_tag.clone());
                     // Return `true` if the specified `class_tag` is `"a"`.
-                    vm.external_question_result(call_id, class_tag.0 == "a")
+                    vm.external_question_result(call_id, class_tag.as_str() == "a")
                         .unwrap()
                 }
                 QueryEvent::ExternalOp { .. }
@@ -3765,7 +5327,7 @@ mod tests {
         });
 
         let mut vm = PolarVirtualMachine::new_test(
-            Arc::new(RwLock::new(kb)),
+            Arc::new(kb),
             false,
             vec![query!(call!(
                 "bar",
@@ -3841,7 +5403,7 @@ mod tests {
             fields: btreemap! {sym!("a") => term!("a")},
         })));
 
-        let answer = vm.kb.read().unwrap().gensym("is_subspecializer");
+        let answer = vm.kb.gensym("is_subspecializer");
 
         match vm.is_subspecializer(&answer, &left, &right, &arg).unwrap() {
             QueryEvent::None => (),
@@ -3906,7 +5468,7 @@ mod tests {
         let mut kb = KnowledgeBase::new();
         kb.add_generic_rule(bar_rule);
 
-        let mut vm = PolarVirtualMachine::new_test(Arc::new(RwLock::new(kb)), false, vec![]);
+        let mut vm = PolarVirtualMachine::new_test(Arc::new(kb), false, vec![]);
         vm.bind(&sym!("x"), term!(1)).unwrap();
         let _ = vm.run(None);
         let _ = vm.next(Rc::new(query!(call!("bar", [value!([sym!("x")])]))));
@@ -3929,11 +5491,7 @@ mod tests {
 
     #[test]
     fn choose_conditional() {
-        let mut vm = PolarVirtualMachine::new_test(
-            Arc::new(RwLock::new(KnowledgeBase::new())),
-            false,
-            vec![],
-        );
+        let mut vm = PolarVirtualMachine::new_test(Arc::new(KnowledgeBase::new()), false, vec![]);
         let consequent = Goal::Debug {
             message: "consequent".to_string(),
         };
@@ -3948,10 +5506,10 @@ mod tests {
             vec![alternative.clone()],
         )
         .unwrap();
-        assert_query_events!(vm, [
-            QueryEvent::Debug { message } if &message[..] == "consequent" && vm.is_halted(),
-            QueryEvent::Done { result: true }
-        ]);
+        assert_query_events!(
+            vm,
+            [QueryEvent::Debug { message } if &message[..] == "consequent" && vm.is_halted(), QueryEvent::Done { result: true }]
+        );
 
         // Check alternative path when conditional fails.
         vm.choose_conditional(
@@ -3960,10 +5518,10 @@ mod tests {
             vec![alternative.clone()],
         )
         .unwrap();
-        assert_query_events!(vm, [
-            QueryEvent::Debug { message } if &message[..] == "alternative" && vm.is_halted(),
-            QueryEvent::Done { result: true }
-        ]);
+        assert_query_events!(
+            vm,
+            [QueryEvent::Debug { message } if &message[..] == "alternative" && vm.is_halted(), QueryEvent::Done { result: true }]
+        );
 
         // Ensure bindings are cleaned up after conditional.
         vm.choose_conditional(
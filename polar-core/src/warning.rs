@@ -3,6 +3,7 @@ use std::fmt;
 use indoc::indoc;
 use strum_macros::AsRefStr;
 
+use super::rules::{Deprecation, Rule};
 use super::sources::Context;
 use super::terms::{InstanceLiteral, Pattern, Symbol, Term, Value};
 
@@ -14,12 +15,37 @@ impl PolarWarning {
         "ValidationWarning::".to_string() + self.0.as_ref()
     }
 
+    /// A short, stable code for the kind of warning, e.g., `W003` for
+    /// `ValidationWarning::DuplicateRule`. See `PolarError::code`.
+    pub fn code(&self) -> &'static str {
+        use ValidationWarning::*;
+
+        match &self.0 {
+            AmbiguousPrecedence { .. } => "W001",
+            DeprecatedRuleCall { .. } => "W002",
+            DuplicateRule { .. } => "W003",
+            EffectfulCallInDisjunction { .. } => "W004",
+            MissingAllowRule => "W005",
+            MissingHasPermissionRule => "W006",
+            OrderedEffectfulCalls { .. } => "W007",
+            SubsumedRule { .. } => "W008",
+            UnknownSpecializer { .. } => "W009",
+            TypeMismatch { .. } => "W010",
+        }
+    }
+
     pub fn get_context(&self) -> Option<Context> {
         use ValidationWarning::*;
 
         match &self.0 {
-            AmbiguousPrecedence { term } | UnknownSpecializer { term, .. } => {
-                term.parsed_context().cloned()
+            AmbiguousPrecedence { term }
+            | DeprecatedRuleCall { term, .. }
+            | EffectfulCallInDisjunction { term }
+            | OrderedEffectfulCalls { term, .. }
+            | TypeMismatch { term, .. }
+            | UnknownSpecializer { term, .. } => term.parsed_context().cloned(),
+            DuplicateRule { rule, .. } | SubsumedRule { rule, .. } => {
+                rule.parsed_context().cloned()
             }
             MissingAllowRule | MissingHasPermissionRule => None,
         }
@@ -39,15 +65,49 @@ impl fmt::Display for PolarWarning {
 #[derive(AsRefStr, Debug)]
 pub enum ValidationWarning {
     // Category: general
-    AmbiguousPrecedence { term: Term },
+    AmbiguousPrecedence {
+        term: Term,
+    },
+    // Category: general
+    DeprecatedRuleCall {
+        term: Term,
+        deprecation: Deprecation,
+    },
+    // Category: general
+    DuplicateRule {
+        rule: Rule,
+        original: Rule,
+    },
+    // Category: general
+    EffectfulCallInDisjunction {
+        term: Term,
+    },
     // Category: enforcement
     MissingAllowRule,
     // Category: resource blocks
     MissingHasPermissionRule,
     // Category: general
+    OrderedEffectfulCalls {
+        term: Term,
+        previous: Term,
+    },
+    // Category: general
+    SubsumedRule {
+        rule: Rule,
+        fact: Rule,
+    },
+    // Category: general
     // TODO(gj): won't need `sym` once we have an easier, infallible way of going from `Term` ->
     // `Pattern` -> `InstanceLiteral` -> `tag` (`Symbol`).
-    UnknownSpecializer { term: Term, sym: Symbol },
+    UnknownSpecializer {
+        term: Term,
+        sym: Symbol,
+    },
+    // Category: general
+    TypeMismatch {
+        term: Term,
+        msg: String,
+    },
 }
 
 impl From<ValidationWarning> for PolarWarning {
@@ -89,7 +149,7 @@ const MISSING_HAS_PERMISSION_RULE_MSG: &str = indoc! {"
 
 fn common_specializer_misspellings(term: &Term) -> Option<&str> {
     if let Value::Pattern(Pattern::Instance(InstanceLiteral { tag, .. })) = term.value() {
-        let misspelled_type = match tag.0.as_ref() {
+        let misspelled_type = match tag.as_str() {
             "integer" => "Integer",
             "int" => "Integer",
             "i32" => "Integer",
@@ -131,14 +191,71 @@ impl fmt::Display for ValidationWarning {
 
         match self {
             AmbiguousPrecedence { .. } => write!(f, "{}", AMBIGUOUS_PRECEDENCE_MSG)?,
+            DeprecatedRuleCall { term, deprecation } => {
+                let rule = &deprecation.rule;
+                write!(
+                    f,
+                    "Call to deprecated rule {}/{}: {}",
+                    rule.name,
+                    rule.params.len(),
+                    deprecation.message()
+                )?;
+                if let Some(context) = rule.parsed_context() {
+                    write!(f, "\nDeprecated{}", context)?;
+                }
+                if term.parsed_context().is_some() {
+                    write!(f, "\nCalled")?;
+                }
+            }
+            DuplicateRule { rule, original } => {
+                write!(f, "Duplicate rule: {}", rule)?;
+                if let Some(context) = original.parsed_context() {
+                    write!(f, "\nFirst defined{}", context)?;
+                }
+                if rule.parsed_context().is_some() {
+                    write!(f, "\nDuplicated")?;
+                }
+            }
+            EffectfulCallInDisjunction { term } => {
+                write!(
+                    f,
+                    "Effectful call {} in an `or` branch only runs if the branches before it \
+                     fail, so its effects depend on evaluation order.",
+                    term
+                )?;
+            }
             MissingAllowRule => write!(f, "{}", MISSING_ALLOW_RULE_MSG)?,
             MissingHasPermissionRule => write!(f, "{}", MISSING_HAS_PERMISSION_RULE_MSG)?,
+            OrderedEffectfulCalls { term, previous } => {
+                write!(
+                    f,
+                    "Effectful call {} runs after effectful call {}, so the policy depends on \
+                     the order in which they're evaluated.",
+                    term, previous
+                )?;
+                if let Some(context) = previous.parsed_context() {
+                    write!(f, "\nPrevious call{}", context)?;
+                }
+                if term.parsed_context().is_some() {
+                    write!(f, "\nCalled")?;
+                }
+            }
+            SubsumedRule { rule, fact } => {
+                write!(f, "Rule {} is subsumed by the fact {}", rule, fact)?;
+                if let Some(context) = fact.parsed_context() {
+                    write!(f, "\nFact defined{}", context)?;
+                }
+                if rule.parsed_context().is_some() {
+                    write!(f, "\nSubsumed rule defined")?;
+                }
+            }
             UnknownSpecializer { term, sym } => {
                 write!(f, "Unknown specializer {}", sym)?;
                 if let Some(suggestion) = common_specializer_misspellings(term) {
                     write!(f, ", did you mean {}?", suggestion)?;
                 }
             }
+            TypeMismatch { msg, .. } => write!(f, "Type mismatch: {}", msg)?,
         }
 
         Ok(())
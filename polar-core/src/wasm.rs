@@ -0,0 +1,347 @@
+//! Running policies from JS, e.g., in the browser or in edge workers.
+//!
+//! A [`Bridge`] exposes the same load/query/event loop as [`Polar`] and [`Query`], but the events
+//! it returns are shaped for `serde-wasm-bindgen`. [`QueryEvent`] uses `u64` ids, which JS numbers
+//! can't hold exactly, and serializes as an object keyed by the kind of event. A [`BridgeEvent`]
+//! uses numbers for ids, names its kind in a `kind` field, and uses camelCase field names, so JS
+//! hosts can `switch (event.kind)` on it directly.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use super::debug_protocol::DebuggerEvent;
+use super::error::{invalid_state, unexpected_value, PolarResult};
+use super::events::QueryEvent;
+use super::messages::Message;
+use super::numerics::Numeric;
+use super::polar::Polar;
+use super::query::Query;
+use super::sources::Source;
+use super::terms::{Operator, Symbol, Term, Value};
+
+/// The largest integer a JS number holds exactly, `Number.MAX_SAFE_INTEGER`.
+const MAX_SAFE_INTEGER: f64 = 9_007_199_254_740_991.0;
+
+fn to_js_id(id: u64) -> f64 {
+    id as f64
+}
+
+fn from_js_id(id: f64) -> PolarResult<u64> {
+    if id.fract() == 0.0 && (0.0..=MAX_SAFE_INTEGER).contains(&id) {
+        Ok(id as u64)
+    } else {
+        unexpected_value("id", Term::from(Value::Number(Numeric::Float(id))))
+    }
+}
+
+fn by_name(map: impl IntoIterator<Item = (Symbol, Term)>) -> BTreeMap<String, Term> {
+    map.into_iter()
+        .map(|(k, v)| (k.as_str().to_string(), v))
+        .collect()
+}
+
+/// A [`QueryEvent`] for a JS host.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind")]
+pub enum BridgeEvent {
+    Done {
+        result: bool,
+    },
+    Result {
+        bindings: BTreeMap<String, Term>,
+    },
+    Debug {
+        message: String,
+    },
+    Debugger {
+        event: DebuggerEvent,
+    },
+    #[serde(rename_all = "camelCase")]
+    MakeExternal {
+        instance_id: f64,
+        constructor: Term,
+    },
+    #[serde(rename_all = "camelCase")]
+    ExternalCall {
+        call_id: f64,
+        instance: Term,
+        attribute: String,
+        args: Option<Vec<Term>>,
+        kwargs: Option<BTreeMap<String, Term>>,
+    },
+    #[serde(rename_all = "camelCase")]
+    ExternalIsa {
+        call_id: f64,
+        instance: Term,
+        class_tag: String,
+    },
+    #[serde(rename_all = "camelCase")]
+    ExternalIsaWithPath {
+        call_id: f64,
+        base_tag: String,
+        path: Vec<Term>,
+        class_tag: String,
+    },
+    #[serde(rename_all = "camelCase")]
+    ExternalIsSubSpecializer {
+        call_id: f64,
+        instance_id: f64,
+        left_class_tag: String,
+        right_class_tag: String,
+    },
+    #[serde(rename_all = "camelCase")]
+    ExternalIsSubclass {
+        call_id: f64,
+        left_class_tag: String,
+        right_class_tag: String,
+    },
+    #[serde(rename_all = "camelCase")]
+    ExternalOp {
+        call_id: f64,
+        operator: Operator,
+        args: Vec<Term>,
+    },
+    #[serde(rename_all = "camelCase")]
+    NextExternal {
+        call_id: f64,
+        iterable: Term,
+    },
+    AggregateValue {
+        aggregate: Operator,
+        value: Term,
+    },
+    #[serde(rename_all = "camelCase")]
+    ExternalConstant {
+        call_id: f64,
+        name: String,
+    },
+}
+
+impl BridgeEvent {
+    /// Convert an event returned by [`Query::next_event`], which never returns the `None` and
+    /// `Run` events the VM uses internally.
+    fn from_query_event(event: QueryEvent) -> PolarResult<Self> {
+        use QueryEvent::*;
+
+        Ok(match event {
+            Done { result } => Self::Done { result },
+            Result { bindings, .. } => Self::Result {
+                bindings: by_name(bindings),
+            },
+            Debug { message } => Self::Debug { message },
+            Debugger { event } => Self::Debugger { event },
+            MakeExternal {
+                instance_id,
+                constructor,
+            } => Self::MakeExternal {
+                instance_id: to_js_id(instance_id),
+                constructor,
+            },
+            ExternalCall {
+                call_id,
+                instance,
+                attribute,
+                args,
+                kwargs,
+            } => Self::ExternalCall {
+                call_id: to_js_id(call_id),
+                instance,
+                attribute: attribute.as_str().to_string(),
+                args,
+                kwargs: kwargs.map(by_name),
+            },
+            ExternalIsa {
+                call_id,
+                instance,
+                class_tag,
+            } => Self::ExternalIsa {
+                call_id: to_js_id(call_id),
+                instance,
+                class_tag: class_tag.as_str().to_string(),
+            },
+            ExternalIsaWithPath {
+                call_id,
+                base_tag,
+                path,
+                class_tag,
+            } => Self::ExternalIsaWithPath {
+                call_id: to_js_id(call_id),
+                base_tag: base_tag.as_str().to_string(),
+                path,
+                class_tag: class_tag.as_str().to_string(),
+            },
+            ExternalIsSubSpecializer {
+                call_id,
+                instance_id,
+                left_class_tag,
+                right_class_tag,
+            } => Self::ExternalIsSubSpecializer {
+                call_id: to_js_id(call_id),
+                instance_id: to_js_id(instance_id),
+                left_class_tag: left_class_tag.as_str().to_string(),
+                right_class_tag: right_class_tag.as_str().to_string(),
+            },
+            ExternalIsSubclass {
+                call_id,
+                left_class_tag,
+                right_class_tag,
+            } => Self::ExternalIsSubclass {
+                call_id: to_js_id(call_id),
+                left_class_tag: left_class_tag.as_str().to_string(),
+                right_class_tag: right_class_tag.as_str().to_string(),
+            },
+            ExternalOp {
+                call_id,
+                operator,
+                args,
+            } => Self::ExternalOp {
+                call_id: to_js_id(call_id),
+                operator,
+                args,
+            },
+            NextExternal { call_id, iterable } => Self::NextExternal {
+                call_id: to_js_id(call_id),
+                iterable,
+            },
+            AggregateValue { aggregate, value } => Self::AggregateValue { aggregate, value },
+            ExternalConstant { call_id, name } => Self::ExternalConstant {
+                call_id: to_js_id(call_id),
+                name: name.as_str().to_string(),
+            },
+            None | Run { .. } => return invalid_state("query returned an internal event"),
+        })
+    }
+}
+
+/// A `Polar` driven from JS.
+#[derive(Default)]
+pub struct Bridge {
+    polar: Polar,
+}
+
+impl Bridge {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn polar(&self) -> &Polar {
+        &self.polar
+    }
+
+    pub fn load(&self, sources: Vec<Source>) -> PolarResult<()> {
+        self.polar.load(sources)
+    }
+
+    pub fn clear_rules(&self) {
+        self.polar.clear_rules()
+    }
+
+    pub fn register_constant(&self, name: &str, value: Term) -> PolarResult<()> {
+        self.polar.register_constant(Symbol::new(name), value)
+    }
+
+    pub fn register_lazy_constant(&self, name: &str) -> PolarResult<()> {
+        self.polar.register_lazy_constant(Symbol::new(name))
+    }
+
+    pub fn query(&self, src: &str) -> PolarResult<BridgeQuery> {
+        self.polar.new_query(src, false).map(BridgeQuery)
+    }
+
+    pub fn query_term(&self, term: Term) -> BridgeQuery {
+        BridgeQuery(self.polar.new_query_from_term(term, false))
+    }
+
+    pub fn next_message(&self) -> Option<Message> {
+        self.polar.next_message()
+    }
+}
+
+/// A `Query` driven from JS. Ids passed back to it are the numbers from its [`BridgeEvent`]s.
+pub struct BridgeQuery(Query);
+
+impl BridgeQuery {
+    pub fn next_event(&mut self) -> PolarResult<BridgeEvent> {
+        BridgeEvent::from_query_event(self.0.next_event()?)
+    }
+
+    pub fn call_result(&mut self, call_id: f64, value: Option<Term>) -> PolarResult<()> {
+        self.0.call_result(from_js_id(call_id)?, value)
+    }
+
+    pub fn question_result(&mut self, call_id: f64, result: bool) -> PolarResult<()> {
+        self.0.question_result(from_js_id(call_id)?, result)
+    }
+
+    pub fn application_error(&mut self, message: String) -> PolarResult<()> {
+        self.0.application_error(message)
+    }
+
+    pub fn bind(&mut self, name: &str, value: Term) -> PolarResult<()> {
+        self.0.bind(Symbol::new(name), value)
+    }
+
+    pub fn next_message(&self) -> Option<Message> {
+        self.0.next_message()
+    }
+
+    pub fn query(&self) -> &Query {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bridge_event_loop() {
+        let bridge = Bridge::new();
+        bridge
+            .load(vec![Source::new(r#"f(x, y) if y = x.name;"#)])
+            .unwrap();
+        let instance = Term::from(Value::ExternalInstance(crate::terms::ExternalInstance {
+            instance_id: 1,
+            constructor: Option::None,
+            repr: Option::None,
+            class_repr: Option::None,
+            class_id: Option::None,
+        }));
+        let mut query = bridge.query("f(x, y)").unwrap();
+        query.bind("x", instance).unwrap();
+
+        let call_id = match query.next_event().unwrap() {
+            BridgeEvent::ExternalCall {
+                call_id, attribute, ..
+            } => {
+                assert_eq!(attribute, "name");
+                call_id
+            }
+            event => panic!("unexpected event {:?}", event),
+        };
+        assert!(query.call_result(call_id + 0.5, Some(term!("a"))).is_err());
+        query.call_result(call_id, Some(term!("alice"))).unwrap();
+        match query.next_event().unwrap() {
+            BridgeEvent::Result { bindings } => assert_eq!(bindings["y"], term!("alice")),
+            event => panic!("unexpected event {:?}", event),
+        }
+        assert_eq!(
+            query.next_event().unwrap(),
+            BridgeEvent::Done { result: true }
+        );
+    }
+
+    #[test]
+    fn test_bridge_event_serialization() {
+        let event = BridgeEvent::ExternalIsa {
+            call_id: 2.0,
+            instance: term!(1),
+            class_tag: "User".to_owned(),
+        };
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(json["kind"], "ExternalIsa");
+        assert_eq!(json["callId"], 2.0);
+        assert_eq!(json["classTag"], "User");
+    }
+}
@@ -9,7 +9,7 @@ mod tests {
         let mut kwargs = BTreeMap::new();
         kwargs.insert(Symbol::new("bar"), term!(1));
         let pred = Call {
-            name: Symbol("foo".to_owned()),
+            name: Symbol::new("foo"),
             args: vec![Term::new_from_test(value!(0))],
             kwargs: Some(kwargs),
         };